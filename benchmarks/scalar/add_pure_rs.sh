@@ -0,0 +1,3 @@
+#!/bin/bash
+
+sqlite3x :memory: '.load ../target/scalar_rs' "select add_pure_rs(value, value) from generate_series(1, $1);"
\ No newline at end of file
@@ -19,6 +19,7 @@ bench_scalar_add() {
   hyperfine --warmup 10 --export-json=results-add.json \
     "./add_c.sh $end" \
     "./add_rs.sh $end" \
+    "./add_pure_rs.sh $end" \
     "./add_go.sh $end" 
 }
 
@@ -0,0 +1,203 @@
+//! `cargo sqlite-extension new <name>`, which scaffolds a crate for a new
+//! SQLite extension built with sqlite-loadable: a cdylib with an entrypoint
+//! SQLite finds on its own, a staticlib and C header for linking it into a
+//! program instead, a test that loads it into rusqlite, and linker settings
+//! for cross-compiling.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+/// The sqlite-loadable version new crates depend on.
+const SQLITE_LOADABLE_VERSION: &str = "0.0.6-alpha.6";
+
+const USAGE: &str = "\
+Scaffolds a new SQLite extension crate
+
+Usage: cargo sqlite-extension new <name> [options]
+
+Options:
+  --path <dir>                  Where to create the crate, ./<name> by default
+  --sqlite-loadable-path <dir>  Depend on a local checkout of sqlite-loadable
+  -h, --help                    Print this help";
+
+/// (template, where it goes in the new crate, with {{lib_name}} replaced)
+const TEMPLATES: &[(&str, &str)] = &[
+    (include_str!("templates/Cargo.toml.in"), "Cargo.toml"),
+    (include_str!("templates/lib.rs.in"), "src/lib.rs"),
+    (
+        include_str!("templates/test.rs.in"),
+        "tests/test_{{lib_name}}.rs",
+    ),
+    (
+        include_str!("templates/config.toml.in"),
+        ".cargo/config.toml",
+    ),
+    (include_str!("templates/gitignore.in"), ".gitignore"),
+    (
+        include_str!("templates/header.h.in"),
+        "include/{{lib_name}}.h",
+    ),
+];
+
+/// The names a new extension goes by.
+#[derive(Debug, PartialEq)]
+struct Names {
+    /// The package name, like `sqlite-hello`.
+    crate_name: String,
+    /// The Rust name of the library, like `sqlite_hello`.
+    lib_name: String,
+    /// The entrypoint SQLite derives from the library's file name, like
+    /// `sqlite3_sqlitehello_init`.
+    entrypoint: String,
+}
+
+impl Names {
+    fn new(crate_name: &str) -> Result<Names, String> {
+        let valid = crate_name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid || !crate_name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            return Err(format!(
+                "{:?} isn't a valid crate name, use letters, digits, - and _, starting with a letter",
+                crate_name
+            ));
+        }
+        Ok(Names {
+            crate_name: crate_name.to_owned(),
+            lib_name: crate_name.replace('-', "_"),
+            entrypoint: default_entrypoint(crate_name),
+        })
+    }
+}
+
+/// The entrypoint `.load` and `load_extension()` call when they aren't
+/// given one, for a library of the given name: like sqlite3LoadExtension(),
+/// only the ASCII letters count, lower cased.
+fn default_entrypoint(name: &str) -> String {
+    let letters: String = name
+        .chars()
+        .filter(char::is_ascii_alphabetic)
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    format!("sqlite3_{}_init", letters)
+}
+
+fn render(template: &str, names: &Names, sqlite_loadable: &str) -> String {
+    template
+        .replace("{{crate_name}}", &names.crate_name)
+        .replace("{{lib_name}}", &names.lib_name)
+        .replace("{{LIB_NAME}}", &names.lib_name.to_ascii_uppercase())
+        .replace("{{entrypoint}}", &names.entrypoint)
+        .replace("{{sqlite_loadable}}", sqlite_loadable)
+}
+
+fn scaffold(dir: &Path, names: &Names, sqlite_loadable: &str) -> Result<(), String> {
+    let occupied = fs::read_dir(dir)
+        .map(|mut entries| entries.next().is_some())
+        .unwrap_or(false);
+    if occupied {
+        return Err(format!("{} already exists and isn't empty", dir.display()));
+    }
+    for (template, path) in TEMPLATES {
+        let path = dir.join(render(path, names, sqlite_loadable));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|err| format!("could not create {}: {}", parent.display(), err))?;
+        }
+        fs::write(&path, render(template, names, sqlite_loadable))
+            .map_err(|err| format!("could not write {}: {}", path.display(), err))?;
+    }
+    Ok(())
+}
+
+fn run(mut args: Vec<String>) -> Result<(), String> {
+    // cargo runs `cargo-sqlite-extension sqlite-extension new ...`
+    if args.first().map(String::as_str) == Some("sqlite-extension") {
+        args.remove(0);
+    }
+    let mut args = args.into_iter();
+    let mut name = None;
+    let mut dir = None;
+    let mut local = None;
+    match args.next().as_deref() {
+        Some("new") => {}
+        Some("-h" | "--help") | None => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+        Some(command) => return Err(format!("unknown command {:?}\n\n{}", command, USAGE)),
+    }
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--path" => {
+                dir = Some(PathBuf::from(
+                    args.next().ok_or("--path needs a directory")?,
+                ))
+            }
+            "--sqlite-loadable-path" => {
+                local = Some(
+                    args.next()
+                        .ok_or("--sqlite-loadable-path needs a directory")?,
+                )
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ if arg.starts_with('-') => {
+                return Err(format!("unknown option {}\n\n{}", arg, USAGE))
+            }
+            _ if name.is_none() => name = Some(arg),
+            _ => return Err(format!("unexpected argument {:?}\n\n{}", arg, USAGE)),
+        }
+    }
+    let names = Names::new(&name.ok_or_else(|| format!("missing a name\n\n{}", USAGE))?)?;
+    let dir = dir.unwrap_or_else(|| PathBuf::from(&names.crate_name));
+    // without the bundled SQLite, which would clash with the program's own
+    // when the staticlib is linked into it
+    let sqlite_loadable = match local {
+        Some(path) => format!("{{path={:?}, default-features=false}}", path),
+        None => format!(
+            "{{version={:?}, default-features=false}}",
+            SQLITE_LOADABLE_VERSION
+        ),
+    };
+    scaffold(&dir, &names, &sqlite_loadable)?;
+    println!(
+        "Created {} in {}. `cargo build` it, then `.load target/debug/{}{}` in the sqlite3 CLI.",
+        names.crate_name,
+        dir.display(),
+        env::consts::DLL_PREFIX,
+        names.lib_name
+    );
+    Ok(())
+}
+
+fn main() {
+    if let Err(message) = run(env::args().skip(1).collect()) {
+        eprintln!("error: {}", message);
+        exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        assert_eq!(
+            Names::new("sqlite-hello_2").unwrap(),
+            Names {
+                crate_name: "sqlite-hello_2".to_owned(),
+                lib_name: "sqlite_hello_2".to_owned(),
+                entrypoint: "sqlite3_sqlitehello_init".to_owned(),
+            }
+        );
+        assert!(Names::new("2fast").is_err());
+        assert!(Names::new("has space").is_err());
+        assert!(Names::new("").is_err());
+    }
+}
@@ -0,0 +1,31 @@
+/*
+** Links the staticlib of the crate test_new scaffolds into a program built
+** with the SQLite amalgamation, and calls its entrypoint both ways.
+*/
+#include <assert.h>
+#include <string.h>
+#include "sqlite_hello.h"
+
+static void check_version(sqlite3 *db) {
+  sqlite3_stmt *stmt;
+  int rc = sqlite3_prepare_v2(db, "select sqlite_hello_version()", -1, &stmt, NULL);
+  assert(rc == SQLITE_OK);
+  assert(sqlite3_step(stmt) == SQLITE_ROW);
+  assert(strcmp((const char *)sqlite3_column_text(stmt, 0), "0.1.0") == 0);
+  sqlite3_finalize(stmt);
+}
+
+int main(void) {
+  sqlite3 *db;
+
+  assert(sqlite3_open(":memory:", &db) == SQLITE_OK);
+  assert(sqlite3_sqlitehello_init(db, NULL, NULL) == SQLITE_OK);
+  check_version(db);
+  sqlite3_close(db);
+
+  sqlite3_auto_extension((void (*)(void))sqlite3_sqlitehello_init);
+  assert(sqlite3_open(":memory:", &db) == SQLITE_OK);
+  check_version(db);
+  sqlite3_close(db);
+  return 0;
+}
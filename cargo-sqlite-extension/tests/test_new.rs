@@ -0,0 +1,105 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+fn scaffold(name: &str, args: &[&str]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("cargo-sqlite-extension-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let status = Command::new(env!("CARGO_BIN_EXE_cargo-sqlite-extension"))
+        .args(["sqlite-extension", "new", name, "--path"])
+        .arg(&dir)
+        .args(args)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    dir
+}
+
+#[test]
+fn test_new() {
+    let workspace = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+    let dir = scaffold(
+        "sqlite-hello",
+        &["--sqlite-loadable-path", workspace.to_str().unwrap()],
+    );
+    for file in [
+        "Cargo.toml",
+        "src/lib.rs",
+        "tests/test_sqlite_hello.rs",
+        ".cargo/config.toml",
+        ".gitignore",
+    ] {
+        assert!(dir.join(file).exists(), "{} wasn't created", file);
+    }
+    let lib = fs::read_to_string(dir.join("src/lib.rs")).unwrap();
+    assert!(lib.contains("pub fn sqlite3_sqlitehello_init(db: *mut sqlite3)"));
+    let manifest = fs::read_to_string(dir.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains(r#"crate-type = ["cdylib", "staticlib", "rlib"]"#));
+    let header = fs::read_to_string(dir.join("include/sqlite_hello.h")).unwrap();
+    assert!(header.contains("int sqlite3_sqlitehello_init(sqlite3 *db,"));
+
+    // a directory that isn't empty is left alone
+    let status = Command::new(env!("CARGO_BIN_EXE_cargo-sqlite-extension"))
+        .args(["new", "other", "--path"])
+        .arg(&dir)
+        .status()
+        .unwrap();
+    assert!(!status.success());
+
+    // the new crate's own test passes
+    let target = workspace.join("target/scaffold");
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let status = Command::new(&cargo)
+        .args(["test", "--quiet"])
+        .current_dir(&dir)
+        .env("CARGO_TARGET_DIR", &target)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    // and with the static feature, its staticlib links into a program built
+    // with the amalgamation
+    let status = Command::new(&cargo)
+        .args(["build", "--quiet", "--features", "static"])
+        .current_dir(&dir)
+        .env("CARGO_TARGET_DIR", &target)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let amalgamation = workspace.join("sqlite3ext-sys/sqlite3");
+    if !amalgamation.join("sqlite3.c").exists() || !cc_available() {
+        eprintln!("no C compiler or amalgamation, skipping the embedded build");
+    } else {
+        let program = target.join("embed");
+        let status = Command::new("cc")
+            .arg("-I")
+            .arg(&amalgamation)
+            .arg("-I")
+            .arg(dir.join("include"))
+            .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/embed.c"))
+            .arg(amalgamation.join("sqlite3.c"))
+            .arg(target.join("debug/libsqlite_hello.a"))
+            // drops the rtree helpers, this amalgamation is built without rtree
+            .arg(if cfg!(target_os = "macos") {
+                "-Wl,-dead_strip"
+            } else {
+                "-Wl,--gc-sections"
+            })
+            .args(["-lm", "-lpthread", "-ldl", "-o"])
+            .arg(&program)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert!(Command::new(&program).status().unwrap().success());
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+fn cc_available() -> bool {
+    Command::new("cc")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
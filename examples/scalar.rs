@@ -2,7 +2,7 @@
 //! sqlite3 :memory: '.read examples/test.sql'
 
 use sqlite_loadable::prelude::*;
-use sqlite_loadable::{api, define_scalar_function, Result};
+use sqlite_loadable::{api, define_pure_function, define_scalar_function, Result};
 
 // yo()
 fn yo(context: *mut sqlite3_context, _values: &[*mut sqlite3_value]) -> Result<()> {
@@ -45,5 +45,6 @@ pub fn sqlite3_scalarrs_init(db: *mut sqlite3) -> Result<()> {
     define_scalar_function(db, "connect", -1, connect, flags)?;
     define_scalar_function(db, "yo_rs", 0, yo, flags)?;
     define_scalar_function(db, "add_rs", 2, add, flags)?;
+    define_pure_function(db, "add_pure_rs", |a: i32, b: i32| a.wrapping_add(b), flags)?;
     Ok(())
 }
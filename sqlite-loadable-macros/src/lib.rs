@@ -1,9 +1,9 @@
-use proc_macro2::Ident;
+use proc_macro2::{Ident, Span};
 
 use syn::{parse_macro_input, spanned::Spanned, Item};
 
 use proc_macro::TokenStream;
-use quote::quote_spanned;
+use quote::{quote, quote_spanned};
 
 /// Wraps an entrypoint function to expose an unsafe extern "C" function of the same name.
 #[proc_macro_attribute]
@@ -84,3 +84,212 @@ pub fn sqlite_entrypoint_permanent(_attr: TokenStream, item: TokenStream) -> Tok
         _ => panic!("Only function items are allowed on sqlite_entrypoint"),
     }
 }
+
+/// Derives `sqlite_loadable::static_table::StaticTable` for a struct with named fields,
+/// with a column for each field in order.
+#[proc_macro_derive(StaticTable)]
+pub fn derive_static_table(item: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(item as syn::DeriveInput);
+    let name = ast.ident;
+    if !ast.generics.params.is_empty() {
+        panic!("StaticTable can't be derived for generic structs");
+    }
+    let fields = match ast.data {
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Named(fields),
+            ..
+        }) => fields.named,
+        _ => panic!("StaticTable can only be derived for structs with named fields"),
+    };
+
+    let mut columns = vec![];
+    let mut results = vec![];
+    for (i, field) in fields.iter().enumerate() {
+        let ident = field.ident.clone().expect("named fields have names");
+        let ty = &field.ty;
+        let column = ident.to_string().trim_start_matches("r#").to_owned();
+        let i = i as i32;
+        columns.push(quote! {
+            (#column, <#ty as ::sqlite_loadable::static_table::StaticColumn>::SQL_TYPE)
+        });
+        results.push(quote! {
+            #i => ::sqlite_loadable::static_table::StaticColumn::result(&self.#ident, context),
+        });
+    }
+
+    quote_spanned! {name.span()=>
+        impl ::sqlite_loadable::static_table::StaticTable for #name {
+            const POINTER_TYPE: &'static [u8] = concat!(
+                "sqlite-loadable:", module_path!(), "::", stringify!(#name), "\0"
+            ).as_bytes();
+
+            fn columns() -> Vec<(&'static str, &'static str)> {
+                vec![#(#columns),*]
+            }
+
+            fn column(
+                &self,
+                context: *mut ::sqlite_loadable::prelude::sqlite3_context,
+                i: ::std::os::raw::c_int,
+            ) -> ::sqlite_loadable::Result<()> {
+                match i {
+                    #(#results)*
+                    _ => Ok(()),
+                }
+            }
+        }
+    }
+    .into()
+}
+
+/// Turns a plain Rust function into an SQL scalar function. Alongside the
+/// function, it generates a struct of the same name with the glue:
+/// `NAME`, `NUM_ARGS`, a `call` callback that converts the arguments with
+/// `sqlite_loadable::Args` and the return value with
+/// `sqlite_loadable::ToResult`, and `define(db)` to register it. Functions
+/// live in a different namespace than braced structs, so the two don't
+/// clash, even in a function body.
+///
+/// Takes an optional `name = "..."` for the SQL name, and the flags
+/// `deterministic`, `innocuous`, `directonly` and `subtype`, which lets the
+/// function both read its arguments' subtypes and set one on its result.
+#[proc_macro_attribute]
+pub fn scalar_function(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attr as syn::AttributeArgs);
+    let func = parse_macro_input!(item as syn::Item);
+    match expand_scalar_function(args, func) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand_scalar_function(
+    args: syn::AttributeArgs,
+    func: syn::Item,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let func = match func {
+        Item::Fn(func) => func,
+        other => {
+            return Err(syn::Error::new_spanned(
+                other,
+                "Only function items are allowed on scalar_function",
+            ))
+        }
+    };
+    if let Some(asyncness) = &func.sig.asyncness {
+        return Err(syn::Error::new_spanned(
+            asyncness,
+            "scalar_function can't be async",
+        ));
+    }
+    // the glue calls the function by name, with nothing to infer the
+    // generic parameters from
+    if !func.sig.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &func.sig.generics,
+            "scalar_function can't be generic",
+        ));
+    }
+
+    let ident = func.sig.ident.clone();
+    let vis = func.vis.clone();
+    let mut name = ident.to_string().trim_start_matches("r#").to_owned();
+    let mut flags = vec![quote! { ::sqlite_loadable::FunctionFlags::UTF8 }];
+    for arg in args {
+        match arg {
+            syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                path,
+                lit: syn::Lit::Str(lit),
+                ..
+            })) if path.is_ident("name") => name = lit.value(),
+            syn::NestedMeta::Meta(syn::Meta::Path(path)) => {
+                let names: &[&str] =
+                    match path.get_ident().map(|ident| ident.to_string()).as_deref() {
+                        Some("deterministic") => &["DETERMINISTIC"],
+                        Some("innocuous") => &["INNOCUOUS"],
+                        Some("directonly") => &["DIRECTONLY"],
+                        Some("subtype") => &["SUBTYPE", "RESULT_SUBTYPE"],
+                        _ => {
+                            return Err(syn::Error::new_spanned(
+                                &path,
+                                format!("unknown scalar_function flag {}", quote!(#path)),
+                            ))
+                        }
+                    };
+                for flag in names {
+                    let flag = Ident::new(flag, path.span());
+                    flags.push(quote! { ::sqlite_loadable::FunctionFlags::#flag });
+                }
+            }
+            other => {
+                return Err(syn::Error::new_spanned(
+                    &other,
+                    format!("unknown scalar_function argument {}", quote!(#other)),
+                ))
+            }
+        }
+    }
+
+    // the glue's own variables can't shadow a function named like them
+    let context = Ident::new("context", Span::mixed_site());
+    let values = Ident::new("values", Span::mixed_site());
+    let args_ident = Ident::new("args", Span::mixed_site());
+    let mut arguments = vec![];
+    for (i, input) in func.sig.inputs.iter().enumerate() {
+        if let syn::FnArg::Receiver(receiver) = input {
+            return Err(syn::Error::new_spanned(
+                receiver,
+                "scalar_function can't take self",
+            ));
+        }
+        arguments.push(quote! { #args_ident.get_at(#i)? });
+    }
+    let num_args = arguments.len() as i32;
+
+    // a function returning Result fails with its error, anything else is
+    // the function's result
+    let returns_result = match &func.sig.output {
+        syn::ReturnType::Type(_, ty) => match ty.as_ref() {
+            syn::Type::Path(path) => path
+                .path
+                .segments
+                .last()
+                .is_some_and(|segment| segment.ident == "Result"),
+            _ => false,
+        },
+        syn::ReturnType::Default => false,
+    };
+    let call = quote! { #ident(#(#arguments),*) };
+    let result = if returns_result {
+        quote! { #call? }
+    } else {
+        quote! { #call }
+    };
+
+    Ok(quote_spanned! {func.span()=>
+        #func
+
+        #[doc = concat!("SQL glue for [`", stringify!(#ident), "()`].")]
+        #[allow(non_camel_case_types)]
+        #vis struct #ident {}
+
+        #[allow(dead_code)]
+        impl #ident {
+            pub const NAME: &'static str = #name;
+            pub const NUM_ARGS: ::std::os::raw::c_int = #num_args;
+
+            #[allow(unused_variables)]
+            pub fn call(
+                #context: *mut ::sqlite_loadable::prelude::sqlite3_context,
+                #values: &[*mut ::sqlite_loadable::prelude::sqlite3_value],
+            ) -> ::sqlite_loadable::Result<()> {
+                let #args_ident = ::sqlite_loadable::Args::new(#values);
+                ::sqlite_loadable::ToResult::to_result(#result, #context)
+            }
+
+            pub fn define(db: *mut ::sqlite_loadable::prelude::sqlite3) -> ::sqlite_loadable::Result<()> {
+                ::sqlite_loadable::define_scalar_function(db, Self::NAME, Self::NUM_ARGS, Self::call, #(#flags)|*)
+            }
+        }
+    })
+}
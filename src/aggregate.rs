@@ -0,0 +1,267 @@
+//! Define aggregate functions on sqlite3 database connections.
+//!
+//! An [`Aggregate`] is the state of one call of an aggregate function,
+//! made with `Default` for each group, given every row's arguments with
+//! [`Aggregate::step`], and turned into the function's result by
+//! [`Aggregate::fnl`]:
+//!
+//! ```ignore
+//! #[derive(Default)]
+//! struct Median(Vec<f64>);
+//!
+//! impl Aggregate for Median {
+//!     fn step(&mut self, values: &[*mut sqlite3_value]) -> Result<()> {
+//!         if !api::value_is_null(&values[0]) {
+//!             self.0.push(api::value_double(&values[0]));
+//!         }
+//!         Ok(())
+//!     }
+//!
+//!     fn fnl(mut self, context: *mut sqlite3_context) -> Result<()> {
+//!         self.0.sort_by(f64::total_cmp);
+//!         match self.0.get(self.0.len() / 2) {
+//!             Some(median) => api::result_double(context, *median),
+//!             None => api::result_null(context),
+//!         }
+//!         Ok(())
+//!     }
+//! }
+//!
+//! define_aggregate_function::<Median>(db, "median", 1, FunctionFlags::UTF8)?;
+//! ```
+//!
+//! A [`WindowAggregate`] can also be used as a window function, with
+//! [`define_window_function`]. It gives the result for the current window
+//! with [`WindowAggregate::value`], and removes rows as they leave the
+//! window with [`WindowAggregate::inverse`], so a moving average over
+//! `rows between 2 preceding and current row` only ever sees each row twice.
+//!
+//! [`AggregateContext`] is the same state handling, for functions defined
+//! with raw callbacks.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api;
+use crate::constants::{SQLITE_INTERNAL, SQLITE_OKAY};
+use crate::errors::{Error, ErrorKind, Result};
+use crate::ext::{
+    sqlite3, sqlite3_context, sqlite3_value, sqlite3ext_aggregate_context,
+    sqlite3ext_create_window_function,
+};
+use crate::scalar::{create_function_v2, FunctionFlags};
+use std::ffi::CString;
+use std::marker::PhantomData;
+use std::os::raw::c_int;
+use std::{mem, ptr, slice};
+
+/// The state of one call of an aggregate function.
+pub trait Aggregate: Default {
+    /// Adds the arguments of one row.
+    fn step(&mut self, values: &[*mut sqlite3_value]) -> Result<()>;
+
+    /// Sets the function's result. `self` is the default for a group
+    /// without rows, like an aggregate over an empty table.
+    fn fnl(self, context: *mut sqlite3_context) -> Result<()>;
+}
+
+/// An [`Aggregate`] that can be a window function too.
+pub trait WindowAggregate: Aggregate {
+    /// Sets the function's result for the current window, leaving `self`
+    /// as it is for the windows after it.
+    fn value(&self, context: *mut sqlite3_context) -> Result<()>;
+
+    /// Removes the arguments of the row that's leaving the window, the
+    /// oldest one [`Aggregate::step`] added that's still in it.
+    fn inverse(&mut self, values: &[*mut sqlite3_value]) -> Result<()>;
+}
+
+/// The state of one call of an aggregate function, kept in its
+/// [`sqlite3_aggregate_context`](https://www.sqlite.org/c3ref/aggregate_context.html).
+/// The context holds a pointer to a boxed `T`, made with `T::default()`
+/// the first time it's needed, so a `T` of any size can be kept there, and
+/// dropped exactly once, when [`AggregateContext::take`] takes it in the
+/// final call.
+///
+/// ```ignore
+/// unsafe extern "C" fn x_step(context: *mut sqlite3_context, argc: c_int, argv: *mut *mut sqlite3_value) {
+///     if let Ok(sum) = AggregateContext::<f64>::new(context).get_mut() {
+///         *sum += api::value_double(&*argv);
+///     }
+/// }
+///
+/// unsafe extern "C" fn x_final(context: *mut sqlite3_context) {
+///     api::result_double(context, AggregateContext::<f64>::new(context).take());
+/// }
+/// ```
+///
+/// Every call with the same `context` must use the same `T`.
+pub struct AggregateContext<T> {
+    context: *mut sqlite3_context,
+    state: PhantomData<T>,
+}
+
+impl<T: Default> AggregateContext<T> {
+    pub fn new(context: *mut sqlite3_context) -> AggregateContext<T> {
+        AggregateContext {
+            context,
+            state: PhantomData,
+        }
+    }
+
+    /// The slot in the aggregate context that points to the state,
+    /// allocated by SQLite on the first call when `create`, and null
+    /// otherwise.
+    fn slot(&self, create: bool) -> *mut *mut T {
+        let size = if create {
+            mem::size_of::<*mut T>() as c_int
+        } else {
+            0
+        };
+        unsafe { sqlite3ext_aggregate_context(self.context, size).cast::<*mut T>() }
+    }
+
+    /// The state, made with `T::default()` the first time. Fails if SQLite
+    /// can't allocate the aggregate context.
+    pub fn get_mut(&mut self) -> Result<&mut T> {
+        let slot = self.slot(true);
+        if slot.is_null() {
+            return Err(Error::new_message("out of memory for the aggregate state"));
+        }
+        unsafe {
+            if (*slot).is_null() {
+                *slot = Box::into_raw(Box::default());
+            }
+            Ok(&mut **slot)
+        }
+    }
+
+    /// The state, if it's been made, without making it.
+    pub fn get(&self) -> Option<&T> {
+        let slot = self.slot(false);
+        unsafe {
+            if slot.is_null() || (*slot).is_null() {
+                None
+            } else {
+                Some(&**slot)
+            }
+        }
+    }
+
+    /// Takes the state out of the aggregate context, for the final call, or
+    /// `T::default()` if it was never made. Taking it again gets another
+    /// default, so the state is only ever dropped once.
+    pub fn take(self) -> T {
+        let slot = self.slot(false);
+        unsafe {
+            if slot.is_null() || (*slot).is_null() {
+                T::default()
+            } else {
+                *Box::from_raw(mem::replace(&mut *slot, ptr::null_mut()))
+            }
+        }
+    }
+}
+
+fn result_error(context: *mut sqlite3_context, err: Error) {
+    if api::result_error(context, &err.reported_message()).is_err() {
+        api::result_error_code(context, SQLITE_INTERNAL);
+    }
+}
+
+unsafe extern "C" fn x_step<A: Aggregate>(
+    context: *mut sqlite3_context,
+    argc: c_int,
+    argv: *mut *mut sqlite3_value,
+) {
+    let values = slice::from_raw_parts(argv, argc as usize);
+    if let Err(err) = AggregateContext::<A>::new(context)
+        .get_mut()
+        .and_then(|state| state.step(values))
+    {
+        result_error(context, err);
+    }
+}
+
+unsafe extern "C" fn x_final<A: Aggregate>(context: *mut sqlite3_context) {
+    if let Err(err) = AggregateContext::<A>::new(context).take().fnl(context) {
+        result_error(context, err);
+    }
+}
+
+unsafe extern "C" fn x_value<W: WindowAggregate>(context: *mut sqlite3_context) {
+    if let Err(err) = AggregateContext::<W>::new(context)
+        .get_mut()
+        .and_then(|state| state.value(context))
+    {
+        result_error(context, err);
+    }
+}
+
+unsafe extern "C" fn x_inverse<W: WindowAggregate>(
+    context: *mut sqlite3_context,
+    argc: c_int,
+    argv: *mut *mut sqlite3_value,
+) {
+    let values = slice::from_raw_parts(argv, argc as usize);
+    if let Err(err) = AggregateContext::<W>::new(context)
+        .get_mut()
+        .and_then(|state| state.inverse(values))
+    {
+        result_error(context, err);
+    }
+}
+
+/// Defines a new aggregate function on the given database connection, with
+/// a new `A` for each group.
+pub fn define_aggregate_function<A: Aggregate>(
+    db: *mut sqlite3,
+    name: &str,
+    num_args: c_int,
+    func_flags: FunctionFlags,
+) -> Result<()> {
+    create_function_v2(
+        db,
+        name,
+        num_args,
+        func_flags,
+        ptr::null_mut(),
+        None,
+        Some(x_step::<A>),
+        Some(x_final::<A>),
+        None,
+    )
+}
+
+/// Defines a new aggregate function on the given database connection that
+/// can also be used as a window function, with a new `W` for each group or
+/// partition.
+pub fn define_window_function<W: WindowAggregate>(
+    db: *mut sqlite3,
+    name: &str,
+    num_args: c_int,
+    func_flags: FunctionFlags,
+) -> Result<()> {
+    let cname = CString::new(name)?;
+    let result = unsafe {
+        sqlite3ext_create_window_function(
+            db,
+            cname.as_ptr(),
+            num_args,
+            func_flags.bits(),
+            ptr::null_mut(),
+            Some(x_step::<W>),
+            Some(x_final::<W>),
+            Some(x_value::<W>),
+            Some(x_inverse::<W>),
+            None,
+        )
+    };
+    if result != SQLITE_OKAY {
+        Err(Error::with_db_message(
+            ErrorKind::DefineScalarFunction(result),
+            result,
+            db,
+        ))
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+//! Old names for renamed scalar functions, so SQL that still uses them
+//! keeps working while its authors move to the new ones.
+//!
+//! ```ignore
+//! define_scalar_function(db, "xyz_parse", 1, parse, FunctionFlags::UTF8 | FunctionFlags::DETERMINISTIC)?;
+//! register_alias(db, "xyz_parse_text", "xyz_parse", FunctionFlags::UTF8 | FunctionFlags::DETERMINISTIC)?;
+//! ```
+//!
+//! An alias calls the new function with its arguments and returns what it
+//! does, errors included. The first call through an alias, per extension
+//! library, writes a deprecation notice naming the new function to
+//! SQLite's [error log](https://www.sqlite.org/errlog.html).
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api;
+use crate::errors::{Error, Result};
+use crate::ext::sqlite3;
+use crate::scalar::{define_scalar_function, FunctionFlags};
+use crate::statement::Statement;
+use std::collections::BTreeSet;
+use std::os::raw::c_int;
+use std::sync::Mutex;
+
+/// Aliases that have logged their deprecation notice.
+static NOTIFIED: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// Defines `old_name` as a function of any number of arguments that
+/// forwards to `new_name`. `func_flags` should be the new function's, so
+/// the alias can be used wherever it can, like in an index on an
+/// expression.
+///
+/// A call through the alias runs a `SELECT new_name(...)` on the same
+/// connection, which costs more than calling `new_name` directly.
+pub fn register_alias(
+    db: *mut sqlite3,
+    old_name: &str,
+    new_name: &str,
+    func_flags: FunctionFlags,
+) -> Result<()> {
+    let alias = old_name.to_owned();
+    let new_name = new_name.to_owned();
+    define_scalar_function(
+        db,
+        old_name,
+        -1,
+        move |context, values| {
+            notify(&alias, &new_name);
+            let params = (1..=values.len())
+                .map(|i| format!("?{}", i))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let sql = format!("SELECT \"{}\"({})", new_name.replace('"', "\"\""), params);
+            let db = api::context_db_handle(context);
+            let mut stmt = Statement::prepare(db, &sql).map_err(forwarded)?;
+            for (i, value) in values.iter().enumerate() {
+                stmt.bind_value(i as c_int + 1, *value)?;
+            }
+            stmt.step().map_err(forwarded)?;
+            api::result_value(context, &stmt.column_value(0));
+            Ok(())
+        },
+        func_flags | FunctionFlags::RESULT_SUBTYPE,
+    )
+}
+
+/// Logs that `old_name` is deprecated, the first time it's called.
+fn notify(old_name: &str, new_name: &str) {
+    let mut notified = NOTIFIED.lock().unwrap_or_else(|err| err.into_inner());
+    if notified.insert(old_name.to_owned()) {
+        api::log(
+            sqlite3ext_sys::SQLITE_WARNING as c_int,
+            &format!(
+                "{}() is deprecated and will be removed, use {}() instead",
+                old_name, new_name
+            ),
+        );
+    }
+}
+
+/// The new function's own error, without the statement that called it.
+fn forwarded(err: Error) -> Error {
+    match err.sqlite_message() {
+        Some(details) => Error::new_message(&details.message),
+        None => err,
+    }
+}
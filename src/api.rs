@@ -7,17 +7,22 @@
 
 use crate::constants::SQLITE_OKAY;
 use crate::ext::{
-    sqlite3, sqlite3_context, sqlite3_value, sqlite3ext_context_db_handle, sqlite3ext_get_auxdata,
-    sqlite3ext_mprintf, sqlite3ext_overload_function, sqlite3ext_result_blob,
-    sqlite3ext_result_double, sqlite3ext_result_error, sqlite3ext_result_error_code,
-    sqlite3ext_result_int, sqlite3ext_result_int64, sqlite3ext_result_null,
-    sqlite3ext_result_pointer, sqlite3ext_result_subtype, sqlite3ext_result_text,
-    sqlite3ext_set_auxdata, sqlite3ext_value_blob, sqlite3ext_value_bytes, sqlite3ext_value_double,
-    sqlite3ext_value_int, sqlite3ext_value_int64, sqlite3ext_value_pointer,
-    sqlite3ext_value_subtype, sqlite3ext_value_text, sqlite3ext_value_type,
+    sqlite3, sqlite3_context, sqlite3_value, sqlite3ext_context_db_handle, sqlite3ext_db_filename,
+    sqlite3ext_db_name, sqlite3ext_db_readonly, sqlite3ext_get_auxdata,
+    sqlite3ext_libversion_number, sqlite3ext_log, sqlite3ext_memory_used, sqlite3ext_mprintf,
+    sqlite3ext_overload_function, sqlite3ext_result_blob, sqlite3ext_result_double,
+    sqlite3ext_result_error, sqlite3ext_result_error_code, sqlite3ext_result_int,
+    sqlite3ext_result_int64, sqlite3ext_result_null, sqlite3ext_result_pointer,
+    sqlite3ext_result_subtype, sqlite3ext_result_text, sqlite3ext_result_value,
+    sqlite3ext_set_auxdata, sqlite3ext_soft_heap_limit64, sqlite3ext_txn_state,
+    sqlite3ext_value_blob, sqlite3ext_value_bytes, sqlite3ext_value_double, sqlite3ext_value_int,
+    sqlite3ext_value_int64, sqlite3ext_value_pointer, sqlite3ext_value_subtype,
+    sqlite3ext_value_text, sqlite3ext_value_type,
 };
+use crate::vtab_argparse::ColumnDeclaration;
 use crate::Error;
 use sqlite3ext_sys::{SQLITE_BLOB, SQLITE_FLOAT, SQLITE_INTEGER, SQLITE_NULL, SQLITE_TEXT};
+use std::borrow::Cow;
 use std::os::raw::c_int;
 use std::slice::from_raw_parts;
 use std::str::Utf8Error;
@@ -114,11 +119,25 @@ pub fn mprintf(base: &str) -> Result<*mut c_char, MprintfError> {
     }
 }
 
+/// Writes `message` to SQLite's [error log](https://www.sqlite.org/errlog.html)
+/// with the result code `code`, for the callback a program set with
+/// `SQLITE_CONFIG_LOG`, if any. `message` isn't a format string, so it can
+/// have "%"s.
+pub fn log(code: c_int, message: &str) {
+    // a message with a NUL is cut short there rather than dropped
+    let message = CString::new(message.split('\0').next().unwrap_or_default()).unwrap_or_default();
+    unsafe { sqlite3ext_log(code, message.as_ptr()) };
+}
+
 /// Returns the [`sqlite3_value_blob`](https://www.sqlite.org/c3ref/value_blob.html) result
 /// from the given sqlite3_value, as a u8 slice.
 pub fn value_blob<'a>(value: &*mut sqlite3_value) -> &'a [u8] {
     let n = value_bytes(value);
     let b = unsafe { sqlite3ext_value_blob(value.to_owned()) };
+    // zero-length blobs are NULL pointers, which from_raw_parts doesn't allow
+    if b.is_null() || n == 0 {
+        return &[];
+    }
     return unsafe { from_raw_parts(b.cast::<u8>(), n as usize) };
 }
 
@@ -144,6 +163,27 @@ pub fn value_text<'a>(value: &*mut sqlite3_value) -> Result<&'a str, Utf8Error>
     }
 }
 
+/// Like [`value_text`], but invalid UTF-8 is replaced with U+FFFD
+/// (the replacement character) instead of failing. Only allocates if there's
+/// something to replace.
+pub fn value_text_lossy<'a>(value: &*mut sqlite3_value) -> Cow<'a, str> {
+    // sqlite3_value_text before sqlite3_value_bytes, so the byte count is of
+    // the converted text
+    let c_string = unsafe { sqlite3ext_value_text(value.to_owned()) };
+    let n = value_bytes(value);
+    if c_string.is_null() || n == 0 {
+        return Cow::Borrowed("");
+    }
+    String::from_utf8_lossy(unsafe { from_raw_parts(c_string, n as usize) })
+}
+
+/// The raw bytes of a value (the same as [`value_blob`]), decoded as UTF-8
+/// with invalid sequences replaced with U+FFFD. Unlike [`value_text_lossy`],
+/// blobs are used as-is rather than converted by SQLite.
+pub fn value_bytes_as_text<'a>(value: &*mut sqlite3_value) -> Cow<'a, str> {
+    String::from_utf8_lossy(value_blob(value))
+}
+
 pub fn value_text_notnull<'a>(value: &*mut sqlite3_value) -> Result<&'a str, Error> {
     if value_type(value) == ValueType::Null {
         return Err(Error::new_message("Unexpected null value"));
@@ -182,12 +222,144 @@ pub fn value_int64(value: &*mut sqlite3_value) -> i64 {
     unsafe { sqlite3ext_value_int64(value.to_owned()) }
 }
 
+/// Parses the text forms of a boolean that SQLite's PRAGMAs accept: `true`,
+/// `on`, `yes` and `1`, or `false`, `off`, `no` and `0`, in any case.
+pub fn parse_bool(text: &str) -> Option<bool> {
+    match text.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "on" | "yes" => Some(true),
+        "0" | "false" | "off" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+/// The value as a boolean: the integers 0 and 1, or any text
+/// [`parse_bool`] accepts. Anything else is an error, rather than
+/// quietly true or false.
+pub fn value_bool(value: &*mut sqlite3_value) -> crate::Result<bool> {
+    let (parsed, got) = match value_type(value) {
+        ValueType::Integer => {
+            let i = value_int64(value);
+            let parsed = match i {
+                0 => Some(false),
+                1 => Some(true),
+                _ => None,
+            };
+            (parsed, i.to_string())
+        }
+        ValueType::Text => {
+            let text = value_text(value)?;
+            (parse_bool(text), format!("'{}'", text))
+        }
+        ValueType::Float => (None, "a real".to_owned()),
+        ValueType::Blob => (None, "a blob".to_owned()),
+        ValueType::Null => (None, "NULL".to_owned()),
+    };
+    parsed.ok_or_else(|| {
+        Error::new_message(format!(
+            "expected a boolean (0, 1, true, false, on, off, yes or no), got {}",
+            got
+        ))
+    })
+}
+
+/// The value as a duration: a number of seconds, as an integer, real or
+/// text, or text like `5m`, `2h30m` or `PT1H30M`, see
+/// [`crate::duration`]. Negative and other invalid durations are errors.
+pub fn value_duration(value: &*mut sqlite3_value) -> crate::Result<std::time::Duration> {
+    match value_type(value) {
+        ValueType::Integer | ValueType::Float => crate::duration::seconds(value_double(value)),
+        ValueType::Text => crate::duration::parse_duration(value_text(value)?),
+        ValueType::Blob => Err(Error::new_message("expected a duration, got a blob")),
+        ValueType::Null => Err(Error::new_message("expected a duration, got NULL")),
+    }
+}
+
+/// What to do when an integer argument doesn't fit the type it's wanted as,
+/// like a negative size or an index past `i32::MAX`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// Fail with an error that says which value didn't fit.
+    Error,
+    /// Use the nearest value that fits, so -1 is 0 as a `u64`.
+    Clamp,
+    /// Keep the low bits, like a C cast, so -1 is `u64::MAX`.
+    Wrap,
+}
+
+/// Converts `i` to `T` by `overflow`, with `wrap` and the type's bounds for
+/// the values that don't fit.
+fn convert_int<T: TryFrom<i64> + Copy>(
+    i: i64,
+    overflow: Overflow,
+    (min, max): (T, T),
+    wrap: fn(i64) -> T,
+    type_name: &str,
+) -> crate::Result<T> {
+    match T::try_from(i) {
+        Ok(converted) => Ok(converted),
+        Err(_) => match overflow {
+            Overflow::Error => Err(Error::new_message(format!(
+                "{} is out of range for {}",
+                i, type_name
+            ))),
+            Overflow::Clamp if i < 0 => Ok(min),
+            Overflow::Clamp => Ok(max),
+            Overflow::Wrap => Ok(wrap(i)),
+        },
+    }
+}
+
+/// The value as an i32, like [`value_int`], but with an explicit policy for
+/// integers outside i32's range instead of always keeping the low 32 bits.
+pub fn value_int_checked(value: &*mut sqlite3_value, overflow: Overflow) -> crate::Result<i32> {
+    convert_int(
+        value_int64(value),
+        overflow,
+        (i32::MIN, i32::MAX),
+        |i| i as i32,
+        "a 32-bit integer",
+    )
+}
+
+/// The value as a u64, with an explicit policy for negative integers.
+pub fn value_u64(value: &*mut sqlite3_value, overflow: Overflow) -> crate::Result<u64> {
+    convert_int(
+        value_int64(value),
+        overflow,
+        (0, u64::MAX),
+        |i| i as u64,
+        "an unsigned integer",
+    )
+}
+
+/// The value as a usize, for sizes and indexes, with an explicit policy
+/// for negative integers, and on 32-bit targets ones that are too large.
+pub fn value_usize(value: &*mut sqlite3_value, overflow: Overflow) -> crate::Result<usize> {
+    convert_int(
+        value_int64(value),
+        overflow,
+        (0, usize::MAX),
+        |i| i as usize,
+        "a size",
+    )
+}
+
 /// Returns the [`sqlite3_value_double`](https://www.sqlite.org/c3ref/value_blob.html) result
 /// from the given sqlite3_value, as f64.
 pub fn value_double(value: &*mut sqlite3_value) -> f64 {
     unsafe { sqlite3ext_value_double(value.to_owned()) }
 }
+
+/// Parses the given value as JSON, however the caller made it: text from
+/// `json()` (with the 'J' subtype) or plain text, a
+/// [JSONB](https://sqlite.org/jsonb.html) blob from `jsonb()`, or a
+/// number. A blob that isn't valid JSONB is parsed as JSON text.
 pub fn value_json(value: &*mut sqlite3_value) -> serde_json::Result<serde_json::Value> {
+    if value_type(value) == ValueType::Blob {
+        if let Ok(json) = crate::jsonb::decode(value_blob(value)) {
+            return Ok(json);
+        }
+    }
     serde_json::from_slice(value_blob(value))
 }
 
@@ -235,13 +407,11 @@ pub fn value_subtype(value: &*mut sqlite3_value) -> u32 {
 // TODO test
 pub fn value_has_pointer_subtype(value: &*mut sqlite3_value) -> bool {
     // https://github.com/sqlite/sqlite/blob/cc19bed8b10f4584d39aeb3e72fb6c30c3355955/src/vdbemem.c#L957
-    // 112 == 'p'
-    value_subtype(value) == 112
+    value_subtype(value) == u32::from(crate::subtype::POINTER)
 }
 pub fn value_has_json_subtype(value: &*mut sqlite3_value) -> bool {
     // https://github.com/sqlite/sqlite/blob/cc19bed8b10f4584d39aeb3e72fb6c30c3355955/src/json.c#L89
-    // 74 == 'p'
-    value_subtype(value) == 74
+    value_subtype(value) == u32::from(crate::subtype::JSON)
 }
 
 /// Calls [`sqlite3_result_text`](https://www.sqlite.org/c3ref/result_blob.html)
@@ -302,6 +472,12 @@ pub fn result_null(context: *mut sqlite3_context) {
     unsafe { sqlite3ext_result_null(context) };
 }
 
+/// Calls [`sqlite3_result_value`](https://www.sqlite.org/c3ref/result_blob.html)
+/// to return a copy of `value`, whatever its type.
+pub fn result_value(context: *mut sqlite3_context, value: &*mut sqlite3_value) {
+    unsafe { sqlite3ext_result_value(context, value.to_owned()) };
+}
+
 /// Calls [`sqlite3_result_error`](https://www.sqlite.org/c3ref/result_blob.html)
 /// to represent that a function returns an error with the given value.
 /// Note: You can typically rely on [`crate::Result`] to do this for you.
@@ -343,7 +519,49 @@ pub fn result_bool(context: *mut sqlite3_context, value: bool) {
 pub fn result_json(context: *mut sqlite3_context, value: serde_json::Value) -> crate::Result<()> {
     result_text(context, value.to_string().as_str())?;
     // https://github.com/sqlite/sqlite/blob/master/src/json.c#L88-L89
-    result_subtype(context, b'J');
+    result_subtype(context, crate::subtype::JSON);
+    Ok(())
+}
+
+/// Deserializes a MessagePack-encoded blob into `T`.
+#[cfg(feature = "msgpack")]
+pub fn value_msgpack<T: serde::de::DeserializeOwned>(
+    value: &*mut sqlite3_value,
+) -> crate::Result<T> {
+    rmp_serde::from_slice(value_blob(value))
+        .map_err(|err| Error::new_message(format!("invalid MessagePack: {}", err)))
+}
+
+/// Results `value` as a MessagePack-encoded blob. Structs are encoded as maps,
+/// so field names are kept like in JSON.
+#[cfg(feature = "msgpack")]
+pub fn result_msgpack<T: serde::Serialize>(
+    context: *mut sqlite3_context,
+    value: &T,
+) -> crate::Result<()> {
+    let blob = rmp_serde::to_vec_named(value)
+        .map_err(|err| Error::new_message(format!("could not encode MessagePack: {}", err)))?;
+    result_blob(context, &blob);
+    Ok(())
+}
+
+/// Deserializes a CBOR-encoded blob into `T`.
+#[cfg(feature = "cbor")]
+pub fn value_cbor<T: serde::de::DeserializeOwned>(value: &*mut sqlite3_value) -> crate::Result<T> {
+    ciborium::de::from_reader(value_blob(value))
+        .map_err(|err| Error::new_message(format!("invalid CBOR: {}", err)))
+}
+
+/// Results `value` as a CBOR-encoded blob.
+#[cfg(feature = "cbor")]
+pub fn result_cbor<T: serde::Serialize>(
+    context: *mut sqlite3_context,
+    value: &T,
+) -> crate::Result<()> {
+    let mut blob = vec![];
+    ciborium::ser::into_writer(value, &mut blob)
+        .map_err(|err| Error::new_message(format!("could not encode CBOR: {}", err)))?;
+    result_blob(context, &blob);
     Ok(())
 }
 
@@ -351,6 +569,9 @@ pub fn result_json(context: *mut sqlite3_context, value: serde_json::Value) -> c
 pub fn result_subtype(context: *mut sqlite3_context, subtype: u8) {
     // Explanation for u8: "Only the lower 8 bits of the subtype T are preserved
     // in current versions of SQLite; higher order bits are discarded"
+    if cfg!(debug_assertions) {
+        crate::subtype::check_result(subtype);
+    }
     unsafe { sqlite3ext_result_subtype(context, subtype.into()) };
 }
 
@@ -402,6 +623,138 @@ pub fn overload_function(db: *mut sqlite3, func_name: &str, n_args: i32) -> crat
     }
     Ok(())
 }
+
+/// [`sqlite3_libversion_number`](https://www.sqlite.org/c3ref/libversion.html),
+/// the version of the SQLite the extension runs in, like 3039004 for 3.39.4.
+/// Routines newer than that version aren't there to call.
+pub fn libversion_number() -> i32 {
+    unsafe { sqlite3ext_libversion_number() }
+}
+
+/// [`sqlite3_memory_used`](https://www.sqlite.org/c3ref/memory_highwater.html),
+/// the number of bytes currently allocated by SQLite.
+pub fn memory_used() -> i64 {
+    unsafe { sqlite3ext_memory_used() }
+}
+
+/// The current [soft heap limit](https://www.sqlite.org/c3ref/hard_heap_limit64.html)
+/// in bytes, or None if there isn't one.
+pub fn soft_heap_limit() -> Option<i64> {
+    match unsafe { sqlite3ext_soft_heap_limit64(-1) } {
+        limit if limit > 0 => Some(limit),
+        _ => None,
+    }
+}
+
+/// [`sqlite3_db_filename`](https://www.sqlite.org/c3ref/db_filename.html),
+/// the full path of the given schema's ("main", "temp", or an attached
+/// name) database file. None for in-memory and temporary databases, and an
+/// error if there's no database with that name.
+pub fn db_filename(db: *mut sqlite3, schema: &str) -> crate::Result<Option<String>> {
+    let cschema = CString::new(schema)?;
+    let filename = unsafe { sqlite3ext_db_filename(db, cschema.as_ptr()) };
+    if filename.is_null() {
+        // the temp database isn't opened until something is stored in it
+        if schema.eq_ignore_ascii_case("temp") {
+            return Ok(None);
+        }
+        return Err(Error::new_message(format!("no such database: {}", schema)));
+    }
+    let filename = unsafe { CStr::from_ptr(filename) }.to_str()?;
+    Ok(if filename.is_empty() {
+        None
+    } else {
+        Some(filename.to_owned())
+    })
+}
+
+/// [`sqlite3_db_readonly`](https://www.sqlite.org/c3ref/db_readonly.html),
+/// whether the given schema's database is read-only, or an error if there's
+/// no database with that name.
+pub fn db_readonly(db: *mut sqlite3, schema: &str) -> crate::Result<bool> {
+    let cschema = CString::new(schema)?;
+    match unsafe { sqlite3ext_db_readonly(db, cschema.as_ptr()) } {
+        -1 => Err(Error::new_message(format!("no such database: {}", schema))),
+        readonly => Ok(readonly == 1),
+    }
+}
+
+/// The schema names of every database on the connection, with
+/// [`sqlite3_db_name`](https://www.sqlite.org/c3ref/db_name.html): "main",
+/// "temp", then attached databases in the order they were attached.
+/// Requires SQLite 3.39.0 or later.
+pub fn db_names(db: *mut sqlite3) -> crate::Result<Vec<String>> {
+    let mut names = vec![];
+    loop {
+        let name = unsafe { sqlite3ext_db_name(db, names.len() as c_int) };
+        if name.is_null() {
+            return Ok(names);
+        }
+        names.push(unsafe { CStr::from_ptr(name) }.to_str()?.to_owned());
+    }
+}
+/// The transaction state of a database, from
+/// [`sqlite3_txn_state`](https://www.sqlite.org/c3ref/txn_state.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TransactionState {
+    /// No transaction is open, aka SQLITE_TXN_NONE
+    None,
+    /// A read transaction is open, aka SQLITE_TXN_READ
+    Read,
+    /// A write transaction is open, aka SQLITE_TXN_WRITE
+    Write,
+}
+
+/// The transaction state of the given schema's database, or the highest
+/// state across all databases when `schema` is None. Errors if there's no
+/// database with that name.
+pub fn txn_state(db: *mut sqlite3, schema: Option<&str>) -> crate::Result<TransactionState> {
+    let cschema = schema.map(CString::new).transpose()?;
+    let state = unsafe {
+        sqlite3ext_txn_state(
+            db,
+            cschema.as_ref().map_or(std::ptr::null(), |s| s.as_ptr()),
+        )
+    };
+    match state {
+        0 => Ok(TransactionState::None),
+        1 => Ok(TransactionState::Read),
+        2 => Ok(TransactionState::Write),
+        _ => Err(Error::new_message(format!(
+            "no such database: {}",
+            schema.unwrap_or_default()
+        ))),
+    }
+}
+
+/// Errors with "{operation} cannot be run inside a transaction" if any
+/// transaction is open, for operations like VACUUM or changing journal modes.
+pub fn require_no_transaction(db: *mut sqlite3, operation: &str) -> crate::Result<()> {
+    match txn_state(db, None)? {
+        TransactionState::None => Ok(()),
+        _ => Err(Error::new_message(format!(
+            "{} cannot be run inside a transaction",
+            operation
+        ))),
+    }
+}
+
+/// Errors with "{operation} must be run inside a write transaction" unless
+/// the given schema's database has a write transaction open.
+pub fn require_write_transaction(
+    db: *mut sqlite3,
+    schema: &str,
+    operation: &str,
+) -> crate::Result<()> {
+    match txn_state(db, Some(schema))? {
+        TransactionState::Write => Ok(()),
+        _ => Err(Error::new_message(format!(
+            "{} must be run inside a write transaction",
+            operation
+        ))),
+    }
+}
+
 /// A columns "affinity". <https://www.sqlite.org/datatype3.html#type_affinity>
 /* TODO maybe include extra affinities?
 - JSON - parse as text, see if it's JSON, if so then set subtype
@@ -409,6 +762,7 @@ pub fn overload_function(db: *mut sqlite3, func_name: &str, n_args: i32) -> crat
 - datetime - idk man
 - interval - idk man
  */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ColumnAffinity {
     /// "char", "clob", or "text"
     Text,
@@ -497,6 +851,58 @@ impl ColumnAffinity {
     }
 }
 
+/// The affinities of a table's columns, worked out once from their declared
+/// types, so cursors can look them up by column number instead of calling
+/// [`ColumnAffinity::from_declared_type`] for every value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnAffinityMap {
+    affinities: Vec<ColumnAffinity>,
+}
+
+impl ColumnAffinityMap {
+    /// Builds the map from each column's declared type, in column order.
+    /// Columns without a declared type have BLOB affinity.
+    pub fn from_declared_types<I, S>(declared_types: I) -> Self
+    where
+        I: IntoIterator<Item = Option<S>>,
+        S: AsRef<str>,
+    {
+        ColumnAffinityMap {
+            affinities: declared_types
+                .into_iter()
+                .map(|declared_type| match declared_type {
+                    Some(declared_type) => {
+                        ColumnAffinity::from_declared_type(declared_type.as_ref())
+                    }
+                    None => ColumnAffinity::Blob,
+                })
+                .collect(),
+        }
+    }
+
+    /// Builds the map from columns parsed out of a virtual table's arguments.
+    pub fn from_columns(columns: &[ColumnDeclaration]) -> Self {
+        ColumnAffinityMap {
+            affinities: columns.iter().map(ColumnDeclaration::affinity).collect(),
+        }
+    }
+
+    /// The affinity of the column at index `column`, or None if there's no
+    /// such column.
+    pub fn get(&self, column: usize) -> Option<ColumnAffinity> {
+        self.affinities.get(column).copied()
+    }
+
+    /// The number of columns.
+    pub fn len(&self) -> usize {
+        self.affinities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.affinities.is_empty()
+    }
+}
+
 /// A columns "extended affinity". The traditional affinity does
 /// not include supplementary "types" that SQLite doesn't support
 /// out of the box, like JSON, boolean, or datetime. This is an
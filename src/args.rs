@@ -0,0 +1,435 @@
+//! Typed access to the arguments and results of a function.
+//!
+//! [`Args`] converts a function's `sqlite3_value`s to Rust types that
+//! implement [`FromValue`], checking their SQL types on the way, so a
+//! function doesn't have to call [`crate::api::value_type`] and the
+//! `value_*` getters itself:
+//!
+//! ```ignore
+//! fn repeat(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+//!     let (text, count, separator): (&str, i64, Option<&str>) = Args::new(values).get()?;
+//!     api::result_text(context, vec![text; count as usize].join(separator.unwrap_or("")))
+//! }
+//! ```
+//!
+//! NULL is only accepted for `Option`s, which are also `None` for trailing
+//! arguments a variadic function wasn't given. A value of the wrong type
+//! fails with an error naming the argument, like `argument 2: expected an
+//! integer, got text`. Integers are accepted where a real is expected, and
+//! text where bytes are, but nothing else is converted, except that a
+//! `bool` is read with [`crate::api::value_bool`] and a `Duration` with
+//! [`crate::api::value_duration`].
+//!
+//! Going the other way, a function can return any [`ToResult`] instead of
+//! calling a `result_*` function, with `None` for NULL:
+//!
+//! ```ignore
+//! define_scalar_function(db, "half", 1, |_context, values| {
+//!     let (i,): (Option<i64>,) = Args::new(values).get()?;
+//!     Ok(i.map(|i| i as f64 / 2.0))
+//! }, FunctionFlags::UTF8 | FunctionFlags::DETERMINISTIC)?;
+//! ```
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api::{self, Overflow, ValueType};
+use crate::errors::{Error, Result};
+use crate::ext::{sqlite3_context, sqlite3_value};
+use std::os::raw::c_int;
+use std::time::Duration;
+
+/// A Rust type an SQL value converts to.
+pub trait FromValue<'a>: Sized {
+    /// Converts `value`, failing if its type doesn't fit.
+    fn from_value(value: &*mut sqlite3_value) -> Result<Self>;
+
+    /// The value of an argument that wasn't given, if the type has one.
+    fn from_missing() -> Option<Self> {
+        None
+    }
+}
+
+fn type_name(value_type: ValueType) -> &'static str {
+    match value_type {
+        ValueType::Integer => "an integer",
+        ValueType::Float => "a real",
+        ValueType::Text => "text",
+        ValueType::Blob => "a blob",
+        ValueType::Null => "NULL",
+    }
+}
+
+fn mismatch(expected: &str, value: &*mut sqlite3_value) -> Error {
+    Error::new_message(format!(
+        "expected {}, got {}",
+        expected,
+        type_name(api::value_type(value))
+    ))
+}
+
+impl<'a> FromValue<'a> for i64 {
+    fn from_value(value: &*mut sqlite3_value) -> Result<Self> {
+        match api::value_type(value) {
+            ValueType::Integer => Ok(api::value_int64(value)),
+            _ => Err(mismatch("an integer", value)),
+        }
+    }
+}
+
+impl<'a> FromValue<'a> for i32 {
+    fn from_value(value: &*mut sqlite3_value) -> Result<Self> {
+        i64::from_value(value)?;
+        api::value_int_checked(value, Overflow::Error)
+    }
+}
+
+impl<'a> FromValue<'a> for u64 {
+    fn from_value(value: &*mut sqlite3_value) -> Result<Self> {
+        i64::from_value(value)?;
+        api::value_u64(value, Overflow::Error)
+    }
+}
+
+impl<'a> FromValue<'a> for usize {
+    fn from_value(value: &*mut sqlite3_value) -> Result<Self> {
+        i64::from_value(value)?;
+        api::value_usize(value, Overflow::Error)
+    }
+}
+
+impl<'a> FromValue<'a> for f64 {
+    fn from_value(value: &*mut sqlite3_value) -> Result<Self> {
+        match api::value_type(value) {
+            ValueType::Integer | ValueType::Float => Ok(api::value_double(value)),
+            _ => Err(mismatch("a real", value)),
+        }
+    }
+}
+
+impl<'a> FromValue<'a> for bool {
+    fn from_value(value: &*mut sqlite3_value) -> Result<Self> {
+        api::value_bool(value)
+    }
+}
+
+impl<'a> FromValue<'a> for Duration {
+    fn from_value(value: &*mut sqlite3_value) -> Result<Self> {
+        api::value_duration(value)
+    }
+}
+
+impl<'a> FromValue<'a> for &'a str {
+    fn from_value(value: &*mut sqlite3_value) -> Result<Self> {
+        match api::value_type(value) {
+            ValueType::Text => Ok(api::value_text(value)?),
+            _ => Err(mismatch("text", value)),
+        }
+    }
+}
+
+impl<'a> FromValue<'a> for String {
+    fn from_value(value: &*mut sqlite3_value) -> Result<Self> {
+        <&str>::from_value(value).map(str::to_owned)
+    }
+}
+
+impl<'a> FromValue<'a> for &'a [u8] {
+    fn from_value(value: &*mut sqlite3_value) -> Result<Self> {
+        match api::value_type(value) {
+            ValueType::Blob | ValueType::Text => Ok(api::value_blob(value)),
+            _ => Err(mismatch("a blob", value)),
+        }
+    }
+}
+
+impl<'a> FromValue<'a> for Vec<u8> {
+    fn from_value(value: &*mut sqlite3_value) -> Result<Self> {
+        <&[u8]>::from_value(value).map(<[u8]>::to_vec)
+    }
+}
+
+impl<'a, T: FromValue<'a>> FromValue<'a> for Option<T> {
+    fn from_value(value: &*mut sqlite3_value) -> Result<Self> {
+        if api::value_is_null(value) {
+            Ok(None)
+        } else {
+            T::from_value(value).map(Some)
+        }
+    }
+
+    fn from_missing() -> Option<Self> {
+        Some(None)
+    }
+}
+
+/// The arguments of one function call.
+#[derive(Debug, Clone, Copy)]
+pub struct Args<'a> {
+    values: &'a [*mut sqlite3_value],
+}
+
+impl<'a> Args<'a> {
+    pub fn new(values: &'a [*mut sqlite3_value]) -> Self {
+        Args { values }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Converts every argument, into a tuple with a type for each.
+    pub fn get<T: FromArgs<'a>>(&self) -> Result<T> {
+        T::from_args(self)
+    }
+
+    /// Converts the argument at the 0-based index `i`.
+    pub fn get_at<T: FromValue<'a>>(&self, i: usize) -> Result<T> {
+        match self.values.get(i) {
+            Some(value) => T::from_value(value).map_err(|err| {
+                Error::new_message(format!(
+                    "argument {}: {}",
+                    i + 1,
+                    err.result_error_message()
+                ))
+            }),
+            None => T::from_missing()
+                .ok_or_else(|| Error::new_message(format!("argument {} is missing", i + 1))),
+        }
+    }
+}
+
+/// A tuple of [`FromValue`] types, one for each argument.
+pub trait FromArgs<'a>: Sized {
+    fn from_args(args: &Args<'a>) -> Result<Self>;
+}
+
+macro_rules! tuple_from_args {
+    ($len:expr; $($t:ident $i:tt),+) => {
+        impl<'a, $($t: FromValue<'a>),+> FromArgs<'a> for ($($t,)+) {
+            fn from_args(args: &Args<'a>) -> Result<Self> {
+                if args.len() > $len {
+                    return Err(Error::new_message(format!(
+                        "expected at most {} arguments, got {}",
+                        $len,
+                        args.len()
+                    )));
+                }
+                Ok(($(args.get_at::<$t>($i)?,)+))
+            }
+        }
+    };
+}
+tuple_from_args!(1; A 0);
+tuple_from_args!(2; A 0, B 1);
+tuple_from_args!(3; A 0, B 1, C 2);
+tuple_from_args!(4; A 0, B 1, C 2, D 3);
+tuple_from_args!(5; A 0, B 1, C 2, D 3, E 4);
+tuple_from_args!(6; A 0, B 1, C 2, D 3, E 4, F 5);
+tuple_from_args!(7; A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+tuple_from_args!(8; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+
+/// The most arguments SQLite passes a function, with the default
+/// `SQLITE_MAX_FUNCTION_ARG`.
+pub const MAX_ARGS: usize = 127;
+
+/// How many arguments a function takes, which
+/// [`crate::define_scalar_function_variadic`] checks before calling it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arity {
+    min: usize,
+    max: Option<usize>,
+}
+
+impl Arity {
+    pub fn exactly(n: usize) -> Self {
+        Arity {
+            min: n,
+            max: Some(n),
+        }
+    }
+
+    pub fn at_least(min: usize) -> Self {
+        Arity { min, max: None }
+    }
+
+    pub fn between(min: usize, max: usize) -> Self {
+        Arity {
+            min,
+            max: Some(max),
+        }
+    }
+
+    pub fn any() -> Self {
+        Arity::at_least(0)
+    }
+
+    pub fn min(&self) -> usize {
+        self.min
+    }
+
+    pub fn max(&self) -> Option<usize> {
+        self.max
+    }
+
+    /// The nArg to register a function with: the count for an exact
+    /// arity, -1 for "any" otherwise.
+    pub fn num_args(&self) -> c_int {
+        match self.max {
+            Some(max) if max == self.min => max as c_int,
+            _ => -1,
+        }
+    }
+
+    /// Fails if the arity can't be met, or allows more than [`MAX_ARGS`].
+    pub fn validate(&self) -> Result<()> {
+        match self.max {
+            Some(max) if max < self.min => Err(Error::new_message(format!(
+                "invalid arity: at most {} arguments is fewer than at least {}",
+                max, self.min
+            ))),
+            Some(max) if max > MAX_ARGS => Err(Error::new_message(format!(
+                "invalid arity: SQLite passes at most {} arguments, not {}",
+                MAX_ARGS, max
+            ))),
+            None if self.min > MAX_ARGS => Err(Error::new_message(format!(
+                "invalid arity: SQLite passes at most {} arguments, not {}",
+                MAX_ARGS, self.min
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails with an error like `repeat() takes 2 or 3 arguments, got 1`
+    /// unless a call to `name` with `count` arguments fits.
+    pub fn check(&self, name: &str, count: usize) -> Result<()> {
+        if count >= self.min && self.max.map_or(true, |max| count <= max) {
+            return Ok(());
+        }
+        let plural = |n: usize| if n == 1 { "" } else { "s" };
+        let takes = match self.max {
+            Some(max) if max == self.min => format!("exactly {} argument{}", max, plural(max)),
+            Some(max) if max == self.min + 1 => format!("{} or {} arguments", self.min, max),
+            Some(max) => format!("{} to {} arguments", self.min, max),
+            None => format!("at least {} argument{}", self.min, plural(self.min)),
+        };
+        Err(Error::new_message(format!(
+            "{}() takes {}, got {}",
+            name, takes, count
+        )))
+    }
+}
+
+/// A Rust value a function can return as its SQL result.
+pub trait ToResult {
+    /// Sets `context`'s result to the value.
+    fn to_result(self, context: *mut sqlite3_context) -> Result<()>;
+}
+
+/// For functions that set their result themselves.
+impl ToResult for () {
+    fn to_result(self, _context: *mut sqlite3_context) -> Result<()> {
+        Ok(())
+    }
+}
+
+macro_rules! int_to_result {
+    ($($t:ty),+) => {
+        $(
+            impl ToResult for $t {
+                fn to_result(self, context: *mut sqlite3_context) -> Result<()> {
+                    api::result_int64(context, i64::from(self));
+                    Ok(())
+                }
+            }
+        )+
+    };
+}
+int_to_result!(i8, i16, i32, i64, u8, u16, u32);
+
+macro_rules! wide_int_to_result {
+    ($($t:ty),+) => {
+        $(
+            impl ToResult for $t {
+                fn to_result(self, context: *mut sqlite3_context) -> Result<()> {
+                    let i = i64::try_from(self).map_err(|_| {
+                        Error::new_message(format!("{} is out of range for an integer", self))
+                    })?;
+                    api::result_int64(context, i);
+                    Ok(())
+                }
+            }
+        )+
+    };
+}
+wide_int_to_result!(u64, usize, isize);
+
+impl ToResult for f64 {
+    fn to_result(self, context: *mut sqlite3_context) -> Result<()> {
+        api::result_double(context, self);
+        Ok(())
+    }
+}
+
+impl ToResult for f32 {
+    fn to_result(self, context: *mut sqlite3_context) -> Result<()> {
+        api::result_double(context, f64::from(self));
+        Ok(())
+    }
+}
+
+impl ToResult for bool {
+    fn to_result(self, context: *mut sqlite3_context) -> Result<()> {
+        api::result_bool(context, self);
+        Ok(())
+    }
+}
+
+impl ToResult for &str {
+    fn to_result(self, context: *mut sqlite3_context) -> Result<()> {
+        api::result_text(context, self)
+    }
+}
+
+impl ToResult for String {
+    fn to_result(self, context: *mut sqlite3_context) -> Result<()> {
+        api::result_text(context, self)
+    }
+}
+
+impl ToResult for &[u8] {
+    fn to_result(self, context: *mut sqlite3_context) -> Result<()> {
+        if i32::try_from(self.len()).is_err() {
+            return Err(Error::new_message("i32 overflow, blob too large"));
+        }
+        api::result_blob(context, self);
+        Ok(())
+    }
+}
+
+impl ToResult for Vec<u8> {
+    fn to_result(self, context: *mut sqlite3_context) -> Result<()> {
+        self.as_slice().to_result(context)
+    }
+}
+
+/// JSON, as text with the JSON subtype.
+impl ToResult for serde_json::Value {
+    fn to_result(self, context: *mut sqlite3_context) -> Result<()> {
+        api::result_json(context, self)
+    }
+}
+
+impl<T: ToResult> ToResult for Option<T> {
+    fn to_result(self, context: *mut sqlite3_context) -> Result<()> {
+        match self {
+            Some(value) => value.to_result(context),
+            None => {
+                api::result_null(context);
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! Process large blobs in fixed-size chunks.
+//!
+//! A blob passed to a function as an argument is already fully in memory,
+//! and [`value_chunks`] only splits it up. [`BlobChunks`] reads a blob
+//! stored in a table with
+//! [incremental blob I/O](https://www.sqlite.org/c3ref/blob_open.html)
+//! instead, so hashing or concatenating many large blobs only ever needs
+//! one chunk in memory at a time.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api;
+use crate::errors::{Error, ErrorKind, Result};
+use crate::ext::{
+    sqlite3, sqlite3_blob, sqlite3_value, sqlite3ext_blob_bytes, sqlite3ext_blob_close,
+    sqlite3ext_blob_open, sqlite3ext_blob_read,
+};
+use std::{
+    ffi::CString,
+    os::raw::{c_int, c_void},
+};
+
+/// The blob of a value in chunks of `chunk_size` bytes (the last one may be
+/// shorter). Text and numbers are chunked as their text representation.
+pub fn value_chunks<'a>(
+    value: &*mut sqlite3_value,
+    chunk_size: usize,
+) -> std::slice::Chunks<'a, u8> {
+    api::value_blob(value).chunks(chunk_size.max(1))
+}
+
+/// A read-only handle on a blob stored in a table, read in chunks into a
+/// reused buffer.
+pub struct BlobChunks {
+    blob: *mut sqlite3_blob,
+    db: *mut sqlite3,
+    len: usize,
+    offset: usize,
+    buffer: Vec<u8>,
+}
+
+impl BlobChunks {
+    /// Opens the blob in `column` of the row with `rowid` in `schema.table`,
+    /// to be read `chunk_size` bytes at a time.
+    pub fn open(
+        db: *mut sqlite3,
+        schema: &str,
+        table: &str,
+        column: &str,
+        rowid: i64,
+        chunk_size: usize,
+    ) -> Result<BlobChunks> {
+        let (cschema, ctable, ccolumn) = (
+            CString::new(schema)?,
+            CString::new(table)?,
+            CString::new(column)?,
+        );
+        let mut blob: *mut sqlite3_blob = std::ptr::null_mut();
+        let rc = unsafe {
+            sqlite3ext_blob_open(
+                db,
+                cschema.as_ptr(),
+                ctable.as_ptr(),
+                ccolumn.as_ptr(),
+                rowid,
+                0,
+                &mut blob,
+            )
+        };
+        if rc != 0 {
+            // the handle is set to NULL on failure
+            return Err(Error::with_db_message(
+                ErrorKind::Message(format!(
+                    "could not open blob in {}.{}.{} for rowid {}",
+                    schema, table, column, rowid
+                )),
+                rc,
+                db,
+            ));
+        }
+        let len = unsafe { sqlite3ext_blob_bytes(blob) } as usize;
+        Ok(BlobChunks {
+            blob,
+            db,
+            len,
+            offset: 0,
+            buffer: vec![0; chunk_size.max(1).min(len.max(1))],
+        })
+    }
+
+    /// The size of the whole blob in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The next chunk, or None once the whole blob has been read. Fails if
+    /// the row was changed or deleted after the blob was opened.
+    pub fn next_chunk(&mut self) -> Result<Option<&[u8]>> {
+        if self.offset >= self.len {
+            return Ok(None);
+        }
+        let n = self.buffer.len().min(self.len - self.offset);
+        let rc = unsafe {
+            sqlite3ext_blob_read(
+                self.blob,
+                self.buffer.as_mut_ptr().cast::<c_void>(),
+                n as c_int,
+                self.offset as c_int,
+            )
+        };
+        if rc != 0 {
+            return Err(Error::with_db_message(
+                ErrorKind::Message(format!("could not read blob at offset {}", self.offset)),
+                rc,
+                self.db,
+            ));
+        }
+        self.offset += n;
+        Ok(Some(&self.buffer[..n]))
+    }
+
+    /// Calls `f` with each remaining chunk in order, stopping at the first error.
+    pub fn for_each<F>(mut self, mut f: F) -> Result<()>
+    where
+        F: FnMut(&[u8]) -> Result<()>,
+    {
+        while let Some(chunk) = self.next_chunk()? {
+            f(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for BlobChunks {
+    fn drop(&mut self) {
+        unsafe { sqlite3ext_blob_close(self.blob) };
+    }
+}
@@ -0,0 +1,206 @@
+//! Fast initial loads into the tables behind writable virtual tables.
+//!
+//! Inserting rows one SQL statement at a time prepares, plans and commits
+//! each of them. [`BulkInserter`] prepares one `INSERT` and reuses it for
+//! every row, and [`insert_many`] runs all the rows in a single transaction.
+//! A writable virtual table can keep a `BulkInserter` for its shadow table
+//! and call it from xUpdate, and [`define_load_function`] exposes a load as a
+//! SQL function:
+//!
+//! ```sql
+//! select docs_load('[[1, "alex"], {"id": 2, "name": "brian"}]');
+//! ```
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api;
+use crate::errors::{Error, Result};
+use crate::ext::{sqlite3, sqlite3_value};
+use crate::scalar::{define_scalar_function, FunctionFlags};
+use crate::schema::quote_identifier;
+use crate::statement::{in_savepoint, Statement};
+use serde_json::Value;
+
+/// One prepared `INSERT` into some columns of a table, reused for each row.
+pub struct BulkInserter {
+    stmt: Statement,
+    columns: Vec<String>,
+    inserted: usize,
+}
+
+impl BulkInserter {
+    /// Prepares an insert into `columns` of `schema.table`.
+    pub fn new(db: *mut sqlite3, schema: &str, table: &str, columns: &[&str]) -> Result<Self> {
+        if columns.is_empty() {
+            return Err(Error::new_message(
+                "a bulk insert needs at least one column",
+            ));
+        }
+        let names: Vec<String> = columns.iter().map(|c| quote_identifier(c)).collect();
+        let parameters: Vec<String> = (1..=columns.len()).map(|i| format!("?{}", i)).collect();
+        let stmt = Statement::prepare(
+            db,
+            &format!(
+                "INSERT INTO {}.{}({}) VALUES ({})",
+                quote_identifier(schema),
+                quote_identifier(table),
+                names.join(", "),
+                parameters.join(", ")
+            ),
+        )?;
+        Ok(BulkInserter {
+            stmt,
+            columns: columns.iter().map(|c| (*c).to_owned()).collect(),
+            inserted: 0,
+        })
+    }
+
+    /// The columns rows are inserted into, in order.
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// How many rows have been inserted so far.
+    pub fn inserted(&self) -> usize {
+        self.inserted
+    }
+
+    /// Inserts one row, with a value for each column in order. In xUpdate,
+    /// those are the arguments after the rowids.
+    pub fn insert(&mut self, values: &[*mut sqlite3_value]) -> Result<()> {
+        self.check_len(values.len())?;
+        for (i, value) in values.iter().enumerate() {
+            self.stmt.bind_value(i as i32 + 1, *value)?;
+        }
+        self.execute()
+    }
+
+    /// Inserts one row from JSON, either an array of values in column order
+    /// or an object keyed by column name, where missing columns are NULL.
+    /// Nested arrays and objects are inserted as JSON text.
+    pub fn insert_json(&mut self, row: &Value) -> Result<()> {
+        match row {
+            Value::Array(values) => {
+                self.check_len(values.len())?;
+                for (i, value) in values.iter().enumerate() {
+                    self.bind_json(i as i32 + 1, value)?;
+                }
+            }
+            Value::Object(record) => {
+                if let Some(key) = record
+                    .keys()
+                    .find(|key| !self.columns.iter().any(|c| c == *key))
+                {
+                    return Err(Error::new_message(format!("unknown column {:?}", key)));
+                }
+                for i in 0..self.columns.len() {
+                    let value = record.get(&self.columns[i]).unwrap_or(&Value::Null);
+                    self.bind_json(i as i32 + 1, value)?;
+                }
+            }
+            _ => {
+                return Err(Error::new_message(
+                    "each row must be a JSON array or object",
+                ))
+            }
+        }
+        self.execute()
+    }
+
+    fn check_len(&self, len: usize) -> Result<()> {
+        if len != self.columns.len() {
+            return Err(Error::new_message(format!(
+                "a row has {} values, but {} columns are inserted",
+                len,
+                self.columns.len()
+            )));
+        }
+        Ok(())
+    }
+
+    fn bind_json(&mut self, i: i32, value: &Value) -> Result<()> {
+        match value {
+            Value::Null => self.stmt.bind_null(i),
+            Value::Bool(b) => self.stmt.bind_int64(i, *b as i64),
+            Value::Number(n) => match n.as_i64() {
+                Some(n) => self.stmt.bind_int64(i, n),
+                // too big for an INTEGER, or fractional
+                None => self.stmt.bind_double(i, n.as_f64().unwrap_or(f64::NAN)),
+            },
+            Value::String(s) => self.stmt.bind_text(i, s),
+            Value::Array(_) | Value::Object(_) => self.stmt.bind_text(i, &value.to_string()),
+        }
+    }
+
+    fn execute(&mut self) -> Result<()> {
+        let result = self.stmt.step();
+        self.stmt.reset();
+        result?;
+        self.inserted += 1;
+        Ok(())
+    }
+}
+
+/// Inserts every row of `rows` into `columns` of `schema.table` with one
+/// prepared statement, in a single transaction: if any row fails, none are
+/// inserted. Returns how many rows were inserted.
+pub fn insert_many<I, R>(
+    db: *mut sqlite3,
+    schema: &str,
+    table: &str,
+    columns: &[&str],
+    rows: I,
+) -> Result<usize>
+where
+    I: IntoIterator<Item = R>,
+    R: AsRef<[*mut sqlite3_value]>,
+{
+    let mut inserter = BulkInserter::new(db, schema, table, columns)?;
+    in_savepoint(db, "sqlite_loadable_bulk", || {
+        for row in rows {
+            inserter.insert(row.as_ref())?;
+        }
+        Ok(inserter.inserted())
+    })
+}
+
+/// Defines `name(rows)`, which inserts a JSON array of rows (see
+/// [`BulkInserter::insert_json`]) into `columns` of `schema.table` in a
+/// single transaction, returning how many were inserted. Functions that
+/// write shouldn't run from triggers or views, so it's DIRECTONLY.
+pub fn define_load_function(
+    db: *mut sqlite3,
+    name: &str,
+    schema: &str,
+    table: &str,
+    columns: &[&str],
+) -> Result<()> {
+    let schema = schema.to_owned();
+    let table = table.to_owned();
+    let columns: Vec<String> = columns.iter().map(|c| (*c).to_owned()).collect();
+    define_scalar_function(
+        db,
+        name,
+        1,
+        move |context, values| {
+            let rows: Value = serde_json::from_str(api::value_text(&values[0])?)
+                .map_err(|err| Error::new_message(format!("invalid JSON: {}", err)))?;
+            let rows = rows
+                .as_array()
+                .ok_or_else(|| Error::new_message("rows must be a JSON array"))?;
+            let db = api::context_db_handle(context);
+            let columns: Vec<&str> = columns.iter().map(String::as_str).collect();
+            let mut inserter = BulkInserter::new(db, &schema, &table, &columns)?;
+            let inserted = in_savepoint(db, "sqlite_loadable_bulk", || {
+                for (i, row) in rows.iter().enumerate() {
+                    inserter.insert_json(row).map_err(|err| {
+                        Error::new_message(format!("row {}: {}", i + 1, err.result_error_message()))
+                    })?;
+                }
+                Ok(inserter.inserted())
+            })?;
+            api::result_int64(context, inserted as i64);
+            Ok(())
+        },
+        FunctionFlags::UTF8 | FunctionFlags::DIRECTONLY,
+    )
+}
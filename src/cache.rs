@@ -0,0 +1,210 @@
+//! A size-bounded least-recently-used cache, a building block for extensions
+//! that keep pages, responses, or parsed objects around between calls.
+//!
+//! SQLite has no API for extensions to add their own allocations to
+//! `sqlite3_status()`, so instead the cache tracks its own usage in bytes
+//! (see [`LruCache::memory_used`]) and can optionally yield to SQLite's
+//! [soft heap limit](https://www.sqlite.org/c3ref/hard_heap_limit64.html):
+//! when SQLite's own usage plus the cache's would go over the limit, the
+//! oldest entries are evicted first.
+
+use crate::api;
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+struct Entry<V> {
+    value: V,
+    size: usize,
+    tick: u64,
+}
+
+/// A least-recently-used cache bounded by the total size of its entries.
+/// Sizes are given by the caller on insert, usually the byte length of the
+/// value.
+pub struct LruCache<K, V> {
+    entries: HashMap<K, Entry<V>>,
+    /// access tick -> key, oldest first
+    order: BTreeMap<u64, K>,
+    tick: u64,
+    used: usize,
+    capacity: usize,
+    soft_heap_limit: bool,
+}
+
+impl<K: Hash + Eq + Clone, V> LruCache<K, V> {
+    /// A new cache that holds at most `capacity` bytes of entries.
+    pub fn new(capacity: usize) -> LruCache<K, V> {
+        LruCache {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            used: 0,
+            capacity,
+            soft_heap_limit: false,
+        }
+    }
+
+    /// Changes the capacity, evicting entries if the cache is now too large.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// When enabled, inserts also evict entries while SQLite's memory usage
+    /// plus the cache's is over SQLite's soft heap limit. Requires the
+    /// extension to be loaded, since it calls into SQLite.
+    pub fn set_respect_soft_heap_limit(&mut self, enabled: bool) {
+        self.soft_heap_limit = enabled;
+    }
+
+    /// Total size of all entries in the cache, in bytes.
+    pub fn memory_used(&self) -> usize {
+        self.used
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the cached value and marks it as most recently used.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.tick += 1;
+        let entry = self.entries.get_mut(key)?;
+        if let Some(key) = self.order.remove(&entry.tick) {
+            self.order.insert(self.tick, key);
+        }
+        entry.tick = self.tick;
+        Some(&entry.value)
+    }
+
+    /// Returns the cached value without changing its recency.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.entries.get(key).map(|entry| &entry.value)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.entries.contains_key(key)
+    }
+
+    /// Inserts a value with the given size, evicting the least recently used
+    /// entries to make room. Returns the previous value for the key, if any.
+    /// Values larger than the whole capacity aren't cached.
+    pub fn insert(&mut self, key: K, value: V, size: usize) -> Option<V> {
+        let previous = self.remove(&key);
+        if size > self.capacity {
+            return previous;
+        }
+        self.tick += 1;
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                size,
+                tick: self.tick,
+            },
+        );
+        self.used += size;
+        self.evict();
+        previous
+    }
+
+    /// Removes the entry for the key, returning its value.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let entry = self.entries.remove(key)?;
+        self.order.remove(&entry.tick);
+        self.used -= entry.size;
+        Some(entry.value)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+        self.used = 0;
+    }
+
+    fn over_soft_heap_limit(&self) -> bool {
+        if !self.soft_heap_limit {
+            return false;
+        }
+        match api::soft_heap_limit() {
+            Some(limit) => api::memory_used().saturating_add(self.used as i64) > limit,
+            None => false,
+        }
+    }
+
+    fn evict(&mut self) {
+        // under soft heap pressure, keep at least the newest entry
+        while self.used > self.capacity || (self.entries.len() > 1 && self.over_soft_heap_limit()) {
+            let oldest = match self.order.keys().next() {
+                Some(tick) => *tick,
+                None => break,
+            };
+            if let Some(key) = self.order.remove(&oldest) {
+                if let Some(entry) = self.entries.remove(&key) {
+                    self.used -= entry.size;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::*;
+
+    #[test]
+    fn test_lru_cache() {
+        let mut cache: LruCache<String, &str> = LruCache::new(10);
+        cache.insert("a".to_owned(), "aaaa", 4);
+        cache.insert("b".to_owned(), "bbbb", 4);
+        assert_eq!(cache.memory_used(), 8);
+
+        // "a" is now the most recently used, so "b" gets evicted
+        assert_eq!(cache.get("a"), Some(&"aaaa"));
+        cache.insert("c".to_owned(), "cccc", 4);
+        assert!(!cache.contains_key("b"));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.memory_used(), 8);
+
+        // replacing returns the old value and updates the size
+        assert_eq!(cache.insert("c".to_owned(), "cc", 2), Some("cccc"));
+        assert_eq!(cache.memory_used(), 6);
+
+        // too large to ever fit
+        assert_eq!(cache.insert("d".to_owned(), "dddddddddddd", 12), None);
+        assert!(!cache.contains_key("d"));
+
+        cache.set_capacity(3);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.peek("c"), Some(&"cc"));
+        assert_eq!(cache.remove("c"), Some("cc"));
+        assert!(cache.is_empty());
+        assert_eq!(cache.memory_used(), 0);
+    }
+}
@@ -0,0 +1,470 @@
+//! Change data capture: a log of the rows a connection changes, queryable
+//! as the `changes` table.
+//!
+//! [`define_changes`] starts logging every row the connection inserts,
+//! updates or deletes, and adds the `changes` table function, with a row
+//! for each change that committed, in order:
+//!
+//! ```sql
+//! select seq, op, table_name, row_id from changes;
+//! -- changes after the 42nd, to resume replication where it left off
+//! select * from changes(42);
+//! ```
+//!
+//! Rows are logged with [`on_update`], which has no row values, so `old`
+//! and `new` are NULL until `changes_track(table [, schema])` adds TEMP
+//! triggers that fill them in, as JSON objects of the row's columns before
+//! and after the change. BLOBs, which JSON has no type for, are objects
+//! like `{"blob": "01FF"}` with their bytes in hex. Calling
+//! `changes_track()` again after `ALTER TABLE` picks up new columns.
+//! Tracked tables need rowids. Changes that roll back
+//! are dropped, unless only a savepoint rolls back, and the changes
+//! [`on_update`] misses aren't logged. The log keeps the last
+//! [`MAX_CHANGES`] changes.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api;
+use crate::errors::{Error, Result};
+use crate::ext::{sqlite3, sqlite3_context, sqlite3_value, sqlite3_vtab, sqlite3_vtab_cursor};
+use crate::hooks::{on_commit, on_update, UpdateOperation};
+use crate::scalar::{define_scalar_function, FunctionFlags};
+use crate::schema::quote_identifier;
+use crate::statement::{execute_batch, Statement};
+use crate::table::{
+    define_table_function, BestIndexError, ConstraintOperator, IndexInfo, VTab, VTabArguments,
+    VTabCursor,
+};
+use std::collections::VecDeque;
+use std::os::raw::c_int;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// How many changes the log keeps before dropping the oldest.
+pub const MAX_CHANGES: usize = 100_000;
+
+/// The hidden function the `changes_track()` triggers call with row values.
+const VALUES_FUNCTION: &str = "sqlite_loadable_changes_values";
+
+/// One changed row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    /// Where the change is in the log, from 1.
+    pub seq: i64,
+    pub operation: UpdateOperation,
+    pub schema: String,
+    pub table: String,
+    pub rowid: i64,
+    /// The row before the change, as a JSON object, for updates and deletes
+    /// of tracked tables.
+    pub old: Option<String>,
+    /// The row after the change, as a JSON object, for inserts and updates
+    /// of tracked tables.
+    pub new: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct LogState {
+    /// Changes of the open transaction, without their `seq` yet.
+    pending: Vec<Change>,
+    committed: VecDeque<Change>,
+    last_seq: i64,
+}
+
+/// A connection's log of committed changes.
+#[derive(Debug, Clone, Default)]
+pub struct ChangeLog(Arc<Mutex<LogState>>);
+
+impl ChangeLog {
+    fn state(&self) -> Result<MutexGuard<'_, LogState>> {
+        self.0
+            .lock()
+            .map_err(|_| Error::new_message("change log was poisoned"))
+    }
+
+    /// The committed changes after `seq`, oldest first.
+    pub fn since(&self, seq: i64) -> Result<Vec<Change>> {
+        Ok(self
+            .state()?
+            .committed
+            .iter()
+            .filter(|change| change.seq > seq)
+            .cloned()
+            .collect())
+    }
+
+    /// The `seq` of the last committed change, 0 before any.
+    pub fn last_seq(&self) -> Result<i64> {
+        Ok(self.state()?.last_seq)
+    }
+
+    /// Logs a change as part of the open transaction. Returns whether it's
+    /// the transaction's first, which needs a [`Flush`] queued.
+    fn push(&self, change: Change) -> bool {
+        match self.0.lock() {
+            Ok(mut state) => {
+                state.pending.push(change);
+                state.pending.len() == 1
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Fills in the values of the last pending change to a row.
+    fn set_values(
+        &self,
+        operation: UpdateOperation,
+        schema: &str,
+        table: &str,
+        rowid: i64,
+        old: Option<String>,
+        new: Option<String>,
+    ) -> Result<()> {
+        let mut state = self.state()?;
+        if let Some(change) = state.pending.iter_mut().rev().find(|change| {
+            change.operation == operation
+                && change.rowid == rowid
+                && change.schema == schema
+                && change.table.eq_ignore_ascii_case(table)
+        }) {
+            change.old = old;
+            change.new = new;
+        }
+        Ok(())
+    }
+}
+
+/// Moves the transaction's changes into the log when it commits, or drops
+/// them if it rolls back, which drops the queued commit closure holding
+/// this.
+struct Flush {
+    log: ChangeLog,
+    committed: bool,
+}
+
+impl Flush {
+    fn commit(mut self) {
+        if let Ok(mut state) = self.log.0.lock() {
+            let state = &mut *state;
+            for mut change in state.pending.drain(..) {
+                state.last_seq += 1;
+                change.seq = state.last_seq;
+                state.committed.push_back(change);
+            }
+            let excess = state.committed.len().saturating_sub(MAX_CHANGES);
+            state.committed.drain(..excess);
+        }
+        self.committed = true;
+    }
+}
+
+impl Drop for Flush {
+    fn drop(&mut self) {
+        if !self.committed {
+            if let Ok(mut state) = self.log.0.lock() {
+                state.pending.clear();
+            }
+        }
+    }
+}
+
+fn operation_name(operation: UpdateOperation) -> &'static str {
+    match operation {
+        UpdateOperation::Insert => "insert",
+        UpdateOperation::Update => "update",
+        UpdateOperation::Delete => "delete",
+    }
+}
+
+fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Starts logging the connection's changes, and defines the `changes`
+/// table, `changes_track(table [, schema])`, which is DIRECTONLY since it
+/// creates triggers, and the function those triggers call. Returns the log,
+/// for Rust code to read too.
+pub fn define_changes(db: *mut sqlite3) -> Result<ChangeLog> {
+    let log = ChangeLog::default();
+
+    // installs the commit and rollback hooks now, so queuing a flush from
+    // the update hook never has to touch the connection
+    on_commit(db, || {})?;
+    let changes = log.clone();
+    let key = db as usize;
+    on_update(db, move |operation, schema, table, rowid| {
+        let first = changes.push(Change {
+            seq: 0,
+            operation,
+            schema: schema.to_owned(),
+            table: table.to_owned(),
+            rowid,
+            old: None,
+            new: None,
+        });
+        if first {
+            let flush = Flush {
+                log: changes.clone(),
+                committed: false,
+            };
+            // nothing can be reported from here; without a flush, the
+            // changes wait for the next transaction's
+            let _ = on_commit(key as *mut sqlite3, move || flush.commit());
+        }
+    })?;
+
+    let values = log.clone();
+    define_scalar_function(
+        db,
+        VALUES_FUNCTION,
+        6,
+        move |context, args| {
+            let operation = match api::value_text(&args[0])? {
+                "insert" => UpdateOperation::Insert,
+                "update" => UpdateOperation::Update,
+                _ => UpdateOperation::Delete,
+            };
+            let text = |value: &*mut sqlite3_value| -> Result<Option<String>> {
+                if api::value_is_null(value) {
+                    Ok(None)
+                } else {
+                    Ok(Some(api::value_text(value)?.to_owned()))
+                }
+            };
+            values.set_values(
+                operation,
+                api::value_text(&args[1])?,
+                api::value_text(&args[2])?,
+                api::value_int64(&args[3]),
+                text(&args[4])?,
+                text(&args[5])?,
+            )?;
+            api::result_null(context);
+            Ok(())
+        },
+        FunctionFlags::UTF8 | FunctionFlags::DIRECTONLY,
+    )?;
+
+    for num_args in [1, 2] {
+        define_scalar_function(
+            db,
+            "changes_track",
+            num_args,
+            |context, values| {
+                let table = api::value_text(&values[0])?;
+                let schema = match values.get(1) {
+                    Some(schema) => api::value_text(schema)?,
+                    None => "main",
+                };
+                track(api::context_db_handle(context), schema, table)?;
+                api::result_null(context);
+                Ok(())
+            },
+            FunctionFlags::UTF8 | FunctionFlags::DIRECTONLY,
+        )?;
+    }
+
+    define_table_function::<ChangesTable>(db, "changes", Some(log.clone()))?;
+    Ok(log)
+}
+
+/// Creates the TEMP triggers that log the values of `schema.table`'s rows,
+/// replacing any from before, which would have the old columns.
+fn track(db: *mut sqlite3, schema: &str, table: &str) -> Result<()> {
+    let mut stmt = Statement::prepare(db, "select name from pragma_table_info(?1, ?2)")?;
+    stmt.bind_text(1, table)?;
+    stmt.bind_text(2, schema)?;
+    let mut columns = vec![];
+    while stmt.step()? {
+        columns.push(stmt.column_string(0));
+    }
+    if columns.is_empty() {
+        return Err(Error::new_message(format!(
+            "changes_track(): no table {}.{}",
+            schema, table
+        )));
+    }
+    let row = |alias: &str| {
+        let fields: Vec<String> = columns
+            .iter()
+            .map(|column| {
+                let value = format!("{}.{}", alias, quote_identifier(column));
+                // json_object() fails on BLOBs
+                format!(
+                    "{0}, CASE WHEN typeof({1}) = 'blob' THEN json_object('blob', hex({1})) \
+                     ELSE {1} END",
+                    quote_literal(column),
+                    value
+                )
+            })
+            .collect();
+        format!("json_object({})", fields.join(", "))
+    };
+    for (operation, rowid, old, new) in [
+        (
+            UpdateOperation::Insert,
+            "new.rowid",
+            "NULL".to_owned(),
+            row("new"),
+        ),
+        (UpdateOperation::Update, "new.rowid", row("old"), row("new")),
+        (
+            UpdateOperation::Delete,
+            "old.rowid",
+            row("old"),
+            "NULL".to_owned(),
+        ),
+    ] {
+        let name = operation_name(operation);
+        let trigger = quote_identifier(&format!("changes_{}_{}_{}", schema, table, name));
+        execute_batch(
+            db,
+            &format!(
+                "DROP TRIGGER IF EXISTS temp.{0}; \
+                 CREATE TEMP TRIGGER {0} AFTER {1} ON {2}.{3} BEGIN \
+                 SELECT {4}({5}, {6}, {7}, {8}, {9}, {10}); END",
+                trigger,
+                name.to_uppercase(),
+                quote_identifier(schema),
+                quote_identifier(table),
+                VALUES_FUNCTION,
+                quote_literal(name),
+                quote_literal(schema),
+                quote_literal(table),
+                rowid,
+                old,
+                new
+            ),
+        )?;
+    }
+    Ok(())
+}
+
+/// idxNum when changes after a `seq` are asked for.
+const PLAN_SINCE: c_int = 1;
+
+/// The hidden column of the `seq` to read changes after.
+const COLUMN_SINCE: c_int = 7;
+
+#[repr(C)]
+pub struct ChangesTable {
+    /// must be first
+    base: sqlite3_vtab,
+    log: ChangeLog,
+}
+
+impl<'vtab> VTab<'vtab> for ChangesTable {
+    type Aux = ChangeLog;
+    type Cursor = ChangesCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, ChangesTable)> {
+        let log = aux
+            .cloned()
+            .ok_or_else(|| Error::new_message("changes table has no log"))?;
+        let base: sqlite3_vtab = unsafe { std::mem::zeroed() };
+        Ok((
+            "CREATE TABLE x(seq INTEGER, op TEXT, schema_name TEXT, table_name TEXT, \
+             row_id INTEGER, old JSON, new JSON, since hidden)"
+                .to_owned(),
+            ChangesTable { base, log },
+        ))
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        let mut plan = 0;
+        for mut constraint in info.constraints() {
+            if constraint.column_idx() != COLUMN_SINCE {
+                continue;
+            }
+            if !constraint.usable() || constraint.op() != Some(ConstraintOperator::EQ) {
+                return Err(BestIndexError::Constraint);
+            }
+            constraint.set_argv_index(1);
+            constraint.set_omit(true);
+            plan = PLAN_SINCE;
+        }
+        info.set_idxnum(plan);
+        info.set_estimated_cost(if plan == PLAN_SINCE { 10.0 } else { 1000.0 });
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<ChangesCursor> {
+        let base: sqlite3_vtab_cursor = unsafe { std::mem::zeroed() };
+        Ok(ChangesCursor {
+            base,
+            log: self.log.clone(),
+            rows: Vec::new(),
+            index: 0,
+        })
+    }
+}
+
+#[repr(C)]
+pub struct ChangesCursor {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    log: ChangeLog,
+    /// The changes as of the scan's start.
+    rows: Vec<Change>,
+    index: usize,
+}
+
+impl VTabCursor for ChangesCursor {
+    fn filter(
+        &mut self,
+        idx_num: c_int,
+        _idx_str: Option<&str>,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        let since = match (idx_num, values.first()) {
+            (PLAN_SINCE, Some(since)) => api::value_int64(since),
+            _ => 0,
+        };
+        self.rows = self.log.since(since)?;
+        self.index = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.index += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.index >= self.rows.len()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, i: c_int) -> Result<()> {
+        let change = self
+            .rows
+            .get(self.index)
+            .ok_or_else(|| Error::new_message("changes has no current row"))?;
+        let json = |value: &Option<String>| -> Result<()> {
+            match value {
+                Some(value) => {
+                    api::result_text(context, value)?;
+                    api::result_subtype(context, crate::subtype::JSON);
+                }
+                None => api::result_null(context),
+            }
+            Ok(())
+        };
+        match i {
+            0 => api::result_int64(context, change.seq),
+            1 => api::result_text(context, operation_name(change.operation))?,
+            2 => api::result_text(context, &change.schema)?,
+            3 => api::result_text(context, &change.table)?,
+            4 => api::result_int64(context, change.rowid),
+            5 => json(&change.old)?,
+            6 => json(&change.new)?,
+            _ => api::result_null(context),
+        }
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.rows.get(self.index).map_or(0, |change| change.seq))
+    }
+}
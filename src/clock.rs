@@ -0,0 +1,75 @@
+//! The current time and sleeping, through a connection's VFS.
+//!
+//! SQLite never reads the system clock or sleeps directly, it asks the VFS,
+//! which is how `date('now')` works under test VFSes with a fixed clock or
+//! in sandboxes like WASM without a system clock. These functions go
+//! through the same VFS, so an extension's idea of "now" matches SQLite's.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::database::main_vfs;
+use crate::errors::{Error, Result};
+use crate::ext::{sqlite3, sqlite3_vfs, sqlite3ext_vfs_find};
+use std::{os::raw::c_int, time::Duration};
+
+/// The Julian day number of the Unix epoch, in milliseconds.
+const UNIX_EPOCH_JULIAN_MILLIS: i64 = 210_866_760_000_000;
+
+/// The VFS of the connection's main database, or the default VFS.
+fn vfs(db: *mut sqlite3) -> Result<*mut sqlite3_vfs> {
+    let mut vfs = main_vfs(db)?;
+    if vfs.is_null() {
+        vfs = unsafe { sqlite3ext_vfs_find(std::ptr::null()) };
+    }
+    if vfs.is_null() {
+        return Err(Error::new_message("no VFS is registered"));
+    }
+    Ok(vfs)
+}
+
+/// The current time as a Julian day number in milliseconds, the VFS's own
+/// unit, from xCurrentTimeInt64 or from xCurrentTime on older VFSes.
+pub fn vfs_julian_day_millis(db: *mut sqlite3) -> Result<i64> {
+    let vfs = vfs(db)?;
+    unsafe {
+        if (*vfs).iVersion >= 2 {
+            if let Some(current_time) = (*vfs).xCurrentTimeInt64 {
+                let mut now: i64 = 0;
+                if current_time(vfs, &mut now) != 0 {
+                    return Err(Error::new_message("the VFS couldn't read the current time"));
+                }
+                return Ok(now);
+            }
+        }
+        match (*vfs).xCurrentTime {
+            Some(current_time) => {
+                let mut days: f64 = 0.0;
+                if current_time(vfs, &mut days) != 0 {
+                    return Err(Error::new_message("the VFS couldn't read the current time"));
+                }
+                Ok((days * 86_400_000.0) as i64)
+            }
+            None => Err(Error::new_message("the VFS has no clock")),
+        }
+    }
+}
+
+/// The current time in milliseconds since the Unix epoch, by the same clock
+/// SQLite uses for `date('now')`.
+pub fn vfs_current_time_millis(db: *mut sqlite3) -> Result<i64> {
+    Ok(vfs_julian_day_millis(db)? - UNIX_EPOCH_JULIAN_MILLIS)
+}
+
+/// Sleeps for at least `ms` milliseconds with the VFS's xSleep, returning
+/// how long the VFS says it slept. Some VFSes round up to whole seconds, and
+/// some can't sleep at all and return at once.
+pub fn vfs_sleep(db: *mut sqlite3, ms: u32) -> Result<Duration> {
+    let vfs = vfs(db)?;
+    let microseconds = (ms as u64 * 1000).min(c_int::MAX as u64) as c_int;
+    match unsafe { (*vfs).xSleep } {
+        Some(sleep) => {
+            let slept = unsafe { sleep(vfs, microseconds) };
+            Ok(Duration::from_micros(slept.max(0) as u64))
+        }
+        None => Err(Error::new_message("the VFS can't sleep")),
+    }
+}
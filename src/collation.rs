@@ -4,19 +4,56 @@
 use crate::{
     constants::SQLITE_OKAY,
     errors::{Error, ErrorKind, Result},
-    ext::{sqlite3, sqlite3ext_collation_v2},
+    ext::{sqlite3, sqlite3ext_collation_needed, sqlite3ext_collation_v2},
+    hooks::on_connection_close,
+};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    ffi::{CStr, CString},
+    os::raw::{c_char, c_int, c_void},
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::{Arc, Mutex},
 };
-use std::{ffi::CString, os::raw::c_void};
 
 use sqlite3ext_sys::SQLITE_UTF8;
 
-pub fn define_collation<F>(db: *mut sqlite3, name: &str, x_func: F) -> Result<()>
+/// What a collation's compare function returns: an [`Ordering`], or a
+/// negative number, zero, or a positive number like `memcmp`.
+pub trait Comparison {
+    fn to_c_int(self) -> c_int;
+}
+
+impl Comparison for Ordering {
+    fn to_c_int(self) -> c_int {
+        self as c_int
+    }
+}
+
+impl Comparison for i32 {
+    fn to_c_int(self) -> c_int {
+        self
+    }
+}
+
+/// Defines a collation that compares UTF-8 text with `x_func`:
+///
+/// ```ignore
+/// define_collation(db, "by_length", |a: &[u8], b: &[u8]| {
+///     a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+/// })?;
+/// ```
+///
+/// `x_func` is dropped when the collation is replaced or deleted, or the
+/// connection closes.
+pub fn define_collation<F, R>(db: *mut sqlite3, name: &str, x_func: F) -> Result<()>
 where
-    F: Fn(&[u8], &[u8]) -> i32,
+    F: Fn(&[u8], &[u8]) -> R,
+    R: Comparison,
 {
     let function_pointer: *mut F = Box::into_raw(Box::new(x_func));
 
-    unsafe extern "C" fn compare_function_wrapper<F>(
+    unsafe extern "C" fn compare_function_wrapper<F, R>(
         func: *mut std::os::raw::c_void,
         a_size: std::os::raw::c_int,
         a_pointer: *const std::os::raw::c_void,
@@ -24,12 +61,16 @@ where
         b_pointer: *const ::std::os::raw::c_void,
     ) -> i32
     where
-        F: Fn(&[u8], &[u8]) -> i32,
+        F: Fn(&[u8], &[u8]) -> R,
+        R: Comparison,
     {
         let boxed_function: *mut F = func.cast::<F>();
         let a = std::slice::from_raw_parts(a_pointer as *const u8, a_size as usize);
         let b = std::slice::from_raw_parts(b_pointer as *const u8, b_size as usize);
-        (*boxed_function)(a, b)
+        (*boxed_function)(a, b).to_c_int()
+    }
+    unsafe extern "C" fn destroy<F>(func: *mut c_void) {
+        drop(Box::from_raw(func.cast::<F>()));
     }
     let cname = CString::new(name)?;
     let result = unsafe {
@@ -38,14 +79,113 @@ where
             cname.as_ptr(),
             SQLITE_UTF8 as i32,
             function_pointer.cast::<c_void>(),
-            Some(compare_function_wrapper::<F>),
-            None,
+            Some(compare_function_wrapper::<F, R>),
+            Some(destroy::<F>),
         )
     };
 
     if result != SQLITE_OKAY {
-        Err(Error::new(ErrorKind::DefineScalarFunction(result)))
+        // unlike sqlite3_create_function_v2, SQLite doesn't call the
+        // destructor when registering fails
+        unsafe { destroy::<F>(function_pointer.cast::<c_void>()) };
+        Err(Error::with_db_message(
+            ErrorKind::DefineScalarFunction(result),
+            result,
+            db,
+        ))
     } else {
         Ok(())
     }
 }
+
+/// Defines a collation backed by some state, like a locale's collator,
+/// passed to `x_func` as the 3rd argument. `aux` is dropped along with the
+/// collation, see [`define_collation`].
+pub fn define_collation_with_aux<F, T, R>(
+    db: *mut sqlite3,
+    name: &str,
+    x_func: F,
+    aux: T,
+) -> Result<()>
+where
+    F: Fn(&[u8], &[u8], &T) -> R,
+    R: Comparison,
+{
+    define_collation(db, name, move |a, b| x_func(a, b, &aux))
+}
+
+/// Called with the connection and the name of a collation it doesn't have.
+pub type CollationNeeded = Arc<dyn Fn(*mut sqlite3, &str) -> Result<()> + Send + Sync>;
+
+/// Callbacks set with [`on_collation_needed`], by connection.
+static COLLATION_NEEDED: Mutex<Option<HashMap<usize, CollationNeeded>>> = Mutex::new(None);
+
+unsafe extern "C" fn x_collation_needed(
+    _p_arg: *mut c_void,
+    db: *mut sqlite3,
+    _text_rep: c_int,
+    name: *const c_char,
+) {
+    let name = match CStr::from_ptr(name).to_str() {
+        Ok(name) => name,
+        Err(_) => return,
+    };
+    // cloned out of the registry, so the callback can use it too
+    let f = COLLATION_NEEDED
+        .lock()
+        .ok()
+        .and_then(|callbacks| callbacks.as_ref()?.get(&(db as usize)).cloned());
+    if let Some(f) = f {
+        // a panic can't unwind into SQLite, and an error has nowhere to
+        // go: the statement fails with "no such collation sequence"
+        let _ = catch_unwind(AssertUnwindSafe(|| f(db, name)));
+    }
+}
+
+/// Calls `f` with the connection and the collation's name whenever a
+/// statement uses a collation the connection doesn't have, so `f` can
+/// define it with [`define_collation`] then, instead of defining every
+/// collation it might need up front:
+///
+/// ```ignore
+/// on_collation_needed(db, |db, name| match name.strip_prefix("len_") {
+///     Some(_) => define_collation(db, name, |a: &[u8], b: &[u8]| a.len().cmp(&b.len())),
+///     None => Ok(()),
+/// })?;
+/// ```
+///
+/// If `f` doesn't define the collation, or fails, the statement fails with
+/// "no such collation sequence". Like
+/// [`sqlite3_collation_needed`](https://www.sqlite.org/c3ref/collation_needed.html),
+/// there's one callback for each connection, so this replaces any earlier
+/// one, and `f` is dropped when the connection closes.
+pub fn on_collation_needed<F>(db: *mut sqlite3, f: F) -> Result<()>
+where
+    F: Fn(*mut sqlite3, &str) -> Result<()> + Send + Sync + 'static,
+{
+    let mut callbacks = COLLATION_NEEDED
+        .lock()
+        .map_err(|_| Error::new_message("collation callback registry was poisoned"))?;
+    let callbacks = callbacks.get_or_insert_with(HashMap::new);
+    let key = db as usize;
+    if !callbacks.contains_key(&key) {
+        on_connection_close(db, move || {
+            let callback = COLLATION_NEEDED
+                .lock()
+                .ok()
+                .and_then(|mut callbacks| callbacks.as_mut()?.remove(&key));
+            drop(callback);
+        })?;
+        let rc = unsafe {
+            sqlite3ext_collation_needed(db, std::ptr::null_mut(), Some(x_collation_needed))
+        };
+        if rc != SQLITE_OKAY {
+            return Err(Error::new_message(format!(
+                "could not set the collation needed callback, error code {}",
+                rc
+            )));
+        }
+    }
+    callbacks.insert(key, Arc::new(f));
+    Ok(())
+}
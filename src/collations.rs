@@ -0,0 +1,154 @@
+//! Ready-made collations for the orderings most extensions end up needing:
+//! Unicode case-insensitive, "natural" (numeric-aware), and locale-aware
+//! collations from [ICU4X](https://github.com/unicode-org/icu4x).
+//!
+//! ```sql
+//! select name from files order by name collate natural_sort;
+//! ```
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::collation::{define_collation, on_collation_needed};
+use crate::errors::{Error, Result};
+use crate::ext::sqlite3;
+use icu_collator::{Collator, CollatorOptions, Strength};
+use std::{cmp::Ordering, str::FromStr};
+
+/// Compares text case-insensitively, with Unicode's lowercase mappings
+/// rather than SQLite's ASCII-only `NOCASE`. Invalid UTF-8 is compared
+/// byte-by-byte.
+pub fn unicode_nocase(a: &[u8], b: &[u8]) -> Ordering {
+    match (std::str::from_utf8(a), std::str::from_utf8(b)) {
+        (Ok(a), Ok(b)) => a
+            .chars()
+            .flat_map(char::to_lowercase)
+            .cmp(b.chars().flat_map(char::to_lowercase)),
+        _ => a.cmp(b),
+    }
+}
+
+/// Compares text with runs of ASCII digits ordered by their numeric value,
+/// so "file2" sorts before "file10". Leading zeros only break ties, with
+/// fewest first: "file2" < "file02" < "file2a".
+pub fn natural(a: &[u8], b: &[u8]) -> Ordering {
+    let (mut i, mut j) = (0, 0);
+    let mut zeros = Ordering::Equal;
+    while i < a.len() && j < b.len() {
+        if a[i].is_ascii_digit() && b[j].is_ascii_digit() {
+            let a_end = i + a[i..].iter().take_while(|c| c.is_ascii_digit()).count();
+            let b_end = j + b[j..].iter().take_while(|c| c.is_ascii_digit()).count();
+            let (a_digits, b_digits) = (&a[i..a_end], &b[j..b_end]);
+            let a_value = trim_zeros(a_digits);
+            let b_value = trim_zeros(b_digits);
+            // with leading zeros gone, a longer number is a bigger one
+            let ordering = a_value
+                .len()
+                .cmp(&b_value.len())
+                .then_with(|| a_value.cmp(b_value));
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+            zeros = zeros.then(a_digits.len().cmp(&b_digits.len()));
+            i = a_end;
+            j = b_end;
+        } else {
+            if a[i] != b[j] {
+                return a[i].cmp(&b[j]);
+            }
+            i += 1;
+            j += 1;
+        }
+    }
+    (a.len() - i).cmp(&(b.len() - j)).then(zeros)
+}
+
+fn trim_zeros(digits: &[u8]) -> &[u8] {
+    let zeros = digits.iter().take_while(|c| **c == b'0').count();
+    &digits[zeros..]
+}
+
+/// How different two strings must be for a locale collation to tell them
+/// apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sensitivity {
+    /// Only different base letters, so "a", "A" and "á" are equal.
+    Base,
+    /// Different base letters or accents, so "a" and "A" are equal.
+    Accent,
+    /// Any difference, including case.
+    Case,
+}
+
+/// A collator for `locale` (a BCP-47 tag like "de" or "sv-SE"), using the
+/// locale data compiled into ICU4X.
+fn collator(locale: &str, sensitivity: Sensitivity) -> Result<Collator> {
+    let locale = icu_locid::Locale::from_str(locale)
+        .map_err(|err| Error::new_message(format!("invalid locale {:?}: {}", locale, err)))?;
+    let mut options = CollatorOptions::new();
+    options.strength = Some(match sensitivity {
+        Sensitivity::Base => Strength::Primary,
+        Sensitivity::Accent => Strength::Secondary,
+        Sensitivity::Case => Strength::Tertiary,
+    });
+    Collator::try_new(&(&locale).into(), options)
+        .map_err(|err| Error::new_message(format!("no collation data for {}: {}", locale, err)))
+}
+
+/// Defines a collation named `name` that orders text the way `locale`
+/// does, like `define_locale_collation(db, "swedish", "sv", Sensitivity::Case)`.
+pub fn define_locale_collation(
+    db: *mut sqlite3,
+    name: &str,
+    locale: &str,
+    sensitivity: Sensitivity,
+) -> Result<()> {
+    let collator = collator(locale, sensitivity)?;
+    define_collation(db, name, move |a, b| collator.compare_utf8(a, b))
+}
+
+/// Defines a locale collation the first time a statement uses it, for any
+/// collation named `icu_` and a locale, like `icu_sv` or `icu_de_ch`, rather
+/// than one for every locale up front. Other names are left alone. Uses the
+/// connection's [`on_collation_needed`] callback.
+pub fn define_locale_collations_on_demand(db: *mut sqlite3) -> Result<()> {
+    on_collation_needed(db, |db, name| match name.strip_prefix("icu_") {
+        Some(locale) => {
+            define_locale_collation(db, name, &locale.replace('_', "-"), Sensitivity::Case)
+        }
+        None => Ok(()),
+    })
+}
+
+/// Defines the `unicode_nocase` collation, and [`natural`] as `natural_sort`
+/// (NATURAL is a keyword, so it would need quoting everywhere).
+pub fn define_collations(db: *mut sqlite3) -> Result<()> {
+    define_collation(db, "unicode_nocase", unicode_nocase)?;
+    define_collation(db, "natural_sort", natural)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::collations::*;
+
+    #[test]
+    fn test_natural() {
+        let mut names = vec![
+            "file10", "file2", "file02", "File1", "file", "file2a", "10", "9",
+        ];
+        names.sort_by(|a, b| natural(a.as_bytes(), b.as_bytes()));
+        assert_eq!(
+            names,
+            vec!["9", "10", "File1", "file", "file2", "file02", "file2a", "file10"]
+        );
+    }
+
+    #[test]
+    fn test_unicode_nocase() {
+        assert_eq!(
+            unicode_nocase("ÉCOLE".as_bytes(), "école".as_bytes()),
+            Ordering::Equal
+        );
+        assert_eq!(unicode_nocase(b"a", b"B"), Ordering::Less);
+        assert_eq!(unicode_nocase(b"\xff", b"a"), Ordering::Greater);
+    }
+}
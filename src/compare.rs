@@ -0,0 +1,419 @@
+//! Compare SQLite values in Rust with the same semantics as SQLite itself.
+//!
+//! Virtual tables that filter or sort rows in Rust should order values exactly
+//! like SQLite would, otherwise `WHERE` clauses and `ORDER BY`s will disagree
+//! with the rest of the query. The rules implemented here come from
+//! <https://www.sqlite.org/datatype3.html#comparison_expressions>. LIKE, GLOB
+//! and case-insensitive comparisons call SQLite's own implementations.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api::{value_blob, value_double, value_int64, value_type, ColumnAffinity, ValueType};
+use crate::ext::{
+    sqlite3_value, sqlite3ext_strglob, sqlite3ext_stricmp, sqlite3ext_strlike, sqlite3ext_strnicmp,
+};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::ffi::CString;
+use std::os::raw::{c_int, c_uint};
+
+/// A borrowed SQL value, the unit that comparisons work with.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueRef<'a> {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(Cow<'a, [u8]>),
+    Blob(Cow<'a, [u8]>),
+}
+
+impl<'a> ValueRef<'a> {
+    /// Reads the given sqlite3_value, borrowing its text or blob contents.
+    pub fn from_value(value: &*mut sqlite3_value) -> ValueRef<'a> {
+        match value_type(value) {
+            ValueType::Null => ValueRef::Null,
+            ValueType::Integer => ValueRef::Integer(value_int64(value)),
+            // SQLite never stores NaN, it becomes NULL
+            ValueType::Float => match value_double(value) {
+                f if f.is_nan() => ValueRef::Null,
+                f => ValueRef::Real(f),
+            },
+            ValueType::Text => ValueRef::Text(Cow::Borrowed(value_blob(value))),
+            ValueType::Blob => ValueRef::Blob(Cow::Borrowed(value_blob(value))),
+        }
+    }
+
+    /// Applies the given column affinity to the value, like SQLite does before
+    /// comparing an operand against a column.
+    /// <https://www.sqlite.org/datatype3.html#type_conversions_prior_to_comparison>
+    pub fn with_affinity(self, affinity: &ColumnAffinity) -> ValueRef<'a> {
+        match (affinity, self) {
+            (
+                ColumnAffinity::Integer | ColumnAffinity::Real | ColumnAffinity::Numeric,
+                ValueRef::Text(text),
+            ) => match parse_numeric(&text) {
+                Some(numeric) => numeric,
+                None => ValueRef::Text(text),
+            },
+            (ColumnAffinity::Text, ValueRef::Integer(i)) => {
+                ValueRef::Text(Cow::Owned(i.to_string().into_bytes()))
+            }
+            (ColumnAffinity::Text, ValueRef::Real(f)) => {
+                ValueRef::Text(Cow::Owned(format_real(f).into_bytes()))
+            }
+            (_, value) => value,
+        }
+    }
+
+    /// Orders the two values like SQLite: NULLs first, then INTEGER and REAL
+    /// values compared numerically, then TEXT values compared with the given
+    /// collation, then BLOBs compared with memcmp().
+    pub fn compare(&self, other: &ValueRef, collation: &Collation) -> Ordering {
+        match (self, other) {
+            (ValueRef::Integer(a), ValueRef::Integer(b)) => a.cmp(b),
+            (ValueRef::Real(a), ValueRef::Real(b)) => a.partial_cmp(b).unwrap_or(Ordering::Equal),
+            (ValueRef::Integer(a), ValueRef::Real(b)) => compare_int_real(*a, *b),
+            (ValueRef::Real(a), ValueRef::Integer(b)) => compare_int_real(*b, *a).reverse(),
+            (ValueRef::Text(a), ValueRef::Text(b)) => collation.compare(a, b),
+            (ValueRef::Blob(a), ValueRef::Blob(b)) => a.cmp(b),
+            (a, b) => a.type_rank().cmp(&b.type_rank()),
+        }
+    }
+
+    /// Copies any borrowed text or blob, so the value can outlive the
+    /// sqlite3_value it was read from.
+    pub fn into_owned(self) -> ValueRef<'static> {
+        match self {
+            ValueRef::Null => ValueRef::Null,
+            ValueRef::Integer(i) => ValueRef::Integer(i),
+            ValueRef::Real(f) => ValueRef::Real(f),
+            ValueRef::Text(text) => ValueRef::Text(Cow::Owned(text.into_owned())),
+            ValueRef::Blob(blob) => ValueRef::Blob(Cow::Owned(blob.into_owned())),
+        }
+    }
+
+    fn type_rank(&self) -> u8 {
+        match self {
+            ValueRef::Null => 0,
+            ValueRef::Integer(_) | ValueRef::Real(_) => 1,
+            ValueRef::Text(_) => 2,
+            ValueRef::Blob(_) => 3,
+        }
+    }
+}
+
+/// A collating sequence used to compare TEXT values.
+/// <https://www.sqlite.org/datatype3.html#collating_sequences>
+pub enum Collation<'a> {
+    /// Compares with memcmp(), the default.
+    Binary,
+    /// Like Binary, but the 26 upper case ASCII characters are folded to lower case.
+    NoCase,
+    /// Like Binary, but trailing space characters are ignored.
+    RTrim,
+    /// Any other comparison, like one registered with [`crate::define_collation`].
+    Custom(&'a dyn Fn(&[u8], &[u8]) -> Ordering),
+}
+
+impl<'a> Collation<'a> {
+    /// Returns the built-in collation with the given name (case-insensitive),
+    /// if there is one.
+    pub fn from_name(name: &str) -> Option<Collation<'a>> {
+        match name.to_ascii_lowercase().as_str() {
+            "binary" => Some(Collation::Binary),
+            "nocase" => Some(Collation::NoCase),
+            "rtrim" => Some(Collation::RTrim),
+            _ => None,
+        }
+    }
+
+    /// Compares the two strings with the collation.
+    pub fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        match self {
+            Collation::Binary => a.cmp(b),
+            Collation::NoCase => a
+                .iter()
+                .map(u8::to_ascii_lowercase)
+                .cmp(b.iter().map(u8::to_ascii_lowercase)),
+            Collation::RTrim => trim_end_spaces(a).cmp(trim_end_spaces(b)),
+            Collation::Custom(f) => f(a, b),
+        }
+    }
+}
+
+/// Compares the two sqlite3_values with SQLite's rules for ORDER BY and
+/// comparison operators, where neither operand has an affinity.
+/// See [`ValueRef::compare`].
+pub fn compare_values(
+    a: &*mut sqlite3_value,
+    b: &*mut sqlite3_value,
+    collation: &Collation,
+) -> Ordering {
+    ValueRef::from_value(a).compare(&ValueRef::from_value(b), collation)
+}
+
+/// Compares a column value `a` to an operand `b`, first applying the column's
+/// affinity to `b`. This matches how SQLite evaluates `column = ?` or
+/// `column < 'literal'` style constraints.
+pub fn compare_values_with_affinity(
+    a: &*mut sqlite3_value,
+    b: &*mut sqlite3_value,
+    affinity: &ColumnAffinity,
+    collation: &Collation,
+) -> Ordering {
+    let b = ValueRef::from_value(b).with_affinity(affinity);
+    ValueRef::from_value(a).compare(&b, collation)
+}
+
+/// Evaluates `text LIKE pattern [ESCAPE escape]` with SQLite's own
+/// sqlite3_strlike(), so it matches the built-in like() exactly. `%` matches
+/// any sequence of characters and `_` matches any one character. Only ASCII
+/// characters are case-folded, and the escape character makes the following
+/// character match literally. Like in SQL, text ends at a NUL character.
+pub fn like(pattern: &str, text: &str, escape: Option<char>) -> bool {
+    let pattern = nul_terminated(pattern);
+    let text = nul_terminated(text);
+    let escape = escape.map_or(0, |c| c as c_uint);
+    unsafe { sqlite3ext_strlike(pattern.as_ptr(), text.as_ptr(), escape) == 0 }
+}
+
+/// Evaluates `text GLOB pattern` with SQLite's own sqlite3_strglob(), so it
+/// matches the built-in glob() exactly. `*` matches any sequence of
+/// characters, `?` matches any one character, and `[...]` matches one
+/// character from a set, with `^` negating and `-` for ranges. GLOB is case
+/// sensitive and has no escape character.
+pub fn glob(pattern: &str, text: &str) -> bool {
+    let pattern = nul_terminated(pattern);
+    let text = nul_terminated(text);
+    unsafe { sqlite3ext_strglob(pattern.as_ptr(), text.as_ptr()) == 0 }
+}
+
+/// Compares two strings with SQLite's sqlite3_stricmp(), folding only the 26
+/// ASCII letters to lower case, the comparison behind the NOCASE collation
+/// and case-insensitive identifier lookups.
+pub fn stricmp(a: &str, b: &str) -> Ordering {
+    let a = nul_terminated(a);
+    let b = nul_terminated(b);
+    unsafe { sqlite3ext_stricmp(a.as_ptr(), b.as_ptr()) }.cmp(&0)
+}
+
+/// Like [`stricmp`], but only compares the first `n` bytes.
+pub fn strnicmp(a: &str, b: &str, n: usize) -> Ordering {
+    let a = nul_terminated(a);
+    let b = nul_terminated(b);
+    let n = n.min(c_int::MAX as usize) as c_int;
+    unsafe { sqlite3ext_strnicmp(a.as_ptr(), b.as_ptr(), n) }.cmp(&0)
+}
+
+/// The string up to its first NUL character, which is where SQLite's C
+/// string functions stop anyway.
+fn nul_terminated(s: &str) -> CString {
+    let end = s.find('\0').unwrap_or(s.len());
+    CString::new(&s[..end]).unwrap()
+}
+
+fn trim_end_spaces(s: &[u8]) -> &[u8] {
+    let end = s.iter().rposition(|c| *c != b' ').map_or(0, |i| i + 1);
+    &s[..end]
+}
+
+/// Exact comparison of an integer and a float, without losing precision
+/// for integers larger than 2^53. Mirrors sqlite3IntFloatCompare().
+fn compare_int_real(i: i64, r: f64) -> Ordering {
+    if r.is_nan() {
+        // SQLite treats NaN as NULL, which is less than any number
+        return Ordering::Greater;
+    }
+    if r < -9223372036854775808.0 {
+        return Ordering::Greater;
+    }
+    if r >= 9223372036854775808.0 {
+        return Ordering::Less;
+    }
+    let truncated = r as i64;
+    match i.cmp(&truncated) {
+        Ordering::Equal => (i as f64).partial_cmp(&r).unwrap_or(Ordering::Equal),
+        ordering => ordering,
+    }
+}
+
+/// Converts text to an INTEGER or REAL if it's a well-formed number, like NUMERIC
+/// affinity does. Leading and trailing spaces are allowed.
+fn parse_numeric<'a>(text: &[u8]) -> Option<ValueRef<'a>> {
+    let text = std::str::from_utf8(text).ok()?.trim_matches(' ');
+    if text.is_empty() || !text.bytes().any(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    // reject things Rust parses but SQLite doesn't, like "inf" or "NaN"
+    if !text
+        .bytes()
+        .all(|c| c.is_ascii_digit() || matches!(c, b'+' | b'-' | b'.' | b'e' | b'E'))
+    {
+        return None;
+    }
+    if let Ok(i) = text.parse::<i64>() {
+        return Some(ValueRef::Integer(i));
+    }
+    let f = text.parse::<f64>().ok()?;
+    // "a REAL that can be exactly represented as an integer is converted to INTEGER"
+    if f.fract() == 0.0 && f.abs() < 9223372036854775808.0 {
+        Some(ValueRef::Integer(f as i64))
+    } else {
+        Some(ValueRef::Real(f))
+    }
+}
+
+/// Formats a REAL how SQLite would when converting to TEXT, "%!.15g".
+fn format_real(f: f64) -> String {
+    if f.is_infinite() {
+        return if f > 0.0 {
+            "Inf".to_owned()
+        } else {
+            "-Inf".to_owned()
+        };
+    }
+    let exponent = if f == 0.0 {
+        0
+    } else {
+        f.abs().log10().floor() as i32
+    };
+    if !(-4..15).contains(&exponent) {
+        let formatted = format!("{:.14e}", f);
+        let (mantissa, exp) = formatted.split_once('e').unwrap_or((&formatted, "0"));
+        let mantissa = mantissa.trim_end_matches('0');
+        let mantissa = if mantissa.ends_with('.') {
+            format!("{}0", mantissa)
+        } else {
+            mantissa.to_owned()
+        };
+        let exp: i32 = exp.parse().unwrap_or(0);
+        return format!(
+            "{}e{}{:02}",
+            mantissa,
+            if exp < 0 { '-' } else { '+' },
+            exp.abs()
+        );
+    }
+    let decimals = (14 - exponent).max(0) as usize;
+    let formatted = format!("{:.*}", decimals, f);
+    let formatted = if formatted.contains('.') {
+        formatted.trim_end_matches('0').to_owned()
+    } else {
+        formatted
+    };
+    if formatted.ends_with('.') {
+        format!("{}0", formatted)
+    } else if !formatted.contains('.') {
+        format!("{}.0", formatted)
+    } else {
+        formatted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compare::*;
+
+    fn text(s: &str) -> ValueRef<'_> {
+        ValueRef::Text(Cow::Borrowed(s.as_bytes()))
+    }
+
+    #[test]
+    fn test_type_ordering() {
+        let ordered = [
+            ValueRef::Null,
+            ValueRef::Integer(-1),
+            ValueRef::Real(0.5),
+            ValueRef::Integer(1),
+            text("A"),
+            text("a"),
+            ValueRef::Blob(Cow::Borrowed(b"\x00")),
+        ];
+        for (i, a) in ordered.iter().enumerate() {
+            for (j, b) in ordered.iter().enumerate() {
+                assert_eq!(
+                    a.compare(b, &Collation::Binary),
+                    i.cmp(&j),
+                    "{:?} {:?}",
+                    a,
+                    b
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_int_real() {
+        assert_eq!(
+            ValueRef::Integer(1).compare(&ValueRef::Real(1.0), &Collation::Binary),
+            Ordering::Equal
+        );
+        // 2^53 + 1 isn't representable as a f64
+        assert_eq!(
+            ValueRef::Integer(9007199254740993)
+                .compare(&ValueRef::Real(9007199254740992.0), &Collation::Binary),
+            Ordering::Greater
+        );
+        assert_eq!(
+            ValueRef::Real(1e300).compare(&ValueRef::Integer(i64::MAX), &Collation::Binary),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn test_collations() {
+        assert_eq!(
+            text("ABC").compare(&text("abc"), &Collation::NoCase),
+            Ordering::Equal
+        );
+        assert_eq!(
+            text("abc  ").compare(&text("abc"), &Collation::RTrim),
+            Ordering::Equal
+        );
+        assert_eq!(
+            text("abc  ").compare(&text("abc"), &Collation::Binary),
+            Ordering::Greater
+        );
+        let reverse = |a: &[u8], b: &[u8]| b.cmp(a);
+        assert_eq!(
+            text("a").compare(&text("b"), &Collation::Custom(&reverse)),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn test_affinity() {
+        assert_eq!(
+            text(" 12 ").with_affinity(&ColumnAffinity::Integer),
+            ValueRef::Integer(12)
+        );
+        assert_eq!(
+            text("1e3").with_affinity(&ColumnAffinity::Numeric),
+            ValueRef::Integer(1000)
+        );
+        assert_eq!(
+            text("1.5").with_affinity(&ColumnAffinity::Real),
+            ValueRef::Real(1.5)
+        );
+        assert_eq!(
+            text("12a").with_affinity(&ColumnAffinity::Integer),
+            text("12a")
+        );
+        assert_eq!(
+            ValueRef::Integer(12).with_affinity(&ColumnAffinity::Text),
+            text("12")
+        );
+        assert_eq!(
+            ValueRef::Real(1.5).with_affinity(&ColumnAffinity::Text),
+            text("1.5")
+        );
+        assert_eq!(
+            ValueRef::Real(100.0).with_affinity(&ColumnAffinity::Text),
+            text("100.0")
+        );
+        assert_eq!(
+            ValueRef::Real(1e20).with_affinity(&ColumnAffinity::Text),
+            text("1.0e+20")
+        );
+        assert_eq!(text("12").with_affinity(&ColumnAffinity::Blob), text("12"));
+    }
+}
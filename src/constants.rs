@@ -19,3 +19,19 @@ pub const SQLITE_ERROR: i32 = 1;
 
 /// https://www.sqlite.org/rescode.html#constraint
 pub const SQLITE_CONSTRAINT: i32 = 19;
+
+/// https://www.sqlite.org/c3ref/c_vtab_constraint_support.html
+pub const SQLITE_VTAB_CONSTRAINT_SUPPORT: i32 = 1;
+pub const SQLITE_VTAB_INNOCUOUS: i32 = 2;
+pub const SQLITE_VTAB_DIRECTONLY: i32 = 3;
+
+/// https://www.sqlite.org/c3ref/c_fail.html
+pub const SQLITE_ROLLBACK: i32 = 1;
+pub const SQLITE_IGNORE: i32 = 2;
+pub const SQLITE_FAIL: i32 = 3;
+pub const SQLITE_ABORT: i32 = 4;
+pub const SQLITE_REPLACE: i32 = 5;
+
+/// https://www.sqlite.org/c3ref/c_deterministic.html#sqliteresultsubtype,
+/// new in SQLite 3.45, which earlier versions ignore.
+pub const SQLITE_RESULT_SUBTYPE: i32 = 0x001000000;
@@ -0,0 +1,72 @@
+//! Formatting values as CSV or TSV rows, quoted according to
+//! [RFC 4180](https://www.rfc-editor.org/rfc/rfc4180).
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api::{result_text, value_blob, value_text, value_type, ValueType};
+use crate::errors::Result;
+use crate::ext::{sqlite3_context, sqlite3_value};
+use std::borrow::Cow;
+
+/// Returns the field quoted if it needs to be, ex `a,b` becomes `"a,b"` and
+/// `say "hi"` becomes `"say ""hi"""`. Fields are quoted if they contain the
+/// delimiter, a double quote, or a line break.
+pub fn quote_field(field: &str, delimiter: char) -> Cow<'_, str> {
+    let needs_quotes = field
+        .chars()
+        .any(|c| c == delimiter || c == '"' || c == '\r' || c == '\n');
+    if needs_quotes {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+/// Joins the fields into a single row, quoting each field as needed. The row
+/// has no trailing line terminator, RFC 4180 uses "\r\n" between rows.
+pub fn format_row<I, S>(fields: I, delimiter: char) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut row = String::new();
+    for (i, field) in fields.into_iter().enumerate() {
+        if i > 0 {
+            row.push(delimiter);
+        }
+        row.push_str(&quote_field(field.as_ref(), delimiter));
+    }
+    row
+}
+
+/// The CSV text of a single sqlite3_value: NULL is an empty field, numbers
+/// use SQLite's own text conversion, and blobs are read as (lossy) UTF-8.
+pub fn value_csv_field(value: &*mut sqlite3_value) -> Result<Cow<'static, str>> {
+    Ok(match value_type(value) {
+        ValueType::Null => Cow::Borrowed(""),
+        ValueType::Blob => Cow::Owned(String::from_utf8_lossy(value_blob(value)).into_owned()),
+        _ => Cow::Owned(value_text(value)?.to_owned()),
+    })
+}
+
+fn result_row(
+    context: *mut sqlite3_context,
+    values: &[*mut sqlite3_value],
+    delimiter: char,
+) -> Result<()> {
+    let fields = values
+        .iter()
+        .map(value_csv_field)
+        .collect::<Result<Vec<_>>>()?;
+    result_text(context, format_row(fields, delimiter))
+}
+
+/// Results the given values as a single comma-separated row.
+pub fn result_csv_row(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    result_row(context, values, ',')
+}
+
+/// Results the given values as a single tab-separated row, with the same
+/// quoting rules as CSV.
+pub fn result_tsv_row(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    result_row(context, values, '\t')
+}
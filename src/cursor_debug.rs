@@ -0,0 +1,189 @@
+//! Dumps of virtual table cursors' internal state, for debugging complex
+//! cursor logic during development.
+//!
+//! A cursor that implements [`DebugState`] describes itself as JSON, like
+//! its current index, the pages it has left to fetch or its cache hits.
+//! Wrapped in [`Debugged`], it publishes that state after every filter and
+//! next, and [`define_cursor_debug`] adds a `<module>_cursor_debug()`
+//! function that returns the latest state of every open cursor of the
+//! module's tables:
+//!
+//! ```sql
+//! select value, feed_cursor_debug() from feed limit 3;
+//! -- 1|[{"cursor":1,"eof":false,"state":{"page":1,"cache_hits":0},"table":"feed"}]
+//! ```
+//!
+//! ```ignore
+//! impl DebugState for FeedCursor {
+//!     fn debug_state(&self) -> serde_json::Value {
+//!         json!({"page": self.page, "cache_hits": self.cache_hits})
+//!     }
+//! }
+//!
+//! fn open(&mut self) -> Result<Debugged<FeedCursor>> {
+//!     Ok(Debugged::new(FeedCursor::new(), "feed", &self.name))
+//! }
+//! ```
+//!
+//! States are only published for modules that [`define_cursor_debug`] was
+//! called for, so a `Debugged` cursor costs next to nothing otherwise.
+//! Published states belong to the extension library, so every connection
+//! it's loaded into shares them, and a cursor's state is dropped when the
+//! cursor is closed.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api;
+use crate::errors::Result;
+use crate::ext::{sqlite3, sqlite3_context, sqlite3_value};
+use crate::scalar::{define_scalar_function, FunctionFlags};
+use crate::table::VTabCursor;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::os::raw::c_int;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// A cursor that can describe its internal state.
+pub trait DebugState {
+    /// The cursor's state, as JSON.
+    fn debug_state(&self) -> Value;
+}
+
+/// The latest state of each open cursor, by cursor, for each module being
+/// debugged.
+type Published = BTreeMap<String, BTreeMap<u64, Value>>;
+
+static PUBLISHED: Mutex<Published> = Mutex::new(BTreeMap::new());
+static NEXT_CURSOR: AtomicU64 = AtomicU64::new(1);
+
+fn with_published<T>(f: impl FnOnce(&mut Published) -> T) -> T {
+    // states are only ever replaced whole, so a panic while holding the
+    // lock can't leave one half written
+    let mut published = PUBLISHED.lock().unwrap_or_else(|err| err.into_inner());
+    f(&mut published)
+}
+
+/// The latest states of the open cursors of `module`'s tables, oldest
+/// cursor first, or None if `module` isn't being debugged.
+pub fn cursor_states(module: &str) -> Option<Vec<Value>> {
+    with_published(|published| {
+        published
+            .get(module)
+            .map(|cursors| cursors.values().cloned().collect())
+    })
+}
+
+/// A cursor that publishes its [`DebugState`] for `<module>_cursor_debug()`.
+/// Must be the table's `Cursor`, with the wrapped cursor's
+/// `sqlite3_vtab_cursor` first in it, as usual.
+#[repr(C)]
+pub struct Debugged<C> {
+    /// first, so its base is this cursor's too
+    cursor: C,
+    module: String,
+    table: String,
+    id: u64,
+}
+
+impl<C> Debugged<C> {
+    /// Wraps `cursor` of the table `table`, made by the module `module`.
+    pub fn new(cursor: C, module: &str, table: &str) -> Debugged<C> {
+        Debugged {
+            cursor,
+            module: module.to_owned(),
+            table: table.to_owned(),
+            id: NEXT_CURSOR.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.cursor
+    }
+
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.cursor
+    }
+}
+
+impl<C: VTabCursor + DebugState> Debugged<C> {
+    /// Publishes the cursor's state, if its module is being debugged.
+    fn publish(&self) {
+        if !with_published(|published| published.contains_key(&self.module)) {
+            return;
+        }
+        // outside the lock, so debug_state() can't deadlock on it
+        let state = json!({
+            "table": self.table,
+            "cursor": self.id,
+            "eof": self.cursor.eof(),
+            "state": self.cursor.debug_state(),
+        });
+        with_published(|published| {
+            if let Some(cursors) = published.get_mut(&self.module) {
+                cursors.insert(self.id, state);
+            }
+        });
+    }
+}
+
+impl<C> Drop for Debugged<C> {
+    fn drop(&mut self) {
+        with_published(|published| {
+            if let Some(cursors) = published.get_mut(&self.module) {
+                cursors.remove(&self.id);
+            }
+        });
+    }
+}
+
+impl<C: VTabCursor + DebugState> VTabCursor for Debugged<C> {
+    fn filter(
+        &mut self,
+        idx_num: c_int,
+        idx_str: Option<&str>,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        let result = self.cursor.filter(idx_num, idx_str, values);
+        self.publish();
+        result
+    }
+
+    fn next(&mut self) -> Result<()> {
+        let result = self.cursor.next();
+        self.publish();
+        result
+    }
+
+    fn eof(&self) -> bool {
+        self.cursor.eof()
+    }
+
+    fn column(&self, ctx: *mut sqlite3_context, i: c_int) -> Result<()> {
+        self.cursor.column(ctx, i)
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        self.cursor.rowid()
+    }
+}
+
+/// Starts publishing the states of `module`'s [`Debugged`] cursors, and
+/// defines `<module>_cursor_debug()`, which returns them as a JSON array.
+/// The states change as queries run, so the function is neither
+/// deterministic nor allowed in triggers and views.
+pub fn define_cursor_debug(db: *mut sqlite3, module: &str) -> Result<()> {
+    with_published(|published| {
+        published.entry(module.to_owned()).or_default();
+    });
+    let key = module.to_owned();
+    define_scalar_function(
+        db,
+        &format!("{}_cursor_debug", module),
+        0,
+        move |context, _values| {
+            let states = cursor_states(&key).unwrap_or_default();
+            api::result_json(context, Value::Array(states))
+        },
+        FunctionFlags::UTF8 | FunctionFlags::DIRECTONLY | FunctionFlags::RESULT_SUBTYPE,
+    )
+}
@@ -0,0 +1,136 @@
+//! Owned database connections, opened from inside an extension.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api::{db_filename, db_readonly};
+use crate::errors::{Error, Result};
+use crate::ext::{
+    sqlite3, sqlite3_vfs, sqlite3ext_close_v2, sqlite3ext_errmsg, sqlite3ext_file_control,
+    sqlite3ext_open_v2,
+};
+use sqlite3ext_sys::{SQLITE_FCNTL_VFS_POINTER, SQLITE_OPEN_READONLY, SQLITE_OPEN_READWRITE};
+use std::{
+    ffi::{CStr, CString},
+    os::raw::{c_char, c_int, c_void},
+};
+
+/// A database connection that's closed when dropped, unlike the `*mut sqlite3`
+/// handles passed to entrypoints and callbacks, which belong to the host.
+pub struct Database {
+    db: *mut sqlite3,
+}
+
+// a connection can be moved to another thread, as long as it's only used by
+// one thread at a time
+unsafe impl Send for Database {}
+
+fn c_str<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    unsafe { CStr::from_ptr(s) }.to_str().ok()
+}
+
+impl Database {
+    /// Opens a connection with
+    /// [`sqlite3_open_v2`](https://www.sqlite.org/c3ref/open.html). `vfs` of
+    /// None uses the default VFS.
+    pub fn open(filename: &str, flags: c_int, vfs: Option<&str>) -> Result<Database> {
+        let cfilename = CString::new(filename)?;
+        let cvfs = vfs.map(CString::new).transpose()?;
+        let mut db: *mut sqlite3 = std::ptr::null_mut();
+        let rc = unsafe {
+            sqlite3ext_open_v2(
+                cfilename.as_ptr(),
+                &mut db,
+                flags,
+                cvfs.as_ref().map_or(std::ptr::null(), |vfs| vfs.as_ptr()),
+            )
+        };
+        // SQLite allocates a handle even when opening fails, to hold the error
+        let database = Database { db };
+        if rc != 0 {
+            let message = c_str(unsafe { sqlite3ext_errmsg(db) }).unwrap_or("out of memory");
+            return Err(Error::new_message(format!(
+                "could not open {:?}: {}",
+                filename, message
+            )));
+        }
+        Ok(database)
+    }
+
+    /// Opens another, independent connection to the main database of `db`,
+    /// with the same VFS. It's opened read-only if `db` is, otherwise read-write.
+    ///
+    /// Queries on the new connection don't block `db`, so long-running work
+    /// can be moved to a background thread. In-memory and temporary databases
+    /// can't be shared this way, and return an error.
+    pub fn open_same_file(db: *mut sqlite3) -> Result<Database> {
+        SameFile::of(db)?.open()
+    }
+
+    /// The raw connection handle, for use with the rest of this crate.
+    pub fn handle(&self) -> *mut sqlite3 {
+        self.db
+    }
+}
+
+/// How [`Database::open_same_file`] opens a connection to the main
+/// database of another, kept to open more of them later.
+pub(crate) struct SameFile {
+    filename: String,
+    flags: c_int,
+    vfs: Option<String>,
+}
+
+impl SameFile {
+    pub(crate) fn of(db: *mut sqlite3) -> Result<SameFile> {
+        let filename = db_filename(db, "main")?.ok_or_else(|| {
+            Error::new_message(
+                "cannot open another connection to an in-memory or temporary database",
+            )
+        })?;
+        let vfs = main_vfs(db)?;
+        let vfs = if vfs.is_null() {
+            None
+        } else {
+            c_str(unsafe { (*vfs).zName }).map(str::to_owned)
+        };
+        let flags = if db_readonly(db, "main")? {
+            SQLITE_OPEN_READONLY
+        } else {
+            SQLITE_OPEN_READWRITE
+        };
+        Ok(SameFile {
+            filename,
+            flags: flags as c_int,
+            vfs,
+        })
+    }
+
+    pub(crate) fn open(&self) -> Result<Database> {
+        Database::open(&self.filename, self.flags, self.vfs.as_deref())
+    }
+}
+
+/// The VFS of the connection's main database, or null if it couldn't be found.
+pub(crate) fn main_vfs(db: *mut sqlite3) -> Result<*mut sqlite3_vfs> {
+    let main = CString::new("main")?;
+    let mut vfs: *mut sqlite3_vfs = std::ptr::null_mut();
+    let rc = unsafe {
+        sqlite3ext_file_control(
+            db,
+            main.as_ptr(),
+            SQLITE_FCNTL_VFS_POINTER as c_int,
+            (&mut vfs as *mut *mut sqlite3_vfs).cast::<c_void>(),
+        )
+    };
+    Ok(if rc == 0 { vfs } else { std::ptr::null_mut() })
+}
+
+impl Drop for Database {
+    fn drop(&mut self) {
+        // sqlite3_close_v2 defers closing until any remaining statements are
+        // finalized, so it never fails with SQLITE_BUSY
+        unsafe { sqlite3ext_close_v2(self.db) };
+    }
+}
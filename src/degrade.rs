@@ -0,0 +1,384 @@
+//! Graceful degradation for virtual table scans over backends that can fail
+//! part of the way through, like a paged remote API.
+//!
+//! By default an error from a cursor's xFilter or xNext fails the whole
+//! query. [`Degrading`] wraps a cursor and handles the error by the table's
+//! [`FailurePolicy`] instead:
+//!
+//! - `error` fails the query, as usual.
+//! - `truncate` ends the scan, keeping the rows returned so far.
+//! - `null` ends the scan after one more row with every column NULL, so the
+//!   query still sees that something is missing.
+//!
+//! Whatever the policy, the failure is recorded, and the `scan_status`
+//! table that [`define_scan_status`] adds lists the recorded failures:
+//!
+//! ```sql
+//! create virtual table feed using remote_feed(url='https://...', on_error=truncate);
+//! select count(*) from feed;
+//! select "table", policy, rows, error from scan_status;
+//! -- feed|truncate|250|connection reset by peer
+//! ```
+//!
+//! ```ignore
+//! fn connect(db: *mut sqlite3, _aux: Option<&()>, mut args: VTabArguments) -> Result<(String, FeedTable)> {
+//!     let policy = FailurePolicy::take_from(&mut args.arguments)?;
+//!     // ... the rest of args.arguments are the table's own
+//! }
+//!
+//! fn open(&mut self) -> Result<Degrading<FeedCursor>> {
+//!     Ok(Degrading::new(FeedCursor::new(), self.db, &self.name, self.policy))
+//! }
+//! ```
+//!
+//! A [`crate::timeout::Timed`] cursor inside a `Degrading` one has its
+//! timeouts handled the same way, so a slow scan can end early with the
+//! rows it has instead of failing.
+//!
+//! Recorded failures belong to the extension library, so every connection
+//! it's loaded into shares them. Only the latest [`MAX_RECORDED_FAILURES`]
+//! are kept, until [`reset`] drops them.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api;
+use crate::clock::vfs_current_time_millis;
+use crate::errors::{Error, Result};
+use crate::ext::{sqlite3, sqlite3_context, sqlite3_value, sqlite3_vtab, sqlite3_vtab_cursor};
+use crate::table::{
+    define_table_function, BestIndexError, IndexInfo, VTab, VTabArguments, VTabCursor,
+};
+use crate::vtab_argparse::{parse_argument, Argument, ConfigOptionValue};
+use std::collections::VecDeque;
+use std::mem;
+use std::os::raw::c_int;
+use std::sync::Mutex;
+
+/// How many failures [`record`] keeps before dropping the oldest.
+pub const MAX_RECORDED_FAILURES: usize = 1000;
+
+/// What a scan does when its cursor fails part of the way through.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FailurePolicy {
+    /// Fail the query.
+    #[default]
+    Error,
+    /// End the scan with the rows returned so far.
+    Truncate,
+    /// End the scan with one more row of NULLs.
+    NullFill,
+}
+
+impl FailurePolicy {
+    /// The policy's name in the `on_error` option.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailurePolicy::Error => "error",
+            FailurePolicy::Truncate => "truncate",
+            FailurePolicy::NullFill => "null",
+        }
+    }
+
+    /// The policy named `name`, as it's written in the `on_error` option.
+    pub fn parse(name: &str) -> Result<FailurePolicy> {
+        match name.to_lowercase().as_str() {
+            "error" => Ok(FailurePolicy::Error),
+            "truncate" => Ok(FailurePolicy::Truncate),
+            "null" => Ok(FailurePolicy::NullFill),
+            _ => Err(Error::new_message(format!(
+                "on_error must be error, truncate or null, not {}",
+                name
+            ))),
+        }
+    }
+
+    /// Removes the `on_error=<policy>` option from a table's arguments and
+    /// returns it, or [`FailurePolicy::Error`] if there isn't one.
+    pub fn take_from(arguments: &mut Vec<String>) -> Result<FailurePolicy> {
+        let mut policy = Ok(FailurePolicy::default());
+        arguments.retain(|argument| match parse_argument(argument) {
+            Ok(Argument::Config(option)) if option.key.trim() == "on_error" => {
+                let value = match &option.value {
+                    ConfigOptionValue::Bareword(value)
+                    | ConfigOptionValue::Quoted(value)
+                    | ConfigOptionValue::SqliteParameter(value) => value,
+                };
+                if policy.is_ok() {
+                    policy = FailurePolicy::parse(value);
+                }
+                false
+            }
+            _ => true,
+        });
+        policy
+    }
+}
+
+/// A scan that failed, as `scan_status` lists it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanFailure {
+    /// The virtual table that was scanned.
+    pub table: String,
+    /// How the failure was handled.
+    pub policy: FailurePolicy,
+    /// How many rows the scan returned before it failed.
+    pub rows: i64,
+    /// The cursor's error message.
+    pub error: String,
+    /// When it failed, in milliseconds since the Unix epoch by the VFS's
+    /// clock, if the clock could be read.
+    pub failed_at: Option<i64>,
+}
+
+static FAILURES: Mutex<VecDeque<ScanFailure>> = Mutex::new(VecDeque::new());
+
+fn with_failures<T>(f: impl FnOnce(&mut VecDeque<ScanFailure>) -> T) -> T {
+    // failures are only ever pushed or popped whole, so a panic while
+    // holding the lock can't leave one half written
+    let mut failures = FAILURES.lock().unwrap_or_else(|err| err.into_inner());
+    f(&mut failures)
+}
+
+/// Records a failed scan, dropping the oldest one if there are already
+/// [`MAX_RECORDED_FAILURES`].
+pub fn record(failure: ScanFailure) {
+    with_failures(|failures| {
+        if failures.len() >= MAX_RECORDED_FAILURES {
+            failures.pop_front();
+        }
+        failures.push_back(failure);
+    });
+}
+
+/// Copies of the recorded failures, oldest first.
+pub fn snapshot() -> Vec<ScanFailure> {
+    with_failures(|failures| failures.iter().cloned().collect())
+}
+
+/// Drops every recorded failure, returning how many were dropped.
+pub fn reset() -> usize {
+    with_failures(|failures| mem::take(failures).len())
+}
+
+/// A cursor whose scans handle failures by a [`FailurePolicy`]. Must be the
+/// table's `Cursor`, with the wrapped cursor's `sqlite3_vtab_cursor` first
+/// in it, as usual.
+#[repr(C)]
+pub struct Degrading<C> {
+    /// first, so its base is this cursor's too
+    cursor: C,
+    db: *mut sqlite3,
+    table: String,
+    policy: FailurePolicy,
+    /// Rows returned by the current scan.
+    rows: i64,
+    /// Whether the current row is the row of NULLs after a failure.
+    filling: bool,
+    /// Whether the current scan ended early after a failure.
+    ended: bool,
+}
+
+impl<C> Degrading<C> {
+    /// Wraps `cursor` of the table `table`, reading the time of failures
+    /// from `db`'s VFS.
+    pub fn new(cursor: C, db: *mut sqlite3, table: &str, policy: FailurePolicy) -> Degrading<C> {
+        Degrading {
+            cursor,
+            db,
+            table: table.to_owned(),
+            policy,
+            rows: 0,
+            filling: false,
+            ended: false,
+        }
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.cursor
+    }
+
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.cursor
+    }
+
+    /// Records `err` and ends the scan by the policy, or returns it to
+    /// fail the query.
+    fn fail(&mut self, err: Error) -> Result<()> {
+        record(ScanFailure {
+            table: self.table.clone(),
+            policy: self.policy,
+            rows: self.rows,
+            error: err.reported_message(),
+            failed_at: vfs_current_time_millis(self.db).ok(),
+        });
+        match self.policy {
+            FailurePolicy::Error => Err(err),
+            FailurePolicy::Truncate => {
+                self.ended = true;
+                Ok(())
+            }
+            FailurePolicy::NullFill => {
+                self.filling = true;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<C: VTabCursor> Degrading<C> {
+    /// Counts the row the wrapped cursor moved to, or handles its failure.
+    fn moved(&mut self, result: Result<()>) -> Result<()> {
+        match result {
+            Ok(()) => {
+                if !self.cursor.eof() {
+                    self.rows += 1;
+                }
+                Ok(())
+            }
+            Err(err) => self.fail(err),
+        }
+    }
+}
+
+impl<C: VTabCursor> VTabCursor for Degrading<C> {
+    fn filter(
+        &mut self,
+        idx_num: c_int,
+        idx_str: Option<&str>,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.rows = 0;
+        self.filling = false;
+        self.ended = false;
+        let result = self.cursor.filter(idx_num, idx_str, values);
+        self.moved(result)
+    }
+
+    fn next(&mut self) -> Result<()> {
+        if self.filling {
+            self.filling = false;
+            self.ended = true;
+            return Ok(());
+        }
+        let result = self.cursor.next();
+        self.moved(result)
+    }
+
+    fn eof(&self) -> bool {
+        self.ended || (!self.filling && self.cursor.eof())
+    }
+
+    fn column(&self, ctx: *mut sqlite3_context, i: c_int) -> Result<()> {
+        if self.filling {
+            api::result_null(ctx);
+            return Ok(());
+        }
+        self.cursor.column(ctx, i)
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        // the row of NULLs has no rowid of its own, so it gets its place in
+        // the scan
+        if self.filling {
+            return Ok(self.rows + 1);
+        }
+        self.cursor.rowid()
+    }
+}
+
+/// Defines the `scan_status` table of recorded failures.
+pub fn define_scan_status(db: *mut sqlite3) -> Result<()> {
+    define_table_function::<ScanStatusTable>(db, "scan_status", None)
+}
+
+#[repr(C)]
+pub struct ScanStatusTable {
+    /// must be first
+    base: sqlite3_vtab,
+}
+
+impl<'vtab> VTab<'vtab> for ScanStatusTable {
+    type Aux = ();
+    type Cursor = ScanStatusCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, ScanStatusTable)> {
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        Ok((
+            "CREATE TABLE x(\"table\" TEXT, policy TEXT, rows INTEGER, error TEXT, \
+             failed_at INTEGER)"
+                .to_owned(),
+            ScanStatusTable { base },
+        ))
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        info.set_estimated_cost(MAX_RECORDED_FAILURES as f64);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<ScanStatusCursor> {
+        let base: sqlite3_vtab_cursor = unsafe { mem::zeroed() };
+        Ok(ScanStatusCursor {
+            base,
+            rows: Vec::new(),
+            index: 0,
+        })
+    }
+}
+
+#[repr(C)]
+pub struct ScanStatusCursor {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    /// The failures as of the scan's start.
+    rows: Vec<ScanFailure>,
+    index: usize,
+}
+
+impl VTabCursor for ScanStatusCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.rows = snapshot();
+        self.index = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.index += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.index >= self.rows.len()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, i: c_int) -> Result<()> {
+        let failure = self
+            .rows
+            .get(self.index)
+            .ok_or_else(|| Error::new_message("scan_status has no current row"))?;
+        match i {
+            0 => api::result_text(context, &failure.table)?,
+            1 => api::result_text(context, failure.policy.as_str())?,
+            2 => api::result_int64(context, failure.rows),
+            3 => api::result_text(context, &failure.error)?,
+            4 => match failure.failed_at {
+                Some(failed_at) => api::result_int64(context, failed_at),
+                None => api::result_null(context),
+            },
+            _ => api::result_null(context),
+        }
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.index as i64)
+    }
+}
@@ -0,0 +1,284 @@
+//! Documentation for an extension's functions, readable from SQL.
+//!
+//! [`document`] stores a [`FunctionDoc`], a description of a function and
+//! its arguments, in the extension's registry, usually right after the
+//! function is defined:
+//!
+//! ```ignore
+//! define_scalar_function(db, "repeat", 2, repeat, FunctionFlags::UTF8)?;
+//! docs::document(
+//!     FunctionDoc::new("repeat", "Repeats text a number of times.")
+//!         .arg("text", "The text to repeat.")
+//!         .arg("count", "How many times, 0 if negative."),
+//! );
+//! docs::define_info(db, "xyz")?;
+//! ```
+//!
+//! [`define_info`] then adds a table and a function that read them back,
+//! named after the extension:
+//!
+//! ```sql
+//! select name, signature, description from xyz_info;
+//! select xyz_help('repeat');
+//! -- repeat(text, count)
+//! --
+//! -- Repeats text a number of times.
+//! --
+//! --   text   The text to repeat.
+//! --   count  How many times, 0 if negative.
+//! ```
+//!
+//! Like [`crate::histogram`]'s, the registry belongs to the extension
+//! library, so every connection it's loaded into sees the same functions.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api;
+use crate::errors::{Error, Result};
+use crate::ext::{sqlite3, sqlite3_context, sqlite3_value, sqlite3_vtab, sqlite3_vtab_cursor};
+use crate::scalar::{define_scalar_function, FunctionFlags};
+use crate::table::{
+    define_table_function, BestIndexError, IndexInfo, VTab, VTabArguments, VTabCursor,
+};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::mem;
+use std::os::raw::c_int;
+use std::sync::Mutex;
+
+/// An argument of a documented function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArgDoc {
+    pub name: String,
+    pub description: String,
+    /// Whether calls can leave the argument out.
+    pub optional: bool,
+}
+
+/// A function's docstring and the descriptions of its arguments, in order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionDoc {
+    pub name: String,
+    pub description: String,
+    pub args: Vec<ArgDoc>,
+}
+
+impl FunctionDoc {
+    pub fn new(name: &str, description: &str) -> Self {
+        FunctionDoc {
+            name: name.to_owned(),
+            description: description.to_owned(),
+            args: Vec::new(),
+        }
+    }
+
+    /// Adds a required argument.
+    pub fn arg(mut self, name: &str, description: &str) -> Self {
+        self.args.push(ArgDoc {
+            name: name.to_owned(),
+            description: description.to_owned(),
+            optional: false,
+        });
+        self
+    }
+
+    /// Adds an argument that calls can leave out. Optional arguments go
+    /// after the required ones.
+    pub fn optional_arg(mut self, name: &str, description: &str) -> Self {
+        self.args.push(ArgDoc {
+            name: name.to_owned(),
+            description: description.to_owned(),
+            optional: true,
+        });
+        self
+    }
+
+    /// How a call looks, like `hist(value [, name])`.
+    pub fn signature(&self) -> String {
+        let mut signature = format!("{}(", self.name);
+        let mut optional = 0;
+        for (i, arg) in self.args.iter().enumerate() {
+            let separator = if i == 0 { "" } else { ", " };
+            if arg.optional {
+                signature.push_str(if i == 0 { "[" } else { " [" });
+                optional += 1;
+            }
+            signature.push_str(separator);
+            signature.push_str(&arg.name);
+        }
+        signature.push_str(&"]".repeat(optional));
+        signature.push(')');
+        signature
+    }
+
+    /// The signature, description and arguments as plain text, which is
+    /// what `<ext>_help()` returns.
+    pub fn help(&self) -> String {
+        let mut help = format!("{}\n\n{}", self.signature(), self.description);
+        let width = self.args.iter().map(|arg| arg.name.len()).max();
+        if let Some(width) = width {
+            help.push('\n');
+            for arg in &self.args {
+                let _ = write!(help, "\n  {:width$}  {}", arg.name, arg.description);
+            }
+        }
+        help
+    }
+
+    fn args_json(&self) -> serde_json::Value {
+        self.args
+            .iter()
+            .map(|arg| {
+                json!({
+                    "name": arg.name,
+                    "description": arg.description,
+                    "optional": arg.optional,
+                })
+            })
+            .collect()
+    }
+}
+
+static DOCS: Mutex<BTreeMap<String, FunctionDoc>> = Mutex::new(BTreeMap::new());
+
+/// Stores `doc`, replacing the one for a function of the same name.
+pub fn document(doc: FunctionDoc) {
+    with_docs(|docs| {
+        docs.insert(doc.name.clone(), doc);
+    });
+}
+
+/// A copy of the documentation for the function `name`.
+pub fn get(name: &str) -> Option<FunctionDoc> {
+    with_docs(|docs| docs.get(name).cloned())
+}
+
+/// Copies of every function's documentation, by name.
+pub fn snapshot() -> Vec<FunctionDoc> {
+    with_docs(|docs| docs.values().cloned().collect())
+}
+
+fn with_docs<T>(f: impl FnOnce(&mut BTreeMap<String, FunctionDoc>) -> T) -> T {
+    let mut docs = DOCS.lock().unwrap_or_else(|err| err.into_inner());
+    f(&mut docs)
+}
+
+/// Defines the `<ext>_info` table of documented functions, and
+/// `<ext>_help([name])`, which returns [`FunctionDoc::help`] for the
+/// function `name`, or for every documented function without one.
+pub fn define_info(db: *mut sqlite3, ext: &str) -> Result<()> {
+    for num_args in [0, 1] {
+        define_scalar_function(
+            db,
+            &format!("{ext}_help"),
+            num_args,
+            |context, values| {
+                let help = match values.first() {
+                    Some(name) => {
+                        let name = api::value_text(name)?;
+                        get(name)
+                            .ok_or_else(|| {
+                                Error::new_message(format!("no documentation for {name}()"))
+                            })?
+                            .help()
+                    }
+                    None => snapshot()
+                        .iter()
+                        .map(FunctionDoc::help)
+                        .collect::<Vec<_>>()
+                        .join("\n\n"),
+                };
+                api::result_text(context, help)
+            },
+            FunctionFlags::UTF8,
+        )?;
+    }
+    define_table_function::<InfoTable>(db, &format!("{ext}_info"), None)
+}
+
+#[repr(C)]
+pub struct InfoTable {
+    /// must be first
+    base: sqlite3_vtab,
+}
+
+impl<'vtab> VTab<'vtab> for InfoTable {
+    type Aux = ();
+    type Cursor = InfoCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, InfoTable)> {
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        Ok((
+            "CREATE TABLE x(name TEXT, signature TEXT, description TEXT, args TEXT)".to_owned(),
+            InfoTable { base },
+        ))
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        info.set_estimated_cost(100.0);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<InfoCursor> {
+        let base: sqlite3_vtab_cursor = unsafe { mem::zeroed() };
+        Ok(InfoCursor {
+            base,
+            rows: Vec::new(),
+            index: 0,
+        })
+    }
+}
+
+#[repr(C)]
+pub struct InfoCursor {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    /// The documentation as of the scan's start.
+    rows: Vec<FunctionDoc>,
+    index: usize,
+}
+
+impl VTabCursor for InfoCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.rows = snapshot();
+        self.index = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.index += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.index >= self.rows.len()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, i: c_int) -> Result<()> {
+        let doc = self
+            .rows
+            .get(self.index)
+            .ok_or_else(|| Error::new_message("info table has no current row"))?;
+        match i {
+            0 => api::result_text(context, &doc.name)?,
+            1 => api::result_text(context, doc.signature())?,
+            2 => api::result_text(context, &doc.description)?,
+            3 => api::result_json(context, doc.args_json())?,
+            _ => api::result_null(context),
+        }
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.index as i64)
+    }
+}
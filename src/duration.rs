@@ -0,0 +1,167 @@
+//! Parsing durations, for the functions and tables that take a timeout or
+//! a TTL.
+//!
+//! [`parse_duration`] reads a duration written out in text:
+//!
+//! - a number of seconds, like `90` or `1.5`
+//! - numbers with units, largest first, like `250ms`, `5m` or `2h30m`. The
+//!   units are `w`, `d`, `h`, `m`, `s` and `ms`, and the numbers can have
+//!   fractions, like `1.5h`.
+//! - [ISO 8601 durations](https://en.wikipedia.org/wiki/ISO_8601#Durations),
+//!   like `PT30S`, `PT1H30M` or `P1DT12H`. Years and months aren't a fixed
+//!   length, so they're not accepted.
+//!
+//! [`crate::api::value_duration`] reads an argument the same way, and also
+//! takes a number of seconds as an integer or real.
+
+use crate::errors::{Error, Result};
+use std::time::Duration;
+
+const MINUTE: f64 = 60.0;
+const HOUR: f64 = 60.0 * MINUTE;
+const DAY: f64 = 24.0 * HOUR;
+const WEEK: f64 = 7.0 * DAY;
+
+/// The units of the short form, largest first, with their length in
+/// seconds.
+const UNITS: &[(&str, f64)] = &[
+    ("w", WEEK),
+    ("d", DAY),
+    ("h", HOUR),
+    ("m", MINUTE),
+    ("s", 1.0),
+    ("ms", 0.001),
+];
+/// The units of an ISO 8601 duration, before its `T`.
+const ISO_DATE_UNITS: &[(&str, f64)] = &[("W", WEEK), ("D", DAY)];
+/// The units of an ISO 8601 duration, after its `T`.
+const ISO_TIME_UNITS: &[(&str, f64)] = &[("H", HOUR), ("M", MINUTE), ("S", 1.0)];
+
+/// Parses a duration in any of the forms in the [module docs](self).
+pub fn parse_duration(text: &str) -> Result<Duration> {
+    let trimmed = text.trim();
+    let seconds = match trimmed.strip_prefix(['P', 'p']) {
+        Some(iso) => iso_8601(&iso.to_ascii_uppercase()),
+        None => match trimmed.parse::<f64>() {
+            Ok(seconds) if trimmed.starts_with(|c: char| c.is_ascii_digit() || c == '.') => {
+                Some(seconds)
+            }
+            Ok(_) => None,
+            Err(_) => components(&trimmed.to_ascii_lowercase(), UNITS, true),
+        },
+    };
+    seconds
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| {
+            Error::new_message(format!(
+                "invalid duration '{}', expected seconds or a duration like '5m', '2h30m' or 'PT1H30M'",
+                text
+            ))
+        })
+}
+
+/// The duration in seconds, if it's valid.
+pub(crate) fn seconds(seconds: f64) -> Result<Duration> {
+    Duration::try_from_secs_f64(seconds).map_err(|_| {
+        Error::new_message(format!(
+            "invalid duration {}, expected a non-negative number of seconds",
+            seconds
+        ))
+    })
+}
+
+/// The seconds of an ISO 8601 duration, without its leading `P`.
+fn iso_8601(text: &str) -> Option<f64> {
+    let (date, time) = match text.split_once('T') {
+        Some((_, "")) => return None,
+        Some((date, time)) => (date, Some(time)),
+        None => (text, None),
+    };
+    if date.is_empty() && time.is_none() {
+        return None;
+    }
+    let date = if date.is_empty() {
+        0.0
+    } else {
+        components(date, ISO_DATE_UNITS, false)?
+    };
+    let time = match time {
+        Some(time) => components(time, ISO_TIME_UNITS, false)?,
+        None => 0.0,
+    };
+    Some(date + time)
+}
+
+/// The total seconds of numbers followed by units, each unit at most once
+/// and smaller than the one before it.
+fn components(text: &str, units: &[(&str, f64)], spaces: bool) -> Option<f64> {
+    let mut rest = text;
+    let mut next_unit = 0;
+    let mut total = 0.0;
+    while !rest.is_empty() {
+        let number_end = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        let number = &rest[..number_end];
+        if !number.starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+        let number: f64 = number.parse().ok()?;
+        rest = &rest[number_end..];
+        let unit_end = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        let position = units[next_unit..]
+            .iter()
+            .position(|(unit, _)| *unit == &rest[..unit_end])?;
+        total += number * units[next_unit + position].1;
+        next_unit += position + 1;
+        rest = &rest[unit_end..];
+        if spaces {
+            rest = rest.trim_start();
+        }
+    }
+    if next_unit == 0 {
+        return None;
+    }
+    Some(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::duration::*;
+
+    fn secs(seconds: f64) -> Duration {
+        Duration::from_secs_f64(seconds)
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90").unwrap(), secs(90.0));
+        assert_eq!(parse_duration("1.5").unwrap(), secs(1.5));
+        assert_eq!(parse_duration("250ms").unwrap(), secs(0.25));
+        assert_eq!(parse_duration("5m").unwrap(), secs(300.0));
+        assert_eq!(parse_duration("2h30m").unwrap(), secs(9000.0));
+        assert_eq!(parse_duration(" 1d 1.5h ").unwrap(), secs(91800.0));
+        assert_eq!(parse_duration("1W").unwrap(), secs(604800.0));
+        assert_eq!(parse_duration("PT30S").unwrap(), secs(30.0));
+        assert_eq!(parse_duration("PT1H30M").unwrap(), secs(5400.0));
+        assert_eq!(parse_duration("P1DT12H").unwrap(), secs(129600.0));
+        assert_eq!(parse_duration("p2w").unwrap(), secs(1209600.0));
+        assert_eq!(parse_duration("PT0.5S").unwrap(), secs(0.5));
+    }
+
+    #[test]
+    fn test_parse_duration_invalid() {
+        for text in [
+            "", "-5", "-5m", "m", "5x", "30m2h", "5m5m", "P", "PT", "P1M", "P1Y", "PT1D", "P1H",
+            "inf", "NaN",
+        ] {
+            assert!(parse_duration(text).is_err(), "{:?}", text);
+        }
+        assert_eq!(
+            parse_duration("soon").unwrap_err().reported_message(),
+            "invalid duration 'soon', expected seconds or a duration like '5m', '2h30m' or 'PT1H30M'"
+        );
+    }
+}
@@ -1,5 +1,35 @@
 //! Utilities for working with SQLite's "sqlite3_extension_init"-style
 //! entrypoints.
+//!
+//! Entrypoints are `extern "C"` and exported with `#[no_mangle]`, which is
+//! what SQLite's loader expects on every target: SQLite's default
+//! `SQLITE_APICALL` is the C calling convention, including on 32-bit
+//! Windows, and cdylibs export `#[no_mangle]` functions on both the MSVC and
+//! GNU Windows toolchains. What differs between targets is whether a
+//! loadable library can be built at all:
+//!
+//! | Target | Loadable extension | `static` feature |
+//! | --- | --- | --- |
+//! | Linux (gnu), macOS (x86_64 and aarch64), Windows (msvc and gnu) | yes | yes |
+//! | Linux (musl) | with `-C target-feature=-crt-static` | yes |
+//! | Android | yes, but the system SQLite can't load extensions, so the app has to ship its own | yes |
+//! | `wasm32-unknown-emscripten` | no | yes, see `examples/hello.rs` |
+//!
+//! musl targets link the C runtime statically by default, and rustc then
+//! drops the `cdylib` crate type with only a warning, so the crate refuses
+//! to build for them unless that's turned off or the `static` feature is
+//! used.
+//!
+//! The same entrypoint serves an extension built into a program. With
+//! `crate-type = ["cdylib", "staticlib"]` and a feature that turns on
+//! `sqlite-loadable/static`, the staticlib calls the `sqlite3_*` functions
+//! directly and ignores `pApi`, so the program calls
+//! `sqlite3_xyz_init(db, NULL, NULL)` or hands it to
+//! `sqlite3_auto_extension()`. Without default features, sqlite-loadable
+//! doesn't compile its own SQLite into the staticlib, and it links against
+//! the program's, like one compiled with the amalgamation. Link with
+//! `-Wl,--gc-sections` (`-Wl,-dead_strip` on macOS) if that SQLite is built
+//! without `SQLITE_ENABLE_RTREE`, so the unused rtree helpers are dropped.
 use crate::{
     errors::Result,
     ext::{faux_sqlite_extension_init2, sqlite3, sqlite3_api_routines},
@@ -9,6 +39,18 @@ use sqlite3ext_sys::SQLITE_OK;
 
 use std::os::raw::{c_char, c_uint};
 
+#[cfg(all(
+    target_env = "musl",
+    target_feature = "crt-static",
+    not(feature = "static")
+))]
+compile_error!(
+    "loadable SQLite extensions can't be built for musl targets with a statically linked C \
+     runtime, rustc would silently skip the cdylib. Build with \
+     RUSTFLAGS=\"-C target-feature=-crt-static\", or enable the `static` feature to link \
+     SQLite into a binary instead."
+);
+
 /// Low-level wrapper around a typical entrypoint to a SQLite extension.
 /// You shouldn't have to use this directly - the sqlite_entrypoint
 /// macro will do this for you.
@@ -1,9 +1,14 @@
 //! Custom Error/Result for sqlite-loadable-rs APIs.
+use crate::constants::{SQLITE_ABORT, SQLITE_CONSTRAINT};
+use crate::ext::{
+    sqlite3, sqlite3ext_errcode, sqlite3ext_errmsg, sqlite3ext_error_offset, sqlite3ext_errstr,
+};
 use std::{
-    ffi::NulError,
+    ffi::{CStr, NulError},
     fmt,
-    os::raw::{c_int, c_uint},
+    os::raw::{c_char, c_int, c_uint},
     result,
+    sync::RwLock,
 };
 
 /// A type alias for `Result<T, xxx::Error>`.
@@ -11,15 +16,79 @@ pub type Result<T> = result::Result<T, Error>;
 
 /// Any error that occurs while creating or using a SQLite extension.
 #[derive(Debug, PartialEq, Eq)]
-pub struct Error(Box<ErrorKind>);
+pub struct Error(Box<ErrorKind>, Option<Box<SqliteMessage>>);
+
+/// What SQLite said about a failed call, from
+/// [`sqlite3_errmsg`](https://www.sqlite.org/c3ref/errcode.html) and
+/// [`sqlite3_error_offset`](https://www.sqlite.org/c3ref/errcode.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SqliteMessage {
+    /// The result code the call failed with.
+    pub code: c_int,
+    pub message: String,
+    /// The byte offset into the SQL text where the error was found, if any.
+    pub offset: Option<usize>,
+}
+
+impl SqliteMessage {
+    /// What SQLite said about a call on `db` that failed with `rc`. Call
+    /// right after the failing call, before anything else on the connection
+    /// can overwrite the message.
+    pub(crate) fn from_db(rc: c_int, db: *mut sqlite3) -> SqliteMessage {
+        let c_string = |s: *const c_char| {
+            if s.is_null() {
+                String::new()
+            } else {
+                unsafe { CStr::from_ptr(s) }.to_string_lossy().into_owned()
+            }
+        };
+        // some failures, like API misuse, return early without recording an
+        // error on the connection, so errmsg would be stale or "not an error"
+        if unsafe { sqlite3ext_errcode(db) } != 0 {
+            SqliteMessage {
+                code: rc,
+                message: c_string(unsafe { sqlite3ext_errmsg(db) }),
+                offset: usize::try_from(unsafe { sqlite3ext_error_offset(db) }).ok(),
+            }
+        } else {
+            SqliteMessage {
+                code: rc,
+                message: c_string(unsafe { sqlite3ext_errstr(rc) }),
+                offset: None,
+            }
+        }
+    }
+}
 
 /// Generic Error
 impl Error {
     pub fn new(kind: ErrorKind) -> Error {
-        Error(Box::new(kind))
+        Error(Box::new(kind), None)
     }
     pub fn new_message<S: AsRef<str>>(message: S) -> Error {
-        Error(Box::new(ErrorKind::Message(message.as_ref().to_owned())))
+        Error::new(ErrorKind::Message(message.as_ref().to_owned()))
+    }
+
+    /// An error of the given kind for a call that failed with `rc`, with
+    /// the connection's error message attached. Call right after the failing
+    /// call, before anything else on the connection can overwrite the message.
+    pub(crate) fn with_db_message(kind: ErrorKind, rc: c_int, db: *mut sqlite3) -> Error {
+        Error(
+            Box::new(kind),
+            Some(Box::new(SqliteMessage::from_db(rc, db))),
+        )
+    }
+
+    /// Attaches SQLite's own message about the failed call behind the error.
+    pub(crate) fn with_sqlite_message(mut self, message: SqliteMessage) -> Error {
+        self.1 = Some(Box::new(message));
+        self
+    }
+
+    /// SQLite's own message about the error, when it came from a failed call
+    /// into SQLite.
+    pub fn sqlite_message(&self) -> Option<&SqliteMessage> {
+        self.1.as_deref()
     }
 
     /// Return the specific type of this error.
@@ -32,23 +101,96 @@ impl Error {
         *self.0
     }
 
+    /// An error for a row that violates a constraint, reported to SQLite as
+    /// SQLITE_CONSTRAINT so `ON CONFLICT` clauses apply to it.
+    pub fn constraint<S: AsRef<str>>(message: S) -> Error {
+        Error::new(ErrorKind::Constraint(message.as_ref().to_owned()))
+    }
+
+    /// An error for an operation that gave up before finishing, like a scan
+    /// past its timeout, reported to SQLite as SQLITE_ABORT.
+    pub fn abort<S: AsRef<str>>(message: S) -> Error {
+        Error::new(ErrorKind::Abort(message.as_ref().to_owned()))
+    }
+
     pub fn code(self) -> c_int {
-        1
+        match *self.0 {
+            ErrorKind::Constraint(_) => SQLITE_CONSTRAINT,
+            ErrorKind::Abort(_) => SQLITE_ABORT,
+            _ => 1,
+        }
     }
     pub fn code_extended(self) -> c_uint {
-        1
+        self.code() as c_uint
     }
     pub fn result_error_message(self) -> String {
-        match *self.0 {
+        self.message()
+    }
+
+    /// The message SQLite reports for this error: its
+    /// [`Error::result_error_message`], passed through the mapper installed
+    /// with [`set_error_mapper`]. The crate's glue between Rust functions and
+    /// SQLite reports errors with this.
+    pub fn reported_message(&self) -> String {
+        let message = self.message();
+        match ERROR_MAPPER.read() {
+            Ok(mapper) => match mapper.as_ref() {
+                Some(mapper) => mapper(self.kind(), message),
+                None => message,
+            },
+            Err(_) => message,
+        }
+    }
+
+    fn message(&self) -> String {
+        let message = match &*self.0 {
             ErrorKind::DefineScalarFunction(_) => "Error defining scalar function".to_owned(),
             ErrorKind::CStringError(e) => format!("String Nul error: {}", e),
             ErrorKind::CStringUtf8Error(_) => "utf8 err".to_owned(),
-            ErrorKind::Message(msg) => msg,
+            ErrorKind::Message(msg) => msg.clone(),
             ErrorKind::TableFunction(_) => "table func error".to_owned(),
+            ErrorKind::Constraint(msg) => msg.clone(),
+            ErrorKind::Abort(msg) => msg.clone(),
+        };
+        match &self.1 {
+            Some(details) => format!("{}: {}", message, details.message),
+            None => message,
         }
     }
 }
 
+type ErrorMapper = Box<dyn Fn(&ErrorKind, String) -> String + Send + Sync>;
+
+static ERROR_MAPPER: RwLock<Option<ErrorMapper>> = RwLock::new(None);
+
+/// Rewrites every error message the extension reports to SQLite, from
+/// scalar functions and virtual tables, to translate it or brand it:
+///
+/// ```ignore
+/// set_error_mapper(|_kind, message| format!("[acme] {}", message));
+/// ```
+///
+/// `f` gets the error's kind and message and returns the message SQLite
+/// reports. It replaces any mapper set before, and applies to every
+/// connection the extension is loaded into, since each extension library
+/// has its own. Errors the extension handles itself, and the messages of
+/// [`Error::result_error_message`], aren't mapped.
+pub fn set_error_mapper<F>(f: F)
+where
+    F: Fn(&ErrorKind, String) -> String + Send + Sync + 'static,
+{
+    if let Ok(mut mapper) = ERROR_MAPPER.write() {
+        *mapper = Some(Box::new(f));
+    }
+}
+
+/// Removes the mapper [`set_error_mapper`] installed.
+pub fn clear_error_mapper() {
+    if let Ok(mut mapper) = ERROR_MAPPER.write() {
+        *mapper = None;
+    }
+}
+
 /// The specific type of an error.
 #[derive(Debug, PartialEq, Eq)]
 pub enum ErrorKind {
@@ -57,6 +199,10 @@ pub enum ErrorKind {
     CStringUtf8Error(std::str::Utf8Error),
     TableFunction(c_int),
     Message(String),
+    /// A constraint violation, see [`Error::constraint`].
+    Constraint(String),
+    /// An operation that gave up, see [`Error::abort`].
+    Abort(String),
 }
 
 impl From<NulError> for Error {
@@ -87,8 +233,14 @@ impl ErrorKind {}
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self.0 {
-            ErrorKind::DefineScalarFunction(ref err) => err.fmt(f),
-            _ => unreachable!(),
+            ErrorKind::DefineScalarFunction(ref err) => err.fmt(f)?,
+            _ => return f.write_str(&self.message()),
+        }
+        match self.1 {
+            Some(ref details) => write!(f, ": {}", details.message),
+            None => Ok(()),
         }
     }
 }
+
+impl std::error::Error for Error {}
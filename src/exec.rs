@@ -1,7 +1,8 @@
-use sqlite3ext_sys::{sqlite3, sqlite3_stmt};
+use bitflags::bitflags;
+use sqlite3ext_sys::{sqlite3, sqlite3_stmt, SQLITE_PREPARE_NO_VTAB, SQLITE_PREPARE_PERSISTENT};
 use std::{
     error::Error,
-    ffi::{c_char, c_void, CString},
+    ffi::{c_char, c_void, CStr, CString},
 };
 
 use crate::{
@@ -9,7 +10,7 @@ use crate::{
     ext::{
         sqlite3ext_bind_int, sqlite3ext_bind_text, sqlite3ext_column_bytes,
         sqlite3ext_column_int64, sqlite3ext_column_text, sqlite3ext_finalize,
-        sqlite3ext_prepare_v2, sqlite3ext_step,
+        sqlite3ext_normalized_sql, sqlite3ext_prepare_v3, sqlite3ext_step,
     },
 };
 
@@ -21,20 +22,69 @@ unsafe extern "C" fn cstring_destructor(raw: *mut c_void) {
     drop(CString::from_raw(raw.cast::<c_char>()));
 }
 
+bitflags! {
+    /// Flags for [`Statement::prepare_with_flags`], passed to
+    /// [sqlite3_prepare_v3](https://www.sqlite.org/c3ref/prepare.html).
+    pub struct PrepareFlags: u32 {
+        /// "... a hint to the query planner that the prepared statement will be retained for
+        /// a long time and probably reused many times."
+        /// <https://www.sqlite.org/c3ref/c_prepare_normalize.html#sqlitepreparepersistent>
+        const PERSISTENT = SQLITE_PREPARE_PERSISTENT;
+        /// Fails to prepare statements that use virtual tables, for internal statements
+        /// that shouldn't be redirected to one by a schema an attacker controls.
+        const NO_VTAB = SQLITE_PREPARE_NO_VTAB;
+    }
+}
+
 impl Statement {
     pub fn prepare(db: *mut sqlite3, sql: &str) -> Result<Self, Box<dyn Error>> {
+        Statement::prepare_with_flags(db, sql, PrepareFlags::empty())
+    }
+    pub fn prepare_with_flags(
+        db: *mut sqlite3,
+        sql: &str,
+        flags: PrepareFlags,
+    ) -> Result<Self, Box<dyn Error>> {
         let s = unsafe { CString::from_vec_unchecked(sql.into()) };
 
         let n: i32 = sql.len().try_into()?;
         let mut stmt: *mut sqlite3_stmt = std::ptr::null_mut();
-        let result =
-            unsafe { sqlite3ext_prepare_v2(db, s.as_ptr(), n, &mut stmt, std::ptr::null_mut()) };
+        let result = unsafe {
+            sqlite3ext_prepare_v3(
+                db,
+                s.as_ptr(),
+                n,
+                flags.bits(),
+                &mut stmt,
+                std::ptr::null_mut(),
+            )
+        };
         if result != SQLITE_OKAY {
-            Err("".into())
+            Err(crate::errors::Error::with_db_message(
+                crate::errors::ErrorKind::Message(format!("could not prepare {:?}", sql)),
+                result,
+                db,
+            )
+            .into())
         } else {
             Ok(Statement { stmt })
         }
     }
+    /// The statement's SQL with literals replaced by `?` and whitespace and
+    /// keyword case normalized, for grouping statements that only differ by
+    /// their values. None unless SQLite was built with `SQLITE_ENABLE_NORMALIZE`.
+    pub fn normalized_sql(&self) -> Option<String> {
+        let sql = unsafe { sqlite3ext_normalized_sql(self.stmt) };
+        if sql.is_null() {
+            None
+        } else {
+            Some(
+                unsafe { CStr::from_ptr(sql) }
+                    .to_string_lossy()
+                    .into_owned(),
+            )
+        }
+    }
     pub fn bind_i32(&mut self, param_idx: i32, value: i32) -> Result<(), Box<dyn Error>> {
         let result = unsafe { sqlite3ext_bind_int(self.stmt, param_idx, value) };
         if result == SQLITE_OKAY {
@@ -12,24 +12,26 @@
 /// on the sqlite_loadable library.
 use std::{
     mem,
-    os::raw::{c_char, c_int, c_uchar, c_void},
+    os::raw::{c_char, c_int, c_uchar, c_uint, c_void},
 };
 
 #[cfg(feature = "static")]
 pub use libsqlite3_sys::{
-    sqlite3, sqlite3_api_routines, sqlite3_context,
+    sqlite3, sqlite3_api_routines, sqlite3_blob, sqlite3_context, sqlite3_file,
     sqlite3_index_constraint as sqlite3_index_info_sqlite3_index_constraint,
     sqlite3_index_constraint_usage as sqlite3_index_info_sqlite3_index_constraint_usage,
     sqlite3_index_info, sqlite3_index_orderby as sqlite3_index_info_sqlite3_index_orderby,
-    sqlite3_module, sqlite3_stmt, sqlite3_value, sqlite3_vtab, sqlite3_vtab_cursor,
+    sqlite3_module, sqlite3_rtree_geometry, sqlite3_rtree_query_info, sqlite3_stmt, sqlite3_value,
+    sqlite3_vfs, sqlite3_vtab, sqlite3_vtab_cursor,
 };
 
 #[cfg(not(feature = "static"))]
 pub use sqlite3ext_sys::{
-    sqlite3, sqlite3_api_routines, sqlite3_context, sqlite3_index_info,
+    sqlite3, sqlite3_api_routines, sqlite3_blob, sqlite3_context, sqlite3_file, sqlite3_index_info,
     sqlite3_index_info_sqlite3_index_constraint, sqlite3_index_info_sqlite3_index_constraint_usage,
-    sqlite3_index_info_sqlite3_index_orderby, sqlite3_module, sqlite3_stmt, sqlite3_value,
-    sqlite3_vtab, sqlite3_vtab_cursor,
+    sqlite3_index_info_sqlite3_index_orderby, sqlite3_module, sqlite3_rtree_geometry,
+    sqlite3_rtree_query_info, sqlite3_stmt, sqlite3_value, sqlite3_vfs, sqlite3_vtab,
+    sqlite3_vtab_cursor,
 };
 
 /// If creating a dynmically loadable extension, this MUST be redefined to point
@@ -254,6 +256,24 @@ pub unsafe fn sqlite3ext_value_double(arg1: *mut sqlite3_value) -> f64 {
     ((*SQLITE3_API).value_double.expect(EXPECT_MESSAGE))(arg1)
 }
 
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_value_dup(arg1: *const sqlite3_value) -> *mut sqlite3_value {
+    libsqlite3_sys::sqlite3_value_dup(arg1)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_value_dup(arg1: *const sqlite3_value) -> *mut sqlite3_value {
+    ((*SQLITE3_API).value_dup.expect(EXPECT_MESSAGE))(arg1)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_value_free(arg1: *mut sqlite3_value) {
+    libsqlite3_sys::sqlite3_value_free(arg1)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_value_free(arg1: *mut sqlite3_value) {
+    ((*SQLITE3_API).value_free.expect(EXPECT_MESSAGE))(arg1)
+}
+
 #[cfg(feature = "static")]
 pub unsafe fn sqlite3ext_value_pointer(arg1: *mut sqlite3_value, p: *mut c_char) -> *mut c_void {
     libsqlite3_sys::sqlite3_value_pointer(arg1, p)
@@ -445,6 +465,41 @@ pub unsafe fn sqlite3ext_create_function_v2(
         db, s, argc, text_rep, p_app, x_func, x_step, x_final, destroy,
     )
 }
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_create_window_function(
+    db: *mut sqlite3,
+    s: *const c_char,
+    argc: i32,
+    text_rep: i32,
+    p_app: *mut c_void,
+    x_step: Option<unsafe extern "C" fn(*mut sqlite3_context, i32, *mut *mut sqlite3_value)>,
+    x_final: Option<unsafe extern "C" fn(*mut sqlite3_context)>,
+    x_value: Option<unsafe extern "C" fn(*mut sqlite3_context)>,
+    x_inverse: Option<unsafe extern "C" fn(*mut sqlite3_context, i32, *mut *mut sqlite3_value)>,
+    destroy: Option<unsafe extern "C" fn(*mut c_void)>,
+) -> c_int {
+    libsqlite3_sys::sqlite3_create_window_function(
+        db, s, argc, text_rep, p_app, x_step, x_final, x_value, x_inverse, destroy,
+    )
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_create_window_function(
+    db: *mut sqlite3,
+    s: *const c_char,
+    argc: i32,
+    text_rep: i32,
+    p_app: *mut c_void,
+    x_step: Option<unsafe extern "C" fn(*mut sqlite3_context, i32, *mut *mut sqlite3_value)>,
+    x_final: Option<unsafe extern "C" fn(*mut sqlite3_context)>,
+    x_value: Option<unsafe extern "C" fn(*mut sqlite3_context)>,
+    x_inverse: Option<unsafe extern "C" fn(*mut sqlite3_context, i32, *mut *mut sqlite3_value)>,
+    destroy: Option<unsafe extern "C" fn(*mut c_void)>,
+) -> c_int {
+    ((*SQLITE3_API).create_window_function.expect(EXPECT_MESSAGE))(
+        db, s, argc, text_rep, p_app, x_step, x_final, x_value, x_inverse, destroy,
+    )
+}
 #[cfg(feature = "static")]
 pub unsafe fn sqlite3ext_collation_v2(
     db: *mut sqlite3,
@@ -486,6 +541,28 @@ pub unsafe fn sqlite3ext_collation_v2(
     )
 }
 
+/// The callback of [`sqlite3ext_collation_needed`], given the connection,
+/// the text encoding and the name of the missing collation.
+pub type CollationNeededCallback =
+    unsafe extern "C" fn(*mut c_void, *mut sqlite3, c_int, *const c_char);
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_collation_needed(
+    db: *mut sqlite3,
+    p_arg: *mut c_void,
+    callback: Option<CollationNeededCallback>,
+) -> c_int {
+    libsqlite3_sys::sqlite3_collation_needed(db, p_arg, callback)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_collation_needed(
+    db: *mut sqlite3,
+    p_arg: *mut c_void,
+    callback: Option<CollationNeededCallback>,
+) -> c_int {
+    ((*SQLITE3_API).collation_needed.expect(EXPECT_MESSAGE))(db, p_arg, callback)
+}
+
 #[cfg(feature = "static")]
 pub unsafe fn sqlite3ext_create_module_v2(
     db: *mut sqlite3,
@@ -563,6 +640,38 @@ pub unsafe fn sqlite3ext_vtab_in_next(
     ((*SQLITE3_API).vtab_in_next.expect(EXPECT_MESSAGE))(value_list, value_out)
 }
 
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_vtab_rhs_value(
+    index_info: *mut sqlite3_index_info,
+    constraint_idx: i32,
+    value_out: *mut *mut sqlite3_value,
+) -> i32 {
+    libsqlite3_sys::sqlite3_vtab_rhs_value(index_info, constraint_idx, value_out)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_vtab_rhs_value(
+    index_info: *mut sqlite3_index_info,
+    constraint_idx: i32,
+    value_out: *mut *mut sqlite3_value,
+) -> i32 {
+    ((*SQLITE3_API).vtab_rhs_value.expect(EXPECT_MESSAGE))(index_info, constraint_idx, value_out)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_vtab_collation(
+    index_info: *mut sqlite3_index_info,
+    constraint_idx: i32,
+) -> *const c_char {
+    libsqlite3_sys::sqlite3_vtab_collation(index_info, constraint_idx)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_vtab_collation(
+    index_info: *mut sqlite3_index_info,
+    constraint_idx: i32,
+) -> *const c_char {
+    ((*SQLITE3_API).vtab_collation.expect(EXPECT_MESSAGE))(index_info, constraint_idx)
+}
+
 #[cfg(feature = "static")]
 pub unsafe fn sqlite3ext_declare_vtab(db: *mut sqlite3, s: *const c_char) -> i32 {
     libsqlite3_sys::sqlite3_declare_vtab(db, s)
@@ -573,6 +682,43 @@ pub unsafe fn sqlite3ext_declare_vtab(db: *mut sqlite3, s: *const c_char) -> i32
     ((*SQLITE3_API).declare_vtab.expect(EXPECT_MESSAGE))(db, s)
 }
 
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_result_value(context: *mut sqlite3_context, value: *mut sqlite3_value) {
+    libsqlite3_sys::sqlite3_result_value(context, value);
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_result_value(context: *mut sqlite3_context, value: *mut sqlite3_value) {
+    ((*SQLITE3_API).result_value.expect(EXPECT_MESSAGE))(context, value);
+}
+
+/// Logs `message` as is, since it's passed to a "%s".
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_log(code: c_int, message: *const c_char) {
+    libsqlite3_sys::sqlite3_log(code, c"%s".as_ptr(), message);
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_log(code: c_int, message: *const c_char) {
+    ((*SQLITE3_API).log.expect(EXPECT_MESSAGE))(code, c"%s".as_ptr(), message);
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_errmsg(db: *mut sqlite3) -> *const c_char {
+    libsqlite3_sys::sqlite3_errmsg(db)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_errmsg(db: *mut sqlite3) -> *const c_char {
+    ((*SQLITE3_API).errmsg.expect(EXPECT_MESSAGE))(db)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_error_offset(db: *mut sqlite3) -> c_int {
+    libsqlite3_sys::sqlite3_error_offset(db)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_error_offset(db: *mut sqlite3) -> c_int {
+    ((*SQLITE3_API).error_offset.expect(EXPECT_MESSAGE))(db)
+}
+
 #[cfg(feature = "static")]
 pub unsafe fn sqlite3ext_overload_function(db: *mut sqlite3, s: *const c_char, n: i32) -> i32 {
     libsqlite3_sys::sqlite3_overload_function(db, s, n)
@@ -600,6 +746,14 @@ pub unsafe fn sqlite3ext_user_data(context: *mut sqlite3_context) -> *mut c_void
     ((*SQLITE3_API).user_data.expect(EXPECT_MESSAGE))(context)
 }
 #[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_aggregate_context(context: *mut sqlite3_context, n: c_int) -> *mut c_void {
+    libsqlite3_sys::sqlite3_aggregate_context(context, n)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_aggregate_context(context: *mut sqlite3_context, n: c_int) -> *mut c_void {
+    ((*SQLITE3_API).aggregate_context.expect(EXPECT_MESSAGE))(context, n)
+}
+#[cfg(feature = "static")]
 pub unsafe fn sqlite3ext_mprintf(s: *const c_char) -> *mut c_char {
     libsqlite3_sys::sqlite3_mprintf(s)
 }
@@ -616,3 +770,552 @@ pub unsafe fn sqlite3ext_auto_extension(f: unsafe extern "C" fn()) -> i32 {
 pub unsafe fn sqlite3ext_auto_extension(f: unsafe extern "C" fn()) -> i32 {
     ((*SQLITE3_API).auto_extension.expect(EXPECT_MESSAGE))(Some(f))
 }
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_libversion_number() -> c_int {
+    libsqlite3_sys::sqlite3_libversion_number()
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_libversion_number() -> c_int {
+    ((*SQLITE3_API).libversion_number.expect(EXPECT_MESSAGE))()
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_memory_used() -> i64 {
+    libsqlite3_sys::sqlite3_memory_used()
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_memory_used() -> i64 {
+    ((*SQLITE3_API).memory_used.expect(EXPECT_MESSAGE))()
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_soft_heap_limit64(n: i64) -> i64 {
+    libsqlite3_sys::sqlite3_soft_heap_limit64(n)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_soft_heap_limit64(n: i64) -> i64 {
+    ((*SQLITE3_API).soft_heap_limit64.expect(EXPECT_MESSAGE))(n)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_open_v2(
+    filename: *const c_char,
+    db: *mut *mut sqlite3,
+    flags: c_int,
+    vfs: *const c_char,
+) -> c_int {
+    libsqlite3_sys::sqlite3_open_v2(filename, db, flags, vfs)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_open_v2(
+    filename: *const c_char,
+    db: *mut *mut sqlite3,
+    flags: c_int,
+    vfs: *const c_char,
+) -> c_int {
+    ((*SQLITE3_API).open_v2.expect(EXPECT_MESSAGE))(filename, db, flags, vfs)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_close_v2(db: *mut sqlite3) -> c_int {
+    libsqlite3_sys::sqlite3_close_v2(db)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_close_v2(db: *mut sqlite3) -> c_int {
+    ((*SQLITE3_API).close_v2.expect(EXPECT_MESSAGE))(db)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_db_filename(db: *mut sqlite3, schema: *const c_char) -> *const c_char {
+    libsqlite3_sys::sqlite3_db_filename(db, schema)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_db_filename(db: *mut sqlite3, schema: *const c_char) -> *const c_char {
+    ((*SQLITE3_API).db_filename.expect(EXPECT_MESSAGE))(db, schema)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_db_readonly(db: *mut sqlite3, schema: *const c_char) -> c_int {
+    libsqlite3_sys::sqlite3_db_readonly(db, schema)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_db_readonly(db: *mut sqlite3, schema: *const c_char) -> c_int {
+    ((*SQLITE3_API).db_readonly.expect(EXPECT_MESSAGE))(db, schema)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_file_control(
+    db: *mut sqlite3,
+    schema: *const c_char,
+    op: c_int,
+    arg: *mut c_void,
+) -> c_int {
+    libsqlite3_sys::sqlite3_file_control(db, schema, op, arg)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_file_control(
+    db: *mut sqlite3,
+    schema: *const c_char,
+    op: c_int,
+    arg: *mut c_void,
+) -> c_int {
+    ((*SQLITE3_API).file_control.expect(EXPECT_MESSAGE))(db, schema, op, arg)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_db_name(db: *mut sqlite3, n: c_int) -> *const c_char {
+    libsqlite3_sys::sqlite3_db_name(db, n)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_db_name(db: *mut sqlite3, n: c_int) -> *const c_char {
+    ((*SQLITE3_API).db_name.expect(EXPECT_MESSAGE))(db, n)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_txn_state(db: *mut sqlite3, schema: *const c_char) -> c_int {
+    libsqlite3_sys::sqlite3_txn_state(db, schema)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_txn_state(db: *mut sqlite3, schema: *const c_char) -> c_int {
+    ((*SQLITE3_API).txn_state.expect(EXPECT_MESSAGE))(db, schema)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_vfs_find(name: *const c_char) -> *mut sqlite3_vfs {
+    libsqlite3_sys::sqlite3_vfs_find(name)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_vfs_find(name: *const c_char) -> *mut sqlite3_vfs {
+    ((*SQLITE3_API).vfs_find.expect(EXPECT_MESSAGE))(name)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_commit_hook(
+    db: *mut sqlite3,
+    callback: Option<unsafe extern "C" fn(*mut c_void) -> c_int>,
+    p_arg: *mut c_void,
+) -> *mut c_void {
+    libsqlite3_sys::sqlite3_commit_hook(db, callback, p_arg)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_commit_hook(
+    db: *mut sqlite3,
+    callback: Option<unsafe extern "C" fn(*mut c_void) -> c_int>,
+    p_arg: *mut c_void,
+) -> *mut c_void {
+    ((*SQLITE3_API).commit_hook.expect(EXPECT_MESSAGE))(db, callback, p_arg)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_rollback_hook(
+    db: *mut sqlite3,
+    callback: Option<unsafe extern "C" fn(*mut c_void)>,
+    p_arg: *mut c_void,
+) -> *mut c_void {
+    libsqlite3_sys::sqlite3_rollback_hook(db, callback, p_arg)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_rollback_hook(
+    db: *mut sqlite3,
+    callback: Option<unsafe extern "C" fn(*mut c_void)>,
+    p_arg: *mut c_void,
+) -> *mut c_void {
+    ((*SQLITE3_API).rollback_hook.expect(EXPECT_MESSAGE))(db, callback, p_arg)
+}
+
+/// The callback of [`sqlite3ext_update_hook`], given the operation, schema,
+/// table and rowid of each changed row.
+pub type UpdateHookCallback =
+    unsafe extern "C" fn(*mut c_void, c_int, *const c_char, *const c_char, i64);
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_update_hook(
+    db: *mut sqlite3,
+    callback: Option<UpdateHookCallback>,
+    p_arg: *mut c_void,
+) -> *mut c_void {
+    libsqlite3_sys::sqlite3_update_hook(db, callback, p_arg)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_update_hook(
+    db: *mut sqlite3,
+    callback: Option<UpdateHookCallback>,
+    p_arg: *mut c_void,
+) -> *mut c_void {
+    ((*SQLITE3_API).update_hook.expect(EXPECT_MESSAGE))(db, callback, p_arg)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_changes(db: *mut sqlite3) -> c_int {
+    libsqlite3_sys::sqlite3_changes(db)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_changes(db: *mut sqlite3) -> c_int {
+    ((*SQLITE3_API).changes.expect(EXPECT_MESSAGE))(db)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_trace_v2(
+    db: *mut sqlite3,
+    mask: c_uint,
+    callback: Option<unsafe extern "C" fn(c_uint, *mut c_void, *mut c_void, *mut c_void) -> c_int>,
+    context: *mut c_void,
+) -> c_int {
+    libsqlite3_sys::sqlite3_trace_v2(db, mask, callback, context)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_trace_v2(
+    db: *mut sqlite3,
+    mask: c_uint,
+    callback: Option<unsafe extern "C" fn(c_uint, *mut c_void, *mut c_void, *mut c_void) -> c_int>,
+    context: *mut c_void,
+) -> c_int {
+    ((*SQLITE3_API).trace_v2.expect(EXPECT_MESSAGE))(db, mask, callback, context)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_sql(stmt: *mut sqlite3_stmt) -> *const c_char {
+    libsqlite3_sys::sqlite3_sql(stmt)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_sql(stmt: *mut sqlite3_stmt) -> *const c_char {
+    ((*SQLITE3_API).sql.expect(EXPECT_MESSAGE))(stmt)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_errcode(db: *mut sqlite3) -> c_int {
+    libsqlite3_sys::sqlite3_errcode(db)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_errcode(db: *mut sqlite3) -> c_int {
+    ((*SQLITE3_API).errcode.expect(EXPECT_MESSAGE))(db)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_errstr(rc: c_int) -> *const c_char {
+    libsqlite3_sys::sqlite3_errstr(rc)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_errstr(rc: c_int) -> *const c_char {
+    ((*SQLITE3_API).errstr.expect(EXPECT_MESSAGE))(rc)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_blob_open(
+    db: *mut sqlite3,
+    schema: *const c_char,
+    table: *const c_char,
+    column: *const c_char,
+    rowid: i64,
+    flags: c_int,
+    blob: *mut *mut sqlite3_blob,
+) -> c_int {
+    libsqlite3_sys::sqlite3_blob_open(db, schema, table, column, rowid, flags, blob)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_blob_open(
+    db: *mut sqlite3,
+    schema: *const c_char,
+    table: *const c_char,
+    column: *const c_char,
+    rowid: i64,
+    flags: c_int,
+    blob: *mut *mut sqlite3_blob,
+) -> c_int {
+    ((*SQLITE3_API).blob_open.expect(EXPECT_MESSAGE))(db, schema, table, column, rowid, flags, blob)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_blob_bytes(blob: *mut sqlite3_blob) -> c_int {
+    libsqlite3_sys::sqlite3_blob_bytes(blob)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_blob_bytes(blob: *mut sqlite3_blob) -> c_int {
+    ((*SQLITE3_API).blob_bytes.expect(EXPECT_MESSAGE))(blob)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_blob_read(
+    blob: *mut sqlite3_blob,
+    buffer: *mut c_void,
+    n: c_int,
+    offset: c_int,
+) -> c_int {
+    libsqlite3_sys::sqlite3_blob_read(blob, buffer, n, offset)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_blob_read(
+    blob: *mut sqlite3_blob,
+    buffer: *mut c_void,
+    n: c_int,
+    offset: c_int,
+) -> c_int {
+    ((*SQLITE3_API).blob_read.expect(EXPECT_MESSAGE))(blob, buffer, n, offset)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_blob_close(blob: *mut sqlite3_blob) -> c_int {
+    libsqlite3_sys::sqlite3_blob_close(blob)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_blob_close(blob: *mut sqlite3_blob) -> c_int {
+    ((*SQLITE3_API).blob_close.expect(EXPECT_MESSAGE))(blob)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_exec(db: *mut sqlite3, sql: *const c_char) -> c_int {
+    libsqlite3_sys::sqlite3_exec(db, sql, None, std::ptr::null_mut(), std::ptr::null_mut())
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_exec(db: *mut sqlite3, sql: *const c_char) -> c_int {
+    ((*SQLITE3_API).exec.expect(EXPECT_MESSAGE))(
+        db,
+        sql,
+        None,
+        std::ptr::null_mut(),
+        std::ptr::null_mut(),
+    )
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_next_stmt(db: *mut sqlite3, stmt: *mut sqlite3_stmt) -> *mut sqlite3_stmt {
+    libsqlite3_sys::sqlite3_next_stmt(db, stmt)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_next_stmt(db: *mut sqlite3, stmt: *mut sqlite3_stmt) -> *mut sqlite3_stmt {
+    ((*SQLITE3_API).next_stmt.expect(EXPECT_MESSAGE))(db, stmt)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_stmt_busy(stmt: *mut sqlite3_stmt) -> c_int {
+    libsqlite3_sys::sqlite3_stmt_busy(stmt)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_stmt_busy(stmt: *mut sqlite3_stmt) -> c_int {
+    ((*SQLITE3_API).stmt_busy.expect(EXPECT_MESSAGE))(stmt)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_stmt_readonly(stmt: *mut sqlite3_stmt) -> c_int {
+    libsqlite3_sys::sqlite3_stmt_readonly(stmt)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_stmt_readonly(stmt: *mut sqlite3_stmt) -> c_int {
+    ((*SQLITE3_API).stmt_readonly.expect(EXPECT_MESSAGE))(stmt)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_bind_value(
+    stmt: *mut sqlite3_stmt,
+    c: c_int,
+    value: *const sqlite3_value,
+) -> c_int {
+    libsqlite3_sys::sqlite3_bind_value(stmt, c, value)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_bind_value(
+    stmt: *mut sqlite3_stmt,
+    c: c_int,
+    value: *const sqlite3_value,
+) -> c_int {
+    ((*SQLITE3_API).bind_value.expect(EXPECT_MESSAGE))(stmt, c, value)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_bind_double(stmt: *mut sqlite3_stmt, c: c_int, v: f64) -> c_int {
+    libsqlite3_sys::sqlite3_bind_double(stmt, c, v)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_bind_double(stmt: *mut sqlite3_stmt, c: c_int, v: f64) -> c_int {
+    ((*SQLITE3_API).bind_double.expect(EXPECT_MESSAGE))(stmt, c, v)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_bind_null(stmt: *mut sqlite3_stmt, c: c_int) -> c_int {
+    libsqlite3_sys::sqlite3_bind_null(stmt, c)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_bind_null(stmt: *mut sqlite3_stmt, c: c_int) -> c_int {
+    ((*SQLITE3_API).bind_null.expect(EXPECT_MESSAGE))(stmt, c)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_reset(stmt: *mut sqlite3_stmt) -> c_int {
+    libsqlite3_sys::sqlite3_reset(stmt)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_reset(stmt: *mut sqlite3_stmt) -> c_int {
+    ((*SQLITE3_API).reset.expect(EXPECT_MESSAGE))(stmt)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_randomness(n: c_int, buffer: *mut c_void) {
+    libsqlite3_sys::sqlite3_randomness(n, buffer)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_randomness(n: c_int, buffer: *mut c_void) {
+    ((*SQLITE3_API).randomness.expect(EXPECT_MESSAGE))(n, buffer)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_test_control(op: c_int) -> c_int {
+    libsqlite3_sys::sqlite3_test_control(op)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_test_control(op: c_int) -> c_int {
+    ((*SQLITE3_API).test_control.expect(EXPECT_MESSAGE))(op)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_test_control_int(op: c_int, arg: c_int) -> c_int {
+    libsqlite3_sys::sqlite3_test_control(op, arg)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_test_control_int(op: c_int, arg: c_int) -> c_int {
+    ((*SQLITE3_API).test_control.expect(EXPECT_MESSAGE))(op, arg)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_test_control_int_db(op: c_int, arg: c_int, db: *mut sqlite3) -> c_int {
+    libsqlite3_sys::sqlite3_test_control(op, arg, db)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_test_control_int_db(op: c_int, arg: c_int, db: *mut sqlite3) -> c_int {
+    ((*SQLITE3_API).test_control.expect(EXPECT_MESSAGE))(op, arg, db)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_complete(sql: *const c_char) -> c_int {
+    libsqlite3_sys::sqlite3_complete(sql)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_complete(sql: *const c_char) -> c_int {
+    ((*SQLITE3_API).complete.expect(EXPECT_MESSAGE))(sql)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_keyword_count() -> c_int {
+    libsqlite3_sys::sqlite3_keyword_count()
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_keyword_count() -> c_int {
+    ((*SQLITE3_API).keyword_count.expect(EXPECT_MESSAGE))()
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_keyword_name(i: c_int, name: *mut *const c_char, n: *mut c_int) -> c_int {
+    libsqlite3_sys::sqlite3_keyword_name(i, name, n)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_keyword_name(i: c_int, name: *mut *const c_char, n: *mut c_int) -> c_int {
+    ((*SQLITE3_API).keyword_name.expect(EXPECT_MESSAGE))(i, name, n)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_keyword_check(name: *const c_char, n: c_int) -> c_int {
+    libsqlite3_sys::sqlite3_keyword_check(name, n)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_keyword_check(name: *const c_char, n: c_int) -> c_int {
+    ((*SQLITE3_API).keyword_check.expect(EXPECT_MESSAGE))(name, n)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_prepare_v3(
+    db: *mut sqlite3,
+    sql: *const c_char,
+    n: c_int,
+    flags: c_uint,
+    stmt: *mut *mut sqlite3_stmt,
+    leftover: *mut *const c_char,
+) -> c_int {
+    libsqlite3_sys::sqlite3_prepare_v3(db, sql, n, flags, stmt, leftover)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_prepare_v3(
+    db: *mut sqlite3,
+    sql: *const c_char,
+    n: c_int,
+    flags: c_uint,
+    stmt: *mut *mut sqlite3_stmt,
+    leftover: *mut *const c_char,
+) -> c_int {
+    ((*SQLITE3_API).prepare_v3.expect(EXPECT_MESSAGE))(db, sql, n, flags, stmt, leftover)
+}
+
+// sqlite3_normalized_sql only exists in builds with SQLITE_ENABLE_NORMALIZE,
+// which the bundled static library isn't, so both return NULL without it
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_normalized_sql(_stmt: *mut sqlite3_stmt) -> *const c_char {
+    std::ptr::null()
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_normalized_sql(stmt: *mut sqlite3_stmt) -> *const c_char {
+    match (*SQLITE3_API).normalized_sql {
+        Some(normalized_sql) => normalized_sql(stmt),
+        None => std::ptr::null(),
+    }
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_strglob(pattern: *const c_char, s: *const c_char) -> c_int {
+    libsqlite3_sys::sqlite3_strglob(pattern, s)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_strglob(pattern: *const c_char, s: *const c_char) -> c_int {
+    ((*SQLITE3_API).strglob.expect(EXPECT_MESSAGE))(pattern, s)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_strlike(
+    pattern: *const c_char,
+    s: *const c_char,
+    escape: c_uint,
+) -> c_int {
+    libsqlite3_sys::sqlite3_strlike(pattern, s, escape)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_strlike(
+    pattern: *const c_char,
+    s: *const c_char,
+    escape: c_uint,
+) -> c_int {
+    ((*SQLITE3_API).strlike.expect(EXPECT_MESSAGE))(pattern, s, escape)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_stricmp(a: *const c_char, b: *const c_char) -> c_int {
+    libsqlite3_sys::sqlite3_stricmp(a, b)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_stricmp(a: *const c_char, b: *const c_char) -> c_int {
+    ((*SQLITE3_API).stricmp.expect(EXPECT_MESSAGE))(a, b)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_strnicmp(a: *const c_char, b: *const c_char, n: c_int) -> c_int {
+    libsqlite3_sys::sqlite3_strnicmp(a, b, n)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_strnicmp(a: *const c_char, b: *const c_char, n: c_int) -> c_int {
+    ((*SQLITE3_API).strnicmp.expect(EXPECT_MESSAGE))(a, b, n)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_vtab_on_conflict(db: *mut sqlite3) -> c_int {
+    libsqlite3_sys::sqlite3_vtab_on_conflict(db)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_vtab_on_conflict(db: *mut sqlite3) -> c_int {
+    ((*SQLITE3_API).vtab_on_conflict.expect(EXPECT_MESSAGE))(db)
+}
+
+#[cfg(feature = "static")]
+pub unsafe fn sqlite3ext_vtab_config(db: *mut sqlite3, op: c_int, arg: c_int) -> c_int {
+    libsqlite3_sys::sqlite3_vtab_config(db, op, arg)
+}
+#[cfg(not(feature = "static"))]
+pub unsafe fn sqlite3ext_vtab_config(db: *mut sqlite3, op: c_int, arg: c_int) -> c_int {
+    ((*SQLITE3_API).vtab_config.expect(EXPECT_MESSAGE))(db, op, arg)
+}
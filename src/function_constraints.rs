@@ -0,0 +1,169 @@
+//! Overloaded functions as constraints a virtual table handles itself.
+//!
+//! When [`VTabFind::find_function`](crate::table::VTabFind::find_function)
+//! returns a constraint code of [`SQLITE_INDEX_CONSTRAINT_FUNCTION`] or
+//! more for a function, a `WHERE` term like `near(location, ?)` on one of
+//! the table's columns reaches xBestIndex as a
+//! [`ConstraintOperator::FUNCTION`] constraint with that code. Claim them
+//! with [`FunctionConstraints::claim`], pass them to xFilter through idxStr,
+//! and decode which function and operand each filter value is with
+//! [`FunctionConstraints::bind`]:
+//!
+//! ```ignore
+//! fn best_index(&self, mut info: IndexInfo) -> Result<(), BestIndexError> {
+//!     let functions = FunctionConstraints::claim(&mut info, 1, |_, column| (column == 0).then_some(true));
+//!     info.set_idxstr(&functions.to_idx_str()).map_err(|_| BestIndexError::Error)?;
+//!     Ok(())
+//! }
+//!
+//! fn filter(&mut self, _: c_int, idx_str: Option<&str>, values: &[*mut sqlite3_value]) -> Result<()> {
+//!     for arg in FunctionConstraints::from_idx_str(idx_str.unwrap_or(""))?.bind::<Geo>(values)? {
+//!         match arg.function {
+//!             Geo::Near => self.near = Some(api::value_text(arg.operand)?.to_owned()),
+//!         }
+//!     }
+//!     Ok(())
+//! }
+//! ```
+//!
+//! Omitted constraints are left to the table, SQLite never calls the
+//! function for them. Ones that aren't omitted are still checked by calling
+//! the function on each row, so the table only has to narrow its scan.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::errors::{Error, Result};
+use crate::ext::sqlite3_value;
+use crate::table::{ConstraintOperator, IndexInfo};
+use serde::{Deserialize, Serialize};
+
+/// The smallest constraint code xFindFunction can return to make a function
+/// a constraint. Codes 150 to 255 are free for a virtual table to use.
+/// <https://www.sqlite.org/vtab.html#xfindfunction>
+pub const SQLITE_INDEX_CONSTRAINT_FUNCTION: u8 = 150;
+
+/// A function constraint claimed in xBestIndex.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionConstraint {
+    /// The constraint code find_function returned for the function.
+    pub function: u8,
+    /// The column the function's first argument is.
+    pub column: i32,
+    /// The 1-based index into the xFilter values that holds the function's
+    /// second argument.
+    pub argv_index: i32,
+}
+
+/// The function constraints of a query plan, built in xBestIndex and
+/// serialized into idxStr so xFilter can decode its values.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionConstraints {
+    pub constraints: Vec<FunctionConstraint>,
+}
+
+/// A function constraint's operand, as it arrived in xFilter.
+#[derive(Debug)]
+pub struct FunctionArg<'a, F> {
+    pub function: F,
+    pub column: i32,
+    pub operand: &'a *mut sqlite3_value,
+}
+
+impl FunctionConstraints {
+    /// Claims the usable function constraints `accept(code, column)` wants,
+    /// giving them argvIndexes from `first_argv_index` on. `accept` returns
+    /// whether to omit the constraint, or None to leave it to SQLite. Other
+    /// constraints a table claims have to take indexes before or after
+    /// these, see [`FunctionConstraints::next_argv_index`].
+    pub fn claim<A>(
+        info: &mut IndexInfo,
+        first_argv_index: i32,
+        mut accept: A,
+    ) -> FunctionConstraints
+    where
+        A: FnMut(u8, i32) -> Option<bool>,
+    {
+        let mut constraints = vec![];
+        let mut argv_index = first_argv_index;
+        for mut constraint in info.constraints() {
+            let function = match constraint.op() {
+                Some(ConstraintOperator::FUNCTION(function)) => function,
+                _ => continue,
+            };
+            let column = constraint.column_idx();
+            if !constraint.usable() {
+                continue;
+            }
+            let omit = match accept(function, column) {
+                Some(omit) => omit,
+                None => continue,
+            };
+            constraint.set_argv_index(argv_index);
+            constraint.set_omit(omit);
+            constraints.push(FunctionConstraint {
+                function,
+                column,
+                argv_index,
+            });
+            argv_index += 1;
+        }
+        FunctionConstraints { constraints }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.constraints.is_empty()
+    }
+
+    /// The argvIndex after the last claimed constraint's.
+    pub fn next_argv_index(&self) -> Option<i32> {
+        self.constraints.last().map(|c| c.argv_index + 1)
+    }
+
+    /// Serializes the constraints to a string suitable for `IndexInfo::set_idxstr`.
+    pub fn to_idx_str(&self) -> String {
+        serde_json::to_string(self).expect("function constraints are always serializable")
+    }
+
+    /// Parses constraints from an idxStr written with `to_idx_str`. An empty
+    /// string means no constraints.
+    pub fn from_idx_str(idx_str: &str) -> Result<FunctionConstraints> {
+        if idx_str.is_empty() {
+            return Ok(FunctionConstraints::default());
+        }
+        serde_json::from_str(idx_str).map_err(|err| {
+            Error::new_message(format!("invalid function constraints in idxStr: {}", err))
+        })
+    }
+
+    /// Pairs each constraint with its operand in the xFilter values, with
+    /// the constraint code decoded into the table's own type of function.
+    pub fn bind<'a, F: TryFrom<u8>>(
+        &self,
+        values: &'a [*mut sqlite3_value],
+    ) -> Result<Vec<FunctionArg<'a, F>>> {
+        self.constraints
+            .iter()
+            .map(|constraint| {
+                let function = F::try_from(constraint.function).map_err(|_| {
+                    Error::new_message(format!(
+                        "unknown function constraint {}",
+                        constraint.function
+                    ))
+                })?;
+                let operand = usize::try_from(constraint.argv_index - 1)
+                    .ok()
+                    .and_then(|idx| values.get(idx))
+                    .ok_or_else(|| {
+                        Error::new_message(format!(
+                            "no filter value for function constraint {}",
+                            constraint.function
+                        ))
+                    })?;
+                Ok(FunctionArg {
+                    function,
+                    column: constraint.column,
+                    operand,
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,130 @@
+//! Generation counters for detecting writes to a virtual table during a scan.
+//!
+//! A writable virtual table that's modified while one of its cursors is still
+//! open (ex a one-pass `UPDATE`, or a scalar function that writes to the table
+//! being scanned) will often silently skip or duplicate rows, depending on
+//! how the cursor walks its underlying storage. A [`Generation`] counter makes
+//! that deterministic: the table bumps it on every xUpdate, each cursor takes
+//! a [`GenerationGuard`] in xFilter, and then either fails the scan with
+//! [`CONCURRENT_MODIFICATION_MESSAGE`] or resyncs itself, for example by
+//! re-seeking to the current rowid.
+//!
+//! ```ignore
+//! impl<'vtab> VTabWriteable<'vtab> for MyTable {
+//!     fn update(&'vtab mut self, operation: UpdateOperation, _p_rowid: *mut i64) -> Result<()> {
+//!         self.generation.bump();
+//!         // ...
+//!     }
+//! }
+//!
+//! impl VTabCursor for MyCursor {
+//!     fn filter(&mut self, ...) -> Result<()> {
+//!         self.guard = self.generation.guard();
+//!         // ...
+//!     }
+//!     fn next(&mut self) -> Result<()> {
+//!         self.guard.check()?;
+//!         // ...
+//!     }
+//! }
+//! ```
+
+use crate::errors::{Error, Result};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+/// The error message returned by [`GenerationGuard::check`] when the table
+/// was modified after the scan started.
+pub const CONCURRENT_MODIFICATION_MESSAGE: &str =
+    "virtual table was modified while a cursor was scanning it";
+
+/// A shared counter owned by a virtual table, cloned into each of its cursors.
+#[derive(Debug, Clone, Default)]
+pub struct Generation(Arc<AtomicU64>);
+
+impl Generation {
+    pub fn new() -> Generation {
+        Generation::default()
+    }
+
+    /// The current generation.
+    pub fn current(&self) -> u64 {
+        self.0.load(Ordering::Acquire)
+    }
+
+    /// Marks the table as modified, invalidating all outstanding guards.
+    /// Call this in xUpdate (and anywhere else the table's rows change).
+    pub fn bump(&self) -> u64 {
+        self.0.fetch_add(1, Ordering::AcqRel) + 1
+    }
+
+    /// Starts tracking a new scan at the current generation, usually in xFilter.
+    pub fn guard(&self) -> GenerationGuard {
+        GenerationGuard {
+            generation: self.clone(),
+            seen: self.current(),
+        }
+    }
+}
+
+/// Remembers the generation a cursor's scan started at.
+#[derive(Debug, Clone, Default)]
+pub struct GenerationGuard {
+    generation: Generation,
+    seen: u64,
+}
+
+impl GenerationGuard {
+    /// Whether the table was modified since the guard was created or last resynced.
+    pub fn is_stale(&self) -> bool {
+        self.generation.current() != self.seen
+    }
+
+    /// Fails with [`CONCURRENT_MODIFICATION_MESSAGE`] if the table was
+    /// modified, for cursors that can't safely continue. Call it at the start
+    /// of xNext and xColumn.
+    pub fn check(&self) -> Result<()> {
+        if self.is_stale() {
+            return Err(Error::new_message(CONCURRENT_MODIFICATION_MESSAGE));
+        }
+        Ok(())
+    }
+
+    /// For cursors that can adjust to writes: returns true if the table was
+    /// modified, and accepts the new generation so the cursor can reposition
+    /// itself before continuing.
+    pub fn resync(&mut self) -> bool {
+        let current = self.generation.current();
+        let stale = current != self.seen;
+        self.seen = current;
+        stale
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::generation::*;
+
+    #[test]
+    fn test_generation_guard() {
+        let generation = Generation::new();
+        let mut guard = generation.guard();
+        assert!(guard.check().is_ok());
+
+        generation.bump();
+        assert_eq!(
+            guard.check(),
+            Err(Error::new_message(CONCURRENT_MODIFICATION_MESSAGE))
+        );
+        assert!(guard.resync());
+        assert!(!guard.resync());
+        assert!(guard.check().is_ok());
+
+        // new scans start at the latest generation
+        generation.bump();
+        assert!(generation.guard().check().is_ok());
+        assert!(guard.is_stale());
+    }
+}
@@ -0,0 +1,477 @@
+//! Convert geometries between
+//! [WKB](https://libgeos.org/specifications/wkb/) blobs,
+//! [GeoJSON](https://datatracker.ietf.org/doc/html/rfc7946) text, and simple
+//! (lat, lng) pairs, so geospatial extensions can accept and return either
+//! format.
+//!
+//! Only 2D geometries are supported. WKB is read in either byte order and
+//! always written little-endian. Results are tagged with a subtype, [`WKB_SUBTYPE`]
+//! for WKB blobs and 'J' for GeoJSON, so other functions can tell them
+//! apart from arbitrary blobs and text.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api::{
+    result_blob, result_json, result_subtype, value_blob, value_text, value_type, ValueType,
+};
+use crate::errors::{Error, Result};
+use crate::ext::{sqlite3_context, sqlite3_value};
+use serde_json::{json, Value};
+
+/// The subtype of WKB blobs returned by [`result_wkb`].
+pub const WKB_SUBTYPE: u8 = crate::subtype::WKB;
+
+/// A single position, `x` is the longitude and `y` the latitude.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Coord {
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Geometry {
+    Point(Coord),
+    LineString(Vec<Coord>),
+    /// The exterior ring followed by any holes.
+    Polygon(Vec<Vec<Coord>>),
+    MultiPoint(Vec<Coord>),
+    MultiLineString(Vec<Vec<Coord>>),
+    MultiPolygon(Vec<Vec<Vec<Coord>>>),
+    GeometryCollection(Vec<Geometry>),
+}
+
+impl Geometry {
+    /// A point at the given latitude and longitude, in degrees.
+    pub fn point(lat: f64, lng: f64) -> Geometry {
+        Geometry::Point(Coord { x: lng, y: lat })
+    }
+
+    /// The (lat, lng) pair of a point, None for other geometries.
+    pub fn lat_lng(&self) -> Option<(f64, f64)> {
+        match self {
+            Geometry::Point(coord) => Some((coord.y, coord.x)),
+            _ => None,
+        }
+    }
+
+    /// The WKB geometry type code.
+    fn wkb_type(&self) -> u32 {
+        match self {
+            Geometry::Point(_) => 1,
+            Geometry::LineString(_) => 2,
+            Geometry::Polygon(_) => 3,
+            Geometry::MultiPoint(_) => 4,
+            Geometry::MultiLineString(_) => 5,
+            Geometry::MultiPolygon(_) => 6,
+            Geometry::GeometryCollection(_) => 7,
+        }
+    }
+
+    /// Parses a WKB blob. Trailing bytes are an error.
+    pub fn from_wkb(wkb: &[u8]) -> Result<Geometry> {
+        let mut reader = WkbReader { wkb, pos: 0 };
+        let geometry = reader.geometry(0)?;
+        if reader.pos != wkb.len() {
+            return Err(Error::new_message(format!(
+                "invalid WKB: {} trailing bytes",
+                wkb.len() - reader.pos
+            )));
+        }
+        Ok(geometry)
+    }
+
+    /// Encodes the geometry as little-endian WKB.
+    pub fn to_wkb(&self) -> Vec<u8> {
+        let mut wkb = vec![];
+        write_wkb(&mut wkb, self);
+        wkb
+    }
+
+    /// Reads a GeoJSON geometry object. Features are read as their geometry.
+    pub fn from_geojson(geojson: &Value) -> Result<Geometry> {
+        let kind = geojson
+            .get("type")
+            .and_then(Value::as_str)
+            .ok_or_else(|| Error::new_message("invalid GeoJSON: missing \"type\""))?;
+        if kind == "Feature" {
+            return Geometry::from_geojson(geojson.get("geometry").unwrap_or(&Value::Null));
+        }
+        if kind == "GeometryCollection" {
+            let geometries = geojson
+                .get("geometries")
+                .and_then(Value::as_array)
+                .ok_or_else(|| Error::new_message("invalid GeoJSON: missing \"geometries\""))?;
+            return Ok(Geometry::GeometryCollection(
+                geometries
+                    .iter()
+                    .map(Geometry::from_geojson)
+                    .collect::<Result<_>>()?,
+            ));
+        }
+        let coordinates = geojson
+            .get("coordinates")
+            .ok_or_else(|| Error::new_message("invalid GeoJSON: missing \"coordinates\""))?;
+        Ok(match kind {
+            "Point" => Geometry::Point(geojson_coord(coordinates)?),
+            "LineString" => Geometry::LineString(geojson_coords(coordinates)?),
+            "Polygon" => Geometry::Polygon(geojson_list(coordinates, geojson_coords)?),
+            "MultiPoint" => Geometry::MultiPoint(geojson_coords(coordinates)?),
+            "MultiLineString" => {
+                Geometry::MultiLineString(geojson_list(coordinates, geojson_coords)?)
+            }
+            "MultiPolygon" => Geometry::MultiPolygon(geojson_list(coordinates, |polygon| {
+                geojson_list(polygon, geojson_coords)
+            })?),
+            _ => {
+                return Err(Error::new_message(format!(
+                    "invalid GeoJSON: unknown geometry type {:?}",
+                    kind
+                )))
+            }
+        })
+    }
+
+    /// The GeoJSON geometry object.
+    pub fn to_geojson(&self) -> Value {
+        fn coord(c: &Coord) -> Value {
+            json!([c.x, c.y])
+        }
+        fn coords(cs: &[Coord]) -> Value {
+            Value::Array(cs.iter().map(coord).collect())
+        }
+        fn rings(rs: &[Vec<Coord>]) -> Value {
+            Value::Array(rs.iter().map(|r| coords(r)).collect())
+        }
+        match self {
+            Geometry::Point(c) => json!({"type": "Point", "coordinates": coord(c)}),
+            Geometry::LineString(cs) => json!({"type": "LineString", "coordinates": coords(cs)}),
+            Geometry::Polygon(rs) => json!({"type": "Polygon", "coordinates": rings(rs)}),
+            Geometry::MultiPoint(cs) => json!({"type": "MultiPoint", "coordinates": coords(cs)}),
+            Geometry::MultiLineString(ls) => {
+                json!({"type": "MultiLineString", "coordinates": rings(ls)})
+            }
+            Geometry::MultiPolygon(ps) => json!({
+                "type": "MultiPolygon",
+                "coordinates": Value::Array(ps.iter().map(|p| rings(p)).collect()),
+            }),
+            Geometry::GeometryCollection(gs) => json!({
+                "type": "GeometryCollection",
+                "geometries": Value::Array(gs.iter().map(Geometry::to_geojson).collect()),
+            }),
+        }
+    }
+}
+
+fn geojson_coord(value: &Value) -> Result<Coord> {
+    match value.as_array().map(|position| position.as_slice()) {
+        // positions may have an altitude, which is dropped
+        Some([x, y, ..]) => match (x.as_f64(), y.as_f64()) {
+            (Some(x), Some(y)) => Ok(Coord { x, y }),
+            _ => Err(Error::new_message(
+                "invalid GeoJSON: positions must be numbers",
+            )),
+        },
+        _ => Err(Error::new_message(
+            "invalid GeoJSON: positions must be arrays of at least 2 numbers",
+        )),
+    }
+}
+
+fn geojson_coords(value: &Value) -> Result<Vec<Coord>> {
+    geojson_list(value, geojson_coord)
+}
+
+fn geojson_list<T>(value: &Value, item: impl Fn(&Value) -> Result<T>) -> Result<Vec<T>> {
+    value
+        .as_array()
+        .ok_or_else(|| Error::new_message("invalid GeoJSON: expected an array of coordinates"))?
+        .iter()
+        .map(item)
+        .collect()
+}
+
+/// How deeply geometries can nest in a WKB blob, so a corrupt one can't
+/// overflow the stack.
+const MAX_WKB_DEPTH: usize = 64;
+
+struct WkbReader<'a> {
+    wkb: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> WkbReader<'a> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let bytes = self
+            .wkb
+            .get(self.pos..self.pos + N)
+            .ok_or_else(|| Error::new_message("invalid WKB: unexpected end of blob"))?;
+        self.pos += N;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn u32(&mut self, little_endian: bool) -> Result<u32> {
+        let bytes = self.take::<4>()?;
+        Ok(if little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn f64(&mut self, little_endian: bool) -> Result<f64> {
+        let bytes = self.take::<8>()?;
+        Ok(if little_endian {
+            f64::from_le_bytes(bytes)
+        } else {
+            f64::from_be_bytes(bytes)
+        })
+    }
+
+    fn coord(&mut self, little_endian: bool) -> Result<Coord> {
+        Ok(Coord {
+            x: self.f64(little_endian)?,
+            y: self.f64(little_endian)?,
+        })
+    }
+
+    /// A count followed by that many items. The count isn't used to
+    /// preallocate, since a corrupt blob could claim billions of items.
+    fn list<T>(
+        &mut self,
+        little_endian: bool,
+        mut item: impl FnMut(&mut Self) -> Result<T>,
+    ) -> Result<Vec<T>> {
+        let n = self.u32(little_endian)?;
+        let mut items = vec![];
+        for _ in 0..n {
+            items.push(item(self)?);
+        }
+        Ok(items)
+    }
+
+    fn coords(&mut self, little_endian: bool) -> Result<Vec<Coord>> {
+        self.list(little_endian, |r| r.coord(little_endian))
+    }
+
+    fn rings(&mut self, little_endian: bool) -> Result<Vec<Vec<Coord>>> {
+        self.list(little_endian, |r| r.coords(little_endian))
+    }
+
+    /// Reads a nested geometry of a multi-geometry at `depth`, which must be
+    /// `expected`.
+    fn member(&mut self, expected: u32, depth: usize) -> Result<Geometry> {
+        let geometry = self.geometry(depth + 1)?;
+        if geometry.wkb_type() != expected {
+            return Err(Error::new_message(format!(
+                "invalid WKB: expected geometry type {} in collection, found {}",
+                expected,
+                geometry.wkb_type()
+            )));
+        }
+        Ok(geometry)
+    }
+
+    /// Reads a geometry inside `depth` others.
+    fn geometry(&mut self, depth: usize) -> Result<Geometry> {
+        if depth > MAX_WKB_DEPTH {
+            return Err(Error::new_message(format!(
+                "invalid WKB: geometries nested more than {} deep",
+                MAX_WKB_DEPTH
+            )));
+        }
+        let little_endian = match self.take::<1>()? {
+            [0] => false,
+            [1] => true,
+            [b] => {
+                return Err(Error::new_message(format!(
+                    "invalid WKB: unknown byte order {}",
+                    b
+                )))
+            }
+        };
+        let kind = self.u32(little_endian)?;
+        Ok(match kind {
+            1 => Geometry::Point(self.coord(little_endian)?),
+            2 => Geometry::LineString(self.coords(little_endian)?),
+            3 => Geometry::Polygon(self.rings(little_endian)?),
+            4 => Geometry::MultiPoint(self.list(little_endian, |r| match r.member(1, depth)? {
+                Geometry::Point(c) => Ok(c),
+                _ => unreachable!(),
+            })?),
+            5 => Geometry::MultiLineString(self.list(
+                little_endian,
+                |r| match r.member(2, depth)? {
+                    Geometry::LineString(cs) => Ok(cs),
+                    _ => unreachable!(),
+                },
+            )?),
+            6 => {
+                Geometry::MultiPolygon(self.list(little_endian, |r| match r.member(3, depth)? {
+                    Geometry::Polygon(rs) => Ok(rs),
+                    _ => unreachable!(),
+                })?)
+            }
+            7 => Geometry::GeometryCollection(self.list(little_endian, |r| r.geometry(depth + 1))?),
+            _ => {
+                return Err(Error::new_message(format!(
+                    "unsupported WKB geometry type {}, only 2D geometries are supported",
+                    kind
+                )))
+            }
+        })
+    }
+}
+
+fn write_wkb(wkb: &mut Vec<u8>, geometry: &Geometry) {
+    fn coord(wkb: &mut Vec<u8>, c: &Coord) {
+        wkb.extend(c.x.to_le_bytes());
+        wkb.extend(c.y.to_le_bytes());
+    }
+    fn count(wkb: &mut Vec<u8>, n: usize) {
+        wkb.extend((n as u32).to_le_bytes());
+    }
+    fn coords(wkb: &mut Vec<u8>, cs: &[Coord]) {
+        count(wkb, cs.len());
+        cs.iter().for_each(|c| coord(wkb, c));
+    }
+    fn rings(wkb: &mut Vec<u8>, rs: &[Vec<Coord>]) {
+        count(wkb, rs.len());
+        rs.iter().for_each(|r| coords(wkb, r));
+    }
+    wkb.push(1);
+    wkb.extend(geometry.wkb_type().to_le_bytes());
+    match geometry {
+        Geometry::Point(c) => coord(wkb, c),
+        Geometry::LineString(cs) => coords(wkb, cs),
+        Geometry::Polygon(rs) => rings(wkb, rs),
+        Geometry::MultiPoint(cs) => {
+            count(wkb, cs.len());
+            cs.iter().for_each(|c| write_wkb(wkb, &Geometry::Point(*c)));
+        }
+        Geometry::MultiLineString(ls) => {
+            count(wkb, ls.len());
+            ls.iter()
+                .for_each(|l| write_wkb(wkb, &Geometry::LineString(l.clone())));
+        }
+        Geometry::MultiPolygon(ps) => {
+            count(wkb, ps.len());
+            ps.iter()
+                .for_each(|p| write_wkb(wkb, &Geometry::Polygon(p.clone())));
+        }
+        Geometry::GeometryCollection(gs) => {
+            count(wkb, gs.len());
+            gs.iter().for_each(|g| write_wkb(wkb, g));
+        }
+    }
+}
+
+/// Reads a geometry from a sqlite3_value: blobs are read as WKB, and text as
+/// GeoJSON.
+pub fn value_geometry(value: &*mut sqlite3_value) -> Result<Geometry> {
+    match value_type(value) {
+        ValueType::Blob => Geometry::from_wkb(value_blob(value)),
+        ValueType::Text => {
+            let geojson: Value = serde_json::from_str(value_text(value)?)
+                .map_err(|err| Error::new_message(format!("invalid GeoJSON: {}", err)))?;
+            Geometry::from_geojson(&geojson)
+        }
+        _ => Err(Error::new_message(
+            "geometries must be WKB blobs or GeoJSON text",
+        )),
+    }
+}
+
+/// Results the geometry as a WKB blob, with a subtype of [`WKB_SUBTYPE`].
+pub fn result_wkb(context: *mut sqlite3_context, geometry: &Geometry) {
+    result_blob(context, &geometry.to_wkb());
+    result_subtype(context, WKB_SUBTYPE);
+}
+
+/// Results the geometry as GeoJSON text, with a JSON subtype.
+pub fn result_geojson(context: *mut sqlite3_context, geometry: &Geometry) -> Result<()> {
+    result_json(context, geometry.to_geojson())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geo::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_wkb() {
+        let point = Geometry::point(2.0, 1.0);
+        let wkb = hex("0101000000000000000000f03f0000000000000040");
+        assert_eq!(point.to_wkb(), wkb);
+        assert_eq!(Geometry::from_wkb(&wkb).unwrap(), point);
+        // big-endian
+        assert_eq!(
+            Geometry::from_wkb(&hex("00000000013ff00000000000004000000000000000")).unwrap(),
+            point
+        );
+        assert_eq!(point.lat_lng(), Some((2.0, 1.0)));
+
+        let collection = Geometry::GeometryCollection(vec![
+            Geometry::MultiPoint(vec![Coord { x: 1.0, y: 2.0 }, Coord { x: 3.0, y: 4.0 }]),
+            Geometry::MultiPolygon(vec![vec![vec![
+                Coord { x: 0.0, y: 0.0 },
+                Coord { x: 1.0, y: 0.0 },
+                Coord { x: 0.0, y: 1.0 },
+                Coord { x: 0.0, y: 0.0 },
+            ]]]),
+        ]);
+        assert_eq!(
+            Geometry::from_wkb(&collection.to_wkb()).unwrap(),
+            collection
+        );
+
+        assert!(Geometry::from_wkb(&wkb[..10]).is_err());
+        assert!(Geometry::from_wkb(&[wkb.as_slice(), &[0]].concat()).is_err());
+        // POINT Z
+        assert!(Geometry::from_wkb(&hex("01e9030000")).is_err());
+    }
+
+    #[test]
+    fn test_wkb_depth() {
+        let nested = |depth: usize| {
+            (0..depth).fold(Geometry::point(0.0, 0.0), |g, _| {
+                Geometry::GeometryCollection(vec![g])
+            })
+        };
+        let deepest = nested(MAX_WKB_DEPTH);
+        assert_eq!(Geometry::from_wkb(&deepest.to_wkb()).unwrap(), deepest);
+        assert_eq!(
+            Geometry::from_wkb(&nested(MAX_WKB_DEPTH + 1).to_wkb())
+                .unwrap_err()
+                .result_error_message(),
+            "invalid WKB: geometries nested more than 64 deep"
+        );
+        // a blob of collections, each claiming one member, far deeper than
+        // the stack could recurse
+        let wkb = hex("010700000001000000").repeat(100_000);
+        assert!(Geometry::from_wkb(&wkb).is_err());
+    }
+
+    #[test]
+    fn test_geojson() {
+        let line = Geometry::LineString(vec![Coord { x: 1.0, y: 2.0 }, Coord { x: 3.0, y: 4.0 }]);
+        let geojson = json!({"type": "LineString", "coordinates": [[1.0, 2.0], [3.0, 4.0]]});
+        assert_eq!(line.to_geojson(), geojson);
+        assert_eq!(Geometry::from_geojson(&geojson).unwrap(), line);
+        assert_eq!(
+            Geometry::from_geojson(&json!({
+                "type": "Feature",
+                "properties": {},
+                "geometry": {"type": "Point", "coordinates": [1, 2, 100]},
+            }))
+            .unwrap(),
+            Geometry::point(2.0, 1.0)
+        );
+        assert!(Geometry::from_geojson(&json!({"type": "Point", "coordinates": [1]})).is_err());
+        assert!(Geometry::from_geojson(&json!({"type": "Circle", "coordinates": []})).is_err());
+    }
+}
@@ -0,0 +1,419 @@
+//! Exponential histograms, to capture a distribution of values and read its
+//! percentiles back.
+//!
+//! A [`Histogram`] counts values in buckets that grow by a constant factor,
+//! so it stays small however many values it sees, and its percentiles are
+//! within [`RELATIVE_ERROR`] of the exact ones. [`define_hist`] adds the
+//! `hist(value [, name])` aggregate, which returns a summary of its values as
+//! JSON, and the `hist_stats` table of named histograms:
+//!
+//! ```sql
+//! select hist(duration) from requests;
+//! -- {"count":1000,"max":2.5,"mean":0.12,"min":0.01,"p50":0.08,"p95":0.4,"p99":1.9}
+//! select hist(duration, 'requests') from requests;
+//! select name, count, p50, p95, p99 from hist_stats;
+//! ```
+//!
+//! `hist()` with a name also merges its values into the histogram of that
+//! name, which is where other code feeds its own measurements, with
+//! [`record`]. A [`crate::trace`] callback, for instance, can record how
+//! long each statement takes:
+//!
+//! ```ignore
+//! trace(db, TraceEvents::PROFILE, |event| {
+//!     if let TraceEvent::Profile { elapsed, .. } = event {
+//!         histogram::record("statements", elapsed.as_secs_f64());
+//!     }
+//! })?;
+//! ```
+//!
+//! Named histograms belong to the extension library, so every connection it's
+//! loaded into shares them, and they last until `hist_reset()` or [`reset`]
+//! drops them.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::aggregate::{define_aggregate_function, Aggregate};
+use crate::api;
+use crate::errors::{Error, Result};
+use crate::ext::{sqlite3, sqlite3_context, sqlite3_value, sqlite3_vtab, sqlite3_vtab_cursor};
+use crate::scalar::{define_scalar_function, FunctionFlags};
+use crate::table::{
+    define_table_function, BestIndexError, IndexInfo, VTab, VTabArguments, VTabCursor,
+};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::mem;
+use std::os::raw::c_int;
+use std::sync::Mutex;
+
+/// How many buckets cover each doubling of magnitude.
+pub const BUCKETS_PER_DOUBLING: i32 = 16;
+
+/// How far, relative to the value, a percentile can be from the exact one:
+/// half a bucket.
+pub const RELATIVE_ERROR: f64 = 0.022;
+
+/// Counts of values in exponentially growing buckets, for each sign, with
+/// the exact count, sum, minimum and maximum alongside.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Histogram {
+    positive: BTreeMap<i32, u64>,
+    negative: BTreeMap<i32, u64>,
+    zeros: u64,
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `value`. NaNs are ignored.
+    pub fn record(&mut self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+        if value == 0.0 {
+            self.zeros += 1;
+        } else {
+            let buckets = if value > 0.0 {
+                &mut self.positive
+            } else {
+                &mut self.negative
+            };
+            *buckets.entry(bucket(value.abs())).or_default() += 1;
+        }
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+
+    /// Adds every value of `other`.
+    pub fn merge(&mut self, other: &Histogram) {
+        if other.count == 0 {
+            return;
+        }
+        for (bucket, n) in &other.positive {
+            *self.positive.entry(*bucket).or_default() += n;
+        }
+        for (bucket, n) in &other.negative {
+            *self.negative.entry(*bucket).or_default() += n;
+        }
+        if self.count == 0 {
+            self.min = other.min;
+            self.max = other.max;
+        } else {
+            self.min = self.min.min(other.min);
+            self.max = self.max.max(other.max);
+        }
+        self.zeros += other.zeros;
+        self.count += other.count;
+        self.sum += other.sum;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.max)
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum / self.count as f64)
+    }
+
+    /// The value below which a `q` fraction of the values fall, `0.5` for
+    /// the median, or `None` without values.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = (q.clamp(0.0, 1.0) * (self.count - 1) as f64).round() as u64;
+        // the ends are known exactly
+        if rank == 0 {
+            return Some(self.min);
+        }
+        if rank == self.count - 1 {
+            return Some(self.max);
+        }
+        // in ascending order: the largest negative magnitudes, zeros, then
+        // the smallest positive ones
+        let buckets = self
+            .negative
+            .iter()
+            .rev()
+            .map(|(bucket, n)| (-midpoint(*bucket), *n))
+            .chain(std::iter::once((0.0, self.zeros)))
+            .chain(
+                self.positive
+                    .iter()
+                    .map(|(bucket, n)| (midpoint(*bucket), *n)),
+            );
+        let mut seen = 0;
+        for (value, n) in buckets {
+            seen += n;
+            if rank < seen {
+                return Some(value.clamp(self.min, self.max));
+            }
+        }
+        Some(self.max)
+    }
+
+    /// The count, minimum, maximum, mean and 50th, 95th and 99th
+    /// percentiles, as a JSON object, what `hist()` returns.
+    pub fn summary(&self) -> serde_json::Value {
+        json!({
+            "count": self.count,
+            "min": self.min(),
+            "max": self.max(),
+            "mean": self.mean(),
+            "p50": self.quantile(0.5),
+            "p95": self.quantile(0.95),
+            "p99": self.quantile(0.99),
+        })
+    }
+}
+
+fn bucket(magnitude: f64) -> i32 {
+    (magnitude.log2() * BUCKETS_PER_DOUBLING as f64).floor() as i32
+}
+
+/// The geometric middle of a bucket, within [`RELATIVE_ERROR`] of any value
+/// in it.
+fn midpoint(bucket: i32) -> f64 {
+    ((bucket as f64 + 0.5) / BUCKETS_PER_DOUBLING as f64).exp2()
+}
+
+static HISTOGRAMS: Mutex<BTreeMap<String, Histogram>> = Mutex::new(BTreeMap::new());
+
+/// Adds `value` to the histogram `name`, creating it if needed.
+pub fn record(name: &str, value: f64) {
+    with_histograms(|histograms| {
+        histograms.entry(name.to_owned()).or_default().record(value);
+    });
+}
+
+/// Adds every value of `histogram` to the histogram `name`.
+pub fn merge(name: &str, histogram: &Histogram) {
+    with_histograms(|histograms| {
+        histograms
+            .entry(name.to_owned())
+            .or_default()
+            .merge(histogram);
+    });
+}
+
+/// A copy of the histogram `name`.
+pub fn get(name: &str) -> Option<Histogram> {
+    with_histograms(|histograms| histograms.get(name).cloned())
+}
+
+/// Copies of every histogram, by name.
+pub fn snapshot() -> Vec<(String, Histogram)> {
+    with_histograms(|histograms| {
+        histograms
+            .iter()
+            .map(|(name, histogram)| (name.clone(), histogram.clone()))
+            .collect()
+    })
+}
+
+/// Drops the histogram `name`, or every histogram without one, returning
+/// how many were dropped.
+pub fn reset(name: Option<&str>) -> usize {
+    with_histograms(|histograms| match name {
+        Some(name) => usize::from(histograms.remove(name).is_some()),
+        None => mem::take(histograms).len(),
+    })
+}
+
+fn with_histograms<T>(f: impl FnOnce(&mut BTreeMap<String, Histogram>) -> T) -> T {
+    // a panic while holding the lock can't leave a histogram half updated
+    // in a way that matters, so carry on with it
+    let mut histograms = HISTOGRAMS.lock().unwrap_or_else(|err| err.into_inner());
+    f(&mut histograms)
+}
+
+/// Defines the `hist(value [, name])` aggregate, `hist_reset([name])`, and
+/// the `hist_stats` table. `hist_reset()` changes what every connection
+/// sees, so it's DIRECTONLY: a trigger or view can't call it.
+pub fn define_hist(db: *mut sqlite3) -> Result<()> {
+    for num_args in [1, 2] {
+        define_aggregate_function::<HistState>(
+            db,
+            "hist",
+            num_args,
+            FunctionFlags::UTF8 | FunctionFlags::RESULT_SUBTYPE,
+        )?;
+    }
+    for num_args in [0, 1] {
+        define_scalar_function(
+            db,
+            "hist_reset",
+            num_args,
+            |context, values| {
+                let name = match values.first() {
+                    Some(name) => Some(api::value_text(name)?),
+                    None => None,
+                };
+                api::result_int64(context, reset(name) as i64);
+                Ok(())
+            },
+            FunctionFlags::UTF8 | FunctionFlags::DIRECTONLY,
+        )?;
+    }
+    define_table_function::<HistStatsTable>(db, "hist_stats", None)
+}
+
+/// A `hist()` call's values so far, and the name to merge them into.
+#[derive(Default)]
+struct HistState {
+    histogram: Histogram,
+    name: Option<String>,
+}
+
+impl Aggregate for HistState {
+    fn step(&mut self, values: &[*mut sqlite3_value]) -> Result<()> {
+        if self.name.is_none() {
+            if let Some(name) = values.get(1) {
+                if !api::value_is_null(name) {
+                    self.name = Some(api::value_text(name)?.to_owned());
+                }
+            }
+        }
+        if let Some(value) = values.first() {
+            if !api::value_is_null(value) {
+                self.histogram.record(api::value_double(value));
+            }
+        }
+        Ok(())
+    }
+
+    fn fnl(self, context: *mut sqlite3_context) -> Result<()> {
+        if let Some(name) = &self.name {
+            merge(name, &self.histogram);
+        }
+        if self.histogram.count() == 0 {
+            api::result_null(context);
+            Ok(())
+        } else {
+            api::result_json(context, self.histogram.summary())
+        }
+    }
+}
+
+#[repr(C)]
+pub struct HistStatsTable {
+    /// must be first
+    base: sqlite3_vtab,
+}
+
+impl<'vtab> VTab<'vtab> for HistStatsTable {
+    type Aux = ();
+    type Cursor = HistStatsCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, HistStatsTable)> {
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        Ok((
+            "CREATE TABLE x(name TEXT, count INTEGER, sum REAL, min REAL, max REAL, mean REAL, \
+             p50 REAL, p95 REAL, p99 REAL)"
+                .to_owned(),
+            HistStatsTable { base },
+        ))
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        info.set_estimated_cost(100.0);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<HistStatsCursor> {
+        let base: sqlite3_vtab_cursor = unsafe { mem::zeroed() };
+        Ok(HistStatsCursor {
+            base,
+            rows: Vec::new(),
+            index: 0,
+        })
+    }
+}
+
+#[repr(C)]
+pub struct HistStatsCursor {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    /// The histograms as of the scan's start.
+    rows: Vec<(String, Histogram)>,
+    index: usize,
+}
+
+impl VTabCursor for HistStatsCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.rows = snapshot();
+        self.index = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.index += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.index >= self.rows.len()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, i: c_int) -> Result<()> {
+        let (name, histogram) = self
+            .rows
+            .get(self.index)
+            .ok_or_else(|| Error::new_message("hist_stats has no current row"))?;
+        let real = |value: Option<f64>| match value {
+            Some(value) => api::result_double(context, value),
+            None => api::result_null(context),
+        };
+        match i {
+            0 => api::result_text(context, name)?,
+            1 => api::result_int64(context, histogram.count() as i64),
+            2 => api::result_double(context, histogram.sum()),
+            3 => real(histogram.min()),
+            4 => real(histogram.max()),
+            5 => real(histogram.mean()),
+            6 => real(histogram.quantile(0.5)),
+            7 => real(histogram.quantile(0.95)),
+            8 => real(histogram.quantile(0.99)),
+            _ => api::result_null(context),
+        }
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.index as i64)
+    }
+}
@@ -0,0 +1,242 @@
+//! Run Rust code in response to connection-level events.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::errors::{Error, Result};
+use crate::ext::{
+    sqlite3, sqlite3_context, sqlite3_value, sqlite3ext_commit_hook, sqlite3ext_create_function_v2,
+    sqlite3ext_rollback_hook, sqlite3ext_update_hook,
+};
+use sqlite3ext_sys::{SQLITE_DELETE, SQLITE_DIRECTONLY, SQLITE_INSERT, SQLITE_UTF8};
+use std::{
+    cell::Cell,
+    collections::{hash_map::Entry, HashMap},
+    ffi::{CStr, CString},
+    os::raw::{c_char, c_int, c_void},
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+static NEXT_CLOSE_HOOK: AtomicUsize = AtomicUsize::new(0);
+
+struct CloseHook<F> {
+    f: Option<F>,
+    // false until registration succeeds, so a failed registration (which
+    // also calls the destructor) doesn't run the hook
+    armed: Cell<bool>,
+}
+
+/// Runs `f` once, when the connection is closed.
+///
+/// There's no close hook in SQLite's API, so this registers a hidden SQL
+/// function whose destructor runs `f`. SQLite destroys the function while
+/// closing the connection, after every statement has been finalized.
+pub fn on_connection_close<F>(db: *mut sqlite3, f: F) -> Result<()>
+where
+    F: FnOnce(),
+{
+    unsafe extern "C" fn x_func(
+        context: *mut sqlite3_context,
+        _argc: c_int,
+        _argv: *mut *mut sqlite3_value,
+    ) {
+        // best effort, there's nothing useful to do if this fails too
+        let _ = crate::api::result_error(context, "internal sqlite-loadable function");
+    }
+    unsafe extern "C" fn x_destroy<F: FnOnce()>(p: *mut c_void) {
+        let mut hook = Box::from_raw(p.cast::<CloseHook<F>>());
+        if hook.armed.get() {
+            if let Some(f) = hook.f.take() {
+                f();
+            }
+        }
+    }
+
+    let id = NEXT_CLOSE_HOOK.fetch_add(1, Ordering::Relaxed);
+    let name = CString::new(format!("sqlite_loadable_close_hook_{}", id))?;
+    let hook = Box::into_raw(Box::new(CloseHook {
+        f: Some(f),
+        armed: Cell::new(false),
+    }));
+    let rc = unsafe {
+        sqlite3ext_create_function_v2(
+            db,
+            name.as_ptr(),
+            0,
+            (SQLITE_UTF8 | SQLITE_DIRECTONLY) as c_int,
+            hook.cast::<c_void>(),
+            Some(x_func),
+            None,
+            None,
+            Some(x_destroy::<F>),
+        )
+    };
+    if rc != 0 {
+        return Err(Error::new_message(format!(
+            "could not register connection close hook, error code {}",
+            rc
+        )));
+    }
+    unsafe { (*hook).armed.set(true) };
+    Ok(())
+}
+
+type CommitQueue = Vec<Box<dyn FnOnce() + Send>>;
+
+/// Closures queued with [`on_commit`], by connection. A connection has an
+/// entry once its commit and rollback hooks are installed.
+static COMMIT_QUEUES: Mutex<Option<HashMap<usize, CommitQueue>>> = Mutex::new(None);
+
+fn take_commit_queue(db: *mut c_void) -> CommitQueue {
+    match COMMIT_QUEUES.lock() {
+        Ok(mut queues) => queues
+            .as_mut()
+            .and_then(|queues| queues.get_mut(&(db as usize)))
+            .map(std::mem::take)
+            .unwrap_or_default(),
+        Err(_) => CommitQueue::new(),
+    }
+}
+
+unsafe extern "C" fn x_commit(db: *mut c_void) -> c_int {
+    // the lock is released before running anything, so closures can queue
+    // more work (for the next commit)
+    for f in take_commit_queue(db) {
+        // a panic can't unwind into SQLite, and shouldn't stop the rest of
+        // the queue or turn the commit into a rollback
+        let _ = catch_unwind(AssertUnwindSafe(f));
+    }
+    0
+}
+
+unsafe extern "C" fn x_rollback(db: *mut c_void) {
+    drop(take_commit_queue(db));
+}
+
+/// Runs `f` once, when the connection's current transaction commits. If the
+/// transaction rolls back instead, `f` is dropped without running.
+///
+/// Outside an explicit transaction, `f` runs when the current statement's
+/// implicit transaction commits. Only transactions that write anything
+/// commit, so work queued from a read-only statement waits for the next
+/// write. Rolling back to a savepoint doesn't discard anything.
+///
+/// The first call on a connection installs its
+/// [commit and rollback hooks](https://www.sqlite.org/c3ref/commit_hook.html),
+/// replacing any others, so don't mix this with `sqlite3_commit_hook`. `f`
+/// runs inside the commit hook, just before the commit completes, and like
+/// the hook itself must not use the connection.
+pub fn on_commit<F>(db: *mut sqlite3, f: F) -> Result<()>
+where
+    F: FnOnce() + Send + 'static,
+{
+    let mut queues = COMMIT_QUEUES
+        .lock()
+        .map_err(|_| Error::new_message("commit queue registry was poisoned"))?;
+    let queues = queues.get_or_insert_with(HashMap::new);
+    let key = db as usize;
+    let queue = match queues.entry(key) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => {
+            on_connection_close(db, move || {
+                let queue = COMMIT_QUEUES
+                    .lock()
+                    .ok()
+                    .and_then(|mut queues| queues.as_mut()?.remove(&key));
+                drop(queue);
+            })?;
+            unsafe {
+                sqlite3ext_commit_hook(db, Some(x_commit), db.cast::<c_void>());
+                sqlite3ext_rollback_hook(db, Some(x_rollback), db.cast::<c_void>());
+            }
+            entry.insert(CommitQueue::new())
+        }
+    };
+    queue.push(Box::new(f));
+    Ok(())
+}
+
+/// What happened to a row, for [`on_update`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateOperation {
+    Insert,
+    Update,
+    Delete,
+}
+
+/// A row that changed: its operation, schema, table and rowid.
+pub type UpdateListener = Arc<dyn Fn(UpdateOperation, &str, &str, i64) + Send + Sync>;
+
+/// Listeners added with [`on_update`], by connection. A connection has an
+/// entry once its update hook is installed.
+static UPDATE_LISTENERS: Mutex<Option<HashMap<usize, Vec<UpdateListener>>>> = Mutex::new(None);
+
+unsafe extern "C" fn x_update(
+    db: *mut c_void,
+    operation: c_int,
+    schema: *const c_char,
+    table: *const c_char,
+    rowid: i64,
+) {
+    let operation = match operation as u32 {
+        SQLITE_INSERT => UpdateOperation::Insert,
+        SQLITE_DELETE => UpdateOperation::Delete,
+        _ => UpdateOperation::Update,
+    };
+    let schema = CStr::from_ptr(schema).to_string_lossy();
+    let table = CStr::from_ptr(table).to_string_lossy();
+    // cloned out of the registry, so listeners run without its lock and
+    // other connections' hooks aren't held up by them
+    let listeners: Vec<UpdateListener> = UPDATE_LISTENERS
+        .lock()
+        .ok()
+        .and_then(|listeners| listeners.as_ref()?.get(&(db as usize)).cloned())
+        .unwrap_or_default();
+    for f in listeners {
+        // a panic can't unwind into SQLite
+        let _ = catch_unwind(AssertUnwindSafe(|| f(operation, &schema, &table, rowid)));
+    }
+}
+
+/// Calls `f` with the operation, schema, table and rowid of every row the
+/// connection inserts, updates or deletes, for as long as it's open.
+///
+/// The first call on a connection installs its
+/// [update hook](https://www.sqlite.org/c3ref/update_hook.html), replacing
+/// any other, so don't mix this with `sqlite3_update_hook`. Like the hook,
+/// `f` isn't called for `WITHOUT ROWID` tables, changes other connections
+/// make, rows an unqualified `DELETE` removes all at once, or rows an `ON
+/// CONFLICT REPLACE` removes, and is called for changes that later roll
+/// back. It runs while the statement is writing, so it must not use the
+/// connection. It can call `on_update`, for this or any other connection,
+/// and listeners it adds get the changes after the current one.
+pub fn on_update<F>(db: *mut sqlite3, f: F) -> Result<()>
+where
+    F: Fn(UpdateOperation, &str, &str, i64) + Send + Sync + 'static,
+{
+    let mut listeners = UPDATE_LISTENERS
+        .lock()
+        .map_err(|_| Error::new_message("update listener registry was poisoned"))?;
+    let listeners = listeners.get_or_insert_with(HashMap::new);
+    let key = db as usize;
+    let connection = match listeners.entry(key) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => {
+            on_connection_close(db, move || {
+                let connection = UPDATE_LISTENERS
+                    .lock()
+                    .ok()
+                    .and_then(|mut listeners| listeners.as_mut()?.remove(&key));
+                drop(connection);
+            })?;
+            unsafe {
+                sqlite3ext_update_hook(db, Some(x_update), db.cast::<c_void>());
+            }
+            entry.insert(Vec::new())
+        }
+    };
+    connection.push(Arc::new(f));
+    Ok(())
+}
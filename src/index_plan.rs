@@ -0,0 +1,270 @@
+//! Query plans that survive the trip from xBestIndex to xFilter.
+//!
+//! SQLite hands xFilter nothing of what xBestIndex decided but an integer,
+//! idxNum, and a string, idxStr. An [`IndexPlan`] records which
+//! constraints xBestIndex consumed, with their columns and operators, plus
+//! any payload of the table's own, and carries them across in both:
+//!
+//! ```ignore
+//! fn best_index(&self, mut info: IndexInfo) -> Result<(), BestIndexError> {
+//!     let mut plan = IndexPlan::new(Scan { reverse: false });
+//!     for mut constraint in info.constraints() {
+//!         if constraint.usable() && constraint.op() == Some(ConstraintOperator::EQ) {
+//!             plan.consume(&mut constraint, true);
+//!         }
+//!     }
+//!     plan.apply(&mut info).map_err(|_| BestIndexError::Error)?;
+//!     Ok(())
+//! }
+//!
+//! fn filter(&mut self, idx_num: c_int, idx_str: Option<&str>, values: &[*mut sqlite3_value]) -> Result<()> {
+//!     let plan = IndexPlan::<Scan>::decode(idx_num, idx_str)?;
+//!     for (constraint, value) in plan.bind(values)? {
+//!         // ... constraint.column, constraint.op, value ...
+//!     }
+//! }
+//! ```
+//!
+//! idxStr holds the plan as JSON, in memory from `sqlite3_mprintf` that
+//! SQLite frees, and idxNum the number of constraints consumed, which
+//! [`IndexPlan::decode`] checks the JSON against.
+//!
+//! A table that can page through its rows, like one backed by a remote
+//! API, also takes the query's `LIMIT` and `OFFSET`, after the other
+//! constraints:
+//!
+//! ```ignore
+//! plan.consume_limit(&info);
+//! // ... and in xFilter
+//! let LimitOffset { limit, offset } = plan.limit_offset(values)?;
+//! ```
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api;
+use crate::errors::{Error, Result};
+use crate::ext::sqlite3_value;
+use crate::table::{Constraint, ConstraintOperator, IndexInfo};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::os::raw::c_int;
+
+/// The version of the idxStr format, bumped when it changes.
+const VERSION: u32 = 1;
+
+/// A constraint that xBestIndex consumed, which xFilter gets the
+/// right-hand side of.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedConstraint {
+    /// The column index the constraint is on, -1 for the rowid.
+    pub column: i32,
+    pub op: ConstraintOperator,
+    /// The 1-based index into the xFilter values that holds the right-hand
+    /// side.
+    pub argv_index: i32,
+    /// Whether SQLite was told not to check the constraint itself.
+    pub omit: bool,
+}
+
+/// The `LIMIT` and `OFFSET` of a query, which xFilter gets from
+/// [`IndexPlan::limit_offset`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LimitOffset {
+    /// None without a `LIMIT`, or with a negative one, which SQLite takes
+    /// as none.
+    pub limit: Option<u64>,
+    /// How many rows to skip, 0 without an `OFFSET` or with a negative one.
+    pub offset: u64,
+}
+
+impl LimitOffset {
+    /// The index of the first row past the page, None if it doesn't end.
+    pub fn end(&self) -> Option<u64> {
+        self.limit.map(|limit| self.offset.saturating_add(limit))
+    }
+}
+
+/// The constraints xBestIndex consumed, in argvIndex order, and a payload
+/// of the table's own.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexPlan<P = ()> {
+    version: u32,
+    constraints: Vec<PlannedConstraint>,
+    pub payload: P,
+}
+
+impl<P: Default> Default for IndexPlan<P> {
+    fn default() -> Self {
+        IndexPlan::new(P::default())
+    }
+}
+
+impl<P> IndexPlan<P> {
+    pub fn new(payload: P) -> Self {
+        IndexPlan {
+            version: VERSION,
+            constraints: Vec::new(),
+            payload,
+        }
+    }
+
+    /// Claims `constraint`, giving it the next argvIndex so xFilter gets its
+    /// right-hand side, and returns that argvIndex. With `omit`, SQLite
+    /// trusts the table to check the constraint and doesn't itself.
+    /// Unusable constraints, which SQLite can't give a value for, and ones
+    /// with an operator this crate doesn't know are left alone, and 0
+    /// returned.
+    pub fn consume(&mut self, constraint: &mut Constraint, omit: bool) -> i32 {
+        let op = match constraint.op() {
+            Some(op) if constraint.usable() => op,
+            _ => return 0,
+        };
+        let argv_index = self.constraints.len() as i32 + 1;
+        constraint.set_argv_index(argv_index);
+        constraint.set_omit(omit);
+        self.constraints.push(PlannedConstraint {
+            column: constraint.column_idx(),
+            op,
+            argv_index,
+            omit,
+        });
+        argv_index
+    }
+
+    /// Claims the query's `LIMIT` and `OFFSET`, if SQLite offered them, and
+    /// returns whether it did. SQLite only hands a table its `LIMIT` when
+    /// every other constraint was consumed, so call it last, and it claims
+    /// nothing otherwise. xFilter then returns at most `limit` rows, after
+    /// skipping `offset` of them, which SQLite no longer does.
+    pub fn consume_limit(&mut self, info: &IndexInfo) -> bool {
+        let mut limit = match info.limit() {
+            Some(limit) => limit,
+            None => return false,
+        };
+        let all_consumed = info.constraints().iter().all(|constraint| {
+            constraint.argv_index() > 0
+                || matches!(
+                    constraint.op(),
+                    Some(ConstraintOperator::LIMIT | ConstraintOperator::OFFSET)
+                )
+        });
+        if !all_consumed {
+            return false;
+        }
+        self.consume(&mut limit, true);
+        if let Some(mut offset) = info.offset() {
+            self.consume(&mut offset, true);
+        }
+        true
+    }
+
+    /// The consumed constraints, in argvIndex order.
+    pub fn constraints(&self) -> &[PlannedConstraint] {
+        &self.constraints
+    }
+
+    /// The consumed constraint on `column` with `op`, if any.
+    pub fn find(&self, column: i32, op: ConstraintOperator) -> Option<&PlannedConstraint> {
+        self.constraints
+            .iter()
+            .find(|constraint| constraint.column == column && constraint.op == op)
+    }
+
+    pub fn len(&self) -> usize {
+        self.constraints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.constraints.is_empty()
+    }
+
+    /// Pairs each consumed constraint with its right-hand side from the
+    /// xFilter `values`, failing if there aren't exactly as many values as
+    /// constraints.
+    pub fn bind<'a>(
+        &'a self,
+        values: &'a [*mut sqlite3_value],
+    ) -> Result<impl Iterator<Item = (&'a PlannedConstraint, *mut sqlite3_value)>> {
+        if values.len() != self.constraints.len() {
+            return Err(Error::new_message(format!(
+                "index plan has {} constraints, but xFilter got {} values",
+                self.constraints.len(),
+                values.len()
+            )));
+        }
+        Ok(self
+            .constraints
+            .iter()
+            .map(move |constraint| (constraint, values[constraint.argv_index as usize - 1])))
+    }
+
+    /// The `LIMIT` and `OFFSET` [`IndexPlan::consume_limit`] claimed, from
+    /// the xFilter `values`. Without them, the whole table.
+    pub fn limit_offset(&self, values: &[*mut sqlite3_value]) -> Result<LimitOffset> {
+        let mut limit_offset = LimitOffset::default();
+        for (constraint, value) in self.bind(values)? {
+            match constraint.op {
+                ConstraintOperator::LIMIT => {
+                    limit_offset.limit = u64::try_from(api::value_int64(&value)).ok();
+                }
+                ConstraintOperator::OFFSET => {
+                    limit_offset.offset = u64::try_from(api::value_int64(&value)).unwrap_or(0);
+                }
+                _ => (),
+            }
+        }
+        Ok(limit_offset)
+    }
+}
+
+impl<P: Serialize + DeserializeOwned> IndexPlan<P> {
+    /// Sets idxNum and idxStr to the plan, for xFilter to [`decode`](Self::decode).
+    pub fn apply(&self, info: &mut IndexInfo) -> Result<()> {
+        info.set_idxnum(self.constraints.len() as c_int);
+        info.set_idxstr(&self.to_idx_str()?)
+    }
+
+    /// The plan as the JSON that goes in idxStr.
+    pub fn to_idx_str(&self) -> Result<String> {
+        serde_json::to_string(self)
+            .map_err(|err| Error::new_message(format!("could not serialize index plan: {}", err)))
+    }
+
+    /// Parses a plan from the JSON [`IndexPlan::to_idx_str`] wrote.
+    pub fn from_idx_str(idx_str: &str) -> Result<Self> {
+        let plan: Self = serde_json::from_str(idx_str)
+            .map_err(|err| Error::new_message(format!("invalid index plan in idxStr: {}", err)))?;
+        if plan.version != VERSION {
+            return Err(Error::new_message(format!(
+                "index plan has version {}, expected {}",
+                plan.version, VERSION
+            )));
+        }
+        let in_order = plan
+            .constraints
+            .iter()
+            .enumerate()
+            .all(|(i, constraint)| constraint.argv_index == i as i32 + 1);
+        if !in_order {
+            return Err(Error::new_message(
+                "index plan's constraints are out of argvIndex order",
+            ));
+        }
+        Ok(plan)
+    }
+
+    /// Rebuilds the plan [`IndexPlan::apply`] set, from xFilter's idxNum and
+    /// idxStr, failing if they weren't set by it.
+    pub fn decode(idx_num: c_int, idx_str: Option<&str>) -> Result<Self> {
+        let idx_str =
+            idx_str.ok_or_else(|| Error::new_message("xFilter got no idxStr for an index plan"))?;
+        let plan = Self::from_idx_str(idx_str)?;
+        if plan.constraints.len() != idx_num as usize {
+            return Err(Error::new_message(format!(
+                "index plan has {} constraints, but idxNum is {}",
+                plan.constraints.len(),
+                idx_num
+            )));
+        }
+        Ok(plan)
+    }
+}
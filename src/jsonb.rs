@@ -0,0 +1,324 @@
+//! Decoding of [JSONB](https://sqlite.org/jsonb.html), the binary JSON
+//! format SQLite's `jsonb()` functions return since 3.45.
+//!
+//! Each element is a header, whose low four bits are its type and high
+//! four bits its payload's size (or how many big-endian bytes after the
+//! header hold the size), followed by the payload. Numbers and text are
+//! kept as their JSON (or JSON5) text, and arrays and objects hold their
+//! elements one after another.
+
+use crate::errors::{Error, Result};
+use serde_json::{Map, Number, Value};
+
+const NULL: u8 = 0;
+const TRUE: u8 = 1;
+const FALSE: u8 = 2;
+const INT: u8 = 3;
+const INT5: u8 = 4;
+const FLOAT: u8 = 5;
+const FLOAT5: u8 = 6;
+const TEXT: u8 = 7;
+const TEXTJ: u8 = 8;
+const TEXT5: u8 = 9;
+const TEXTRAW: u8 = 10;
+const ARRAY: u8 = 11;
+const OBJECT: u8 = 12;
+
+/// How deeply arrays and objects can nest, as in SQLite's JSON functions.
+const MAX_DEPTH: usize = 1000;
+
+fn invalid(message: impl std::fmt::Display) -> Error {
+    Error::new_message(format!("invalid JSONB: {}", message))
+}
+
+/// Decodes a JSONB blob, which must be exactly one element, with arrays and
+/// objects nested at most 1000 deep.
+pub fn decode(blob: &[u8]) -> Result<Value> {
+    let (value, rest) = element(blob, 0)?;
+    if !rest.is_empty() {
+        return Err(invalid(format!(
+            "{} bytes after the end of the value",
+            rest.len()
+        )));
+    }
+    Ok(value)
+}
+
+/// Splits the element at the start of `bytes` into its type and payload,
+/// and the bytes after it.
+fn header(bytes: &[u8]) -> Result<(u8, &[u8], &[u8])> {
+    let first = *bytes.first().ok_or_else(|| invalid("unexpected end"))?;
+    let (size, start) = match first >> 4 {
+        size @ 0..=11 => (size as u64, 1),
+        n => {
+            let width = 1usize << (n - 12);
+            let size_bytes = bytes
+                .get(1..1 + width)
+                .ok_or_else(|| invalid("unexpected end of a header"))?;
+            let size = size_bytes
+                .iter()
+                .fold(0u64, |size, byte| (size << 8) | *byte as u64);
+            (size, 1 + width)
+        }
+    };
+    let end = usize::try_from(size)
+        .ok()
+        .and_then(|size| size.checked_add(start))
+        .filter(|end| *end <= bytes.len())
+        .ok_or_else(|| invalid("an element is larger than the blob"))?;
+    Ok((first & 0x0f, &bytes[start..end], &bytes[end..]))
+}
+
+/// Decodes the element at the start of `bytes`, inside `depth` arrays and
+/// objects.
+fn element(bytes: &[u8], depth: usize) -> Result<(Value, &[u8])> {
+    let (kind, payload, rest) = header(bytes)?;
+    if (kind == ARRAY || kind == OBJECT) && depth >= MAX_DEPTH {
+        return Err(invalid(format!("nested more than {} deep", MAX_DEPTH)));
+    }
+    if kind <= FALSE && !payload.is_empty() {
+        return Err(invalid("null, true and false have no payload"));
+    }
+    let value = match kind {
+        NULL => Value::Null,
+        TRUE => Value::Bool(true),
+        FALSE => Value::Bool(false),
+        INT | FLOAT => number(text(payload)?)?,
+        INT5 => int5(text(payload)?)?,
+        FLOAT5 => float5(text(payload)?)?,
+        TEXT | TEXTRAW => Value::String(text(payload)?.to_owned()),
+        TEXTJ | TEXT5 => Value::String(unescape(text(payload)?)?),
+        ARRAY => array(payload, depth + 1)?,
+        OBJECT => object(payload, depth + 1)?,
+        kind => return Err(invalid(format!("unknown element type {}", kind))),
+    };
+    Ok((value, rest))
+}
+
+fn array(mut payload: &[u8], depth: usize) -> Result<Value> {
+    let mut items = Vec::new();
+    while !payload.is_empty() {
+        let (item, after) = element(payload, depth)?;
+        items.push(item);
+        payload = after;
+    }
+    Ok(Value::Array(items))
+}
+
+fn object(mut payload: &[u8], depth: usize) -> Result<Value> {
+    let mut map = Map::new();
+    while !payload.is_empty() {
+        let (key, after) = element(payload, depth)?;
+        let key = match key {
+            Value::String(key) => key,
+            _ => return Err(invalid("an object key isn't text")),
+        };
+        if after.is_empty() {
+            return Err(invalid(format!("object key {:?} has no value", key)));
+        }
+        let (value, after) = element(after, depth)?;
+        map.insert(key, value);
+        payload = after;
+    }
+    Ok(Value::Object(map))
+}
+
+fn text(payload: &[u8]) -> Result<&str> {
+    std::str::from_utf8(payload).map_err(invalid)
+}
+
+/// A number kept as JSON text.
+fn number(text: &str) -> Result<Value> {
+    match serde_json::from_str::<Number>(text) {
+        Ok(number) => Ok(Value::Number(number)),
+        Err(_) => Err(invalid(format!("{:?} isn't a number", text))),
+    }
+}
+
+/// A JSON5 integer: hexadecimal, or with a leading `+`.
+fn int5(text: &str) -> Result<Value> {
+    let (negative, digits) = match text.as_bytes().first() {
+        Some(b'-') => (true, &text[1..]),
+        Some(b'+') => (false, &text[1..]),
+        _ => (false, text),
+    };
+    let magnitude = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => digits.parse::<u64>(),
+    }
+    .map_err(|_| invalid(format!("{:?} isn't an integer", text)))?;
+    let number = if negative {
+        0i64.checked_sub_unsigned(magnitude)
+            .map(Number::from)
+            .unwrap_or_else(|| finite(-(magnitude as f64)))
+    } else {
+        Number::from(magnitude)
+    };
+    Ok(Value::Number(number))
+}
+
+/// A JSON5 float, like `.5`, `5.` or `+Infinity`. JSON has no infinities,
+/// so they're the largest finite number instead, and NaN is null, like
+/// `json()` makes it.
+fn float5(text: &str) -> Result<Value> {
+    let unsigned = text.trim_start_matches(['+', '-']);
+    let negative = text.starts_with('-');
+    if unsigned == "NaN" {
+        return Ok(Value::Null);
+    }
+    let value = if unsigned == "Infinity" {
+        f64::INFINITY
+    } else {
+        unsigned
+            .parse::<f64>()
+            .map_err(|_| invalid(format!("{:?} isn't a number", text)))?
+    };
+    Ok(Value::Number(finite(if negative { -value } else { value })))
+}
+
+fn finite(value: f64) -> Number {
+    Number::from_f64(value.clamp(f64::MIN, f64::MAX)).expect("a finite number")
+}
+
+/// Unescapes text with JSON or JSON5 escapes.
+fn unescape(text: &str) -> Result<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        let escape = chars.next().ok_or_else(|| invalid("text ends in \\"))?;
+        match escape {
+            '"' | '\\' | '/' | '\'' => out.push(escape),
+            'b' => out.push('\u{8}'),
+            'f' => out.push('\u{c}'),
+            'n' => out.push('\n'),
+            'r' => out.push('\r'),
+            't' => out.push('\t'),
+            'v' => out.push('\u{b}'),
+            '0' => out.push('\0'),
+            'x' => out.push(hex_char(&mut chars, 2)?),
+            'u' => {
+                let high = hex_code(&mut chars, 4)?;
+                let code = if (0xd800..0xdc00).contains(&high) {
+                    // a surrogate pair, as JSON writes characters outside
+                    // the Basic Multilingual Plane
+                    if chars.next() != Some('\\') || chars.next() != Some('u') {
+                        return Err(invalid("unpaired surrogate in \\u escape"));
+                    }
+                    let low = hex_code(&mut chars, 4)?;
+                    0x10000 + ((high - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff)
+                } else {
+                    high
+                };
+                out.push(char::from_u32(code).ok_or_else(|| invalid("invalid \\u escape"))?);
+            }
+            // JSON5 line continuations
+            '\n' | '\u{2028}' | '\u{2029}' => {}
+            '\r' => {
+                if chars.clone().next() == Some('\n') {
+                    chars.next();
+                }
+            }
+            other => return Err(invalid(format!("unknown escape \\{}", other))),
+        }
+    }
+    Ok(out)
+}
+
+fn hex_code(chars: &mut std::str::Chars, digits: usize) -> Result<u32> {
+    let hex: String = chars.by_ref().take(digits).collect();
+    if hex.len() != digits {
+        return Err(invalid("text ends inside an escape"));
+    }
+    u32::from_str_radix(&hex, 16).map_err(|_| invalid(format!("invalid escape digits {:?}", hex)))
+}
+
+fn hex_char(chars: &mut std::str::Chars, digits: usize) -> Result<char> {
+    let code = hex_code(chars, digits)?;
+    char::from_u32(code).ok_or_else(|| invalid("invalid \\x escape"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::jsonb::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_decode() {
+        // jsonb('{"a":[1,2.5,true,null],"b":"x"}')
+        let blob = [
+            0xcc, 0x0f, 0x17, 0x61, 0x8b, 0x13, 0x31, 0x35, 0x32, 0x2e, 0x35, 0x01, 0x00, 0x17,
+            0x62, 0x17, 0x78,
+        ];
+        assert_eq!(
+            decode(&blob).unwrap(),
+            json!({"a": [1, 2.5, true, null], "b": "x"})
+        );
+        // a two-byte size
+        let long = "x".repeat(300);
+        let mut blob = vec![0xd7, 0x01, 0x2c];
+        blob.extend(long.as_bytes());
+        assert_eq!(decode(&blob).unwrap(), json!(long));
+    }
+
+    #[test]
+    fn test_decode_json5() {
+        assert_eq!(decode(b"\x44\x30\x78\x31\x46").unwrap(), json!(31));
+        assert_eq!(decode(b"\x26.5").unwrap(), json!(0.5));
+        assert_eq!(decode(b"\x36NaN").unwrap(), json!(null));
+        assert_eq!(decode(b"\x88\\u00e9\\n").unwrap(), json!("é\n"));
+        assert_eq!(decode(b"\x89\\x41\\'\\\n").unwrap(), json!("A'"));
+        assert_eq!(decode(b"\xc8\x0c\\ud83d\\ude00").unwrap(), json!("😀"));
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        assert!(decode(b"").is_err());
+        assert!(decode(b"\x57ab").is_err());
+        assert!(decode(b"\x00\x00").is_err());
+        assert!(decode(b"\x0d").is_err());
+        assert!(decode(b"\x11x").is_err());
+        assert!(decode(b"\x2c\x13\x31").is_err());
+    }
+
+    /// `depth` arrays, one in another, each with a four-byte size.
+    fn nested(depth: usize) -> Vec<u8> {
+        let mut blob = Vec::new();
+        for level in 1..depth {
+            let size = ((depth - level) * 5 - 4) as u32;
+            blob.push(0xeb);
+            blob.extend(size.to_be_bytes());
+        }
+        blob.push(0x0b);
+        blob
+    }
+
+    #[test]
+    fn test_decode_depth() {
+        // a main thread's stack, since debug builds take a few kilobytes a
+        // level, more than a test thread has
+        let test = std::thread::Builder::new().stack_size(8 << 20).spawn(|| {
+            let value = decode(&nested(MAX_DEPTH)).unwrap();
+            let mut inner = &value;
+            for _ in 0..MAX_DEPTH - 1 {
+                inner = &inner[0];
+            }
+            assert_eq!(inner, &json!([]));
+            assert_eq!(
+                decode(&nested(MAX_DEPTH + 1))
+                    .unwrap_err()
+                    .result_error_message(),
+                "invalid JSONB: nested more than 1000 deep"
+            );
+            // far deeper than the stack could recurse
+            assert!(decode(&nested(100_000)).is_err());
+        });
+        test.unwrap().join().unwrap();
+    }
+}
@@ -0,0 +1,48 @@
+//! SQL keywords and statement completeness, for extensions that generate
+//! or check SQL, like schema builders or linters.
+//!
+//! These ask the SQLite library that loaded the extension, so the keyword
+//! list matches that version and its compile-time options.
+
+use crate::ext::{
+    sqlite3ext_complete, sqlite3ext_keyword_check, sqlite3ext_keyword_count,
+    sqlite3ext_keyword_name,
+};
+use std::{
+    ffi::CString,
+    os::raw::{c_char, c_int},
+};
+
+/// Whether `word` is an SQL keyword, in any case, like "select" or "NATURAL".
+/// Keywords need quoting to be used as identifiers.
+pub fn is_keyword(word: &str) -> bool {
+    match c_int::try_from(word.len()) {
+        Ok(n) => unsafe { sqlite3ext_keyword_check(word.as_ptr().cast::<c_char>(), n) != 0 },
+        Err(_) => false,
+    }
+}
+
+/// Every SQL keyword, in upper case.
+pub fn keywords() -> impl Iterator<Item = &'static str> {
+    let count = unsafe { sqlite3ext_keyword_count() };
+    (0..count).filter_map(|i| {
+        let mut name: *const c_char = std::ptr::null();
+        let mut n: c_int = 0;
+        // the names live in a static table, and aren't nul-terminated
+        if unsafe { sqlite3ext_keyword_name(i, &mut name, &mut n) } != 0 || name.is_null() {
+            return None;
+        }
+        let bytes = unsafe { std::slice::from_raw_parts(name.cast::<u8>(), n as usize) };
+        std::str::from_utf8(bytes).ok()
+    })
+}
+
+/// Whether `sql` ends with a complete statement, so a semicolon that isn't
+/// inside a string, comment or trigger body. Only the tokens are checked,
+/// not whether the statement is valid.
+pub fn is_complete(sql: &str) -> bool {
+    match CString::new(sql) {
+        Ok(sql) => unsafe { sqlite3ext_complete(sql.as_ptr()) != 0 },
+        Err(_) => false,
+    }
+}
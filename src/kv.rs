@@ -0,0 +1,589 @@
+//! A writable virtual table over any key-value store.
+//!
+//! Implement [`KvStore`] for a backend, like a sled tree, a redb table or a
+//! map behind a lock, and [`define_kv_module`] makes it a virtual table
+//! module with `key` and `value` columns:
+//!
+//! ```sql
+//! create virtual table settings using kv();
+//! insert into settings values ('theme', 'dark');
+//! select value from settings where key = 'theme';
+//! select * from settings where key glob 'user.*';
+//! ```
+//!
+//! Keys and values are stored as bytes. They read back as TEXT when they're
+//! valid UTF-8 and as BLOBs otherwise. `key = ?` is a single [`KvStore::get`],
+//! and a `key GLOB 'prefix*'` only scans keys starting with the prefix.
+//! [`define_buffered_kv_module`] holds a transaction's writes in a
+//! [`WriteBuffer`] and only writes them to the store when it commits. It
+//! undoes them back to a savepoint on `ROLLBACK TO`, and to the start of a
+//! statement that fails partway through.
+//! Inserting a key that's already set fails like a UNIQUE constraint unless
+//! the statement says `INSERT OR REPLACE` or `INSERT OR IGNORE`, and a NULL
+//! value is stored as empty. Every table created with the module shares the one
+//! store.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api::{self, ValueType};
+use crate::errors::{Error, Result};
+use crate::ext::{sqlite3, sqlite3_context, sqlite3_value, sqlite3_vtab, sqlite3_vtab_cursor};
+use crate::table::{
+    define_virtual_table_writeable, define_virtual_table_writeable_with_nested_transactions,
+    vtab_config, BestIndexError, ConflictMode, ConstraintOperator, IndexInfo, UpdateOperation,
+    VTab, VTabArguments, VTabConfig, VTabCursor, VTabWriteable, VTabWriteableNestedTransactions,
+    VTabWriteableWithTransactions,
+};
+use std::collections::BTreeMap;
+use std::iter::Peekable;
+use std::os::raw::c_int;
+use std::sync::{Arc, Mutex};
+
+/// One key and its value.
+pub type KvEntry = (Vec<u8>, Vec<u8>);
+
+/// A key-value store backing a [`define_kv_module`] virtual table. Methods
+/// take `&self`, stores that need `&mut` to write can use a lock.
+pub trait KvStore {
+    /// The value of `key`, if it's set.
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
+    /// Sets `key` to `value`, replacing any value it had.
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<()>;
+    /// Removes `key`, if it's set.
+    fn delete(&self, key: &[u8]) -> Result<()>;
+    /// Every entry whose key starts with `prefix`, all of them for an empty
+    /// prefix. Entries are expected in key order, but don't have to be.
+    fn scan_prefix<'a>(
+        &'a self,
+        prefix: &[u8],
+    ) -> Result<Box<dyn Iterator<Item = Result<KvEntry>> + 'a>>;
+}
+
+/// An in-memory [`KvStore`], for tests and small caches.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    entries: Mutex<BTreeMap<Vec<u8>, Vec<u8>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn entries(&self) -> Result<std::sync::MutexGuard<'_, BTreeMap<Vec<u8>, Vec<u8>>>> {
+        self.entries
+            .lock()
+            .map_err(|_| Error::new_message("the store's lock is poisoned"))
+    }
+}
+
+impl KvStore for MemoryStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.entries()?.get(key).cloned())
+    }
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.entries()?.insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+    fn delete(&self, key: &[u8]) -> Result<()> {
+        self.entries()?.remove(key);
+        Ok(())
+    }
+    fn scan_prefix<'a>(
+        &'a self,
+        prefix: &[u8],
+    ) -> Result<Box<dyn Iterator<Item = Result<KvEntry>> + 'a>> {
+        // the lock can't be held while SQLite steps the cursor, so copy
+        let entries: Vec<Result<KvEntry>> = self
+            .entries()?
+            .range(prefix.to_vec()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| Ok((key.clone(), value.clone())))
+            .collect();
+        Ok(Box::new(entries.into_iter()))
+    }
+}
+
+impl<S: KvStore> KvStore for Arc<S> {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        S::get(self, key)
+    }
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        S::put(self, key, value)
+    }
+    fn delete(&self, key: &[u8]) -> Result<()> {
+        S::delete(self, key)
+    }
+    fn scan_prefix<'a>(
+        &'a self,
+        prefix: &[u8],
+    ) -> Result<Box<dyn Iterator<Item = Result<KvEntry>> + 'a>> {
+        S::scan_prefix(self, prefix)
+    }
+}
+
+/// Changes a [`WriteBuffer`] holds, None for a deleted key.
+type Pending = BTreeMap<Vec<u8>, Option<Vec<u8>>>;
+
+/// A [`KvStore`] that holds writes until they're flushed to the store it
+/// wraps. Reads see the held writes over the store's entries, so a
+/// transaction reads back what it wrote before it commits.
+#[derive(Debug, Default)]
+pub struct WriteBuffer<S: KvStore> {
+    store: S,
+    pending: Mutex<Pending>,
+    /// The held changes at each open savepoint, by its id.
+    savepoints: Mutex<Vec<(c_int, Pending)>>,
+}
+
+impl<S: KvStore> WriteBuffer<S> {
+    pub fn new(store: S) -> Self {
+        WriteBuffer {
+            store,
+            pending: Mutex::new(BTreeMap::new()),
+            savepoints: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The wrapped store, without the writes that haven't been flushed.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// How many keys have changes that haven't been flushed.
+    pub fn pending(&self) -> Result<usize> {
+        Ok(self.lock()?.len())
+    }
+
+    /// Writes the held changes to the store, in key order. If one fails, it
+    /// and the ones after it stay held, the ones before it are written.
+    pub fn flush(&self) -> Result<()> {
+        let mut pending = self.lock()?;
+        while let Some((key, value)) = pending.pop_first() {
+            let written = match &value {
+                Some(value) => self.store.put(&key, value),
+                None => self.store.delete(&key),
+            };
+            if let Err(err) = written {
+                pending.insert(key, value);
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops the held changes and the savepoints.
+    pub fn discard(&self) -> Result<()> {
+        self.lock()?.clear();
+        self.lock_savepoints()?.clear();
+        Ok(())
+    }
+
+    /// Starts the savepoint `id`, which keeps a copy of the held changes,
+    /// ending it and any after it if they're open.
+    pub fn savepoint(&self, id: c_int) -> Result<()> {
+        let pending = self.lock()?.clone();
+        let mut savepoints = self.lock_savepoints()?;
+        savepoints.retain(|(open, _)| *open < id);
+        savepoints.push((id, pending));
+        Ok(())
+    }
+
+    /// Ends the savepoint `id` and any after it, keeping the changes made
+    /// since.
+    pub fn release(&self, id: c_int) -> Result<()> {
+        self.lock_savepoints()?.retain(|(open, _)| *open < id);
+        Ok(())
+    }
+
+    /// Puts the held changes back to what they were at the savepoint `id`,
+    /// which stays open, and ends any after it. Without an open savepoint
+    /// at or before `id`, every held change is dropped.
+    pub fn rollback_to(&self, id: c_int) -> Result<()> {
+        let mut savepoints = self.lock_savepoints()?;
+        savepoints.retain(|(open, _)| *open <= id);
+        *self.lock()? = match savepoints.last() {
+            Some((_, pending)) => pending.clone(),
+            None => BTreeMap::new(),
+        };
+        Ok(())
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, Pending>> {
+        self.pending
+            .lock()
+            .map_err(|_| Error::new_message("the write buffer's lock is poisoned"))
+    }
+
+    fn lock_savepoints(&self) -> Result<std::sync::MutexGuard<'_, Vec<(c_int, Pending)>>> {
+        self.savepoints
+            .lock()
+            .map_err(|_| Error::new_message("the write buffer's lock is poisoned"))
+    }
+}
+
+impl<S: KvStore> KvStore for WriteBuffer<S> {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        match self.lock()?.get(key) {
+            Some(change) => Ok(change.clone()),
+            None => self.store.get(key),
+        }
+    }
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.lock()?.insert(key.to_vec(), Some(value.to_vec()));
+        Ok(())
+    }
+    fn delete(&self, key: &[u8]) -> Result<()> {
+        self.lock()?.insert(key.to_vec(), None);
+        Ok(())
+    }
+    fn scan_prefix<'a>(
+        &'a self,
+        prefix: &[u8],
+    ) -> Result<Box<dyn Iterator<Item = Result<KvEntry>> + 'a>> {
+        // the changes as of the start of the scan, so writes made while
+        // it's stepped don't show up halfway through
+        let changes: Vec<(Vec<u8>, Option<Vec<u8>>)> = self
+            .lock()?
+            .range(prefix.to_vec()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        Ok(Box::new(Overlay {
+            entries: self.store.scan_prefix(prefix)?.peekable(),
+            changes,
+            next_change: 0,
+        }))
+    }
+}
+
+/// A [`WriteBuffer`] scan: the store's entries merged with the changes to
+/// them, in key order when the store scans in key order.
+struct Overlay<'a> {
+    entries: Peekable<Box<dyn Iterator<Item = Result<KvEntry>> + 'a>>,
+    changes: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+    next_change: usize,
+}
+
+impl Overlay<'_> {
+    fn changed(&self, key: &[u8]) -> bool {
+        self.changes
+            .binary_search_by(|(changed, _)| changed.as_slice().cmp(key))
+            .is_ok()
+    }
+}
+
+impl Iterator for Overlay<'_> {
+    type Item = Result<KvEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let change_first = match (self.changes.get(self.next_change), self.entries.peek()) {
+                (Some((changed, _)), Some(Ok((key, _)))) => changed <= key,
+                (Some(_), None) => true,
+                _ => false,
+            };
+            if change_first {
+                let (key, value) = &mut self.changes[self.next_change];
+                self.next_change += 1;
+                match value.take() {
+                    // the key stays, it still hides the store's entry
+                    Some(value) => return Some(Ok((key.clone(), value))),
+                    None => continue,
+                }
+            }
+            match self.entries.next()? {
+                Ok((key, _)) if self.changed(&key) => continue,
+                entry => return Some(entry),
+            }
+        }
+    }
+}
+
+/// Defines the `name` virtual table module over `store`.
+pub fn define_kv_module<S: KvStore + 'static>(
+    db: *mut sqlite3,
+    name: &str,
+    store: S,
+) -> Result<()> {
+    define_virtual_table_writeable::<KvTable<S>>(db, name, Some(Arc::new(store)))
+}
+
+/// Defines the `name` virtual table module over `store` like
+/// [`define_kv_module`], but with writes held in a [`WriteBuffer`] until
+/// the transaction commits, and dropped if it or a savepoint rolls back,
+/// or if the statement that made them fails. Writing them isn't atomic: if the store fails partway, the commit fails, but the
+/// writes before the failure stay in the store. The buffer is shared by
+/// every table created with the module, so define it on each connection
+/// that uses the store rather than sharing one definition.
+pub fn define_buffered_kv_module<S: KvStore + 'static>(
+    db: *mut sqlite3,
+    name: &str,
+    store: S,
+) -> Result<()> {
+    define_virtual_table_writeable_with_nested_transactions::<KvTable<WriteBuffer<S>>>(
+        db,
+        name,
+        Some(Arc::new(WriteBuffer::new(store))),
+    )
+}
+
+const COLUMN_KEY: c_int = 0;
+const COLUMN_VALUE: c_int = 1;
+
+/// xBestIndex plans, passed to xFilter as idxNum.
+const PLAN_SCAN: c_int = 0;
+const PLAN_KEY: c_int = 1;
+const PLAN_GLOB: c_int = 2;
+
+#[repr(C)]
+pub struct KvTable<S: KvStore> {
+    /// must be first
+    base: sqlite3_vtab,
+    store: Arc<S>,
+}
+
+impl<'vtab, S: KvStore + 'vtab> VTab<'vtab> for KvTable<S> {
+    type Aux = Arc<S>;
+    type Cursor = KvCursor<'vtab, S>;
+
+    fn connect(
+        db: *mut sqlite3,
+        aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, KvTable<S>)> {
+        let store = aux
+            .ok_or_else(|| Error::new_message("the kv module was defined without a store"))?
+            .clone();
+        vtab_config(db, VTabConfig::ConstraintSupport)?;
+        let base: sqlite3_vtab = unsafe { std::mem::zeroed() };
+        Ok((
+            "CREATE TABLE x(key PRIMARY KEY NOT NULL, value) WITHOUT ROWID".to_owned(),
+            KvTable { base, store },
+        ))
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        let constraints = info.constraints();
+        let on_key = |op: ConstraintOperator| {
+            constraints.iter().position(|constraint| {
+                constraint.usable()
+                    && constraint.column_idx() == COLUMN_KEY
+                    && constraint.op() == Some(op)
+            })
+        };
+        // SQLite still checks a GLOB's whole pattern, only its prefix is used
+        let (plan, used) = match (
+            on_key(ConstraintOperator::EQ),
+            on_key(ConstraintOperator::GLOB),
+        ) {
+            (Some(i), _) => (PLAN_KEY, Some(i)),
+            (None, Some(i)) => (PLAN_GLOB, Some(i)),
+            (None, None) => (PLAN_SCAN, None),
+        };
+        for (i, mut constraint) in constraints.into_iter().enumerate() {
+            if Some(i) == used {
+                constraint.set_argv_index(1);
+                constraint.set_omit(plan == PLAN_KEY);
+            }
+        }
+        match plan {
+            PLAN_KEY => {
+                info.set_estimated_cost(1.0);
+                info.set_estimated_rows(1);
+            }
+            PLAN_GLOB => {
+                info.set_estimated_cost(1000.0);
+                info.set_estimated_rows(100);
+            }
+            _ => {
+                info.set_estimated_cost(100_000.0);
+                info.set_estimated_rows(10_000);
+            }
+        }
+        info.set_idxnum(plan);
+        Ok(())
+    }
+
+    fn open(&'vtab mut self) -> Result<KvCursor<'vtab, S>> {
+        Ok(KvCursor::new(&self.store))
+    }
+}
+
+impl<S: KvStore> KvTable<S> {
+    /// Fails if `key` is already set, unless the statement replaces it.
+    fn check_unique(&self, key: &[u8], on_conflict: ConflictMode) -> Result<()> {
+        if on_conflict != ConflictMode::Replace && self.store.get(key)?.is_some() {
+            return Err(Error::constraint("UNIQUE constraint failed: key"));
+        }
+        Ok(())
+    }
+}
+
+fn non_null_key(key: &*mut sqlite3_value) -> Result<&[u8]> {
+    if api::value_type(key) == ValueType::Null {
+        return Err(Error::constraint("a key can't be NULL"));
+    }
+    Ok(api::value_blob(key))
+}
+
+impl<'vtab, S: KvStore + 'vtab> VTabWriteable<'vtab> for KvTable<S> {
+    fn update(&'vtab mut self, operation: UpdateOperation, _p_rowid: *mut i64) -> Result<()> {
+        match operation {
+            UpdateOperation::Delete(key) => self.store.delete(api::value_blob(key)),
+            UpdateOperation::Insert {
+                values,
+                on_conflict,
+                ..
+            } => {
+                let key = non_null_key(&values[COLUMN_KEY as usize])?;
+                self.check_unique(key, on_conflict)?;
+                let value = api::value_blob(&values[COLUMN_VALUE as usize]);
+                self.store.put(key, value)
+            }
+            UpdateOperation::Update {
+                rowid,
+                new_rowid,
+                values,
+                on_conflict,
+            } => {
+                let key = non_null_key(new_rowid)?;
+                let old_key = api::value_blob(rowid);
+                if old_key != key {
+                    self.check_unique(key, on_conflict)?;
+                    self.store.delete(old_key)?;
+                }
+                self.store
+                    .put(key, api::value_blob(&values[COLUMN_VALUE as usize]))
+            }
+        }
+    }
+}
+
+impl<'vtab, S: KvStore + 'vtab> VTabWriteableWithTransactions<'vtab> for KvTable<WriteBuffer<S>> {
+    fn begin(&'vtab mut self) -> Result<()> {
+        Ok(())
+    }
+    // flushing before the commit makes a failed write fail the commit, so
+    // SQLite rolls back and the changes still held are discarded. The ones
+    // flushed before the failure stay in the store, which can't undo them.
+    fn sync(&'vtab mut self) -> Result<()> {
+        self.store.flush()
+    }
+    fn commit(&'vtab mut self) -> Result<()> {
+        self.store.release(0)
+    }
+    fn rollback(&'vtab mut self) -> Result<()> {
+        self.store.discard()
+    }
+}
+
+// SQLite makes a savepoint before each statement in a transaction, so
+// these also undo the writes of a statement that fails
+impl<'vtab, S: KvStore + 'vtab> VTabWriteableNestedTransactions<'vtab> for KvTable<WriteBuffer<S>> {
+    fn savepoint(&'vtab mut self, id: c_int) -> Result<()> {
+        self.store.savepoint(id)
+    }
+    fn release(&'vtab mut self, id: c_int) -> Result<()> {
+        self.store.release(id)
+    }
+    fn rollback_to(&'vtab mut self, id: c_int) -> Result<()> {
+        self.store.rollback_to(id)
+    }
+}
+
+#[repr(C)]
+pub struct KvCursor<'vtab, S: KvStore> {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    store: &'vtab S,
+    entries: Box<dyn Iterator<Item = Result<KvEntry>> + 'vtab>,
+    current: Option<KvEntry>,
+    rowid: i64,
+}
+
+impl<'vtab, S: KvStore> KvCursor<'vtab, S> {
+    fn new(store: &'vtab S) -> KvCursor<'vtab, S> {
+        let base: sqlite3_vtab_cursor = unsafe { std::mem::zeroed() };
+        KvCursor {
+            base,
+            store,
+            entries: Box::new(std::iter::empty()),
+            current: None,
+            rowid: 0,
+        }
+    }
+}
+
+/// The literal start of a GLOB pattern, before its first wildcard.
+fn glob_prefix(pattern: &[u8]) -> &[u8] {
+    let end = pattern
+        .iter()
+        .position(|c| matches!(c, b'*' | b'?' | b'['))
+        .unwrap_or(pattern.len());
+    &pattern[..end]
+}
+
+fn result_bytes(context: *mut sqlite3_context, bytes: &[u8]) -> Result<()> {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => api::result_text(context, text),
+        Err(_) => {
+            api::result_blob(context, bytes);
+            Ok(())
+        }
+    }
+}
+
+impl<S: KvStore> VTabCursor for KvCursor<'_, S> {
+    fn filter(
+        &mut self,
+        idx_num: c_int,
+        _idx_str: Option<&str>,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.rowid = 0;
+        self.entries = match idx_num {
+            PLAN_KEY => {
+                // a NULL key never matches
+                if api::value_type(&values[0]) == ValueType::Null {
+                    Box::new(std::iter::empty())
+                } else {
+                    let key = api::value_blob(&values[0]).to_vec();
+                    let entry = self.store.get(&key)?.map(|value| Ok((key, value)));
+                    Box::new(entry.into_iter())
+                }
+            }
+            PLAN_GLOB => self
+                .store
+                .scan_prefix(glob_prefix(api::value_blob(&values[0])))?,
+            _ => self.store.scan_prefix(&[])?,
+        };
+        self.current = self.entries.next().transpose()?;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.rowid += 1;
+        self.current = self.entries.next().transpose()?;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.current.is_none()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, i: c_int) -> Result<()> {
+        let (key, value) = match &self.current {
+            Some(entry) => entry,
+            None => return Ok(()),
+        };
+        match i {
+            COLUMN_KEY => result_bytes(context, key),
+            COLUMN_VALUE => result_bytes(context, value),
+            _ => Ok(()),
+        }
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.rowid)
+    }
+}
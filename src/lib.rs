@@ -1,33 +1,119 @@
 #![doc = include_str!("../README.md")]
 #![allow(clippy::not_unsafe_ptr_arg_deref)]
 
+pub mod aggregate;
+pub mod alias;
 pub mod api;
+pub mod args;
+pub mod blob;
+pub mod bulk;
+pub mod cache;
+pub mod changes;
+pub mod clock;
 pub mod collation;
+#[cfg(feature = "collations")]
+pub mod collations;
+pub mod compare;
 mod constants;
+pub mod csv;
+pub mod cursor_debug;
+pub mod database;
+pub mod degrade;
+pub mod docs;
+pub mod duration;
 pub mod entrypoints;
 pub mod errors;
 
 #[cfg(feature = "exec")]
 pub mod exec;
 pub mod ext; // TODO dont expose
+pub mod function_constraints;
+pub mod generation;
+pub mod geo;
+pub mod histogram;
+pub mod hooks;
+pub mod index_plan;
+pub mod jsonb;
+pub mod keywords;
+pub mod kv;
+#[cfg(feature = "testing")]
+pub mod load_test;
+pub mod materialize;
+pub mod migration;
+pub mod pagination;
+pub mod params;
+#[cfg(feature = "static")]
+pub mod pcache;
+pub mod pool;
 pub mod prelude;
+pub mod primary_key;
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
+pub mod pure;
+pub mod random;
+pub mod record;
+pub mod residual;
+pub mod rtree;
 pub mod scalar;
+pub mod scan;
+pub mod schema;
+#[cfg(feature = "script")]
+pub mod script;
+pub mod sql_pretty;
+mod statement;
+pub mod static_table;
+pub mod stream;
+pub mod subtype;
 pub mod table;
+#[cfg(feature = "tarfile")]
+pub mod tarfile;
+pub mod temp;
+#[cfg(feature = "testing")]
+pub mod test_control;
+#[cfg(all(feature = "testing", not(feature = "static")))]
+pub mod testing;
+pub mod timeout;
+pub mod trace;
+pub mod ttl;
+#[cfg(feature = "unicode")]
+pub mod unicode;
 pub mod vtab_argparse;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(any(feature = "xml", feature = "html"))]
+pub mod xml;
+#[cfg(feature = "zipfile")]
+pub mod zipfile;
 
 #[doc(inline)]
-pub use errors::{Error, ErrorKind, Result};
+pub use args::{Args, Arity, FromArgs, FromValue, ToResult};
 
 #[doc(inline)]
-pub use scalar::{define_scalar_function, define_scalar_function_with_aux, FunctionFlags};
+pub use errors::{Error, ErrorKind, Result, SqliteMessage};
 
 #[doc(inline)]
-pub use collation::define_collation;
+pub use scalar::{
+    define_scalar_function, define_scalar_function_variadic, define_scalar_function_with_aux,
+    FunctionFlags,
+};
+
+#[doc(inline)]
+pub use pure::define_pure_function;
+
+#[doc(inline)]
+pub use aggregate::{
+    define_aggregate_function, define_window_function, Aggregate, WindowAggregate,
+};
+
+#[doc(inline)]
+pub use collation::{define_collation, define_collation_with_aux, on_collation_needed};
 
 #[doc(inline)]
 pub use table::{
     define_table_function, define_virtual_table, define_virtual_table_with_find,
-    define_virtual_table_writeable, define_virtual_table_writeablex, BestIndexError,
+    define_virtual_table_writeable, define_virtual_table_writeable_with_nested_transactions,
+    define_virtual_table_writeable_with_transactions, define_virtual_table_writeablex,
+    BestIndexError,
 };
 
 pub use constants::*;
@@ -0,0 +1,297 @@
+//! End-to-end tests of an extension compiled to a loadable library.
+//!
+//! The other testing tools run an extension's entrypoint inside the test
+//! binary. [`CompiledExtension`] builds one of a crate's examples as a
+//! cdylib, finds the library cargo wrote, and loads it like a user would:
+//! into a connection with `load_extension()`, or into a spawned `sqlite3`
+//! CLI with [`CompiledExtension::run_cli`]. [`CompiledExtension::run_python`]
+//! and [`CompiledExtension::run_node`] generate a script that loads it with
+//! Python's `sqlite3` module or Node's `better-sqlite3` and runs
+//! [`SmokeCheck`]s, to catch an extension that only loads into some hosts.
+//!
+//! ```ignore
+//! let hello = CompiledExtension::build_example(env!("CARGO_MANIFEST_DIR"), "hello")?;
+//! assert_eq!(hello.run_cli("select hello('alex');")?, "hello, alex!\n");
+//!
+//! let db = rusqlite::Connection::open_in_memory()?;
+//! unsafe {
+//!     let _guard = rusqlite::LoadExtensionGuard::new(&db)?;
+//!     db.load_extension(hello.path(), None)?;
+//! }
+//!
+//! hello.run_python(&[SmokeCheck::new("select hello('alex')", "hello, alex!")])?;
+//! ```
+//!
+//! Examples need `crate-type = ["cdylib"]` in their `[[example]]` section.
+//! Building runs the `cargo` that's running the tests, or the one on the
+//! PATH, in the same target directory.
+
+use crate::errors::{Error, Result};
+use serde_json::Value;
+use std::env;
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// A loadable extension library on disk.
+#[derive(Debug, Clone)]
+pub struct CompiledExtension {
+    path: PathBuf,
+    entrypoint: Option<String>,
+}
+
+impl CompiledExtension {
+    /// Runs `cargo build --example name` for the crate in `manifest_dir`
+    /// and returns the cdylib it built.
+    pub fn build_example(manifest_dir: impl AsRef<Path>, name: &str) -> Result<Self> {
+        let manifest = manifest_dir.as_ref().join("Cargo.toml");
+        let cargo = env::var_os("CARGO").unwrap_or_else(|| OsString::from("cargo"));
+        let output = Command::new(cargo)
+            .arg("build")
+            .arg("--manifest-path")
+            .arg(&manifest)
+            .args([
+                "--example",
+                name,
+                "--message-format=json-render-diagnostics",
+            ])
+            .output()
+            .map_err(|err| Error::new_message(format!("could not run cargo: {}", err)))?;
+        if !output.status.success() {
+            return Err(Error::new_message(format!(
+                "cargo build --example {} failed: {}",
+                name,
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            )));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        stdout
+            .lines()
+            .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+            .filter(|message| {
+                message["reason"] == "compiler-artifact" && message["target"]["name"] == name
+            })
+            .flat_map(|message| match &message["filenames"] {
+                Value::Array(filenames) => filenames.clone(),
+                _ => vec![],
+            })
+            .filter_map(|filename| filename.as_str().map(PathBuf::from))
+            .find(|path| path.to_string_lossy().ends_with(env::consts::DLL_SUFFIX))
+            .map(CompiledExtension::from_path)
+            .ok_or_else(|| {
+                Error::new_message(format!(
+                    "example {} didn't build a {} library, is its crate-type cdylib?",
+                    name,
+                    env::consts::DLL_SUFFIX
+                ))
+            })
+    }
+
+    /// An already compiled library.
+    pub fn from_path(path: impl Into<PathBuf>) -> Self {
+        CompiledExtension {
+            path: path.into(),
+            entrypoint: None,
+        }
+    }
+
+    /// Loads the library with the given entrypoint, instead of the one
+    /// SQLite derives from its file name.
+    pub fn with_entrypoint(mut self, entrypoint: &str) -> Self {
+        self.entrypoint = Some(entrypoint.to_owned());
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn entrypoint(&self) -> Option<&str> {
+        self.entrypoint.as_deref()
+    }
+
+    /// Runs `sql` in a `sqlite3` CLI on an in-memory database with the
+    /// extension loaded, and returns what it printed. The CLI is the one
+    /// the `SQLITE3` environment variable names, or `sqlite3` on the PATH.
+    /// Fails on the first statement that does.
+    pub fn run_cli(&self, sql: &str) -> Result<String> {
+        // single quoted dot-command arguments don't treat \ as an escape
+        let script = format!(
+            ".load '{}' {}\n{}\n",
+            self.path.to_string_lossy(),
+            self.entrypoint.as_deref().unwrap_or(""),
+            sql
+        );
+        run_script(
+            program("SQLITE3", "sqlite3"),
+            &["-batch", "-bail", ":memory:"],
+            &script,
+        )
+    }
+
+    /// A Python script that loads the extension into an in-memory
+    /// `sqlite3` connection and runs `checks`, exiting with an error on the
+    /// first that fails.
+    pub fn python_script(&self, checks: &[SmokeCheck]) -> String {
+        format!(
+            r#"import json, sqlite3, sys
+
+config = json.loads({})
+db = sqlite3.connect(":memory:")
+db.enable_load_extension(True)
+db.execute("select load_extension(?, ?)", (config["path"], config["entrypoint"]))
+for sql, expected in config["checks"]:
+    actual = db.execute(sql).fetchone()[0]
+    if actual != expected:
+        sys.exit(f"{{sql}}: expected {{expected!r}}, got {{actual!r}}")
+"#,
+            self.script_config(checks)
+        )
+    }
+
+    /// A Node script that does what [`CompiledExtension::python_script`]
+    /// does with `better-sqlite3`.
+    pub fn node_script(&self, checks: &[SmokeCheck]) -> String {
+        format!(
+            r#"const Database = require("better-sqlite3");
+
+const config = JSON.parse({});
+const db = new Database(":memory:");
+if (config.entrypoint === null) db.loadExtension(config.path);
+else db.loadExtension(config.path, config.entrypoint);
+for (const [sql, expected] of config.checks) {{
+  const actual = db.prepare(sql).pluck().get();
+  if (JSON.stringify(actual) !== JSON.stringify(expected)) {{
+    console.error(`${{sql}}: expected ${{JSON.stringify(expected)}}, got ${{JSON.stringify(actual)}}`);
+    process.exit(1);
+  }}
+}}
+"#,
+            self.script_config(checks)
+        )
+    }
+
+    /// Runs [`CompiledExtension::python_script`] with the Python the
+    /// `PYTHON` environment variable names, or `python3` on the PATH.
+    pub fn run_python(&self, checks: &[SmokeCheck]) -> Result<()> {
+        run_script(
+            program("PYTHON", "python3"),
+            &["-"],
+            &self.python_script(checks),
+        )
+        .map(|_| ())
+    }
+
+    /// Runs [`CompiledExtension::node_script`] with the Node the `NODE`
+    /// environment variable names, or `node` on the PATH. `better-sqlite3`
+    /// has to be installed where Node finds it from the current directory,
+    /// or in `NODE_PATH`.
+    pub fn run_node(&self, checks: &[SmokeCheck]) -> Result<()> {
+        run_script(program("NODE", "node"), &["-"], &self.node_script(checks)).map(|_| ())
+    }
+
+    /// The library, entrypoint and checks as a JSON string literal, which
+    /// reads the same in Python and JavaScript.
+    fn script_config(&self, checks: &[SmokeCheck]) -> String {
+        let config = serde_json::json!({
+            "path": self.path.to_string_lossy(),
+            "entrypoint": self.entrypoint,
+            "checks": checks
+                .iter()
+                .map(|check| serde_json::json!([check.sql, check.expected]))
+                .collect::<Vec<_>>(),
+        });
+        Value::String(config.to_string()).to_string()
+    }
+}
+
+/// A query for the generated scripts to run, and the value in the first
+/// column of its first row. Integers, reals, text and NULL compare the same
+/// in both languages, blobs don't.
+#[derive(Debug, Clone)]
+pub struct SmokeCheck {
+    sql: String,
+    expected: Value,
+}
+
+impl SmokeCheck {
+    pub fn new(sql: &str, expected: impl Into<Value>) -> Self {
+        SmokeCheck {
+            sql: sql.to_owned(),
+            expected: expected.into(),
+        }
+    }
+}
+
+fn program(variable: &str, default: &str) -> OsString {
+    env::var_os(variable).unwrap_or_else(|| OsString::from(default))
+}
+
+/// Runs `program` with `script` on its stdin, and returns what it printed.
+/// Anything on stderr counts as a failure.
+fn run_script(program: OsString, args: &[&str], script: &str) -> Result<String> {
+    let name = program.to_string_lossy().into_owned();
+    let mut child = Command::new(&program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| Error::new_message(format!("could not run {}: {}", name, err)))?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(script.as_bytes())
+        .map_err(|err| Error::new_message(format!("could not write to {}: {}", name, err)))?;
+    let output = child
+        .wait_with_output()
+        .map_err(|err| Error::new_message(format!("{} failed: {}", name, err)))?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() || !stderr.is_empty() {
+        return Err(Error::new_message(format!(
+            "{} failed: {}",
+            name,
+            stderr.trim_end()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Whether a `sqlite3` CLI can be run, for tests to skip
+/// [`CompiledExtension::run_cli`] where there isn't one.
+pub fn cli_available() -> bool {
+    succeeds(program("SQLITE3", "sqlite3"), &["-version"])
+}
+
+/// Whether [`CompiledExtension::run_python`] can run: some Python builds,
+/// like the one macOS ships, leave out loading extensions.
+pub fn python_available() -> bool {
+    succeeds(
+        program("PYTHON", "python3"),
+        &[
+            "-c",
+            "import sqlite3; sqlite3.connect(':memory:').enable_load_extension(True)",
+        ],
+    )
+}
+
+/// Whether [`CompiledExtension::run_node`] can run, with `better-sqlite3`
+/// installed.
+pub fn node_available() -> bool {
+    succeeds(
+        program("NODE", "node"),
+        &["-e", "require('better-sqlite3')"],
+    )
+}
+
+fn succeeds(program: OsString, args: &[&str]) -> bool {
+    Command::new(program)
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
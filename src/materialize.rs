@@ -0,0 +1,248 @@
+//! Materialized views: a query's rows, kept in a table and refreshed on
+//! demand.
+//!
+//! A [`Materializer`] copies the rows of a query into a target table, often
+//! the shadow table behind an extension's virtual table, and
+//! [`define_refresh_function`] adds `name_refresh()` to bring it up to date:
+//!
+//! ```ignore
+//! let totals = Materializer::new(
+//!     "order_totals",
+//!     "select id, customer, price * quantity as total from orders",
+//! )
+//! .incremental("id", "orders");
+//! define_refresh_function(db, "order_totals", totals)?;
+//! ```
+//!
+//! ```sql
+//! select order_totals_refresh();
+//! select order_totals_refresh('full');
+//! ```
+//!
+//! A full refresh replaces every row of the target. An incremental one only
+//! replaces the rows for the rowids of the source table that changed since
+//! the last refresh, found with [`on_update`], so the query needs a column
+//! holding the source's rowid and can't aggregate over several source
+//! rows. The first refresh on each connection is full, since changes from
+//! before the extension was loaded, or from other connections, aren't
+//! seen, and neither are changes to other tables the query reads, or the
+//! changes [`on_update`] misses. Refresh with `'full'` after those.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api;
+use crate::errors::{Error, Result};
+use crate::ext::{sqlite3, sqlite3ext_changes};
+use crate::hooks::on_update;
+use crate::scalar::{define_scalar_function, FunctionFlags};
+use crate::schema::quote_identifier;
+use crate::statement::{execute_batch, in_savepoint, Statement};
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+
+/// How a [`Materializer`] refreshes its target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefreshMode {
+    /// Incrementally when it can, fully otherwise.
+    Auto,
+    Full,
+}
+
+/// The source rows changed since the last refresh.
+#[derive(Debug)]
+struct Pending {
+    /// Whether the whole target needs replacing.
+    all: bool,
+    rowids: BTreeSet<i64>,
+}
+
+/// Keeps a target table filled with the rows of a query.
+#[derive(Debug, Clone)]
+pub struct Materializer {
+    schema: String,
+    target: String,
+    query: String,
+    /// The query's column with the source table's rowid, and the source.
+    incremental: Option<(String, String)>,
+    pending: Arc<Mutex<Pending>>,
+}
+
+impl Materializer {
+    /// Materializes the rows of `query` into the table `target` of the
+    /// "main" schema, created from the query's columns on the first refresh
+    /// unless it exists.
+    pub fn new(target: &str, query: &str) -> Self {
+        Materializer {
+            schema: "main".to_owned(),
+            target: target.to_owned(),
+            query: query.to_owned(),
+            incremental: None,
+            pending: Arc::new(Mutex::new(Pending {
+                all: true,
+                rowids: BTreeSet::new(),
+            })),
+        }
+    }
+
+    /// The schema of the target and source tables.
+    pub fn schema(mut self, schema: &str) -> Self {
+        self.schema = schema.to_owned();
+        self
+    }
+
+    /// Refreshes only the rows whose `key` column, the rowid of the table
+    /// `source`, changed since the last refresh.
+    pub fn incremental(mut self, key: &str, source: &str) -> Self {
+        self.incremental = Some((key.to_owned(), source.to_owned()));
+        self
+    }
+
+    fn target_table(&self) -> String {
+        format!(
+            "{}.{}",
+            quote_identifier(&self.schema),
+            quote_identifier(&self.target)
+        )
+    }
+
+    /// Starts tracking changes to the source table on `db`, for incremental
+    /// refreshes.
+    pub fn watch(&self, db: *mut sqlite3) -> Result<()> {
+        let (_, source) = match &self.incremental {
+            Some(incremental) => incremental.clone(),
+            None => return Ok(()),
+        };
+        let schema = self.schema.clone();
+        let pending = self.pending.clone();
+        on_update(db, move |_operation, changed_schema, table, rowid| {
+            if changed_schema == schema && table.eq_ignore_ascii_case(&source) {
+                if let Ok(mut pending) = pending.lock() {
+                    pending.rowids.insert(rowid);
+                }
+            }
+        })
+    }
+
+    /// Brings the target up to date, returning how many rows were written.
+    pub fn refresh(&self, db: *mut sqlite3, mode: RefreshMode) -> Result<usize> {
+        let (all, rowids) = {
+            let pending = self
+                .pending
+                .lock()
+                .map_err(|_| Error::new_message("materializer state was poisoned"))?;
+            (pending.all, pending.rowids.clone())
+        };
+        let written = in_savepoint(db, "sqlite_loadable_materialize", || {
+            match &self.incremental {
+                Some((key, _)) if !all && mode == RefreshMode::Auto => {
+                    self.refresh_rows(db, key, &rowids)
+                }
+                _ => self.refresh_full(db),
+            }
+        })?;
+        // rows that changed while refreshing stay pending
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.all = false;
+            pending.rowids.retain(|rowid| !rowids.contains(rowid));
+        }
+        Ok(written)
+    }
+
+    fn refresh_full(&self, db: *mut sqlite3) -> Result<usize> {
+        let target = self.target_table();
+        execute_batch(
+            db,
+            &format!(
+                "CREATE TABLE IF NOT EXISTS {} AS SELECT * FROM ({}) WHERE 0",
+                target, self.query
+            ),
+        )?;
+        if let Some((key, _)) = &self.incremental {
+            execute_batch(
+                db,
+                &format!(
+                    "CREATE INDEX IF NOT EXISTS {}.{} ON {}({})",
+                    quote_identifier(&self.schema),
+                    quote_identifier(&format!("{}_key", self.target)),
+                    quote_identifier(&self.target),
+                    quote_identifier(key)
+                ),
+            )?;
+        }
+        execute_batch(
+            db,
+            &format!(
+                "DELETE FROM {}; INSERT INTO {} SELECT * FROM ({})",
+                target, target, self.query
+            ),
+        )?;
+        Ok(unsafe { sqlite3ext_changes(db) } as usize)
+    }
+
+    fn refresh_rows(&self, db: *mut sqlite3, key: &str, rowids: &BTreeSet<i64>) -> Result<usize> {
+        if rowids.is_empty() {
+            return Ok(0);
+        }
+        let target = self.target_table();
+        let rowids = serde_json::to_string(rowids)
+            .map_err(|err| Error::new_message(format!("could not list changed rows: {}", err)))?;
+        let key = quote_identifier(key);
+        let changed = "(SELECT value FROM json_each(?1))";
+        let mut delete = Statement::prepare(
+            db,
+            &format!("DELETE FROM {} WHERE {} IN {}", target, key, changed),
+        )?;
+        delete.bind_text(1, &rowids)?;
+        delete.step()?;
+        let mut insert = Statement::prepare(
+            db,
+            &format!(
+                "INSERT INTO {} SELECT * FROM ({}) WHERE {} IN {}",
+                target, self.query, key, changed
+            ),
+        )?;
+        insert.bind_text(1, &rowids)?;
+        insert.step()?;
+        Ok(unsafe { sqlite3ext_changes(db) } as usize)
+    }
+}
+
+/// Defines `name_refresh([mode])`, which refreshes `materializer`'s target
+/// and returns how many rows it wrote, fully when `mode` is `'full'`. It
+/// writes, so it's DIRECTONLY: a trigger or view can't call it.
+pub fn define_refresh_function(
+    db: *mut sqlite3,
+    name: &str,
+    materializer: Materializer,
+) -> Result<()> {
+    materializer.watch(db)?;
+    let function_name = format!("{}_refresh", name);
+    for num_args in [0, 1] {
+        let materializer = materializer.clone();
+        let function_name = function_name.clone();
+        define_scalar_function(
+            db,
+            &function_name.clone(),
+            num_args,
+            move |context, values| {
+                let mode = match values.first() {
+                    None => RefreshMode::Auto,
+                    Some(mode) => match api::value_text(mode)? {
+                        "auto" => RefreshMode::Auto,
+                        "full" => RefreshMode::Full,
+                        other => {
+                            return Err(Error::new_message(format!(
+                                "{}(): unknown mode {:?}, expected 'auto' or 'full'",
+                                function_name, other
+                            )))
+                        }
+                    },
+                };
+                let written = materializer.refresh(api::context_db_handle(context), mode)?;
+                api::result_int64(context, written as i64);
+                Ok(())
+            },
+            FunctionFlags::UTF8 | FunctionFlags::DIRECTONLY,
+        )?;
+    }
+    Ok(())
+}
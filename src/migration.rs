@@ -0,0 +1,164 @@
+//! Versioned migrations for the shadow tables of stateful virtual tables.
+//!
+//! A virtual table `docs` keeps its layout's version in a `docs_meta` shadow
+//! table. [`migrate`] compares that version to the extension's list of
+//! [`Migration`]s, and applies the missing ones in order inside a savepoint,
+//! so a database is either fully upgraded or left as it was. Call it from
+//! xCreate, where it builds the shadow tables from scratch, and from
+//! xConnect, where it upgrades tables written by older releases.
+//!
+//! ```ignore
+//! fn data_table(schema: &str, name: &str) -> String {
+//!     format!("{}.{}", quote_identifier(schema), quote_identifier(&format!("{}_data", name)))
+//! }
+//!
+//! const MIGRATIONS: &[Migration] = &[
+//!     Migration::new(1, |schema, name| {
+//!         format!("CREATE TABLE {}(id INTEGER PRIMARY KEY, body TEXT)", data_table(schema, name))
+//!     }),
+//!     Migration::new(2, |schema, name| {
+//!         format!("ALTER TABLE {} ADD COLUMN created INTEGER", data_table(schema, name))
+//!     }),
+//! ];
+//!
+//! migrate(db, &args.database_name, &args.table_name, MIGRATIONS)?;
+//! ```
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::errors::{Error, Result};
+use crate::ext::sqlite3;
+use crate::schema::quote_identifier;
+use crate::statement::{execute_batch, in_savepoint, Statement};
+
+/// One step in the history of an extension's shadow tables.
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    /// Versions start at 1 and must increase along the list.
+    pub version: i64,
+    /// The SQL for this step, which may hold several statements, given the
+    /// schema and name of the virtual table. Quote both, ex with
+    /// [`quote_identifier`].
+    pub sql: fn(schema: &str, vtab_name: &str) -> String,
+}
+
+impl Migration {
+    pub const fn new(version: i64, sql: fn(&str, &str) -> String) -> Migration {
+        Migration { version, sql }
+    }
+}
+
+/// The name of the shadow table holding the layout version of `vtab_name`.
+pub fn meta_table_name(vtab_name: &str) -> String {
+    format!("{}_meta", vtab_name)
+}
+
+fn meta_table(schema: &str, vtab_name: &str) -> String {
+    format!(
+        "{}.{}",
+        quote_identifier(schema),
+        quote_identifier(&meta_table_name(vtab_name))
+    )
+}
+
+/// The layout version of the shadow tables of `schema.vtab_name`, or 0 if
+/// no migration has been applied yet.
+pub fn schema_version(db: *mut sqlite3, schema: &str, vtab_name: &str) -> Result<i64> {
+    let mut stmt = Statement::prepare(
+        db,
+        &format!(
+            "select 1 from {}.sqlite_master where type = 'table' and name = ?1",
+            quote_identifier(schema)
+        ),
+    )?;
+    stmt.bind_text(1, &meta_table_name(vtab_name))?;
+    if !stmt.step()? {
+        return Ok(0);
+    }
+    let mut stmt = Statement::prepare(
+        db,
+        &format!(
+            "select value from {} where key = 'schema_version'",
+            meta_table(schema, vtab_name)
+        ),
+    )?;
+    Ok(if stmt.step()? {
+        stmt.column_int64(0)
+    } else {
+        0
+    })
+}
+
+/// Brings the shadow tables of `schema.vtab_name` up to the last version in
+/// `migrations`, returning that version. Nothing is written when they're
+/// already up to date, so this is cheap to call on every xConnect. Fails
+/// without changing anything if a migration fails, or if the tables were
+/// written by a newer release that knows migrations this one doesn't.
+pub fn migrate(
+    db: *mut sqlite3,
+    schema: &str,
+    vtab_name: &str,
+    migrations: &[Migration],
+) -> Result<i64> {
+    let mut latest = 0;
+    for migration in migrations {
+        if migration.version <= latest {
+            return Err(Error::new_message(format!(
+                "migration versions must start at 1 and increase, found {} after {}",
+                migration.version, latest
+            )));
+        }
+        latest = migration.version;
+    }
+    let current = schema_version(db, schema, vtab_name)?;
+    if current > latest {
+        return Err(Error::new_message(format!(
+            "{}.{} has shadow table version {}, but this version of the extension only knows up to {}; upgrade the extension",
+            schema, vtab_name, current, latest
+        )));
+    }
+    if current == latest {
+        return Ok(latest);
+    }
+    in_savepoint(db, "sqlite_loadable_migrate", || {
+        apply(db, schema, vtab_name, migrations, current)
+    })?;
+    Ok(latest)
+}
+
+fn apply(
+    db: *mut sqlite3,
+    schema: &str,
+    vtab_name: &str,
+    migrations: &[Migration],
+    current: i64,
+) -> Result<()> {
+    let meta_table = meta_table(schema, vtab_name);
+    execute_batch(
+        db,
+        &format!(
+            "CREATE TABLE IF NOT EXISTS {}(key TEXT PRIMARY KEY, value) WITHOUT ROWID",
+            meta_table
+        ),
+    )?;
+    for migration in migrations.iter().filter(|m| m.version > current) {
+        execute_batch(db, &(migration.sql)(schema, vtab_name)).map_err(|err| {
+            Error::new_message(format!(
+                "migration {} of {}.{} failed: {}",
+                migration.version,
+                schema,
+                vtab_name,
+                err.result_error_message()
+            ))
+        })?;
+        let mut stmt = Statement::prepare(
+            db,
+            &format!(
+                "INSERT OR REPLACE INTO {}(key, value) VALUES ('schema_version', ?1)",
+                meta_table
+            ),
+        )?;
+        stmt.bind_int64(1, migration.version)?;
+        stmt.step()?;
+    }
+    Ok(())
+}
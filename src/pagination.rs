@@ -0,0 +1,318 @@
+//! Paging through large results, for functions that return "page N" of
+//! something instead of everything at once.
+//!
+//! A page is its items and an opaque token for the next one, which the
+//! caller passes back to get it, until there's no next token:
+//!
+//! ```ignore
+//! // list_files(path, page_size [, token]) -> {"items": [...], "next": "..."}
+//! fn list_files(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+//!     let (path, page_size, token): (&str, usize, Option<&str>) = Args::new(values).get()?;
+//!     let page = paginate(files_in(path)?, &TOKENS, token, page_size)?;
+//!     api::result_json(context, page.to_json()?)
+//! }
+//! ```
+//!
+//! and a table function over the items is just `json_each`:
+//!
+//! ```sql
+//! select value from json_each(list_files('/data', 100, :token), '$.items');
+//! ```
+//!
+//! Tokens are made by [`PageTokens`], which serializes any state the
+//! function needs to pick up where it left off, like an offset or the
+//! last key seen, and signs it, so a caller can't hand back a token it
+//! made up or one from another extension. Tokens also carry a version,
+//! so one from before a change to the state is refused instead of
+//! misread. They're signed, not encrypted: the state in them isn't
+//! secret.
+
+use crate::errors::{Error, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// Signs and checks page tokens with a secret key.
+#[derive(Clone)]
+pub struct PageTokens {
+    key: [u8; 16],
+    version: u8,
+}
+
+impl std::fmt::Debug for PageTokens {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PageTokens")
+            .field("version", &self.version)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PageTokens {
+    /// Tokens signed with `key`, at version 1. Tokens stay valid as long
+    /// as the key does, so a fixed key keeps them working across
+    /// connections and restarts.
+    pub fn new(key: [u8; 16]) -> PageTokens {
+        PageTokens { key, version: 1 }
+    }
+
+    /// Tokens signed with a random key, from SQLite's PRNG, which are
+    /// only valid until the extension is unloaded.
+    pub fn random() -> PageTokens {
+        let mut key = [0; 16];
+        crate::random::randomness(&mut key);
+        PageTokens::new(key)
+    }
+
+    /// Sets the version tokens are made with, and the only one they're
+    /// accepted at. Bump it when the state in them changes.
+    pub fn version(mut self, version: u8) -> PageTokens {
+        self.version = version;
+        self
+    }
+
+    /// A token holding `state`.
+    pub fn encode<T: Serialize>(&self, state: &T) -> Result<String> {
+        let mut bytes = vec![self.version];
+        serde_json::to_writer(&mut bytes, state)
+            .map_err(|err| Error::new_message(format!("can't encode a page token: {}", err)))?;
+        let tag = siphash(&self.key, &bytes);
+        bytes.extend(tag.to_le_bytes());
+        Ok(base64_encode(&bytes))
+    }
+
+    /// The state in `token`, if it's one of these tokens' and its
+    /// version.
+    pub fn decode<T: DeserializeOwned>(&self, token: &str) -> Result<T> {
+        let invalid = || Error::new_message("invalid page token");
+        let bytes = base64_decode(token).ok_or_else(invalid)?;
+        if bytes.len() < 1 + 8 {
+            return Err(invalid());
+        }
+        let (signed, tag) = bytes.split_at(bytes.len() - 8);
+        if siphash(&self.key, signed).to_le_bytes() != tag {
+            return Err(invalid());
+        }
+        if signed[0] != self.version {
+            return Err(Error::new_message(format!(
+                "page token is from version {}, expected version {}",
+                signed[0], self.version
+            )));
+        }
+        serde_json::from_slice(&signed[1..]).map_err(|_| invalid())
+    }
+}
+
+/// One page of results, and the token for the next page, if there is one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next: Option<String>,
+}
+
+impl<T: Serialize> Page<T> {
+    /// The page as `{"items": [...], "next": "..."}`, with a null `next`
+    /// on the last page.
+    pub fn to_json(&self) -> Result<serde_json::Value> {
+        let items = serde_json::to_value(&self.items)
+            .map_err(|err| Error::new_message(format!("can't encode a page: {}", err)))?;
+        Ok(serde_json::json!({ "items": items, "next": self.next }))
+    }
+}
+
+/// The state [`paginate`] keeps in its tokens.
+#[derive(Serialize, Deserialize)]
+struct Offset {
+    offset: usize,
+}
+
+/// The page of `items` that `token` points to, or the first page without
+/// one, of up to `page_size` items. The items must come in the same order
+/// on every call, for the pages to neither skip nor repeat any.
+pub fn paginate<T>(
+    items: impl IntoIterator<Item = T>,
+    tokens: &PageTokens,
+    token: Option<&str>,
+    page_size: usize,
+) -> Result<Page<T>> {
+    if page_size == 0 {
+        return Err(Error::new_message("page size must be at least 1"));
+    }
+    let offset = match token {
+        Some(token) => tokens.decode::<Offset>(token)?.offset,
+        None => 0,
+    };
+    let mut rest = items.into_iter().skip(offset);
+    let items: Vec<T> = rest.by_ref().take(page_size).collect();
+    let next = if items.len() == page_size && rest.next().is_some() {
+        Some(tokens.encode(&Offset {
+            offset: offset + page_size,
+        })?)
+    } else {
+        None
+    };
+    Ok(Page { items, next })
+}
+
+/// [SipHash-2-4](https://www.aumasson.jp/siphash/siphash.pdf), a keyed
+/// hash made for authenticating short messages like these.
+fn siphash(key: &[u8; 16], message: &[u8]) -> u64 {
+    let k0 = u64::from_le_bytes(key[..8].try_into().unwrap());
+    let k1 = u64::from_le_bytes(key[8..].try_into().unwrap());
+    let mut v = [
+        k0 ^ 0x736f6d6570736575,
+        k1 ^ 0x646f72616e646f6d,
+        k0 ^ 0x6c7967656e657261,
+        k1 ^ 0x7465646279746573,
+    ];
+    fn round(v: &mut [u64; 4]) {
+        v[0] = v[0].wrapping_add(v[1]);
+        v[1] = v[1].rotate_left(13) ^ v[0];
+        v[0] = v[0].rotate_left(32);
+        v[2] = v[2].wrapping_add(v[3]);
+        v[3] = v[3].rotate_left(16) ^ v[2];
+        v[0] = v[0].wrapping_add(v[3]);
+        v[3] = v[3].rotate_left(21) ^ v[0];
+        v[2] = v[2].wrapping_add(v[1]);
+        v[1] = v[1].rotate_left(17) ^ v[2];
+        v[2] = v[2].rotate_left(32);
+    }
+    let mut compress = |m: u64| {
+        v[3] ^= m;
+        round(&mut v);
+        round(&mut v);
+        v[0] ^= m;
+    };
+    let mut chunks = message.chunks_exact(8);
+    for chunk in chunks.by_ref() {
+        compress(u64::from_le_bytes(chunk.try_into().unwrap()));
+    }
+    let mut last = [0; 8];
+    last[..chunks.remainder().len()].copy_from_slice(chunks.remainder());
+    last[7] = message.len() as u8;
+    compress(u64::from_le_bytes(last));
+    v[2] ^= 0xff;
+    for _ in 0..4 {
+        round(&mut v);
+    }
+    v[0] ^ v[1] ^ v[2] ^ v[3]
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// URL-safe base64 without padding, so tokens can go in URLs as they are.
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity((bytes.len() * 4).div_ceil(3));
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, byte)| n | ((*byte as u32) << (16 - 8 * i)));
+        for i in 0..=chunk.len() {
+            out.push(BASE64[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+        }
+    }
+    out
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 4 == 1 {
+        return None;
+    }
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    for chunk in text.as_bytes().chunks(4) {
+        let mut n = 0u32;
+        for (i, c) in chunk.iter().enumerate() {
+            let digit = BASE64.iter().position(|b| b == c)? as u32;
+            n |= digit << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            out.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pagination::*;
+
+    #[test]
+    fn test_siphash() {
+        // the reference implementation's first test vectors, with the key
+        // 00 01 .. 0f and the messages 00 01 .. (n - 1)
+        let key: [u8; 16] = std::array::from_fn(|i| i as u8);
+        let message: Vec<u8> = (0..16).collect();
+        assert_eq!(siphash(&key, &message[..0]), 0x726fdb47dd0e0e31);
+        assert_eq!(siphash(&key, &message[..1]), 0x74f839c593dc67fd);
+        assert_eq!(siphash(&key, &message[..8]), 0x93f5f5799a932462);
+        assert_eq!(siphash(&key, &message[..15]), 0xa129ca6149be45e5);
+    }
+
+    #[test]
+    fn test_base64() {
+        for bytes in [&b""[..], b"f", b"fo", b"foo", b"foob", b"\xff\xfe\xfd\xfc"] {
+            assert_eq!(base64_decode(&base64_encode(bytes)).unwrap(), bytes);
+        }
+        assert_eq!(base64_encode(b"\xfb\xff"), "-_8");
+        assert_eq!(base64_decode("a"), None);
+        assert_eq!(base64_decode("a+=="), None);
+    }
+
+    #[test]
+    fn test_tokens() {
+        let tokens = PageTokens::new([7; 16]);
+        let token = tokens.encode(&("key", 42)).unwrap();
+        assert_eq!(
+            tokens.decode::<(String, i32)>(&token).unwrap(),
+            ("key".to_owned(), 42)
+        );
+
+        // tampered with, or from another key
+        let mut tampered = token.clone().into_bytes();
+        tampered[2] = if tampered[2] == b'A' { b'B' } else { b'A' };
+        let tampered = String::from_utf8(tampered).unwrap();
+        assert_eq!(
+            tokens
+                .decode::<(String, i32)>(&tampered)
+                .unwrap_err()
+                .reported_message(),
+            "invalid page token"
+        );
+        assert!(PageTokens::new([8; 16])
+            .decode::<(String, i32)>(&token)
+            .is_err());
+        assert!(tokens.decode::<(String, i32)>("").is_err());
+
+        // from another version
+        assert_eq!(
+            tokens
+                .clone()
+                .version(2)
+                .decode::<(String, i32)>(&token)
+                .unwrap_err()
+                .reported_message(),
+            "page token is from version 1, expected version 2"
+        );
+    }
+
+    #[test]
+    fn test_paginate() {
+        let tokens = PageTokens::new([1; 16]);
+        let first = paginate(1..=5, &tokens, None, 2).unwrap();
+        assert_eq!(first.items, vec![1, 2]);
+        let second = paginate(1..=5, &tokens, first.next.as_deref(), 2).unwrap();
+        assert_eq!(second.items, vec![3, 4]);
+        let last = paginate(1..=5, &tokens, second.next.as_deref(), 2).unwrap();
+        assert_eq!(
+            last,
+            Page {
+                items: vec![5],
+                next: None
+            }
+        );
+        // a last page that's exactly full has no next page either
+        let full = paginate(1..=4, &tokens, first.next.as_deref(), 2).unwrap();
+        assert_eq!(full.items, vec![3, 4]);
+        assert_eq!(full.next, None);
+        assert_eq!(paginate(1..=2, &tokens, None, 2).unwrap().next, None);
+        assert!(paginate(1..=2, &tokens, None, 0).is_err());
+    }
+}
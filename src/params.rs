@@ -0,0 +1,256 @@
+//! Named values set from SQL, for views to read as parameters.
+//!
+//! [`define_params`] adds `param_set(key, value)`, `param(key)` and the
+//! `params` table, which share one set of values per connection:
+//!
+//! ```sql
+//! create view recent_orders as
+//!   select * from orders where created_at > param('since');
+//!
+//! select param_set('since', '2024-01-01');
+//! select * from recent_orders;
+//! select key, value from params;
+//! ```
+//!
+//! Setting a key to NULL removes it, and a key that isn't set reads as
+//! NULL. `param_clear()` removes them all. Values are kept as they were
+//! set, integers, reals, text or blobs, until the connection closes.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api;
+use crate::compare::ValueRef;
+use crate::errors::{Error, Result};
+use crate::ext::{sqlite3, sqlite3_context, sqlite3_value, sqlite3_vtab, sqlite3_vtab_cursor};
+use crate::scalar::{define_scalar_function, FunctionFlags};
+use crate::stream::result_value;
+use crate::table::{
+    define_table_function, BestIndexError, ConstraintOperator, IndexInfo, VTab, VTabArguments,
+    VTabCursor,
+};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::os::raw::c_int;
+use std::rc::Rc;
+
+/// A connection's parameters, by key. Clones share the same values.
+#[derive(Debug, Clone, Default)]
+pub struct Params {
+    values: Rc<RefCell<BTreeMap<String, ValueRef<'static>>>>,
+}
+
+impl Params {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets `key` to `value`, or removes it if `value` is NULL.
+    pub fn set(&self, key: &str, value: ValueRef<'static>) {
+        let mut values = self.values.borrow_mut();
+        if value == ValueRef::Null {
+            values.remove(key);
+        } else {
+            values.insert(key.to_owned(), value);
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<ValueRef<'static>> {
+        self.values.borrow().get(key).cloned()
+    }
+
+    /// Removes every key, returning how many there were.
+    pub fn clear(&self) -> usize {
+        let mut values = self.values.borrow_mut();
+        let len = values.len();
+        values.clear();
+        len
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.borrow().is_empty()
+    }
+
+    /// Every key and value, in key order.
+    pub fn entries(&self) -> Vec<(String, ValueRef<'static>)> {
+        self.values
+            .borrow()
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+}
+
+/// Defines `param_set(key, value)`, `param(key)`, `param_clear()` and the
+/// `params` table over a new [`Params`], which is returned so Rust code
+/// can set and read them too. `param_set()` and `param_clear()` change
+/// what queries see, so they're DIRECTONLY: a trigger or view can't call
+/// them.
+pub fn define_params(db: *mut sqlite3) -> Result<Params> {
+    let params = Params::new();
+
+    let sets = params.clone();
+    define_scalar_function(
+        db,
+        "param_set",
+        2,
+        move |context, values| {
+            let key = api::value_text(&values[0])?;
+            sets.set(key, ValueRef::from_value(&values[1]).into_owned());
+            api::result_null(context);
+            Ok(())
+        },
+        FunctionFlags::UTF8 | FunctionFlags::DIRECTONLY,
+    )?;
+
+    let gets = params.clone();
+    define_scalar_function(
+        db,
+        "param",
+        1,
+        move |context, values| match gets.get(api::value_text(&values[0])?) {
+            Some(value) => result_value(context, &value),
+            None => {
+                api::result_null(context);
+                Ok(())
+            }
+        },
+        FunctionFlags::UTF8,
+    )?;
+
+    let clears = params.clone();
+    define_scalar_function(
+        db,
+        "param_clear",
+        0,
+        move |context, _values| {
+            api::result_int64(context, clears.clear() as i64);
+            Ok(())
+        },
+        FunctionFlags::UTF8 | FunctionFlags::DIRECTONLY,
+    )?;
+
+    define_table_function::<ParamsTable>(db, "params", Some(params.clone()))?;
+    Ok(params)
+}
+
+/// idxNum when the key is given, so only that row is read.
+const PLAN_KEY: c_int = 1;
+
+#[repr(C)]
+pub struct ParamsTable {
+    /// must be first
+    base: sqlite3_vtab,
+    params: Params,
+}
+
+impl<'vtab> VTab<'vtab> for ParamsTable {
+    type Aux = Params;
+    type Cursor = ParamsCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, ParamsTable)> {
+        let params = aux
+            .cloned()
+            .ok_or_else(|| Error::new_message("params table has no parameters"))?;
+        let base: sqlite3_vtab = unsafe { std::mem::zeroed() };
+        Ok((
+            "CREATE TABLE x(key TEXT PRIMARY KEY, value)".to_owned(),
+            ParamsTable { base, params },
+        ))
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        let mut plan = 0;
+        for mut constraint in info.constraints() {
+            if constraint.column_idx() == 0
+                && constraint.usable()
+                && constraint.op() == Some(ConstraintOperator::EQ)
+            {
+                constraint.set_argv_index(1);
+                constraint.set_omit(true);
+                plan = PLAN_KEY;
+                break;
+            }
+        }
+        info.set_idxnum(plan);
+        if plan == PLAN_KEY {
+            info.set_estimated_rows(1);
+            info.set_estimated_cost(1.0);
+        } else {
+            info.set_estimated_cost(self.params.len() as f64 + 1.0);
+        }
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<ParamsCursor> {
+        let base: sqlite3_vtab_cursor = unsafe { std::mem::zeroed() };
+        Ok(ParamsCursor {
+            base,
+            params: self.params.clone(),
+            rows: Vec::new(),
+            index: 0,
+        })
+    }
+}
+
+#[repr(C)]
+pub struct ParamsCursor {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    params: Params,
+    /// The parameters as of the scan's start.
+    rows: Vec<(String, ValueRef<'static>)>,
+    index: usize,
+}
+
+impl VTabCursor for ParamsCursor {
+    fn filter(
+        &mut self,
+        idx_num: c_int,
+        _idx_str: Option<&str>,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.rows = match (idx_num, values.first()) {
+            (PLAN_KEY, Some(key)) => {
+                let key = api::value_text(key)?;
+                self.params
+                    .get(key)
+                    .map(|value| vec![(key.to_owned(), value)])
+                    .unwrap_or_default()
+            }
+            _ => self.params.entries(),
+        };
+        self.index = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.index += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.index >= self.rows.len()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, i: c_int) -> Result<()> {
+        let (key, value) = self
+            .rows
+            .get(self.index)
+            .ok_or_else(|| Error::new_message("params has no current row"))?;
+        match i {
+            0 => api::result_text(context, key),
+            _ => result_value(context, value),
+        }
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.index as i64)
+    }
+}
@@ -0,0 +1,216 @@
+//! Pluggable page caches, implementing
+//! [`sqlite3_pcache_methods2`](https://www.sqlite.org/c3ref/pcache_methods2.html) in Rust.
+//!
+//! Page caches are configured process-wide with `sqlite3_config()`, which
+//! isn't part of the loadable extension API. So this is only available with
+//! the `static` feature, for applications that link SQLite directly and call
+//! [`install_page_cache`] before SQLite is initialized.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::errors::{Error, Result};
+use libsqlite3_sys::{
+    sqlite3_config, sqlite3_pcache, sqlite3_pcache_methods2, sqlite3_pcache_page,
+    SQLITE_CONFIG_PCACHE2,
+};
+use std::os::raw::{c_int, c_uint, c_void};
+
+/// A single page handed out to SQLite. `buffer` holds the page contents and
+/// `extra` is SQLite's per-page bookkeeping, zeroed when the page is created.
+#[repr(C)]
+pub struct Page {
+    /// must be first, SQLite passes pointers to it back to the cache
+    raw: sqlite3_pcache_page,
+    key: u32,
+    buffer: Box<[u8]>,
+    extra: Box<[u8]>,
+}
+
+impl Page {
+    /// Allocates a new page for the given key, sized for the cache it belongs to.
+    pub fn new(key: u32, page_size: usize, extra_size: usize) -> Box<Page> {
+        let mut page = Box::new(Page {
+            raw: sqlite3_pcache_page {
+                pBuf: std::ptr::null_mut(),
+                pExtra: std::ptr::null_mut(),
+            },
+            key,
+            buffer: vec![0; page_size].into_boxed_slice(),
+            extra: vec![0; extra_size].into_boxed_slice(),
+        });
+        page.raw.pBuf = page.buffer.as_mut_ptr() as *mut c_void;
+        page.raw.pExtra = page.extra.as_mut_ptr() as *mut c_void;
+        page
+    }
+
+    /// The page number SQLite fetched this page with.
+    pub fn key(&self) -> u32 {
+        self.key
+    }
+
+    /// Zeroes the extra bytes, required when a page is recycled for a new key.
+    pub fn reset(&mut self, key: u32) {
+        self.key = key;
+        self.extra.fill(0);
+    }
+}
+
+// the raw pointers only ever point into the page's own buffers
+unsafe impl Send for Page {}
+
+/// How hard xFetch should try to allocate a page that isn't in the cache.
+/// <https://www.sqlite.org/c3ref/pcache_methods2.html>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FetchMode {
+    /// Return None if the page isn't cached.
+    Existing,
+    /// Allocate a new page only if it's cheap to do so.
+    CreateIfEasy,
+    /// Allocate a new page unless out of memory, recycling unpinned pages if needed.
+    Create,
+}
+
+/// A page cache implementation. SQLite creates one instance per database
+/// file it opens, keyed by page number.
+pub trait PageCache: Sized + Send {
+    /// xCreate, None if the cache can't be allocated.
+    fn create(page_size: usize, extra_size: usize, purgeable: bool) -> Option<Self>;
+
+    /// xCachesize, the suggested maximum number of pages to hold.
+    fn set_cache_size(&mut self, pages: i32);
+
+    /// xPagecount, the number of pages currently held, pinned or not.
+    fn page_count(&self) -> i32;
+
+    /// xFetch, returns the page for the key and pins it. Pages should be
+    /// created with [`Page::new`] and stay at the same address until they're
+    /// discarded.
+    fn fetch(&mut self, key: u32, mode: FetchMode) -> Option<&mut Page>;
+
+    /// xUnpin, the page for the key is no longer in use. If `discard`, it must
+    /// be removed from the cache, otherwise it may be kept for later fetches.
+    fn unpin(&mut self, key: u32, discard: bool);
+
+    /// xRekey, the page for `old_key` should now be found under `new_key`.
+    /// Any existing page at `new_key` must be discarded.
+    fn rekey(&mut self, old_key: u32, new_key: u32);
+
+    /// xTruncate, discard all pages with keys greater than or equal to `limit`.
+    fn truncate(&mut self, limit: u32);
+
+    /// xShrink, release as much memory as possible.
+    fn shrink(&mut self) {}
+}
+
+fn fetch_mode(create_flag: c_int) -> FetchMode {
+    match create_flag {
+        0 => FetchMode::Existing,
+        1 => FetchMode::CreateIfEasy,
+        _ => FetchMode::Create,
+    }
+}
+
+unsafe extern "C" fn x_init(_arg: *mut c_void) -> c_int {
+    0
+}
+
+unsafe extern "C" fn x_shutdown(_arg: *mut c_void) {}
+
+unsafe extern "C" fn x_create<T: PageCache>(
+    sz_page: c_int,
+    sz_extra: c_int,
+    purgeable: c_int,
+) -> *mut sqlite3_pcache {
+    match T::create(sz_page as usize, sz_extra as usize, purgeable != 0) {
+        Some(cache) => Box::into_raw(Box::new(cache)).cast::<sqlite3_pcache>(),
+        None => std::ptr::null_mut(),
+    }
+}
+
+unsafe extern "C" fn x_cachesize<T: PageCache>(cache: *mut sqlite3_pcache, n: c_int) {
+    (*cache.cast::<T>()).set_cache_size(n);
+}
+
+unsafe extern "C" fn x_pagecount<T: PageCache>(cache: *mut sqlite3_pcache) -> c_int {
+    (*cache.cast::<T>()).page_count()
+}
+
+unsafe extern "C" fn x_fetch<T: PageCache>(
+    cache: *mut sqlite3_pcache,
+    key: c_uint,
+    create_flag: c_int,
+) -> *mut sqlite3_pcache_page {
+    match (*cache.cast::<T>()).fetch(key, fetch_mode(create_flag)) {
+        Some(page) => {
+            page.key = key;
+            &mut page.raw
+        }
+        None => std::ptr::null_mut(),
+    }
+}
+
+unsafe extern "C" fn x_unpin<T: PageCache>(
+    cache: *mut sqlite3_pcache,
+    page: *mut sqlite3_pcache_page,
+    discard: c_int,
+) {
+    let key = (*page.cast::<Page>()).key;
+    (*cache.cast::<T>()).unpin(key, discard != 0);
+}
+
+unsafe extern "C" fn x_rekey<T: PageCache>(
+    cache: *mut sqlite3_pcache,
+    page: *mut sqlite3_pcache_page,
+    old_key: c_uint,
+    new_key: c_uint,
+) {
+    (*cache.cast::<T>()).rekey(old_key, new_key);
+    (*page.cast::<Page>()).key = new_key;
+}
+
+unsafe extern "C" fn x_truncate<T: PageCache>(cache: *mut sqlite3_pcache, limit: c_uint) {
+    (*cache.cast::<T>()).truncate(limit);
+}
+
+unsafe extern "C" fn x_destroy<T: PageCache>(cache: *mut sqlite3_pcache) {
+    drop(Box::from_raw(cache.cast::<T>()));
+}
+
+unsafe extern "C" fn x_shrink<T: PageCache>(cache: *mut sqlite3_pcache) {
+    (*cache.cast::<T>()).shrink();
+}
+
+/// Installs `T` as the page cache for every database opened afterwards, with
+/// `sqlite3_config(SQLITE_CONFIG_PCACHE2, ...)`. This must be called before
+/// `sqlite3_initialize()` (or after `sqlite3_shutdown()`), otherwise SQLite
+/// returns SQLITE_MISUSE.
+pub fn install_page_cache<T: PageCache>() -> Result<()> {
+    let methods = sqlite3_pcache_methods2 {
+        iVersion: 1,
+        pArg: std::ptr::null_mut(),
+        xInit: Some(x_init),
+        xShutdown: Some(x_shutdown),
+        xCreate: Some(x_create::<T>),
+        xCachesize: Some(x_cachesize::<T>),
+        xPagecount: Some(x_pagecount::<T>),
+        xFetch: Some(x_fetch::<T>),
+        xUnpin: Some(x_unpin::<T>),
+        xRekey: Some(x_rekey::<T>),
+        xTruncate: Some(x_truncate::<T>),
+        xDestroy: Some(x_destroy::<T>),
+        xShrink: Some(x_shrink::<T>),
+    };
+    // SQLite copies the methods, so they don't need to outlive this call
+    let rc = unsafe {
+        sqlite3_config(
+            SQLITE_CONFIG_PCACHE2,
+            &methods as *const sqlite3_pcache_methods2,
+        )
+    };
+    if rc != 0 {
+        return Err(Error::new_message(format!(
+            "sqlite3_config(SQLITE_CONFIG_PCACHE2) failed with code {}",
+            rc
+        )));
+    }
+    Ok(())
+}
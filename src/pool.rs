@@ -0,0 +1,226 @@
+//! A bounded pool of extra connections to a database, so reads on other
+//! threads don't wait on the host connection, or on each other.
+//!
+//! A [`Pool`] opens its connections like [`Database::open_same_file`], as
+//! they're needed and up to a maximum, and keeps them open between uses.
+//! It can be cloned and shared between threads:
+//!
+//! ```ignore
+//! let pool = Pool::new(db, 4)?.with_init(|conn| {
+//!     // set pragmas or define functions on conn.handle()
+//!     Ok(())
+//! });
+//! let handles: Vec<_> = (0..8)
+//!     .map(|_| {
+//!         let pool = pool.clone();
+//!         std::thread::spawn(move || {
+//!             let conn = pool.get()?;
+//!             // ... query conn.handle()
+//!         })
+//!     })
+//!     .collect();
+//! ```
+//!
+//! Connections are checked before they're handed out again, and one that's
+//! left in a transaction, or that can't run a statement, is closed rather
+//! than reused.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api::{txn_state, TransactionState};
+use crate::database::{Database, SameFile};
+use crate::errors::{Error, Result};
+use crate::ext::sqlite3;
+use crate::statement::execute_batch;
+use std::ops::Deref;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+/// Runs on each connection the pool opens, before it's first used, to set
+/// pragmas or define functions.
+type InitHook = dyn Fn(&Database) -> Result<()> + Send + Sync;
+
+struct State {
+    idle: Vec<Database>,
+    /// Connections open, idle or in use, and being opened.
+    open: usize,
+}
+
+struct Shared {
+    same_file: SameFile,
+    max_size: usize,
+    init: Option<Box<InitHook>>,
+    state: Mutex<State>,
+    /// Notified when a connection goes back to the pool, or is closed.
+    returned: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // the state is only counts and idle connections, so it's still
+        // consistent after a panic elsewhere
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Opens a new connection in a slot already counted in `open`, which is
+    /// given back if that fails.
+    fn open(&self) -> Result<Database> {
+        let opened = self.same_file.open().and_then(|conn| {
+            if let Some(init) = &self.init {
+                init(&conn)?;
+            }
+            Ok(conn)
+        });
+        if opened.is_err() {
+            self.closed();
+        }
+        opened
+    }
+
+    fn closed(&self) {
+        self.lock().open -= 1;
+        self.returned.notify_one();
+    }
+}
+
+/// Whether `conn` can be handed out: it's not in a transaction, and can
+/// still run a statement.
+fn healthy(conn: &Database) -> bool {
+    matches!(txn_state(conn.handle(), None), Ok(TransactionState::None))
+        && execute_batch(conn.handle(), "select 1").is_ok()
+}
+
+/// A bounded pool of connections to the main database of a host connection.
+#[derive(Clone)]
+pub struct Pool {
+    shared: Arc<Shared>,
+}
+
+impl Pool {
+    /// A pool of up to `max_size` connections to the main database of `db`,
+    /// with the same VFS, read-only if `db` is. None are opened yet. Fails
+    /// for in-memory and temporary databases, or if `max_size` is 0.
+    pub fn new(db: *mut sqlite3, max_size: usize) -> Result<Pool> {
+        if max_size == 0 {
+            return Err(Error::new_message(
+                "a connection pool needs at least one connection",
+            ));
+        }
+        Ok(Pool {
+            shared: Arc::new(Shared {
+                same_file: SameFile::of(db)?,
+                max_size,
+                init: None,
+                state: Mutex::new(State {
+                    idle: vec![],
+                    open: 0,
+                }),
+                returned: Condvar::new(),
+            }),
+        })
+    }
+
+    /// Runs `init` on each connection the pool opens, before it's used. A
+    /// connection `init` fails on is closed, and [`Pool::get`] returns the
+    /// error. Only applies before the pool is cloned.
+    pub fn with_init<F>(mut self, init: F) -> Self
+    where
+        F: Fn(&Database) -> Result<()> + Send + Sync + 'static,
+    {
+        if let Some(shared) = Arc::get_mut(&mut self.shared) {
+            shared.init = Some(Box::new(init));
+        }
+        self
+    }
+
+    /// A connection from the pool, waiting for one to be returned if all
+    /// `max_size` are in use.
+    pub fn get(&self) -> Result<PooledConnection> {
+        self.checkout(true)
+            .map(|conn| conn.expect("waits until there's a connection"))
+    }
+
+    /// A connection from the pool, or None if all `max_size` are in use.
+    pub fn try_get(&self) -> Result<Option<PooledConnection>> {
+        self.checkout(false)
+    }
+
+    /// An idle connection that passes the health check, or a new one if
+    /// there's room. Waits for a connection to be returned when `wait`.
+    fn checkout(&self, wait: bool) -> Result<Option<PooledConnection>> {
+        let shared = &self.shared;
+        let mut state = shared.lock();
+        loop {
+            while let Some(conn) = state.idle.pop() {
+                drop(state);
+                if healthy(&conn) {
+                    return Ok(Some(self.wrap(conn)));
+                }
+                drop(conn);
+                state = shared.lock();
+                state.open -= 1;
+            }
+            if state.open < shared.max_size {
+                state.open += 1;
+                drop(state);
+                return shared.open().map(|conn| Some(self.wrap(conn)));
+            }
+            if !wait {
+                return Ok(None);
+            }
+            state = shared
+                .returned
+                .wait(state)
+                .unwrap_or_else(|err| err.into_inner());
+        }
+    }
+
+    fn wrap(&self, conn: Database) -> PooledConnection {
+        PooledConnection {
+            conn: Some(conn),
+            shared: Arc::clone(&self.shared),
+        }
+    }
+
+    /// How many connections are open, in use or idle.
+    pub fn size(&self) -> usize {
+        self.shared.lock().open
+    }
+
+    /// How many open connections are waiting to be used.
+    pub fn idle(&self) -> usize {
+        self.shared.lock().idle.len()
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.shared.max_size
+    }
+}
+
+/// A connection from a [`Pool`], returned to it when dropped.
+pub struct PooledConnection {
+    conn: Option<Database>,
+    shared: Arc<Shared>,
+}
+
+impl Deref for PooledConnection {
+    type Target = Database;
+
+    fn deref(&self) -> &Database {
+        self.conn.as_ref().expect("a connection until dropped")
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            // one left in a transaction would block writers, so it's closed,
+            // which rolls the transaction back
+            if matches!(txn_state(conn.handle(), None), Ok(TransactionState::None)) {
+                self.shared.lock().idle.push(conn);
+                self.shared.returned.notify_one();
+            } else {
+                drop(conn);
+                self.shared.closed();
+            }
+        }
+    }
+}
@@ -9,6 +9,7 @@ pub use crate::ext::{
     sqlite3, sqlite3_api_routines, sqlite3_context, sqlite3_value, sqlite3_vtab,
     sqlite3_vtab_cursor,
 };
+pub use sqlite_loadable_macros::scalar_function;
 pub use sqlite_loadable_macros::sqlite_entrypoint;
 pub use sqlite_loadable_macros::sqlite_entrypoint_permanent;
 
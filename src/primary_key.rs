@@ -0,0 +1,165 @@
+//! Multi-column primary keys for writable virtual tables.
+//!
+//! SQLite only allows a writable `WITHOUT ROWID` virtual table to have a
+//! single-column PRIMARY KEY, and xUpdate only tells a table which row it's
+//! changing with that one value or the rowid, in argv[0]. A table keyed by
+//! several columns is declared as a rowid table, keeps the key of each
+//! rowid itself, and finds the key tuples an xUpdate changes with
+//! [`PrimaryKey::route`]:
+//!
+//! ```ignore
+//! let key = PrimaryKey::new(&[0, 1]); // (student, course)
+//! match key.route(&operation, |rowid| self.key_of(api::value_int64(rowid)))? {
+//!     KeyedOperation::Insert { key, .. } => { /* check key is free, insert */ }
+//!     KeyedOperation::Update { old_key, new_key, .. } if old_key != new_key => {
+//!         /* check new_key is free, move the row */
+//!     }
+//!     KeyedOperation::Update { old_key, .. } => { /* update in place */ }
+//!     KeyedOperation::Delete { key, .. } => { /* delete */ }
+//! }
+//! ```
+//!
+//! The old key of an UPDATE isn't in its column values, which already hold
+//! the new row, so it has to come from the table. Comparing `argv[0]` to
+//! `argv[1]` only catches rowid changes, not key changes.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::compare::{Collation, ValueRef};
+use crate::errors::{Error, Result};
+use crate::ext::sqlite3_value;
+use crate::table::UpdateOperation;
+use std::cmp::Ordering;
+
+/// The values of a row's primary key columns. Ordered and compared like
+/// SQLite compares values with the BINARY collation, so keys can be used in
+/// a `BTreeMap` and `1` and `1.0` are the same key.
+#[derive(Debug, Clone)]
+pub struct Key(pub Vec<ValueRef<'static>>);
+
+impl Ord for Key {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0
+            .iter()
+            .zip(&other.0)
+            .map(|(a, b)| a.compare(b, &Collation::Binary))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| self.0.len().cmp(&other.0.len()))
+    }
+}
+
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Key {}
+
+/// An xUpdate call in terms of primary keys.
+#[derive(Debug)]
+pub enum KeyedOperation<'a> {
+    Delete {
+        key: Key,
+        /// The rowid, or single-column PRIMARY KEY, of the deleted row.
+        rowid: &'a *mut sqlite3_value,
+    },
+    Insert {
+        key: Key,
+        values: &'a [*mut sqlite3_value],
+    },
+    Update {
+        old_key: Key,
+        new_key: Key,
+        /// The rowid, or single-column PRIMARY KEY, of the updated row.
+        rowid: &'a *mut sqlite3_value,
+        values: &'a [*mut sqlite3_value],
+    },
+}
+
+impl KeyedOperation<'_> {
+    /// Whether the operation is an UPDATE that changes the row's key.
+    pub fn key_changed(&self) -> bool {
+        match self {
+            KeyedOperation::Update {
+                old_key, new_key, ..
+            } => old_key != new_key,
+            _ => false,
+        }
+    }
+}
+
+/// The columns that make up a table's primary key, in key order.
+#[derive(Debug, Clone)]
+pub struct PrimaryKey {
+    columns: Vec<usize>,
+}
+
+impl PrimaryKey {
+    /// A key made of the given column indexes, as in the table's `CREATE
+    /// TABLE` declaration.
+    pub fn new(columns: &[usize]) -> PrimaryKey {
+        PrimaryKey {
+            columns: columns.to_vec(),
+        }
+    }
+
+    pub fn columns(&self) -> &[usize] {
+        &self.columns
+    }
+
+    /// Reads the key out of a row's column values, the argv[2] and
+    /// following of xUpdate. Fails if a key column is missing or NULL.
+    pub fn key(&self, values: &[*mut sqlite3_value]) -> Result<Key> {
+        self.columns
+            .iter()
+            .map(|&column| {
+                let value = values.get(column).ok_or_else(|| {
+                    Error::new_message(format!("no value for key column {}", column))
+                })?;
+                match ValueRef::from_value(value) {
+                    ValueRef::Null => Err(Error::constraint(format!(
+                        "NOT NULL constraint failed: key column {}",
+                        column
+                    ))),
+                    value => Ok(value.into_owned()),
+                }
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(Key)
+    }
+
+    /// Gives the old and new keys of an xUpdate operation. `old_key` looks up
+    /// the key of the existing row with the given rowid, or single-column
+    /// PRIMARY KEY, for UPDATEs and DELETEs.
+    pub fn route<'a, F>(
+        &self,
+        operation: &UpdateOperation<'a>,
+        old_key: F,
+    ) -> Result<KeyedOperation<'a>>
+    where
+        F: FnOnce(&*mut sqlite3_value) -> Result<Key>,
+    {
+        Ok(match *operation {
+            UpdateOperation::Delete(rowid) => KeyedOperation::Delete {
+                key: old_key(rowid)?,
+                rowid,
+            },
+            UpdateOperation::Insert { values, .. } => KeyedOperation::Insert {
+                key: self.key(values)?,
+                values,
+            },
+            UpdateOperation::Update { rowid, values, .. } => KeyedOperation::Update {
+                old_key: old_key(rowid)?,
+                new_key: self.key(values)?,
+                rowid,
+                values,
+            },
+        })
+    }
+}
@@ -0,0 +1,149 @@
+//! Map protobuf messages to virtual table columns, given the message's
+//! descriptor. Built on [prost-reflect](https://docs.rs/prost-reflect), so
+//! messages are decoded dynamically and don't need generated Rust types.
+//!
+//! Each field of the message becomes a column, in field number order.
+//! Scalars map to INTEGER, REAL, TEXT, or BLOB columns, while nested
+//! messages, repeated fields, and maps become TEXT columns holding the
+//! field's [canonical JSON](https://protobuf.dev/programming-guides/proto3/#json)
+//! (with a JSON subtype).
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api::{result_blob, result_double, result_int64, result_json, result_null, result_text};
+use crate::errors::{Error, Result};
+use crate::ext::sqlite3_context;
+use crate::schema::{create_table_sql, ColumnDefinition};
+use prost_reflect::{
+    DynamicMessage, FieldDescriptor, Kind, MessageDescriptor, SerializeOptions, Value,
+};
+
+/// The columns of a virtual table over messages of a single protobuf type.
+#[derive(Debug, Clone)]
+pub struct ProtobufColumns {
+    descriptor: MessageDescriptor,
+    fields: Vec<FieldDescriptor>,
+}
+
+impl ProtobufColumns {
+    pub fn new(descriptor: MessageDescriptor) -> ProtobufColumns {
+        let mut fields: Vec<FieldDescriptor> = descriptor.fields().collect();
+        fields.sort_by_key(|field| field.number());
+        ProtobufColumns { descriptor, fields }
+    }
+
+    pub fn descriptor(&self) -> &MessageDescriptor {
+        &self.descriptor
+    }
+
+    /// The protobuf field for the given column index.
+    pub fn field(&self, column: usize) -> Option<&FieldDescriptor> {
+        self.fields.get(column)
+    }
+
+    /// One column definition per field, named after the field.
+    pub fn column_definitions(&self) -> Vec<ColumnDefinition> {
+        self.fields
+            .iter()
+            .map(|field| ColumnDefinition::new(field.name(), declared_type(field)))
+            .collect()
+    }
+
+    /// The schema to pass to `declare_vtab`.
+    pub fn create_table_sql(&self) -> String {
+        create_table_sql(&self.column_definitions())
+    }
+
+    /// Decodes a single binary-encoded message.
+    pub fn decode(&self, bytes: &[u8]) -> Result<DynamicMessage> {
+        DynamicMessage::decode(self.descriptor.clone(), bytes).map_err(|err| {
+            Error::new_message(format!(
+                "invalid {} message: {}",
+                self.descriptor.full_name(),
+                err
+            ))
+        })
+    }
+
+    /// Results the value of the column's field in the given message, ideal
+    /// for xColumn. Unset fields that have no presence result their default
+    /// value, while unset optional fields and messages result NULL.
+    pub fn result_column(
+        &self,
+        context: *mut sqlite3_context,
+        message: &DynamicMessage,
+        column: usize,
+    ) -> Result<()> {
+        let field = self.field(column).ok_or_else(|| {
+            Error::new_message(format!("no protobuf field for column {}", column))
+        })?;
+        if field.supports_presence() && !message.has_field(field) {
+            result_null(context);
+            return Ok(());
+        }
+        if field.is_list() || field.is_map() || matches!(field.kind(), Kind::Message(_)) {
+            return result_json(context, field_json(message, field)?);
+        }
+        match message.get_field(field).as_ref() {
+            Value::Bool(b) => result_int64(context, i64::from(*b)),
+            Value::I32(i) => result_int64(context, i64::from(*i)),
+            Value::I64(i) => result_int64(context, *i),
+            Value::U32(i) => result_int64(context, i64::from(*i)),
+            // SQLite has no unsigned 64-bit integers, large values wrap around
+            Value::U64(i) => result_int64(context, *i as i64),
+            Value::F32(f) => result_double(context, f64::from(*f)),
+            Value::F64(f) => result_double(context, *f),
+            Value::String(s) => result_text(context, s)?,
+            Value::Bytes(b) => result_blob(context, b),
+            Value::EnumNumber(n) => result_int64(context, i64::from(*n)),
+            Value::Message(_) | Value::List(_) | Value::Map(_) => {
+                result_json(context, field_json(message, field)?)?
+            }
+        }
+        Ok(())
+    }
+}
+
+fn declared_type(field: &FieldDescriptor) -> &'static str {
+    if field.is_list() || field.is_map() {
+        return "TEXT";
+    }
+    match field.kind() {
+        Kind::Double | Kind::Float => "REAL",
+        Kind::String | Kind::Message(_) => "TEXT",
+        Kind::Bytes => "BLOB",
+        _ => "INTEGER",
+    }
+}
+
+/// The canonical JSON of a single field, by serializing a message that only
+/// contains that field.
+fn field_json(message: &DynamicMessage, field: &FieldDescriptor) -> Result<serde_json::Value> {
+    let mut single = DynamicMessage::new(field.parent_message().clone());
+    single.set_field(field, message.get_field(field).into_owned());
+    let options = SerializeOptions::new().skip_default_fields(false);
+    let json = single
+        .serialize_with_options(serde_json::value::Serializer, &options)
+        .map_err(|err| Error::new_message(format!("could not convert to JSON: {}", err)))?;
+    Ok(json
+        .get(field.json_name())
+        .cloned()
+        .unwrap_or(serde_json::Value::Null))
+}
+
+/// Splits a stream of length-delimited messages (each prefixed with its
+/// varint length, like `writeDelimitedTo()` produces) into single messages.
+pub fn split_length_delimited(mut bytes: &[u8]) -> Result<Vec<&[u8]>> {
+    let mut messages = vec![];
+    while !bytes.is_empty() {
+        let length = prost::decode_length_delimiter(bytes)
+            .map_err(|err| Error::new_message(format!("invalid message length: {}", err)))?;
+        let start = prost::length_delimiter_len(length);
+        let end = start
+            .checked_add(length)
+            .filter(|end| *end <= bytes.len())
+            .ok_or_else(|| Error::new_message("truncated length-delimited message"))?;
+        messages.push(&bytes[start..end]);
+        bytes = &bytes[end..];
+    }
+    Ok(messages)
+}
@@ -0,0 +1,221 @@
+//! A faster path for pure functions over numbers.
+//!
+//! [`define_scalar_function`](crate::define_scalar_function) hands each
+//! call a slice of the arguments, looks up the function in the user data,
+//! and routes a `Result` back through the error path. For a function like
+//! `add(a, b)`, called once a row, that's most of the work. A pure
+//! function takes and returns plain Rust numbers instead:
+//!
+//! ```ignore
+//! define_pure_function(db, "xyz_add", |a: i64, b: i64| a.wrapping_add(b), FunctionFlags::UTF8)?;
+//! define_pure_function(db, "xyz_hypot", f64::hypot, FunctionFlags::UTF8)?;
+//! ```
+//!
+//! Each argument is read straight from its `sqlite3_value` with the
+//! accessor for its type, and the result set with the matching
+//! `sqlite3_result_*`, in a C callback generated for the function, which
+//! has to capture nothing. Arguments get SQLite's own conversions rather
+//! than [`crate::Args`]' checks, so `'12'` is 12 and NULL is 0, unless the
+//! argument is an `Option`, which is `None` for NULL. A result of `None` is
+//! NULL.
+//!
+//! Pure functions are registered as deterministic, and can't fail, read
+//! text or blobs, or set subtypes. Functions that do any of that are
+//! defined with `define_scalar_function`.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::errors::{Error, Result};
+use crate::ext::{
+    sqlite3, sqlite3_context, sqlite3_value, sqlite3ext_result_double, sqlite3ext_result_int,
+    sqlite3ext_result_int64, sqlite3ext_result_null, sqlite3ext_value_double, sqlite3ext_value_int,
+    sqlite3ext_value_int64, sqlite3ext_value_type,
+};
+use crate::scalar::{create_function_v2, FunctionFlags};
+use sqlite3ext_sys::SQLITE_NULL;
+use std::os::raw::c_int;
+use std::ptr::NonNull;
+
+/// An argument type of a pure function, read with SQLite's conversions.
+pub trait PureArg: Sized {
+    /// Reads `value`, which must be a valid `sqlite3_value`.
+    ///
+    /// # Safety
+    /// `value` is passed to SQLite as it is.
+    unsafe fn read(value: *mut sqlite3_value) -> Self;
+}
+
+impl PureArg for i64 {
+    #[inline]
+    unsafe fn read(value: *mut sqlite3_value) -> Self {
+        sqlite3ext_value_int64(value)
+    }
+}
+
+/// The lower 32 bits, like `sqlite3_value_int`.
+impl PureArg for i32 {
+    #[inline]
+    unsafe fn read(value: *mut sqlite3_value) -> Self {
+        sqlite3ext_value_int(value)
+    }
+}
+
+impl PureArg for f64 {
+    #[inline]
+    unsafe fn read(value: *mut sqlite3_value) -> Self {
+        sqlite3ext_value_double(value)
+    }
+}
+
+/// True for anything SQLite would take as true in a `WHERE`, like 0.5.
+impl PureArg for bool {
+    #[inline]
+    unsafe fn read(value: *mut sqlite3_value) -> Self {
+        sqlite3ext_value_double(value) != 0.0
+    }
+}
+
+impl<T: PureArg> PureArg for Option<T> {
+    #[inline]
+    unsafe fn read(value: *mut sqlite3_value) -> Self {
+        if (sqlite3ext_value_type(value) as u32) == SQLITE_NULL {
+            None
+        } else {
+            Some(T::read(value))
+        }
+    }
+}
+
+/// A result type of a pure function.
+pub trait PureResult {
+    /// Sets `context`'s result to the value.
+    ///
+    /// # Safety
+    /// `context` is passed to SQLite as it is.
+    unsafe fn set(self, context: *mut sqlite3_context);
+}
+
+impl PureResult for i64 {
+    #[inline]
+    unsafe fn set(self, context: *mut sqlite3_context) {
+        sqlite3ext_result_int64(context, self);
+    }
+}
+
+impl PureResult for i32 {
+    #[inline]
+    unsafe fn set(self, context: *mut sqlite3_context) {
+        sqlite3ext_result_int(context, self);
+    }
+}
+
+/// NaN is NULL, as SQLite never stores it.
+impl PureResult for f64 {
+    #[inline]
+    unsafe fn set(self, context: *mut sqlite3_context) {
+        sqlite3ext_result_double(context, self);
+    }
+}
+
+impl PureResult for bool {
+    #[inline]
+    unsafe fn set(self, context: *mut sqlite3_context) {
+        sqlite3ext_result_int(context, c_int::from(self));
+    }
+}
+
+impl<T: PureResult> PureResult for Option<T> {
+    #[inline]
+    unsafe fn set(self, context: *mut sqlite3_context) {
+        match self {
+            Some(value) => value.set(context),
+            None => sqlite3ext_result_null(context),
+        }
+    }
+}
+
+/// A Rust function of [`PureArg`]s that returns a [`PureResult`], with
+/// `Args` the tuple of its argument types.
+pub trait PureFunction<Args> {
+    /// How many arguments the function takes.
+    const NUM_ARGS: c_int;
+
+    /// Calls the function with the values in `argv` and sets `context`'s
+    /// result to what it returns.
+    ///
+    /// # Safety
+    /// `argv` must hold [`Self::NUM_ARGS`] valid values.
+    unsafe fn invoke(&self, context: *mut sqlite3_context, argv: *mut *mut sqlite3_value);
+}
+
+macro_rules! pure_function {
+    ($len:expr; $($t:ident $i:tt),*) => {
+        impl<Func, Ret, $($t: PureArg),*> PureFunction<($($t,)*)> for Func
+        where
+            Func: Fn($($t),*) -> Ret,
+            Ret: PureResult,
+        {
+            const NUM_ARGS: c_int = $len;
+
+            #[inline(always)]
+            #[allow(unused_variables)]
+            unsafe fn invoke(&self, context: *mut sqlite3_context, argv: *mut *mut sqlite3_value) {
+                self($(<$t as PureArg>::read(*argv.add($i))),*).set(context)
+            }
+        }
+    };
+}
+pure_function!(0;);
+pure_function!(1; A 0);
+pure_function!(2; A 0, B 1);
+pure_function!(3; A 0, B 1, C 2);
+pure_function!(4; A 0, B 1, C 2, D 3);
+pure_function!(5; A 0, B 1, C 2, D 3, E 4);
+pure_function!(6; A 0, B 1, C 2, D 3, E 4, F 5);
+pure_function!(7; A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+pure_function!(8; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+
+/// Defines `x_func` as a deterministic function of as many arguments as it
+/// takes. It has to be a function or a closure that captures nothing,
+/// since SQLite calls it with nothing but its type, and fails otherwise.
+pub fn define_pure_function<F, Args>(
+    db: *mut sqlite3,
+    name: &str,
+    x_func: F,
+    func_flags: FunctionFlags,
+) -> Result<()>
+where
+    F: PureFunction<Args>,
+{
+    if std::mem::size_of::<F>() != 0 {
+        return Err(Error::new_message(format!(
+            "pure function {}() can't capture anything",
+            name
+        )));
+    }
+    std::mem::forget(x_func);
+
+    unsafe extern "C" fn x_func_wrapper<F, Args>(
+        context: *mut sqlite3_context,
+        _argc: c_int,
+        argv: *mut *mut sqlite3_value,
+    ) where
+        F: PureFunction<Args>,
+    {
+        // F has no data, so any aligned pointer is one to a valid F
+        let function: &F = &*NonNull::<F>::dangling().as_ptr();
+        // SQLite only calls a function with the nArg it was defined with
+        function.invoke(context, argv);
+    }
+
+    create_function_v2(
+        db,
+        name,
+        F::NUM_ARGS,
+        func_flags | FunctionFlags::DETERMINISTIC,
+        std::ptr::null_mut(),
+        Some(x_func_wrapper::<F, Args>),
+        None,
+        None,
+        None,
+    )
+}
@@ -0,0 +1,31 @@
+//! Random bytes from SQLite's own PRNG, the one behind `random()` and
+//! `randomblob()`.
+//!
+//! It's a cryptographic PRNG seeded from the VFS's xRandomness, good
+//! enough for UUIDs and sampling without pulling in another RNG crate.
+//! Tests that fix its seed with `SQLITE_TESTCTRL_PRNG_SEED` make these
+//! reproducible too.
+
+use crate::ext::sqlite3ext_randomness;
+use std::os::raw::{c_int, c_void};
+
+/// Fills `buffer` with random bytes.
+pub fn randomness(buffer: &mut [u8]) {
+    for chunk in buffer.chunks_mut(c_int::MAX as usize) {
+        unsafe { sqlite3ext_randomness(chunk.len() as c_int, chunk.as_mut_ptr().cast::<c_void>()) };
+    }
+}
+
+/// A random integer, any of the 2^64 values.
+pub fn random_i64() -> i64 {
+    let mut bytes = [0; 8];
+    randomness(&mut bytes);
+    i64::from_ne_bytes(bytes)
+}
+
+/// `n` random bytes, like `randomblob(n)`.
+pub fn random_blob(n: usize) -> Vec<u8> {
+    let mut blob = vec![0; n];
+    randomness(&mut blob);
+    blob
+}
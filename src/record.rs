@@ -0,0 +1,304 @@
+//! Return several named values from one function call.
+//!
+//! A SQL function returns one value, so a function with more to say returns
+//! a record: a JSON object with the `J` subtype, whose fields a
+//! [`RecordSchema`] declares. [`define_record_function`] defines the
+//! function, and with it `name_unpack(record)`, a table function with a
+//! column of the declared type for each field:
+//!
+//! ```ignore
+//! let schema = RecordSchema::new()
+//!     .field("scheme", FieldType::Text)
+//!     .field("host", FieldType::Text)
+//!     .field("port", FieldType::Integer);
+//! define_record_function(db, "parse_url", 1, schema, parse_url, FunctionFlags::UTF8)?;
+//! ```
+//!
+//! ```sql
+//! select parse_url('https://example.com:8080');
+//! -- {"host":"example.com","port":8080,"scheme":"https"}
+//! select host, port from parse_url_unpack(parse_url('https://example.com:8080'));
+//! select urls.id, u.* from urls, parse_url_unpack(parse_url(urls.url)) as u;
+//! ```
+//!
+//! The JSON functions read records too, `->>` included. A function that
+//! returns `Value::Null` returns NULL, which unpacks to no rows, and a
+//! field missing from a record is NULL.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api;
+use crate::errors::{Error, Result};
+use crate::ext::{sqlite3, sqlite3_context, sqlite3_value, sqlite3_vtab, sqlite3_vtab_cursor};
+use crate::scalar::{define_scalar_function, FunctionFlags};
+use crate::table::{
+    define_table_function, BestIndexError, ConstraintOperator, IndexInfo, VTab, VTabArguments,
+    VTabCursor,
+};
+use serde_json::{Map, Value};
+use std::os::raw::c_int;
+use std::sync::Arc;
+
+/// The type of a record field, which is also the declared type of its
+/// column in the unpack table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    Integer,
+    Real,
+    Text,
+    /// `true` or `false`, 1 or 0 when unpacked.
+    Boolean,
+    /// Any JSON, unpacked as JSON text.
+    Json,
+}
+
+impl FieldType {
+    pub fn sql_type(&self) -> &'static str {
+        match self {
+            FieldType::Integer | FieldType::Boolean => "INTEGER",
+            FieldType::Real => "REAL",
+            FieldType::Text => "TEXT",
+            FieldType::Json => "JSON",
+        }
+    }
+
+    fn accepts(&self, value: &Value) -> bool {
+        match self {
+            FieldType::Integer => value.is_i64() || value.is_u64(),
+            FieldType::Real => value.is_number(),
+            FieldType::Text => value.is_string(),
+            FieldType::Boolean => value.is_boolean(),
+            FieldType::Json => true,
+        }
+    }
+}
+
+/// The fields of a record, in column order.
+#[derive(Debug, Clone, Default)]
+pub struct RecordSchema {
+    fields: Vec<(String, FieldType)>,
+}
+
+impl RecordSchema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn field(mut self, name: &str, field_type: FieldType) -> Self {
+        self.fields.push((name.to_owned(), field_type));
+        self
+    }
+
+    pub fn fields(&self) -> &[(String, FieldType)] {
+        &self.fields
+    }
+
+    /// Checks that `record` is an object of the declared fields and types,
+    /// or null. Any field can be null or left out.
+    pub fn check(&self, record: &Value) -> Result<()> {
+        let object = match record {
+            Value::Null => return Ok(()),
+            Value::Object(object) => object,
+            _ => return Err(Error::new_message("a record must be a JSON object")),
+        };
+        for (name, value) in object {
+            let (_, field_type) = self
+                .fields
+                .iter()
+                .find(|(field, _)| field == name)
+                .ok_or_else(|| {
+                    Error::new_message(format!("{} isn't a field of the record", name))
+                })?;
+            if !value.is_null() && !field_type.accepts(value) {
+                return Err(Error::new_message(format!(
+                    "field {} is declared {:?}, but is {}",
+                    name, field_type, value
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks `record` against `schema` and sets it as the result, as JSON text
+/// with the `J` subtype, or NULL.
+pub fn result_record(
+    context: *mut sqlite3_context,
+    schema: &RecordSchema,
+    record: Value,
+) -> Result<()> {
+    schema.check(&record)?;
+    if record.is_null() {
+        api::result_null(context);
+        return Ok(());
+    }
+    api::result_json(context, record)
+}
+
+/// Defines the scalar function `name`, which returns the record `x_func`
+/// builds, and the table function `name_unpack` that splits one into
+/// columns.
+pub fn define_record_function<F>(
+    db: *mut sqlite3,
+    name: &str,
+    num_args: c_int,
+    schema: RecordSchema,
+    x_func: F,
+    func_flags: FunctionFlags,
+) -> Result<()>
+where
+    F: Fn(&[*mut sqlite3_value]) -> Result<Value>,
+{
+    let schema = Arc::new(schema);
+    define_table_function::<UnpackTable>(db, &format!("{}_unpack", name), Some(schema.clone()))?;
+    let function_name = name.to_owned();
+    define_scalar_function(
+        db,
+        name,
+        num_args,
+        move |context, values| {
+            result_record(context, &schema, x_func(values)?).map_err(|err| {
+                Error::new_message(format!(
+                    "{}(): {}",
+                    function_name,
+                    err.result_error_message()
+                ))
+            })
+        },
+        func_flags | FunctionFlags::RESULT_SUBTYPE,
+    )
+}
+
+/// idxNum when the record is passed as an argument.
+const PLAN_ARGUMENT: c_int = 1;
+
+#[repr(C)]
+pub struct UnpackTable {
+    /// must be first
+    base: sqlite3_vtab,
+    schema: Arc<RecordSchema>,
+}
+
+impl<'vtab> VTab<'vtab> for UnpackTable {
+    type Aux = Arc<RecordSchema>;
+    type Cursor = UnpackCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, UnpackTable)> {
+        let schema = aux
+            .cloned()
+            .ok_or_else(|| Error::new_message("unpack table has no record schema"))?;
+        let columns: Vec<String> = schema
+            .fields()
+            .iter()
+            .map(|(name, field_type)| {
+                format!(
+                    "\"{}\" {}",
+                    name.replace('"', "\"\""),
+                    field_type.sql_type()
+                )
+            })
+            .collect();
+        let base: sqlite3_vtab = unsafe { std::mem::zeroed() };
+        Ok((
+            format!("CREATE TABLE x({}, __record hidden)", columns.join(", ")),
+            UnpackTable { base, schema },
+        ))
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        let record_column = self.schema.fields().len() as i32;
+        let mut plan = 0;
+        for mut constraint in info.constraints() {
+            if constraint.column_idx() != record_column {
+                continue;
+            }
+            if !constraint.usable() || constraint.op() != Some(ConstraintOperator::EQ) {
+                return Err(BestIndexError::Constraint);
+            }
+            constraint.set_argv_index(1);
+            constraint.set_omit(true);
+            plan = PLAN_ARGUMENT;
+        }
+        // without a record there's nothing to unpack
+        if plan != PLAN_ARGUMENT {
+            return Err(BestIndexError::Constraint);
+        }
+        info.set_idxnum(plan);
+        info.set_estimated_rows(1);
+        info.set_estimated_cost(1.0);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<UnpackCursor> {
+        let base: sqlite3_vtab_cursor = unsafe { std::mem::zeroed() };
+        Ok(UnpackCursor {
+            base,
+            schema: self.schema.clone(),
+            record: None,
+        })
+    }
+}
+
+#[repr(C)]
+pub struct UnpackCursor {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    schema: Arc<RecordSchema>,
+    /// The one row, until next() moves past it.
+    record: Option<Map<String, Value>>,
+}
+
+impl VTabCursor for UnpackCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.record = if api::value_is_null(&values[0]) {
+            None
+        } else {
+            match serde_json::from_str(api::value_text(&values[0])?) {
+                Ok(Value::Object(record)) => Some(record),
+                _ => return Err(Error::new_message("the argument isn't a JSON object")),
+            }
+        };
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.record = None;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.record.is_none()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, i: c_int) -> Result<()> {
+        let value = self
+            .schema
+            .fields()
+            .get(i as usize)
+            .zip(self.record.as_ref())
+            .and_then(|((name, _), record)| record.get(name));
+        match value {
+            None | Some(Value::Null) => api::result_null(context),
+            Some(Value::Bool(b)) => api::result_bool(context, *b),
+            Some(Value::Number(n)) => match n.as_i64() {
+                Some(i) => api::result_int64(context, i),
+                None => api::result_double(context, n.as_f64().unwrap_or(f64::NAN)),
+            },
+            Some(Value::String(s)) => api::result_text(context, s)?,
+            Some(value) => api::result_json(context, value.clone())?,
+        }
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(0)
+    }
+}
@@ -0,0 +1,229 @@
+//! Opt-in evaluation of "residual" constraints in Rust.
+//!
+//! A virtual table that claims a constraint in xBestIndex (by giving it an
+//! argvIndex) but doesn't set `omit` still has to return rows that satisfy
+//! it, or at least not rely on rows being filtered afterwards for things like
+//! LIMIT pushdown. Instead of re-implementing every operator in each cursor,
+//! record the claimed constraints with [`ResidualPredicates::record`], pass
+//! them to xFilter through idxStr, and check each produced row with
+//! [`ResidualFilter::matches`].
+//!
+//! ```ignore
+//! fn best_index(&self, mut info: IndexInfo) -> Result<(), BestIndexError> {
+//!     // ... set_argv_index() on the constraints you want ...
+//!     let residual = ResidualPredicates::record(&info);
+//!     info.set_idxstr(&residual.to_idx_str()).map_err(|_| BestIndexError::Error)?;
+//!     Ok(())
+//! }
+//!
+//! fn filter(&mut self, _: c_int, idx_str: Option<&str>, values: &[*mut sqlite3_value]) -> Result<()> {
+//!     self.residual = ResidualPredicates::from_idx_str(idx_str.unwrap_or(""))?
+//!         .bind(values, &self.affinities);
+//!     // ... then skip rows in next() until self.residual.matches(|i| self.value_ref(i))
+//! }
+//! ```
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api::{ColumnAffinity, ColumnAffinityMap};
+use crate::compare::{glob, like, Collation, ValueRef};
+use crate::errors::{Error, Result};
+use crate::ext::sqlite3_value;
+use crate::table::{ConstraintOperator, IndexInfo};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+/// A single constraint claimed by the virtual table that wasn't omitted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResidualPredicate {
+    /// The column index the constraint is on, -1 for the rowid.
+    pub column: i32,
+    pub op: ConstraintOperator,
+    /// The 1-based index into the xFilter values that holds the right-hand side.
+    pub argv_index: i32,
+    /// The collation text is compared with, like `"BINARY"` or `"NOCASE"`.
+    pub collation: String,
+}
+
+/// All the residual predicates of a query plan, built in xBestIndex and
+/// serialized into idxStr so xFilter can rebuild them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResidualPredicates {
+    pub predicates: Vec<ResidualPredicate>,
+}
+
+impl ResidualPredicates {
+    /// Records every usable constraint that has an argvIndex but isn't
+    /// omitted. Call it at the end of xBestIndex, after claiming constraints.
+    /// Operators that can't be evaluated in Rust (MATCH, REGEXP, overloaded
+    /// functions), LIMIT/OFFSET and comparisons with a collation other than
+    /// BINARY, NOCASE or RTRIM are skipped, SQLite still checks those.
+    pub fn record(info: &IndexInfo) -> ResidualPredicates {
+        let predicates = info
+            .constraints()
+            .iter()
+            .filter(|constraint| {
+                constraint.usable() && constraint.argv_index() > 0 && !constraint.omit()
+            })
+            .filter_map(|constraint| {
+                let op = constraint.op()?;
+                let collation = constraint.collation().unwrap_or("BINARY");
+                if !evaluable(&op) || Collation::from_name(collation).is_none() {
+                    return None;
+                }
+                Some(ResidualPredicate {
+                    column: constraint.column_idx(),
+                    op,
+                    argv_index: constraint.argv_index(),
+                    collation: collation.to_owned(),
+                })
+            })
+            .collect();
+        ResidualPredicates { predicates }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.predicates.is_empty()
+    }
+
+    /// Serializes the predicates to a string suitable for `IndexInfo::set_idxstr`.
+    pub fn to_idx_str(&self) -> String {
+        serde_json::to_string(self).expect("residual predicates are always serializable")
+    }
+
+    /// Parses predicates from an idxStr written with `to_idx_str`. An empty
+    /// string means no predicates.
+    pub fn from_idx_str(idx_str: &str) -> Result<ResidualPredicates> {
+        if idx_str.is_empty() {
+            return Ok(ResidualPredicates::default());
+        }
+        serde_json::from_str(idx_str).map_err(|err| {
+            Error::new_message(format!("invalid residual predicates in idxStr: {}", err))
+        })
+    }
+
+    /// Copies the right-hand side of each predicate out of the xFilter values,
+    /// so rows can be checked after xFilter returns. Comparisons apply the
+    /// column's affinity from `affinities` to the right-hand side, like
+    /// SQLite does, and the rowid has INTEGER affinity.
+    pub fn bind(
+        &self,
+        values: &[*mut sqlite3_value],
+        affinities: &ColumnAffinityMap,
+    ) -> ResidualFilter {
+        let predicates = self
+            .predicates
+            .iter()
+            .map(|predicate| {
+                let value = usize::try_from(predicate.argv_index - 1)
+                    .ok()
+                    .and_then(|idx| values.get(idx))
+                    .map_or(ValueRef::Null, |value| {
+                        ValueRef::from_value(value).into_owned()
+                    });
+                let affinity = match usize::try_from(predicate.column) {
+                    Ok(column) => affinities.get(column).unwrap_or(ColumnAffinity::Blob),
+                    Err(_) => ColumnAffinity::Integer,
+                };
+                // LIKE and GLOB are functions, their patterns have no affinity
+                let value = match predicate.op {
+                    ConstraintOperator::LIKE | ConstraintOperator::GLOB => value,
+                    _ => value.with_affinity(&affinity),
+                };
+                (
+                    predicate.column,
+                    predicate.op,
+                    value,
+                    predicate.collation.clone(),
+                )
+            })
+            .collect();
+        ResidualFilter { predicates }
+    }
+}
+
+fn evaluable(op: &ConstraintOperator) -> bool {
+    !matches!(
+        op,
+        ConstraintOperator::MATCH
+            | ConstraintOperator::REGEXP
+            | ConstraintOperator::FUNCTION(_)
+            | ConstraintOperator::LIMIT
+            | ConstraintOperator::OFFSET
+    )
+}
+
+/// Residual predicates bound to the values of a single xFilter call.
+#[derive(Debug, Clone, Default)]
+pub struct ResidualFilter {
+    predicates: Vec<(i32, ConstraintOperator, ValueRef<'static>, String)>,
+}
+
+impl ResidualFilter {
+    /// Returns true if the row satisfies every predicate. `column` is called
+    /// with a column index (or -1 for the rowid) and returns the row's value
+    /// for that column. Text is compared with each constraint's collation.
+    pub fn matches<'a, F>(&self, mut column: F) -> bool
+    where
+        F: FnMut(i32) -> ValueRef<'a>,
+    {
+        self.predicates.iter().all(|(idx, op, rhs, collation)| {
+            let collation = Collation::from_name(collation).unwrap_or(Collation::Binary);
+            evaluate(op, &column(*idx), rhs, &collation)
+        })
+    }
+}
+
+fn evaluate(
+    op: &ConstraintOperator,
+    lhs: &ValueRef,
+    rhs: &ValueRef,
+    collation: &Collation,
+) -> bool {
+    let is_null = |value: &ValueRef| matches!(value, ValueRef::Null);
+    let compare = |expected: &[Ordering]| {
+        !is_null(lhs) && !is_null(rhs) && expected.contains(&lhs.compare(rhs, collation))
+    };
+    let is = || match (is_null(lhs), is_null(rhs)) {
+        (true, true) => true,
+        (false, false) => lhs.compare(rhs, collation) == Ordering::Equal,
+        _ => false,
+    };
+    match op {
+        ConstraintOperator::EQ => compare(&[Ordering::Equal]),
+        ConstraintOperator::NE => compare(&[Ordering::Less, Ordering::Greater]),
+        ConstraintOperator::GT => compare(&[Ordering::Greater]),
+        ConstraintOperator::GE => compare(&[Ordering::Greater, Ordering::Equal]),
+        ConstraintOperator::LT => compare(&[Ordering::Less]),
+        ConstraintOperator::LE => compare(&[Ordering::Less, Ordering::Equal]),
+        ConstraintOperator::IS => is(),
+        ConstraintOperator::ISNOT => !is(),
+        ConstraintOperator::ISNULL => is_null(lhs),
+        ConstraintOperator::ISNOTNULL => !is_null(lhs),
+        ConstraintOperator::LIKE => match (as_text(lhs), as_text(rhs)) {
+            (Some(text), Some(pattern)) => like(&pattern, &text, None),
+            _ => false,
+        },
+        ConstraintOperator::GLOB => match (as_text(lhs), as_text(rhs)) {
+            (Some(text), Some(pattern)) => glob(&pattern, &text),
+            _ => false,
+        },
+        // never recorded, left for SQLite to check
+        ConstraintOperator::MATCH
+        | ConstraintOperator::REGEXP
+        | ConstraintOperator::FUNCTION(_)
+        | ConstraintOperator::LIMIT
+        | ConstraintOperator::OFFSET => true,
+    }
+}
+
+/// Converts a value to text the way LIKE and GLOB do, None for NULL.
+fn as_text(value: &ValueRef) -> Option<String> {
+    match value.clone().with_affinity(&ColumnAffinity::Text) {
+        ValueRef::Null => None,
+        ValueRef::Text(text) | ValueRef::Blob(text) => {
+            Some(String::from_utf8_lossy(&text).into_owned())
+        }
+        // with_affinity(Text) converts all numbers to text
+        ValueRef::Integer(_) | ValueRef::Real(_) => unreachable!(),
+    }
+}
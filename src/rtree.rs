@@ -0,0 +1,498 @@
+//! Custom [R*Tree](https://www.sqlite.org/rtree.html) queries, for spatial
+//! predicates like `circle()` or `polygon_contains()` used in
+//! `WHERE id MATCH circle(...)` constraints.
+//!
+//! [`define_rtree_query_function`] wraps `sqlite3_rtree_query_callback()`,
+//! and [`define_rtree_geometry_function`] wraps the older, simpler
+//! `sqlite3_rtree_geometry_callback()`. See
+//! <https://www.sqlite.org/rtree.html#custom_r_tree_queries>.
+//!
+//! Neither function is part of the loadable extension API, so loadable
+//! extensions register their callbacks the same way the R*Tree module itself
+//! does: with an SQL function that returns its callback as an "RtreeMatchArg"
+//! pointer value.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::errors::{Error, Result};
+use crate::ext::{sqlite3, sqlite3_rtree_geometry, sqlite3_rtree_query_info, sqlite3_value};
+use std::{
+    any::Any,
+    ffi::CString,
+    os::raw::{c_int, c_void},
+    slice,
+};
+
+/// How much of a node or entry is within the query's region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Within {
+    /// NOT_WITHIN, the entry (or every entry in the node) is skipped.
+    Not = 0,
+    /// PARTLY_WITHIN, only part of the node overlaps the region.
+    Partly = 1,
+    /// FULLY_WITHIN, the entry or node is entirely within the region.
+    Fully = 2,
+}
+
+impl Within {
+    fn from_raw(raw: c_int) -> Within {
+        match raw {
+            0 => Within::Not,
+            1 => Within::Partly,
+            _ => Within::Fully,
+        }
+    }
+}
+
+/// The bounding box of an R*Tree node or entry: a (min, max) range for each
+/// dimension, stored as `[min0, max0, min1, max1, ...]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox<'a> {
+    coords: &'a [f64],
+}
+
+impl<'a> BoundingBox<'a> {
+    pub fn new(coords: &'a [f64]) -> BoundingBox<'a> {
+        BoundingBox { coords }
+    }
+
+    /// The raw coordinates, as (min, max) pairs.
+    pub fn coords(&self) -> &'a [f64] {
+        self.coords
+    }
+
+    /// The number of dimensions, between 1 and 5.
+    pub fn dimensions(&self) -> usize {
+        self.coords.len() / 2
+    }
+
+    /// The (min, max) range of the given dimension, None if out of bounds.
+    pub fn range(&self, dimension: usize) -> Option<(f64, f64)> {
+        match self.coords.get(dimension * 2..dimension * 2 + 2) {
+            Some([min, max]) => Some((*min, *max)),
+            _ => None,
+        }
+    }
+
+    /// The (min, max) range of every dimension.
+    pub fn ranges(&self) -> impl Iterator<Item = (f64, f64)> + 'a {
+        self.coords
+            .chunks_exact(2)
+            .map(|range| (range[0], range[1]))
+    }
+
+    /// Whether the point is inside the box (inclusive), false if the point
+    /// has a different number of dimensions.
+    pub fn contains(&self, point: &[f64]) -> bool {
+        point.len() == self.dimensions()
+            && self
+                .ranges()
+                .zip(point)
+                .all(|((min, max), p)| min <= *p && *p <= max)
+    }
+
+    /// Whether the two boxes overlap (inclusive), false if they have a
+    /// different number of dimensions.
+    pub fn intersects(&self, other: &BoundingBox) -> bool {
+        self.dimensions() == other.dimensions()
+            && self
+                .ranges()
+                .zip(other.ranges())
+                .all(|((min, max), (other_min, other_max))| min <= other_max && other_min <= max)
+    }
+}
+
+/// The node or entry being checked by a query callback, a wrapper around
+/// [`sqlite3_rtree_query_info`](https://www.sqlite.org/rtree.html#the_new_xqueryfunc_callback).
+pub struct QueryInfo<'a> {
+    info: &'a mut sqlite3_rtree_query_info,
+}
+
+impl<'a> QueryInfo<'a> {
+    /// The arguments passed to the SQL function, as doubles.
+    pub fn params(&self) -> &[f64] {
+        unsafe { raw_slice(self.info.aParam, self.info.nParam) }
+    }
+
+    /// The `i`th argument passed to the SQL function, or an error if the
+    /// function was called with fewer arguments.
+    pub fn param(&self, i: usize) -> Result<f64> {
+        self.params().get(i).copied().ok_or_else(|| {
+            Error::new_message(format!(
+                "expected at least {} arguments, found {}",
+                i + 1,
+                self.params().len()
+            ))
+        })
+    }
+
+    /// The arguments passed to the SQL function, as the original values.
+    pub fn sql_params(&self) -> &[*mut sqlite3_value] {
+        unsafe { raw_slice(self.info.apSqlParam, self.info.nParam) }
+    }
+
+    /// The bounding box of the node or entry.
+    pub fn bounding_box(&self) -> BoundingBox<'_> {
+        BoundingBox::new(unsafe { raw_slice(self.info.aCoord, self.info.nCoord) })
+    }
+
+    /// The depth in the tree, 0 for leaf entries.
+    pub fn level(&self) -> i32 {
+        self.info.iLevel
+    }
+
+    /// The level of the root node.
+    pub fn max_level(&self) -> i32 {
+        self.info.mxLevel
+    }
+
+    /// Whether this is an entry in the table, and not an interior node.
+    pub fn is_entry(&self) -> bool {
+        self.info.iLevel == 0
+    }
+
+    /// The rowid of the entry, None for interior nodes.
+    pub fn rowid(&self) -> Option<i64> {
+        self.is_entry().then_some(self.info.iRowid)
+    }
+
+    /// The number of nodes or entries still queued at the given level,
+    /// None if the level is out of bounds.
+    pub fn queue_len(&self, level: usize) -> Option<u32> {
+        let levels = self.info.mxLevel.saturating_add(1);
+        unsafe { raw_slice(self.info.anQueue, levels) }
+            .get(level)
+            .copied()
+    }
+
+    pub fn parent_score(&self) -> f64 {
+        self.info.rParentScore
+    }
+
+    pub fn parent_within(&self) -> Within {
+        Within::from_raw(self.info.eParentWithin)
+    }
+
+    /// Defaults to the parent's visibility.
+    pub fn set_within(&mut self, within: Within) {
+        self.info.eWithin = within as c_int;
+    }
+
+    /// Smaller scores are returned first. Defaults to the parent's score.
+    pub fn set_score(&mut self, score: f64) {
+        self.info.rScore = score;
+    }
+
+    /// State that's kept between calls for the same query (ex a polygon
+    /// parsed from the arguments on the first call), created with
+    /// `T::default()` on first use. Asking for another type than the last
+    /// call did replaces the state with that type's default.
+    pub fn user_data<T: Any + Default>(&mut self) -> &mut T {
+        unsafe extern "C" fn destroy_user_data(p: *mut c_void) {
+            drop(Box::from_raw(p.cast::<Box<dyn Any>>()));
+        }
+        if self.info.pUser.is_null() {
+            let data: Box<dyn Any> = Box::new(T::default());
+            self.info.pUser = Box::into_raw(Box::new(data)).cast::<c_void>();
+            self.info.xDelUser = Some(destroy_user_data);
+        }
+        let data = unsafe { &mut *self.info.pUser.cast::<Box<dyn Any>>() };
+        if !data.is::<T>() {
+            *data = Box::new(T::default());
+        }
+        data.downcast_mut::<T>().expect("user data of type T")
+    }
+}
+
+unsafe fn raw_slice<'a, T>(pointer: *const T, n: c_int) -> &'a [T] {
+    if pointer.is_null() || n <= 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(pointer, n as usize)
+    }
+}
+
+type GeomCallback =
+    unsafe extern "C" fn(*mut sqlite3_rtree_geometry, c_int, *mut f64, *mut c_int) -> c_int;
+type QueryCallback = unsafe extern "C" fn(*mut sqlite3_rtree_query_info) -> c_int;
+
+unsafe extern "C" fn destroy_callback<F>(p: *mut c_void) {
+    drop(Box::from_raw(p.cast::<F>()));
+}
+
+/// Defines `name` as an SQL function for R*Tree MATCH constraints, ex
+/// `WHERE id MATCH circle(x, y, radius)`. The callback is called on every
+/// node and entry that the query visits, and uses
+/// [`QueryInfo::set_within`] and [`QueryInfo::set_score`] to prune nodes
+/// and order results. Returning an error aborts the query.
+pub fn define_rtree_query_function<F>(db: *mut sqlite3, name: &str, callback: F) -> Result<()>
+where
+    F: Fn(&mut QueryInfo) -> Result<()>,
+{
+    unsafe extern "C" fn x_query_func<F>(info: *mut sqlite3_rtree_query_info) -> c_int
+    where
+        F: Fn(&mut QueryInfo) -> Result<()>,
+    {
+        let callback = (*info).pContext.cast::<F>();
+        let mut info = QueryInfo { info: &mut *info };
+        match (*callback)(&mut info) {
+            Ok(()) => 0,
+            Err(err) => err.code(),
+        }
+    }
+    let context = Box::into_raw(Box::new(callback));
+    register(
+        db,
+        name,
+        None,
+        Some(x_query_func::<F>),
+        context.cast::<c_void>(),
+        Some(destroy_callback::<F>),
+    )
+}
+
+/// Defines `name` as an SQL function for R*Tree MATCH constraints, with a
+/// callback given the function's arguments and a node or entry's bounding
+/// box, returning whether it overlaps the query's region.
+pub fn define_rtree_geometry_function<F>(db: *mut sqlite3, name: &str, callback: F) -> Result<()>
+where
+    F: Fn(&[f64], BoundingBox) -> Result<bool>,
+{
+    unsafe extern "C" fn x_geom<F>(
+        geometry: *mut sqlite3_rtree_geometry,
+        n_coord: c_int,
+        a_coord: *mut f64,
+        res: *mut c_int,
+    ) -> c_int
+    where
+        F: Fn(&[f64], BoundingBox) -> Result<bool>,
+    {
+        let callback = (*geometry).pContext.cast::<F>();
+        let params = raw_slice((*geometry).aParam, (*geometry).nParam);
+        let bounding_box = BoundingBox::new(raw_slice(a_coord, n_coord));
+        match (*callback)(params, bounding_box) {
+            Ok(overlaps) => {
+                *res = c_int::from(overlaps);
+                0
+            }
+            Err(err) => err.code(),
+        }
+    }
+    let context = Box::into_raw(Box::new(callback));
+    register(
+        db,
+        name,
+        Some(x_geom::<F>),
+        None,
+        context.cast::<c_void>(),
+        Some(destroy_callback::<F>),
+    )
+}
+
+fn register_error(name: &str, rc: c_int) -> Error {
+    Error::new_message(format!(
+        "could not register R*Tree callback {}, error code {}",
+        name, rc
+    ))
+}
+
+#[cfg(feature = "static")]
+fn register(
+    db: *mut sqlite3,
+    name: &str,
+    x_geom: Option<GeomCallback>,
+    x_query_func: Option<QueryCallback>,
+    context: *mut c_void,
+    destructor: Option<unsafe extern "C" fn(*mut c_void)>,
+) -> Result<()> {
+    let cname = CString::new(name)?;
+    let rc = unsafe {
+        match (x_geom, x_query_func) {
+            (Some(x_geom), _) => {
+                // the legacy API has no destructor, so the callback is leaked
+                libsqlite3_sys::sqlite3_rtree_geometry_callback(
+                    db,
+                    cname.as_ptr(),
+                    Some(x_geom),
+                    context,
+                )
+            }
+            _ => libsqlite3_sys::sqlite3_rtree_query_callback(
+                db,
+                cname.as_ptr(),
+                x_query_func,
+                context,
+                destructor,
+            ),
+        }
+    };
+    if rc != 0 {
+        return Err(register_error(name, rc));
+    }
+    Ok(())
+}
+
+/// Mirrors `RtreeGeomCallback` in SQLite's rtree.c.
+#[cfg(not(feature = "static"))]
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RtreeGeomCallback {
+    x_geom: Option<GeomCallback>,
+    x_query_func: Option<QueryCallback>,
+    x_destructor: Option<unsafe extern "C" fn(*mut c_void)>,
+    p_context: *mut c_void,
+}
+
+/// Mirrors `RtreeMatchArg` in SQLite's rtree.c, which the R*Tree module
+/// copies `i_size` bytes of. `n_param` doubles follow the header, then
+/// `n_param` duplicated values that `ap_sql_param` points to.
+#[cfg(not(feature = "static"))]
+#[repr(C)]
+struct RtreeMatchArg {
+    i_size: u32,
+    cb: RtreeGeomCallback,
+    n_param: c_int,
+    ap_sql_param: *mut *mut sqlite3_value,
+    a_param: [f64; 0],
+}
+
+#[cfg(not(feature = "static"))]
+fn match_arg_layout(n_param: usize) -> std::alloc::Layout {
+    let size = std::mem::size_of::<RtreeMatchArg>()
+        + n_param * std::mem::size_of::<f64>()
+        + n_param * std::mem::size_of::<*mut sqlite3_value>();
+    std::alloc::Layout::from_size_align(size, std::mem::align_of::<RtreeMatchArg>())
+        .expect("valid RtreeMatchArg layout")
+}
+
+#[cfg(not(feature = "static"))]
+unsafe extern "C" fn free_match_arg(p: *mut c_void) {
+    use crate::ext::sqlite3ext_value_free;
+    let arg = p.cast::<RtreeMatchArg>();
+    let n_param = (*arg).n_param as usize;
+    for value in slice::from_raw_parts((*arg).ap_sql_param, n_param) {
+        sqlite3ext_value_free(*value);
+    }
+    std::alloc::dealloc(p.cast::<u8>(), match_arg_layout(n_param));
+}
+
+/// Same as rtree.c's geomCallback(): packs the callback and the function's
+/// arguments into an RtreeMatchArg, which the R*Tree module reads from
+/// MATCH's right-hand side.
+#[cfg(not(feature = "static"))]
+unsafe extern "C" fn match_arg_function(
+    context: *mut crate::ext::sqlite3_context,
+    argc: c_int,
+    argv: *mut *mut sqlite3_value,
+) {
+    use crate::ext::{
+        sqlite3ext_result_pointer, sqlite3ext_user_data, sqlite3ext_value_double,
+        sqlite3ext_value_dup,
+    };
+    let cb = *sqlite3ext_user_data(context).cast::<RtreeGeomCallback>();
+    let args = raw_slice(argv, argc);
+    let layout = match_arg_layout(args.len());
+    let arg = std::alloc::alloc_zeroed(layout).cast::<RtreeMatchArg>();
+    if arg.is_null() {
+        std::alloc::handle_alloc_error(layout);
+    }
+    let params = std::ptr::addr_of_mut!((*arg).a_param).cast::<f64>();
+    let values = params.add(args.len()).cast::<*mut sqlite3_value>();
+    (*arg).i_size = layout.size() as u32;
+    (*arg).cb = cb;
+    (*arg).n_param = args.len() as c_int;
+    (*arg).ap_sql_param = values;
+    for (i, value) in args.iter().enumerate() {
+        *params.add(i) = sqlite3ext_value_double(*value);
+        *values.add(i) = sqlite3ext_value_dup(*value);
+        if (*values.add(i)).is_null() {
+            free_match_arg(arg.cast::<c_void>());
+            crate::api::result_error_code(context, sqlite3ext_sys::SQLITE_NOMEM as c_int);
+            return;
+        }
+    }
+    sqlite3ext_result_pointer(
+        context,
+        arg.cast::<c_void>(),
+        c"RtreeMatchArg".as_ptr().cast_mut(),
+        Some(free_match_arg),
+    );
+}
+
+#[cfg(not(feature = "static"))]
+unsafe extern "C" fn destroy_match_arg_function(p: *mut c_void) {
+    let cb = Box::from_raw(p.cast::<RtreeGeomCallback>());
+    if let Some(x_destructor) = cb.x_destructor {
+        x_destructor(cb.p_context);
+    }
+}
+
+#[cfg(not(feature = "static"))]
+fn register(
+    db: *mut sqlite3,
+    name: &str,
+    x_geom: Option<GeomCallback>,
+    x_query_func: Option<QueryCallback>,
+    context: *mut c_void,
+    destructor: Option<unsafe extern "C" fn(*mut c_void)>,
+) -> Result<()> {
+    use crate::ext::sqlite3ext_create_function_v2;
+    use sqlite3ext_sys::SQLITE_UTF8;
+    let cname = CString::new(name)?;
+    let cb = Box::into_raw(Box::new(RtreeGeomCallback {
+        x_geom,
+        x_query_func,
+        x_destructor: destructor,
+        p_context: context,
+    }));
+    // on failure, SQLite calls the destructor itself
+    let rc = unsafe {
+        sqlite3ext_create_function_v2(
+            db,
+            cname.as_ptr(),
+            -1,
+            SQLITE_UTF8 as c_int,
+            cb.cast::<c_void>(),
+            Some(match_arg_function),
+            None,
+            None,
+            Some(destroy_match_arg_function),
+        )
+    };
+    if rc != 0 {
+        return Err(register_error(name, rc));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rtree::*;
+
+    #[test]
+    fn test_bounding_box() {
+        let a = BoundingBox::new(&[0.0, 2.0, 10.0, 20.0]);
+        assert_eq!(a.dimensions(), 2);
+        assert_eq!(a.range(1), Some((10.0, 20.0)));
+        assert_eq!(a.range(2), None);
+        assert!(a.contains(&[2.0, 10.0]));
+        assert!(!a.contains(&[3.0, 10.0]));
+        assert!(!a.contains(&[1.0]));
+        assert!(a.intersects(&BoundingBox::new(&[2.0, 5.0, 0.0, 10.0])));
+        assert!(!a.intersects(&BoundingBox::new(&[2.5, 5.0, 0.0, 10.0])));
+        assert!(!a.intersects(&BoundingBox::new(&[0.0, 1.0])));
+    }
+
+    #[test]
+    fn test_user_data() {
+        let mut raw: sqlite3_rtree_query_info = unsafe { std::mem::zeroed() };
+        let mut info = QueryInfo { info: &mut raw };
+        *info.user_data::<u64>() += 1;
+        *info.user_data::<u64>() += 1;
+        assert_eq!(*info.user_data::<u64>(), 2);
+        // another type starts over instead of reading the u64 as one
+        assert!(info.user_data::<Vec<String>>().is_empty());
+        info.user_data::<Vec<String>>().push("x".to_owned());
+        assert_eq!(*info.user_data::<u64>(), 0);
+        unsafe { raw.xDelUser.unwrap()(raw.pUser) };
+    }
+}
@@ -1,4 +1,28 @@
 //! Define scalar functions on sqlite3 database connections.
+//!
+//! `#[scalar_function]` writes the glue for a plain Rust function, taking
+//! arguments that implement [`crate::FromValue`] and returning a
+//! [`ToResult`], or a [`Result`] of one:
+//!
+//! ```ignore
+//! #[scalar_function(deterministic)]
+//! fn repeat(text: &str, count: i64) -> String {
+//!     text.repeat(count.max(0) as usize)
+//! }
+//!
+//! repeat::define(db)?;
+//! ```
+//!
+//! It keeps the function, and adds a struct of the same name with `NAME`,
+//! `NUM_ARGS`, the `call` callback for [`define_scalar_function`], and
+//! `define(db)`, which registers it with those. `name = "..."` sets a
+//! different SQL name. Generic functions aren't supported.
+//!
+//! A function that takes a range of argument counts is defined with
+//! [`define_scalar_function_variadic`] and an [`Arity`], which checks the
+//! count of each call before the function sees it, and a function of
+//! numbers that can't fail with [`crate::define_pure_function`], which
+//! skips most of the glue.
 
 #![allow(clippy::not_unsafe_ptr_arg_deref)]
 use std::{
@@ -9,16 +33,20 @@ use std::{
 
 use crate::{
     api,
-    constants::{SQLITE_INTERNAL, SQLITE_OKAY},
+    args::{Arity, ToResult},
+    constants::{SQLITE_INTERNAL, SQLITE_OKAY, SQLITE_RESULT_SUBTYPE},
     errors::{Error, ErrorKind, Result},
     ext::{
         sqlite3, sqlite3_context, sqlite3_value, sqlite3ext_create_function_v2,
         sqlite3ext_user_data,
     },
+    subtype::CallScope,
 };
 
 use bitflags::bitflags;
 
+pub use sqlite_loadable_macros::scalar_function;
+
 use sqlite3ext_sys::{
     SQLITE_DETERMINISTIC, SQLITE_DIRECTONLY, SQLITE_INNOCUOUS, SQLITE_SUBTYPE, SQLITE_UTF16,
     SQLITE_UTF16BE, SQLITE_UTF16LE, SQLITE_UTF8,
@@ -42,10 +70,12 @@ bitflags! {
         const DIRECTONLY = SQLITE_DIRECTONLY as i32;
         const SUBTYPE = SQLITE_SUBTYPE as i32;
         const INNOCUOUS = SQLITE_INNOCUOUS as i32;
+        /// The function may set a subtype on its result, see [`crate::subtype`].
+        const RESULT_SUBTYPE = SQLITE_RESULT_SUBTYPE;
     }
 }
 
-fn create_function_v2(
+pub(crate) fn create_function_v2(
     db: *mut sqlite3,
     name: &str,
     num_args: c_int,
@@ -72,12 +102,29 @@ fn create_function_v2(
     };
 
     if result != SQLITE_OKAY {
-        Err(Error::new(ErrorKind::DefineScalarFunction(result)))
+        Err(Error::with_db_message(
+            ErrorKind::DefineScalarFunction(result),
+            result,
+            db,
+        ))
     } else {
         Ok(())
     }
 }
 
+/// In debug builds, tracks a call to a function with `flags`, to check the
+/// subtypes it sets against them.
+fn debug_call_scope(flags: FunctionFlags) -> Option<CallScope> {
+    cfg!(debug_assertions).then(|| CallScope::enter(flags.contains(FunctionFlags::RESULT_SUBTYPE)))
+}
+
+fn finish_debug_call_scope(scope: Option<CallScope>) -> Result<()> {
+    match scope.and_then(CallScope::finish) {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
 /// Defines a new scalar function on the given database connection.
 ///
 /// # Example
@@ -89,7 +136,12 @@ fn create_function_v2(
 ///
 /// define_scalar_function(db, "xyz_version", 0, xyz_version)?;
 /// ```
-pub fn define_scalar_function<F>(
+///
+/// The function can also return its result as any [`ToResult`]:
+/// ```rust
+/// define_scalar_function(db, "xyz_answer", 0, |_context, _values| Ok(42), FunctionFlags::UTF8)?;
+/// ```
+pub fn define_scalar_function<F, R>(
     db: *mut sqlite3,
     name: &str,
     num_args: c_int,
@@ -101,24 +153,28 @@ where
     // calling `context_result_text(context, "foo")` is long, but maybe
     // `context.result_text("foo")` with a special wrapper struct can be
     // as fast
-    F: Fn(*mut sqlite3_context, &[*mut sqlite3_value]) -> Result<()>,
+    F: Fn(*mut sqlite3_context, &[*mut sqlite3_value]) -> Result<R>,
+    R: ToResult,
 {
-    let function_pointer: *mut F = Box::into_raw(Box::new(x_func));
+    let function_pointer: *mut (F, FunctionFlags) = Box::into_raw(Box::new((x_func, func_flags)));
 
-    unsafe extern "C" fn x_func_wrapper<F>(
+    unsafe extern "C" fn x_func_wrapper<F, R>(
         context: *mut sqlite3_context,
         argc: c_int,
         argv: *mut *mut sqlite3_value,
     ) where
-        F: Fn(*mut sqlite3_context, &[*mut sqlite3_value]) -> Result<()>,
+        F: Fn(*mut sqlite3_context, &[*mut sqlite3_value]) -> Result<R>,
+        R: ToResult,
     {
-        let boxed_function: *mut F = sqlite3ext_user_data(context).cast::<F>();
+        let (boxed_function, flags) = &*sqlite3ext_user_data(context).cast::<(F, FunctionFlags)>();
         // .collect slows things waaaay down, so stick with slice for now
         let args = slice::from_raw_parts(argv, argc as usize);
-        match (*boxed_function)(context, args) {
+        let scope = debug_call_scope(*flags);
+        let result = boxed_function(context, args).and_then(|result| result.to_result(context));
+        match result.and_then(|()| finish_debug_call_scope(scope)) {
             Ok(()) => (),
             Err(e) => {
-                if api::result_error(context, &e.result_error_message()).is_err() {
+                if api::result_error(context, &e.reported_message()).is_err() {
                     api::result_error_code(context, SQLITE_INTERNAL);
                 }
             }
@@ -130,7 +186,7 @@ where
         num_args,
         func_flags,
         function_pointer.cast::<c_void>(),
-        Some(x_func_wrapper::<F>),
+        Some(x_func_wrapper::<F, R>),
         None,
         None,
         None,
@@ -141,7 +197,7 @@ where
 /// application "pointer" as any rust type. Can be accessed in the callback
 /// function as the 3rd argument, as a reference.
 /// <https://www.sqlite.org/c3ref/create_function.html#:~:text=The%20fifth%20parameter%20is%20an%20arbitrary%20pointer.>
-pub fn define_scalar_function_with_aux<F, T>(
+pub fn define_scalar_function_with_aux<F, T, R>(
     db: *mut sqlite3,
     name: &str,
     num_args: c_int,
@@ -150,29 +206,34 @@ pub fn define_scalar_function_with_aux<F, T>(
     aux: T,
 ) -> Result<()>
 where
-    F: Fn(*mut sqlite3_context, &[*mut sqlite3_value], &T) -> Result<()>,
+    F: Fn(*mut sqlite3_context, &[*mut sqlite3_value], &T) -> Result<R>,
+    R: ToResult,
 {
     let function_pointer: *mut F = Box::into_raw(Box::new(x_func));
     let aux_pointer: *mut T = Box::into_raw(Box::new(aux));
-    let app_pointer = Box::into_raw(Box::new((function_pointer, aux_pointer)));
+    let app_pointer = Box::into_raw(Box::new((function_pointer, aux_pointer, func_flags)));
 
-    unsafe extern "C" fn x_func_wrapper<F, T>(
+    unsafe extern "C" fn x_func_wrapper<F, T, R>(
         context: *mut sqlite3_context,
         argc: c_int,
         argv: *mut *mut sqlite3_value,
     ) where
-        F: Fn(*mut sqlite3_context, &[*mut sqlite3_value], &T) -> Result<()>,
+        F: Fn(*mut sqlite3_context, &[*mut sqlite3_value], &T) -> Result<R>,
+        R: ToResult,
     {
-        let x = sqlite3ext_user_data(context).cast::<(*mut F, *mut T)>();
+        let x = sqlite3ext_user_data(context).cast::<(*mut F, *mut T, FunctionFlags)>();
         let boxed_function = (*x).0;
         let aux = (*x).1;
         // .collect slows things waaaay down, so stick with slice for now
         let args = slice::from_raw_parts(argv, argc as usize);
         let b = Box::from_raw(aux);
-        match (*boxed_function)(context, args, &*b) {
+        let scope = debug_call_scope((*x).2);
+        let result =
+            (*boxed_function)(context, args, &*b).and_then(|result| result.to_result(context));
+        match result.and_then(|()| finish_debug_call_scope(scope)) {
             Ok(()) => (),
             Err(e) => {
-                if api::result_error(context, &e.result_error_message()).is_err() {
+                if api::result_error(context, &e.reported_message()).is_err() {
                     api::result_error_code(context, SQLITE_INTERNAL);
                 }
             }
@@ -185,13 +246,47 @@ where
         num_args,
         func_flags,
         app_pointer.cast::<c_void>(),
-        Some(x_func_wrapper::<F, T>),
+        Some(x_func_wrapper::<F, T, R>),
         None,
         None,
         None,
     )
 }
 
+/// Like [`define_scalar_function`], for a function that takes a range of
+/// argument counts. It's registered as taking any number, `nArg` -1,
+/// unless `arity` is exact, and calls with a count outside `arity` fail
+/// before reaching `x_func`, with an error like
+/// `xyz_pad() takes 2 or 3 arguments, got 4`.
+///
+/// ```rust
+/// define_scalar_function_variadic(db, "xyz_max", Arity::at_least(1), xyz_max, FunctionFlags::UTF8)?;
+/// ```
+pub fn define_scalar_function_variadic<F, R>(
+    db: *mut sqlite3,
+    name: &str,
+    arity: Arity,
+    x_func: F,
+    func_flags: FunctionFlags,
+) -> Result<()>
+where
+    F: Fn(*mut sqlite3_context, &[*mut sqlite3_value]) -> Result<R>,
+    R: ToResult,
+{
+    arity.validate()?;
+    let owned_name = name.to_owned();
+    define_scalar_function(
+        db,
+        name,
+        arity.num_args(),
+        move |context, values| {
+            arity.check(&owned_name, values.len())?;
+            x_func(context, values)
+        },
+        func_flags,
+    )
+}
+
 pub fn delete_scalar_function(
     db: *mut sqlite3,
     name: &str,
@@ -211,15 +306,27 @@ pub fn delete_scalar_function(
     )
 }
 
-// TODO only used for find_function, probably can combine with that return type?
+/// The C function SQLite calls for `x_func`, for overloading a function in
+/// [`VTabFind::find_function`](crate::table::VTabFind::find_function)
+/// without a user data pointer.
+///
+/// `x_func` has to be a function or a closure that captures nothing, since
+/// it's called from nothing but the returned function's type, and anything
+/// else fails to compile. Use [`scalar_function_raw_with_aux`] for ones that
+/// need state.
 pub fn scalar_function_raw<F>(
     x_func: F,
 ) -> unsafe extern "C" fn(*mut sqlite3_context, i32, *mut *mut sqlite3_value)
 where
     F: Fn(*mut sqlite3_context, &[*mut sqlite3_value]) -> Result<()>,
 {
-    // TODO: how does x_func even get called here???
-    let _function_pointer: *mut F = Box::into_raw(Box::new(x_func));
+    const {
+        assert!(
+            std::mem::size_of::<F>() == 0,
+            "scalar_function_raw() takes functions and closures that capture nothing"
+        )
+    };
+    std::mem::forget(x_func);
 
     unsafe extern "C" fn x_func_wrapper<F>(
         context: *mut sqlite3_context,
@@ -228,12 +335,13 @@ where
     ) where
         F: Fn(*mut sqlite3_context, &[*mut sqlite3_value]) -> Result<()>,
     {
-        let boxed_function: *mut F = sqlite3ext_user_data(context).cast::<F>();
+        // F has no data, so any aligned pointer is one to a valid F
+        let function: &F = &*std::ptr::NonNull::<F>::dangling().as_ptr();
         let args = slice::from_raw_parts(argv, argc as usize);
-        match (*boxed_function)(context, args) {
+        match function(context, args) {
             Ok(()) => (),
             Err(e) => {
-                if api::result_error(context, &e.result_error_message()).is_err() {
+                if api::result_error(context, &e.reported_message()).is_err() {
                     api::result_error_code(context, SQLITE_INTERNAL);
                 }
             }
@@ -242,6 +350,11 @@ where
 
     x_func_wrapper::<F>
 }
+
+/// Like [`scalar_function_raw`], for a function with state, which it gets
+/// through the returned user data pointer: the third element of a
+/// [`FindResult`](crate::table::FindResult). Both are leaked, so make them
+/// once rather than on every call to `find_function`.
 pub fn scalar_function_raw_with_aux<F, T>(
     x_func: F,
     aux: T,
@@ -252,7 +365,6 @@ pub fn scalar_function_raw_with_aux<F, T>(
 where
     F: Fn(*mut sqlite3_context, &[*mut sqlite3_value], &T) -> Result<()>,
 {
-    // TODO: how does x_func even get called here???
     let function_pointer: *mut F = Box::into_raw(Box::new(x_func));
     let aux_pointer: *mut T = Box::into_raw(Box::new(aux));
     let app_pointer = Box::into_raw(Box::new((function_pointer, aux_pointer)));
@@ -273,7 +385,7 @@ where
         match (*boxed_function)(context, args, &*b) {
             Ok(()) => (),
             Err(e) => {
-                if api::result_error(context, &e.result_error_message()).is_err() {
+                if api::result_error(context, &e.reported_message()).is_err() {
                     api::result_error_code(context, SQLITE_INTERNAL);
                 }
             }
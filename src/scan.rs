@@ -0,0 +1,115 @@
+//! Fast scans over the bytes of text and blob values.
+//!
+//! Extensions that parse CSV, JSON or log lines out of a value spend most
+//! of each row looking for the next delimiter or checking that bytes are
+//! UTF-8. These helpers take the value's bytes, from
+//! [`crate::api::value_blob`], which borrows them from SQLite without a
+//! copy:
+//!
+//! ```ignore
+//! let bytes = api::value_blob(&values[0]);
+//! for line in scan::lines(bytes) {
+//!     let line = scan::validate_utf8(line)?;
+//!     // ...
+//! }
+//! ```
+//!
+//! With the `simd` feature, searches use
+//! [memchr](https://docs.rs/memchr) and UTF-8 validation
+//! [simdutf8](https://docs.rs/simdutf8), which check many bytes at once with the CPU's vector
+//! instructions. Without it they fall back to the standard library, with
+//! the same results.
+
+use std::iter::FusedIterator;
+use std::str::Utf8Error;
+
+/// Checks that `bytes` are UTF-8 and returns them as a str, like
+/// `std::str::from_utf8`.
+pub fn validate_utf8(bytes: &[u8]) -> Result<&str, Utf8Error> {
+    #[cfg(feature = "simd")]
+    if let Ok(text) = simdutf8::basic::from_utf8(bytes) {
+        return Ok(text);
+    }
+    // without simd, and for the details of where invalid bytes are
+    std::str::from_utf8(bytes)
+}
+
+/// The index of the first `needle` in `haystack`.
+pub fn find_byte(haystack: &[u8], needle: u8) -> Option<usize> {
+    #[cfg(feature = "simd")]
+    {
+        memchr::memchr(needle, haystack)
+    }
+    #[cfg(not(feature = "simd"))]
+    {
+        haystack.iter().position(|&b| b == needle)
+    }
+}
+
+/// The index of the first of any of `needles` in `haystack`, like the next
+/// delimiter, quote or line break of a CSV field. Up to three needles are
+/// searched for at once with `simd`.
+pub fn find_any(haystack: &[u8], needles: &[u8]) -> Option<usize> {
+    #[cfg(feature = "simd")]
+    match *needles {
+        [] => return None,
+        [a] => return memchr::memchr(a, haystack),
+        [a, b] => return memchr::memchr2(a, b, haystack),
+        [a, b, c] => return memchr::memchr3(a, b, c, haystack),
+        _ => (),
+    }
+    haystack.iter().position(|b| needles.contains(b))
+}
+
+/// How many times `needle` is in `haystack`.
+pub fn count_byte(haystack: &[u8], needle: u8) -> usize {
+    #[cfg(feature = "simd")]
+    {
+        memchr::memchr_iter(needle, haystack).count()
+    }
+    #[cfg(not(feature = "simd"))]
+    {
+        haystack.iter().filter(|&&b| b == needle).count()
+    }
+}
+
+/// How many lines [`lines`] splits `bytes` into: the `\n`s, plus one for
+/// text after the last of them.
+pub fn count_lines(bytes: &[u8]) -> usize {
+    let trailing = bytes.last().is_some_and(|&b| b != b'\n');
+    count_byte(bytes, b'\n') + usize::from(trailing)
+}
+
+/// Splits `bytes` into lines, like `str::lines`: at each `\n` or `\r\n`,
+/// which isn't part of the line, with no empty line after a final line
+/// break.
+pub fn lines(bytes: &[u8]) -> Lines<'_> {
+    Lines { rest: bytes }
+}
+
+/// The lines of some bytes, from [`lines`].
+#[derive(Debug, Clone)]
+pub struct Lines<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        match find_byte(self.rest, b'\n') {
+            Some(i) => {
+                let line = &self.rest[..i];
+                self.rest = &self.rest[i + 1..];
+                Some(line.strip_suffix(b"\r").unwrap_or(line))
+            }
+            // a \r without a \n after it stays part of the line
+            None => Some(std::mem::take(&mut self.rest)),
+        }
+    }
+}
+
+impl FusedIterator for Lines<'_> {}
@@ -0,0 +1,753 @@
+//! Generate virtual table schemas (the `CREATE TABLE` statements passed to
+//! [`declare_vtab`](crate::table::declare_vtab)) from a data source's own
+//! metadata, like a sample JSON record, a JSON Schema document, or an
+//! Arrow schema.
+//!
+//! [`verify`] checks the other direction: that the shadow tables a virtual
+//! table stores its data in still have the layout the extension expects.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::errors::{Error, Result};
+use crate::ext::sqlite3;
+use crate::statement::Statement;
+use serde_json::{Map, Value};
+
+/// A column in a generated schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnDefinition {
+    pub name: String,
+    /// The declared type, ex "INTEGER" or "TEXT". Empty for no declared type.
+    pub declared_type: String,
+    pub not_null: bool,
+}
+
+impl ColumnDefinition {
+    pub fn new<S: Into<String>, T: Into<String>>(name: S, declared_type: T) -> ColumnDefinition {
+        ColumnDefinition {
+            name: name.into(),
+            declared_type: declared_type.into(),
+            not_null: false,
+        }
+    }
+}
+
+/// Quotes an identifier so any column name is valid in a schema,
+/// ex `my "col"` becomes `"my ""col"""`.
+pub fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// A column's definition in a `CREATE TABLE` statement, ex `"id" INTEGER NOT NULL`.
+fn column_sql(column: &ColumnDefinition) -> String {
+    let mut definition = quote_identifier(&column.name);
+    if !column.declared_type.is_empty() {
+        definition.push(' ');
+        definition.push_str(&column.declared_type);
+    }
+    if column.not_null {
+        definition.push_str(" NOT NULL");
+    }
+    definition
+}
+
+fn column_list(columns: &[ColumnDefinition]) -> String {
+    columns
+        .iter()
+        .map(column_sql)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Builds the `CREATE TABLE x(...)` statement for the given columns.
+pub fn create_table_sql(columns: &[ColumnDefinition]) -> String {
+    format!("CREATE TABLE x({})", column_list(columns))
+}
+
+/// Builds a `CREATE TABLE` statement for
+/// [`declare_vtab`](crate::table::declare_vtab) from typed columns, quoting
+/// every name and checking the declaration before SQLite sees it.
+///
+/// ```ignore
+/// let sql = SchemaBuilder::new()
+///     .add_column("key", "TEXT", false, true)
+///     .add_column("value", "", false, false)
+///     .add_column("path", "TEXT", true, false)
+///     .without_rowid()
+///     .build()?;
+/// // CREATE TABLE x("key" TEXT, "value", "path" TEXT HIDDEN, PRIMARY KEY("key")) WITHOUT ROWID
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SchemaBuilder {
+    columns: Vec<SchemaColumn>,
+    without_rowid: bool,
+    strict: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SchemaColumn {
+    name: String,
+    declared_type: String,
+    hidden: bool,
+    primary_key: bool,
+}
+
+/// The only types a STRICT table allows.
+const STRICT_TYPES: [&str; 6] = ["INT", "INTEGER", "REAL", "TEXT", "BLOB", "ANY"];
+
+/// Words that start a column constraint, or make a virtual table's column
+/// hidden, rather than being part of a type name.
+const CONSTRAINT_KEYWORDS: [&str; 12] = [
+    "AS",
+    "CHECK",
+    "COLLATE",
+    "CONSTRAINT",
+    "DEFAULT",
+    "GENERATED",
+    "HIDDEN",
+    "NOT",
+    "NULL",
+    "PRIMARY",
+    "REFERENCES",
+    "UNIQUE",
+];
+
+/// Whether `declared_type` is empty or a type name as SQLite parses one:
+/// words, then optionally one or two numbers in parentheses, ex "TEXT",
+/// "UNSIGNED BIG INT", "VARCHAR(10)" or "DECIMAL(10, 2)". None of the words
+/// can be a [`CONSTRAINT_KEYWORDS`] one.
+fn is_type_name(declared_type: &str) -> bool {
+    let (name, size) = match declared_type.split_once('(') {
+        Some((name, rest)) => match rest.strip_suffix(')') {
+            Some(size) => (name, Some(size)),
+            None => return false,
+        },
+        None => (declared_type, None),
+    };
+    let is_word = |word: &str| {
+        word.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && word.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && !CONSTRAINT_KEYWORDS
+                .iter()
+                .any(|keyword| keyword.eq_ignore_ascii_case(word))
+    };
+    let is_number = |number: &str| {
+        let number = number.trim();
+        let digits = number.strip_prefix(['+', '-']).unwrap_or(number);
+        digits.starts_with(|c: char| c.is_ascii_digit())
+            && digits.chars().all(|c| c.is_ascii_digit() || c == '.')
+    };
+    let mut words = name.split(' ').filter(|word| !word.is_empty()).peekable();
+    if size.is_some() && words.peek().is_none() {
+        return false;
+    }
+    words.all(is_word)
+        && size.map_or(true, |size| {
+            let numbers: Vec<&str> = size.split(',').collect();
+            numbers.len() <= 2 && numbers.into_iter().all(is_number)
+        })
+}
+
+impl SchemaBuilder {
+    pub fn new() -> SchemaBuilder {
+        SchemaBuilder::default()
+    }
+
+    /// Adds a column. `declared_type` can be empty for no type. Hidden columns
+    /// are left out of `SELECT *` and are how table-valued functions take
+    /// arguments. Several primary key columns make a composite key.
+    pub fn add_column(
+        mut self,
+        name: &str,
+        declared_type: &str,
+        hidden: bool,
+        primary_key: bool,
+    ) -> SchemaBuilder {
+        self.columns.push(SchemaColumn {
+            name: name.to_owned(),
+            declared_type: declared_type.trim().to_owned(),
+            hidden,
+            primary_key,
+        });
+        self
+    }
+
+    /// Declares the table WITHOUT ROWID, which needs a primary key.
+    pub fn without_rowid(mut self) -> SchemaBuilder {
+        self.without_rowid = true;
+        self
+    }
+
+    /// Declares the table STRICT, which limits declared types to INT,
+    /// INTEGER, REAL, TEXT, BLOB and ANY. HIDDEN is part of a column's type
+    /// as far as SQLite is concerned, so STRICT tables can't have hidden
+    /// columns.
+    pub fn strict(mut self) -> SchemaBuilder {
+        self.strict = true;
+        self
+    }
+
+    /// Checks the columns and renders the statement.
+    pub fn build(&self) -> Result<String> {
+        if self.columns.is_empty() {
+            return Err(Error::new_message("a schema needs at least one column"));
+        }
+        let mut definitions = Vec::with_capacity(self.columns.len() + 1);
+        for (i, column) in self.columns.iter().enumerate() {
+            if column.name.is_empty() || column.name.contains('\0') {
+                return Err(Error::new_message(format!(
+                    "invalid column name {:?}",
+                    column.name
+                )));
+            }
+            if self.columns[..i]
+                .iter()
+                .any(|other| other.name.eq_ignore_ascii_case(&column.name))
+            {
+                return Err(Error::new_message(format!(
+                    "duplicate column name {:?}",
+                    column.name
+                )));
+            }
+            // type names are pasted in as-is, so they can't add columns
+            if !is_type_name(&column.declared_type) {
+                return Err(Error::new_message(format!(
+                    "invalid declared type {:?} for column {:?}",
+                    column.declared_type, column.name
+                )));
+            }
+            if self.strict
+                && !STRICT_TYPES
+                    .iter()
+                    .any(|t| t.eq_ignore_ascii_case(&column.declared_type))
+            {
+                return Err(Error::new_message(format!(
+                    "column {:?} has type {:?}, but STRICT tables only allow {}",
+                    column.name,
+                    column.declared_type,
+                    STRICT_TYPES.join(", ")
+                )));
+            }
+            if self.strict && column.hidden {
+                return Err(Error::new_message(format!(
+                    "column {:?} is HIDDEN, which STRICT tables don't allow",
+                    column.name
+                )));
+            }
+            let mut definition = quote_identifier(&column.name);
+            if !column.declared_type.is_empty() {
+                definition.push(' ');
+                definition.push_str(&column.declared_type);
+            }
+            if column.hidden {
+                definition.push_str(" HIDDEN");
+            }
+            definitions.push(definition);
+        }
+        let primary_key: Vec<String> = self
+            .columns
+            .iter()
+            .filter(|column| column.primary_key)
+            .map(|column| quote_identifier(&column.name))
+            .collect();
+        if !primary_key.is_empty() {
+            definitions.push(format!("PRIMARY KEY({})", primary_key.join(", ")));
+        } else if self.without_rowid {
+            return Err(Error::new_message(
+                "a WITHOUT ROWID table needs a primary key",
+            ));
+        }
+        let mut options = vec![];
+        if self.without_rowid {
+            options.push("WITHOUT ROWID");
+        }
+        if self.strict {
+            options.push("STRICT");
+        }
+        let mut sql = format!("CREATE TABLE x({})", definitions.join(", "));
+        if !options.is_empty() {
+            sql.push(' ');
+            sql.push_str(&options.join(", "));
+        }
+        Ok(sql)
+    }
+}
+
+/// The expected layout of one shadow table, named `{vtab}_{suffix}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShadowTable {
+    pub suffix: String,
+    pub columns: Vec<ColumnDefinition>,
+}
+
+impl ShadowTable {
+    pub fn new<S: Into<String>>(suffix: S, columns: Vec<ColumnDefinition>) -> ShadowTable {
+        ShadowTable {
+            suffix: suffix.into(),
+            columns,
+        }
+    }
+
+    /// The name of this shadow table for the virtual table `vtab_name`.
+    pub fn table_name(&self, vtab_name: &str) -> String {
+        format!("{}_{}", vtab_name, self.suffix)
+    }
+
+    /// The `CREATE TABLE` statement for this shadow table, for xCreate.
+    pub fn create_sql(&self, schema: &str, vtab_name: &str) -> String {
+        format!(
+            "CREATE TABLE {}.{}({})",
+            quote_identifier(schema),
+            quote_identifier(&self.table_name(vtab_name)),
+            column_list(&self.columns)
+        )
+    }
+}
+
+/// The columns of `schema.table` as they are on disk, or None if there's no
+/// such table.
+fn table_columns(
+    db: *mut sqlite3,
+    schema: &str,
+    table: &str,
+) -> Result<Option<Vec<ColumnDefinition>>> {
+    let mut stmt = Statement::prepare(
+        db,
+        "select name, type, \"notnull\" from pragma_table_xinfo(?1, ?2)",
+    )?;
+    stmt.bind_text(1, table)?;
+    stmt.bind_text(2, schema)?;
+    let mut columns = vec![];
+    while stmt.step()? {
+        columns.push(ColumnDefinition {
+            name: stmt.column_string(0),
+            declared_type: stmt.column_string(1),
+            not_null: stmt.column_int64(2) != 0,
+        });
+    }
+    Ok(if columns.is_empty() {
+        None
+    } else {
+        Some(columns)
+    })
+}
+
+/// What differs between the expected and actual columns of one shadow table,
+/// as human-readable problems.
+fn diff_columns(
+    table: &str,
+    expected: &[ColumnDefinition],
+    actual: &[ColumnDefinition],
+) -> Vec<String> {
+    let mut problems = vec![];
+    for column in expected {
+        match actual
+            .iter()
+            .find(|other| other.name.eq_ignore_ascii_case(&column.name))
+        {
+            None => problems.push(format!(
+                "{} is missing column {:?} (add it with: ALTER TABLE {} ADD COLUMN {})",
+                table,
+                column.name,
+                quote_identifier(table),
+                column_sql(column),
+            )),
+            Some(other) => {
+                if !other
+                    .declared_type
+                    .trim()
+                    .eq_ignore_ascii_case(column.declared_type.trim())
+                {
+                    problems.push(format!(
+                        "column {:?} of {} has type {:?}, expected {:?}",
+                        column.name, table, other.declared_type, column.declared_type
+                    ));
+                }
+                if other.not_null != column.not_null {
+                    problems.push(format!(
+                        "column {:?} of {} is {}, expected {}",
+                        column.name,
+                        table,
+                        if other.not_null {
+                            "NOT NULL"
+                        } else {
+                            "nullable"
+                        },
+                        if column.not_null {
+                            "NOT NULL"
+                        } else {
+                            "nullable"
+                        },
+                    ));
+                }
+            }
+        }
+    }
+    for other in actual {
+        if !expected
+            .iter()
+            .any(|column| column.name.eq_ignore_ascii_case(&other.name))
+        {
+            problems.push(format!("{} has unexpected column {:?}", table, other.name));
+        }
+    }
+    problems
+}
+
+/// Checks that the shadow tables of the virtual table `schema.vtab_name` have
+/// the expected columns, types and NOT NULL constraints. Call it from
+/// xConnect, so a database written by an older or newer version of an
+/// extension fails loudly instead of being read or written with the wrong
+/// layout. The error lists every difference found, with the `CREATE TABLE`
+/// or `ALTER TABLE` statement that would fix missing tables and columns.
+pub fn verify(
+    db: *mut sqlite3,
+    schema: &str,
+    vtab_name: &str,
+    shadow_tables: &[ShadowTable],
+) -> Result<()> {
+    let mut problems = vec![];
+    for shadow_table in shadow_tables {
+        let table = shadow_table.table_name(vtab_name);
+        match table_columns(db, schema, &table)? {
+            None => problems.push(format!(
+                "shadow table {} is missing (create it with: {})",
+                table,
+                shadow_table.create_sql(schema, vtab_name)
+            )),
+            Some(actual) => problems.extend(diff_columns(&table, &shadow_table.columns, &actual)),
+        }
+    }
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::new_message(format!(
+            "shadow tables of {}.{} don't match this version of the extension, a migration is needed: {}",
+            schema,
+            vtab_name,
+            problems.join("; ")
+        )))
+    }
+}
+
+/// SQL type for a single JSON value, None for null.
+fn json_value_type(value: &Value) -> Option<&'static str> {
+    match value {
+        Value::Null => None,
+        Value::Bool(_) => Some("INTEGER"),
+        Value::Number(n) if n.is_i64() || n.is_u64() => Some("INTEGER"),
+        Value::Number(_) => Some("REAL"),
+        Value::String(_) => Some("TEXT"),
+        // nested arrays and objects are stored as JSON text
+        Value::Array(_) | Value::Object(_) => Some("TEXT"),
+    }
+}
+
+/// Picks a type that fits values of both types: INTEGER and REAL become REAL,
+/// anything else mixed becomes TEXT.
+fn widen(a: Option<&'static str>, b: Option<&'static str>) -> Option<&'static str> {
+    match (a, b) {
+        (None, t) | (t, None) => t,
+        (Some(a), Some(b)) if a == b => Some(a),
+        (Some("INTEGER"), Some("REAL")) | (Some("REAL"), Some("INTEGER")) => Some("REAL"),
+        _ => Some("TEXT"),
+    }
+}
+
+/// Infers columns from a sample JSON object, or an array of objects whose
+/// keys are merged. Columns appear in the order they're first seen (note that
+/// serde_json sorts object keys unless its "preserve_order" feature is
+/// enabled), and columns with differing types across records are widened.
+pub fn columns_from_json_sample(sample: &Value) -> Result<Vec<ColumnDefinition>> {
+    let records: Vec<&Map<String, Value>> = match sample {
+        Value::Object(record) => vec![record],
+        Value::Array(records) => records
+            .iter()
+            .map(|record| {
+                record.as_object().ok_or_else(|| {
+                    Error::new_message("every record in a JSON sample must be an object")
+                })
+            })
+            .collect::<Result<_>>()?,
+        _ => {
+            return Err(Error::new_message(
+                "a JSON sample must be an object or an array of objects",
+            ))
+        }
+    };
+    let mut columns: Vec<(String, Option<&'static str>)> = vec![];
+    for record in records {
+        for (key, value) in record {
+            match columns.iter_mut().find(|(name, _)| name == key) {
+                Some((_, t)) => *t = widen(*t, json_value_type(value)),
+                None => columns.push((key.clone(), json_value_type(value))),
+            }
+        }
+    }
+    Ok(columns
+        .into_iter()
+        .map(|(name, t)| ColumnDefinition::new(name, t.unwrap_or("")))
+        .collect())
+}
+
+/// Generates a schema from a sample JSON record, see [`columns_from_json_sample`].
+pub fn ddl_from_json_sample(sample: &Value) -> Result<String> {
+    Ok(create_table_sql(&columns_from_json_sample(sample)?))
+}
+
+fn json_schema_type(name: &str) -> Option<&'static str> {
+    match name {
+        "integer" | "boolean" => Some("INTEGER"),
+        "number" => Some("REAL"),
+        "string" | "array" | "object" => Some("TEXT"),
+        _ => None,
+    }
+}
+
+/// Infers columns from a [JSON Schema](https://json-schema.org) document
+/// describing a record, from its top-level "properties". Properties listed in
+/// "required" that can't be null are declared NOT NULL.
+pub fn columns_from_json_schema(schema: &Value) -> Result<Vec<ColumnDefinition>> {
+    let properties = schema
+        .get("properties")
+        .and_then(Value::as_object)
+        .ok_or_else(|| Error::new_message("JSON Schema must have an object of \"properties\""))?;
+    let required: Vec<&str> = schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|required| required.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    let columns = properties
+        .iter()
+        .map(|(name, property)| {
+            // "type" is either a single type name, or a list like ["string", "null"]
+            let types: Vec<&str> = match property.get("type") {
+                Some(Value::String(t)) => vec![t.as_str()],
+                Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).collect(),
+                _ => vec![],
+            };
+            let declared_type = types
+                .iter()
+                .map(|t| json_schema_type(t))
+                .reduce(widen)
+                .flatten()
+                .unwrap_or("");
+            ColumnDefinition {
+                name: name.clone(),
+                declared_type: declared_type.to_owned(),
+                not_null: required.contains(&name.as_str()) && !types.contains(&"null"),
+            }
+        })
+        .collect();
+    Ok(columns)
+}
+
+/// Generates a schema from a JSON Schema document, see [`columns_from_json_schema`].
+pub fn ddl_from_json_schema(schema: &Value) -> Result<String> {
+    Ok(create_table_sql(&columns_from_json_schema(schema)?))
+}
+
+/// Infers columns from an Arrow schema. Non-nullable fields are declared NOT
+/// NULL, and nested or unusual types are declared as TEXT or BLOB.
+#[cfg(feature = "arrow")]
+pub fn columns_from_arrow_schema(schema: &arrow_schema::Schema) -> Vec<ColumnDefinition> {
+    use arrow_schema::DataType;
+    schema
+        .fields()
+        .iter()
+        .map(|field| {
+            let declared_type = match field.data_type() {
+                DataType::Null => "",
+                DataType::Boolean
+                | DataType::Int8
+                | DataType::Int16
+                | DataType::Int32
+                | DataType::Int64
+                | DataType::UInt8
+                | DataType::UInt16
+                | DataType::UInt32
+                | DataType::UInt64
+                | DataType::Date32
+                | DataType::Date64
+                | DataType::Time32(_)
+                | DataType::Time64(_)
+                | DataType::Timestamp(_, _)
+                | DataType::Duration(_) => "INTEGER",
+                DataType::Float16 | DataType::Float32 | DataType::Float64 => "REAL",
+                DataType::Binary | DataType::LargeBinary | DataType::FixedSizeBinary(_) => "BLOB",
+                _ => "TEXT",
+            };
+            ColumnDefinition {
+                name: field.name().clone(),
+                declared_type: declared_type.to_owned(),
+                not_null: !field.is_nullable(),
+            }
+        })
+        .collect()
+}
+
+/// Generates a schema from an Arrow schema, see [`columns_from_arrow_schema`].
+#[cfg(feature = "arrow")]
+pub fn ddl_from_arrow_schema(schema: &arrow_schema::Schema) -> String {
+    create_table_sql(&columns_from_arrow_schema(schema))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::schema::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_ddl_from_json_sample() {
+        assert_eq!(
+            ddl_from_json_sample(&json!([
+                {"id": 1, "name": "alex", "score": 1, "tags": ["a"]},
+                {"id": 2, "name": null, "score": 1.5, "extra": null, "my \"col\"": true},
+            ]))
+            .unwrap(),
+            r#"CREATE TABLE x("id" INTEGER, "name" TEXT, "score" REAL, "tags" TEXT, "extra", "my ""col""" INTEGER)"#
+        );
+        assert!(ddl_from_json_sample(&json!([1, 2])).is_err());
+    }
+
+    #[test]
+    fn test_ddl_from_json_schema() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "id": {"type": "integer"},
+                "name": {"type": ["string", "null"]},
+                "price": {"type": "number"},
+                "any": {},
+            },
+            "required": ["id", "name"],
+        });
+        assert_eq!(
+            ddl_from_json_schema(&schema).unwrap(),
+            r#"CREATE TABLE x("any", "id" INTEGER NOT NULL, "name" TEXT, "price" REAL)"#
+        );
+        assert!(ddl_from_json_schema(&json!({"type": "string"})).is_err());
+    }
+
+    #[test]
+    fn test_schema_builder() {
+        assert_eq!(
+            SchemaBuilder::new()
+                .add_column("value", "", false, false)
+                .add_column("start", "INTEGER", true, false)
+                .build()
+                .unwrap(),
+            r#"CREATE TABLE x("value", "start" INTEGER HIDDEN)"#
+        );
+        assert_eq!(
+            SchemaBuilder::new()
+                .add_column("key", "TEXT", false, true)
+                .add_column("my \"value\"", "any", false, false)
+                .add_column("path", "text", false, false)
+                .without_rowid()
+                .strict()
+                .build()
+                .unwrap(),
+            r#"CREATE TABLE x("key" TEXT, "my ""value""" any, "path" text, PRIMARY KEY("key")) WITHOUT ROWID, STRICT"#
+        );
+        assert_eq!(
+            SchemaBuilder::new()
+                .add_column("a", "DECIMAL(10, 2)", false, true)
+                .add_column("b", "INTEGER", false, true)
+                .build()
+                .unwrap(),
+            r#"CREATE TABLE x("a" DECIMAL(10, 2), "b" INTEGER, PRIMARY KEY("a", "b"))"#
+        );
+
+        let err = |builder: SchemaBuilder| builder.build().unwrap_err().result_error_message();
+        assert_eq!(
+            err(SchemaBuilder::new()),
+            "a schema needs at least one column"
+        );
+        assert_eq!(
+            err(SchemaBuilder::new()
+                .add_column("a", "", false, false)
+                .add_column("A", "", false, false)),
+            "duplicate column name \"A\""
+        );
+        assert_eq!(
+            err(SchemaBuilder::new().add_column("a", "TEXT); DROP TABLE t; --", false, false)),
+            "invalid declared type \"TEXT); DROP TABLE t; --\" for column \"a\""
+        );
+        for declared_type in [
+            "TEXT, b INTEGER",
+            "DECIMAL(10), b",
+            "VARCHAR(10, b TEXT)",
+            "VARCHAR(1, 2, 3)",
+            "INT)",
+            "(10)",
+            "VARCHAR(10)(5)",
+            "VARCHAR(a)",
+            "1NT",
+            "INTEGER PRIMARY KEY",
+            "TEXT NOT NULL",
+            "TEXT NULL",
+            "TEXT UNIQUE",
+            "INT CHECK",
+            "INT DEFAULT",
+            "TEXT COLLATE NOCASE",
+            "INT REFERENCES other",
+            "INT GENERATED ALWAYS",
+            "INT AS",
+            "TEXT HIDDEN",
+            "hidden",
+            "INT CONSTRAINT c",
+        ] {
+            assert_eq!(
+                err(SchemaBuilder::new().add_column("a", declared_type, false, false)),
+                format!("invalid declared type {:?} for column \"a\"", declared_type)
+            );
+        }
+        assert_eq!(
+            SchemaBuilder::new()
+                .add_column("a", "UNSIGNED BIG INT", false, false)
+                .add_column("b", "NUMERIC(+10,-2)", false, false)
+                .add_column("c", "VARCHAR (255)", false, false)
+                .build()
+                .unwrap(),
+            r#"CREATE TABLE x("a" UNSIGNED BIG INT, "b" NUMERIC(+10,-2), "c" VARCHAR (255))"#
+        );
+        assert_eq!(
+            err(SchemaBuilder::new()
+                .add_column("a", "VARCHAR(10)", false, false)
+                .strict()),
+            "column \"a\" has type \"VARCHAR(10)\", but STRICT tables only allow INT, INTEGER, REAL, TEXT, BLOB, ANY"
+        );
+        assert_eq!(
+            err(SchemaBuilder::new()
+                .add_column("a", "TEXT", true, false)
+                .strict()),
+            "column \"a\" is HIDDEN, which STRICT tables don't allow"
+        );
+        assert_eq!(
+            err(SchemaBuilder::new()
+                .add_column("a", "", false, false)
+                .without_rowid()),
+            "a WITHOUT ROWID table needs a primary key"
+        );
+        assert_eq!(
+            err(SchemaBuilder::new().add_column("", "", false, false)),
+            "invalid column name \"\""
+        );
+    }
+
+    #[cfg(feature = "arrow")]
+    #[test]
+    fn test_ddl_from_arrow_schema() {
+        use arrow_schema::{DataType, Field, Schema};
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, true),
+            Field::new("score", DataType::Float64, true),
+            Field::new("data", DataType::Binary, true),
+        ]);
+        assert_eq!(
+            ddl_from_arrow_schema(&schema),
+            r#"CREATE TABLE x("id" INTEGER NOT NULL, "name" TEXT, "score" REAL, "data" BLOB)"#
+        );
+    }
+}
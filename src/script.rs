@@ -0,0 +1,295 @@
+//! Scalar functions written in [Rhai](https://rhai.rs), defined without
+//! recompiling the extension.
+//!
+//! [`define_script_function`] compiles a script once, then runs it on every
+//! call with the arguments in an `args` array:
+//!
+//! ```ignore
+//! define_script_function(db, "clamp", 3, "args[0].max(args[1]).min(args[2])")?;
+//! ```
+//!
+//! and [`define_script_host`] adds `script_define(name, num_args, script)`
+//! to do the same from SQL, and `script_eval(script, args...)` to run a
+//! script inline:
+//!
+//! ```sql
+//! select script_eval('args[0] * args[1]', price, quantity) from orders;
+//! ```
+//!
+//! `script_eval()` gets the script's text on every row, so each connection
+//! keeps a [`ScriptCache`] of compiled scripts keyed by their text, and
+//! only the first row of a scan parses it. `script_cache_clear()` empties
+//! it, and closing the connection frees it.
+//!
+//! Integers, reals, text and blobs become Rhai integers, floats, strings and
+//! blobs, and NULL becomes `()`. The script's value goes back the same way,
+//! with booleans as 0 or 1 and characters as text; anything else is an
+//! error. Scripts can't touch files or the network, `print` and `debug` go
+//! nowhere, and a call that runs more than [`MAX_OPERATIONS`] operations
+//! fails the query instead of hanging it. So does one that builds a string,
+//! array, blob or object map past [`MAX_STRING_SIZE`], [`MAX_ARRAY_SIZE`]
+//! or [`MAX_MAP_SIZE`], or recurses past [`MAX_CALL_LEVELS`], instead of
+//! running the process out of memory or stack.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api::{self, ValueType};
+use crate::errors::{Error, Result};
+use crate::ext::{sqlite3, sqlite3_context, sqlite3_value};
+use crate::hooks::on_connection_close;
+use crate::scalar::{define_scalar_function, FunctionFlags};
+use rhai::{Array, Blob, Dynamic, Engine, Scope, AST};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::os::raw::c_int;
+use std::rc::Rc;
+
+/// How many operations, roughly a statement or expression each, one call
+/// can run.
+pub const MAX_OPERATIONS: u64 = 10_000_000;
+
+/// How long, in bytes, a string in a script can grow.
+pub const MAX_STRING_SIZE: usize = 16 << 20;
+
+/// How many items an array or blob in a script can hold.
+pub const MAX_ARRAY_SIZE: usize = 1 << 16;
+
+/// How many properties an object map in a script can hold.
+pub const MAX_MAP_SIZE: usize = 1 << 16;
+
+/// How deeply a script's functions can call each other.
+pub const MAX_CALL_LEVELS: usize = 32;
+
+/// How deeply a script's expressions can nest, checked when it's compiled.
+pub const MAX_EXPR_DEPTH: usize = 32;
+
+/// How many compiled scripts a [`ScriptCache`] keeps before it starts over.
+pub const CACHE_CAPACITY: usize = 256;
+
+/// Compiled scripts, keyed by their text, and the engine that runs them.
+/// Lookups hash the text, so a script seen before isn't parsed again.
+pub struct ScriptCache {
+    engine: Engine,
+    scripts: RefCell<HashMap<String, Rc<AST>>>,
+    hits: Cell<u64>,
+    misses: Cell<u64>,
+}
+
+impl ScriptCache {
+    pub fn new() -> Self {
+        ScriptCache {
+            engine: engine(),
+            scripts: RefCell::new(HashMap::new()),
+            hits: Cell::new(0),
+            misses: Cell::new(0),
+        }
+    }
+
+    /// The compiled `script`, from the cache or compiled now. A full cache
+    /// is cleared first, rather than tracking which script was used last.
+    pub fn compile(&self, script: &str) -> Result<Rc<AST>> {
+        if let Some(ast) = self.scripts.borrow().get(script) {
+            self.hits.set(self.hits.get() + 1);
+            return Ok(ast.clone());
+        }
+        self.misses.set(self.misses.get() + 1);
+        let ast = Rc::new(
+            self.engine
+                .compile(script)
+                .map_err(|err| Error::new_message(format!("invalid script: {}", err)))?,
+        );
+        let mut scripts = self.scripts.borrow_mut();
+        if scripts.len() >= CACHE_CAPACITY {
+            scripts.clear();
+        }
+        scripts.insert(script.to_owned(), ast.clone());
+        Ok(ast)
+    }
+
+    /// Drops `script`, returning whether it was cached. Functions already
+    /// defined from it keep their own copy.
+    pub fn invalidate(&self, script: &str) -> bool {
+        self.scripts.borrow_mut().remove(script).is_some()
+    }
+
+    /// Drops every script, returning how many there were.
+    pub fn clear(&self) -> usize {
+        let mut scripts = self.scripts.borrow_mut();
+        let len = scripts.len();
+        scripts.clear();
+        len
+    }
+
+    pub fn len(&self) -> usize {
+        self.scripts.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scripts.borrow().is_empty()
+    }
+
+    /// How many lookups found a compiled script, and how many compiled one.
+    pub fn stats(&self) -> (u64, u64) {
+        (self.hits.get(), self.misses.get())
+    }
+
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+}
+
+impl Default for ScriptCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Compiles `script` and defines the SQL function `name`, which takes
+/// `num_args` arguments (-1 for any number) and returns what the script
+/// evaluates to.
+pub fn define_script_function(
+    db: *mut sqlite3,
+    name: &str,
+    num_args: c_int,
+    script: &str,
+) -> Result<()> {
+    let engine = engine();
+    let ast = engine
+        .compile(script)
+        .map_err(|err| Error::new_message(format!("invalid script for {}: {}", name, err)))?;
+    let name = name.to_owned();
+    define_scalar_function(
+        db,
+        &name.clone(),
+        num_args,
+        move |context, values| call(&engine, &ast, &name, context, values),
+        FunctionFlags::UTF8,
+    )
+}
+
+/// Defines `script_define(name, num_args, script)`, `script_eval(script,
+/// args...)` and `script_cache_clear()`, sharing one [`ScriptCache`] for
+/// the connection. `script_define()` and `script_cache_clear()` modify the
+/// connection, so they're DIRECTONLY: a trigger or view can't call them.
+pub fn define_script_host(db: *mut sqlite3) -> Result<()> {
+    let cache = Rc::new(ScriptCache::new());
+
+    let defines = cache.clone();
+    define_scalar_function(
+        db,
+        "script_define",
+        3,
+        move |context, values| {
+            let name = api::value_text(&values[0])?;
+            let num_args = api::value_int(&values[1]);
+            let ast = defines
+                .compile(api::value_text(&values[2])?)
+                .map_err(|err| {
+                    Error::new_message(format!("{} for {}", err.result_error_message(), name))
+                })?;
+            let cache = defines.clone();
+            let function_name = name.to_owned();
+            define_scalar_function(
+                api::context_db_handle(context),
+                name,
+                num_args,
+                move |context, values| call(cache.engine(), &ast, &function_name, context, values),
+                FunctionFlags::UTF8,
+            )?;
+            api::result_null(context);
+            Ok(())
+        },
+        FunctionFlags::UTF8 | FunctionFlags::DIRECTONLY,
+    )?;
+
+    let evals = cache.clone();
+    define_scalar_function(
+        db,
+        "script_eval",
+        -1,
+        move |context, values| {
+            let (script, args) = values
+                .split_first()
+                .ok_or_else(|| Error::new_message("script_eval() needs a script"))?;
+            let ast = evals.compile(api::value_text(script)?)?;
+            call(evals.engine(), &ast, "script_eval", context, args)
+        },
+        FunctionFlags::UTF8,
+    )?;
+
+    let clears = cache.clone();
+    define_scalar_function(
+        db,
+        "script_cache_clear",
+        0,
+        move |context, _values| {
+            api::result_int64(context, clears.clear() as i64);
+            Ok(())
+        },
+        FunctionFlags::UTF8 | FunctionFlags::DIRECTONLY,
+    )?;
+
+    // the functions above are never freed, so let go of the scripts at least
+    on_connection_close(db, move || {
+        cache.clear();
+    })
+}
+
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_string_size(MAX_STRING_SIZE);
+    engine.set_max_array_size(MAX_ARRAY_SIZE);
+    engine.set_max_map_size(MAX_MAP_SIZE);
+    engine.set_max_call_levels(MAX_CALL_LEVELS);
+    engine.set_max_expr_depths(MAX_EXPR_DEPTH, MAX_EXPR_DEPTH);
+    engine.on_print(|_| {});
+    engine.on_debug(|_, _, _| {});
+    engine
+}
+
+fn call(
+    engine: &Engine,
+    ast: &AST,
+    name: &str,
+    context: *mut sqlite3_context,
+    values: &[*mut sqlite3_value],
+) -> Result<()> {
+    let args = values.iter().map(argument).collect::<Result<Array>>()?;
+    let mut scope = Scope::new();
+    scope.push("args", args);
+    let result = engine
+        .eval_ast_with_scope::<Dynamic>(&mut scope, ast)
+        .map_err(|err| Error::new_message(format!("{}(): {}", name, err)))?;
+    if result.is_unit() {
+        api::result_null(context);
+    } else if let Ok(i) = result.as_int() {
+        api::result_int64(context, i);
+    } else if let Ok(f) = result.as_float() {
+        api::result_double(context, f);
+    } else if let Ok(b) = result.as_bool() {
+        api::result_bool(context, b);
+    } else if let Ok(c) = result.as_char() {
+        api::result_text(context, c.to_string())?;
+    } else if result.is_string() {
+        api::result_text(context, result.into_immutable_string().unwrap_or_default())?;
+    } else if result.is_blob() {
+        api::result_blob(context, &result.cast::<Blob>());
+    } else {
+        return Err(Error::new_message(format!(
+            "{}() returned a value of type {}, which SQLite has no type for",
+            name,
+            result.type_name()
+        )));
+    }
+    Ok(())
+}
+
+fn argument(value: &*mut sqlite3_value) -> Result<Dynamic> {
+    Ok(match api::value_type(value) {
+        ValueType::Integer => Dynamic::from_int(api::value_int64(value)),
+        ValueType::Float => Dynamic::from_float(api::value_double(value)),
+        ValueType::Text => Dynamic::from(api::value_text(value)?.to_owned()),
+        ValueType::Blob => Dynamic::from_blob(api::value_blob(value).to_vec()),
+        ValueType::Null => Dynamic::UNIT,
+    })
+}
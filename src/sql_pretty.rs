@@ -0,0 +1,383 @@
+//! Pretty-printing SQL, with the `sql_pretty(sql)` function.
+//!
+//! [`pretty`] splits SQL into tokens the way SQLite does, upper-cases
+//! keywords, starts each clause on its own line, indents subqueries, and
+//! separates statements with a blank line:
+//!
+//! ```sql
+//! select sql_pretty('select a, count(*) from t where b>1 group by a');
+//! -- SELECT a, count(*)
+//! -- FROM t
+//! -- WHERE b > 1
+//! -- GROUP BY a
+//! ```
+//!
+//! Only whitespace and the case of keywords change, so the result means
+//! the same as the original, comments included. Keywords are checked with
+//! [`crate::keywords::is_keyword`], so they're the ones of the SQLite
+//! library that loaded the extension.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api;
+use crate::errors::{Error, Result};
+use crate::ext::sqlite3;
+use crate::keywords::is_keyword;
+use crate::scalar::{define_scalar_function, FunctionFlags};
+
+/// Keywords that start a clause, and so a line.
+const CLAUSES: &[&str] = &[
+    "SELECT",
+    "FROM",
+    "WHERE",
+    "GROUP",
+    "HAVING",
+    "WINDOW",
+    "ORDER",
+    "LIMIT",
+    "UNION",
+    "INTERSECT",
+    "EXCEPT",
+    "VALUES",
+    "SET",
+    "RETURNING",
+    "JOIN",
+    "NATURAL",
+    "LEFT",
+    "RIGHT",
+    "FULL",
+    "INNER",
+    "CROSS",
+];
+
+/// Keywords that can come before `JOIN`, so they don't start a line
+/// after each other.
+const JOIN_OPERATORS: &[&str] = &[
+    "NATURAL", "LEFT", "RIGHT", "FULL", "INNER", "CROSS", "OUTER",
+];
+
+/// Keywords that are written like function calls, with no space before
+/// their parentheses.
+const FUNCTION_KEYWORDS: &[&str] = &["CAST", "GLOB", "LIKE", "RAISE", "REPLACE"];
+
+/// Keywords whose parentheses hold an expression or window rather than a
+/// query, so clauses inside them stay on the line.
+const INLINE_KEYWORDS: &[&str] = &["FILTER", "OVER"];
+
+/// A token of SQL, with its text as written. Whitespace isn't kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token<'a> {
+    /// An unquoted identifier or keyword.
+    Word(&'a str),
+    /// An identifier quoted with `"`, `` ` `` or `[]`.
+    Quoted(&'a str),
+    /// A string, blob or number.
+    Literal(&'a str),
+    /// A parameter, like `?1`, `:name`, `@name` or `$name`.
+    Variable(&'a str),
+    /// An operator, like `+`, `<=` or `||`.
+    Operator(&'a str),
+    /// `(`, `)`, `,`, `;` or `.`.
+    Punct(&'a str),
+    /// A `--` comment, to the end of the line.
+    LineComment(&'a str),
+    /// A `/* */` comment.
+    BlockComment(&'a str),
+}
+
+fn is_word_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'$' || b >= 0x80
+}
+
+/// The index just past the quote that closes the one at `start`, where a
+/// doubled quote is an escaped one.
+fn closing_quote(bytes: &[u8], start: usize, quote: u8) -> Option<usize> {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        if bytes[i] == quote {
+            if bytes.get(i + 1) == Some(&quote) {
+                i += 2;
+                continue;
+            }
+            return Some(i + 1);
+        }
+        i += 1;
+    }
+    None
+}
+
+fn unterminated(what: &str, start: usize) -> Error {
+    Error::new_message(format!(
+        "sql_pretty: unterminated {} starting at byte {}",
+        what, start
+    ))
+}
+
+/// Splits `sql` into tokens. Fails on a string, quoted identifier or block
+/// comment that doesn't end.
+pub fn tokenize(sql: &str) -> Result<Vec<Token<'_>>> {
+    let bytes = sql.as_bytes();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        let b = bytes[i];
+        let next = bytes.get(i + 1).copied();
+        let token = match b {
+            b if b.is_ascii_whitespace() => {
+                i += 1;
+                continue;
+            }
+            b'-' if next == Some(b'-') => {
+                i = sql[i..].find('\n').map_or(bytes.len(), |n| i + n);
+                Token::LineComment(sql[start..i].trim_end())
+            }
+            b'/' if next == Some(b'*') => {
+                i = sql[i + 2..]
+                    .find("*/")
+                    .map(|n| i + 2 + n + 2)
+                    .ok_or_else(|| unterminated("comment", start))?;
+                Token::BlockComment(&sql[start..i])
+            }
+            b'\'' => {
+                i = closing_quote(bytes, i, b'\'').ok_or_else(|| unterminated("string", start))?;
+                Token::Literal(&sql[start..i])
+            }
+            b'"' | b'`' => {
+                i = closing_quote(bytes, i, b).ok_or_else(|| unterminated("identifier", start))?;
+                Token::Quoted(&sql[start..i])
+            }
+            b'[' => {
+                i = sql[i..]
+                    .find(']')
+                    .map(|n| i + n + 1)
+                    .ok_or_else(|| unterminated("identifier", start))?;
+                Token::Quoted(&sql[start..i])
+            }
+            b'x' | b'X' if next == Some(b'\'') => {
+                i = closing_quote(bytes, i + 1, b'\'')
+                    .ok_or_else(|| unterminated("blob", start))?;
+                Token::Literal(&sql[start..i])
+            }
+            b'0'..=b'9' | b'.' if b != b'.' || next.is_some_and(|n| n.is_ascii_digit()) => {
+                let hex = b == b'0' && matches!(next, Some(b'x' | b'X'));
+                i += 1;
+                while i < bytes.len() {
+                    let c = bytes[i];
+                    let exponent_sign =
+                        !hex && matches!(c, b'+' | b'-') && matches!(bytes[i - 1], b'e' | b'E');
+                    if !(c.is_ascii_alphanumeric() || c == b'_' || c == b'.' || exponent_sign) {
+                        break;
+                    }
+                    i += 1;
+                }
+                Token::Literal(&sql[start..i])
+            }
+            b'?' | b':' | b'@' | b'$' => {
+                i += 1;
+                while i < bytes.len() && is_word_byte(bytes[i]) {
+                    i += 1;
+                }
+                Token::Variable(&sql[start..i])
+            }
+            b if is_word_byte(b) => {
+                while i < bytes.len() && is_word_byte(bytes[i]) {
+                    i += 1;
+                }
+                Token::Word(&sql[start..i])
+            }
+            b'(' | b')' | b',' | b';' | b'.' => {
+                i += 1;
+                Token::Punct(&sql[start..i])
+            }
+            _ => {
+                let rest = &sql[i..];
+                let len = ["->>", "->", "||", "<=", ">=", "<>", "!=", "==", "<<", ">>"]
+                    .iter()
+                    .find(|op| rest.starts_with(*op))
+                    .map_or(1, |op| op.len());
+                i += len;
+                Token::Operator(&sql[start..i])
+            }
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// An open parenthesis.
+struct Paren {
+    /// Holds an expression, like a function's arguments, rather than a
+    /// query, so it's kept on one line.
+    inline: bool,
+    /// Its contents started a new line, so the `)` goes on its own line.
+    broke: bool,
+}
+
+/// Writes tokens with the spacing and line breaks [`pretty`] describes.
+struct Printer {
+    out: String,
+    parens: Vec<Paren>,
+    /// Line breaks to write before the next token.
+    newlines: usize,
+}
+
+impl Printer {
+    fn indent(&self) -> usize {
+        self.parens.iter().filter(|paren| !paren.inline).count()
+    }
+
+    fn line_break(&mut self, count: usize) {
+        if !self.out.is_empty() {
+            self.newlines = self.newlines.max(count);
+        }
+    }
+
+    fn write(&mut self, text: &str, space: bool) {
+        if self.newlines > 0 {
+            for _ in 0..self.newlines {
+                self.out.push('\n');
+            }
+            for _ in 0..self.indent() {
+                self.out.push_str("  ");
+            }
+            self.newlines = 0;
+        } else if space && !self.out.is_empty() {
+            self.out.push(' ');
+        }
+        self.out.push_str(text);
+    }
+}
+
+/// A keyword upper-cased, or None if `word` isn't one.
+fn keyword(word: &str) -> Option<String> {
+    is_keyword(word).then(|| word.to_ascii_uppercase())
+}
+
+/// `sql` reformatted: keywords upper-cased, one space between tokens
+/// (none around `.`, inside parentheses, or before `,` and `;`), each
+/// clause on a new line, subqueries indented by two spaces, and a blank
+/// line between statements. Comments are kept, and everything else is
+/// written as it was.
+pub fn pretty(sql: &str) -> Result<String> {
+    let mut printer = Printer {
+        out: String::with_capacity(sql.len() + sql.len() / 8),
+        parens: vec![],
+        newlines: 0,
+    };
+    // the keyword before the current token, if it was one
+    let mut prev_keyword: Option<String> = None;
+    let mut prev: Option<Token> = None;
+    // the previous token was a unary `-`, `+` or `~`, so this one follows
+    // it directly
+    let mut unary = false;
+    for token in tokenize(sql)? {
+        let after_open = prev == Some(Token::Punct("("));
+        let after_dot = prev == Some(Token::Punct("."));
+        let mut space = !(after_open || after_dot || unary);
+        unary = false;
+        let mut this_keyword = None;
+        match token {
+            Token::Word(word) => match keyword(word) {
+                Some(upper) => {
+                    let upper_str = upper.as_str();
+                    let continues_join = prev_keyword
+                        .as_deref()
+                        .is_some_and(|prev| JOIN_OPERATORS.contains(&prev))
+                        && (upper_str == "JOIN" || JOIN_OPERATORS.contains(&upper_str));
+                    let delete_from =
+                        upper_str == "FROM" && prev_keyword.as_deref() == Some("DELETE");
+                    let in_query = printer.parens.last().map_or(true, |paren| !paren.inline);
+                    if CLAUSES.contains(&upper_str)
+                        && !continues_join
+                        && !delete_from
+                        && !after_dot
+                        && in_query
+                    {
+                        printer.line_break(1);
+                        if let Some(paren) = printer.parens.last_mut() {
+                            paren.broke = true;
+                        }
+                    }
+                    printer.write(&upper, space);
+                    this_keyword = Some(upper);
+                }
+                None => printer.write(word, space),
+            },
+            Token::Punct("(") => {
+                let function = match prev {
+                    Some(Token::Word(_)) => prev_keyword
+                        .as_deref()
+                        .map_or(true, |prev| FUNCTION_KEYWORDS.contains(&prev)),
+                    Some(Token::Quoted(_)) => true,
+                    _ => false,
+                };
+                let inline = function
+                    || prev_keyword
+                        .as_deref()
+                        .is_some_and(|prev| INLINE_KEYWORDS.contains(&prev));
+                printer.write("(", space && !function);
+                printer.parens.push(Paren {
+                    inline,
+                    broke: false,
+                });
+            }
+            Token::Punct(")") => {
+                if let Some(paren) = printer.parens.pop() {
+                    if paren.broke {
+                        printer.line_break(1);
+                    }
+                }
+                printer.write(")", false);
+            }
+            Token::Punct(";") => {
+                printer.write(";", false);
+                printer.parens.clear();
+                printer.line_break(2);
+            }
+            Token::Punct(punct) => printer.write(punct, false),
+            Token::Operator(op) => {
+                unary = op == "~"
+                    || (matches!(op, "-" | "+")
+                        && (prev_keyword.is_some()
+                            || matches!(
+                                prev,
+                                None | Some(Token::Operator(_))
+                                    | Some(Token::Punct("(" | "," | ";"))
+                            )));
+                if op == "*" && matches!(prev, Some(Token::Punct("."))) {
+                    space = false;
+                }
+                printer.write(op, space);
+            }
+            Token::LineComment(comment) => {
+                printer.write(comment, space);
+                printer.line_break(1);
+            }
+            Token::Quoted(text)
+            | Token::Literal(text)
+            | Token::Variable(text)
+            | Token::BlockComment(text) => printer.write(text, space),
+        }
+        prev_keyword = this_keyword;
+        prev = Some(token);
+    }
+    Ok(printer.out)
+}
+
+/// Defines `sql_pretty(sql)`, which returns [`pretty`] of its argument,
+/// or NULL for NULL.
+pub fn define_sql_pretty(db: *mut sqlite3) -> Result<()> {
+    define_scalar_function(
+        db,
+        "sql_pretty",
+        1,
+        |context, values| {
+            if api::value_is_null(&values[0]) {
+                api::result_null(context);
+                return Ok(());
+            }
+            api::result_text(context, pretty(api::value_text(&values[0])?)?)
+        },
+        FunctionFlags::UTF8 | FunctionFlags::DETERMINISTIC | FunctionFlags::INNOCUOUS,
+    )
+}
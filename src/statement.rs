@@ -0,0 +1,182 @@
+//! A minimal prepared statement for running the library's own SQL, like
+//! reading shadow tables, without the "exec" feature.
+
+use crate::constants::{SQLITE_DONE, SQLITE_ROW};
+use crate::errors::{Error, ErrorKind, Result};
+use crate::ext::{
+    sqlite3, sqlite3_stmt, sqlite3_value, sqlite3ext_bind_double, sqlite3ext_bind_int64,
+    sqlite3ext_bind_null, sqlite3ext_bind_text, sqlite3ext_bind_value, sqlite3ext_column_bytes,
+    sqlite3ext_column_int64, sqlite3ext_column_text, sqlite3ext_column_value, sqlite3ext_exec,
+    sqlite3ext_finalize, sqlite3ext_next_stmt, sqlite3ext_prepare_v2, sqlite3ext_reset,
+    sqlite3ext_sql, sqlite3ext_step, sqlite3ext_stmt_busy, sqlite3ext_stmt_readonly,
+};
+use std::{
+    collections::HashMap,
+    ffi::{CStr, CString},
+};
+
+/// Runs `sql`, which may hold several statements.
+pub(crate) fn execute_batch(db: *mut sqlite3, sql: &str) -> Result<()> {
+    let csql = CString::new(sql)?;
+    let rc = unsafe { sqlite3ext_exec(db, csql.as_ptr()) };
+    if rc != 0 {
+        return Err(Error::with_db_message(
+            ErrorKind::Message(format!("could not run {:?}", sql)),
+            rc,
+            db,
+        ));
+    }
+    Ok(())
+}
+
+/// Whether a statement that writes is running on `db`.
+fn writing(db: *mut sqlite3) -> bool {
+    let mut stmt = unsafe { sqlite3ext_next_stmt(db, std::ptr::null_mut()) };
+    while !stmt.is_null() {
+        if unsafe { sqlite3ext_stmt_busy(stmt) != 0 && sqlite3ext_stmt_readonly(stmt) == 0 } {
+            return true;
+        }
+        stmt = unsafe { sqlite3ext_next_stmt(db, stmt) };
+    }
+    false
+}
+
+/// Runs `f` in a savepoint, so everything it writes is undone if it fails.
+pub(crate) fn in_savepoint<T, F>(db: *mut sqlite3, name: &str, f: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T>,
+{
+    if writing(db) {
+        // SQLite can't open a savepoint while a write is in progress, like
+        // the CREATE VIRTUAL TABLE that called xCreate or the INSERT that
+        // called xUpdate. Its failure undoes whatever f wrote anyway.
+        return f();
+    }
+    execute_batch(db, &format!("SAVEPOINT {}", name))?;
+    match f() {
+        Ok(value) => {
+            execute_batch(db, &format!("RELEASE {}", name))?;
+            Ok(value)
+        }
+        Err(err) => {
+            // undo everything, then close the savepoint; the original error is
+            // more useful than any from the rollback
+            let _ = execute_batch(db, &format!("ROLLBACK TO {}; RELEASE {}", name, name));
+            Err(err)
+        }
+    }
+}
+
+/// Finalized when dropped, so early returns don't leak it.
+pub(crate) struct Statement {
+    db: *mut sqlite3,
+    stmt: *mut sqlite3_stmt,
+    // bound text by parameter, kept alive until the parameter is bound again
+    // or the statement is finalized, so SQLite needn't copy it
+    texts: HashMap<i32, CString>,
+}
+
+impl Statement {
+    pub(crate) fn prepare(db: *mut sqlite3, sql: &str) -> Result<Statement> {
+        let csql = CString::new(sql)?;
+        let mut stmt: *mut sqlite3_stmt = std::ptr::null_mut();
+        let rc = unsafe {
+            sqlite3ext_prepare_v2(db, csql.as_ptr(), -1, &mut stmt, std::ptr::null_mut())
+        };
+        if rc != 0 {
+            return Err(Error::with_db_message(
+                ErrorKind::Message(format!("could not prepare {:?}", sql)),
+                rc,
+                db,
+            ));
+        }
+        Ok(Statement {
+            db,
+            stmt,
+            texts: HashMap::new(),
+        })
+    }
+
+    /// Binds text to the 1-based parameter `i`.
+    pub(crate) fn bind_text(&mut self, i: i32, value: &str) -> Result<()> {
+        let value = CString::new(value)?;
+        let rc = unsafe { sqlite3ext_bind_text(self.stmt, i, value.as_ptr(), -1, None) };
+        self.texts.insert(i, value);
+        self.check(rc, "could not bind parameter")
+    }
+
+    pub(crate) fn bind_int64(&mut self, i: i32, value: i64) -> Result<()> {
+        let rc = unsafe { sqlite3ext_bind_int64(self.stmt, i, value) };
+        self.check(rc, "could not bind parameter")
+    }
+
+    pub(crate) fn bind_double(&mut self, i: i32, value: f64) -> Result<()> {
+        let rc = unsafe { sqlite3ext_bind_double(self.stmt, i, value) };
+        self.check(rc, "could not bind parameter")
+    }
+
+    pub(crate) fn bind_null(&mut self, i: i32) -> Result<()> {
+        let rc = unsafe { sqlite3ext_bind_null(self.stmt, i) };
+        self.check(rc, "could not bind parameter")
+    }
+
+    /// Binds a copy of `value`.
+    pub(crate) fn bind_value(&mut self, i: i32, value: *mut sqlite3_value) -> Result<()> {
+        let rc = unsafe { sqlite3ext_bind_value(self.stmt, i, value) };
+        self.check(rc, "could not bind parameter")
+    }
+
+    /// Readies the statement to run again, keeping its bindings.
+    pub(crate) fn reset(&mut self) {
+        // reset returns the last step's error, which step already reported
+        unsafe { sqlite3ext_reset(self.stmt) };
+    }
+
+    /// Steps to the next row, false once there are no more.
+    pub(crate) fn step(&mut self) -> Result<bool> {
+        match unsafe { sqlite3ext_step(self.stmt) } {
+            SQLITE_ROW => Ok(true),
+            SQLITE_DONE => Ok(false),
+            rc => self.check(rc, "could not step statement").map(|_| false),
+        }
+    }
+
+    pub(crate) fn column_int64(&self, i: i32) -> i64 {
+        unsafe { sqlite3ext_column_int64(self.stmt, i) }
+    }
+
+    /// Column `i` of the current row, valid until the next step or reset.
+    pub(crate) fn column_value(&self, i: i32) -> *mut sqlite3_value {
+        unsafe { sqlite3ext_column_value(self.stmt, i) }
+    }
+
+    /// The text in column `i` of the current row, "" for NULL.
+    pub(crate) fn column_string(&self, i: i32) -> String {
+        unsafe {
+            let text = sqlite3ext_column_text(self.stmt, i);
+            if text.is_null() {
+                return String::new();
+            }
+            let n = sqlite3ext_column_bytes(self.stmt, i) as usize;
+            String::from_utf8_lossy(std::slice::from_raw_parts(text, n)).into_owned()
+        }
+    }
+
+    fn check(&self, rc: i32, message: &str) -> Result<()> {
+        if rc == 0 {
+            return Ok(());
+        }
+        let sql = unsafe { CStr::from_ptr(sqlite3ext_sql(self.stmt)) }.to_string_lossy();
+        Err(Error::with_db_message(
+            ErrorKind::Message(format!("{} in {:?}", message, sql)),
+            rc,
+            self.db,
+        ))
+    }
+}
+
+impl Drop for Statement {
+    fn drop(&mut self) {
+        unsafe { sqlite3ext_finalize(self.stmt) };
+    }
+}
@@ -0,0 +1,295 @@
+//! Query a `Vec` of Rust structs from SQL.
+//!
+//! `#[derive(StaticTable)]` on a struct with named fields implements
+//! [`StaticTable`], with a column for each field. [`define_static_table`]
+//! then registers a read-only, eponymous table over the rows:
+//!
+//! ```ignore
+//! #[derive(StaticTable)]
+//! struct Person {
+//!     name: String,
+//!     age: i64,
+//!     email: Option<String>,
+//! }
+//!
+//! define_static_table(db, "people", Some(Arc::new(people)))?;
+//! ```
+//!
+//! ```sql
+//! select name from people where age > 30;
+//! ```
+//!
+//! Queries read the rows in place, nothing is copied into SQLite beyond the
+//! column values a query asks for. The table also takes rows as a pointer
+//! argument, for data that only exists while a statement runs: a function
+//! that returns them with [`result_rows`] can be queried like
+//! `select * from people(todays_people())`.
+//!
+//! Field types map to columns with [`StaticColumn`], implemented for
+//! integers, floats, `bool`, `String`, `&'static str`, `Vec<u8>` and
+//! `Option`s of those, where `None` is NULL.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api;
+use crate::errors::{Error, Result};
+use crate::ext::{sqlite3, sqlite3_context, sqlite3_value, sqlite3_vtab, sqlite3_vtab_cursor};
+use crate::table::{
+    define_table_function, BestIndexError, ConstraintOperator, IndexInfo, VTab, VTabArguments,
+    VTabCursor,
+};
+use std::os::raw::c_int;
+use std::sync::Arc;
+
+pub use sqlite_loadable_macros::StaticTable;
+
+/// A struct whose values are rows of a [`define_static_table`] table.
+/// Usually derived with `#[derive(StaticTable)]`.
+pub trait StaticTable: Sized {
+    /// The pointer type of [`result_rows`] values, a nul-terminated string
+    /// unique to the struct.
+    const POINTER_TYPE: &'static [u8];
+
+    /// The name and declared SQL type of each column, in order.
+    fn columns() -> Vec<(&'static str, &'static str)>;
+
+    /// Sets the result of column `i` of this row.
+    fn column(&self, context: *mut sqlite3_context, i: c_int) -> Result<()>;
+}
+
+/// A Rust type that's a column of a [`StaticTable`].
+pub trait StaticColumn {
+    /// The declared type of the column, which gives it its affinity.
+    const SQL_TYPE: &'static str;
+
+    fn result(&self, context: *mut sqlite3_context) -> Result<()>;
+}
+
+macro_rules! integer_column {
+    ($($t:ty),*) => {
+        $(
+            impl StaticColumn for $t {
+                const SQL_TYPE: &'static str = "INTEGER";
+                fn result(&self, context: *mut sqlite3_context) -> Result<()> {
+                    api::result_int64(context, *self as i64);
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+integer_column!(i8, i16, i32, i64, u8, u16, u32, isize);
+
+impl StaticColumn for u64 {
+    const SQL_TYPE: &'static str = "INTEGER";
+    fn result(&self, context: *mut sqlite3_context) -> Result<()> {
+        let value = i64::try_from(*self)
+            .map_err(|_| Error::new_message(format!("{} is too large for an INTEGER", self)))?;
+        api::result_int64(context, value);
+        Ok(())
+    }
+}
+
+impl StaticColumn for f32 {
+    const SQL_TYPE: &'static str = "REAL";
+    fn result(&self, context: *mut sqlite3_context) -> Result<()> {
+        api::result_double(context, *self as f64);
+        Ok(())
+    }
+}
+
+impl StaticColumn for f64 {
+    const SQL_TYPE: &'static str = "REAL";
+    fn result(&self, context: *mut sqlite3_context) -> Result<()> {
+        api::result_double(context, *self);
+        Ok(())
+    }
+}
+
+impl StaticColumn for bool {
+    const SQL_TYPE: &'static str = "INTEGER";
+    fn result(&self, context: *mut sqlite3_context) -> Result<()> {
+        api::result_bool(context, *self);
+        Ok(())
+    }
+}
+
+impl StaticColumn for String {
+    const SQL_TYPE: &'static str = "TEXT";
+    fn result(&self, context: *mut sqlite3_context) -> Result<()> {
+        api::result_text(context, self)
+    }
+}
+
+impl StaticColumn for &'static str {
+    const SQL_TYPE: &'static str = "TEXT";
+    fn result(&self, context: *mut sqlite3_context) -> Result<()> {
+        api::result_text(context, self)
+    }
+}
+
+impl StaticColumn for Vec<u8> {
+    const SQL_TYPE: &'static str = "BLOB";
+    fn result(&self, context: *mut sqlite3_context) -> Result<()> {
+        api::result_blob(context, self);
+        Ok(())
+    }
+}
+
+impl<T: StaticColumn> StaticColumn for Option<T> {
+    const SQL_TYPE: &'static str = T::SQL_TYPE;
+    fn result(&self, context: *mut sqlite3_context) -> Result<()> {
+        match self {
+            Some(value) => value.result(context),
+            None => {
+                api::result_null(context);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Registers `name` as a table over `rows`, or one that only reads rows
+/// passed to it with [`result_rows`] when `rows` is None.
+pub fn define_static_table<T: StaticTable + 'static>(
+    db: *mut sqlite3,
+    name: &str,
+    rows: Option<Arc<Vec<T>>>,
+) -> Result<()> {
+    define_table_function::<StaticTableVTab<T>>(db, name, Some(rows))
+}
+
+/// Sets `rows` as the result of a function, as a pointer a
+/// [`define_static_table`] table of the same struct takes as its argument.
+pub fn result_rows<T: StaticTable + 'static>(context: *mut sqlite3_context, rows: Arc<Vec<T>>) {
+    api::result_pointer(context, T::POINTER_TYPE, rows);
+}
+
+/// idxNum when the rows are passed as an argument.
+const PLAN_ARGUMENT: c_int = 1;
+
+#[repr(C)]
+pub struct StaticTableVTab<T: StaticTable> {
+    /// must be first
+    base: sqlite3_vtab,
+    rows: Option<Arc<Vec<T>>>,
+}
+
+impl<'vtab, T: StaticTable + 'vtab> VTab<'vtab> for StaticTableVTab<T> {
+    type Aux = Option<Arc<Vec<T>>>;
+    type Cursor = StaticTableCursor<T>;
+
+    fn connect(
+        _db: *mut sqlite3,
+        aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, StaticTableVTab<T>)> {
+        let columns: Vec<String> = T::columns()
+            .iter()
+            .map(|(name, sql_type)| format!("\"{}\" {}", name.replace('"', "\"\""), sql_type))
+            .collect();
+        let base: sqlite3_vtab = unsafe { std::mem::zeroed() };
+        Ok((
+            format!("CREATE TABLE x({}, __rows hidden)", columns.join(", ")),
+            StaticTableVTab {
+                base,
+                rows: aux.cloned().flatten(),
+            },
+        ))
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        let rows_column = T::columns().len() as i32;
+        let mut plan = 0;
+        for mut constraint in info.constraints() {
+            if constraint.column_idx() != rows_column {
+                continue;
+            }
+            if !constraint.usable() || constraint.op() != Some(ConstraintOperator::EQ) {
+                return Err(BestIndexError::Constraint);
+            }
+            constraint.set_argv_index(1);
+            constraint.set_omit(true);
+            plan = PLAN_ARGUMENT;
+        }
+        let estimated_rows = match (&self.rows, plan) {
+            (Some(rows), 0) => rows.len() as i64,
+            _ => 1000,
+        };
+        info.set_idxnum(plan);
+        info.set_estimated_rows(estimated_rows);
+        info.set_estimated_cost(estimated_rows as f64);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<StaticTableCursor<T>> {
+        Ok(StaticTableCursor::new(self.rows.clone()))
+    }
+}
+
+#[repr(C)]
+pub struct StaticTableCursor<T: StaticTable> {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    table_rows: Option<Arc<Vec<T>>>,
+    rows: Arc<Vec<T>>,
+    index: usize,
+}
+
+impl<T: StaticTable> StaticTableCursor<T> {
+    fn new(table_rows: Option<Arc<Vec<T>>>) -> StaticTableCursor<T> {
+        let base: sqlite3_vtab_cursor = unsafe { std::mem::zeroed() };
+        StaticTableCursor {
+            base,
+            table_rows,
+            rows: Arc::new(vec![]),
+            index: 0,
+        }
+    }
+}
+
+impl<T: StaticTable> VTabCursor for StaticTableCursor<T> {
+    fn filter(
+        &mut self,
+        idx_num: c_int,
+        _idx_str: Option<&str>,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.rows = if idx_num == PLAN_ARGUMENT {
+            let rows = unsafe { api::value_pointer::<Arc<Vec<T>>>(&values[0], T::POINTER_TYPE) };
+            match rows {
+                Some(rows) => unsafe { (*rows).clone() },
+                None => {
+                    return Err(Error::new_message(
+                        "the argument isn't a pointer to rows of this table",
+                    ))
+                }
+            }
+        } else {
+            self.table_rows.clone().ok_or_else(|| {
+                Error::new_message("this table has no rows of its own, pass them as an argument")
+            })?
+        };
+        self.index = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.index += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.index >= self.rows.len()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, i: c_int) -> Result<()> {
+        match self.rows.get(self.index) {
+            Some(row) => row.column(context, i),
+            None => Ok(()),
+        }
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.index as i64)
+    }
+}
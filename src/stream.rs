@@ -0,0 +1,333 @@
+//! A table function over rows that Rust threads push through channels.
+//!
+//! The host opens named streams on a [`Streams`] and sends rows into them
+//! from any thread, and [`define_stream_function`] lets SQL read them as
+//! they arrive:
+//!
+//! ```ignore
+//! let streams = Streams::new();
+//! define_stream_function(db, "stream", &["kind", "payload"], streams.clone())?;
+//! let events = streams.open("events", 64);
+//! std::thread::spawn(move || {
+//!     events.send(vec![ValueRef::Text(Cow::Borrowed(b"click")), ValueRef::Null]).unwrap();
+//! });
+//! ```
+//!
+//! ```sql
+//! select * from stream('events');      -- every row, until the stream ends
+//! select * from stream('events', 100); -- stops after 100ms without a row
+//! select * from stream('events', 0);   -- only the rows already queued
+//! ```
+//!
+//! A stream ends when every sender is dropped, after SQL reads the rows
+//! still queued. Streams are bounded: once `capacity` rows are waiting,
+//! senders block until a query reads some, so a slow consumer slows the
+//! producers down instead of buffering without limit. One query reads a
+//! stream at a time, and rows it doesn't read stay queued for the next.
+//!
+//! Without a wait, a query blocks until the stream ends and
+//! `sqlite3_interrupt()` can't stop it, so long-lived streams should be
+//! read with one.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api::{self, ValueType};
+use crate::compare::ValueRef;
+use crate::errors::{Error, Result};
+use crate::ext::{sqlite3, sqlite3_context, sqlite3_value, sqlite3_vtab, sqlite3_vtab_cursor};
+use crate::schema::quote_identifier;
+use crate::table::{
+    define_table_function, BestIndexError, ConstraintOperator, IndexInfo, VTab, VTabArguments,
+    VTabCursor,
+};
+use std::collections::HashMap;
+use std::os::raw::c_int;
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// One row of a stream, a value for each column in order. Missing trailing
+/// values are NULL.
+pub type StreamRow = Vec<ValueRef<'static>>;
+
+/// A stream that isn't being read. None while a query has its receiver.
+type Slot = Option<Receiver<StreamRow>>;
+
+/// Named streams, shared by the host and every query on them. Clones refer
+/// to the same streams.
+#[derive(Clone, Default)]
+pub struct Streams {
+    slots: Arc<Mutex<HashMap<String, Slot>>>,
+}
+
+impl Streams {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens the stream `name`, replacing any stream of that name, and
+    /// returns its sender. Up to `capacity` rows wait to be read before
+    /// senders block, at least one.
+    pub fn open(&self, name: &str, capacity: usize) -> SyncSender<StreamRow> {
+        let (sender, receiver) = sync_channel(capacity.max(1));
+        self.slots().insert(name.to_owned(), Some(receiver));
+        sender
+    }
+
+    /// Closes the stream `name`, dropping any rows that weren't read.
+    /// Returns whether there was one.
+    pub fn close(&self, name: &str) -> bool {
+        self.slots().remove(name).is_some()
+    }
+
+    /// The names of the open streams.
+    pub fn names(&self) -> Vec<String> {
+        self.slots().keys().cloned().collect()
+    }
+
+    fn slots(&self) -> std::sync::MutexGuard<'_, HashMap<String, Slot>> {
+        // a panic while holding the lock leaves the map itself consistent
+        self.slots
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn take(&self, name: &str) -> Result<Receiver<StreamRow>> {
+        match self.slots().get_mut(name) {
+            Some(slot) => slot.take().ok_or_else(|| {
+                Error::new_message(format!("stream {:?} is already being read", name))
+            }),
+            None => Err(Error::new_message(format!("no stream named {:?}", name))),
+        }
+    }
+
+    /// Puts back a receiver a query is done with, unless the stream has
+    /// been closed or reopened since.
+    fn give_back(&self, name: &str, receiver: Receiver<StreamRow>) {
+        let mut slots = self.slots();
+        if let Some(slot) = slots.get_mut(name) {
+            if slot.is_none() {
+                *slot = Some(receiver);
+            }
+        }
+    }
+
+    /// Forgets a stream whose senders are all gone, unless it's been
+    /// reopened since.
+    fn end(&self, name: &str) {
+        let mut slots = self.slots();
+        if matches!(slots.get(name), Some(None)) {
+            slots.remove(name);
+        }
+    }
+}
+
+/// Defines the table function `name(stream [, wait_ms])` with the given
+/// columns, reading rows from `streams`.
+pub fn define_stream_function(
+    db: *mut sqlite3,
+    name: &str,
+    columns: &[&str],
+    streams: Streams,
+) -> Result<()> {
+    if columns.is_empty() {
+        return Err(Error::new_message("a stream needs at least one column"));
+    }
+    let columns = columns.iter().map(|c| (*c).to_owned()).collect();
+    define_table_function::<StreamTable>(db, name, Some((columns, streams)))
+}
+
+/// Set in idxNum when a wait_ms argument was given.
+const HAS_WAIT: c_int = 1;
+
+#[repr(C)]
+pub struct StreamTable {
+    /// must be first
+    base: sqlite3_vtab,
+    columns: usize,
+    streams: Streams,
+}
+
+impl<'vtab> VTab<'vtab> for StreamTable {
+    type Aux = (Vec<String>, Streams);
+    type Cursor = StreamCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, StreamTable)> {
+        let (columns, streams) =
+            aux.ok_or_else(|| Error::new_message("stream function defined without streams"))?;
+        let names: Vec<String> = columns.iter().map(|c| quote_identifier(c)).collect();
+        let base: sqlite3_vtab = unsafe { std::mem::zeroed() };
+        Ok((
+            format!(
+                "CREATE TABLE x({}, stream hidden, wait_ms hidden)",
+                names.join(", ")
+            ),
+            StreamTable {
+                base,
+                columns: columns.len(),
+                streams: streams.clone(),
+            },
+        ))
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        let stream_column = self.columns as i32;
+        let mut has_stream = false;
+        let mut idx_num = 0;
+        for mut constraint in info.constraints() {
+            let argv_index = match constraint.column_idx() - stream_column {
+                0 => 1,
+                1 => 2,
+                _ => continue,
+            };
+            if !constraint.usable() || constraint.op() != Some(ConstraintOperator::EQ) {
+                return Err(BestIndexError::Constraint);
+            }
+            constraint.set_argv_index(argv_index);
+            constraint.set_omit(true);
+            if argv_index == 1 {
+                has_stream = true;
+            } else {
+                idx_num |= HAS_WAIT;
+            }
+        }
+        if !has_stream {
+            return Err(BestIndexError::Constraint);
+        }
+        info.set_estimated_cost(1.0);
+        info.set_idxnum(idx_num);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<StreamCursor> {
+        Ok(StreamCursor::new(self.streams.clone()))
+    }
+}
+
+#[repr(C)]
+pub struct StreamCursor {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    streams: Streams,
+    reading: Option<(String, Receiver<StreamRow>)>,
+    wait: Option<Duration>,
+    current: Option<StreamRow>,
+    rowid: i64,
+}
+
+impl StreamCursor {
+    fn new(streams: Streams) -> StreamCursor {
+        let base: sqlite3_vtab_cursor = unsafe { std::mem::zeroed() };
+        StreamCursor {
+            base,
+            streams,
+            reading: None,
+            wait: None,
+            current: None,
+            rowid: 0,
+        }
+    }
+
+    fn release(&mut self) {
+        if let Some((name, receiver)) = self.reading.take() {
+            self.streams.give_back(&name, receiver);
+        }
+    }
+
+    fn receive(&mut self) {
+        let (name, receiver) = match &self.reading {
+            Some(reading) => reading,
+            None => {
+                self.current = None;
+                return;
+            }
+        };
+        let (row, ended) = match self.wait {
+            None => match receiver.recv() {
+                Ok(row) => (Some(row), false),
+                Err(_) => (None, true),
+            },
+            Some(wait) if wait.is_zero() => match receiver.try_recv() {
+                Ok(row) => (Some(row), false),
+                Err(TryRecvError::Empty) => (None, false),
+                Err(TryRecvError::Disconnected) => (None, true),
+            },
+            Some(wait) => match receiver.recv_timeout(wait) {
+                Ok(row) => (Some(row), false),
+                Err(RecvTimeoutError::Timeout) => (None, false),
+                Err(RecvTimeoutError::Disconnected) => (None, true),
+            },
+        };
+        if ended {
+            self.streams.end(name);
+            self.reading = None;
+        }
+        self.current = row;
+    }
+}
+
+impl Drop for StreamCursor {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+pub(crate) fn result_value(context: *mut sqlite3_context, value: &ValueRef) -> Result<()> {
+    match value {
+        ValueRef::Null => api::result_null(context),
+        ValueRef::Integer(i) => api::result_int64(context, *i),
+        ValueRef::Real(f) => api::result_double(context, *f),
+        ValueRef::Text(text) => return api::result_text(context, String::from_utf8_lossy(text)),
+        ValueRef::Blob(blob) => api::result_blob(context, blob),
+    }
+    Ok(())
+}
+
+impl VTabCursor for StreamCursor {
+    fn filter(
+        &mut self,
+        idx_num: c_int,
+        _idx_str: Option<&str>,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.release();
+        let name = api::value_text(&values[0])?.to_owned();
+        self.wait = if idx_num & HAS_WAIT != 0 && api::value_type(&values[1]) != ValueType::Null {
+            let ms = api::value_int64(&values[1]);
+            if ms < 0 {
+                return Err(Error::new_message("wait_ms can't be negative"));
+            }
+            Some(Duration::from_millis(ms as u64))
+        } else {
+            None
+        };
+        self.reading = Some((name.clone(), self.streams.take(&name)?));
+        self.rowid = 0;
+        self.receive();
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.rowid += 1;
+        self.receive();
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.current.is_none()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, i: c_int) -> Result<()> {
+        match self.current.as_ref().and_then(|row| row.get(i as usize)) {
+            Some(value) => result_value(context, value),
+            None => Ok(()),
+        }
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.rowid)
+    }
+}
@@ -0,0 +1,222 @@
+//! Subtypes, the one-byte tags SQLite carries along with a function's
+//! result, like the `J` that marks text from `json()` as JSON.
+//!
+//! There are only 255 of them and every extension loaded in a process
+//! shares them, so they're split in two:
+//!
+//! - `0x01..=0x7f`, mostly ASCII letters, are for well-known conventions
+//!   that extensions agree on, like [`JSON`], [`POINTER`] and [`WKB`].
+//! - `0x80..=0xff`, [`PRIVATE`], are for a single extension's own values,
+//!   which nothing else is expected to understand.
+//!
+//! [`register`] claims a subtype for a name, failing if it's already
+//! someone else's, and [`allocate`] picks a free private one:
+//!
+//! ```ignore
+//! let VECTOR = subtype::allocate("vec0.vector")?;
+//! ```
+//!
+//! The registry is per copy of this crate, so it catches collisions
+//! between the parts of one extension, or extensions linked into one
+//! program, not with extensions that are loaded separately.
+//!
+//! A function that sets a result subtype has to be defined with
+//! [`FunctionFlags::RESULT_SUBTYPE`](crate::FunctionFlags::RESULT_SUBTYPE),
+//! or SQLite 3.45 and later may drop it. In debug builds, a function
+//! defined with [`define_scalar_function`](crate::define_scalar_function)
+//! without it that sets one fails with an error saying so.
+
+use crate::errors::{Error, Result};
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+use std::sync::Mutex;
+
+/// JSON text, from `json()` and [`crate::api::result_json`].
+pub const JSON: u8 = b'J';
+/// A pointer passed with [`crate::api::result_pointer`], which SQLite
+/// tags itself.
+pub const POINTER: u8 = b'p';
+/// A geometry as a WKB blob, from [`crate::geo::result_wkb`].
+pub const WKB: u8 = b'W';
+
+/// The subtypes for an extension's own use.
+pub const PRIVATE: RangeInclusive<u8> = 0x80..=0xff;
+
+const WELL_KNOWN: [(u8, &str); 3] = [(JSON, "json"), (POINTER, "pointer"), (WKB, "wkb")];
+
+static REGISTRY: Mutex<Option<BTreeMap<u8, String>>> = Mutex::new(None);
+
+fn with_registry<R>(f: impl FnOnce(&mut BTreeMap<u8, String>) -> R) -> R {
+    let mut registry = REGISTRY.lock().unwrap_or_else(|err| err.into_inner());
+    let registry = registry.get_or_insert_with(|| {
+        WELL_KNOWN
+            .iter()
+            .map(|(subtype, name)| (*subtype, name.to_string()))
+            .collect()
+    });
+    f(registry)
+}
+
+fn describe(subtype: u8) -> String {
+    if subtype.is_ascii_graphic() {
+        format!("'{}'", subtype as char)
+    } else {
+        format!("0x{:02x}", subtype)
+    }
+}
+
+/// Claims `subtype` for `name`. Registering the same name and subtype
+/// again does nothing, but a subtype that's another name's, or a name
+/// that has another subtype, is an error.
+pub fn register(name: &str, subtype: u8) -> Result<u8> {
+    if subtype == 0 {
+        return Err(Error::new_message("subtype 0 means no subtype"));
+    }
+    with_registry(|registry| {
+        if let Some(owner) = registry.get(&subtype) {
+            if owner == name {
+                return Ok(subtype);
+            }
+            return Err(Error::new_message(format!(
+                "subtype {} is already registered for {}",
+                describe(subtype),
+                owner
+            )));
+        }
+        if let Some((existing, _)) = registry.iter().find(|(_, owner)| *owner == name) {
+            return Err(Error::new_message(format!(
+                "{} is already registered with subtype {}",
+                name,
+                describe(*existing)
+            )));
+        }
+        registry.insert(subtype, name.to_owned());
+        Ok(subtype)
+    })
+}
+
+/// The private subtype for `name`, registering the first free one if it
+/// doesn't have one yet.
+pub fn allocate(name: &str) -> Result<u8> {
+    with_registry(|registry| {
+        if let Some((subtype, _)) = registry
+            .iter()
+            .find(|(subtype, owner)| PRIVATE.contains(subtype) && *owner == name)
+        {
+            return Ok(*subtype);
+        }
+        let subtype = PRIVATE
+            .clone()
+            .find(|subtype| !registry.contains_key(subtype))
+            .ok_or_else(|| Error::new_message("all the private subtypes are taken"))?;
+        registry.insert(subtype, name.to_owned());
+        Ok(subtype)
+    })
+}
+
+/// The name `subtype` is registered for.
+pub fn name(subtype: u8) -> Option<String> {
+    with_registry(|registry| registry.get(&subtype).cloned())
+}
+
+#[derive(Clone, Copy)]
+struct Call {
+    declares_result_subtype: bool,
+    undeclared: Option<u8>,
+}
+
+thread_local! {
+    /// The function being called, if it was defined by this crate.
+    static CALL: Cell<Option<Call>> = const { Cell::new(None) };
+}
+
+/// Tracks a function call, to check its results' subtypes against its
+/// flags in debug builds.
+pub(crate) struct CallScope {
+    outer: Option<Call>,
+}
+
+impl CallScope {
+    pub(crate) fn enter(declares_result_subtype: bool) -> CallScope {
+        let outer = CALL.with(|call| {
+            call.replace(Some(Call {
+                declares_result_subtype,
+                undeclared: None,
+            }))
+        });
+        CallScope { outer }
+    }
+
+    /// The error for a subtype the function set without declaring it.
+    pub(crate) fn finish(self) -> Option<Error> {
+        let call = CALL.with(|call| call.replace(self.outer));
+        let subtype = call?.undeclared?;
+        Some(Error::new_message(format!(
+            "function set the result subtype {} but wasn't defined with FunctionFlags::RESULT_SUBTYPE",
+            describe(subtype)
+        )))
+    }
+}
+
+/// Notes a result subtype, for [`CallScope::finish`].
+pub(crate) fn check_result(subtype: u8) {
+    CALL.with(|call| {
+        if let Some(mut current) = call.get() {
+            if !current.declares_result_subtype && current.undeclared.is_none() {
+                current.undeclared = Some(subtype);
+                call.set(Some(current));
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::subtype::*;
+
+    #[test]
+    fn test_register() {
+        assert_eq!(name(JSON).as_deref(), Some("json"));
+        assert_eq!(register("json", JSON).unwrap(), JSON);
+        assert_eq!(
+            register("my.json", JSON).unwrap_err().reported_message(),
+            "subtype 'J' is already registered for json"
+        );
+        assert_eq!(register("test.tagged", b'T').unwrap(), b'T');
+        assert_eq!(
+            register("test.tagged", b'U')
+                .unwrap_err()
+                .reported_message(),
+            "test.tagged is already registered with subtype 'T'"
+        );
+        assert!(register("test.none", 0).is_err());
+    }
+
+    #[test]
+    fn test_allocate() {
+        let first = allocate("test.first").unwrap();
+        let second = allocate("test.second").unwrap();
+        assert!(PRIVATE.contains(&first) && PRIVATE.contains(&second));
+        assert_ne!(first, second);
+        assert_eq!(allocate("test.first").unwrap(), first);
+        assert_eq!(name(second).as_deref(), Some("test.second"));
+    }
+
+    #[test]
+    fn test_call_scope() {
+        let scope = CallScope::enter(false);
+        // a nested call is checked on its own
+        let nested = CallScope::enter(true);
+        check_result(JSON);
+        assert!(nested.finish().is_none());
+        check_result(0x80);
+        assert_eq!(
+            scope.finish().unwrap().reported_message(),
+            "function set the result subtype 0x80 but wasn't defined with FunctionFlags::RESULT_SUBTYPE"
+        );
+        // outside any call, nothing's checked
+        check_result(JSON);
+        assert!(CallScope::enter(false).finish().is_none());
+    }
+}
@@ -11,21 +11,23 @@ use std::ptr;
 use std::slice;
 use std::str::Utf8Error;
 
-use crate::api::{mprintf, value_type, MprintfError, ValueType};
-use crate::errors::{Error, ErrorKind, Result};
+use crate::api::{mprintf, value_int64, value_type, MprintfError, ValueType};
+use crate::compare::ValueRef;
+use crate::errors::{Error, ErrorKind, Result, SqliteMessage};
 use crate::ext::{
     sqlite3, sqlite3_context, sqlite3_index_info, sqlite3_index_info_sqlite3_index_constraint,
     sqlite3_index_info_sqlite3_index_constraint_usage, sqlite3_index_info_sqlite3_index_orderby,
     sqlite3_module, sqlite3_value, sqlite3_vtab, sqlite3_vtab_cursor, sqlite3ext_create_module_v2,
-    sqlite3ext_declare_vtab, sqlite3ext_vtab_distinct, sqlite3ext_vtab_in,
-    sqlite3ext_vtab_in_first, sqlite3ext_vtab_in_next,
+    sqlite3ext_declare_vtab, sqlite3ext_vtab_collation, sqlite3ext_vtab_config,
+    sqlite3ext_vtab_distinct, sqlite3ext_vtab_in, sqlite3ext_vtab_in_first,
+    sqlite3ext_vtab_in_next, sqlite3ext_vtab_on_conflict, sqlite3ext_vtab_rhs_value,
 };
 use serde::{Deserialize, Serialize};
 
 /// Possible operators for a given constraint, found and used in xBestIndex and xFilter.
 /// <https://www.sqlite.org/c3ref/c_index_constraint_eq.html>
 /// TODO EQ=Equals, GT=GreaterThan, etc.
-#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ConstraintOperator {
     /// 'Equals', ex "="
     EQ,
@@ -105,6 +107,76 @@ pub fn operator(op: u8) -> Option<ConstraintOperator> {
     }
 }
 
+impl ConstraintOperator {
+    /// Same as [`operator`], the operator for a raw SQLITE_INDEX_CONSTRAINT_* code.
+    pub fn from_raw(op: u8) -> Option<ConstraintOperator> {
+        operator(op)
+    }
+
+    /// The raw SQLITE_INDEX_CONSTRAINT_* code for the operator.
+    pub fn to_raw(&self) -> u8 {
+        match self {
+            ConstraintOperator::EQ => 2,
+            ConstraintOperator::GT => 4,
+            ConstraintOperator::LE => 8,
+            ConstraintOperator::LT => 16,
+            ConstraintOperator::GE => 32,
+            ConstraintOperator::MATCH => 64,
+            ConstraintOperator::LIKE => 65,
+            ConstraintOperator::GLOB => 66,
+            ConstraintOperator::REGEXP => 67,
+            ConstraintOperator::NE => 68,
+            ConstraintOperator::ISNOT => 69,
+            ConstraintOperator::ISNOTNULL => 70,
+            ConstraintOperator::ISNULL => 71,
+            ConstraintOperator::IS => 72,
+            ConstraintOperator::LIMIT => 73,
+            ConstraintOperator::OFFSET => 74,
+            ConstraintOperator::FUNCTION(op) => *op,
+        }
+    }
+}
+
+impl TryFrom<u8> for ConstraintOperator {
+    type Error = u8;
+
+    /// Fails with the given code if it isn't a known operator.
+    fn try_from(op: u8) -> std::result::Result<Self, Self::Error> {
+        operator(op).ok_or(op)
+    }
+}
+
+impl From<ConstraintOperator> for u8 {
+    fn from(op: ConstraintOperator) -> u8 {
+        op.to_raw()
+    }
+}
+
+/// Displays the operator as it would appear in SQL, ex "<=" or "IS NOT NULL".
+impl std::fmt::Display for ConstraintOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConstraintOperator::EQ => write!(f, "="),
+            ConstraintOperator::GT => write!(f, ">"),
+            ConstraintOperator::LE => write!(f, "<="),
+            ConstraintOperator::LT => write!(f, "<"),
+            ConstraintOperator::GE => write!(f, ">="),
+            ConstraintOperator::MATCH => write!(f, "MATCH"),
+            ConstraintOperator::LIKE => write!(f, "LIKE"),
+            ConstraintOperator::GLOB => write!(f, "GLOB"),
+            ConstraintOperator::REGEXP => write!(f, "REGEXP"),
+            ConstraintOperator::NE => write!(f, "!="),
+            ConstraintOperator::ISNOT => write!(f, "IS NOT"),
+            ConstraintOperator::ISNOTNULL => write!(f, "IS NOT NULL"),
+            ConstraintOperator::ISNULL => write!(f, "IS NULL"),
+            ConstraintOperator::IS => write!(f, "IS"),
+            ConstraintOperator::LIMIT => write!(f, "LIMIT"),
+            ConstraintOperator::OFFSET => write!(f, "OFFSET"),
+            ConstraintOperator::FUNCTION(op) => write!(f, "FUNCTION({})", op),
+        }
+    }
+}
+
 /// Wraps the raw sqlite3_index_info C struct, which represents
 /// the possible constraints and outputs the xBestIndex method
 /// should use and return.
@@ -145,6 +217,27 @@ impl IndexInfo {
             })
             .collect();
     }
+    /// The query's `LIMIT`, as a constraint with the limit as its
+    /// right-hand side. SQLite 3.38 and later offer it when the table is
+    /// the only one in the query and nothing like a `GROUP BY` comes
+    /// between it and the `LIMIT`, but only use it if every other
+    /// constraint gets an argvIndex. Consume it and [`IndexInfo::offset`]
+    /// with [`IndexPlan::consume_limit`](crate::index_plan::IndexPlan::consume_limit).
+    pub fn limit(&self) -> Option<Constraint> {
+        self.find_operator(ConstraintOperator::LIMIT)
+    }
+
+    /// The query's `OFFSET`, when SQLite offers [`IndexInfo::limit`].
+    pub fn offset(&self) -> Option<Constraint> {
+        self.find_operator(ConstraintOperator::OFFSET)
+    }
+
+    fn find_operator(&self, op: ConstraintOperator) -> Option<Constraint> {
+        self.constraints()
+            .into_iter()
+            .find(|constraint| constraint.usable() && constraint.op() == Some(op))
+    }
+
     pub fn order_bys(&self) -> Vec<OrderBy> {
         let order_bys = unsafe {
             slice::from_raw_parts(
@@ -191,11 +284,15 @@ impl IndexInfo {
     }
     // TODO ORDER BY
 
-    // TODO the u64 by itself isn't very useful - offer a func that does the
-    // manually bitshifting/checks internally.
     pub fn columns_used(&self) -> u64 {
         unsafe { (*self.index_info).colUsed }
     }
+    /// Whether the query reads column `i`. SQLite only tracks the first 63
+    /// columns one by one, and column 63 and later all share the last bit.
+    pub fn column_used(&self, i: c_int) -> bool {
+        let bit = i.clamp(0, 63) as u64;
+        self.columns_used() & (1 << bit) != 0
+    }
     pub fn distinct(&self) -> i32 {
         unsafe { sqlite3ext_vtab_distinct(self.index_info) }
     }
@@ -232,21 +329,95 @@ impl Constraint {
         unsafe { (*self.usage).omit = u8::from(value) }
     }
 
-    // # TODO check for sqlite version 3.38.0 (2022-02-22)
+    /// The argvIndex previously assigned with `set_argv_index`, 0 if unclaimed.
+    pub fn argv_index(&self) -> i32 {
+        unsafe { (*self.usage).argvIndex }
+    }
+    /// Whether `set_omit(true)` was previously called on this constraint.
+    pub fn omit(&self) -> bool {
+        unsafe { (*self.usage).omit != 0 }
+    }
+
+    /// Whether the constraint is an `IN (...)` that xFilter can get all
+    /// the values of at once, with
+    /// [`sqlite3_vtab_in`](https://www.sqlite.org/c3ref/vtab_in.html).
+    /// Always false before SQLite 3.38, which added it.
     pub fn can_process_all_in(&self) -> bool {
-        unsafe { sqlite3ext_vtab_in(self.index_info, self.constraint_idx, -1) == 1 }
+        self.vtab_in(-1)
     }
+
+    /// Asks for all the values of the `IN (...)` constraint in a single
+    /// xFilter call, instead of a call for each, returning whether SQLite
+    /// agreed. The constraint also needs an argvIndex, and its value in
+    /// xFilter is then read with [`InValues`].
     pub fn enable_process_all_in(&self) -> bool {
-        unsafe { sqlite3ext_vtab_in(self.index_info, self.constraint_idx, 1) == 1 }
+        self.vtab_in(1)
     }
+
+    /// Takes back [`Constraint::enable_process_all_in`], so xFilter is
+    /// called for each of the values.
     pub fn disable_process_all_in(&self) -> bool {
-        unsafe { sqlite3ext_vtab_in(self.index_info, self.constraint_idx, 0) == 1 }
+        self.vtab_in(0)
+    }
+
+    /// The right-hand side of the constraint, when it's known while
+    /// planning, like the literal in `x > 10`, from
+    /// [`sqlite3_vtab_rhs_value`](https://www.sqlite.org/c3ref/vtab_rhs_value.html).
+    /// None when it's only known later, like a bound parameter or a column
+    /// of another table, and always before SQLite 3.38, which added it.
+    /// Text and blob contents are borrowed from SQLite until xBestIndex
+    /// returns.
+    pub fn rhs_value(&self) -> Option<ValueRef<'_>> {
+        if crate::api::libversion_number() < 3_038_000 {
+            return None;
+        }
+        let mut value: *mut sqlite3_value = ptr::null_mut();
+        let rc =
+            unsafe { sqlite3ext_vtab_rhs_value(self.index_info, self.constraint_idx, &mut value) };
+        (rc == SQLITE_OKAY && !value.is_null()).then(|| ValueRef::from_value(&value))
+    }
+
+    /// The name of the collation the constraint compares text with, like
+    /// `"BINARY"` or `"NOCASE"`, from
+    /// [`sqlite3_vtab_collation`](https://www.sqlite.org/c3ref/vtab_collation.html).
+    /// Only valid in xBestIndex, and always None before SQLite 3.22, which
+    /// added it.
+    pub fn collation(&self) -> Option<&str> {
+        if crate::api::libversion_number() < 3_022_000 {
+            return None;
+        }
+        let name = unsafe { sqlite3ext_vtab_collation(self.index_info, self.constraint_idx) };
+        if name.is_null() {
+            return None;
+        }
+        unsafe { CStr::from_ptr(name) }.to_str().ok()
+    }
+
+    fn vtab_in(&self, handle: c_int) -> bool {
+        if crate::api::libversion_number() < 3_038_000 {
+            return false;
+        }
+        unsafe { sqlite3ext_vtab_in(self.index_info, self.constraint_idx, handle) == 1 }
     }
 }
 
+/// The values of an `IN (...)` constraint that xBestIndex enabled with
+/// [`Constraint::enable_process_all_in`], read from its xFilter value with
+/// [`sqlite3_vtab_in_first`](https://www.sqlite.org/c3ref/vtab_in_first.html)
+/// and `sqlite3_vtab_in_next`. Each value is valid until xFilter returns.
+///
+/// ```ignore
+/// for value in InValues::new(values[0]) {
+///     keys.push(api::value_int64(&value?));
+/// }
+/// ```
+///
+/// Any other value fails on the first call to `next()`, after which the
+/// iterator is done.
 pub struct InValues {
     list_value: *mut sqlite3_value,
     yielded_first: bool,
+    done: bool,
     value: *mut sqlite3_value,
 }
 impl InValues {
@@ -254,28 +425,46 @@ impl InValues {
         InValues {
             list_value,
             yielded_first: false,
-            value: unsafe { std::mem::zeroed() },
+            done: false,
+            value: ptr::null_mut(),
         }
     }
 }
 impl Iterator for InValues {
     type Item = Result<*mut sqlite3_value>;
     fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
         let rc = if self.yielded_first {
             unsafe { sqlite3ext_vtab_in_next(self.list_value, &mut self.value) }
         } else {
             self.yielded_first = true;
             unsafe { sqlite3ext_vtab_in_first(self.list_value, &mut self.value) }
         };
-        if rc == SQLITE_DONE {
-            None
-        } else if rc == SQLITE_ERROR {
-            Some(Err(Error::new_message("TODO")))
-        } else {
-            Some(Ok(self.value))
+        match rc {
+            SQLITE_OKAY => Some(Ok(self.value)),
+            SQLITE_DONE => {
+                self.done = true;
+                None
+            }
+            SQLITE_ERROR => {
+                self.done = true;
+                Some(Err(Error::new_message(
+                    "value isn't the list of an IN constraint enabled with enable_process_all_in()",
+                )))
+            }
+            rc => {
+                self.done = true;
+                Some(Err(Error::new_message(format!(
+                    "could not read the values of an IN constraint, error code {}",
+                    rc
+                ))))
+            }
         }
     }
 }
+impl std::iter::FusedIterator for InValues {}
 
 #[derive(Debug)]
 pub enum OrderByDirection {
@@ -305,12 +494,34 @@ pub enum BestIndexError {
     Constraint,
     Error,
 }
-#[repr(transparent)]
+
+/// The signature of xIntegrity, new in SQLite 3.44.
+/// <https://www.sqlite.org/vtab.html#the_xintegrity_method>
+type XIntegrity = unsafe extern "C" fn(
+    *mut sqlite3_vtab,
+    *const c_char,
+    *const c_char,
+    c_int,
+    *mut *mut c_char,
+) -> c_int;
+
+/// A `sqlite3_module`, with the xIntegrity that version 4 modules have
+/// after xShadowName, which the bindings' older `sqlite3_module` lacks.
+#[repr(C)]
 struct Module<'vtab, T: VTab<'vtab>> {
     base: sqlite3_module,
+    x_integrity: Option<XIntegrity>,
     phantom: PhantomData<&'vtab T>,
 }
 
+impl<'vtab, T: VTab<'vtab>> Module<'vtab, T> {
+    /// A pointer to the whole module, xIntegrity included, for
+    /// sqlite3_create_module_v2.
+    fn as_ptr(&self) -> *const sqlite3_module {
+        (self as *const Self).cast()
+    }
+}
+
 unsafe impl<'vtab, T: VTab<'vtab>> Send for Module<'vtab, T> {}
 unsafe impl<'vtab, T: VTab<'vtab>> Sync for Module<'vtab, T> {}
 
@@ -349,6 +560,7 @@ pub fn define_table_function<'vtab, T: VTab<'vtab> + 'vtab>(
             xRollbackTo: None,
             xShadowName: None,
         },
+        x_integrity: None,
         phantom: PhantomData::<&'vtab T>,
     };
     let cname = CString::new(name)?;
@@ -363,13 +575,17 @@ pub fn define_table_function<'vtab, T: VTab<'vtab> + 'vtab>(
         sqlite3ext_create_module_v2(
             db,
             cname.as_ptr(),
-            &m.base,
+            m.as_ptr(),
             p_app,
             Some(destroy_aux::<T::Aux>),
         )
     };
     if result != SQLITE_OKAY {
-        return Err(Error::new(ErrorKind::TableFunction(result)));
+        return Err(Error::with_db_message(
+            ErrorKind::TableFunction(result),
+            result,
+            db,
+        ));
     }
     Ok(())
 }
@@ -405,6 +621,7 @@ pub fn define_table_function_with_find<'vtab, T: VTabFind<'vtab> + 'vtab>(
             xRollbackTo: None,
             xShadowName: None,
         },
+        x_integrity: None,
         phantom: PhantomData::<&'vtab T>,
     };
     let cname = CString::new(name)?;
@@ -419,13 +636,17 @@ pub fn define_table_function_with_find<'vtab, T: VTabFind<'vtab> + 'vtab>(
         sqlite3ext_create_module_v2(
             db,
             cname.as_ptr(),
-            &m.base,
+            m.as_ptr(),
             p_app,
             Some(destroy_aux::<T::Aux>),
         )
     };
     if result != SQLITE_OKAY {
-        return Err(Error::new(ErrorKind::TableFunction(result)));
+        return Err(Error::with_db_message(
+            ErrorKind::TableFunction(result),
+            result,
+            db,
+        ));
     }
     Ok(())
 }
@@ -446,7 +667,7 @@ pub fn define_virtual_table<'vtab, T: VTab<'vtab> + 'vtab>(
 ) -> Result<()> {
     let m = &Module {
         base: sqlite3_module {
-            iVersion: 2,
+            iVersion: 4,
             xCreate: Some(rust_create::<T>),
             xConnect: Some(rust_connect::<T>),
             xBestIndex: Some(rust_best_index::<T>),
@@ -465,12 +686,13 @@ pub fn define_virtual_table<'vtab, T: VTab<'vtab> + 'vtab>(
             xCommit: None,
             xRollback: None,
             xFindFunction: None,
-            xRename: None,
+            xRename: Some(rust_rename::<T>),
             xSavepoint: None,
             xRelease: None,
             xRollbackTo: None,
-            xShadowName: None,
+            xShadowName: Some(rust_shadow_name::<T>),
         },
+        x_integrity: Some(rust_integrity::<T>),
         phantom: PhantomData::<&'vtab T>,
     };
     let cname = CString::new(name)?;
@@ -482,13 +704,17 @@ pub fn define_virtual_table<'vtab, T: VTab<'vtab> + 'vtab>(
         sqlite3ext_create_module_v2(
             db,
             cname.as_ptr(),
-            &m.base,
+            m.as_ptr(),
             app_pointer,
             Some(destroy_aux::<T::Aux>),
         )
     };
     if result != SQLITE_OKAY {
-        return Err(Error::new(ErrorKind::TableFunction(result)));
+        return Err(Error::with_db_message(
+            ErrorKind::TableFunction(result),
+            result,
+            db,
+        ));
     }
     Ok(())
 }
@@ -500,7 +726,7 @@ pub fn define_virtual_table_with_find<'vtab, T: VTabFind<'vtab> + 'vtab>(
 ) -> Result<()> {
     let m = &Module {
         base: sqlite3_module {
-            iVersion: 2,
+            iVersion: 4,
             xCreate: Some(rust_create::<T>),
             xConnect: Some(rust_connect::<T>),
             xBestIndex: Some(rust_best_index::<T>),
@@ -519,12 +745,13 @@ pub fn define_virtual_table_with_find<'vtab, T: VTabFind<'vtab> + 'vtab>(
             xCommit: None,   //Some(rust_commit::<T>),
             xRollback: None, //Some(rust_rollback::<T>),
             xFindFunction: Some(rust_find_function::<T>),
-            xRename: None,
+            xRename: Some(rust_rename::<T>),
             xSavepoint: None,
             xRelease: None,
             xRollbackTo: None,
-            xShadowName: None,
+            xShadowName: Some(rust_shadow_name::<T>),
         },
+        x_integrity: Some(rust_integrity::<T>),
         phantom: PhantomData::<&'vtab T>,
     };
     let cname = CString::new(name)?;
@@ -539,13 +766,17 @@ pub fn define_virtual_table_with_find<'vtab, T: VTabFind<'vtab> + 'vtab>(
         sqlite3ext_create_module_v2(
             db,
             cname.as_ptr(),
-            &m.base,
+            m.as_ptr(),
             p_app,
             Some(destroy_aux::<T::Aux>),
         )
     };
     if result != SQLITE_OKAY {
-        return Err(Error::new(ErrorKind::TableFunction(result)));
+        return Err(Error::with_db_message(
+            ErrorKind::TableFunction(result),
+            result,
+            db,
+        ));
     }
     Ok(())
 }
@@ -556,7 +787,7 @@ pub fn define_virtual_table_writeable<'vtab, T: VTabWriteable<'vtab> + 'vtab>(
 ) -> Result<()> {
     let m = &Module {
         base: sqlite3_module {
-            iVersion: 2,
+            iVersion: 4,
             xCreate: Some(rust_create::<T>),
             xConnect: Some(rust_connect::<T>),
             xBestIndex: Some(rust_best_index::<T>),
@@ -575,12 +806,13 @@ pub fn define_virtual_table_writeable<'vtab, T: VTabWriteable<'vtab> + 'vtab>(
             xCommit: None,   //Some(rust_commit::<T>),
             xRollback: None, //Some(rust_rollback::<T>),
             xFindFunction: None,
-            xRename: None,
+            xRename: Some(rust_rename::<T>),
             xSavepoint: None,
             xRelease: None,
             xRollbackTo: None,
-            xShadowName: None,
+            xShadowName: Some(rust_shadow_name::<T>),
         },
+        x_integrity: Some(rust_integrity::<T>),
         phantom: PhantomData::<&'vtab T>,
     };
     let cname = CString::new(name)?;
@@ -595,17 +827,23 @@ pub fn define_virtual_table_writeable<'vtab, T: VTabWriteable<'vtab> + 'vtab>(
         sqlite3ext_create_module_v2(
             db,
             cname.as_ptr(),
-            &m.base,
+            m.as_ptr(),
             p_app,
             Some(destroy_aux::<T::Aux>),
         )
     };
     if result != SQLITE_OKAY {
-        return Err(Error::new(ErrorKind::TableFunction(result)));
+        return Err(Error::with_db_message(
+            ErrorKind::TableFunction(result),
+            result,
+            db,
+        ));
     }
     Ok(())
 }
 
+/// Like [`define_virtual_table_writeable`], for a table that also gets the
+/// [`VTabWriteableWithTransactions`] calls.
 pub fn define_virtual_table_writeable_with_transactions<
     'vtab,
     T: VTabWriteableWithTransactions<'vtab> + 'vtab,
@@ -616,7 +854,7 @@ pub fn define_virtual_table_writeable_with_transactions<
 ) -> Result<()> {
     let m = &Module {
         base: sqlite3_module {
-            iVersion: 2,
+            iVersion: 4,
             xCreate: Some(rust_create::<T>),
             xConnect: Some(rust_connect::<T>),
             xBestIndex: Some(rust_best_index::<T>),
@@ -635,12 +873,13 @@ pub fn define_virtual_table_writeable_with_transactions<
             xCommit: Some(rust_commit::<T>),
             xRollback: Some(rust_rollback::<T>),
             xFindFunction: None,
-            xRename: None,
+            xRename: Some(rust_rename::<T>),
             xSavepoint: None,
             xRelease: None,
             xRollbackTo: None,
-            xShadowName: None,
+            xShadowName: Some(rust_shadow_name::<T>),
         },
+        x_integrity: Some(rust_integrity::<T>),
         phantom: PhantomData::<&'vtab T>,
     };
     let cname = CString::new(name)?;
@@ -655,13 +894,84 @@ pub fn define_virtual_table_writeable_with_transactions<
         sqlite3ext_create_module_v2(
             db,
             cname.as_ptr(),
-            &m.base,
+            m.as_ptr(),
             p_app,
             Some(destroy_aux::<T::Aux>),
         )
     };
     if result != SQLITE_OKAY {
-        return Err(Error::new(ErrorKind::TableFunction(result)));
+        return Err(Error::with_db_message(
+            ErrorKind::TableFunction(result),
+            result,
+            db,
+        ));
+    }
+    Ok(())
+}
+
+/// Like [`define_virtual_table_writeable_with_transactions`], for a table
+/// that also gets the [`VTabWriteableNestedTransactions`] calls.
+pub fn define_virtual_table_writeable_with_nested_transactions<
+    'vtab,
+    T: VTabWriteableNestedTransactions<'vtab> + 'vtab,
+>(
+    db: *mut sqlite3,
+    name: &str,
+    aux: Option<T::Aux>,
+) -> Result<()> {
+    let m = &Module {
+        base: sqlite3_module {
+            iVersion: 4,
+            xCreate: Some(rust_create::<T>),
+            xConnect: Some(rust_connect::<T>),
+            xBestIndex: Some(rust_best_index::<T>),
+            xDisconnect: Some(rust_disconnect::<T>),
+            xDestroy: Some(rust_destroy::<T>),
+            xOpen: Some(rust_open::<T>),
+            xClose: Some(rust_close::<T::Cursor>),
+            xFilter: Some(rust_filter::<T::Cursor>),
+            xNext: Some(rust_next::<T::Cursor>),
+            xEof: Some(rust_eof::<T::Cursor>),
+            xColumn: Some(rust_column::<T::Cursor>),
+            xRowid: Some(rust_rowid::<T::Cursor>),
+            xUpdate: Some(rust_update::<T>),
+            xBegin: Some(rust_begin::<T>),
+            xSync: Some(rust_sync::<T>),
+            xCommit: Some(rust_commit::<T>),
+            xRollback: Some(rust_rollback::<T>),
+            xFindFunction: None,
+            xRename: Some(rust_rename::<T>),
+            xSavepoint: Some(rust_savepoint::<T>),
+            xRelease: Some(rust_release::<T>),
+            xRollbackTo: Some(rust_rollback_to::<T>),
+            xShadowName: Some(rust_shadow_name::<T>),
+        },
+        x_integrity: Some(rust_integrity::<T>),
+        phantom: PhantomData::<&'vtab T>,
+    };
+    let cname = CString::new(name)?;
+    let p_app = match aux {
+        Some(aux) => {
+            let boxed_aux: *mut T::Aux = Box::into_raw(Box::new(aux));
+            boxed_aux.cast::<c_void>()
+        }
+        None => ptr::null_mut(),
+    };
+    let result = unsafe {
+        sqlite3ext_create_module_v2(
+            db,
+            cname.as_ptr(),
+            m.as_ptr(),
+            p_app,
+            Some(destroy_aux::<T::Aux>),
+        )
+    };
+    if result != SQLITE_OKAY {
+        return Err(Error::with_db_message(
+            ErrorKind::TableFunction(result),
+            result,
+            db,
+        ));
     }
     Ok(())
 }
@@ -698,6 +1008,7 @@ pub fn define_virtual_table_writeablex<'vtab, T: VTabWriteable<'vtab> + 'vtab>(
             xRollbackTo: None,
             xShadowName: None,
         },
+        x_integrity: None,
         phantom: PhantomData::<&'vtab T>,
     };
     let cname = CString::new(name)?;
@@ -712,13 +1023,17 @@ pub fn define_virtual_table_writeablex<'vtab, T: VTabWriteable<'vtab> + 'vtab>(
         sqlite3ext_create_module_v2(
             db,
             cname.as_ptr(),
-            &m.base,
+            m.as_ptr(),
             p_app,
             Some(destroy_aux::<T::Aux>),
         )
     };
     if result != SQLITE_OKAY {
-        return Err(Error::new(ErrorKind::TableFunction(result)));
+        return Err(Error::with_db_message(
+            ErrorKind::TableFunction(result),
+            result,
+            db,
+        ));
     }
     Ok(())
 }
@@ -748,34 +1063,185 @@ pub trait VTab<'vtab>: Sized {
     fn destroy(&self) -> Result<()> {
         Ok(())
     }
+
+    /// The suffixes of the table's shadow tables, without their
+    /// underscores, like `["data", "idx"]` for a table that keeps its
+    /// contents in `<name>_data` and `<name>_idx`. SQLite only compares the
+    /// part after the last underscore, so suffixes can't have one.
+    ///
+    /// With [`SQLITE_DBCONFIG_DEFENSIVE`](https://www.sqlite.org/c3ref/c_dbconfig_defensive.html)
+    /// on, shadow tables are read-only to SQL outside the table's own
+    /// methods, so they can't be corrupted with direct writes. None by
+    /// default.
+    fn shadow_names() -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Called by `ALTER TABLE ... RENAME TO`, before the table is renamed
+    /// to `new_name`, so a table with shadow tables can rename them too.
+    /// An error stops the rename. Does nothing by default.
+    fn rename(&mut self, _new_name: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called by `PRAGMA integrity_check` on SQLite 3.44 and later, and
+    /// `PRAGMA quick_check` with `quick`, to check the table in `schema`
+    /// named `table`, like that its shadow tables agree with each other.
+    /// Returns a description of the corruption it finds, which the pragma
+    /// reports as one of its rows, or an error if it couldn't check.
+    /// Finds nothing by default.
+    fn integrity(&self, _schema: &str, _table: &str, _quick: bool) -> Result<Option<String>> {
+        Ok(None)
+    }
 }
 
 pub trait VTabWriteable<'vtab>: VTab<'vtab> {
+    /// Inserts, updates or deletes a row.
+    ///
+    /// For an [`UpdateOperation::Insert`] into a rowid table without a
+    /// `rowid` given, the table picks one and writes it to `p_rowid`, which
+    /// is what `last_insert_rowid()` returns afterwards. `INSERT ...
+    /// RETURNING` gets the inserted values, but is evaluated before xUpdate
+    /// runs, so its `rowid` is -1 for rows the table picks one for. SQLite
+    /// doesn't allow RETURNING on UPDATEs or DELETEs of virtual tables.
     fn update(&'vtab mut self, operation: UpdateOperation, p_rowid: *mut i64) -> Result<()>;
 }
 
+/// A writeable rowid table, with a method for each kind of change instead
+/// of one [`VTabWriteable::update`]. Every [`VTabRowidWriteable`] is a
+/// [`VTabWriteable`], so it works with all the `define_*_writeable`
+/// functions.
+///
+/// ```ignore
+/// fn insert(&'vtab mut self, rowid: Option<i64>, values: &[*mut sqlite3_value]) -> Result<i64> {
+///     let rowid = rowid.unwrap_or_else(|| self.store.next_key());
+///     self.store.put(rowid, api::value_text(&values[0])?)?;
+///     Ok(rowid)
+/// }
+/// ```
+///
+/// `WITHOUT ROWID` tables, whose keys aren't integers, and tables that
+/// handle `ON CONFLICT` themselves implement [`VTabWriteable`] instead.
+pub trait VTabRowidWriteable<'vtab>: VTab<'vtab> {
+    /// Inserts a row with the given column values, at `rowid` if the
+    /// statement gave one, and returns the row's rowid, which is what
+    /// `last_insert_rowid()` returns afterwards.
+    fn insert(&'vtab mut self, rowid: Option<i64>, values: &[*mut sqlite3_value]) -> Result<i64>;
+
+    /// Replaces the row at `rowid` with the given column values, moving it
+    /// to `new_rowid` if that's different.
+    fn update(
+        &'vtab mut self,
+        rowid: i64,
+        new_rowid: i64,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()>;
+
+    /// Deletes the row at `rowid`.
+    fn delete(&'vtab mut self, rowid: i64) -> Result<()>;
+}
+
+impl<'vtab, T: VTabRowidWriteable<'vtab>> VTabWriteable<'vtab> for T {
+    fn update(&'vtab mut self, operation: UpdateOperation, p_rowid: *mut i64) -> Result<()> {
+        match operation {
+            UpdateOperation::Delete(rowid) => self.delete(value_int64(rowid)),
+            UpdateOperation::Insert { values, rowid, .. } => {
+                let rowid = self.insert(rowid.map(value_int64), values)?;
+                if !p_rowid.is_null() {
+                    unsafe { *p_rowid = rowid };
+                }
+                Ok(())
+            }
+            UpdateOperation::Update {
+                rowid,
+                new_rowid,
+                values,
+                ..
+            } => {
+                VTabRowidWriteable::update(self, value_int64(rowid), value_int64(new_rowid), values)
+            }
+        }
+    }
+}
+
+/// The function to call instead of the overloaded one, an optional
+/// constraint code, and an optional pointer for the function's
+/// `sqlite3_user_data()`. A code of
+/// [`SQLITE_INDEX_CONSTRAINT_FUNCTION`](crate::function_constraints::SQLITE_INDEX_CONSTRAINT_FUNCTION)
+/// or more also passes `WHERE` terms using the function to xBestIndex, see
+/// [`function_constraints`](crate::function_constraints).
 pub type FindResult = (
     unsafe extern "C" fn(*mut sqlite3_context, i32, *mut *mut sqlite3_value),
     Option<i32>,
     Option<*mut c_void>,
 );
 
+/// A table that overloads functions called on its columns, like FTS5's
+/// `highlight()` or a `contains()` the table can answer from an index,
+/// defined with [`define_virtual_table_with_find`] or
+/// [`define_table_function_with_find`].
+///
+/// SQLite only asks about functions that already exist, so register a
+/// placeholder for each with [`overload_function`](crate::api::overload_function).
+/// Then `find_function` returns the function to call instead, made with
+/// [`scalar_function_raw`](crate::scalar::scalar_function_raw), and with a
+/// constraint code it also becomes a constraint in xBestIndex, see
+/// [`function_constraints`](crate::function_constraints).
 pub trait VTabFind<'vtab>: VTab<'vtab> {
-    // TODO should be able to return SQLITE_INDEX_CONSTRAINT_FUNCTION or more
+    /// The function to call instead of `name` with `argc` arguments, when
+    /// its first argument is one of the table's columns, or `None` to
+    /// call the usual one.
     fn find_function(&'vtab mut self, argc: i32, name: &str) -> Option<FindResult>;
 }
 
+/// A writeable table that takes part in the connection's transactions,
+/// defined with [`define_virtual_table_writeable_with_transactions`], so it
+/// can buffer its writes and apply them all at once, or throw them away.
+///
+/// SQLite calls `begin` before the first write of a transaction, then
+/// `sync` and `commit` when the transaction commits, or `rollback`. Errors
+/// from `commit` are ignored, so a table whose writes can fail, like one
+/// backed by a remote API, applies them in `sync`: an error there rolls
+/// the whole transaction back, with the error's message.
 pub trait VTabWriteableWithTransactions<'vtab>: VTabWriteable<'vtab> {
-    fn begin(&'vtab mut self) -> Result<()>;
-    fn sync(&'vtab mut self) -> Result<()>;
+    /// Starts a transaction. Does nothing by default.
+    fn begin(&'vtab mut self) -> Result<()> {
+        Ok(())
+    }
+    /// Applies the transaction's writes, before any table commits. Does
+    /// nothing by default.
+    fn sync(&'vtab mut self) -> Result<()> {
+        Ok(())
+    }
     fn commit(&'vtab mut self) -> Result<()>;
     fn rollback(&'vtab mut self) -> Result<()>;
 }
 
-pub trait VTabWriteableNestedTransactions<'vtab>: VTabWriteable<'vtab> {
-    fn savepoint(&'vtab mut self, id: c_int) -> Result<()>;
-    fn release(&'vtab mut self, id: c_int) -> Result<()>;
-    fn rollback_to(&'vtab mut self, id: c_int) -> Result<()>;
+/// A transactional table that also takes part in savepoints, defined with
+/// [`define_virtual_table_writeable_with_nested_transactions`], so a table
+/// with state of its own can undo the changes of a `ROLLBACK TO` as well as
+/// a whole transaction's.
+///
+/// Savepoints are numbered by their depth, from 0 for the outermost one.
+/// SQLite also makes savepoints of its own around each statement, so a
+/// statement that fails part way through is undone, and only tells a table
+/// about the ones after its [`begin`](VTabWriteableWithTransactions::begin),
+/// so a table can be rolled back to a savepoint it never saw, which undoes
+/// everything since `begin`. Each method does nothing by default.
+pub trait VTabWriteableNestedTransactions<'vtab>: VTabWriteableWithTransactions<'vtab> {
+    /// Starts the savepoint `id`, which is the table's state as it is now.
+    fn savepoint(&'vtab mut self, _id: c_int) -> Result<()> {
+        Ok(())
+    }
+    /// Ends the savepoint `id` and any after it, keeping their changes.
+    fn release(&'vtab mut self, _id: c_int) -> Result<()> {
+        Ok(())
+    }
+    /// Undoes the changes since the savepoint `id`, which stays open,
+    /// and ends any after it.
+    fn rollback_to(&'vtab mut self, _id: c_int) -> Result<()> {
+        Ok(())
+    }
 }
 
 pub trait VTabCursor: Sized {
@@ -791,6 +1257,125 @@ pub trait VTabCursor: Sized {
     fn rowid(&self) -> Result<i64>;
 }
 
+/// A cursor that describes its whole current row at once, instead of
+/// answering xColumn one column at a time. Every [`VTabRowCursor`] is a
+/// [`VTabCursor`], so it works with all the `define_*` functions.
+///
+/// ```ignore
+/// fn row(&self, w: &mut RowWriter) -> Result<()> {
+///     w.int64(0, || self.id)?
+///         .text(1, || self.name())?
+///         .text(2, || self.expensive_summary())?;
+///     Ok(())
+/// }
+/// ```
+pub trait VTabRowCursor: Sized {
+    fn filter(
+        &mut self,
+        idx_num: c_int,
+        idx_str: Option<&str>,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()>;
+    fn next(&mut self) -> Result<()>;
+    fn eof(&self) -> bool;
+    /// Writes the current row. Only the producer for the column SQLite
+    /// asked for is called, so expensive columns a query doesn't select
+    /// cost nothing.
+    fn row(&self, w: &mut RowWriter) -> Result<()>;
+    fn rowid(&self) -> Result<i64>;
+}
+
+impl<T: VTabRowCursor> VTabCursor for T {
+    fn filter(
+        &mut self,
+        idx_num: c_int,
+        idx_str: Option<&str>,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        VTabRowCursor::filter(self, idx_num, idx_str, values)
+    }
+    fn next(&mut self) -> Result<()> {
+        VTabRowCursor::next(self)
+    }
+    fn eof(&self) -> bool {
+        VTabRowCursor::eof(self)
+    }
+    fn column(&self, ctx: *mut sqlite3_context, i: c_int) -> Result<()> {
+        self.row(&mut RowWriter {
+            context: ctx,
+            column: i,
+        })
+    }
+    fn rowid(&self) -> Result<i64> {
+        VTabRowCursor::rowid(self)
+    }
+}
+
+/// Given to [`VTabRowCursor::row`], writes the one column SQLite asked for
+/// and skips the producers of all the others. Columns that are never
+/// written are NULL.
+pub struct RowWriter {
+    context: *mut sqlite3_context,
+    column: c_int,
+}
+
+impl RowWriter {
+    /// The index of the column SQLite asked for.
+    pub fn column(&self) -> c_int {
+        self.column
+    }
+
+    /// Whether column `i` is the one SQLite asked for.
+    pub fn wants(&self, i: c_int) -> bool {
+        self.column == i
+    }
+
+    /// Writes column `i` with `producer`, which gets the raw context to set
+    /// any kind of result on, like [`result_json`](crate::api::result_json).
+    pub fn with<F>(&mut self, i: c_int, producer: F) -> Result<&mut Self>
+    where
+        F: FnOnce(*mut sqlite3_context) -> Result<()>,
+    {
+        if self.wants(i) {
+            producer(self.context)?;
+        }
+        Ok(self)
+    }
+
+    pub fn int64<F: FnOnce() -> i64>(&mut self, i: c_int, producer: F) -> Result<&mut Self> {
+        self.with(i, |context| {
+            crate::api::result_int64(context, producer());
+            Ok(())
+        })
+    }
+
+    pub fn double<F: FnOnce() -> f64>(&mut self, i: c_int, producer: F) -> Result<&mut Self> {
+        self.with(i, |context| {
+            crate::api::result_double(context, producer());
+            Ok(())
+        })
+    }
+
+    pub fn text<S, F>(&mut self, i: c_int, producer: F) -> Result<&mut Self>
+    where
+        S: AsRef<str>,
+        F: FnOnce() -> S,
+    {
+        self.with(i, |context| crate::api::result_text(context, producer()))
+    }
+
+    pub fn blob<B, F>(&mut self, i: c_int, producer: F) -> Result<&mut Self>
+    where
+        B: AsRef<[u8]>,
+        F: FnOnce() -> B,
+    {
+        self.with(i, |context| {
+            crate::api::result_blob(context, producer().as_ref());
+            Ok(())
+        })
+    }
+}
+
 use std::ffi::CStr;
 
 /// Represents all the arguments given to the virtual table implementation
@@ -859,6 +1444,74 @@ fn process_create_args(
         arguments: arguments.to_vec(),
     })
 }
+/// How virtual tables are allocated: the implementation first, so a pointer
+/// to the allocation is also one to its sqlite3_vtab, then the connection it
+/// was created on, for the methods SQLite doesn't pass it to.
+#[repr(C)]
+struct VTabAllocation<T> {
+    vtab: T,
+    db: *mut sqlite3,
+}
+
+/// Calls [`sqlite3_declare_vtab`](https://www.sqlite.org/c3ref/declare_vtab.html)
+/// with the given schema. On failure, the error message includes SQLite's
+/// own message, the offending schema, and a marker pointing to where in the
+/// schema the error occurred, when SQLite reports one, and
+/// [`Error::sqlite_message`] has the result code it failed with.
+pub fn declare_vtab(db: *mut sqlite3, sql: &str) -> Result<()> {
+    let c_sql = CString::new(sql)?;
+    let rc = unsafe { sqlite3ext_declare_vtab(db, c_sql.as_ptr()) };
+    if rc == SQLITE_OKAY {
+        return Ok(());
+    }
+    let details = SqliteMessage::from_db(rc, db);
+    let message = declare_vtab_diagnostic(sql, &details.message, details.offset);
+    Err(Error::new_message(message).with_sqlite_message(details))
+}
+
+/// Formats a declare_vtab error, pointing to the position in the schema if known:
+///
+/// ```text
+/// declare_vtab failed: near ",": syntax error
+///   CREATE TABLE x(a,, b)
+///                    ^ offset 17
+/// ```
+fn declare_vtab_diagnostic(sql: &str, message: &str, offset: Option<usize>) -> String {
+    // fall back to the token in "near "...": syntax error" messages
+    let offset = offset
+        .filter(|offset| sql.is_char_boundary(*offset))
+        .or_else(|| {
+            let near = message.strip_prefix("near \"")?.split_once("\":")?.0;
+            sql.find(near)
+        });
+    match offset {
+        Some(offset) => {
+            let line_start = sql[..offset].rfind('\n').map_or(0, |i| i + 1);
+            let line_end = sql[offset..].find('\n').map_or(sql.len(), |i| offset + i);
+            let column = sql[line_start..offset].chars().count();
+            format!(
+                "declare_vtab failed: {}\n  {}\n  {}^ offset {}",
+                message,
+                &sql[line_start..line_end],
+                " ".repeat(column),
+                offset
+            )
+        }
+        None => format!("declare_vtab failed: {}, in schema: {}", message, sql),
+    }
+}
+
+/// Sets `err`'s message, mapped by [`crate::errors::set_error_mapper`], as
+/// the error message SQLite reports for a virtual table call.
+unsafe fn set_error_message(err_msg: *mut *mut c_char, err: &Error) {
+    if let ErrorKind::Message(_) | ErrorKind::Constraint(_) | ErrorKind::Abort(_) = err.kind() {
+        // messages can have "%"s, from a schema or the values in a row
+        if let Ok(msg) = mprintf(&err.reported_message().replace('%', "%%")) {
+            *err_msg = msg;
+        }
+    }
+}
+
 /// <https://www.sqlite.org/vtab.html#the_xcreate_method>
 // TODO set error message properly
 unsafe extern "C" fn rust_create<'vtab, T>(
@@ -878,25 +1531,21 @@ where
         Err(_) => return SQLITE_ERROR,
     };
     match T::create(db, aux.as_ref(), args) {
-        Ok((sql, vtab)) => match CString::new(sql) {
-            Ok(c_sql) => {
-                let rc = sqlite3ext_declare_vtab(db, c_sql.as_ptr());
-                if rc == SQLITE_OKAY {
-                    let boxed_vtab: *mut T = Box::into_raw(Box::new(vtab));
-                    *pp_vtab = boxed_vtab.cast::<sqlite3_vtab>();
-                    SQLITE_OKAY
-                } else {
-                    rc
-                }
+        Ok((sql, vtab)) => match declare_vtab(db, &sql) {
+            Ok(()) => {
+                let boxed_vtab = Box::into_raw(Box::new(VTabAllocation { vtab, db }));
+                *pp_vtab = boxed_vtab.cast::<sqlite3_vtab>();
+                SQLITE_OKAY
+            }
+            Err(err) => {
+                set_error_message(err_msg, &err);
+                // SQLite's own code, which isn't always SQLITE_ERROR
+                err.sqlite_message()
+                    .map_or(SQLITE_ERROR, |message| message.code)
             }
-            Err(_err) => SQLITE_ERROR,
         },
         Err(err) => {
-            if let ErrorKind::Message(msg) = err.kind() {
-                if let Ok(err) = mprintf(msg) {
-                    *err_msg = err;
-                }
-            };
+            set_error_message(err_msg, &err);
             err.code()
         }
     }
@@ -921,25 +1570,20 @@ where
         Err(_) => return SQLITE_ERROR,
     };
     match T::connect(db, aux.as_ref(), args) {
-        Ok((sql, vtab)) => match CString::new(sql) {
-            Ok(c_sql) => {
-                let rc = sqlite3ext_declare_vtab(db, c_sql.as_ptr());
-                if rc == SQLITE_OKAY {
-                    let boxed_vtab: *mut T = Box::into_raw(Box::new(vtab));
-                    *pp_vtab = boxed_vtab.cast::<sqlite3_vtab>();
-                    SQLITE_OKAY
-                } else {
-                    rc
-                }
+        Ok((sql, vtab)) => match declare_vtab(db, &sql) {
+            Ok(()) => {
+                let boxed_vtab = Box::into_raw(Box::new(VTabAllocation { vtab, db }));
+                *pp_vtab = boxed_vtab.cast::<sqlite3_vtab>();
+                SQLITE_OKAY
+            }
+            Err(err) => {
+                set_error_message(err_msg, &err);
+                err.sqlite_message()
+                    .map_or(SQLITE_ERROR, |message| message.code)
             }
-            Err(_err) => SQLITE_ERROR,
         },
         Err(err) => {
-            if let ErrorKind::Message(msg) = err.kind() {
-                if let Ok(err) = mprintf(msg) {
-                    *err_msg = err;
-                }
-            };
+            set_error_message(err_msg, &err);
             err.code()
         }
     }
@@ -973,8 +1617,7 @@ where
     if vtab.is_null() {
         return SQLITE_OKAY;
     }
-    let vtab = vtab.cast::<T>();
-    drop(Box::from_raw(vtab));
+    drop(Box::from_raw(vtab.cast::<VTabAllocation<T>>()));
     SQLITE_OKAY
 }
 
@@ -994,14 +1637,80 @@ where
     }
 }
 
+/// <https://www.sqlite.org/vtab.html#the_xrename_method>
+unsafe extern "C" fn rust_rename<'vtab, T>(vtab: *mut sqlite3_vtab, z_new: *const c_char) -> c_int
+where
+    T: VTab<'vtab>,
+{
+    let vt = vtab.cast::<T>();
+    let result = CStr::from_ptr(z_new)
+        .to_str()
+        .map_err(Error::from)
+        .and_then(|new_name| (*vt).rename(new_name));
+    match result {
+        Ok(_) => SQLITE_OKAY,
+        Err(err) => {
+            set_error_message(&mut (*vtab).zErrMsg, &err);
+            err.code()
+        }
+    }
+}
+
+/// <https://www.sqlite.org/vtab.html#the_xintegrity_method>
+unsafe extern "C" fn rust_integrity<'vtab, T>(
+    vtab: *mut sqlite3_vtab,
+    z_schema: *const c_char,
+    z_tab_name: *const c_char,
+    m_flags: c_int,
+    pz_err: *mut *mut c_char,
+) -> c_int
+where
+    T: VTab<'vtab>,
+{
+    let vt = vtab.cast::<T>();
+    let result = CStr::from_ptr(z_schema)
+        .to_str()
+        .map_err(Error::from)
+        .and_then(|schema| {
+            let table = CStr::from_ptr(z_tab_name).to_str()?;
+            // bit 0 is set for quick_check
+            (*vt).integrity(schema, table, m_flags & 1 != 0)
+        });
+    match result {
+        Ok(None) => SQLITE_OKAY,
+        Ok(Some(message)) => match mprintf(&message.replace('%', "%%")) {
+            Ok(message) => {
+                *pz_err = message;
+                SQLITE_OKAY
+            }
+            Err(_) => sqlite3ext_sys::SQLITE_NOMEM as c_int,
+        },
+        Err(err) => {
+            set_error_message(&mut (*vtab).zErrMsg, &err);
+            err.code()
+        }
+    }
+}
+
+/// <https://www.sqlite.org/vtab.html#the_xshadowname_method>
+unsafe extern "C" fn rust_shadow_name<'vtab, T>(name: *const c_char) -> c_int
+where
+    T: VTab<'vtab>,
+{
+    let name = CStr::from_ptr(name).to_bytes();
+    T::shadow_names()
+        .iter()
+        .any(|suffix| suffix.as_bytes().eq_ignore_ascii_case(name)) as c_int
+}
+
 /// <https://www.sqlite.org/vtab.html#the_xopen_method>
 // TODO set error message properly
-unsafe extern "C" fn rust_open<'vtab, T: 'vtab>(
+unsafe extern "C" fn rust_open<'vtab, T>(
     vtab: *mut sqlite3_vtab,
     pp_cursor: *mut *mut sqlite3_vtab_cursor,
 ) -> c_int
 where
-    T: VTab<'vtab>,
+    T: VTab<'vtab> + 'vtab,
 {
     let vt = vtab.cast::<T>();
     match (*vt).open() {
@@ -1014,6 +1723,76 @@ where
     }
 }
 
+/// The `ON CONFLICT` mode of the statement running xUpdate, from
+/// [`sqlite3_vtab_on_conflict`](https://www.sqlite.org/c3ref/vtab_on_conflict.html).
+///
+/// A table that checks its own constraints should, when a row violates
+/// one, replace the conflicting row itself for `Replace`, and otherwise
+/// return an [`Error::constraint`] without having changed anything. With
+/// [`VTabConfig::ConstraintSupport`] set, SQLite then skips the row for
+/// `Ignore`, and keeps or rolls back the statement's earlier changes for
+/// `Fail` or `Rollback`. Undoing the rows a statement already changed
+/// under `Abort` is up to the table's own transaction methods.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictMode {
+    Rollback,
+    Ignore,
+    Fail,
+    /// The default, when a statement doesn't say.
+    Abort,
+    Replace,
+}
+
+impl ConflictMode {
+    /// The mode for a raw SQLITE_ROLLBACK, SQLITE_IGNORE etc. code, Abort
+    /// for anything else.
+    pub fn from_raw(mode: c_int) -> ConflictMode {
+        match mode {
+            SQLITE_ROLLBACK => ConflictMode::Rollback,
+            SQLITE_IGNORE => ConflictMode::Ignore,
+            SQLITE_FAIL => ConflictMode::Fail,
+            SQLITE_REPLACE => ConflictMode::Replace,
+            _ => ConflictMode::Abort,
+        }
+    }
+}
+
+/// Options a virtual table sets on itself from xCreate or xConnect, with
+/// [`vtab_config`].
+/// <https://www.sqlite.org/c3ref/c_vtab_constraint_support.html>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VTabConfig {
+    /// The table returns constraint errors from xUpdate before changing
+    /// anything, so `ON CONFLICT` modes other than ABORT can apply.
+    ConstraintSupport,
+    /// The table is safe to use from triggers and views in untrusted schemas.
+    Innocuous,
+    /// The table can't be used from triggers or views.
+    DirectOnly,
+}
+
+/// Sets `config` on the virtual table being created or connected on `db`.
+/// Only valid from within xCreate or xConnect.
+pub fn vtab_config(db: *mut sqlite3, config: VTabConfig) -> Result<()> {
+    let rc = unsafe {
+        match config {
+            VTabConfig::ConstraintSupport => {
+                sqlite3ext_vtab_config(db, SQLITE_VTAB_CONSTRAINT_SUPPORT, 1)
+            }
+            VTabConfig::Innocuous => sqlite3ext_vtab_config(db, SQLITE_VTAB_INNOCUOUS, 0),
+            VTabConfig::DirectOnly => sqlite3ext_vtab_config(db, SQLITE_VTAB_DIRECTONLY, 0),
+        }
+    };
+    if rc != SQLITE_OKAY {
+        return Err(Error::with_db_message(
+            ErrorKind::Message(format!("could not set {:?} on the virtual table", config)),
+            rc,
+            db,
+        ));
+    }
+    Ok(())
+}
+
 // https://www.sqlite.org/vtab.html#the_xupdate_method
 #[derive(Debug)]
 pub enum UpdateOperation<'a> {
@@ -1021,15 +1800,25 @@ pub enum UpdateOperation<'a> {
     Insert {
         values: &'a [*mut sqlite3_value],
         rowid: Option<&'a *mut sqlite3_value>,
+        /// What to do if the new row violates a constraint.
+        on_conflict: ConflictMode,
     },
     Update {
-        _values: &'a [*mut sqlite3_value],
+        /// The rowid or PRIMARY KEY of the row being updated.
+        rowid: &'a *mut sqlite3_value,
+        /// The row's new rowid or PRIMARY KEY, equal to `rowid` unless the
+        /// update changes it.
+        new_rowid: &'a *mut sqlite3_value,
+        values: &'a [*mut sqlite3_value],
+        /// What to do if the updated row violates a constraint.
+        on_conflict: ConflictMode,
     },
 }
 
 fn determine_update_operation<'a>(
     argc: c_int,
     argv: *mut *mut sqlite3_value,
+    on_conflict: ConflictMode,
 ) -> UpdateOperation<'a> {
     let args = unsafe { slice::from_raw_parts(argv, argc as usize) };
 
@@ -1048,114 +1837,167 @@ fn determine_update_operation<'a>(
         .get(1)
         .expect("argv[1] should be defined on all non-delete operations");
 
+    let values = args
+        .get(2..)
+        .expect("argv[0-1] should be defined on INSERT and UPDATE operations");
+
     //  argc > 1 AND argv[0] = NULL
     // "INSERT: A new row is inserted with column values taken from argv[2] and following."
-    if value_type(argv1) == ValueType::Null {
-        let rowid = if value_type(argv0) == ValueType::Null {
+    // argv[1] is the new rowid, or NULL to let the table pick one
+    if value_type(argv0) == ValueType::Null {
+        let rowid = if value_type(argv1) == ValueType::Null {
             None
         } else {
             Some(argv1)
         };
         UpdateOperation::Insert {
-            values: args
-                .get(2..)
-                .expect("argv[0-1] should be defined on INSERT operations"),
+            values,
             rowid,
+            on_conflict,
         }
     }
-    // argc > 1 AND argv[0] ≠ NULL AND argv[0] = argv[1]
-    // "UPDATE: The row with rowid or PRIMARY KEY argv[0] is updated with new values in argv[2] and following parameters.'
-    else if argv0 == argv1 {
+    // argc > 1 AND argv[0] ≠ NULL
+    // "UPDATE: The row with rowid or PRIMARY KEY argv[0] is updated with new values in argv[2] and following parameters."
+    // When the update changes the rowid or PRIMARY KEY, the new one is in argv[1].
+    else {
         UpdateOperation::Update {
-            _values: args
-                .get(2..)
-                .expect("argv[0-1] should be defined on INSERT operations"),
+            rowid: argv0,
+            new_rowid: argv1,
+            values,
+            on_conflict,
         }
     }
-    //argc > 1 AND argv[0] ≠ NULL AND argv[0] ≠ argv[1]
-    // "UPDATE with rowid or PRIMARY KEY change: The row with rowid or PRIMARY KEY argv[0] is updated with
-    // the rowid or PRIMARY KEY in argv[1] and new values in argv[2] and following parameters. "
-    // what the hell does this even mean
-    else if true {
-        todo!();
-    } else {
-        todo!("some unsupported update operation?")
-    }
 }
 /// <https://www.sqlite.org/vtab.html#the_xupdate_method>
-// TODO set error message properly
-unsafe extern "C" fn rust_update<'vtab, T: 'vtab>(
+unsafe extern "C" fn rust_update<'vtab, T>(
     vtab: *mut sqlite3_vtab,
     argc: c_int,
     argv: *mut *mut sqlite3_value,
     p_rowid: *mut i64,
 ) -> c_int
 where
-    T: VTabWriteable<'vtab>,
+    T: VTabWriteable<'vtab> + 'vtab,
 {
-    let vt = vtab.cast::<T>();
-
-    match (*vt).update(determine_update_operation(argc, argv), p_rowid) {
+    let allocation = vtab.cast::<VTabAllocation<T>>();
+    let on_conflict = ConflictMode::from_raw(sqlite3ext_vtab_on_conflict((*allocation).db));
+    let operation = determine_update_operation(argc, argv, on_conflict);
+    match (*allocation).vtab.update(operation, p_rowid) {
         Ok(_) => SQLITE_OKAY,
-        Err(err) => err.code(),
+        Err(err) => {
+            set_error_message(&mut (*vtab).zErrMsg, &err);
+            err.code()
+        }
     }
 }
 
 /// <https://www.sqlite.org/vtab.html#the_xbegin_method>
-// TODO set error message properly
-unsafe extern "C" fn rust_begin<'vtab, T: 'vtab>(vtab: *mut sqlite3_vtab) -> c_int
+unsafe extern "C" fn rust_begin<'vtab, T>(vtab: *mut sqlite3_vtab) -> c_int
 where
-    T: VTabWriteableWithTransactions<'vtab>,
+    T: VTabWriteableWithTransactions<'vtab> + 'vtab,
 {
     let vt = vtab.cast::<T>();
     match (*vt).begin() {
         Ok(_) => SQLITE_OKAY,
-        Err(err) => err.code(),
+        Err(err) => {
+            set_error_message(&mut (*vtab).zErrMsg, &err);
+            err.code()
+        }
     }
 }
 
 /// <https://www.sqlite.org/vtab.html#the_xsync_method>
-// TODO set error message properly
-unsafe extern "C" fn rust_sync<'vtab, T: 'vtab>(vtab: *mut sqlite3_vtab) -> c_int
+unsafe extern "C" fn rust_sync<'vtab, T>(vtab: *mut sqlite3_vtab) -> c_int
 where
-    T: VTabWriteableWithTransactions<'vtab>,
+    T: VTabWriteableWithTransactions<'vtab> + 'vtab,
 {
     let vt = vtab.cast::<T>();
     match (*vt).sync() {
         Ok(_) => SQLITE_OKAY,
-        Err(err) => err.code(),
+        Err(err) => {
+            set_error_message(&mut (*vtab).zErrMsg, &err);
+            err.code()
+        }
     }
 }
 
 /// <https://www.sqlite.org/vtab.html#the_xrollback_method>
-// TODO set error message properly
-unsafe extern "C" fn rust_rollback<'vtab, T: 'vtab>(vtab: *mut sqlite3_vtab) -> c_int
+unsafe extern "C" fn rust_rollback<'vtab, T>(vtab: *mut sqlite3_vtab) -> c_int
 where
-    T: VTabWriteableWithTransactions<'vtab>,
+    T: VTabWriteableWithTransactions<'vtab> + 'vtab,
 {
     let vt = vtab.cast::<T>();
     match (*vt).rollback() {
         Ok(_) => SQLITE_OKAY,
-        Err(err) => err.code(),
+        Err(err) => {
+            set_error_message(&mut (*vtab).zErrMsg, &err);
+            err.code()
+        }
     }
 }
 
 /// <https://www.sqlite.org/vtab.html#the_xcommit_method>
-// TODO set error message properly
-unsafe extern "C" fn rust_commit<'vtab, T: 'vtab>(vtab: *mut sqlite3_vtab) -> c_int
+unsafe extern "C" fn rust_commit<'vtab, T>(vtab: *mut sqlite3_vtab) -> c_int
 where
-    T: VTabWriteableWithTransactions<'vtab>,
+    T: VTabWriteableWithTransactions<'vtab> + 'vtab,
 {
     let vt = vtab.cast::<T>();
     match (*vt).commit() {
         Ok(_) => SQLITE_OKAY,
-        Err(err) => err.code(),
+        Err(err) => {
+            set_error_message(&mut (*vtab).zErrMsg, &err);
+            err.code()
+        }
+    }
+}
+
+/// <https://www.sqlite.org/vtab.html#the_xsavepoint_xrelease_and_xrollbackto_methods>
+unsafe extern "C" fn rust_savepoint<'vtab, T>(vtab: *mut sqlite3_vtab, id: c_int) -> c_int
+where
+    T: VTabWriteableNestedTransactions<'vtab> + 'vtab,
+{
+    let vt = vtab.cast::<T>();
+    match (*vt).savepoint(id) {
+        Ok(_) => SQLITE_OKAY,
+        Err(err) => {
+            set_error_message(&mut (*vtab).zErrMsg, &err);
+            err.code()
+        }
+    }
+}
+
+/// <https://www.sqlite.org/vtab.html#the_xsavepoint_xrelease_and_xrollbackto_methods>
+unsafe extern "C" fn rust_release<'vtab, T>(vtab: *mut sqlite3_vtab, id: c_int) -> c_int
+where
+    T: VTabWriteableNestedTransactions<'vtab> + 'vtab,
+{
+    let vt = vtab.cast::<T>();
+    match (*vt).release(id) {
+        Ok(_) => SQLITE_OKAY,
+        Err(err) => {
+            set_error_message(&mut (*vtab).zErrMsg, &err);
+            err.code()
+        }
+    }
+}
+
+/// <https://www.sqlite.org/vtab.html#the_xsavepoint_xrelease_and_xrollbackto_methods>
+unsafe extern "C" fn rust_rollback_to<'vtab, T>(vtab: *mut sqlite3_vtab, id: c_int) -> c_int
+where
+    T: VTabWriteableNestedTransactions<'vtab> + 'vtab,
+{
+    let vt = vtab.cast::<T>();
+    match (*vt).rollback_to(id) {
+        Ok(_) => SQLITE_OKAY,
+        Err(err) => {
+            set_error_message(&mut (*vtab).zErrMsg, &err);
+            err.code()
+        }
     }
 }
 
 /// <https://www.sqlite.org/vtab.html#the_xfindfunction_method>
 // TODO set error message properly
-unsafe extern "C" fn rust_find_function<'vtab, T: 'vtab>(
+unsafe extern "C" fn rust_find_function<'vtab, T>(
     vtab: *mut sqlite3_vtab,
     n_arg: c_int,
     name: *const c_char,
@@ -1163,7 +2005,7 @@ unsafe extern "C" fn rust_find_function<'vtab, T: 'vtab>(
     p_p_arg: *mut *mut c_void,
 ) -> c_int
 where
-    T: VTabFind<'vtab>,
+    T: VTabFind<'vtab> + 'vtab,
 {
     let vt = vtab.cast::<T>();
     let name = CStr::from_ptr(name).to_bytes();
@@ -1217,11 +2059,7 @@ where
     match (*cr).filter(idx_num, idx_name, args) {
         Ok(()) => SQLITE_OKAY,
         Err(err) => {
-            if let ErrorKind::Message(msg) = err.kind() {
-                if let Ok(err) = mprintf(msg) {
-                    (*(*cursor).pVtab).zErrMsg = err;
-                }
-            };
+            set_error_message(&mut (*(*cursor).pVtab).zErrMsg, &err);
             err.code()
         }
     }
@@ -1238,11 +2076,7 @@ where
     match (*cr).next() {
         Ok(()) => SQLITE_OKAY,
         Err(err) => {
-            if let ErrorKind::Message(msg) = err.kind() {
-                if let Ok(err) = mprintf(msg) {
-                    (*(*cursor).pVtab).zErrMsg = err;
-                }
-            };
+            set_error_message(&mut (*(*cursor).pVtab).zErrMsg, &err);
             err.code()
         }
     }
@@ -1273,11 +2107,7 @@ where
     match (*cr).column(ctx, i) {
         Ok(()) => SQLITE_OKAY,
         Err(err) => {
-            if let ErrorKind::Message(msg) = err.kind() {
-                if let Ok(err) = mprintf(msg) {
-                    (*(*cursor).pVtab).zErrMsg = err;
-                }
-            };
+            set_error_message(&mut (*(*cursor).pVtab).zErrMsg, &err);
             err.code()
         }
     }
@@ -1301,3 +2131,138 @@ where
         Err(err) => err.code(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::ext::{faux_sqlite_extension_init2, sqlite3_api_routines};
+    use crate::table::*;
+
+    /// A table whose full integrity check finds a problem, with a "%" in
+    /// its message.
+    #[repr(C)]
+    struct Damaged {
+        base: sqlite3_vtab,
+    }
+
+    #[repr(C)]
+    struct DamagedCursor {
+        base: sqlite3_vtab_cursor,
+    }
+
+    impl<'vtab> VTab<'vtab> for Damaged {
+        type Aux = ();
+        type Cursor = DamagedCursor;
+
+        fn connect(
+            _db: *mut sqlite3,
+            _aux: Option<&Self::Aux>,
+            _args: VTabArguments,
+        ) -> Result<(String, Damaged)> {
+            unreachable!()
+        }
+
+        fn best_index(&self, _info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+            unreachable!()
+        }
+
+        fn open(&mut self) -> Result<DamagedCursor> {
+            unreachable!()
+        }
+
+        fn integrity(&self, schema: &str, table: &str, quick: bool) -> Result<Option<String>> {
+            match (schema, quick) {
+                ("main", false) => Ok(Some(format!("{}: 100% off", table))),
+                ("main", true) => Ok(None),
+                _ => Err(Error::new_message(format!("no 100% {} schema", schema))),
+            }
+        }
+    }
+
+    impl VTabCursor for DamagedCursor {
+        fn filter(
+            &mut self,
+            _idx_num: c_int,
+            _idx_str: Option<&str>,
+            _values: &[*mut sqlite3_value],
+        ) -> Result<()> {
+            unreachable!()
+        }
+
+        fn next(&mut self) -> Result<()> {
+            unreachable!()
+        }
+
+        fn eof(&self) -> bool {
+            unreachable!()
+        }
+
+        fn column(&self, _ctx: *mut sqlite3_context, _i: c_int) -> Result<()> {
+            unreachable!()
+        }
+
+        fn rowid(&self) -> Result<i64> {
+            unreachable!()
+        }
+    }
+
+    /// Takes an SQLite-allocated message, if any.
+    unsafe fn take_message(message: &mut *mut c_char) -> Option<String> {
+        if message.is_null() {
+            return None;
+        }
+        let text = CStr::from_ptr(*message).to_str().unwrap().to_owned();
+        rusqlite::ffi::sqlite3_free((*message).cast::<c_void>());
+        *message = ptr::null_mut();
+        Some(text)
+    }
+
+    /// Calls xIntegrity directly, since the bundled SQLite predates it.
+    #[test]
+    fn test_rust_integrity() {
+        unsafe extern "C" fn init(
+            _db: *mut sqlite3,
+            _pz_err_msg: *mut *mut c_char,
+            api: *mut sqlite3_api_routines,
+        ) -> c_int {
+            faux_sqlite_extension_init2(api);
+            SQLITE_OKAY
+        }
+        // the API table comes from a connection, like an entrypoint's
+        let entrypoint =
+            unsafe { std::mem::transmute::<*const (), unsafe extern "C" fn()>(init as *const ()) };
+        unsafe { rusqlite::ffi::sqlite3_auto_extension(Some(entrypoint)) };
+        let _db = rusqlite::Connection::open_in_memory().unwrap();
+        // auto extensions are process-wide, and the other tests here don't
+        // expect connections to run this one
+        unsafe { rusqlite::ffi::sqlite3_cancel_auto_extension(Some(entrypoint)) };
+
+        let mut vtab = Damaged {
+            base: unsafe { std::mem::zeroed() },
+        };
+        let vtab = (&mut vtab as *mut Damaged).cast::<sqlite3_vtab>();
+        let check = |schema: &CStr, flags: c_int| unsafe {
+            let mut message = ptr::null_mut();
+            let rc = rust_integrity::<Damaged>(
+                vtab,
+                schema.as_ptr(),
+                c"ledger".as_ptr(),
+                flags,
+                &mut message,
+            );
+            (rc, take_message(&mut message))
+        };
+
+        assert_eq!(
+            check(c"main", 0),
+            (SQLITE_OKAY, Some("ledger: 100% off".to_owned()))
+        );
+        // quick_check
+        assert_eq!(check(c"main", 1), (SQLITE_OKAY, None));
+        // errors go to the vtab, not the integrity check's message
+        assert_eq!(check(c"temp", 0), (1, None));
+        assert_eq!(
+            unsafe { take_message(&mut (*vtab).zErrMsg) },
+            Some("no 100% temp schema".to_owned())
+        );
+    }
+}
@@ -0,0 +1,291 @@
+//! Tar archives as tables, with the `tarfile` feature.
+//!
+//! [`define_tarfile`] adds the `tarfile(file)` table function, which lists
+//! the entries of a tar archive given its path or its bytes, gzipped or
+//! not:
+//!
+//! ```sql
+//! select name, sz from tarfile('backup.tar.gz') where type = 'file';
+//! select data from tarfile('backup.tar') where name = 'etc/hosts';
+//! ```
+//!
+//! Its columns are `name`, `mode`, `mtime` (seconds since the Unix epoch),
+//! `sz`, `type` (`file`, `dir`, `symlink`, `link` or `other`), `linkname`
+//! and `data`. A tar archive can only be read front to back, so a scan
+//! streams through it, decompressing as it goes, and only keeps the
+//! current entry in memory. An entry's data is only read into memory when
+//! the query selects `data`.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api::{self, ValueType};
+use crate::errors::{Error, Result};
+use crate::ext::{sqlite3, sqlite3_context, sqlite3_value, sqlite3_vtab, sqlite3_vtab_cursor};
+use crate::table::{
+    define_table_function, BestIndexError, ConstraintOperator, IndexInfo, VTab, VTabArguments,
+    VTabCursor,
+};
+use flate2::read::GzDecoder;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Cursor, Read};
+use std::os::raw::c_int;
+use tar::{Archive, Entries, EntryType};
+
+const COLUMN_NAME: c_int = 0;
+const COLUMN_MODE: c_int = 1;
+const COLUMN_MTIME: c_int = 2;
+const COLUMN_SZ: c_int = 3;
+const COLUMN_TYPE: c_int = 4;
+const COLUMN_LINKNAME: c_int = 5;
+const COLUMN_DATA: c_int = 6;
+/// The hidden argument of `tarfile(file)`.
+const COLUMN_FILE: c_int = 7;
+
+/// The `tarfile(file)` argument is given.
+const PLAN_FILE: c_int = 1;
+/// The query reads `data`, so entries' contents are kept.
+const PLAN_DATA: c_int = 2;
+
+/// The two bytes gzip streams start with.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Defines the `tarfile(file)` table function.
+pub fn define_tarfile(db: *mut sqlite3) -> Result<()> {
+    define_table_function::<TarfileTable>(db, "tarfile", None)
+}
+
+fn io_error(err: std::io::Error) -> Error {
+    Error::new_message(format!("tar: {}", err))
+}
+
+/// `reader`, decompressed if it's gzipped.
+fn decompressed<R: BufRead + 'static>(mut reader: R) -> Result<Box<dyn Read>> {
+    let gzipped = reader
+        .fill_buf()
+        .map_err(io_error)?
+        .starts_with(&GZIP_MAGIC);
+    Ok(if gzipped {
+        Box::new(GzDecoder::new(reader))
+    } else {
+        Box::new(reader)
+    })
+}
+
+/// The entries of an archive, read as a stream.
+struct TarStream {
+    // borrows `_archive`, so it's declared first to be dropped first
+    entries: Entries<'static, Box<dyn Read>>,
+    _archive: Box<Archive<Box<dyn Read>>>,
+}
+
+impl TarStream {
+    fn new(reader: Box<dyn Read>) -> Result<TarStream> {
+        let mut archive = Box::new(Archive::new(reader));
+        // SAFETY: the archive is boxed, so it doesn't move, and it's only
+        // dropped after `entries`, the one reference to it.
+        let archive_ref: &'static mut Archive<Box<dyn Read>> =
+            unsafe { &mut *(archive.as_mut() as *mut Archive<Box<dyn Read>>) };
+        let entries = archive_ref.entries().map_err(io_error)?;
+        Ok(TarStream {
+            entries,
+            _archive: archive,
+        })
+    }
+
+    /// The archive in a `tarfile()` argument, a path or the archive's
+    /// bytes.
+    fn from_value(value: &*mut sqlite3_value) -> Result<Option<TarStream>> {
+        let reader = match api::value_type(value) {
+            ValueType::Null => return Ok(None),
+            ValueType::Blob => decompressed(Cursor::new(api::value_blob(value).to_vec()))?,
+            _ => {
+                let path = api::value_text(value)?;
+                let file = File::open(path)
+                    .map_err(|err| Error::new_message(format!("tar: {}: {}", path, err)))?;
+                decompressed(BufReader::new(file))?
+            }
+        };
+        TarStream::new(reader).map(Some)
+    }
+
+    /// The next entry, with its contents if `with_data`.
+    fn next_row(&mut self, with_data: bool) -> Result<Option<Row>> {
+        let mut entry = match self.entries.next() {
+            None => return Ok(None),
+            Some(entry) => entry.map_err(io_error)?,
+        };
+        let header = entry.header();
+        let entry_type = match header.entry_type() {
+            EntryType::Regular | EntryType::Continuous => "file",
+            EntryType::Directory => "dir",
+            EntryType::Symlink => "symlink",
+            EntryType::Link => "link",
+            _ => "other",
+        };
+        let mut row = Row {
+            name: entry
+                .path()
+                .map_err(io_error)?
+                .to_string_lossy()
+                .into_owned(),
+            mode: header.mode().map_err(io_error)?,
+            mtime: header.mtime().map_err(io_error)?,
+            size: entry.size(),
+            entry_type,
+            linkname: entry
+                .link_name()
+                .map_err(io_error)?
+                .map(|link| link.to_string_lossy().into_owned()),
+            data: None,
+        };
+        if with_data && entry_type == "file" {
+            let mut data = Vec::with_capacity(row.size.min(1 << 24) as usize);
+            entry.read_to_end(&mut data).map_err(io_error)?;
+            row.data = Some(data);
+        }
+        Ok(Some(row))
+    }
+}
+
+/// One entry of an archive.
+struct Row {
+    name: String,
+    mode: u32,
+    mtime: u64,
+    size: u64,
+    entry_type: &'static str,
+    linkname: Option<String>,
+    data: Option<Vec<u8>>,
+}
+
+#[repr(C)]
+pub struct TarfileTable {
+    /// must be first
+    base: sqlite3_vtab,
+}
+
+impl<'vtab> VTab<'vtab> for TarfileTable {
+    type Aux = ();
+    type Cursor = TarfileCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, TarfileTable)> {
+        let base: sqlite3_vtab = unsafe { std::mem::zeroed() };
+        Ok((
+            "CREATE TABLE x(name TEXT, mode INTEGER, mtime INTEGER, sz INTEGER, type TEXT, linkname TEXT, data BLOB, file hidden)".to_owned(),
+            TarfileTable { base },
+        ))
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        let mut plan = 0;
+        for mut constraint in info.constraints() {
+            if constraint.column_idx() != COLUMN_FILE {
+                continue;
+            }
+            if !constraint.usable() || constraint.op() != Some(ConstraintOperator::EQ) {
+                return Err(BestIndexError::Constraint);
+            }
+            constraint.set_argv_index(1);
+            constraint.set_omit(true);
+            plan = PLAN_FILE;
+        }
+        // without an archive there's nothing to list
+        if plan != PLAN_FILE {
+            return Err(BestIndexError::Constraint);
+        }
+        if info.column_used(COLUMN_DATA) {
+            plan |= PLAN_DATA;
+        }
+        info.set_idxnum(plan);
+        info.set_estimated_cost(1000.0);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<TarfileCursor> {
+        let base: sqlite3_vtab_cursor = unsafe { std::mem::zeroed() };
+        Ok(TarfileCursor {
+            base,
+            stream: None,
+            with_data: false,
+            row: None,
+            rowid: 0,
+        })
+    }
+}
+
+#[repr(C)]
+pub struct TarfileCursor {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    stream: Option<TarStream>,
+    with_data: bool,
+    row: Option<Row>,
+    rowid: i64,
+}
+
+impl TarfileCursor {
+    fn advance(&mut self) -> Result<()> {
+        self.row = match self.stream.as_mut() {
+            Some(stream) => stream.next_row(self.with_data)?,
+            None => None,
+        };
+        self.rowid += 1;
+        Ok(())
+    }
+}
+
+impl VTabCursor for TarfileCursor {
+    fn filter(
+        &mut self,
+        idx_num: c_int,
+        _idx_str: Option<&str>,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.stream = match values.first() {
+            Some(file) => TarStream::from_value(file)?,
+            None => None,
+        };
+        self.with_data = idx_num & PLAN_DATA != 0;
+        self.rowid = 0;
+        self.advance()
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.advance()
+    }
+
+    fn eof(&self) -> bool {
+        self.row.is_none()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, i: c_int) -> Result<()> {
+        let row = self
+            .row
+            .as_ref()
+            .ok_or_else(|| Error::new_message("tar: no current entry"))?;
+        match i {
+            COLUMN_NAME => api::result_text(context, &row.name)?,
+            COLUMN_MODE => api::result_int64(context, row.mode as i64),
+            COLUMN_MTIME => api::result_int64(context, row.mtime as i64),
+            COLUMN_SZ => api::result_int64(context, row.size as i64),
+            COLUMN_TYPE => api::result_text(context, row.entry_type)?,
+            COLUMN_LINKNAME => match &row.linkname {
+                Some(linkname) => api::result_text(context, linkname)?,
+                None => api::result_null(context),
+            },
+            COLUMN_DATA => match &row.data {
+                Some(data) => api::result_blob(context, data),
+                None => api::result_null(context),
+            },
+            _ => api::result_null(context),
+        }
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.rowid)
+    }
+}
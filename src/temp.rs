@@ -0,0 +1,227 @@
+//! Temporary files for virtual tables that spill data to disk, like large
+//! sorts or materialized results.
+//!
+//! [`TempFile`] is opened through the connection's VFS the same way SQLite
+//! opens its own temporary files, so it follows the VFS's rules (and any
+//! encryption or in-memory VFS in use), and is deleted when closed.
+//! [`connection_temp_dir`] is a plain directory for code that needs real
+//! paths, removed when the connection closes.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::database::main_vfs;
+use crate::errors::{Error, Result};
+use crate::ext::{sqlite3, sqlite3_file, sqlite3_vfs, sqlite3ext_vfs_find};
+use crate::hooks::on_connection_close;
+use sqlite3ext_sys::{
+    SQLITE_IOERR_SHORT_READ, SQLITE_OPEN_CREATE, SQLITE_OPEN_DELETEONCLOSE, SQLITE_OPEN_EXCLUSIVE,
+    SQLITE_OPEN_READWRITE, SQLITE_OPEN_TEMP_JOURNAL,
+};
+use std::{
+    alloc::Layout,
+    collections::HashMap,
+    os::raw::{c_int, c_void},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+/// The directory SQLite would use for temporary files: `SQLITE_TMPDIR` if
+/// set, otherwise the platform's temporary directory (`TMPDIR` on Unix).
+pub fn temp_directory() -> PathBuf {
+    match std::env::var_os("SQLITE_TMPDIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => std::env::temp_dir(),
+    }
+}
+
+/// Temporary directories created by [`connection_temp_dir`], by connection.
+static CONNECTION_TEMP_DIRS: Mutex<Option<HashMap<usize, PathBuf>>> = Mutex::new(None);
+static NEXT_TEMP_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/// A temporary directory for the given connection, created on first use
+/// inside [`temp_directory`] and removed, with everything in it, when the
+/// connection closes.
+pub fn connection_temp_dir(db: *mut sqlite3) -> Result<PathBuf> {
+    let mut dirs = CONNECTION_TEMP_DIRS
+        .lock()
+        .map_err(|_| Error::new_message("temporary directory registry was poisoned"))?;
+    let dirs = dirs.get_or_insert_with(HashMap::new);
+    if let Some(dir) = dirs.get(&(db as usize)) {
+        return Ok(dir.clone());
+    }
+    let dir = temp_directory().join(format!(
+        "sqlite-loadable-{}-{}",
+        std::process::id(),
+        NEXT_TEMP_DIR.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::create_dir_all(&dir).map_err(|err| {
+        Error::new_message(format!(
+            "could not create temporary directory {}: {}",
+            dir.display(),
+            err
+        ))
+    })?;
+    let key = db as usize;
+    on_connection_close(db, move || {
+        if let Ok(mut dirs) = CONNECTION_TEMP_DIRS.lock() {
+            if let Some(dir) = dirs.as_mut().and_then(|dirs| dirs.remove(&key)) {
+                let _ = std::fs::remove_dir_all(dir);
+            }
+        }
+    })
+    .inspect_err(|_| {
+        let _ = std::fs::remove_dir_all(&dir);
+    })?;
+    dirs.insert(key, dir.clone());
+    Ok(dir)
+}
+
+/// An anonymous temporary file opened with a VFS's xOpen, deleted when dropped.
+pub struct TempFile {
+    file: *mut sqlite3_file,
+    layout: Layout,
+}
+
+// the file is only ever used through &mut self
+unsafe impl Send for TempFile {}
+
+fn io_error(operation: &str, rc: c_int) -> Error {
+    Error::new_message(format!(
+        "temporary file {} failed with error code {}",
+        operation, rc
+    ))
+}
+
+impl TempFile {
+    /// Opens a temporary file with the VFS of the connection's main
+    /// database, or the default VFS if that can't be found.
+    pub fn new(db: *mut sqlite3) -> Result<TempFile> {
+        let vfs = main_vfs(db)?;
+        if vfs.is_null() {
+            return TempFile::with_default_vfs();
+        }
+        TempFile::with_vfs(vfs)
+    }
+
+    /// Opens a temporary file with the default VFS.
+    pub fn with_default_vfs() -> Result<TempFile> {
+        let vfs = unsafe { sqlite3ext_vfs_find(std::ptr::null()) };
+        if vfs.is_null() {
+            return Err(Error::new_message("no default VFS is registered"));
+        }
+        TempFile::with_vfs(vfs)
+    }
+
+    fn with_vfs(vfs: *mut sqlite3_vfs) -> Result<TempFile> {
+        let size = unsafe { (*vfs).szOsFile }.max(std::mem::size_of::<sqlite3_file>() as c_int);
+        let layout = Layout::from_size_align(size as usize, 8)
+            .map_err(|_| Error::new_message("invalid VFS file size"))?;
+        let file = unsafe { std::alloc::alloc_zeroed(layout) }.cast::<sqlite3_file>();
+        if file.is_null() {
+            std::alloc::handle_alloc_error(layout);
+        }
+        // a null name asks the VFS for an anonymous file that it picks the
+        // location of, the same as SQLite's own temporary files
+        let flags = SQLITE_OPEN_READWRITE
+            | SQLITE_OPEN_CREATE
+            | SQLITE_OPEN_EXCLUSIVE
+            | SQLITE_OPEN_DELETEONCLOSE
+            | SQLITE_OPEN_TEMP_JOURNAL;
+        let mut out_flags: c_int = 0;
+        let rc = unsafe {
+            match (*vfs).xOpen {
+                Some(x_open) => x_open(vfs, std::ptr::null(), file, flags as c_int, &mut out_flags),
+                None => 1,
+            }
+        };
+        // TempFile's drop closes the file if xOpen set its methods, even on failure
+        let temp_file = TempFile { file, layout };
+        if rc != 0 {
+            return Err(io_error("open", rc));
+        }
+        Ok(temp_file)
+    }
+
+    /// Reads `buffer.len()` bytes at `offset`. Returns the number of bytes
+    /// read, less than requested at the end of the file.
+    pub fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let size = self.len()?;
+        let rc = unsafe {
+            let x_read = (*(*self.file).pMethods).xRead.expect("xRead");
+            x_read(
+                self.file,
+                buffer.as_mut_ptr().cast::<c_void>(),
+                buffer.len() as c_int,
+                offset as i64,
+            )
+        };
+        match rc as u32 {
+            0 => Ok(buffer.len()),
+            // the VFS zero-fills the rest of the buffer
+            SQLITE_IOERR_SHORT_READ => Ok(size.saturating_sub(offset) as usize),
+            _ => Err(io_error("read", rc)),
+        }
+    }
+
+    /// Writes all of `data` at `offset`, extending the file if needed.
+    pub fn write_at(&mut self, offset: u64, data: &[u8]) -> Result<()> {
+        let rc = unsafe {
+            let x_write = (*(*self.file).pMethods).xWrite.expect("xWrite");
+            x_write(
+                self.file,
+                data.as_ptr().cast::<c_void>(),
+                data.len() as c_int,
+                offset as i64,
+            )
+        };
+        if rc != 0 {
+            return Err(io_error("write", rc));
+        }
+        Ok(())
+    }
+
+    /// The size of the file in bytes.
+    pub fn len(&self) -> Result<u64> {
+        let mut size: i64 = 0;
+        let rc = unsafe {
+            let x_file_size = (*(*self.file).pMethods).xFileSize.expect("xFileSize");
+            x_file_size(self.file, &mut size)
+        };
+        if rc != 0 {
+            return Err(io_error("size", rc));
+        }
+        Ok(size as u64)
+    }
+
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Truncates (or extends) the file to `size` bytes.
+    pub fn truncate(&mut self, size: u64) -> Result<()> {
+        let rc = unsafe {
+            let x_truncate = (*(*self.file).pMethods).xTruncate.expect("xTruncate");
+            x_truncate(self.file, size as i64)
+        };
+        if rc != 0 {
+            return Err(io_error("truncate", rc));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        unsafe {
+            let methods = (*self.file).pMethods;
+            if !methods.is_null() {
+                if let Some(x_close) = (*methods).xClose {
+                    x_close(self.file);
+                }
+            }
+            std::alloc::dealloc(self.file.cast::<u8>(), self.layout);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+//! Wrappers around a few [`sqlite3_test_control`](https://www.sqlite.org/c3ref/test_control.html)
+//! verbs, for making randomized and contention-related extension behavior
+//! reproducible in tests.
+//!
+//! These change process-wide state that every connection shares, so tests
+//! using them shouldn't run in parallel with others that care. They do
+//! nothing in SQLite builds compiled with `SQLITE_UNTESTABLE`.
+
+use crate::errors::{Error, Result};
+use crate::ext::{
+    sqlite3, sqlite3ext_test_control, sqlite3ext_test_control_int, sqlite3ext_test_control_int_db,
+};
+use sqlite3ext_sys::{
+    SQLITE_TESTCTRL_PENDING_BYTE, SQLITE_TESTCTRL_PRNG_RESTORE, SQLITE_TESTCTRL_PRNG_SAVE,
+    SQLITE_TESTCTRL_PRNG_SEED,
+};
+use std::os::raw::c_int;
+
+/// Seeds SQLite's PRNG, so `random()`, `randomblob()` and
+/// [`random`](crate::random) give the same sequence on every run. A seed
+/// of 0 with a connection uses that database's schema cookie instead.
+pub fn prng_seed(seed: i32, db: Option<*mut sqlite3>) -> Result<()> {
+    let rc = unsafe {
+        sqlite3ext_test_control_int_db(
+            SQLITE_TESTCTRL_PRNG_SEED as c_int,
+            seed,
+            db.unwrap_or(std::ptr::null_mut()),
+        )
+    };
+    if rc != 0 {
+        return Err(Error::new_message(format!(
+            "could not seed the PRNG, error code {}",
+            rc
+        )));
+    }
+    Ok(())
+}
+
+/// Saves the PRNG's state, to be brought back with [`prng_restore`].
+pub fn prng_save() {
+    unsafe { sqlite3ext_test_control(SQLITE_TESTCTRL_PRNG_SAVE as c_int) };
+}
+
+/// Brings back the PRNG state of the last [`prng_save`], so the same random
+/// values come out again.
+pub fn prng_restore() {
+    unsafe { sqlite3ext_test_control(SQLITE_TESTCTRL_PRNG_RESTORE as c_int) };
+}
+
+/// Moves the pending byte, the offset of the byte range SQLite locks in
+/// database files, returning the previous offset. Moving it into the first
+/// pages of a small test database makes lock contention paths reachable.
+/// It must only be changed while no database files are open.
+pub fn set_pending_byte(offset: u32) -> u32 {
+    unsafe {
+        sqlite3ext_test_control_int(SQLITE_TESTCTRL_PENDING_BYTE as c_int, offset as c_int) as u32
+    }
+}
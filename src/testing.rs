@@ -0,0 +1,545 @@
+//! A pure-Rust stand-in for SQLite's `sqlite3_api_routines` table, for testing
+//! extension code without linking a real SQLite library.
+//!
+//! [`install`] points the crate's internal API table (see [`crate::ext`]) at
+//! Rust implementations of the value, result, and context routines. Every
+//! `sqlite3_value` and `sqlite3_context` handed to your functions is backed by
+//! Rust-owned memory, so both your function logic and the crate's unsafe glue
+//! can be run under miri or AddressSanitizer.
+//!
+//! ```rust,ignore
+//! let mut db = MockDatabase::new();
+//! sqlite3_hello_init(db.as_ptr(), std::ptr::null_mut(), std::ptr::null_mut());
+//! let context = db.call("hello", &[MockValue::Text("alex".to_owned())]).unwrap();
+//! assert_eq!(context.result(), &MockResult::Value(MockValue::Text("hello, alex!".to_owned())));
+//! ```
+//!
+//! Only the routines needed by scalar functions are implemented. Calling any
+//! other sqlite3 API (including `sqlite3_mprintf`, which can't be defined in
+//! stable Rust since it's variadic) panics, same as an extension that was never
+//! initialized. Only available without the `static` feature, where the API
+//! table isn't used.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::{
+    ext::{
+        faux_sqlite_extension_init2, sqlite3, sqlite3_api_routines, sqlite3_context, sqlite3_value,
+    },
+    scalar::{define_scalar_function, FunctionFlags},
+    Result, SQLITE_ERROR, SQLITE_OKAY,
+};
+use sqlite3ext_sys::{SQLITE_BLOB, SQLITE_FLOAT, SQLITE_INTEGER, SQLITE_NULL, SQLITE_TEXT};
+use std::{
+    cell::OnceCell,
+    ffi::CStr,
+    mem,
+    os::raw::{c_char, c_int, c_uchar, c_uint, c_void},
+    ptr, slice,
+    sync::OnceLock,
+};
+
+type Destructor = Option<unsafe extern "C" fn(*mut c_void)>;
+type FunctionCallback = unsafe extern "C" fn(*mut sqlite3_context, c_int, *mut *mut sqlite3_value);
+
+/// An owned SQL value, used both as an argument to and a result of mocked calls.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockValue {
+    Null,
+    Integer(i64),
+    Float(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+/// What a mocked function call resulted in.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockResult {
+    /// No `sqlite3_result_*` routine was called, which SQLite treats as NULL.
+    Unset,
+    /// A value was returned with one of the `sqlite3_result_*` routines.
+    Value(MockValue),
+    /// A pointer was returned with `sqlite3_result_pointer`, with the given type name.
+    Pointer(String),
+    /// An error was returned with `sqlite3_result_error` and/or
+    /// `sqlite3_result_error_code`.
+    Error { message: Option<String>, code: i32 },
+}
+
+/// The memory behind a mocked `*mut sqlite3_value`.
+struct RawValue {
+    value: MockValue,
+    /// Text representation of the value, with a trailing NUL. Computed once,
+    /// so pointers handed out by value_text/value_blob stay valid for the
+    /// lifetime of the value.
+    text: OnceCell<Vec<u8>>,
+}
+
+impl RawValue {
+    fn new(value: MockValue) -> Self {
+        RawValue {
+            value,
+            text: OnceCell::new(),
+        }
+    }
+
+    fn text(&self) -> &[u8] {
+        self.text.get_or_init(|| {
+            let mut bytes = match &self.value {
+                MockValue::Null => vec![],
+                MockValue::Integer(i) => i.to_string().into_bytes(),
+                MockValue::Float(f) => format_double(*f).into_bytes(),
+                MockValue::Text(s) => s.as_bytes().to_vec(),
+                MockValue::Blob(b) => b.clone(),
+            };
+            bytes.push(0);
+            bytes
+        })
+    }
+
+    fn bytes(&self) -> &[u8] {
+        let text = self.text();
+        &text[..text.len() - 1]
+    }
+
+    fn int64(&self) -> i64 {
+        match &self.value {
+            MockValue::Null => 0,
+            MockValue::Integer(i) => *i,
+            MockValue::Float(f) => *f as i64,
+            MockValue::Text(_) | MockValue::Blob(_) => parse_number(self.bytes()).0,
+        }
+    }
+
+    fn double(&self) -> f64 {
+        match &self.value {
+            MockValue::Null => 0.0,
+            MockValue::Integer(i) => *i as f64,
+            MockValue::Float(f) => *f,
+            MockValue::Text(_) | MockValue::Blob(_) => parse_number(self.bytes()).1,
+        }
+    }
+}
+
+/// Roughly how SQLite prints a REAL as text: always with a decimal point.
+fn format_double(f: f64) -> String {
+    if f.is_finite() && f.fract() == 0.0 && f.abs() < 1e15 {
+        format!("{:.1}", f)
+    } else {
+        f.to_string()
+    }
+}
+
+/// Parses the numeric prefix of text like SQLite's value_int64/value_double,
+/// returning 0 when there is none.
+fn parse_number(bytes: &[u8]) -> (i64, f64) {
+    let text = String::from_utf8_lossy(bytes);
+    let text = text.trim();
+    let end = text
+        .char_indices()
+        .take_while(|(i, c)| {
+            c.is_ascii_digit() || *c == '.' || ((*c == '-' || *c == '+') && *i == 0)
+        })
+        .map(|(i, c)| i + c.len_utf8())
+        .last()
+        .unwrap_or(0);
+    let prefix = &text[..end];
+    let double = prefix.parse::<f64>().unwrap_or(0.0);
+    let int = prefix.parse::<i64>().unwrap_or(double as i64);
+    (int, double)
+}
+
+/// The memory behind a mocked `*mut sqlite3_context`, returned from
+/// [`MockDatabase::call`] to inspect what the function resulted in.
+pub struct MockContext {
+    result: MockResult,
+    subtype: u32,
+    user_data: *mut c_void,
+    db: *mut sqlite3,
+    auxdata: Vec<(c_int, *mut c_void, Destructor)>,
+    pointer: Option<(*mut c_void, Destructor)>,
+}
+
+impl MockContext {
+    fn new(user_data: *mut c_void, db: *mut sqlite3) -> Self {
+        MockContext {
+            result: MockResult::Unset,
+            subtype: 0,
+            user_data,
+            db,
+            auxdata: vec![],
+            pointer: None,
+        }
+    }
+
+    /// The result the function returned.
+    pub fn result(&self) -> &MockResult {
+        &self.result
+    }
+
+    /// The subtype set with `sqlite3_result_subtype`, or 0.
+    pub fn subtype(&self) -> u32 {
+        self.subtype
+    }
+
+    fn set_result(&mut self, result: MockResult) {
+        self.drop_pointer();
+        self.subtype = 0;
+        self.result = result;
+    }
+
+    fn drop_pointer(&mut self) {
+        if let Some((pointer, Some(destructor))) = self.pointer.take() {
+            unsafe { destructor(pointer) };
+        }
+    }
+}
+
+impl Drop for MockContext {
+    fn drop(&mut self) {
+        self.drop_pointer();
+        for (_, pointer, destructor) in self.auxdata.drain(..) {
+            if let Some(destructor) = destructor {
+                unsafe { destructor(pointer) };
+            }
+        }
+    }
+}
+
+struct MockFunction {
+    name: String,
+    num_args: c_int,
+    p_app: *mut c_void,
+    x_func: Option<FunctionCallback>,
+    destroy: Destructor,
+}
+
+impl Drop for MockFunction {
+    fn drop(&mut self) {
+        if let Some(destroy) = self.destroy {
+            unsafe { destroy(self.p_app) };
+        }
+    }
+}
+
+/// A fake database connection that records the functions registered on it,
+/// so they can be called with [`MockDatabase::call`].
+pub struct MockDatabase {
+    functions: Vec<MockFunction>,
+}
+
+impl MockDatabase {
+    /// Creates a new mock connection, calling [`install`] if needed. Boxed so
+    /// the pointer returned by [`MockDatabase::as_ptr`] stays stable.
+    pub fn new() -> Box<MockDatabase> {
+        install();
+        Box::new(MockDatabase { functions: vec![] })
+    }
+
+    /// The `*mut sqlite3` handle to pass to entrypoints and `define_*` functions.
+    pub fn as_ptr(&mut self) -> *mut sqlite3 {
+        (self as *mut MockDatabase).cast::<sqlite3>()
+    }
+
+    /// Calls the scalar function registered under `name` with the given
+    /// arguments. Returns None if no function with that name and number of
+    /// arguments was registered.
+    pub fn call(&mut self, name: &str, args: &[MockValue]) -> Option<MockContext> {
+        let db = self.as_ptr();
+        let num_args = args.len() as c_int;
+        let function = self
+            .functions
+            .iter()
+            .filter(|f| f.name.eq_ignore_ascii_case(name))
+            .filter(|f| f.num_args == num_args || f.num_args == -1)
+            .min_by_key(|f| f.num_args == -1)?;
+        let x_func = function.x_func?;
+        let p_app = function.p_app;
+
+        let mut values: Vec<RawValue> = args.iter().cloned().map(RawValue::new).collect();
+        let base = values.as_mut_ptr();
+        let mut pointers: Vec<*mut sqlite3_value> = (0..values.len())
+            .map(|i| unsafe { base.add(i) }.cast::<sqlite3_value>())
+            .collect();
+        let mut context = MockContext::new(p_app, db);
+        unsafe {
+            x_func(
+                ptr::addr_of_mut!(context).cast::<sqlite3_context>(),
+                num_args,
+                pointers.as_mut_ptr(),
+            )
+        };
+        Some(context)
+    }
+}
+
+/// Calls the given scalar function once with the given arguments, through the
+/// same glue as [`define_scalar_function`].
+pub fn call_scalar<F>(x_func: F, args: &[MockValue]) -> MockContext
+where
+    F: Fn(*mut sqlite3_context, &[*mut sqlite3_value]) -> Result<()>,
+{
+    let mut db = MockDatabase::new();
+    define_scalar_function(
+        db.as_ptr(),
+        "mock",
+        -1,
+        x_func,
+        FunctionFlags::UTF8 | FunctionFlags::RESULT_SUBTYPE,
+    )
+    .expect("registering on a mock database should never fail");
+    db.call("mock", args)
+        .expect("function was just registered on the mock database")
+}
+
+/// Points the crate's API table at the mocked routines. Safe to call multiple times.
+pub fn install() {
+    static API: OnceLock<sqlite3_api_routines> = OnceLock::new();
+    let api = API.get_or_init(routines);
+    unsafe {
+        faux_sqlite_extension_init2((api as *const sqlite3_api_routines).cast_mut());
+    }
+}
+
+fn routines() -> sqlite3_api_routines {
+    // all routines default to None, which panics when called
+    let mut api: sqlite3_api_routines = unsafe { mem::zeroed() };
+    api.value_type = Some(value_type);
+    api.value_text = Some(value_text);
+    api.value_bytes = Some(value_bytes);
+    api.value_blob = Some(value_blob);
+    api.value_int = Some(value_int);
+    api.value_int64 = Some(value_int64);
+    api.value_double = Some(value_double);
+    api.value_subtype = Some(value_subtype);
+    api.value_pointer = Some(value_pointer);
+    api.result_int = Some(result_int);
+    api.result_int64 = Some(result_int64);
+    api.result_double = Some(result_double);
+    api.result_null = Some(result_null);
+    api.result_text = Some(result_text);
+    api.result_blob = Some(result_blob);
+    api.result_error = Some(result_error);
+    api.result_error_code = Some(result_error_code);
+    api.result_subtype = Some(result_subtype);
+    api.result_pointer = Some(result_pointer);
+    api.user_data = Some(user_data);
+    api.context_db_handle = Some(context_db_handle);
+    api.get_auxdata = Some(get_auxdata);
+    api.set_auxdata = Some(set_auxdata);
+    api.create_function_v2 = Some(create_function_v2);
+    api
+}
+
+unsafe fn raw_value<'a>(value: *mut sqlite3_value) -> &'a RawValue {
+    &*value.cast::<RawValue>()
+}
+
+unsafe fn mock_context<'a>(context: *mut sqlite3_context) -> &'a mut MockContext {
+    &mut *context.cast::<MockContext>()
+}
+
+/// Copies `n` bytes (or up to the NUL terminator if `n` is negative) that
+/// SQLite would have taken ownership of, then releases them with `destructor`.
+unsafe fn take_bytes(p: *const c_void, n: c_int, destructor: Destructor) -> Vec<u8> {
+    let bytes = if p.is_null() {
+        vec![]
+    } else if n < 0 {
+        CStr::from_ptr(p.cast::<c_char>()).to_bytes().to_vec()
+    } else {
+        slice::from_raw_parts(p.cast::<u8>(), n as usize).to_vec()
+    };
+    // SQLITE_TRANSIENT is -1 and SQLITE_STATIC is NULL, neither are called.
+    if let Some(destructor) = destructor {
+        if destructor as usize != usize::MAX {
+            destructor(p.cast_mut());
+        }
+    }
+    bytes
+}
+
+unsafe extern "C" fn value_type(value: *mut sqlite3_value) -> c_int {
+    let raw_type = match raw_value(value).value {
+        MockValue::Null => SQLITE_NULL,
+        MockValue::Integer(_) => SQLITE_INTEGER,
+        MockValue::Float(_) => SQLITE_FLOAT,
+        MockValue::Text(_) => SQLITE_TEXT,
+        MockValue::Blob(_) => SQLITE_BLOB,
+    };
+    raw_type as c_int
+}
+
+unsafe extern "C" fn value_text(value: *mut sqlite3_value) -> *const c_uchar {
+    let value = raw_value(value);
+    if value.value == MockValue::Null {
+        return ptr::null();
+    }
+    value.text().as_ptr()
+}
+
+unsafe extern "C" fn value_bytes(value: *mut sqlite3_value) -> c_int {
+    raw_value(value).bytes().len() as c_int
+}
+
+unsafe extern "C" fn value_blob(value: *mut sqlite3_value) -> *const c_void {
+    let value = raw_value(value);
+    if value.bytes().is_empty() {
+        return ptr::null();
+    }
+    value.bytes().as_ptr().cast::<c_void>()
+}
+
+unsafe extern "C" fn value_int(value: *mut sqlite3_value) -> c_int {
+    raw_value(value).int64() as c_int
+}
+
+unsafe extern "C" fn value_int64(value: *mut sqlite3_value) -> i64 {
+    raw_value(value).int64()
+}
+
+unsafe extern "C" fn value_double(value: *mut sqlite3_value) -> f64 {
+    raw_value(value).double()
+}
+
+unsafe extern "C" fn value_subtype(_value: *mut sqlite3_value) -> c_uint {
+    // arguments can't carry subtypes in the mock
+    0
+}
+
+unsafe extern "C" fn value_pointer(
+    _value: *mut sqlite3_value,
+    _name: *const c_char,
+) -> *mut c_void {
+    // mock arguments are never pointers
+    ptr::null_mut()
+}
+
+unsafe extern "C" fn result_int(context: *mut sqlite3_context, v: c_int) {
+    mock_context(context).set_result(MockResult::Value(MockValue::Integer(v.into())));
+}
+
+unsafe extern "C" fn result_int64(context: *mut sqlite3_context, v: i64) {
+    mock_context(context).set_result(MockResult::Value(MockValue::Integer(v)));
+}
+
+unsafe extern "C" fn result_double(context: *mut sqlite3_context, v: f64) {
+    mock_context(context).set_result(MockResult::Value(MockValue::Float(v)));
+}
+
+unsafe extern "C" fn result_null(context: *mut sqlite3_context) {
+    mock_context(context).set_result(MockResult::Value(MockValue::Null));
+}
+
+unsafe extern "C" fn result_text(
+    context: *mut sqlite3_context,
+    s: *const c_char,
+    n: c_int,
+    destructor: Destructor,
+) {
+    let bytes = take_bytes(s.cast::<c_void>(), n, destructor);
+    let text = String::from_utf8_lossy(&bytes).into_owned();
+    mock_context(context).set_result(MockResult::Value(MockValue::Text(text)));
+}
+
+unsafe extern "C" fn result_blob(
+    context: *mut sqlite3_context,
+    p: *const c_void,
+    n: c_int,
+    destructor: Destructor,
+) {
+    let bytes = take_bytes(p, n, destructor);
+    mock_context(context).set_result(MockResult::Value(MockValue::Blob(bytes)));
+}
+
+unsafe extern "C" fn result_error(context: *mut sqlite3_context, s: *const c_char, n: c_int) {
+    let bytes = take_bytes(s.cast::<c_void>(), n, None);
+    mock_context(context).set_result(MockResult::Error {
+        message: Some(String::from_utf8_lossy(&bytes).into_owned()),
+        code: SQLITE_ERROR,
+    });
+}
+
+unsafe extern "C" fn result_error_code(context: *mut sqlite3_context, code: c_int) {
+    let context = mock_context(context);
+    let message = match &context.result {
+        MockResult::Error { message, .. } => message.clone(),
+        _ => None,
+    };
+    context.set_result(MockResult::Error { message, code });
+}
+
+unsafe extern "C" fn result_subtype(context: *mut sqlite3_context, subtype: c_uint) {
+    // "Only the lower 8 bits of the subtype T are preserved"
+    mock_context(context).subtype = subtype & 0xff;
+}
+
+unsafe extern "C" fn result_pointer(
+    context: *mut sqlite3_context,
+    pointer: *mut c_void,
+    name: *const c_char,
+    destructor: Destructor,
+) {
+    let name = CStr::from_ptr(name).to_string_lossy().into_owned();
+    let context = mock_context(context);
+    context.set_result(MockResult::Pointer(name));
+    context.pointer = Some((pointer, destructor));
+}
+
+unsafe extern "C" fn user_data(context: *mut sqlite3_context) -> *mut c_void {
+    mock_context(context).user_data
+}
+
+unsafe extern "C" fn context_db_handle(context: *mut sqlite3_context) -> *mut sqlite3 {
+    mock_context(context).db
+}
+
+unsafe extern "C" fn get_auxdata(context: *mut sqlite3_context, n: c_int) -> *mut c_void {
+    mock_context(context)
+        .auxdata
+        .iter()
+        .find(|(col, _, _)| *col == n)
+        .map_or(ptr::null_mut(), |(_, pointer, _)| *pointer)
+}
+
+unsafe extern "C" fn set_auxdata(
+    context: *mut sqlite3_context,
+    n: c_int,
+    pointer: *mut c_void,
+    destructor: Destructor,
+) {
+    let context = mock_context(context);
+    if let Some(idx) = context.auxdata.iter().position(|(col, _, _)| *col == n) {
+        let (_, old, old_destructor) = context.auxdata.remove(idx);
+        if let Some(old_destructor) = old_destructor {
+            old_destructor(old);
+        }
+    }
+    context.auxdata.push((n, pointer, destructor));
+}
+
+unsafe extern "C" fn create_function_v2(
+    db: *mut sqlite3,
+    name: *const c_char,
+    num_args: c_int,
+    _text_rep: c_int,
+    p_app: *mut c_void,
+    x_func: Option<FunctionCallback>,
+    _x_step: Option<FunctionCallback>,
+    _x_final: Option<unsafe extern "C" fn(*mut sqlite3_context)>,
+    destroy: Destructor,
+) -> c_int {
+    let db = &mut *db.cast::<MockDatabase>();
+    let name = CStr::from_ptr(name).to_string_lossy().into_owned();
+    // same name and number of arguments overwrites (and destroys) the old one
+    db.functions
+        .retain(|f| !(f.name.eq_ignore_ascii_case(&name) && f.num_args == num_args));
+    if x_func.is_some() {
+        db.functions.push(MockFunction {
+            name,
+            num_args,
+            p_app,
+            x_func,
+            destroy,
+        });
+    }
+    SQLITE_OKAY
+}
@@ -0,0 +1,202 @@
+//! Timeouts for virtual table scans, so a query over a slow backend, like a
+//! remote API, fails instead of hanging.
+//!
+//! SQLite can't interrupt a cursor in the middle of xFilter or xNext, so
+//! the timeout is cooperative: [`Timed`] wraps a cursor, starts the clock
+//! when a scan is filtered, and fails the scan with SQLITE_ABORT at the
+//! first filter or next that returns after the timeout. A cursor that makes
+//! slow calls of its own can check a [`ScanDeadline`] between them too.
+//! Time is read from the VFS's clock, like [`crate::clock`], so the
+//! timeout follows the same clock as `date('now')`.
+//!
+//! Tables take their timeout from a `timeout` option in milliseconds,
+//! which [`ScanTimeout::take_from`] reads from the table's arguments:
+//!
+//! ```sql
+//! create virtual table feed using remote_feed(url='https://...', timeout=2000);
+//! ```
+//!
+//! ```ignore
+//! fn connect(db: *mut sqlite3, _aux: Option<&()>, mut args: VTabArguments) -> Result<(String, FeedTable)> {
+//!     let timeout = ScanTimeout::take_from(&mut args.arguments)?;
+//!     // ... the rest of args.arguments are the table's own
+//! }
+//!
+//! fn open(&mut self) -> Result<Timed<FeedCursor>> {
+//!     Ok(Timed::new(FeedCursor::new(), self.db, self.timeout))
+//! }
+//! ```
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::clock::vfs_current_time_millis;
+use crate::errors::{Error, Result};
+use crate::ext::{sqlite3, sqlite3_context, sqlite3_value};
+use crate::table::VTabCursor;
+use crate::vtab_argparse::{parse_argument, Argument, ConfigOptionValue};
+use std::os::raw::c_int;
+use std::time::Duration;
+
+/// How long a scan may take, or no limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScanTimeout(Option<Duration>);
+
+impl ScanTimeout {
+    pub fn new(timeout: Duration) -> ScanTimeout {
+        ScanTimeout(Some(timeout))
+    }
+
+    /// No limit.
+    pub fn none() -> ScanTimeout {
+        ScanTimeout(None)
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        self.0
+    }
+
+    /// Removes the `timeout=<milliseconds>` option from a table's
+    /// arguments and returns it, or no limit if there isn't one. A timeout
+    /// of 0 is no limit too.
+    pub fn take_from(arguments: &mut Vec<String>) -> Result<ScanTimeout> {
+        let mut timeout = ScanTimeout::none();
+        let mut error = None;
+        arguments.retain(|argument| match parse_argument(argument) {
+            Ok(Argument::Config(option)) if option.key.trim() == "timeout" => {
+                let value = match &option.value {
+                    ConfigOptionValue::Bareword(value) | ConfigOptionValue::Quoted(value) => {
+                        value.as_str()
+                    }
+                    ConfigOptionValue::SqliteParameter(value) => value.as_str(),
+                };
+                match value.parse::<u64>() {
+                    Ok(0) => timeout = ScanTimeout::none(),
+                    Ok(ms) => timeout = ScanTimeout::new(Duration::from_millis(ms)),
+                    Err(_) => {
+                        error = Some(Error::new_message(format!(
+                            "timeout must be a whole number of milliseconds, not {}",
+                            value
+                        )))
+                    }
+                }
+                false
+            }
+            _ => true,
+        });
+        match error {
+            Some(error) => Err(error),
+            None => Ok(timeout),
+        }
+    }
+}
+
+/// When the current scan has to be done by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanDeadline {
+    db: *mut sqlite3,
+    timeout: Duration,
+    /// In milliseconds since the Unix epoch, by the VFS's clock.
+    deadline: i64,
+}
+
+impl ScanDeadline {
+    /// The deadline for a scan starting now on `db`, or None if `timeout`
+    /// is no limit.
+    pub fn start(db: *mut sqlite3, timeout: ScanTimeout) -> Result<Option<ScanDeadline>> {
+        let timeout = match timeout.duration() {
+            Some(timeout) => timeout,
+            None => return Ok(None),
+        };
+        let millis = i64::try_from(timeout.as_millis()).unwrap_or(i64::MAX);
+        Ok(Some(ScanDeadline {
+            db,
+            timeout,
+            deadline: vfs_current_time_millis(db)?.saturating_add(millis),
+        }))
+    }
+
+    /// Fails with an [`Error::abort`] once the deadline has passed.
+    pub fn check(&self) -> Result<()> {
+        if vfs_current_time_millis(self.db)? >= self.deadline {
+            return Err(Error::abort(format!(
+                "virtual table scan timed out after {} ms",
+                self.timeout.as_millis()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// A cursor whose scans fail once they take longer than a [`ScanTimeout`].
+/// Must be the table's `Cursor`, with the wrapped cursor's
+/// `sqlite3_vtab_cursor` first in it, as usual.
+#[repr(C)]
+pub struct Timed<C> {
+    /// first, so its base is this cursor's too
+    cursor: C,
+    db: *mut sqlite3,
+    timeout: ScanTimeout,
+    deadline: Option<ScanDeadline>,
+}
+
+impl<C> Timed<C> {
+    /// Wraps `cursor`, reading the time from `db`'s VFS.
+    pub fn new(cursor: C, db: *mut sqlite3, timeout: ScanTimeout) -> Timed<C> {
+        Timed {
+            cursor,
+            db,
+            timeout,
+            deadline: None,
+        }
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.cursor
+    }
+
+    pub fn inner_mut(&mut self) -> &mut C {
+        &mut self.cursor
+    }
+
+    /// The deadline of the current scan, to check during slow work in the
+    /// wrapped cursor, or None without a timeout.
+    pub fn deadline(&self) -> Option<ScanDeadline> {
+        self.deadline
+    }
+
+    fn check(&self) -> Result<()> {
+        match &self.deadline {
+            Some(deadline) => deadline.check(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<C: VTabCursor> VTabCursor for Timed<C> {
+    fn filter(
+        &mut self,
+        idx_num: c_int,
+        idx_str: Option<&str>,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.deadline = ScanDeadline::start(self.db, self.timeout)?;
+        self.cursor.filter(idx_num, idx_str, values)?;
+        self.check()
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.cursor.next()?;
+        self.check()
+    }
+
+    fn eof(&self) -> bool {
+        self.cursor.eof()
+    }
+
+    fn column(&self, ctx: *mut sqlite3_context, i: c_int) -> Result<()> {
+        self.cursor.column(ctx, i)
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        self.cursor.rowid()
+    }
+}
@@ -0,0 +1,144 @@
+//! Observe statements as a connection runs them, with
+//! [`sqlite3_trace_v2`](https://www.sqlite.org/c3ref/trace_v2.html).
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::errors::{Error, Result};
+use crate::ext::{sqlite3, sqlite3_stmt, sqlite3ext_sql, sqlite3ext_trace_v2};
+use crate::hooks::on_connection_close;
+use bitflags::bitflags;
+use sqlite3ext_sys::{
+    SQLITE_TRACE_CLOSE, SQLITE_TRACE_PROFILE, SQLITE_TRACE_ROW, SQLITE_TRACE_STMT,
+};
+use std::{
+    ffi::CStr,
+    os::raw::{c_char, c_int, c_uint, c_void},
+    panic::{catch_unwind, AssertUnwindSafe},
+    time::Duration,
+};
+
+bitflags! {
+    /// The events a trace callback is called for, the `uMask` of
+    /// sqlite3_trace_v2.
+    pub struct TraceEvents: u32 {
+        const STMT = SQLITE_TRACE_STMT;
+        const PROFILE = SQLITE_TRACE_PROFILE;
+        const ROW = SQLITE_TRACE_ROW;
+        const CLOSE = SQLITE_TRACE_CLOSE;
+    }
+}
+
+/// A single trace event, see
+/// <https://www.sqlite.org/c3ref/c_trace.html>.
+#[derive(Debug)]
+pub enum TraceEvent<'a> {
+    /// A statement started running. `sql` is its unexpanded SQL, or a
+    /// comment like `-- TRIGGER name` when a trigger starts.
+    Stmt {
+        stmt: *mut sqlite3_stmt,
+        sql: &'a str,
+    },
+    /// A statement finished, after running for `elapsed`.
+    Profile {
+        stmt: *mut sqlite3_stmt,
+        elapsed: Duration,
+    },
+    /// A statement returned a row.
+    Row { stmt: *mut sqlite3_stmt },
+    /// The connection is closing.
+    Close { db: *mut sqlite3 },
+}
+
+/// The SQL text a statement was prepared with.
+pub fn statement_sql<'a>(stmt: *mut sqlite3_stmt) -> Option<&'a str> {
+    let sql = unsafe { sqlite3ext_sql(stmt) };
+    if sql.is_null() {
+        return None;
+    }
+    unsafe { CStr::from_ptr(sql) }.to_str().ok()
+}
+
+struct Tracer<F> {
+    f: F,
+}
+
+unsafe extern "C" fn x_trace<F>(
+    event: c_uint,
+    context: *mut c_void,
+    p: *mut c_void,
+    x: *mut c_void,
+) -> c_int
+where
+    F: FnMut(TraceEvent),
+{
+    let tracer = &mut *context.cast::<Tracer<F>>();
+    let stmt = p.cast::<sqlite3_stmt>();
+    let event = match event {
+        SQLITE_TRACE_STMT => TraceEvent::Stmt {
+            stmt,
+            sql: if x.is_null() {
+                ""
+            } else {
+                CStr::from_ptr(x.cast::<c_char>()).to_str().unwrap_or("")
+            },
+        },
+        SQLITE_TRACE_PROFILE => TraceEvent::Profile {
+            stmt,
+            elapsed: Duration::from_nanos((*x.cast::<i64>()).max(0) as u64),
+        },
+        SQLITE_TRACE_ROW => TraceEvent::Row { stmt },
+        SQLITE_TRACE_CLOSE => TraceEvent::Close {
+            db: p.cast::<sqlite3>(),
+        },
+        _ => return 0,
+    };
+    // a panic can't unwind into SQLite
+    let _ = catch_unwind(AssertUnwindSafe(|| (tracer.f)(event)));
+    // the return value is reserved, and must be 0
+    0
+}
+
+/// Calls `f` for each of the given events on the connection, replacing any
+/// earlier trace callback.
+///
+/// `f` is kept until the connection closes, even if it's replaced or
+/// removed with [`clear_trace`] before then.
+pub fn trace<F>(db: *mut sqlite3, events: TraceEvents, f: F) -> Result<()>
+where
+    F: FnMut(TraceEvent) + 'static,
+{
+    let tracer = Box::into_raw(Box::new(Tracer { f }));
+    let free = move || drop(unsafe { Box::from_raw(tracer) });
+    // the close hook is registered first so a failure doesn't leave SQLite
+    // with a pointer to the freed tracer
+    if let Err(err) = on_connection_close(db, free) {
+        drop(unsafe { Box::from_raw(tracer) });
+        return Err(err);
+    }
+    let rc = unsafe {
+        sqlite3ext_trace_v2(
+            db,
+            events.bits(),
+            Some(x_trace::<F>),
+            tracer.cast::<c_void>(),
+        )
+    };
+    if rc != 0 {
+        return Err(Error::new_message(format!(
+            "could not register trace callback, error code {}",
+            rc
+        )));
+    }
+    Ok(())
+}
+
+/// Removes the connection's trace callback.
+pub fn clear_trace(db: *mut sqlite3) -> Result<()> {
+    let rc = unsafe { sqlite3ext_trace_v2(db, 0, None, std::ptr::null_mut()) };
+    if rc != 0 {
+        return Err(Error::new_message(format!(
+            "could not remove trace callback, error code {}",
+            rc
+        )));
+    }
+    Ok(())
+}
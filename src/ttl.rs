@@ -0,0 +1,207 @@
+//! Expiring rows in the shadow tables of cache-like virtual tables.
+//!
+//! By convention a row expires when the time in its `expires_at` column,
+//! milliseconds since the Unix epoch, has passed, and never expires when
+//! it's NULL. A [`TtlSweeper`] for a shadow table handles the rest:
+//!
+//! ```ignore
+//! let sweeper = TtlSweeper::new(&args.database_name, &format!("{}_data", args.table_name))
+//!     .interval(Duration::from_secs(60));
+//!
+//! // writing a row
+//! let expires_at = sweeper.expires_at(db, Duration::from_secs(300))?;
+//!
+//! // scanning: skip expired rows, then delete them
+//! let now = sweeper.now(db)?;
+//! if !sweeper.check_row(rowid, expires_at, now) { continue; }
+//! sweeper.flush(db)?;
+//! ```
+//!
+//! Scans hide expired rows right away and delete them lazily, with
+//! [`TtlSweeper::flush`] once the scan is done. Rows no scan reads are
+//! deleted by [`TtlSweeper::sweep`], which [`TtlSweeper::maybe_sweep`] runs
+//! at most once per interval, so calling it from every xFilter or xUpdate
+//! cleans up periodically without a background thread.
+//! [`define_sweep_function`] adds `name_sweep()` to sweep from SQL.
+//!
+//! Times come from [`crate::clock`], the clock SQLite uses for `'now'`.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api;
+use crate::clock::vfs_current_time_millis;
+use crate::errors::{Error, Result};
+use crate::ext::{sqlite3, sqlite3ext_changes};
+use crate::scalar::{define_scalar_function, FunctionFlags};
+use crate::schema::quote_identifier;
+use crate::statement::Statement;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The column rows expire by, unless [`TtlSweeper::column`] says otherwise.
+pub const EXPIRES_COLUMN: &str = "expires_at";
+
+#[derive(Debug, Default)]
+struct SweepState {
+    /// Expired rows scans skipped, to delete.
+    expired: Vec<i64>,
+    /// When the last sweep ran, in milliseconds since the Unix epoch.
+    last_sweep: Option<i64>,
+}
+
+/// Deletes the expired rows of one shadow table.
+#[derive(Debug)]
+pub struct TtlSweeper {
+    schema: String,
+    table: String,
+    column: String,
+    interval: Option<Duration>,
+    state: Mutex<SweepState>,
+}
+
+impl TtlSweeper {
+    /// A sweeper for `schema.table`, by its [`EXPIRES_COLUMN`].
+    pub fn new(schema: &str, table: &str) -> Self {
+        TtlSweeper {
+            schema: schema.to_owned(),
+            table: table.to_owned(),
+            column: EXPIRES_COLUMN.to_owned(),
+            interval: None,
+            state: Mutex::new(SweepState::default()),
+        }
+    }
+
+    /// Expires rows by `column` instead.
+    pub fn column(mut self, column: &str) -> Self {
+        self.column = column.to_owned();
+        self
+    }
+
+    /// How often [`TtlSweeper::maybe_sweep`] sweeps. Without one, it never
+    /// does.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    fn state(&self) -> Result<std::sync::MutexGuard<'_, SweepState>> {
+        self.state
+            .lock()
+            .map_err(|_| Error::new_message("TTL sweeper state was poisoned"))
+    }
+
+    fn qualified_table(&self) -> String {
+        format!(
+            "{}.{}",
+            quote_identifier(&self.schema),
+            quote_identifier(&self.table)
+        )
+    }
+
+    /// The current time in milliseconds since the Unix epoch.
+    pub fn now(&self, db: *mut sqlite3) -> Result<i64> {
+        vfs_current_time_millis(db)
+    }
+
+    /// The expiry time of a row that lives for `ttl` from now.
+    pub fn expires_at(&self, db: *mut sqlite3, ttl: Duration) -> Result<i64> {
+        Ok(self
+            .now(db)?
+            .saturating_add(i64::try_from(ttl.as_millis()).unwrap_or(i64::MAX)))
+    }
+
+    /// An SQL condition that's true for rows that haven't expired at the
+    /// time bound to parameter `param`, for scans to skip expired rows in
+    /// their own query.
+    pub fn live_condition(&self, param: usize) -> String {
+        let column = quote_identifier(&self.column);
+        format!("({} IS NULL OR {} > ?{})", column, column, param)
+    }
+
+    /// Whether the row `rowid`, expiring at `expires_at`, is still live at
+    /// `now`. Expired rows are queued for [`TtlSweeper::flush`] to delete.
+    pub fn check_row(&self, rowid: i64, expires_at: Option<i64>, now: i64) -> bool {
+        match expires_at {
+            Some(expires_at) if expires_at <= now => {
+                if let Ok(mut state) = self.state.lock() {
+                    state.expired.push(rowid);
+                }
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// Deletes the expired rows scans found, returning how many.
+    pub fn flush(&self, db: *mut sqlite3) -> Result<usize> {
+        let expired = std::mem::take(&mut self.state()?.expired);
+        if expired.is_empty() {
+            return Ok(0);
+        }
+        let rowids = serde_json::to_string(&expired)
+            .map_err(|err| Error::new_message(format!("could not list expired rows: {}", err)))?;
+        let mut stmt = Statement::prepare(
+            db,
+            &format!(
+                // checked again, in case a row was written since the scan
+                "DELETE FROM {} WHERE rowid IN (SELECT value FROM json_each(?1)) AND {} <= ?2",
+                self.qualified_table(),
+                quote_identifier(&self.column)
+            ),
+        )?;
+        stmt.bind_text(1, &rowids)?;
+        stmt.bind_int64(2, self.now(db)?)?;
+        stmt.step()?;
+        Ok(unsafe { sqlite3ext_changes(db) } as usize)
+    }
+
+    /// Deletes every expired row, returning how many.
+    pub fn sweep(&self, db: *mut sqlite3) -> Result<usize> {
+        let now = self.now(db)?;
+        let mut stmt = Statement::prepare(
+            db,
+            &format!(
+                "DELETE FROM {} WHERE {} <= ?1",
+                self.qualified_table(),
+                quote_identifier(&self.column)
+            ),
+        )?;
+        stmt.bind_int64(1, now)?;
+        stmt.step()?;
+        let deleted = unsafe { sqlite3ext_changes(db) } as usize;
+        let mut state = self.state()?;
+        state.expired.clear();
+        state.last_sweep = Some(now);
+        Ok(deleted)
+    }
+
+    /// Sweeps if the interval has passed since the last sweep, or there
+    /// hasn't been one, returning how many rows were deleted.
+    pub fn maybe_sweep(&self, db: *mut sqlite3) -> Result<usize> {
+        let interval = match self.interval {
+            Some(interval) => i64::try_from(interval.as_millis()).unwrap_or(i64::MAX),
+            None => return Ok(0),
+        };
+        let last_sweep = self.state()?.last_sweep;
+        match last_sweep {
+            Some(last_sweep) if self.now(db)?.saturating_sub(last_sweep) < interval => Ok(0),
+            _ => self.sweep(db),
+        }
+    }
+}
+
+/// Defines `name_sweep()`, which deletes the expired rows of `sweeper`'s
+/// table and returns how many. It writes, so it's DIRECTONLY: a trigger or
+/// view can't call it.
+pub fn define_sweep_function(db: *mut sqlite3, name: &str, sweeper: TtlSweeper) -> Result<()> {
+    define_scalar_function(
+        db,
+        &format!("{}_sweep", name),
+        0,
+        move |context, _values| {
+            let deleted = sweeper.sweep(api::context_db_handle(context))?;
+            api::result_int64(context, deleted as i64);
+            Ok(())
+        },
+        FunctionFlags::UTF8 | FunctionFlags::DIRECTONLY,
+    )
+}
@@ -0,0 +1,176 @@
+//! Unicode-aware string functions, counting and slicing by grapheme
+//! cluster (what a reader sees as one character) instead of by code point,
+//! plus full case mapping and normalization.
+//!
+//! The `unicode_*` functions take SQL values and set results directly, so
+//! they can be registered under any name with
+//! [`define_scalar_function`](crate::define_scalar_function), or all at once
+//! with [`define_unicode_functions`]. NULL arguments give NULL.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api;
+use crate::errors::Result;
+use crate::ext::{sqlite3, sqlite3_context, sqlite3_value};
+use crate::scalar::{define_scalar_function, FunctionFlags};
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The number of grapheme clusters in `s`, so "é" is 1 whether or not it's
+/// written with a combining accent.
+pub fn grapheme_len(s: &str) -> usize {
+    s.graphemes(true).count()
+}
+
+/// Grapheme clusters of `s`, selected like SQLite's `substr(s, start, length)`:
+/// `start` is 1-based, negative counts from the end, and a negative `length`
+/// takes the clusters before `start`. Without a `length`, it takes the rest.
+pub fn grapheme_substr(s: &str, start: i64, length: Option<i64>) -> &str {
+    let boundaries: Vec<usize> = s
+        .grapheme_indices(true)
+        .map(|(i, _)| i)
+        .chain(std::iter::once(s.len()))
+        .collect();
+    let len = (boundaries.len() - 1) as i64;
+    // the same arithmetic as SQLite's substrFunc, saturating where SQLite's
+    // 64-bit math would overflow
+    let (mut p1, mut p2) = (start, length.unwrap_or(len));
+    let negative_length = p2 < 0;
+    if negative_length {
+        p2 = p2.saturating_neg();
+    }
+    if p1 < 0 {
+        p1 = p1.saturating_add(len);
+        if p1 < 0 {
+            p2 = p2.saturating_add(p1).max(0);
+            p1 = 0;
+        }
+    } else if p1 > 0 {
+        p1 -= 1;
+    } else if p2 > 0 {
+        p2 -= 1;
+    }
+    if negative_length {
+        p1 = p1.saturating_sub(p2);
+        if p1 < 0 {
+            p2 = p2.saturating_add(p1);
+            p1 = 0;
+        }
+    }
+    let begin = p1.clamp(0, len);
+    let end = match length {
+        Some(_) => p1.saturating_add(p2.max(0)).clamp(begin, len),
+        None => len,
+    };
+    &s[boundaries[begin as usize]..boundaries[end as usize]]
+}
+
+/// The text argument at `i`, or None if it's NULL or missing.
+fn text_arg<'a>(values: &[*mut sqlite3_value], i: usize) -> Result<Option<&'a str>> {
+    match values.get(i) {
+        Some(value) if !api::value_is_null(value) => Ok(Some(api::value_text(value)?)),
+        _ => Ok(None),
+    }
+}
+
+/// `unicode_length(text)`, the number of grapheme clusters.
+pub fn unicode_length(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    match text_arg(values, 0)? {
+        Some(text) => api::result_int64(context, grapheme_len(text) as i64),
+        None => api::result_null(context),
+    }
+    Ok(())
+}
+
+/// `unicode_substr(text, start [, length])`, like `substr` but counting
+/// grapheme clusters.
+pub fn unicode_substr(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let (text, start) = match (text_arg(values, 0)?, values.get(1)) {
+        (Some(text), Some(start)) if !api::value_is_null(start) => (text, api::value_int64(start)),
+        _ => {
+            api::result_null(context);
+            return Ok(());
+        }
+    };
+    let length = match values.get(2) {
+        Some(length) if api::value_is_null(length) => {
+            api::result_null(context);
+            return Ok(());
+        }
+        Some(length) => Some(api::value_int64(length)),
+        None => None,
+    };
+    api::result_text(context, grapheme_substr(text, start, length))
+}
+
+fn result_mapped<F>(
+    context: *mut sqlite3_context,
+    values: &[*mut sqlite3_value],
+    f: F,
+) -> Result<()>
+where
+    F: Fn(&str) -> String,
+{
+    match text_arg(values, 0)? {
+        Some(text) => api::result_text(context, f(text)),
+        None => {
+            api::result_null(context);
+            Ok(())
+        }
+    }
+}
+
+/// `unicode_upper(text)`, with full Unicode case mapping, so "straße"
+/// becomes "STRASSE".
+pub fn unicode_upper(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    result_mapped(context, values, str::to_uppercase)
+}
+
+/// `unicode_lower(text)`, with full Unicode case mapping.
+pub fn unicode_lower(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    result_mapped(context, values, str::to_lowercase)
+}
+
+/// `unicode_nfc(text)`, the text in Normalization Form C (composed).
+pub fn unicode_nfc(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    result_mapped(context, values, |text| text.nfc().collect())
+}
+
+/// `unicode_nfd(text)`, the text in Normalization Form D (decomposed).
+pub fn unicode_nfd(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    result_mapped(context, values, |text| text.nfd().collect())
+}
+
+/// Defines `unicode_length`, `unicode_substr`, `unicode_upper`,
+/// `unicode_lower`, `unicode_nfc` and `unicode_nfd`.
+pub fn define_unicode_functions(db: *mut sqlite3) -> Result<()> {
+    let flags = FunctionFlags::UTF8 | FunctionFlags::DETERMINISTIC;
+    define_scalar_function(db, "unicode_length", 1, unicode_length, flags)?;
+    define_scalar_function(db, "unicode_substr", 2, unicode_substr, flags)?;
+    define_scalar_function(db, "unicode_substr", 3, unicode_substr, flags)?;
+    define_scalar_function(db, "unicode_upper", 1, unicode_upper, flags)?;
+    define_scalar_function(db, "unicode_lower", 1, unicode_lower, flags)?;
+    define_scalar_function(db, "unicode_nfc", 1, unicode_nfc, flags)?;
+    define_scalar_function(db, "unicode_nfd", 1, unicode_nfd, flags)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::unicode::*;
+
+    #[test]
+    fn test_grapheme_substr() {
+        // "e" followed by a combining acute accent is one grapheme
+        let s = "ae\u{301}i🇫🇷o";
+        assert_eq!(grapheme_len(s), 5);
+        assert_eq!(grapheme_substr(s, 2, Some(1)), "e\u{301}");
+        assert_eq!(grapheme_substr(s, 2, None), "e\u{301}i🇫🇷o");
+        assert_eq!(grapheme_substr(s, -2, Some(1)), "🇫🇷");
+        assert_eq!(grapheme_substr(s, 0, Some(2)), "a");
+        assert_eq!(grapheme_substr(s, 3, Some(-2)), "ae\u{301}");
+        assert_eq!(grapheme_substr(s, 4, Some(-10)), "ae\u{301}i");
+        assert_eq!(grapheme_substr(s, -10, Some(7)), "ae\u{301}");
+        assert_eq!(grapheme_substr(s, 10, Some(2)), "");
+        assert_eq!(grapheme_substr("", 1, Some(2)), "");
+    }
+}
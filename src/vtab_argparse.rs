@@ -132,6 +132,28 @@ pub enum ConfigOptionValue {
     Bareword(String),
 }
 
+impl ConfigOptionValue {
+    /// The value, whether quoted or not.
+    pub fn as_str(&self) -> &str {
+        match self {
+            ConfigOptionValue::Quoted(value)
+            | ConfigOptionValue::SqliteParameter(value)
+            | ConfigOptionValue::Bareword(value) => value,
+        }
+    }
+
+    /// The value as a boolean, with the same forms as
+    /// [`crate::api::value_bool`], like `strict=on` or `strict='false'`.
+    pub fn as_bool(&self) -> std::result::Result<bool, String> {
+        crate::api::parse_bool(self.as_str()).ok_or_else(|| {
+            format!(
+                "expected a boolean (0, 1, true, false, on, off, yes or no), got '{}'",
+                self.as_str()
+            )
+        })
+    }
+}
+
 /// Given a raw argument, returns a parsed [`Argument`]. Should already by
 /// comma (?) delimited, typically sourced from [`VTabArguments`](crate::table::VTabArguments).
 pub fn parse_argument(argument: &str) -> std::result::Result<Argument, String> {
@@ -203,7 +225,39 @@ pub fn arg_is_column_declaration(arg: &str) -> Result<Option<ColumnDeclaration>,
 
 #[cfg(test)]
 mod tests {
+    use crate::api::{ColumnAffinity, ColumnAffinityMap};
     use crate::vtab_argparse::*;
+
+    #[test]
+    fn test_column_affinity_map() {
+        let columns: Vec<ColumnDeclaration> = ["id integer", "name varchar(10)", "data", "price"]
+            .into_iter()
+            .map(|argument| match parse_argument(argument) {
+                Ok(Argument::Column(column)) => column,
+                _ => panic!("not a column: {}", argument),
+            })
+            .collect();
+        let map = ColumnAffinityMap::from_columns(&columns);
+        assert_eq!(map.len(), 4);
+        assert_eq!(map.get(0), Some(ColumnAffinity::Integer));
+        assert_eq!(map.get(1), Some(ColumnAffinity::Text));
+        assert_eq!(map.get(2), Some(ColumnAffinity::Blob));
+        assert_eq!(map.get(3), Some(ColumnAffinity::Blob));
+        assert_eq!(map.get(4), None);
+
+        let map = ColumnAffinityMap::from_declared_types([Some("DOUBLE"), None, Some("decimal")]);
+        assert_eq!(
+            (0..map.len())
+                .map(|i| map.get(i).unwrap())
+                .collect::<Vec<_>>(),
+            vec![
+                ColumnAffinity::Real,
+                ColumnAffinity::Blob,
+                ColumnAffinity::Numeric
+            ]
+        );
+    }
+
     #[test]
     fn test_parse_argument() {
         assert_eq!(
@@ -242,4 +296,22 @@ mod tests {
             }))
         );
     }
+
+    #[test]
+    fn test_config_option_as_bool() {
+        let value = |argument: &str| match parse_argument(argument) {
+            Ok(Argument::Config(option)) => option.value.as_bool(),
+            _ => panic!("not a config option: {}", argument),
+        };
+        assert_eq!(value("strict=on"), Ok(true));
+        assert_eq!(value("strict='False'"), Ok(false));
+        assert_eq!(value("strict=1"), Ok(true));
+        assert_eq!(
+            value("strict=maybe"),
+            Err(
+                "expected a boolean (0, 1, true, false, on, off, yes or no), got 'maybe'"
+                    .to_owned()
+            )
+        );
+    }
 }
@@ -0,0 +1,194 @@
+//! Scalar functions from WebAssembly modules, defined at runtime.
+//!
+//! [`define_wasm_host`] adds `wasm_define(name, module [, export])`, which
+//! compiles a module with wasmtime and defines the SQL function `name` from
+//! one of its exports, `name` itself unless `export` says otherwise:
+//!
+//! ```sql
+//! select wasm_define('fib', readfile('fib.wasm'));
+//! select fib(10);
+//! ```
+//!
+//! Modules can't import anything, so they only compute on their arguments.
+//! Exports can take and return `i32`, `i64`, `f32` and `f64`, and return at
+//! most one value: integers come from and go to SQLite integers, floats to
+//! reals, a NULL argument makes the result NULL, and a function that
+//! returns nothing returns NULL. Each call gets [`FUEL_PER_CALL`] and
+//! [`MEMORY_LIMIT`], so a module that loops or allocates forever fails the
+//! query instead of hanging it.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api;
+use crate::errors::{Error, Result};
+use crate::ext::{sqlite3, sqlite3_context, sqlite3_value};
+use crate::scalar::{define_scalar_function, FunctionFlags};
+use std::cell::RefCell;
+use wasmtime::{
+    Config, Engine, Func, Instance, Module, Store, StoreLimits, StoreLimitsBuilder, Trap, Val,
+    ValType,
+};
+
+/// How much fuel, roughly a WebAssembly instruction each, one call can
+/// burn.
+pub const FUEL_PER_CALL: u64 = 100_000_000;
+
+/// How large, in bytes, a module's memory can grow.
+pub const MEMORY_LIMIT: usize = 64 << 20;
+
+/// An engine that meters calls with fuel, for [`define_wasm_function`].
+pub fn engine() -> Result<Engine> {
+    let mut config = Config::new();
+    config.consume_fuel(true);
+    Engine::new(&config).map_err(|err| Error::new_message(format!("wasmtime: {:#}", err)))
+}
+
+/// Defines `wasm_define(name, module [, export])`. It modifies the
+/// connection's functions, so it's DIRECTONLY: a trigger or view can't
+/// call it.
+pub fn define_wasm_host(db: *mut sqlite3) -> Result<()> {
+    let engine = engine()?;
+    define_scalar_function(
+        db,
+        "wasm_define",
+        -1,
+        move |context, values| {
+            if !(2..=3).contains(&values.len()) {
+                return Err(Error::new_message(
+                    "wasm_define() takes a name, a module, and optionally an export",
+                ));
+            }
+            let name = api::value_text(&values[0])?;
+            let export = match values.get(2) {
+                Some(export) => api::value_text(export)?,
+                None => name,
+            };
+            let db = api::context_db_handle(context);
+            define_wasm_function(db, &engine, name, api::value_blob(&values[1]), export)?;
+            api::result_null(context);
+            Ok(())
+        },
+        FunctionFlags::UTF8 | FunctionFlags::DIRECTONLY,
+    )
+}
+
+/// Compiles `module`, binary or text, and defines the SQL function `name`
+/// that calls its export `export`.
+pub fn define_wasm_function(
+    db: *mut sqlite3,
+    engine: &Engine,
+    name: &str,
+    module: &[u8],
+    export: &str,
+) -> Result<()> {
+    // the text parser's errors quote the input, which can hold NULs that
+    // an error message can't
+    let module = Module::new(engine, module).map_err(|err| {
+        Error::new_message(format!("invalid WebAssembly module: {:#}", err).replace('\0', ""))
+    })?;
+    let mut store = Store::new(
+        engine,
+        StoreLimitsBuilder::new().memory_size(MEMORY_LIMIT).build(),
+    );
+    store.limiter(|limits| limits);
+    let instance = Instance::new(&mut store, &module, &[]).map_err(|err| {
+        Error::new_message(format!("could not instantiate the module: {:#}", err))
+    })?;
+    let func = instance
+        .get_func(&mut store, export)
+        .ok_or_else(|| Error::new_message(format!("the module exports no function {}", export)))?;
+    let ty = func.ty(&store);
+    let params: Vec<ValType> = ty.params().collect();
+    let results: Vec<ValType> = ty.results().collect();
+    for param in params.iter().chain(&results) {
+        if !is_numeric(param) {
+            return Err(Error::new_message(format!(
+                "{} has a {} parameter or result, only i32, i64, f32 and f64 are supported",
+                export, param
+            )));
+        }
+    }
+    if results.len() > 1 {
+        return Err(Error::new_message(format!(
+            "{} returns {} values, at most one is supported",
+            export,
+            results.len()
+        )));
+    }
+    let function = WasmFunction {
+        store: RefCell::new(store),
+        func,
+        params,
+        result: results.first().cloned(),
+    };
+    let num_args = function.params.len() as i32;
+    define_scalar_function(
+        db,
+        name,
+        num_args,
+        move |context, values| function.call(context, values),
+        FunctionFlags::UTF8,
+    )
+}
+
+fn is_numeric(ty: &ValType) -> bool {
+    matches!(
+        ty,
+        ValType::I32 | ValType::I64 | ValType::F32 | ValType::F64
+    )
+}
+
+/// An export and the instance it runs in, one per SQL function.
+struct WasmFunction {
+    store: RefCell<Store<StoreLimits>>,
+    func: Func,
+    params: Vec<ValType>,
+    result: Option<ValType>,
+}
+
+impl WasmFunction {
+    fn call(&self, context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+        if values.iter().any(api::value_is_null) {
+            api::result_null(context);
+            return Ok(());
+        }
+        let args = values
+            .iter()
+            .zip(&self.params)
+            .enumerate()
+            .map(|(i, (value, ty))| argument(value, ty, i))
+            .collect::<Result<Vec<Val>>>()?;
+        let mut results = vec![Val::I32(0); usize::from(self.result.is_some())];
+        let mut store = self.store.borrow_mut();
+        store
+            .set_fuel(FUEL_PER_CALL)
+            .map_err(|err| Error::new_message(format!("wasmtime: {:#}", err)))?;
+        self.func
+            .call(&mut *store, &args, &mut results)
+            .map_err(|err| match err.downcast_ref::<Trap>() {
+                Some(Trap::OutOfFuel) => Error::new_message(format!(
+                    "WebAssembly function ran out of fuel after {} instructions",
+                    FUEL_PER_CALL
+                )),
+                _ => Error::new_message(format!("WebAssembly function failed: {:#}", err)),
+            })?;
+        match results.first() {
+            Some(Val::I32(i)) => api::result_int(context, *i),
+            Some(Val::I64(i)) => api::result_int64(context, *i),
+            Some(Val::F32(bits)) => api::result_double(context, f32::from_bits(*bits).into()),
+            Some(Val::F64(bits)) => api::result_double(context, f64::from_bits(*bits)),
+            _ => api::result_null(context),
+        }
+        Ok(())
+    }
+}
+
+fn argument(value: &*mut sqlite3_value, ty: &ValType, i: usize) -> Result<Val> {
+    Ok(match ty {
+        ValType::I32 => Val::I32(i32::try_from(api::value_int64(value)).map_err(|_| {
+            Error::new_message(format!("argument {} is out of range for an i32", i + 1))
+        })?),
+        ValType::I64 => Val::I64(api::value_int64(value)),
+        ValType::F32 => Val::F32((api::value_double(value) as f32).to_bits()),
+        _ => Val::F64(api::value_double(value).to_bits()),
+    })
+}
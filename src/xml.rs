@@ -0,0 +1,518 @@
+//! Extract parts of XML and HTML documents stored in sqlite3_values, returning
+//! the matched nodes as a JSON array (ideal for [`result_json`](crate::api::result_json)).
+//!
+//! With the `xml` feature, [`value_xml_select`] parses XML with
+//! [roxmltree](https://docs.rs/roxmltree) and selects nodes with a subset of
+//! XPath 1.0: absolute and relative location paths, `//`, `.`, `..`, `*`,
+//! `@name`, `@*`, `text()`, `node()`, and predicates like `[2]`, `[last()]`,
+//! `[@id]`, `[@id='x']`, `[title!='y']`.
+//!
+//! With the `html` feature, [`value_html_select`] parses (possibly malformed)
+//! HTML with [scraper](https://docs.rs/scraper) and selects elements with CSS
+//! selectors.
+//!
+//! Matched elements become objects like
+//! `{"name": "a", "attributes": {"href": "/"}, "text": "home"}`, where "text"
+//! is all of the element's descendant text. Attributes and text nodes become
+//! plain strings.
+
+use crate::api::value_text;
+use crate::errors::{Error, Result};
+use crate::ext::sqlite3_value;
+use serde_json::{Map, Value};
+
+fn element_json<'a, A, T>(name: &str, attributes: A, text: T) -> Value
+where
+    A: Iterator<Item = (&'a str, &'a str)>,
+    T: Iterator<Item = &'a str>,
+{
+    let attributes: Map<String, Value> = attributes
+        .map(|(name, value)| (name.to_owned(), Value::String(value.to_owned())))
+        .collect();
+    let mut object = Map::new();
+    object.insert("name".to_owned(), Value::String(name.to_owned()));
+    object.insert("attributes".to_owned(), Value::Object(attributes));
+    object.insert("text".to_owned(), Value::String(text.collect()));
+    Value::Object(object)
+}
+
+fn document_arg<'a>(value: &*mut sqlite3_value) -> Result<&'a str> {
+    value_text(value).map_err(|err| Error::new_message(format!("document is not UTF-8: {}", err)))
+}
+
+#[cfg(feature = "xml")]
+mod xpath {
+    use crate::errors::{Error, Result};
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub(super) enum Test {
+        /// `name`, or `*` when None
+        Element(Option<String>),
+        /// `@name`, or `@*` when None
+        Attribute(Option<String>),
+        /// `text()`
+        Text,
+        /// `node()`
+        Node,
+        /// `.`
+        SelfNode,
+        /// `..`
+        Parent,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub(super) enum Predicate {
+        Position(usize),
+        Last,
+        /// `[@name]`, `[name]`, `[text()]`, or `[.]`, optionally compared to a
+        /// literal with `=` (true) or `!=` (false)
+        Compare(Test, Option<(bool, String)>),
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub(super) struct Step {
+        /// Preceded by `//`, so matched against all descendants of the
+        /// context nodes, not only their children.
+        pub descendants: bool,
+        pub test: Test,
+        pub predicates: Vec<Predicate>,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub(super) struct Path {
+        pub steps: Vec<Step>,
+    }
+
+    struct Parser<'a> {
+        expr: &'a str,
+        pos: usize,
+    }
+
+    impl<'a> Parser<'a> {
+        fn error<T>(&self, message: &str) -> Result<T> {
+            Err(Error::new_message(format!(
+                "invalid XPath expression {:?} at offset {}: {}",
+                self.expr, self.pos, message
+            )))
+        }
+
+        fn rest(&self) -> &'a str {
+            &self.expr[self.pos..]
+        }
+
+        fn skip_whitespace(&mut self) {
+            let rest = self.rest();
+            self.pos += rest.len() - rest.trim_start().len();
+        }
+
+        fn eat(&mut self, token: &str) -> bool {
+            self.skip_whitespace();
+            if self.rest().starts_with(token) {
+                self.pos += token.len();
+                true
+            } else {
+                false
+            }
+        }
+
+        fn name(&mut self) -> Option<String> {
+            self.skip_whitespace();
+            let len = self
+                .rest()
+                .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':')))
+                .unwrap_or(self.rest().len());
+            if len == 0 || self.rest().starts_with('.') {
+                return None;
+            }
+            let name = self.rest()[..len].to_owned();
+            self.pos += len;
+            Some(name)
+        }
+
+        fn literal(&mut self) -> Result<String> {
+            self.skip_whitespace();
+            let quote = match self.rest().chars().next() {
+                Some(quote @ ('\'' | '"')) => quote,
+                _ => {
+                    // unquoted numbers are compared as text
+                    let len = self
+                        .rest()
+                        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-'))
+                        .unwrap_or(self.rest().len());
+                    if len == 0 {
+                        return self.error("expected a string or number literal");
+                    }
+                    let literal = self.rest()[..len].to_owned();
+                    self.pos += len;
+                    return Ok(literal);
+                }
+            };
+            match self.rest()[1..].find(quote) {
+                Some(end) => {
+                    let literal = self.rest()[1..end + 1].to_owned();
+                    self.pos += end + 2;
+                    Ok(literal)
+                }
+                None => self.error("unterminated string literal"),
+            }
+        }
+
+        fn test(&mut self) -> Result<Test> {
+            if self.eat("..") {
+                Ok(Test::Parent)
+            } else if self.eat(".") {
+                Ok(Test::SelfNode)
+            } else if self.eat("@") {
+                if self.eat("*") {
+                    Ok(Test::Attribute(None))
+                } else {
+                    match self.name() {
+                        Some(name) => Ok(Test::Attribute(Some(name))),
+                        None => self.error("expected an attribute name after '@'"),
+                    }
+                }
+            } else if self.eat("*") {
+                Ok(Test::Element(None))
+            } else if self.eat("text()") {
+                Ok(Test::Text)
+            } else if self.eat("node()") {
+                Ok(Test::Node)
+            } else {
+                match self.name() {
+                    Some(name) => Ok(Test::Element(Some(name))),
+                    None => self.error("expected a step"),
+                }
+            }
+        }
+
+        fn predicate(&mut self) -> Result<Predicate> {
+            self.skip_whitespace();
+            if self.eat("last()") {
+                return Ok(Predicate::Last);
+            }
+            let digits = self
+                .rest()
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(self.rest().len());
+            if digits > 0 {
+                let position: usize = self.rest()[..digits].parse().unwrap_or(0);
+                self.pos += digits;
+                if position == 0 {
+                    return self.error("positions start at 1");
+                }
+                return Ok(Predicate::Position(position));
+            }
+            let test = match self.test()? {
+                test @ (Test::Element(_) | Test::Attribute(_) | Test::Text | Test::SelfNode) => {
+                    test
+                }
+                _ => return self.error("unsupported predicate"),
+            };
+            let comparison = if self.eat("!=") {
+                Some((false, self.literal()?))
+            } else if self.eat("=") {
+                Some((true, self.literal()?))
+            } else {
+                None
+            };
+            Ok(Predicate::Compare(test, comparison))
+        }
+
+        fn step(&mut self, descendants: bool) -> Result<Step> {
+            let test = self.test()?;
+            let mut predicates = vec![];
+            while self.eat("[") {
+                predicates.push(self.predicate()?);
+                if !self.eat("]") {
+                    return self.error("expected ']'");
+                }
+            }
+            Ok(Step {
+                descendants,
+                test,
+                predicates,
+            })
+        }
+    }
+
+    pub(super) fn parse(expr: &str) -> Result<Path> {
+        let mut parser = Parser { expr, pos: 0 };
+        let mut steps = vec![];
+        // relative paths are evaluated against the document too, since
+        // there's no other context node, so a leading "/" changes nothing
+        let mut descendants = parser.eat("//");
+        if !descendants && parser.eat("/") {
+            parser.skip_whitespace();
+            // "/" alone selects the document itself
+            if parser.rest().is_empty() {
+                return Ok(Path { steps });
+            }
+        }
+        loop {
+            steps.push(parser.step(descendants)?);
+            if parser.eat("//") {
+                descendants = true;
+            } else if parser.eat("/") {
+                descendants = false;
+            } else {
+                break;
+            }
+        }
+        parser.skip_whitespace();
+        if !parser.rest().is_empty() {
+            return parser.error("unexpected trailing characters");
+        }
+        Ok(Path { steps })
+    }
+}
+
+#[cfg(feature = "xml")]
+mod select {
+    use super::xpath::{Path, Predicate, Step, Test};
+    use roxmltree::Node;
+
+    /// A node in the result set. roxmltree doesn't represent attributes as
+    /// nodes, so they're kept as their element and index.
+    #[derive(Clone, Copy)]
+    pub(super) enum Item<'a, 'input> {
+        Node(Node<'a, 'input>),
+        Attribute(Node<'a, 'input>, usize),
+    }
+
+    impl<'a, 'input> Item<'a, 'input> {
+        fn key(&self) -> (usize, usize) {
+            match self {
+                Item::Node(node) => (node.id().get_usize(), 0),
+                Item::Attribute(node, i) => (node.id().get_usize(), i + 1),
+            }
+        }
+
+        pub(super) fn string_value(&self) -> String {
+            match self {
+                Item::Node(node) if node.is_text() => node.text().unwrap_or("").to_owned(),
+                Item::Node(node) => node
+                    .descendants()
+                    .filter(|n| n.is_text())
+                    .filter_map(|n| n.text())
+                    .collect(),
+                Item::Attribute(node, i) => node
+                    .attributes()
+                    .nth(*i)
+                    .map(|a| a.value().to_owned())
+                    .unwrap_or_default(),
+            }
+        }
+    }
+
+    fn candidates<'a, 'input>(node: Node<'a, 'input>, test: &Test) -> Vec<Item<'a, 'input>> {
+        match test {
+            Test::SelfNode => vec![Item::Node(node)],
+            Test::Parent => node.parent().map(Item::Node).into_iter().collect(),
+            Test::Attribute(name) => node
+                .attributes()
+                .enumerate()
+                .filter(|(_, a)| name.as_deref().map_or(true, |name| a.name() == name))
+                .map(|(i, _)| Item::Attribute(node, i))
+                .collect(),
+            Test::Element(name) => node
+                .children()
+                .filter(|n| {
+                    n.is_element()
+                        && name
+                            .as_deref()
+                            .map_or(true, |name| n.tag_name().name() == name)
+                })
+                .map(Item::Node)
+                .collect(),
+            Test::Text => node
+                .children()
+                .filter(|n| n.is_text())
+                .map(Item::Node)
+                .collect(),
+            Test::Node => node
+                .children()
+                .filter(|n| n.is_element() || n.is_text())
+                .map(Item::Node)
+                .collect(),
+        }
+    }
+
+    fn matches(item: &Item, test: &Test, comparison: &Option<(bool, String)>) -> bool {
+        let node = match item {
+            Item::Node(node) => *node,
+            Item::Attribute(..) => return false,
+        };
+        let mut values = candidates(node, test).into_iter().map(|c| c.string_value());
+        match comparison {
+            None => values.next().is_some(),
+            Some((true, literal)) => values.any(|v| &v == literal),
+            Some((false, literal)) => values.any(|v| &v != literal),
+        }
+    }
+
+    fn apply_predicates<'a, 'input>(
+        mut items: Vec<Item<'a, 'input>>,
+        predicates: &[Predicate],
+    ) -> Vec<Item<'a, 'input>> {
+        for predicate in predicates {
+            items = match predicate {
+                Predicate::Position(n) => items.into_iter().nth(n - 1).into_iter().collect(),
+                Predicate::Last => items.pop().into_iter().collect(),
+                Predicate::Compare(test, comparison) => items
+                    .into_iter()
+                    .filter(|item| matches(item, test, comparison))
+                    .collect(),
+            };
+        }
+        items
+    }
+
+    fn step<'a, 'input>(context: &[Item<'a, 'input>], step: &Step) -> Vec<Item<'a, 'input>> {
+        let mut result = vec![];
+        for item in context {
+            let node = match item {
+                Item::Node(node) => *node,
+                Item::Attribute(..) => continue,
+            };
+            // "a//b" is "a/descendant-or-self::node()/b", so positions are
+            // relative to each parent, not to the whole descendant set
+            let bases: Vec<Node> = if step.descendants {
+                node.descendants()
+                    .filter(|n| n.is_element() || n.is_root())
+                    .collect()
+            } else {
+                vec![node]
+            };
+            for base in bases {
+                result.extend(apply_predicates(
+                    candidates(base, &step.test),
+                    &step.predicates,
+                ));
+            }
+        }
+        // node sets are unique and in document order
+        result.sort_by_key(|item| item.key());
+        result.dedup_by_key(|item| item.key());
+        result
+    }
+
+    pub(super) fn evaluate<'a, 'input>(
+        root: Node<'a, 'input>,
+        path: &Path,
+    ) -> Vec<Item<'a, 'input>> {
+        let mut items = vec![Item::Node(root)];
+        for s in &path.steps {
+            items = step(&items, s);
+        }
+        items
+    }
+}
+
+/// Selects nodes from an XML document with an XPath expression (see the
+/// [module docs](self) for the supported subset), as a JSON array.
+#[cfg(feature = "xml")]
+pub fn xml_select(document: &str, xpath: &str) -> Result<Value> {
+    let path = xpath::parse(xpath)?;
+    let document = roxmltree::Document::parse(document)
+        .map_err(|err| Error::new_message(format!("invalid XML: {}", err)))?;
+    let nodes = select::evaluate(document.root(), &path)
+        .into_iter()
+        .map(|item| match item {
+            select::Item::Node(node) if node.is_element() => element_json(
+                node.tag_name().name(),
+                node.attributes().map(|a| (a.name(), a.value())),
+                node.descendants()
+                    .filter(|n| n.is_text())
+                    .filter_map(|n| n.text()),
+            ),
+            item => Value::String(item.string_value()),
+        })
+        .collect();
+    Ok(Value::Array(nodes))
+}
+
+/// Selects nodes from the XML document in the given value, see [`xml_select`].
+#[cfg(feature = "xml")]
+pub fn value_xml_select(value: &*mut sqlite3_value, xpath: &str) -> Result<Value> {
+    xml_select(document_arg(value)?, xpath)
+}
+
+/// Selects elements from an HTML document with a CSS selector, as a JSON array.
+/// Malformed HTML is parsed the same way browsers would.
+#[cfg(feature = "html")]
+pub fn html_select(document: &str, selector: &str) -> Result<Value> {
+    let selector = scraper::Selector::parse(selector).map_err(|err| {
+        Error::new_message(format!("invalid CSS selector {:?}: {}", selector, err))
+    })?;
+    let document = scraper::Html::parse_document(document);
+    let elements = document
+        .select(&selector)
+        .map(|element| {
+            element_json(
+                element.value().name(),
+                element.value().attrs(),
+                element.text(),
+            )
+        })
+        .collect();
+    Ok(Value::Array(elements))
+}
+
+/// Selects elements from the HTML document in the given value, see [`html_select`].
+#[cfg(feature = "html")]
+pub fn value_html_select(value: &*mut sqlite3_value, selector: &str) -> Result<Value> {
+    html_select(document_arg(value)?, selector)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::xml::*;
+    #[allow(unused_imports)]
+    use serde_json::json;
+
+    #[cfg(feature = "xml")]
+    #[test]
+    fn test_xml_select() {
+        let doc = r#"<library>
+            <book id="1" lang="en"><title>Dune</title><year>1965</year></book>
+            <book id="2"><title>Solaris</title><year>1961</year></book>
+            <shelf><book id="3"><title>Ubik</title></book></shelf>
+        </library>"#;
+        let select = |xpath| xml_select(doc, xpath).unwrap();
+
+        assert_eq!(
+            select("/library/book/title/text()"),
+            json!(["Dune", "Solaris"])
+        );
+        assert_eq!(select("//book/@id"), json!(["1", "2", "3"]));
+        assert_eq!(select("//book[2]/title/text()"), json!(["Solaris"]));
+        assert_eq!(select("//book[last()]/@id"), json!(["2", "3"]));
+        assert_eq!(select("//book[@lang]/@id"), json!(["1"]));
+        assert_eq!(select("//book[@id!='1'][year]/@id"), json!(["2"]));
+        assert_eq!(select("library/*[title='Ubik']/@id"), json!([]));
+        assert_eq!(select("//title[.='Ubik']/../@id"), json!(["3"]));
+        assert_eq!(select("//book[year=1961]/@*"), json!(["2"]));
+        assert_eq!(
+            select("/library/book[1]"),
+            json!([{"name": "book", "attributes": {"id": "1", "lang": "en"}, "text": "Dune1965"}])
+        );
+        assert_eq!(select("//missing"), json!([]));
+
+        assert!(xml_select(doc, "//book[").is_err());
+        assert!(xml_select(doc, "//book[0]").is_err());
+        assert!(xml_select(doc, "/library/").is_err());
+        assert!(xml_select("<unclosed>", "/").is_err());
+    }
+
+    #[cfg(feature = "html")]
+    #[test]
+    fn test_html_select() {
+        let doc = r#"<ul><li><a href="/a">A</a><li class="x"><a href="/b">B <b>bold</b></a></ul>"#;
+        assert_eq!(
+            html_select(doc, "li.x a").unwrap(),
+            json!([{"name": "a", "attributes": {"href": "/b"}, "text": "B bold"}])
+        );
+        assert_eq!(html_select(doc, "a").unwrap().as_array().unwrap().len(), 2);
+        assert!(html_select(doc, "li[").is_err());
+    }
+}
@@ -0,0 +1,702 @@
+//! Zip archives as tables, with the `zipfile` feature.
+//!
+//! [`define_zipfile`] adds the `zipfile(file)` table function, which lists
+//! the entries of an archive given its path or its bytes, and the
+//! `zipfile_table` module, a writable table over an archive on disk:
+//!
+//! ```sql
+//! select name, sz, data from zipfile('assets.zip') where name like '%.json';
+//! select name from zipfile((select archive from uploads where id = 1));
+//!
+//! create virtual table temp.bundle using zipfile_table('bundle.zip');
+//! insert into temp.bundle(name, data) values ('hello.txt', 'hello');
+//! delete from temp.bundle where name like 'old/%';
+//! ```
+//!
+//! Both have the columns `name`, `mode`, `mtime` (seconds since the Unix
+//! epoch), `sz` (the uncompressed size), `data` and `method` (0 for stored
+//! entries, 8 for deflated ones). Only the archive's directory is read up
+//! front, and an entry is decompressed when its `data` is read, so listing
+//! names decompresses nothing.
+//!
+//! Inserting takes a `name`, and `data` for files, while a name ending in
+//! `/` is a directory. `mode`, `mtime` and `method` default to 0o644 (0o755
+//! for directories), now and 8, and `sz` is always computed. Changes are
+//! kept in memory until the transaction commits, when a new archive is
+//! written next to the old one and renamed over it, so a failed write
+//! leaves the old archive as it was. Entries that didn't change are copied
+//! without being recompressed. Zip times have two-second precision, can't
+//! be before 1980, and are taken as UTC.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use crate::api::{self, ValueType};
+use crate::errors::{Error, Result};
+use crate::ext::{sqlite3, sqlite3_context, sqlite3_value, sqlite3_vtab, sqlite3_vtab_cursor};
+use crate::table::{
+    define_table_function, define_virtual_table_writeable_with_transactions, vtab_config,
+    BestIndexError, ConflictMode, ConstraintOperator, IndexInfo, UpdateOperation, VTab,
+    VTabArguments, VTabConfig, VTabCursor, VTabWriteable, VTabWriteableWithTransactions,
+};
+use std::cell::RefCell;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Cursor, Read, Seek, Write};
+use std::os::raw::c_int;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use zip::write::{FileOptions, ZipWriter};
+use zip::{CompressionMethod, DateTime, ZipArchive};
+
+const COLUMN_NAME: c_int = 0;
+const COLUMN_MODE: c_int = 1;
+const COLUMN_MTIME: c_int = 2;
+const COLUMN_SZ: c_int = 3;
+const COLUMN_DATA: c_int = 4;
+const COLUMN_METHOD: c_int = 5;
+/// The hidden argument of `zipfile(file)`.
+const COLUMN_FILE: c_int = 6;
+
+/// The `method` of entries stored as they are.
+pub const METHOD_STORED: u16 = 0;
+/// The `method` of deflated entries.
+pub const METHOD_DEFLATED: u16 = 8;
+
+const S_IFMT: u32 = 0o170000;
+const S_IFREG: u32 = 0o100000;
+const S_IFDIR: u32 = 0o040000;
+
+/// Defines the `zipfile(file)` table function and the `zipfile_table`
+/// module.
+pub fn define_zipfile(db: *mut sqlite3) -> Result<()> {
+    define_table_function::<ZipfileFunction>(db, "zipfile", None)?;
+    define_virtual_table_writeable_with_transactions::<ZipfileTable>(db, "zipfile_table", None)?;
+    Ok(())
+}
+
+fn zip_error(err: zip::result::ZipError) -> Error {
+    Error::new_message(format!("zip: {}", err))
+}
+
+fn io_error(err: std::io::Error) -> Error {
+    Error::new_message(format!("zip: {}", err))
+}
+
+trait ReadSeek: Read + Seek {}
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// One file or directory of an archive.
+#[derive(Debug, Clone)]
+struct Entry {
+    name: String,
+    mode: u32,
+    /// Seconds since the Unix epoch.
+    mtime: i64,
+    size: u64,
+    method: u16,
+    data: EntryData,
+}
+
+impl Entry {
+    fn is_dir(&self) -> bool {
+        self.name.ends_with('/')
+    }
+}
+
+#[derive(Debug, Clone)]
+enum EntryData {
+    /// The entry at this index of the archive, unchanged.
+    Archived(usize),
+    /// New contents, compressed when the archive is written.
+    New(Rc<[u8]>),
+}
+
+/// An archive's entries, with any changes not written yet.
+struct Archive {
+    /// The archive the [`EntryData::Archived`] entries are in, if any.
+    reader: Option<ZipArchive<Box<dyn ReadSeek>>>,
+    entries: Vec<Entry>,
+    changed: bool,
+}
+
+impl Archive {
+    fn empty() -> Archive {
+        Archive {
+            reader: None,
+            entries: vec![],
+            changed: false,
+        }
+    }
+
+    fn read(reader: Box<dyn ReadSeek>) -> Result<Archive> {
+        let mut reader = ZipArchive::new(reader).map_err(zip_error)?;
+        let mut entries = Vec::with_capacity(reader.len());
+        for i in 0..reader.len() {
+            let file = reader.by_index_raw(i).map_err(zip_error)?;
+            let (file_type, default_mode) = if file.is_dir() {
+                (S_IFDIR, 0o755)
+            } else {
+                (S_IFREG, 0o644)
+            };
+            // some writers leave out the file type bits
+            let mode = match file.unix_mode() {
+                Some(mode) if mode & S_IFMT != 0 => mode,
+                Some(mode) => mode | file_type,
+                None => default_mode | file_type,
+            };
+            #[allow(deprecated)]
+            let method = file.compression().to_u16();
+            entries.push(Entry {
+                name: file.name().to_owned(),
+                mode,
+                mtime: unix_time(file.last_modified()),
+                size: file.size(),
+                method,
+                data: EntryData::Archived(i),
+            });
+        }
+        Ok(Archive {
+            reader: Some(reader),
+            entries,
+            changed: false,
+        })
+    }
+
+    /// The archive at `path`, or an empty one if there's no file there yet.
+    fn open(path: &Path) -> Result<Archive> {
+        match File::open(path) {
+            Ok(file) => Archive::read(Box::new(BufReader::new(file))),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Archive::empty()),
+            Err(err) => Err(io_error(err)),
+        }
+    }
+
+    /// The archive in a `zipfile()` argument, a path or the archive's bytes.
+    fn from_value(value: &*mut sqlite3_value) -> Result<Archive> {
+        match api::value_type(value) {
+            ValueType::Null => Ok(Archive::empty()),
+            ValueType::Blob => {
+                Archive::read(Box::new(Cursor::new(api::value_blob(value).to_vec())))
+            }
+            _ => {
+                let path = api::value_text(value)?;
+                let file = File::open(path)
+                    .map_err(|err| Error::new_message(format!("zip: {}: {}", path, err)))?;
+                Archive::read(Box::new(BufReader::new(file)))
+            }
+        }
+    }
+
+    /// The uncompressed contents of `entry`.
+    fn data(&mut self, entry: &Entry) -> Result<Rc<[u8]>> {
+        let i = match &entry.data {
+            EntryData::New(data) => return Ok(data.clone()),
+            EntryData::Archived(i) => *i,
+        };
+        let reader = self
+            .reader
+            .as_mut()
+            .ok_or_else(|| Error::new_message("zip: the archive is closed"))?;
+        let mut file = reader.by_index(i).map_err(|err| match err {
+            zip::result::ZipError::UnsupportedArchive(_) => Error::new_message(format!(
+                "zip: {} uses compression method {}, which isn't supported",
+                entry.name, entry.method
+            )),
+            err => zip_error(err),
+        })?;
+        let mut data = Vec::with_capacity(entry.size.min(1 << 24) as usize);
+        file.read_to_end(&mut data).map_err(io_error)?;
+        Ok(data.into())
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.name == name)
+    }
+
+    /// Writes the archive to `path`, by way of a temporary file.
+    fn write(&mut self, path: &Path) -> Result<()> {
+        let mut temp = OsString::from(path.as_os_str());
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        let result = self.write_to(&temp);
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        result?;
+        fs::rename(&temp, path).map_err(io_error)?;
+
+        // the entries are in the new archive now, in the same order
+        let reader: Box<dyn ReadSeek> =
+            Box::new(BufReader::new(File::open(path).map_err(io_error)?));
+        self.reader = Some(ZipArchive::new(reader).map_err(zip_error)?);
+        for (i, entry) in self.entries.iter_mut().enumerate() {
+            entry.data = EntryData::Archived(i);
+        }
+        self.changed = false;
+        Ok(())
+    }
+
+    fn write_to(&mut self, temp: &Path) -> Result<()> {
+        let file = File::create(temp).map_err(io_error)?;
+        let mut writer = ZipWriter::new(BufWriter::new(file));
+        for entry in &self.entries {
+            match &entry.data {
+                EntryData::Archived(i) => {
+                    let reader = self
+                        .reader
+                        .as_mut()
+                        .ok_or_else(|| Error::new_message("zip: the archive is closed"))?;
+                    let file = reader.by_index_raw(*i).map_err(zip_error)?;
+                    writer.raw_copy_file(file).map_err(zip_error)?;
+                }
+                EntryData::New(data) => {
+                    let method = match entry.method {
+                        METHOD_STORED => CompressionMethod::Stored,
+                        _ => CompressionMethod::Deflated,
+                    };
+                    let options = FileOptions::default()
+                        .compression_method(method)
+                        .unix_permissions(entry.mode)
+                        .last_modified_time(zip_time(entry.mtime)?)
+                        .large_file(data.len() as u64 >= u32::MAX as u64);
+                    if entry.is_dir() {
+                        writer
+                            .add_directory(entry.name.as_str(), options)
+                            .map_err(zip_error)?;
+                    } else {
+                        writer
+                            .start_file(entry.name.as_str(), options)
+                            .map_err(zip_error)?;
+                        writer.write_all(data).map_err(io_error)?;
+                    }
+                }
+            }
+        }
+        let file = writer
+            .finish()
+            .map_err(zip_error)?
+            .into_inner()
+            .map_err(|err| io_error(err.into_error()))?;
+        file.sync_all().map_err(io_error)
+    }
+}
+
+/// Days since the Unix epoch of a date, from
+/// <http://howardhinnant.github.io/date_algorithms.html#days_from_civil>.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = (if year >= 0 { year } else { year - 399 }) / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The date of a number of days since the Unix epoch, from
+/// <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = (if days >= 0 { days } else { days - 146096 }) / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+/// Seconds since the Unix epoch of a zip time.
+fn unix_time(time: DateTime) -> i64 {
+    let days = days_from_civil(time.year() as i64, time.month() as i64, time.day() as i64);
+    days * 86400 + time.hour() as i64 * 3600 + time.minute() as i64 * 60 + time.second() as i64
+}
+
+/// The zip time of seconds since the Unix epoch.
+fn zip_time(unix: i64) -> Result<DateTime> {
+    let (year, month, day) = civil_from_days(unix.div_euclid(86400));
+    let seconds = unix.rem_euclid(86400);
+    u16::try_from(year)
+        .ok()
+        .and_then(|year| {
+            DateTime::from_date_and_time(
+                year,
+                month as u8,
+                day as u8,
+                (seconds / 3600) as u8,
+                (seconds / 60 % 60) as u8,
+                (seconds % 60) as u8,
+            )
+            .ok()
+        })
+        .ok_or_else(|| {
+            Error::new_message(format!(
+                "zip: mtime {} isn't between the years 1980 and 2107",
+                unix
+            ))
+        })
+}
+
+/// The `zipfile(file)` table function.
+#[repr(C)]
+pub struct ZipfileFunction {
+    /// must be first
+    base: sqlite3_vtab,
+}
+
+impl<'vtab> VTab<'vtab> for ZipfileFunction {
+    type Aux = ();
+    type Cursor = ZipfileCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, ZipfileFunction)> {
+        let base: sqlite3_vtab = unsafe { std::mem::zeroed() };
+        Ok((
+            "CREATE TABLE x(name TEXT, mode INTEGER, mtime INTEGER, sz INTEGER, data BLOB, method INTEGER, file hidden)".to_owned(),
+            ZipfileFunction { base },
+        ))
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        let mut plan = 0;
+        for mut constraint in info.constraints() {
+            if constraint.column_idx() != COLUMN_FILE {
+                continue;
+            }
+            if !constraint.usable() || constraint.op() != Some(ConstraintOperator::EQ) {
+                return Err(BestIndexError::Constraint);
+            }
+            constraint.set_argv_index(1);
+            constraint.set_omit(true);
+            plan = PLAN_FILE;
+        }
+        // without an archive there's nothing to list
+        if plan != PLAN_FILE {
+            return Err(BestIndexError::Constraint);
+        }
+        info.set_idxnum(plan);
+        info.set_estimated_cost(1000.0);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<ZipfileCursor> {
+        Ok(ZipfileCursor::new(None))
+    }
+}
+
+/// A writable table over an archive on disk, created with
+/// `zipfile_table(path)`.
+#[repr(C)]
+pub struct ZipfileTable {
+    /// must be first
+    base: sqlite3_vtab,
+    path: PathBuf,
+    archive: Rc<RefCell<Archive>>,
+}
+
+/// The path in a `zipfile_table('path')` argument.
+fn unquote(argument: &str) -> String {
+    let argument = argument.trim();
+    for quote in ['\'', '"'] {
+        if argument.len() >= 2 && argument.starts_with(quote) && argument.ends_with(quote) {
+            return argument[1..argument.len() - 1]
+                .replace(&format!("{}{}", quote, quote), &quote.to_string());
+        }
+    }
+    argument.to_owned()
+}
+
+impl<'vtab> VTab<'vtab> for ZipfileTable {
+    type Aux = ();
+    type Cursor = ZipfileCursor;
+
+    fn connect(
+        db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        args: VTabArguments,
+    ) -> Result<(String, ZipfileTable)> {
+        let path = match args.arguments.as_slice() {
+            [path] => PathBuf::from(unquote(path)),
+            _ => {
+                return Err(Error::new_message(
+                    "zipfile_table takes one argument, the archive's path",
+                ))
+            }
+        };
+        let archive = Archive::open(&path)?;
+        vtab_config(db, VTabConfig::ConstraintSupport)?;
+        let base: sqlite3_vtab = unsafe { std::mem::zeroed() };
+        Ok((
+            "CREATE TABLE x(name TEXT PRIMARY KEY NOT NULL, mode INTEGER, mtime INTEGER, sz INTEGER, data BLOB, method INTEGER) WITHOUT ROWID".to_owned(),
+            ZipfileTable {
+                base,
+                path,
+                archive: Rc::new(RefCell::new(archive)),
+            },
+        ))
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        let mut plan = PLAN_SCAN;
+        for mut constraint in info.constraints() {
+            if constraint.column_idx() == COLUMN_NAME
+                && constraint.usable()
+                && constraint.op() == Some(ConstraintOperator::EQ)
+            {
+                constraint.set_argv_index(1);
+                constraint.set_omit(true);
+                plan = PLAN_NAME;
+                break;
+            }
+        }
+        if plan == PLAN_NAME {
+            info.set_estimated_cost(1.0);
+            info.set_estimated_rows(1);
+        } else {
+            info.set_estimated_cost(self.archive.borrow().entries.len() as f64 + 1.0);
+        }
+        info.set_idxnum(plan);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<ZipfileCursor> {
+        Ok(ZipfileCursor::new(Some(self.archive.clone())))
+    }
+}
+
+/// The entry inserted or updated with `values`, the table's columns.
+fn new_entry(values: &[*mut sqlite3_value]) -> Result<Entry> {
+    let column = |i: c_int| &values[i as usize];
+    let name = match api::value_type(column(COLUMN_NAME)) {
+        ValueType::Null => return Err(Error::constraint("an entry's name can't be NULL")),
+        _ => api::value_text(column(COLUMN_NAME))?.to_owned(),
+    };
+    if name.is_empty() || name == "/" {
+        return Err(Error::new_message("zip: an entry's name can't be empty"));
+    }
+    let is_dir = name.ends_with('/');
+    let data: Rc<[u8]> = if api::value_is_null(column(COLUMN_DATA)) {
+        Rc::from(&[][..])
+    } else {
+        Rc::from(api::value_blob(column(COLUMN_DATA)))
+    };
+    if is_dir && !data.is_empty() {
+        return Err(Error::new_message(format!(
+            "zip: {} is a directory, so it can't have data",
+            name
+        )));
+    }
+    let mode = match api::value_type(column(COLUMN_MODE)) {
+        ValueType::Null if is_dir => 0o755,
+        ValueType::Null => 0o644,
+        _ => api::value_int64(column(COLUMN_MODE)) as u32 & 0o777,
+    } | if is_dir { S_IFDIR } else { S_IFREG };
+    let mtime = match api::value_type(column(COLUMN_MTIME)) {
+        ValueType::Null => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_secs() as i64)
+            .unwrap_or_default(),
+        _ => api::value_int64(column(COLUMN_MTIME)),
+    };
+    // checked now rather than when the archive is written
+    zip_time(mtime)?;
+    let method = match api::value_type(column(COLUMN_METHOD)) {
+        ValueType::Null if is_dir => METHOD_STORED,
+        ValueType::Null => METHOD_DEFLATED,
+        _ => match api::value_int64(column(COLUMN_METHOD)) {
+            0 => METHOD_STORED,
+            8 => METHOD_DEFLATED,
+            other => {
+                return Err(Error::new_message(format!(
+                    "zip: method {} isn't supported, only 0 (stored) and 8 (deflated) are",
+                    other
+                )))
+            }
+        },
+    };
+    Ok(Entry {
+        name,
+        mode,
+        mtime,
+        size: data.len() as u64,
+        method,
+        data: EntryData::New(data),
+    })
+}
+
+impl<'vtab> VTabWriteable<'vtab> for ZipfileTable {
+    fn update(&'vtab mut self, operation: UpdateOperation, _p_rowid: *mut i64) -> Result<()> {
+        let mut archive = self.archive.borrow_mut();
+        match operation {
+            UpdateOperation::Delete(name) => {
+                if let Some(i) = archive.position(api::value_text(name)?) {
+                    archive.entries.remove(i);
+                }
+            }
+            UpdateOperation::Insert {
+                values,
+                on_conflict,
+                ..
+            } => {
+                let entry = new_entry(values)?;
+                match archive.position(&entry.name) {
+                    Some(i) if on_conflict == ConflictMode::Replace => archive.entries[i] = entry,
+                    Some(_) => return Err(Error::constraint("UNIQUE constraint failed: name")),
+                    None => archive.entries.push(entry),
+                }
+            }
+            UpdateOperation::Update {
+                rowid,
+                values,
+                on_conflict,
+                ..
+            } => {
+                let entry = new_entry(values)?;
+                let old_name = api::value_text(rowid)?;
+                if entry.name != old_name {
+                    match archive.position(&entry.name) {
+                        Some(i) if on_conflict == ConflictMode::Replace => {
+                            archive.entries.remove(i);
+                        }
+                        Some(_) => return Err(Error::constraint("UNIQUE constraint failed: name")),
+                        None => (),
+                    }
+                }
+                match archive.position(old_name) {
+                    Some(i) => archive.entries[i] = entry,
+                    None => archive.entries.push(entry),
+                }
+            }
+        }
+        archive.changed = true;
+        Ok(())
+    }
+}
+
+impl<'vtab> VTabWriteableWithTransactions<'vtab> for ZipfileTable {
+    fn begin(&'vtab mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn sync(&'vtab mut self) -> Result<()> {
+        let mut archive = self.archive.borrow_mut();
+        if archive.changed {
+            archive.write(&self.path)?;
+        }
+        Ok(())
+    }
+
+    fn commit(&'vtab mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn rollback(&'vtab mut self) -> Result<()> {
+        // back to what's on disk
+        let archive = Archive::open(&self.path)?;
+        *self.archive.borrow_mut() = archive;
+        Ok(())
+    }
+}
+
+const PLAN_SCAN: c_int = 0;
+/// The `zipfile(file)` argument is given.
+const PLAN_FILE: c_int = 1;
+/// `name = ?` on a `zipfile_table`.
+const PLAN_NAME: c_int = 2;
+
+#[repr(C)]
+pub struct ZipfileCursor {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    archive: Option<Rc<RefCell<Archive>>>,
+    /// The entries as of the scan's start.
+    entries: Vec<Entry>,
+    index: usize,
+}
+
+impl ZipfileCursor {
+    fn new(archive: Option<Rc<RefCell<Archive>>>) -> ZipfileCursor {
+        let base: sqlite3_vtab_cursor = unsafe { std::mem::zeroed() };
+        ZipfileCursor {
+            base,
+            archive,
+            entries: vec![],
+            index: 0,
+        }
+    }
+}
+
+impl VTabCursor for ZipfileCursor {
+    fn filter(
+        &mut self,
+        idx_num: c_int,
+        _idx_str: Option<&str>,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        if idx_num == PLAN_FILE {
+            self.archive = Some(Rc::new(RefCell::new(Archive::from_value(&values[0])?)));
+        }
+        let archive = self
+            .archive
+            .as_ref()
+            .ok_or_else(|| Error::new_message("zip: no archive to read"))?
+            .borrow();
+        self.entries = match (idx_num, values.first()) {
+            (PLAN_NAME, Some(name)) => {
+                let name = api::value_text(name)?;
+                archive
+                    .entries
+                    .iter()
+                    .filter(|entry| entry.name == name)
+                    .cloned()
+                    .collect()
+            }
+            _ => archive.entries.clone(),
+        };
+        self.index = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.index += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.index >= self.entries.len()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, i: c_int) -> Result<()> {
+        let entry = self
+            .entries
+            .get(self.index)
+            .ok_or_else(|| Error::new_message("zip: no current entry"))?;
+        match i {
+            COLUMN_NAME => api::result_text(context, &entry.name)?,
+            COLUMN_MODE => api::result_int64(context, entry.mode as i64),
+            COLUMN_MTIME => api::result_int64(context, entry.mtime),
+            COLUMN_SZ => api::result_int64(context, entry.size as i64),
+            COLUMN_DATA if entry.is_dir() => api::result_null(context),
+            COLUMN_DATA => {
+                let archive = self
+                    .archive
+                    .as_ref()
+                    .ok_or_else(|| Error::new_message("zip: no archive to read"))?;
+                let data = archive.borrow_mut().data(entry)?;
+                api::result_blob(context, &data);
+            }
+            COLUMN_METHOD => api::result_int64(context, entry.method as i64),
+            _ => api::result_null(context),
+        }
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.index as i64)
+    }
+}
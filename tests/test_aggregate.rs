@@ -0,0 +1,288 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    aggregate::AggregateContext, api, define_aggregate_function, define_window_function,
+    ext::sqlite3ext_create_function_v2, Aggregate, Error, Result, WindowAggregate,
+};
+use std::os::raw::c_int;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The middle value, or the mean of the two middle values.
+#[derive(Default)]
+struct Median(Vec<f64>);
+
+impl Aggregate for Median {
+    fn step(&mut self, values: &[*mut sqlite3_value]) -> Result<()> {
+        if !api::value_is_null(&values[0]) {
+            self.0.push(api::value_double(&values[0]));
+        }
+        Ok(())
+    }
+
+    fn fnl(mut self, context: *mut sqlite3_context) -> Result<()> {
+        self.0.sort_by(f64::total_cmp);
+        let n = self.0.len();
+        if n == 0 {
+            api::result_null(context);
+        } else if n % 2 == 1 {
+            api::result_double(context, self.0[n / 2]);
+        } else {
+            api::result_double(context, (self.0[n / 2 - 1] + self.0[n / 2]) / 2.0);
+        }
+        Ok(())
+    }
+}
+
+/// percentile(value, p), the nearest-rank percentile, p from 0 to 100.
+#[derive(Default)]
+struct Percentile {
+    values: Vec<f64>,
+    p: Option<f64>,
+}
+
+impl Aggregate for Percentile {
+    fn step(&mut self, values: &[*mut sqlite3_value]) -> Result<()> {
+        let p = api::value_double(&values[1]);
+        if !(0.0..=100.0).contains(&p) {
+            return Err(Error::new_message(format!(
+                "percentile {} isn't between 0 and 100",
+                p
+            )));
+        }
+        self.p = Some(p);
+        if !api::value_is_null(&values[0]) {
+            self.values.push(api::value_double(&values[0]));
+        }
+        Ok(())
+    }
+
+    fn fnl(mut self, context: *mut sqlite3_context) -> Result<()> {
+        match self.p {
+            Some(p) if !self.values.is_empty() => {
+                self.values.sort_by(f64::total_cmp);
+                let rank = (p / 100.0 * self.values.len() as f64).ceil() as usize;
+                api::result_double(context, self.values[rank.max(1) - 1]);
+            }
+            _ => api::result_null(context),
+        }
+        Ok(())
+    }
+}
+
+/// moving_avg(value), the mean of the non-NULL values in the window, kept
+/// as a running sum so each row is only added and removed once.
+#[derive(Default)]
+struct MovingAvg {
+    sum: f64,
+    count: i64,
+}
+
+impl Aggregate for MovingAvg {
+    fn step(&mut self, values: &[*mut sqlite3_value]) -> Result<()> {
+        if !api::value_is_null(&values[0]) {
+            self.sum += api::value_double(&values[0]);
+            self.count += 1;
+        }
+        Ok(())
+    }
+
+    fn fnl(self, context: *mut sqlite3_context) -> Result<()> {
+        self.value(context)
+    }
+}
+
+impl WindowAggregate for MovingAvg {
+    fn value(&self, context: *mut sqlite3_context) -> Result<()> {
+        if self.count == 0 {
+            api::result_null(context);
+        } else {
+            api::result_double(context, self.sum / self.count as f64);
+        }
+        Ok(())
+    }
+
+    fn inverse(&mut self, values: &[*mut sqlite3_value]) -> Result<()> {
+        if !api::value_is_null(&values[0]) {
+            self.sum -= api::value_double(&values[0]);
+            self.count -= 1;
+        }
+        Ok(())
+    }
+}
+
+/// How many `Tally`s were dropped.
+static TALLIES_DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+/// The state of tally(), a count of rows, kept with raw callbacks.
+#[derive(Default)]
+struct Tally(i64);
+
+impl Drop for Tally {
+    fn drop(&mut self) {
+        TALLIES_DROPPED.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+unsafe extern "C" fn tally_step(
+    context: *mut sqlite3_context,
+    _argc: c_int,
+    _argv: *mut *mut sqlite3_value,
+) {
+    match AggregateContext::<Tally>::new(context).get_mut() {
+        Ok(tally) => tally.0 += 1,
+        Err(err) => {
+            let _ = api::result_error(context, &err.reported_message());
+        }
+    }
+}
+
+unsafe extern "C" fn tally_final(context: *mut sqlite3_context) {
+    let tally = AggregateContext::<Tally>::new(context).take();
+    api::result_int64(context, tally.0);
+    // taking it again doesn't drop it twice
+    assert_eq!(AggregateContext::<Tally>::new(context).take().0, 0);
+}
+
+/// Defines tally() with raw callbacks.
+fn define_tally(db: *mut sqlite3, flags: FunctionFlags) -> Result<()> {
+    let name = std::ffi::CString::new("tally")?;
+    let rc = unsafe {
+        sqlite3ext_create_function_v2(
+            db,
+            name.as_ptr(),
+            0,
+            flags.bits(),
+            std::ptr::null_mut(),
+            None,
+            Some(tally_step),
+            Some(tally_final),
+            None,
+        )
+    };
+    if rc != 0 {
+        return Err(Error::new_message("couldn't define tally()"));
+    }
+    Ok(())
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_aggregate_init(db: *mut sqlite3) -> Result<()> {
+    let flags = FunctionFlags::UTF8 | FunctionFlags::DETERMINISTIC;
+    define_aggregate_function::<Median>(db, "median", 1, flags)?;
+    define_aggregate_function::<Percentile>(db, "percentile", 2, flags)?;
+    define_window_function::<MovingAvg>(db, "moving_avg", 1, flags)?;
+    define_tally(db, flags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, types::Value, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_aggregate_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "
+            create table t(k text, v real);
+            insert into t values ('a', 1), ('a', 5), ('a', 3), ('b', 2), ('b', 4), ('b', null);
+            ",
+        )
+        .unwrap();
+        let value = |sql: &str| db.query_row(sql, [], |row| row.get::<_, Value>(0)).unwrap();
+
+        assert_eq!(value("select median(v) from t"), Value::Real(3.0));
+        assert_eq!(value("select percentile(v, 80) from t"), Value::Real(4.0));
+        assert_eq!(value("select percentile(v, 0) from t"), Value::Real(1.0));
+
+        // each group gets its own state
+        let mut stmt = db
+            .prepare("select k, median(v), percentile(v, 100) from t group by k order by k")
+            .unwrap();
+        let rows: Vec<(String, f64, f64)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+        assert_eq!(
+            rows,
+            vec![("a".to_owned(), 3.0, 5.0), ("b".to_owned(), 3.0, 4.0)]
+        );
+
+        // without rows there are no steps, so the final call gets the default
+        assert_eq!(value("select median(v) from t where 0"), Value::Null);
+        assert_eq!(
+            value("select percentile(v, 50) from t where 0"),
+            Value::Null
+        );
+
+        // as a window function, rows leave the window through inverse()
+        let averages = |sql: &str| -> Vec<Option<f64>> {
+            let mut stmt = db.prepare(sql).unwrap();
+            let rows = stmt.query_map([], |row| row.get(0)).unwrap();
+            rows.map(|row| row.unwrap()).collect()
+        };
+        assert_eq!(
+            averages(
+                "select moving_avg(v) over (order by rowid rows between 1 preceding and current row) from t"
+            ),
+            vec![
+                Some(1.0),
+                Some(3.0),
+                Some(4.0),
+                Some(2.5),
+                Some(3.0),
+                Some(4.0)
+            ]
+        );
+        assert_eq!(
+            averages(
+                "select moving_avg(v) over (partition by k order by rowid rows between unbounded preceding and current row) from t order by rowid"
+            ),
+            vec![
+                Some(1.0),
+                Some(3.0),
+                Some(3.0),
+                Some(2.0),
+                Some(3.0),
+                Some(3.0)
+            ]
+        );
+        // and it's still an aggregate
+        assert_eq!(value("select moving_avg(v) from t"), Value::Real(3.0));
+        assert_eq!(value("select moving_avg(v) from t where 0"), Value::Null);
+        // aggregates without inverse() can't slide
+        assert!(db
+            .prepare("select median(v) over (rows between 1 preceding and current row) from t")
+            .is_err());
+
+        // raw callbacks get the same state handling, with the state dropped
+        // once for each group, and once more for each default
+        let dropped = TALLIES_DROPPED.load(Ordering::SeqCst);
+        let mut stmt = db
+            .prepare("select tally() from t group by k order by k")
+            .unwrap();
+        let tallies: Vec<i64> = stmt
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+        assert_eq!(tallies, vec![3, 3]);
+        assert_eq!(TALLIES_DROPPED.load(Ordering::SeqCst) - dropped, 4);
+        assert_eq!(value("select tally() from t where 0"), Value::Integer(0));
+        assert_eq!(TALLIES_DROPPED.load(Ordering::SeqCst) - dropped, 6);
+
+        let err = db
+            .query_row("select percentile(v, 101) from t", [], |_| Ok(()))
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "percentile 101 isn't between 0 and 100");
+    }
+}
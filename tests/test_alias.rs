@@ -0,0 +1,109 @@
+use sqlite_loadable::alias::register_alias;
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{api, define_scalar_function, Error, Result};
+
+#[sqlite_entrypoint]
+pub fn sqlite3_alias_init(db: *mut sqlite3) -> Result<()> {
+    let flags = FunctionFlags::UTF8 | FunctionFlags::DETERMINISTIC;
+    define_scalar_function(
+        db,
+        "xyz_join",
+        -1,
+        |_context, values| {
+            if values.is_empty() {
+                return Err(Error::new_message("xyz_join() needs an argument"));
+            }
+            let parts = values
+                .iter()
+                .map(|value| api::value_text(value).map(str::to_owned))
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            Ok(parts.join("-"))
+        },
+        flags,
+    )?;
+    register_alias(db, "xyz_concat", "xyz_join", flags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi, types::Value, Connection};
+    use std::ffi::CStr;
+    use std::os::raw::{c_char, c_int, c_void};
+    use std::sync::Mutex;
+
+    static LOG: Mutex<Vec<(c_int, String)>> = Mutex::new(Vec::new());
+
+    unsafe extern "C" fn log_callback(_arg: *mut c_void, code: c_int, message: *const c_char) {
+        let message = CStr::from_ptr(message).to_string_lossy().into_owned();
+        LOG.lock().unwrap().push((code, message));
+    }
+
+    // the only test here, since the log has to be set up before SQLite is
+    // initialized
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            let callback: unsafe extern "C" fn(*mut c_void, c_int, *const c_char) = log_callback;
+            assert_eq!(
+                ffi::sqlite3_config(
+                    ffi::SQLITE_CONFIG_LOG,
+                    callback,
+                    std::ptr::null_mut::<c_void>()
+                ),
+                ffi::SQLITE_OK
+            );
+            ffi::sqlite3_auto_extension(Some(std::mem::transmute::<
+                *const (),
+                unsafe extern "C" fn(),
+            >(sqlite3_alias_init as *const ())));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        let value = |sql: &str| db.query_row(sql, [], |row| row.get::<_, Value>(0));
+        let deprecations = || {
+            LOG.lock()
+                .unwrap()
+                .iter()
+                .filter(|(code, message)| {
+                    *code == ffi::SQLITE_WARNING && message.contains("deprecated")
+                })
+                .map(|(_, message)| message.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            value("select xyz_concat('a', 'b', 'c')").unwrap(),
+            Value::Text("a-b-c".to_owned())
+        );
+        assert_eq!(
+            value("select xyz_concat('a')").unwrap(),
+            Value::Text("a".to_owned())
+        );
+        assert_eq!(
+            deprecations(),
+            vec!["xyz_concat() is deprecated and will be removed, use xyz_join() instead"]
+        );
+
+        // the new function's errors come through as they are
+        let err = value("select xyz_concat()").unwrap_err();
+        assert!(
+            err.to_string().contains("xyz_join() needs an argument"),
+            "{}",
+            err
+        );
+
+        // deterministic, like xyz_join, so it works in an index
+        db.execute_batch(
+            "create table t(a, b);
+             create index t_ab on t(xyz_concat(a, b));
+             insert into t values ('x', 'y');",
+        )
+        .unwrap();
+        assert_eq!(
+            value("select a from t where xyz_concat(a, b) = 'x-y'").unwrap(),
+            Value::Text("x".to_owned())
+        );
+        assert_eq!(deprecations().len(), 1);
+    }
+}
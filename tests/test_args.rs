@@ -0,0 +1,400 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api::{self, Overflow},
+    define_scalar_function, define_scalar_function_variadic, Args, Arity, Error, Result,
+};
+
+/// repeat(text, count [, separator])
+pub fn repeat(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let (text, count, separator): (&str, i32, Option<&str>) = Args::new(values).get()?;
+    api::result_text(
+        context,
+        vec![text; count.max(0) as usize].join(separator.unwrap_or("")),
+    )
+}
+
+/// describe(real, bytes, flag, owned)
+pub fn describe(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let (real, bytes, flag, owned): (f64, Vec<u8>, bool, Option<String>) =
+        Args::new(values).get()?;
+    api::result_text(
+        context,
+        format!("{} {:?} {} {:?}", real, bytes, flag, owned),
+    )
+}
+
+pub fn second(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let args = Args::new(values);
+    let value: Option<i64> = args.get_at(1)?;
+    match value {
+        Some(value) => api::result_int64(context, value),
+        None => api::result_null(context),
+    }
+    Ok(())
+}
+
+/// half(integer), NULL for NULL
+pub fn half(_context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<Option<f64>> {
+    let (i,): (Option<i64>,) = Args::new(values).get()?;
+    Ok(i.map(|i| i as f64 / 2.0))
+}
+
+/// tagged(text), as a JSON object
+pub fn tagged(
+    _context: *mut sqlite3_context,
+    values: &[*mut sqlite3_value],
+) -> Result<serde_json::Value> {
+    let (tag,): (&str,) = Args::new(values).get()?;
+    Ok(serde_json::json!({ "tag": tag }))
+}
+
+/// nth_char(text, index), the character at a 0-based index
+pub fn nth_char(
+    _context: *mut sqlite3_context,
+    values: &[*mut sqlite3_value],
+) -> Result<Option<String>> {
+    let (text, index): (&str, usize) = Args::new(values).get()?;
+    Ok(text.chars().nth(index).map(String::from))
+}
+
+fn overflow(values: &[*mut sqlite3_value]) -> Result<Overflow> {
+    match api::value_text(&values[1])? {
+        "error" => Ok(Overflow::Error),
+        "clamp" => Ok(Overflow::Clamp),
+        "wrap" => Ok(Overflow::Wrap),
+        policy => Err(Error::new_message(format!("unknown policy {}", policy))),
+    }
+}
+
+/// as_i32(value, policy), as_u64(value, policy), as_usize(value, policy),
+/// the value converted by api's checked conversions, as text so u64s past
+/// i64::MAX come back whole
+pub fn as_i32(_context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<String> {
+    Ok(api::value_int_checked(&values[0], overflow(values)?)?.to_string())
+}
+
+pub fn as_u64(_context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<String> {
+    Ok(api::value_u64(&values[0], overflow(values)?)?.to_string())
+}
+
+pub fn as_usize(_context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<String> {
+    Ok(api::value_usize(&values[0], overflow(values)?)?.to_string())
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_args_init(db: *mut sqlite3) -> Result<()> {
+    define_scalar_function(db, "repeat", -1, repeat, FunctionFlags::UTF8)?;
+    define_scalar_function(db, "describe", 4, describe, FunctionFlags::UTF8)?;
+    define_scalar_function(db, "second", -1, second, FunctionFlags::UTF8)?;
+    define_scalar_function(db, "half", 1, half, FunctionFlags::UTF8)?;
+    define_scalar_function(
+        db,
+        "tagged",
+        1,
+        tagged,
+        FunctionFlags::UTF8 | FunctionFlags::RESULT_SUBTYPE,
+    )?;
+    define_scalar_function(db, "tagged_undeclared", 1, tagged, FunctionFlags::UTF8)?;
+    define_scalar_function(db, "nth_char", 2, nth_char, FunctionFlags::UTF8)?;
+    define_scalar_function(db, "as_i32", 2, as_i32, FunctionFlags::UTF8)?;
+    define_scalar_function(db, "as_u64", 2, as_u64, FunctionFlags::UTF8)?;
+    define_scalar_function(db, "as_usize", 2, as_usize, FunctionFlags::UTF8)?;
+    define_scalar_function(
+        db,
+        "shout",
+        1,
+        |_context, values| {
+            let (text,): (String,) = Args::new(values).get()?;
+            Ok(text.to_uppercase())
+        },
+        FunctionFlags::UTF8,
+    )?;
+    define_scalar_function(
+        db,
+        "is_even",
+        1,
+        |_context, values| Ok(Args::new(values).get_at::<i64>(0)? % 2 == 0),
+        FunctionFlags::UTF8,
+    )?;
+    define_scalar_function(
+        db,
+        "bytes_of",
+        1,
+        |_context, values| Args::new(values).get_at::<Vec<u8>>(0),
+        FunctionFlags::UTF8,
+    )?;
+    define_scalar_function(
+        db,
+        "duration_ms",
+        1,
+        |_context, values| {
+            let (duration,): (std::time::Duration,) = Args::new(values).get()?;
+            Ok(duration.as_millis() as i64)
+        },
+        FunctionFlags::UTF8,
+    )?;
+    define_scalar_function(
+        db,
+        "max_u64",
+        0,
+        |_context, _values| Ok(u64::MAX),
+        FunctionFlags::UTF8,
+    )?;
+    define_scalar_function_variadic(
+        db,
+        "join_with",
+        Arity::at_least(2),
+        |_context, values| {
+            let separator = api::value_text(&values[0])?;
+            let parts = values[1..]
+                .iter()
+                .map(api::value_text)
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            Ok(parts.join(separator))
+        },
+        FunctionFlags::UTF8,
+    )?;
+    define_scalar_function_variadic(
+        db,
+        "pad",
+        Arity::between(2, 3),
+        |_context, values| {
+            let (text, width, fill): (&str, i64, Option<&str>) = Args::new(values).get()?;
+            let fill = fill.unwrap_or(" ");
+            let missing = (width.max(0) as usize).saturating_sub(text.chars().count());
+            Ok(format!("{}{}", fill.repeat(missing), text))
+        },
+        FunctionFlags::UTF8,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, types::Value, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_args_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        let value = |sql: &str| db.query_row(sql, [], |row| row.get::<_, Value>(0));
+        let error = |sql: &str| value(sql).unwrap_err().to_string();
+        let text = |s: &str| Value::Text(s.to_owned());
+
+        assert_eq!(value("select repeat('ab', 3)").unwrap(), text("ababab"));
+        assert_eq!(
+            value("select repeat('ab', 3, '-')").unwrap(),
+            text("ab-ab-ab")
+        );
+        assert_eq!(value("select repeat('ab', 2, null)").unwrap(), text("abab"));
+        assert_eq!(
+            value("select describe(1, 'hi', 1, null)").unwrap(),
+            text("1 [104, 105] true None")
+        );
+        assert_eq!(
+            value("select describe(1.5, x'00ff', 0, 'x')").unwrap(),
+            text("1.5 [0, 255] false Some(\"x\")")
+        );
+        assert_eq!(
+            value("select describe(1, 'hi', 'Yes', null)").unwrap(),
+            text("1 [104, 105] true None")
+        );
+        assert_eq!(
+            value("select describe(1, 'hi', 'off', null)").unwrap(),
+            text("1 [104, 105] false None")
+        );
+        assert_eq!(value("select second(1)").unwrap(), Value::Null);
+        assert_eq!(value("select second(1, 2)").unwrap(), Value::Integer(2));
+
+        assert_eq!(
+            error("select repeat('ab', 'three')"),
+            "argument 2: expected an integer, got text"
+        );
+        assert_eq!(
+            error("select repeat(null, 3)"),
+            "argument 1: expected text, got NULL"
+        );
+        assert_eq!(
+            error("select repeat('ab', 3000000000)"),
+            "argument 2: 3000000000 is out of range for a 32-bit integer"
+        );
+        assert_eq!(error("select repeat('ab')"), "argument 2 is missing");
+        assert_eq!(
+            error("select repeat('ab', 1, '', 4)"),
+            "expected at most 3 arguments, got 4"
+        );
+        assert_eq!(
+            error("select describe('1.5', x'', 0, null)"),
+            "argument 1: expected a real, got text"
+        );
+        assert_eq!(
+            error("select describe(1.5, 2, 0, null)"),
+            "argument 2: expected a blob, got an integer"
+        );
+        assert_eq!(
+            error("select describe(1, x'', 'maybe', null)"),
+            "argument 3: expected a boolean (0, 1, true, false, on, off, yes or no), got 'maybe'"
+        );
+        assert_eq!(
+            error("select describe(1, x'', 2, null)"),
+            "argument 3: expected a boolean (0, 1, true, false, on, off, yes or no), got 2"
+        );
+        assert_eq!(
+            error("select second(1, 2.5)"),
+            "argument 2: expected an integer, got a real"
+        );
+
+        // return values
+        assert_eq!(value("select half(3)").unwrap(), Value::Real(1.5));
+        assert_eq!(value("select half(null)").unwrap(), Value::Null);
+        assert_eq!(value("select shout('hi')").unwrap(), text("HI"));
+        assert_eq!(value("select is_even(4)").unwrap(), Value::Integer(1));
+        assert_eq!(value("select is_even(3)").unwrap(), Value::Integer(0));
+        assert_eq!(
+            value("select bytes_of('ab')").unwrap(),
+            Value::Blob(b"ab".to_vec())
+        );
+        assert_eq!(value("select tagged('x')").unwrap(), text(r#"{"tag":"x"}"#));
+        // with the JSON subtype, so it nests as JSON rather than a string
+        assert_eq!(
+            value("select json_array(tagged('x'))").unwrap(),
+            text(r#"[{"tag":"x"}]"#)
+        );
+        assert_eq!(
+            value("select duration_ms(90)").unwrap(),
+            Value::Integer(90000)
+        );
+        assert_eq!(
+            value("select duration_ms(0.25)").unwrap(),
+            Value::Integer(250)
+        );
+        assert_eq!(
+            value("select duration_ms('2h30m')").unwrap(),
+            Value::Integer(9000000)
+        );
+        assert_eq!(
+            value("select duration_ms('PT1.5S')").unwrap(),
+            Value::Integer(1500)
+        );
+        assert_eq!(
+            error("select duration_ms(-1)"),
+            "argument 1: invalid duration -1, expected a non-negative number of seconds"
+        );
+        assert_eq!(
+            error("select duration_ms('5 minutes')"),
+            "argument 1: invalid duration '5 minutes', expected seconds or a duration like '5m', '2h30m' or 'PT1H30M'"
+        );
+        assert_eq!(
+            error("select duration_ms(null)"),
+            "argument 1: expected a duration, got NULL"
+        );
+        // debug builds check the subtype against the function's flags
+        assert_eq!(
+            error("select tagged_undeclared('x')"),
+            "function set the result subtype 'J' but wasn't defined with FunctionFlags::RESULT_SUBTYPE"
+        );
+        assert_eq!(
+            error("select max_u64()"),
+            "18446744073709551615 is out of range for an integer"
+        );
+        assert_eq!(
+            error("select half('x')"),
+            "argument 1: expected an integer, got text"
+        );
+
+        // unsigned arguments are checked, not truncated
+        assert_eq!(value("select nth_char('héllo', 1)").unwrap(), text("é"));
+        assert_eq!(value("select nth_char('héllo', 9)").unwrap(), Value::Null);
+        assert_eq!(
+            error("select nth_char('héllo', -1)"),
+            "argument 2: -1 is out of range for a size"
+        );
+
+        // with an explicit policy for values that don't fit
+        let cases = [
+            ("as_i32(3000000000, 'clamp')", "2147483647"),
+            ("as_i32(-3000000000, 'clamp')", "-2147483648"),
+            ("as_i32(4294967297, 'wrap')", "1"),
+            ("as_i32(-5, 'error')", "-5"),
+            ("as_u64(-1, 'clamp')", "0"),
+            ("as_u64(-1, 'wrap')", "18446744073709551615"),
+            (
+                "as_u64(9223372036854775807, 'error')",
+                "9223372036854775807",
+            ),
+            ("as_usize(-2, 'clamp')", "0"),
+            ("as_usize(42, 'error')", "42"),
+        ];
+        for (call, expected) in cases {
+            assert_eq!(
+                value(&format!("select {}", call)).unwrap(),
+                text(expected),
+                "{}",
+                call
+            );
+        }
+        assert_eq!(
+            error("select as_i32(3000000000, 'error')"),
+            "3000000000 is out of range for a 32-bit integer"
+        );
+        assert_eq!(
+            error("select as_u64(-1, 'error')"),
+            "-1 is out of range for an unsigned integer"
+        );
+
+        // variadic functions have their argument count checked first
+        assert_eq!(
+            value("select join_with('-', 'a', 'b', 'c')").unwrap(),
+            text("a-b-c")
+        );
+        assert_eq!(value("select join_with('-', 'a')").unwrap(), text("a"));
+        assert_eq!(
+            error("select join_with('-')"),
+            "join_with() takes at least 2 arguments, got 1"
+        );
+        assert_eq!(value("select pad('7', 3)").unwrap(), text("  7"));
+        assert_eq!(value("select pad('7', 3, '0')").unwrap(), text("007"));
+        assert_eq!(
+            error("select pad('7')"),
+            "pad() takes 2 or 3 arguments, got 1"
+        );
+        assert_eq!(
+            error("select pad('7', 3, '0', 'x')"),
+            "pad() takes 2 or 3 arguments, got 4"
+        );
+    }
+
+    #[test]
+    fn test_arity() {
+        assert_eq!(Arity::exactly(2).num_args(), 2);
+        assert_eq!(Arity::between(1, 3).num_args(), -1);
+        assert_eq!(Arity::any().num_args(), -1);
+        assert!(Arity::any().check("f", 0).is_ok());
+        assert_eq!(
+            Arity::exactly(1)
+                .check("f", 2)
+                .unwrap_err()
+                .result_error_message(),
+            "f() takes exactly 1 argument, got 2"
+        );
+        assert_eq!(
+            Arity::between(1, 4)
+                .check("f", 0)
+                .unwrap_err()
+                .result_error_message(),
+            "f() takes 1 to 4 arguments, got 0"
+        );
+        assert!(Arity::between(3, 2).validate().is_err());
+        assert!(Arity::between(0, 128).validate().is_err());
+        assert!(Arity::at_least(128).validate().is_err());
+        assert!(Arity::between(0, 127).validate().is_ok());
+    }
+}
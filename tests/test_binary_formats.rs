@@ -0,0 +1,107 @@
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+use sqlite_loadable::prelude::*;
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+use sqlite_loadable::{api, define_scalar_function, Error, Result};
+
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+fn json_arg(values: &[*mut sqlite3_value]) -> Result<serde_json::Value> {
+    api::value_json(values.first().expect("1st argument as JSON"))
+        .map_err(|err| Error::new_message(err.to_string()))
+}
+
+#[cfg(feature = "msgpack")]
+pub fn to_msgpack(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    api::result_msgpack(context, &json_arg(values)?)
+}
+
+#[cfg(feature = "msgpack")]
+pub fn from_msgpack(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let value: serde_json::Value = api::value_msgpack(values.first().expect("1st argument"))?;
+    api::result_json(context, value)
+}
+
+#[cfg(feature = "cbor")]
+pub fn to_cbor(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    api::result_cbor(context, &json_arg(values)?)
+}
+
+#[cfg(feature = "cbor")]
+pub fn from_cbor(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let value: serde_json::Value = api::value_cbor(values.first().expect("1st argument"))?;
+    api::result_json(context, value)
+}
+
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+#[sqlite_entrypoint]
+pub fn sqlite3_binaryformats_init(db: *mut sqlite3) -> Result<()> {
+    let flags = FunctionFlags::UTF8 | FunctionFlags::DETERMINISTIC | FunctionFlags::RESULT_SUBTYPE;
+    #[cfg(feature = "msgpack")]
+    {
+        define_scalar_function(db, "to_msgpack", 1, to_msgpack, flags)?;
+        define_scalar_function(db, "from_msgpack", 1, from_msgpack, flags)?;
+    }
+    #[cfg(feature = "cbor")]
+    {
+        define_scalar_function(db, "to_cbor", 1, to_cbor, flags)?;
+        define_scalar_function(db, "from_cbor", 1, from_cbor, flags)?;
+    }
+    Ok(())
+}
+
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    fn connection() -> Connection {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_binaryformats_init as *const (),
+                ),
+            ));
+        }
+        Connection::open_in_memory().unwrap()
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn test_msgpack() {
+        let conn = connection();
+        let (blob, json): (Vec<u8>, String) = conn
+            .query_row(
+                "select to_msgpack('{\"a\":1}'), from_msgpack(to_msgpack('[1,\"two\",null,{\"x\":1.5}]'))",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        // fixmap of 1, fixstr "a", positive fixint 1
+        assert_eq!(blob, vec![0x81, 0xa1, b'a', 0x01]);
+        assert_eq!(json, r#"[1,"two",null,{"x":1.5}]"#);
+        assert!(conn
+            .query_row("select from_msgpack(x'c1')", [], |row| row
+                .get::<_, String>(0))
+            .is_err());
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn test_cbor() {
+        let conn = connection();
+        let (blob, json): (Vec<u8>, String) = conn
+            .query_row(
+                "select to_cbor('{\"a\":1}'), from_cbor(to_cbor('[1,\"two\",null,{\"x\":1.5}]'))",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        // map of 1, text "a", unsigned 1
+        assert_eq!(blob, vec![0xa1, 0x61, b'a', 0x01]);
+        assert_eq!(json, r#"[1,"two",null,{"x":1.5}]"#);
+        assert!(conn
+            .query_row("select from_cbor(x'ff')", [], |row| row.get::<_, String>(0))
+            .is_err());
+    }
+}
@@ -0,0 +1,100 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api,
+    blob::{value_chunks, BlobChunks},
+    define_scalar_function, Result,
+};
+
+const CHUNK_SIZE: usize = 7;
+
+fn fnv1a(hash: u64, chunk: &[u8]) -> u64 {
+    chunk.iter().fold(hash, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// stored_checksum(table, column, rowid) hashes a stored blob, reading it
+/// 7 bytes at a time.
+pub fn stored_checksum(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let table = api::value_text(&values[0])?;
+    let column = api::value_text(&values[1])?;
+    let rowid = api::value_int64(&values[2]);
+    let chunks = BlobChunks::open(
+        api::context_db_handle(context),
+        "main",
+        table,
+        column,
+        rowid,
+        CHUNK_SIZE,
+    )?;
+    let mut hash = 0xcbf29ce484222325;
+    chunks.for_each(|chunk| {
+        assert!(chunk.len() <= CHUNK_SIZE);
+        hash = fnv1a(hash, chunk);
+        Ok(())
+    })?;
+    api::result_int64(context, hash as i64);
+    Ok(())
+}
+
+/// value_checksum(blob) hashes its argument the same way.
+pub fn value_checksum(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let hash = value_chunks(&values[0], CHUNK_SIZE).fold(0xcbf29ce484222325, fnv1a);
+    api::result_int64(context, hash as i64);
+    Ok(())
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_blob_init(db: *mut sqlite3) -> Result<()> {
+    let flags = FunctionFlags::UTF8;
+    define_scalar_function(db, "stored_checksum", 3, stored_checksum, flags)?;
+    define_scalar_function(db, "value_checksum", 1, value_checksum, flags)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_blob_init as *const (),
+                ),
+            ));
+        }
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "
+            create table files(data);
+            insert into files values (randomblob(1000)), (x''), (randomblob(14));
+            ",
+        )
+        .unwrap();
+
+        let matches: bool = conn
+            .query_row(
+                "select count(*) = 3 and sum(stored_checksum('files', 'data', rowid) = value_checksum(data)) = 3 from files",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(matches);
+
+        let err = conn
+            .query_row("select stored_checksum('files', 'data', 99)", [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("could not open blob in main.files.data for rowid 99: no such rowid: 99"),
+            "{}",
+            err
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api,
+    bulk::{define_load_function, insert_many},
+    define_scalar_function, Result,
+};
+
+const COLUMNS: &[&str] = &["id", "name", "price"];
+
+/// items_insert(id, name, price, ...) inserts a row per three arguments.
+pub fn items_insert(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let inserted = insert_many(
+        api::context_db_handle(context),
+        "main",
+        "items",
+        COLUMNS,
+        values.chunks(COLUMNS.len()),
+    )?;
+    api::result_int64(context, inserted as i64);
+    Ok(())
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_bulk_init(db: *mut sqlite3) -> Result<()> {
+    define_load_function(db, "items_load", "main", "items", COLUMNS)?;
+    define_scalar_function(
+        db,
+        "items_insert",
+        -1,
+        items_insert,
+        FunctionFlags::UTF8 | FunctionFlags::DIRECTONLY,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    fn count(conn: &Connection) -> i64 {
+        conn.query_row("select count(*) from items", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_bulk_init as *const (),
+                ),
+            ));
+        }
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "create table items(id integer primary key, name text not null, price real)",
+        )
+        .unwrap();
+
+        let rows: Vec<String> = (1..=1000)
+            .map(|i| format!("[{}, \"item {}\", {}.5]", i, i, i))
+            .collect();
+        let loaded: i64 = conn
+            .query_row(
+                "select items_load(?)",
+                [format!("[{}]", rows.join(","))],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(loaded, 1000);
+        let (sum, name): (f64, String) = conn
+            .query_row(
+                "select sum(price), (select name from items where id = 500) from items",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(sum, 500500.0 + 500.0);
+        assert_eq!(name, "item 500");
+
+        // objects are keyed by column name, with missing columns NULL
+        let loaded: i64 = conn
+            .query_row(
+                r#"select items_load('[{"id": 1001, "name": "object"}, {"name": "no id", "price": true}]')"#,
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(loaded, 2);
+        let (price, typ): (Option<f64>, String) = conn
+            .query_row(
+                "select (select price from items where id = 1001), (select typeof(price) from items where name = 'no id')",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(price, None);
+        assert_eq!(typ, "real");
+
+        // one bad row and nothing is inserted
+        let err = conn
+            .query_row(
+                r#"select items_load('[[2000, "ok", 1], [2001, null, 1]]')"#,
+                [],
+                |row| row.get::<_, i64>(0),
+            )
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("row 2: could not step statement"),
+            "{}",
+            err
+        );
+        let err = conn
+            .query_row(r#"select items_load('[[3000, "short"]]')"#, [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "row 1: a row has 2 values, but 3 columns are inserted"
+        );
+        assert_eq!(count(&conn), 1002);
+
+        let inserted: i64 = conn
+            .query_row(
+                "select items_insert(4000, 'a', 1.0, 4001, 'b', 2.0)",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(inserted, 2);
+        assert!(conn
+            .query_row(
+                "select items_insert(5000, 'a', 1.0, 5001, null, 2.0)",
+                [],
+                |row| row.get::<_, i64>(0)
+            )
+            .is_err());
+        assert_eq!(count(&conn), 1004);
+
+        // inside a write, it joins the outer statement's transaction
+        conn.execute_batch(
+            "create table log(n); insert into log select items_load('[[6000, \"nested\", 0]]')",
+        )
+        .unwrap();
+        assert_eq!(count(&conn), 1005);
+    }
+}
@@ -0,0 +1,158 @@
+use sqlite_loadable::changes::define_changes;
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::Result;
+
+#[sqlite_entrypoint]
+pub fn sqlite3_changes_init(db: *mut sqlite3) -> Result<()> {
+    define_changes(db)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, types::Value, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_changes_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        let rows = |sql: &str| {
+            let mut stmt = db.prepare(sql).unwrap();
+            let rows: Vec<Vec<Value>> = stmt
+                .query_map([], |row| {
+                    (0..row.as_ref().column_count())
+                        .map(|i| row.get::<_, Value>(i))
+                        .collect()
+                })
+                .unwrap()
+                .map(|row| row.unwrap())
+                .collect();
+            rows
+        };
+        let text = |s: &str| Value::Text(s.to_owned());
+
+        db.execute_batch(
+            "create table accounts(id integer primary key, name text, balance integer);
+             insert into accounts values (1, 'alex', 100);",
+        )
+        .unwrap();
+        assert_eq!(
+            rows("select seq, op, schema_name, table_name, row_id, old, new from changes"),
+            vec![vec![
+                Value::Integer(1),
+                text("insert"),
+                text("main"),
+                text("accounts"),
+                Value::Integer(1),
+                Value::Null,
+                Value::Null
+            ]]
+        );
+
+        db.execute_batch("select changes_track('accounts')")
+            .unwrap();
+        db.execute_batch(
+            "insert into accounts values (2, 'brian', 50);
+             update accounts set balance = balance - 10 where id = 1;
+             delete from accounts where id = 2;",
+        )
+        .unwrap();
+        assert_eq!(
+            rows("select seq, op, row_id, old, new from changes(1)"),
+            vec![
+                vec![
+                    Value::Integer(2),
+                    text("insert"),
+                    Value::Integer(2),
+                    Value::Null,
+                    text(r#"{"id":2,"name":"brian","balance":50}"#)
+                ],
+                vec![
+                    Value::Integer(3),
+                    text("update"),
+                    Value::Integer(1),
+                    text(r#"{"id":1,"name":"alex","balance":100}"#),
+                    text(r#"{"id":1,"name":"alex","balance":90}"#)
+                ],
+                vec![
+                    Value::Integer(4),
+                    text("delete"),
+                    Value::Integer(2),
+                    text(r#"{"id":2,"name":"brian","balance":50}"#),
+                    Value::Null
+                ],
+            ]
+        );
+        assert_eq!(
+            rows("select new ->> '$.balance' from changes where seq = 3"),
+            vec![vec![Value::Integer(90)]]
+        );
+
+        // only committed changes are logged
+        db.execute_batch(
+            "begin;
+             insert into accounts values (3, 'carol', 1);
+             rollback;",
+        )
+        .unwrap();
+        db.execute_batch(
+            "begin;
+             insert into accounts values (4, 'dana', 1);
+             update accounts set balance = 2 where id = 4;
+             commit;",
+        )
+        .unwrap();
+        assert_eq!(
+            rows("select seq, op, row_id from changes(4)"),
+            vec![
+                vec![Value::Integer(5), text("insert"), Value::Integer(4)],
+                vec![Value::Integer(6), text("update"), Value::Integer(4)],
+            ]
+        );
+
+        // BLOBs are logged as hex
+        db.execute_batch(
+            "create table files(id integer primary key, data blob);
+             select changes_track('files');
+             insert into files values (1, x'01ff');",
+        )
+        .unwrap();
+        assert_eq!(
+            rows("select new from changes(6)"),
+            vec![vec![text(r#"{"id":1,"data":{"blob":"01FF"}}"#)]]
+        );
+
+        // tracking again picks up new columns
+        db.execute_batch(
+            "alter table files add column size integer;
+             select changes_track('files');
+             update files set size = 2 where id = 1;",
+        )
+        .unwrap();
+        assert_eq!(
+            rows("select old, new from changes(7)"),
+            vec![vec![
+                text(r#"{"id":1,"data":{"blob":"01FF"},"size":null}"#),
+                text(r#"{"id":1,"data":{"blob":"01FF"},"size":2}"#)
+            ]]
+        );
+
+        assert!(db
+            .execute_batch("select changes_track('missing')")
+            .unwrap_err()
+            .to_string()
+            .contains("no table main.missing"));
+        // not from a view
+        db.execute_batch("create view tracks as select changes_track('accounts') as result")
+            .unwrap();
+        assert!(db.execute_batch("select result from tracks").is_err());
+    }
+}
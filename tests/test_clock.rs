@@ -0,0 +1,69 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api,
+    clock::{vfs_current_time_millis, vfs_sleep},
+    define_scalar_function, Result,
+};
+
+/// now_millis(), the VFS's current time in Unix milliseconds.
+pub fn now_millis(context: *mut sqlite3_context, _values: &[*mut sqlite3_value]) -> Result<()> {
+    let now = vfs_current_time_millis(api::context_db_handle(context))?;
+    api::result_int64(context, now);
+    Ok(())
+}
+
+/// vfs_sleep(ms), sleeps and returns how many microseconds were slept.
+pub fn sleep(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let ms = api::value_int64(&values[0]) as u32;
+    let slept = vfs_sleep(api::context_db_handle(context), ms)?;
+    api::result_int64(context, slept.as_micros() as i64);
+    Ok(())
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_clock_init(db: *mut sqlite3) -> Result<()> {
+    define_scalar_function(db, "now_millis", 0, now_millis, FunctionFlags::UTF8)?;
+    define_scalar_function(db, "vfs_sleep", 1, sleep, FunctionFlags::UTF8)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+    use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_clock_init as *const (),
+                ),
+            ));
+        }
+        let conn = Connection::open_in_memory().unwrap();
+
+        let (now, sqlite_now): (i64, f64) = conn
+            .query_row(
+                "select now_millis(), (julianday('now') - 2440587.5) * 86400000",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        let system_now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64;
+        assert!((now - system_now).abs() < 5000, "{} {}", now, system_now);
+        assert!((now as f64 - sqlite_now).abs() < 5000.0);
+
+        let start = Instant::now();
+        let slept: i64 = conn
+            .query_row("select vfs_sleep(20)", [], |row| row.get(0))
+            .unwrap();
+        assert!(slept >= 20_000, "{}", slept);
+        assert!(start.elapsed().as_millis() >= 20);
+    }
+}
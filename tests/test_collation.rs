@@ -1,6 +1,7 @@
 use sqlite_loadable::prelude::*;
-use sqlite_loadable::{define_collation, Result};
+use sqlite_loadable::{define_collation, define_collation_with_aux, on_collation_needed, Result};
 use std::cmp::Ordering;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
 
 fn compare(a: &[u8], b: &[u8]) -> i32 {
     let a: Vec<u8> = a.iter().rev().cloned().collect();
@@ -11,9 +12,72 @@ fn compare(a: &[u8], b: &[u8]) -> i32 {
         Ordering::Greater => 1,
     }
 }
+static COLLATIONS_NEEDED: AtomicUsize = AtomicUsize::new(0);
+
+static FOLDERS_DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+/// A stand-in for a locale's collator: a lookup table that folds bytes
+/// before comparing.
+struct Folder {
+    table: [u8; 256],
+}
+
+impl Folder {
+    fn ascii_case_insensitive() -> Folder {
+        let mut table = [0; 256];
+        for (i, folded) in table.iter_mut().enumerate() {
+            *folded = (i as u8).to_ascii_lowercase();
+        }
+        Folder { table }
+    }
+}
+
+impl Drop for Folder {
+    fn drop(&mut self) {
+        FOLDERS_DROPPED.fetch_add(1, AtomicOrdering::SeqCst);
+    }
+}
+
+fn compare_folded(a: &[u8], b: &[u8], folder: &Folder) -> i32 {
+    let a = a.iter().map(|c| folder.table[*c as usize]);
+    let b = b.iter().map(|c| folder.table[*c as usize]);
+    match a.cmp(b) {
+        Ordering::Less => -1,
+        Ordering::Equal => 0,
+        Ordering::Greater => 1,
+    }
+}
+
 #[sqlite_entrypoint]
 pub fn sqlite3_test_collation_init(db: *mut sqlite3) -> Result<()> {
     define_collation(db, "test_collation", compare)?;
+    // shorter first, then byte by byte
+    define_collation(db, "test_length", |a: &[u8], b: &[u8]| {
+        a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+    })?;
+    define_collation_with_aux(
+        db,
+        "test_folded",
+        compare_folded,
+        Folder::ascii_case_insensitive(),
+    )?;
+    // mod_N compares numbers by their remainder, defined when first used
+    on_collation_needed(db, |db, name| {
+        COLLATIONS_NEEDED.fetch_add(1, AtomicOrdering::SeqCst);
+        let modulus: i64 = match name.strip_prefix("mod_").and_then(|n| n.parse().ok()) {
+            Some(modulus) if modulus > 0 => modulus,
+            _ => return Ok(()),
+        };
+        define_collation(db, name, move |a: &[u8], b: &[u8]| {
+            let remainder = |s: &[u8]| {
+                std::str::from_utf8(s)
+                    .ok()
+                    .and_then(|s| s.parse::<i64>().ok())
+                    .map(|n| n.rem_euclid(modulus))
+            };
+            remainder(a).cmp(&remainder(b)).then_with(|| a.cmp(b))
+        })
+    })?;
     Ok(())
 }
 
@@ -21,7 +85,10 @@ pub fn sqlite3_test_collation_init(db: *mut sqlite3) -> Result<()> {
 mod tests {
     use super::*;
 
-    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+    use rusqlite::{
+        ffi::{sqlite3_auto_extension, sqlite3_create_collation_v2, SQLITE_UTF8},
+        Connection,
+    };
 
     #[test]
     fn test_rusqlite_auto_extension() {
@@ -51,5 +118,57 @@ mod tests {
             .unwrap();
 
         assert_eq!(result, "[\"zzza\",\"yyyb\",\"xxxc\"]");
+
+        let result: String = conn
+            .query_row(
+                "select group_concat(value, ',') from (select value from json_each(?) order by value collate test_length)",
+                [r#"["ccc", "a", "bb", "ab", "b"]"#],
+                |x| x.get(0),
+            )
+            .unwrap();
+        assert_eq!(result, "a,b,ab,bb,ccc");
+
+        let equal: bool = conn
+            .query_row("select 'Hello' = 'hELLO' collate test_folded", [], |x| {
+                x.get(0)
+            })
+            .unwrap();
+        assert!(equal);
+
+        // missing collations are defined on demand, once
+        let needed = COLLATIONS_NEEDED.load(AtomicOrdering::SeqCst);
+        let by_mod = |collation: &str| -> rusqlite::Result<String> {
+            conn.query_row(
+                &format!("select group_concat(value, ',') from (select value from json_each('[\"7\",\"12\",\"5\",\"9\"]') order by value collate {})", collation),
+                [],
+                |x| x.get(0),
+            )
+        };
+        assert_eq!(by_mod("mod_5").unwrap(), "5,12,7,9");
+        assert_eq!(by_mod("mod_5").unwrap(), "5,12,7,9");
+        assert_eq!(by_mod("mod_3").unwrap(), "12,9,7,5");
+        assert_eq!(COLLATIONS_NEEDED.load(AtomicOrdering::SeqCst), needed + 2);
+        let err = by_mod("mod_x").unwrap_err().to_string();
+        assert!(err.contains("no such collation sequence: mod_x"), "{}", err);
+
+        // deleting the collation drops its state
+        let dropped = FOLDERS_DROPPED.load(AtomicOrdering::SeqCst);
+        let rc = unsafe {
+            sqlite3_create_collation_v2(
+                conn.handle(),
+                c"test_folded".as_ptr(),
+                SQLITE_UTF8,
+                std::ptr::null_mut(),
+                None,
+                None,
+            )
+        };
+        assert_eq!(rc, 0);
+        assert_eq!(FOLDERS_DROPPED.load(AtomicOrdering::SeqCst), dropped + 1);
+
+        // and so does closing the connection
+        let conn = Connection::open_in_memory().unwrap();
+        conn.close().unwrap();
+        assert_eq!(FOLDERS_DROPPED.load(AtomicOrdering::SeqCst), dropped + 2);
     }
 }
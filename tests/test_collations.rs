@@ -0,0 +1,95 @@
+#[cfg(feature = "collations")]
+use sqlite_loadable::prelude::*;
+#[cfg(feature = "collations")]
+use sqlite_loadable::{
+    collations::{
+        define_collations, define_locale_collation, define_locale_collations_on_demand, Sensitivity,
+    },
+    Result,
+};
+
+#[cfg(feature = "collations")]
+#[sqlite_entrypoint]
+pub fn sqlite3_collations_init(db: *mut sqlite3) -> Result<()> {
+    define_collations(db)?;
+    define_locale_collation(db, "swedish", "sv", Sensitivity::Case)?;
+    define_locale_collation(db, "german_base", "de", Sensitivity::Base)?;
+    define_locale_collations_on_demand(db)
+}
+
+#[cfg(all(test, feature = "collations"))]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    fn sorted(conn: &Connection, values: &str, collation: &str) -> String {
+        conn.query_row(
+            &format!(
+                "select group_concat(value, ' ') from (select value from json_each(?) order by value collate {})",
+                collation
+            ),
+            [values],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_collations_init as *const (),
+                ),
+            ));
+        }
+        let conn = Connection::open_in_memory().unwrap();
+
+        assert_eq!(
+            sorted(
+                &conn,
+                r#"["img12", "img10", "IMG2", "img1"]"#,
+                "natural_sort"
+            ),
+            "IMG2 img1 img10 img12"
+        );
+        let equal: bool = conn
+            .query_row(
+                "select 'ÄPFEL' = 'äpfel' collate unicode_nocase",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(equal);
+
+        // Swedish sorts å, ä and ö after z
+        assert_eq!(
+            sorted(&conn, r#"["ö", "z", "a", "å", "ä"]"#, "swedish"),
+            "a z å ä ö"
+        );
+        let equal: bool = conn
+            .query_row(
+                "select 'Müller' = 'muller' collate german_base",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(equal);
+
+        // any other locale is defined the first time it's used
+        assert_eq!(
+            sorted(&conn, r#"["ö", "z", "a", "å", "ä"]"#, "icu_sv"),
+            "a z å ä ö"
+        );
+        assert_eq!(
+            sorted(&conn, r#"["ö", "z", "a", "å", "ä"]"#, "icu_de_ch"),
+            "a å ä ö z"
+        );
+        let err = conn
+            .query_row("select 'a' < 'b' collate no_such_locale", [], |_| Ok(()))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("no such collation sequence"), "{}", err);
+    }
+}
@@ -0,0 +1,120 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api,
+    compare::{glob, like, stricmp, strnicmp},
+    define_scalar_function, Result,
+};
+
+/// rs_like(pattern, text [, escape])
+pub fn rs_like(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let escape = match values.get(2) {
+        Some(escape) => api::value_text(escape)?.chars().next(),
+        None => None,
+    };
+    api::result_bool(
+        context,
+        like(
+            api::value_text(&values[0])?,
+            api::value_text(&values[1])?,
+            escape,
+        ),
+    );
+    Ok(())
+}
+
+/// rs_glob(pattern, text)
+pub fn rs_glob(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    api::result_bool(
+        context,
+        glob(api::value_text(&values[0])?, api::value_text(&values[1])?),
+    );
+    Ok(())
+}
+
+/// rs_stricmp(a, b [, n]), -1, 0 or 1.
+pub fn rs_stricmp(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let a = api::value_text(&values[0])?;
+    let b = api::value_text(&values[1])?;
+    let ordering = match values.get(2) {
+        Some(n) => strnicmp(a, b, api::value_int64(n) as usize),
+        None => stricmp(a, b),
+    };
+    api::result_int(context, ordering as i32);
+    Ok(())
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_compare_init(db: *mut sqlite3) -> Result<()> {
+    let flags = FunctionFlags::UTF8 | FunctionFlags::DETERMINISTIC;
+    define_scalar_function(db, "rs_like", 2, rs_like, flags)?;
+    define_scalar_function(db, "rs_like", 3, rs_like, flags)?;
+    define_scalar_function(db, "rs_glob", 2, rs_glob, flags)?;
+    define_scalar_function(db, "rs_stricmp", 2, rs_stricmp, flags)?;
+    define_scalar_function(db, "rs_stricmp", 3, rs_stricmp, flags)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_compare_init as *const (),
+                ),
+            ));
+        }
+        let conn = Connection::open_in_memory().unwrap();
+
+        let cases = [
+            ("a%", "ABC"),
+            ("_b_", "abc"),
+            ("%ß%", "straße"),
+            ("%É%", "é"),
+            ("[a-c]*", "banana"),
+            ("[^a-c]*", "banana"),
+            ("*[]]*", "a]b"),
+            ("?", "é"),
+            ("a\\%", "a%"),
+            ("", ""),
+        ];
+        for (pattern, text) in cases {
+            let (rs, builtin): (bool, bool) = conn
+                .query_row(
+                    "select rs_like(?1, ?2), like(?1, ?2)",
+                    [pattern, text],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .unwrap();
+            assert_eq!(rs, builtin, "{} like {}", text, pattern);
+            let (rs, builtin): (bool, bool) = conn
+                .query_row(
+                    "select rs_glob(?1, ?2), glob(?1, ?2)",
+                    [pattern, text],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .unwrap();
+            assert_eq!(rs, builtin, "{} glob {}", text, pattern);
+            let (rs, builtin): (bool, bool) = conn
+                .query_row(
+                    "select rs_like(?1, ?2, '\\'), like(?1, ?2, '\\')",
+                    [pattern, text],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .unwrap();
+            assert_eq!(rs, builtin, "{} like {} escape \\", text, pattern);
+        }
+
+        let cmp = |sql: &str| -> i32 { conn.query_row(sql, [], |row| row.get(0)).unwrap() };
+        assert_eq!(cmp("select rs_stricmp('Hello', 'hELLO')"), 0);
+        assert_eq!(cmp("select rs_stricmp('a', 'B')"), -1);
+        assert_eq!(cmp("select rs_stricmp('É', 'é')"), -1);
+        assert_eq!(cmp("select rs_stricmp('abcd', 'ABCE', 3)"), 0);
+        assert_eq!(cmp("select rs_stricmp('abcd', 'ABCE', 4)"), -1);
+    }
+}
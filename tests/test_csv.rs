@@ -0,0 +1,57 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{csv, define_scalar_function, Result};
+
+pub fn csv_row(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    csv::result_csv_row(context, values)
+}
+
+pub fn tsv_row(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    csv::result_tsv_row(context, values)
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_csv_init(db: *mut sqlite3) -> Result<()> {
+    let flags = FunctionFlags::UTF8 | FunctionFlags::DETERMINISTIC;
+    define_scalar_function(db, "csv_row", -1, csv_row, flags)?;
+    define_scalar_function(db, "tsv_row", -1, tsv_row, flags)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_csv_init as *const (),
+                ),
+            ));
+        }
+
+        let conn = Connection::open_in_memory().unwrap();
+
+        let result: String = conn
+            .query_row(
+                "select csv_row(1, 1.5, null, 'a,b', 'say \"hi\"', 'two' || char(10) || 'lines', x'6869')",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(
+            result,
+            "1,1.5,,\"a,b\",\"say \"\"hi\"\"\",\"two\nlines\",hi"
+        );
+
+        let result: String = conn
+            .query_row("select tsv_row('a,b', 'c' || char(9) || 'd')", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(result, "a,b\t\"c\td\"");
+    }
+}
@@ -0,0 +1,195 @@
+use serde_json::{json, Value};
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api,
+    cursor_debug::{define_cursor_debug, DebugState, Debugged},
+    define_table_function,
+    table::{IndexInfo, VTab, VTabArguments, VTabCursor},
+    BestIndexError, Result,
+};
+use std::os::raw::c_int;
+
+const ROWS: i64 = 10;
+const PAGE_SIZE: i64 = 4;
+
+/// paged, the numbers 1 to 10, fetched four at a time like pages of a
+/// remote API.
+#[repr(C)]
+pub struct PagedTable {
+    /// must be first
+    base: sqlite3_vtab,
+    name: String,
+}
+
+impl<'vtab> VTab<'vtab> for PagedTable {
+    type Aux = ();
+    type Cursor = Debugged<PagedCursor>;
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        args: VTabArguments,
+    ) -> Result<(String, PagedTable)> {
+        let base: sqlite3_vtab = unsafe { std::mem::zeroed() };
+        Ok((
+            "CREATE TABLE x(value INTEGER)".to_owned(),
+            PagedTable {
+                base,
+                name: args.table_name,
+            },
+        ))
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        info.set_estimated_cost(ROWS as f64);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<Debugged<PagedCursor>> {
+        let base: sqlite3_vtab_cursor = unsafe { std::mem::zeroed() };
+        let cursor = PagedCursor {
+            base,
+            page: Vec::new(),
+            pages_fetched: 0,
+            index: 0,
+        };
+        Ok(Debugged::new(cursor, "paged", &self.name))
+    }
+}
+
+#[repr(C)]
+pub struct PagedCursor {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    page: Vec<i64>,
+    pages_fetched: i64,
+    index: i64,
+}
+
+impl PagedCursor {
+    fn fetch(&mut self) {
+        let start = self.pages_fetched * PAGE_SIZE + 1;
+        self.page = (start..=ROWS.min(start + PAGE_SIZE - 1)).rev().collect();
+        self.pages_fetched += 1;
+    }
+}
+
+impl DebugState for PagedCursor {
+    fn debug_state(&self) -> Value {
+        json!({
+            "index": self.index,
+            "pages_fetched": self.pages_fetched,
+            "buffered": self.page.len(),
+        })
+    }
+}
+
+impl VTabCursor for PagedCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.pages_fetched = 0;
+        self.index = 0;
+        self.fetch();
+        self.page.pop();
+        self.index = 1;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        if self.page.is_empty() && self.index < ROWS {
+            self.fetch();
+        }
+        self.page.pop();
+        self.index += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.index > ROWS
+    }
+
+    fn column(&self, context: *mut sqlite3_context, _i: c_int) -> Result<()> {
+        api::result_int64(context, self.index);
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.index)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_cursordebug_init(db: *mut sqlite3) -> Result<()> {
+    define_table_function::<PagedTable>(db, "paged", None)?;
+    define_cursor_debug(db, "paged")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_cursordebug_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        let debug = |sql: &str| -> Vec<Value> {
+            let mut stmt = db.prepare(sql).unwrap();
+            let rows = stmt.query_map([], |row| row.get::<_, String>(0)).unwrap();
+            rows.map(|row| serde_json::from_str(&row.unwrap()).unwrap())
+                .collect()
+        };
+
+        // no cursors are open between queries
+        assert_eq!(debug("select paged_cursor_debug()"), vec![json!([])]);
+
+        // during a scan, its cursor's state follows it row by row
+        let states = debug("select paged_cursor_debug() from paged");
+        assert_eq!(states.len(), 10);
+        let state = |row: usize| states[row][0]["state"].clone();
+        assert_eq!(
+            state(0),
+            json!({"index": 1, "pages_fetched": 1, "buffered": 3})
+        );
+        assert_eq!(
+            state(4),
+            json!({"index": 5, "pages_fetched": 2, "buffered": 3})
+        );
+        assert_eq!(
+            state(9),
+            json!({"index": 10, "pages_fetched": 3, "buffered": 0})
+        );
+        assert_eq!(states[0][0]["table"], json!("paged"));
+        assert_eq!(states[0][0]["eof"], json!(false));
+
+        // each open cursor is listed separately
+        let states = debug(
+            "select paged_cursor_debug() from paged a join paged b where a.value = 2 and b.value = 3",
+        );
+        assert_eq!(states.len(), 1);
+        let cursors = states[0].as_array().unwrap();
+        assert_eq!(cursors.len(), 2);
+        assert_ne!(cursors[0]["cursor"], cursors[1]["cursor"]);
+
+        // and dropped once it's closed
+        assert_eq!(debug("select paged_cursor_debug()"), vec![json!([])]);
+
+        // only in direct calls, not triggers or views
+        db.execute_batch("create view v as select paged_cursor_debug()")
+            .unwrap();
+        assert!(db
+            .query_row("select * from v", [], |row| row.get::<_, String>(0))
+            .is_err());
+    }
+}
@@ -0,0 +1,91 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{api, database::Database, define_scalar_function, Error, Result};
+
+/// worker_count(table) counts the rows of a table on a background thread,
+/// with its own connection to the database.
+pub fn worker_count(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let table = api::value_text(values.first().expect("1st argument as table name"))?.to_owned();
+    let worker = Database::open_same_file(api::context_db_handle(context))?;
+    let count = std::thread::spawn(move || {
+        let conn = unsafe {
+            rusqlite::Connection::from_handle(worker.handle().cast::<rusqlite::ffi::sqlite3>())
+        }
+        .unwrap();
+        let count: i64 = conn
+            .query_row(&format!("select count(*) from \"{}\"", table), [], |row| {
+                row.get(0)
+            })
+            .map_err(|err| Error::new_message(err.to_string()))?;
+        let readonly = conn.is_readonly(rusqlite::DatabaseName::Main).unwrap();
+        drop(conn);
+        drop(worker);
+        Ok::<_, Error>(if readonly { -count } else { count })
+    })
+    .join()
+    .expect("worker thread")?;
+    api::result_int64(context, count);
+    Ok(())
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_database_init(db: *mut sqlite3) -> Result<()> {
+    define_scalar_function(db, "worker_count", 1, worker_count, FunctionFlags::UTF8)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection, OpenFlags};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_database_init as *const (),
+                ),
+            ));
+        }
+        let path = std::env::temp_dir().join(format!(
+            "sqlite-loadable-test-database-{}.db",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "
+            create table t(x);
+            insert into t values (1), (2), (3);
+            ",
+        )
+        .unwrap();
+        let count: i64 = conn
+            .query_row("select worker_count('t')", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 3);
+
+        // the worker is read-only when the host connection is
+        let readonly =
+            Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY).unwrap();
+        let count: i64 = readonly
+            .query_row("select worker_count('t')", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, -3);
+
+        let memory = Connection::open_in_memory().unwrap();
+        let err = memory
+            .query_row("select worker_count('t')", [], |row| row.get::<_, i64>(0))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "cannot open another connection to an in-memory or temporary database"
+        );
+
+        drop(conn);
+        drop(readonly);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
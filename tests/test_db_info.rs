@@ -0,0 +1,119 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{api, define_scalar_function, Result};
+
+pub fn db_names(context: *mut sqlite3_context, _values: &[*mut sqlite3_value]) -> Result<()> {
+    let names = api::db_names(api::context_db_handle(context))?;
+    api::result_json(context, names.into())
+}
+
+pub fn db_filename(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let schema = api::value_text(values.first().expect("1st argument as schema"))?;
+    match api::db_filename(api::context_db_handle(context), schema)? {
+        Some(filename) => api::result_text(context, filename)?,
+        None => api::result_null(context),
+    }
+    Ok(())
+}
+
+pub fn db_readonly(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let schema = api::value_text(values.first().expect("1st argument as schema"))?;
+    api::result_bool(
+        context,
+        api::db_readonly(api::context_db_handle(context), schema)?,
+    );
+    Ok(())
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_dbinfo_init(db: *mut sqlite3) -> Result<()> {
+    let flags = FunctionFlags::UTF8;
+    define_scalar_function(
+        db,
+        "db_names",
+        0,
+        db_names,
+        flags | FunctionFlags::RESULT_SUBTYPE,
+    )?;
+    define_scalar_function(db, "db_filename", 1, db_filename, flags)?;
+    define_scalar_function(db, "db_readonly", 1, db_readonly, flags)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_dbinfo_init as *const (),
+                ),
+            ));
+        }
+        let path = std::env::temp_dir().join(format!(
+            "sqlite-loadable-test-db-info-{}.db",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            "attach database ? as other",
+            [format!("file:{}?mode=rwc", path.display())],
+        )
+        .unwrap();
+        conn.execute("attach database 'file:ro?mode=memory' as ro", [])
+            .unwrap();
+
+        let names: String = conn
+            .query_row("select db_names()", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(names, r#"["main","temp","other","ro"]"#);
+
+        let filenames: (Option<String>, Option<String>, Option<String>) = conn
+            .query_row(
+                "select db_filename('main'), db_filename('other'), db_filename('temp')",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(filenames.0, None);
+        // before anything opens the temp database
+        assert_eq!(filenames.2, None);
+        assert_eq!(
+            std::fs::canonicalize(filenames.1.unwrap()).unwrap(),
+            std::fs::canonicalize(&path).unwrap()
+        );
+
+        let readonly: bool = conn
+            .query_row("select db_readonly('main')", [], |row| row.get(0))
+            .unwrap();
+        assert!(!readonly);
+
+        let err = conn
+            .query_row("select db_readonly('missing')", [], |row| {
+                row.get::<_, bool>(0)
+            })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "no such database: missing");
+        let err = conn
+            .query_row("select db_filename('missing')", [], |row| {
+                row.get::<_, Option<String>>(0)
+            })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "no such database: missing");
+
+        conn.execute("create temp table t(x)", []).unwrap();
+        let temp: Option<String> = conn
+            .query_row("select db_filename('temp')", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(temp, None);
+
+        drop(conn);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,111 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    define_virtual_table,
+    table::{BestIndexError, IndexInfo, VTab, VTabArguments, VTabCursor},
+    Error, Result,
+};
+
+use std::{mem, os::raw::c_int};
+
+#[repr(C)]
+pub struct BadSchemaTable {
+    /// must be first
+    base: sqlite3_vtab,
+}
+
+impl<'vtab> VTab<'vtab> for BadSchemaTable {
+    type Aux = ();
+    type Cursor = BadSchemaCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        args: VTabArguments,
+    ) -> Result<(String, BadSchemaTable)> {
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        // the schema is given as the vtab's argument, ex `using bad_schema(x(a,, b))`
+        let columns = args.arguments.join(",");
+        Ok((format!("CREATE TABLE {}", columns), BadSchemaTable { base }))
+    }
+    fn destroy(&self) -> Result<()> {
+        Ok(())
+    }
+    fn best_index(&self, _info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        Ok(())
+    }
+    fn open(&mut self) -> Result<BadSchemaCursor> {
+        Err(Error::new_message("unreachable"))
+    }
+}
+
+#[repr(C)]
+pub struct BadSchemaCursor {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+}
+
+impl VTabCursor for BadSchemaCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        Ok(())
+    }
+    fn next(&mut self) -> Result<()> {
+        Ok(())
+    }
+    fn eof(&self) -> bool {
+        true
+    }
+    fn column(&self, _context: *mut sqlite3_context, _i: c_int) -> Result<()> {
+        Ok(())
+    }
+    fn rowid(&self) -> Result<i64> {
+        Ok(0)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_badschema_init(db: *mut sqlite3) -> Result<()> {
+    define_virtual_table::<BadSchemaTable>(db, "bad_schema", None)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_badschema_init as *const (),
+                ),
+            ));
+        }
+
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("create virtual table ok using bad_schema(x(a, b))", [])
+            .unwrap();
+
+        let err = conn
+            .execute(
+                "create virtual table t using bad_schema(x(a, b default '100%' check))",
+                [],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "declare_vtab failed: near \")\": syntax error\n  {}\n  {}^ offset 40",
+                "CREATE TABLE x(a, b default '100%' check)",
+                " ".repeat(40)
+            )
+        );
+    }
+}
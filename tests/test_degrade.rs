@@ -0,0 +1,243 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api, define_virtual_table,
+    degrade::{self, define_scan_status, Degrading, FailurePolicy},
+    table::{IndexInfo, VTab, VTabArguments, VTabCursor},
+    vtab_argparse::{parse_argument, Argument, ConfigOptionValue},
+    BestIndexError, Error, Result,
+};
+use std::os::raw::c_int;
+
+/// flaky(rows=N, fail_at=K), a table of the numbers 1 to N whose backend
+/// fails on the way to row K, like a remote API that drops the connection.
+#[repr(C)]
+pub struct FlakyTable {
+    /// must be first
+    base: sqlite3_vtab,
+    db: *mut sqlite3,
+    name: String,
+    rows: i64,
+    fail_at: i64,
+    policy: FailurePolicy,
+}
+
+impl<'vtab> VTab<'vtab> for FlakyTable {
+    type Aux = ();
+    type Cursor = Degrading<FlakyCursor>;
+
+    fn connect(
+        db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        mut args: VTabArguments,
+    ) -> Result<(String, FlakyTable)> {
+        let policy = FailurePolicy::take_from(&mut args.arguments)?;
+        let (mut rows, mut fail_at) = (0, 0);
+        for argument in &args.arguments {
+            let option = match parse_argument(argument) {
+                Ok(Argument::Config(option)) => option,
+                _ => return Err(Error::new_message(format!("unknown argument {}", argument))),
+            };
+            let value = match &option.value {
+                ConfigOptionValue::Bareword(value) => value.parse::<i64>().ok(),
+                _ => None,
+            }
+            .ok_or_else(|| Error::new_message(format!("{} must be a number", option.key)))?;
+            match option.key.as_str() {
+                "rows" => rows = value,
+                "fail_at" => fail_at = value,
+                key => return Err(Error::new_message(format!("unknown option {}", key))),
+            }
+        }
+        let base: sqlite3_vtab = unsafe { std::mem::zeroed() };
+        Ok((
+            "CREATE TABLE x(value INTEGER, label TEXT)".to_owned(),
+            FlakyTable {
+                base,
+                db,
+                name: args.table_name,
+                rows,
+                fail_at,
+                policy,
+            },
+        ))
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        info.set_estimated_cost(self.rows as f64);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<Degrading<FlakyCursor>> {
+        let base: sqlite3_vtab_cursor = unsafe { std::mem::zeroed() };
+        let cursor = FlakyCursor {
+            base,
+            rows: self.rows,
+            fail_at: self.fail_at,
+            value: 0,
+        };
+        Ok(Degrading::new(cursor, self.db, &self.name, self.policy))
+    }
+}
+
+#[repr(C)]
+pub struct FlakyCursor {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    rows: i64,
+    fail_at: i64,
+    value: i64,
+}
+
+impl VTabCursor for FlakyCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.value = 0;
+        self.next()
+    }
+
+    fn next(&mut self) -> Result<()> {
+        if self.value + 1 == self.fail_at {
+            return Err(Error::new_message("connection reset by peer"));
+        }
+        self.value += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.value > self.rows
+    }
+
+    fn column(&self, context: *mut sqlite3_context, i: c_int) -> Result<()> {
+        match i {
+            0 => api::result_int64(context, self.value),
+            _ => api::result_text(context, format!("row {}", self.value))?,
+        }
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.value)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_degrade_init(db: *mut sqlite3) -> Result<()> {
+    define_virtual_table::<FlakyTable>(db, "flaky", None)?;
+    define_scan_status(db)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, types::Value, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_degrade_init as *const (),
+                ),
+            ));
+        }
+        degrade::reset();
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "
+            create virtual table healthy using flaky(rows=5, on_error=truncate);
+            create virtual table strict using flaky(rows=5, fail_at=3);
+            create virtual table partial using flaky(rows=5, fail_at=4, on_error=truncate);
+            create virtual table filled using flaky(rows=5, fail_at=3, on_error=null);
+            create virtual table down using flaky(rows=5, fail_at=1, on_error=truncate);
+            ",
+        )
+        .unwrap();
+        let rows = |sql: &str| -> Vec<Vec<Value>> {
+            let mut stmt = db.prepare(sql).unwrap();
+            let columns = stmt.column_count();
+            let rows = stmt
+                .query_map([], |row| {
+                    (0..columns).map(|i| row.get::<_, Value>(i)).collect()
+                })
+                .unwrap();
+            rows.map(|row| row.unwrap()).collect()
+        };
+        let values = |table: &str| -> Vec<Value> {
+            rows(&format!("select value from {}", table))
+                .into_iter()
+                .map(|mut row| row.remove(0))
+                .collect()
+        };
+
+        // scans that don't fail aren't affected, or recorded
+        assert_eq!(values("healthy").len(), 5);
+        assert!(rows("select * from scan_status").is_empty());
+
+        // by default a failure fails the query
+        let err = db
+            .query_row("select count(*) from strict", [], |_| Ok(()))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "connection reset by peer");
+
+        // truncate keeps the rows returned so far
+        assert_eq!(
+            values("partial"),
+            vec![Value::Integer(1), Value::Integer(2), Value::Integer(3)]
+        );
+
+        // null ends with a row of NULLs in every column
+        assert_eq!(
+            rows("select value, label from filled"),
+            vec![
+                vec![Value::Integer(1), Value::Text("row 1".to_owned())],
+                vec![Value::Integer(2), Value::Text("row 2".to_owned())],
+                vec![Value::Null, Value::Null],
+            ]
+        );
+
+        // a backend that fails in xFilter gives an empty scan
+        assert!(values("down").is_empty());
+
+        assert_eq!(
+            rows("select \"table\", policy, rows, error from scan_status"),
+            [
+                ("strict", "error", 2),
+                ("partial", "truncate", 3),
+                ("filled", "null", 2),
+                ("down", "truncate", 0),
+            ]
+            .iter()
+            .map(|(table, policy, count)| vec![
+                Value::Text(table.to_string()),
+                Value::Text(policy.to_string()),
+                Value::Integer(*count),
+                Value::Text("connection reset by peer".to_owned()),
+            ])
+            .collect::<Vec<_>>()
+        );
+        let recorded: i64 = db
+            .query_row(
+                "select count(*) from scan_status where failed_at > 0",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(recorded, 4);
+
+        let err = db
+            .execute_batch("create virtual table bad using flaky(rows=1, on_error=retry)")
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("on_error must be error, truncate or null, not retry"),
+            "{}",
+            err
+        );
+        assert_eq!(degrade::reset(), 4);
+    }
+}
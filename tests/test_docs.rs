@@ -0,0 +1,83 @@
+use sqlite_loadable::docs::{self, define_info, FunctionDoc};
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{api, define_scalar_function, Result};
+
+#[sqlite_entrypoint]
+pub fn sqlite3_docs_init(db: *mut sqlite3) -> Result<()> {
+    define_scalar_function(
+        db,
+        "xyz_repeat",
+        2,
+        |_context, values| {
+            let text = api::value_text(&values[0])?;
+            Ok(text.repeat(api::value_int64(&values[1]).max(0) as usize))
+        },
+        FunctionFlags::UTF8 | FunctionFlags::DETERMINISTIC,
+    )?;
+    docs::document(
+        FunctionDoc::new("xyz_repeat", "Repeats text a number of times.")
+            .arg("text", "The text to repeat.")
+            .arg("count", "How many times, 0 if negative."),
+    );
+    docs::document(FunctionDoc::new("xyz_version", "The extension's version."));
+    define_info(db, "xyz")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, types::Value, Connection};
+
+    #[test]
+    fn test_function_doc() {
+        let doc = FunctionDoc::new("hist", "Summarizes values.")
+            .arg("value", "A number.")
+            .optional_arg("name", "A histogram to merge into.");
+        assert_eq!(doc.signature(), "hist(value [, name])");
+        assert_eq!(
+            doc.help(),
+            "hist(value [, name])\n\nSummarizes values.\n\n  value  A number.\n  name   A histogram to merge into."
+        );
+        assert_eq!(
+            FunctionDoc::new("f", "")
+                .optional_arg("a", "")
+                .optional_arg("b", "")
+                .signature(),
+            "f([a [, b]])"
+        );
+        assert_eq!(FunctionDoc::new("f", "Does f.").help(), "f()\n\nDoes f.");
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_docs_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        let value = |sql: &str| db.query_row(sql, [], |row| row.get::<_, Value>(0));
+
+        assert_eq!(
+            value("select group_concat(signature, ' ') from xyz_info").unwrap(),
+            Value::Text("xyz_repeat(text, count) xyz_version()".to_owned())
+        );
+        assert_eq!(
+            value("select args ->> '$[1].name' from xyz_info where name = 'xyz_repeat'").unwrap(),
+            Value::Text("count".to_owned())
+        );
+        assert_eq!(
+            value("select xyz_help('xyz_version')").unwrap(),
+            Value::Text("xyz_version()\n\nThe extension's version.".to_owned())
+        );
+        assert_eq!(
+            value("select instr(xyz_help(), 'xyz_version()') > 0").unwrap(),
+            Value::Integer(1)
+        );
+        assert!(value("select xyz_help('nope')").is_err());
+        assert_eq!(docs::get("xyz_repeat").unwrap().args.len(), 2);
+    }
+}
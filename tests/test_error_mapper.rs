@@ -0,0 +1,65 @@
+use sqlite_loadable::errors::{clear_error_mapper, set_error_mapper};
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::record::{define_record_function, FieldType, RecordSchema};
+use sqlite_loadable::{api, define_scalar_function, Error, ErrorKind, Result};
+
+pub fn fail(_context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let message = api::value_text(&values[0])?;
+    Err(Error::new_message(message))
+}
+
+pub fn point(values: &[*mut sqlite3_value]) -> Result<serde_json::Value> {
+    Ok(serde_json::json!({ "x": api::value_int64(&values[0]) }))
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_errormapper_init(db: *mut sqlite3) -> Result<()> {
+    define_scalar_function(db, "fail", 1, fail, FunctionFlags::UTF8)?;
+    let schema = RecordSchema::new().field("x", FieldType::Integer);
+    define_record_function(db, "point", 1, schema, point, FunctionFlags::UTF8)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_errormapper_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        let error = |sql: &str| {
+            db.query_row(sql, [], |row| row.get::<_, i64>(0))
+                .unwrap_err()
+                .to_string()
+        };
+
+        assert_eq!(error("select fail('disk on fire')"), "disk on fire");
+
+        set_error_mapper(|kind, message| match kind {
+            ErrorKind::Message(_) => format!("[acme] {}", message),
+            _ => message,
+        });
+        assert_eq!(error("select fail('disk on fire')"), "[acme] disk on fire");
+        // virtual table errors too
+        assert_eq!(
+            error("select x from point_unpack('100%')"),
+            "[acme] the argument isn't a JSON object"
+        );
+        assert_eq!(error("select fail('100%')"), "[acme] 100%");
+        // a new mapper replaces the last one
+        set_error_mapper(|_kind, message| message.replace("isn't", "n'est pas"));
+        assert_eq!(error("select fail('it isn''t')"), "it n'est pas");
+
+        clear_error_mapper();
+        assert_eq!(error("select fail('disk on fire')"), "disk on fire");
+    }
+}
@@ -0,0 +1,65 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{api, define_scalar_function, Result};
+
+pub fn noop(context: *mut sqlite3_context, _values: &[*mut sqlite3_value]) -> Result<()> {
+    api::result_null(context);
+    Ok(())
+}
+
+/// define_error() returns the error from registering a function with more
+/// arguments than SQLite allows.
+pub fn define_error(context: *mut sqlite3_context, _values: &[*mut sqlite3_value]) -> Result<()> {
+    let db = api::context_db_handle(context);
+    let err = define_scalar_function(db, "too_many", 1000, noop, FunctionFlags::UTF8).unwrap_err();
+    let details = err.sqlite_message().expect("errmsg is captured").clone();
+    assert_eq!(details.offset, None);
+    assert_eq!(details.code, 21);
+    // redefining a function while a statement is running is recorded on the
+    // connection, unlike the misuse above
+    let err = define_scalar_function(db, "define_error", 0, noop, FunctionFlags::UTF8).unwrap_err();
+    let busy = err.sqlite_message().expect("errmsg is captured").clone();
+    assert_eq!(busy.code, 5);
+    api::result_text(
+        context,
+        format!(
+            "{} | {} | {}",
+            details.message,
+            busy.message,
+            err.result_error_message()
+        ),
+    )
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_errors_init(db: *mut sqlite3) -> Result<()> {
+    define_scalar_function(db, "define_error", 0, define_error, FunctionFlags::UTF8)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_errors_init as *const (),
+                ),
+            ));
+        }
+        let conn = Connection::open_in_memory().unwrap();
+        let message: String = conn
+            .query_row("select define_error()", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(
+            message,
+            "bad parameter or other API misuse \
+            | unable to delete/modify user-function due to active statements \
+            | Error defining scalar function: unable to delete/modify user-function due to active statements"
+        );
+    }
+}
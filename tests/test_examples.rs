@@ -0,0 +1,155 @@
+#[cfg(feature = "testing")]
+#[cfg(test)]
+mod tests {
+    use rusqlite::{Connection, LoadExtensionGuard};
+    use sqlite_loadable::load_test::{
+        cli_available, node_available, python_available, CompiledExtension, SmokeCheck,
+    };
+
+    fn example(name: &str) -> CompiledExtension {
+        CompiledExtension::build_example(env!("CARGO_MANIFEST_DIR"), name).unwrap()
+    }
+
+    fn load(extension: &CompiledExtension) -> Connection {
+        let db = Connection::open_in_memory().unwrap();
+        unsafe {
+            let _guard = LoadExtensionGuard::new(&db).unwrap();
+            db.load_extension(extension.path(), extension.entrypoint())
+                .unwrap();
+        }
+        db
+    }
+
+    fn text(db: &Connection, sql: &str) -> String {
+        db.query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    fn int(db: &Connection, sql: &str) -> i64 {
+        db.query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn test_hello() {
+        let db = load(&example("hello"));
+        assert_eq!(text(&db, "select hello('world')"), "hello, world!");
+        assert_eq!(text(&db, "select hello(1234)"), "hello, 1234!");
+    }
+
+    #[test]
+    fn test_scalar() {
+        let db = load(&example("scalar").with_entrypoint("sqlite3_scalarrs_init"));
+        assert_eq!(text(&db, "select surround_rs('alex')"), "xalexx");
+        assert_eq!(text(&db, "select yo_rs()"), "yo");
+        assert_eq!(int(&db, "select add_rs(1, 2)"), 3);
+        assert_eq!(text(&db, "select connect('-', 'a', 'b', 'c')"), "a-b-c");
+    }
+
+    #[test]
+    fn test_series() {
+        let db = load(&example("series").with_entrypoint("sqlite3_seriesrs_init"));
+        assert_eq!(
+            int(&db, "select sum(value) from generate_series_rs(1, 100)"),
+            5050
+        );
+    }
+
+    #[test]
+    fn test_characters() {
+        let db = load(&example("characters"));
+        assert_eq!(
+            text(
+                &db,
+                "select group_concat(value, '|') from characters('abc')"
+            ),
+            "a|b|c"
+        );
+    }
+
+    #[test]
+    fn test_load_permanent() {
+        let extension = example("load_permanent").with_entrypoint("sqlite3_hello_init");
+        let db = load(&extension);
+        assert_eq!(text(&db, "select hello('world')"), "hello, world!");
+    }
+
+    #[test]
+    fn test_cli() {
+        if !cli_available() {
+            eprintln!("no sqlite3 CLI, skipping");
+            return;
+        }
+        let hello = example("hello");
+        assert_eq!(
+            hello
+                .run_cli("select hello('world');\nselect hello('alex');")
+                .unwrap(),
+            "hello, world!\nhello, alex!\n"
+        );
+        let err = hello.run_cli("select nope();").unwrap_err();
+        assert!(err
+            .result_error_message()
+            .contains("no such function: nope"));
+
+        let series = example("series").with_entrypoint("sqlite3_seriesrs_init");
+        assert_eq!(
+            series
+                .run_cli("select count(*) from generate_series_rs(1, 10);")
+                .unwrap(),
+            "10\n"
+        );
+    }
+
+    /// Examples and what they should return, in every host.
+    fn smoke_checks() -> Vec<(CompiledExtension, Vec<SmokeCheck>)> {
+        vec![
+            (
+                example("hello"),
+                vec![
+                    SmokeCheck::new("select hello('world')", "hello, world!"),
+                    SmokeCheck::new("select hello(1234)", "hello, 1234!"),
+                ],
+            ),
+            (
+                example("series").with_entrypoint("sqlite3_seriesrs_init"),
+                vec![SmokeCheck::new(
+                    "select sum(value) from generate_series_rs(1, 100)",
+                    5050,
+                )],
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_python() {
+        if !python_available() {
+            eprintln!("no python3 that can load extensions, skipping");
+            return;
+        }
+        for (extension, checks) in smoke_checks() {
+            extension.run_python(&checks).unwrap();
+        }
+        let err = example("hello")
+            .run_python(&[SmokeCheck::new("select hello('world')", "hi")])
+            .unwrap_err();
+        assert!(err
+            .result_error_message()
+            .contains("expected 'hi', got 'hello, world!'"));
+    }
+
+    #[test]
+    fn test_node() {
+        if !node_available() {
+            eprintln!("no node with better-sqlite3, skipping");
+            return;
+        }
+        for (extension, checks) in smoke_checks() {
+            extension.run_node(&checks).unwrap();
+        }
+        let err = example("hello")
+            .run_node(&[SmokeCheck::new("select hello('world')", "hi")])
+            .unwrap_err();
+        assert!(err
+            .result_error_message()
+            .contains(r#"expected "hi", got "hello, world!""#));
+    }
+}
@@ -1,7 +1,7 @@
 #[cfg(feature = "exec")]
 use sqlite_loadable::prelude::*;
 #[cfg(feature = "exec")]
-use sqlite_loadable::{api, define_scalar_function, Result};
+use sqlite_loadable::{api, define_scalar_function, Error, Result};
 
 #[cfg(feature = "exec")]
 use sqlite_loadable::exec;
@@ -19,11 +19,60 @@ pub fn t_values(context: *mut sqlite3_context, _values: &[*mut sqlite3_value]) -
     Ok(())
 }
 
+/// t_prepare_no_vtab(sql), the first column of sql's first row, prepared
+/// without access to virtual tables.
+#[cfg(feature = "exec")]
+pub fn t_prepare_no_vtab(
+    context: *mut sqlite3_context,
+    values: &[*mut sqlite3_value],
+) -> Result<()> {
+    let flags = exec::PrepareFlags::PERSISTENT | exec::PrepareFlags::NO_VTAB;
+    let mut stmt = exec::Statement::prepare_with_flags(
+        api::context_db_handle(context),
+        api::value_text(&values[0])?,
+        flags,
+    )
+    // prepare's errors are this crate's, with SQLite's message attached
+    .map_err(|err| *err.downcast::<Error>().unwrap())?;
+    let value = stmt
+        .execute()
+        .next()
+        .unwrap()
+        .unwrap()
+        .get::<i64>(0)
+        .unwrap();
+    api::result_int64(context, value);
+    Ok(())
+}
+
+/// t_normalized(sql), sql normalized by SQLite, or NULL if it can't.
+#[cfg(feature = "exec")]
+pub fn t_normalized(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let stmt = exec::Statement::prepare(
+        api::context_db_handle(context),
+        api::value_text(&values[0])?,
+    )
+    .map_err(|err| Error::new_message(err.to_string()))?;
+    match stmt.normalized_sql() {
+        Some(sql) => api::result_text(context, sql)?,
+        None => api::result_null(context),
+    }
+    Ok(())
+}
+
 #[cfg(feature = "exec")]
 #[sqlite_entrypoint]
 pub fn sqlite3_exec_init(db: *mut sqlite3) -> Result<()> {
     let flags = FunctionFlags::UTF8 | FunctionFlags::DETERMINISTIC;
-    define_scalar_function(db, "t_values", 0, t_values, flags)?;
+    define_scalar_function(
+        db,
+        "t_values",
+        0,
+        t_values,
+        flags | FunctionFlags::RESULT_SUBTYPE,
+    )?;
+    define_scalar_function(db, "t_prepare_no_vtab", 1, t_prepare_no_vtab, flags)?;
+    define_scalar_function(db, "t_normalized", 1, t_normalized, flags)?;
     Ok(())
 }
 
@@ -52,5 +101,32 @@ mod tests {
             .query_row("SELECT t_values()", [], |row| row.get(0))
             .unwrap();
         assert_eq!(result, "[7,8,9]");
+
+        let count: i64 = db
+            .query_row(
+                "SELECT t_prepare_no_vtab('select count(*) from t')",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(count, 3);
+        let err = db
+            .query_row(
+                "SELECT t_prepare_no_vtab('select count(*) from json_each(''[1]'')')",
+                [],
+                |row| row.get::<_, i64>(0),
+            )
+            .unwrap_err();
+        assert!(err.to_string().starts_with("could not prepare"), "{}", err);
+
+        // only available in SQLite builds with SQLITE_ENABLE_NORMALIZE
+        let normalized: Option<String> = db
+            .query_row(
+                "SELECT t_normalized('select value from t where value = 42')",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(normalized.map_or(true, |sql| !sql.contains("42")));
     }
 }
@@ -63,6 +63,12 @@ impl<'vtab> VTabFind<'vtab> for FindTable {
         if name == "wrapped" {
             return Some((scalar_function_raw(wrapped), None, None));
         }
+        if name == "shouted" {
+            let shouted = scalar_function_raw(|context, values| {
+                api::result_text(context, api::value_text(&values[0])?.to_uppercase())
+            });
+            return Some((shouted, None, None));
+        }
         None
     }
 }
@@ -129,6 +135,7 @@ impl VTabCursor for FindCursor {
 #[sqlite_entrypoint]
 pub fn sqlite3_find_init(db: *mut sqlite3) -> Result<()> {
     api::overload_function(db, "wrapped", 1)?;
+    api::overload_function(db, "shouted", 1)?;
     define_table_function_with_find::<FindTable>(db, "find", None)?;
     Ok(())
 }
@@ -158,5 +165,11 @@ mod tests {
                 .unwrap(),
             "Wrapped access! Bare A access!"
         );
+        assert_eq!(
+            db.query_row("select shouted(a) from find", [], |row| row
+                .get::<usize, String>(0))
+                .unwrap(),
+            "BARE A ACCESS!"
+        );
     }
 }
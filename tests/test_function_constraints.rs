@@ -0,0 +1,240 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api,
+    function_constraints::{FunctionConstraints, SQLITE_INDEX_CONSTRAINT_FUNCTION},
+    table::{
+        define_table_function_with_find, BestIndexError, FindResult, IndexInfo, VTab,
+        VTabArguments, VTabCursor, VTabFind,
+    },
+    Result,
+};
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{mem, os::raw::c_int, slice};
+
+static WORDS: &[&str] = &["apple", "banana", "blueberry", "cherry", "bandana"];
+
+/// Rows the cursor handed back to SQLite
+static PRODUCED: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum WordFunction {
+    StartsWith,
+    Contains,
+}
+
+impl WordFunction {
+    fn code(self) -> u8 {
+        match self {
+            WordFunction::StartsWith => SQLITE_INDEX_CONSTRAINT_FUNCTION,
+            WordFunction::Contains => SQLITE_INDEX_CONSTRAINT_FUNCTION + 1,
+        }
+    }
+}
+
+impl TryFrom<u8> for WordFunction {
+    type Error = ();
+    fn try_from(code: u8) -> std::result::Result<Self, ()> {
+        [WordFunction::StartsWith, WordFunction::Contains]
+            .into_iter()
+            .find(|function| function.code() == code)
+            .ok_or(())
+    }
+}
+
+/// The overloads SQLite calls for constraints that weren't omitted.
+unsafe extern "C" fn contains(
+    context: *mut sqlite3_context,
+    argc: c_int,
+    argv: *mut *mut sqlite3_value,
+) {
+    let args = slice::from_raw_parts(argv, argc as usize);
+    let haystack = api::value_text(&args[0]).unwrap_or_default();
+    let needle = api::value_text(&args[1]).unwrap_or_default();
+    api::result_bool(context, haystack.contains(needle));
+}
+
+unsafe extern "C" fn starts_with(
+    context: *mut sqlite3_context,
+    _argc: c_int,
+    _argv: *mut *mut sqlite3_value,
+) {
+    // always omitted, so never called on the table's rows
+    api::result_error(context, "starts_with() is only for words").unwrap();
+}
+
+#[repr(C)]
+pub struct WordsTable {
+    /// must be first
+    base: sqlite3_vtab,
+}
+
+impl<'vtab> VTab<'vtab> for WordsTable {
+    type Aux = ();
+    type Cursor = WordsCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, WordsTable)> {
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        Ok(("CREATE TABLE x(word)".to_owned(), WordsTable { base }))
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        // starts_with() is handled here, contains() only narrows the scan
+        let functions = FunctionConstraints::claim(&mut info, 1, |code, _column| {
+            let function = WordFunction::try_from(code).ok()?;
+            Some(function == WordFunction::StartsWith)
+        });
+        info.set_idxstr(&functions.to_idx_str())
+            .map_err(|_| BestIndexError::Error)?;
+        info.set_estimated_cost(if functions.is_empty() { 100.0 } else { 10.0 });
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<WordsCursor> {
+        Ok(WordsCursor {
+            base: unsafe { mem::zeroed() },
+            rowid: 0,
+            prefixes: vec![],
+            needles: vec![],
+        })
+    }
+}
+
+impl<'vtab> VTabFind<'vtab> for WordsTable {
+    fn find_function(&mut self, argc: i32, name: &str) -> Option<FindResult> {
+        let (function, code) = match (name, argc) {
+            ("starts_with", 2) => (starts_with as _, WordFunction::StartsWith),
+            ("contains", 2) => (contains as _, WordFunction::Contains),
+            _ => return None,
+        };
+        Some((function, Some(code.code() as i32), None))
+    }
+}
+
+#[repr(C)]
+pub struct WordsCursor {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    rowid: usize,
+    prefixes: Vec<String>,
+    needles: Vec<String>,
+}
+
+impl WordsCursor {
+    fn skip_unmatched(&mut self) {
+        while let Some(word) = WORDS.get(self.rowid) {
+            if self.prefixes.iter().all(|prefix| word.starts_with(prefix))
+                && self.needles.iter().all(|needle| word.contains(needle))
+            {
+                break;
+            }
+            self.rowid += 1;
+        }
+    }
+}
+
+impl VTabCursor for WordsCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        idx_str: Option<&str>,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.prefixes.clear();
+        self.needles.clear();
+        let functions = FunctionConstraints::from_idx_str(idx_str.unwrap_or(""))?;
+        for arg in functions.bind::<WordFunction>(values)? {
+            assert_eq!(arg.column, 0);
+            let operand = api::value_text(arg.operand)?.to_owned();
+            match arg.function {
+                WordFunction::StartsWith => self.prefixes.push(operand),
+                WordFunction::Contains => self.needles.push(operand),
+            }
+        }
+        self.rowid = 0;
+        self.skip_unmatched();
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.rowid += 1;
+        self.skip_unmatched();
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.rowid >= WORDS.len()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, i: c_int) -> Result<()> {
+        if i == 0 {
+            PRODUCED.fetch_add(1, Ordering::SeqCst);
+            api::result_text(context, WORDS[self.rowid])?;
+        }
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.rowid as i64)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_functionconstraints_init(db: *mut sqlite3) -> Result<()> {
+    api::overload_function(db, "starts_with", 2)?;
+    api::overload_function(db, "contains", 2)?;
+    define_table_function_with_find::<WordsTable>(db, "words", None)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_functionconstraints_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        let words = |sql: &str| -> Vec<String> {
+            db.prepare(sql)
+                .unwrap()
+                .query_map([], |row| row.get(0))
+                .unwrap()
+                .collect::<std::result::Result<_, _>>()
+                .unwrap()
+        };
+
+        PRODUCED.store(0, Ordering::SeqCst);
+        assert_eq!(
+            words("select word from words where starts_with(word, 'b')"),
+            vec!["banana", "blueberry", "bandana"]
+        );
+        assert_eq!(PRODUCED.load(Ordering::SeqCst), 3);
+
+        // contains() narrows the scan, and SQLite checks it again
+        assert_eq!(
+            words("select word from words where starts_with(word, 'ba') and contains(word, 'dan')"),
+            vec!["bandana"]
+        );
+        assert_eq!(
+            words("select word from words where contains(word, 'rr')"),
+            vec!["blueberry", "cherry"]
+        );
+        assert_eq!(
+            words("select word from words"),
+            WORDS.iter().map(|w| w.to_string()).collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{api, define_scalar_function, geo, Result};
+
+pub fn geo_point(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let lat = api::value_double(values.first().expect("1st argument as latitude"));
+    let lng = api::value_double(values.get(1).expect("2nd argument as longitude"));
+    geo::result_wkb(context, &geo::Geometry::point(lat, lng));
+    Ok(())
+}
+
+pub fn geo_wkb(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let geometry = geo::value_geometry(values.first().expect("1st argument as geometry"))?;
+    geo::result_wkb(context, &geometry);
+    Ok(())
+}
+
+pub fn geo_geojson(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let geometry = geo::value_geometry(values.first().expect("1st argument as geometry"))?;
+    geo::result_geojson(context, &geometry)
+}
+
+pub fn geo_is_wkb(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let subtype = api::value_subtype(values.first().expect("1st argument"));
+    api::result_bool(context, subtype == u32::from(geo::WKB_SUBTYPE));
+    Ok(())
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_geo_init(db: *mut sqlite3) -> Result<()> {
+    let flags = FunctionFlags::UTF8 | FunctionFlags::DETERMINISTIC | FunctionFlags::RESULT_SUBTYPE;
+    define_scalar_function(db, "geo_point", 2, geo_point, flags)?;
+    define_scalar_function(db, "geo_wkb", 1, geo_wkb, flags)?;
+    define_scalar_function(db, "geo_geojson", 1, geo_geojson, flags)?;
+    define_scalar_function(db, "geo_is_wkb", 1, geo_is_wkb, flags)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_geo_init as *const (),
+                ),
+            ));
+        }
+        let conn = Connection::open_in_memory().unwrap();
+
+        let result: (String, String, bool, bool) = conn
+            .query_row(
+                "select hex(geo_point(2, 1)), geo_geojson(geo_point(2, 1)), geo_is_wkb(geo_point(2, 1)), geo_is_wkb(x'00')",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            result,
+            (
+                "0101000000000000000000F03F0000000000000040".to_owned(),
+                r#"{"coordinates":[1.0,2.0],"type":"Point"}"#.to_owned(),
+                true,
+                false
+            )
+        );
+
+        let roundtrip: String = conn
+            .query_row(
+                r#"select geo_geojson(geo_wkb('{"type":"LineString","coordinates":[[1,2],[3,4]]}')) ->> '$.coordinates[1]'"#,
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(roundtrip, "[3.0,4.0]");
+
+        let err = conn
+            .query_row("select geo_geojson(x'0102')", [], |row| {
+                row.get::<_, String>(0)
+            })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "invalid WKB: unexpected end of blob");
+    }
+}
@@ -0,0 +1,142 @@
+use sqlite_loadable::histogram::{self, define_hist, Histogram, RELATIVE_ERROR};
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::Result;
+
+#[sqlite_entrypoint]
+pub fn sqlite3_histogram_init(db: *mut sqlite3) -> Result<()> {
+    define_hist(db)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, types::Value, Connection};
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = Histogram::new();
+        assert_eq!(histogram.quantile(0.5), None);
+        for i in 1..=10_000 {
+            histogram.record(i as f64);
+        }
+        histogram.record(f64::NAN);
+        assert_eq!(histogram.count(), 10_000);
+        assert_eq!(histogram.min(), Some(1.0));
+        assert_eq!(histogram.max(), Some(10_000.0));
+        assert_eq!(histogram.mean(), Some(5000.5));
+        for (q, exact) in [(0.5, 5000.0), (0.95, 9500.0), (0.99, 9900.0)] {
+            let estimate = histogram.quantile(q).unwrap();
+            assert!(
+                (estimate - exact).abs() / exact <= RELATIVE_ERROR,
+                "p{} is {}",
+                q * 100.0,
+                estimate
+            );
+        }
+        assert_eq!(histogram.quantile(0.0), Some(1.0));
+        assert_eq!(histogram.quantile(1.0), Some(10_000.0));
+
+        let mut signed = Histogram::new();
+        for value in [-100.0, -1.0, 0.0, 0.0, 1.0] {
+            signed.record(value);
+        }
+        assert_eq!(signed.quantile(0.0), Some(-100.0));
+        assert_eq!(signed.quantile(0.5), Some(0.0));
+        assert!(signed.quantile(0.25).unwrap() < 0.0);
+
+        let mut merged = Histogram::new();
+        merged.merge(&signed);
+        merged.merge(&Histogram::new());
+        assert_eq!(merged, signed);
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_histogram_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        let value = |sql: &str| db.query_row(sql, [], |row| row.get::<_, Value>(0));
+        db.execute_batch(
+            "create table requests(duration);
+             insert into requests
+               with recursive n(i) as (select 1 union all select i + 1 from n where i < 100)
+               select i from n;
+             insert into requests values (null);",
+        )
+        .unwrap();
+
+        assert_eq!(
+            value("select hist(duration) ->> '$.count' from requests").unwrap(),
+            Value::Integer(100)
+        );
+        assert_eq!(
+            value("select hist(duration) ->> '$.max' from requests").unwrap(),
+            Value::Real(100.0)
+        );
+        assert_eq!(
+            value("select json_type(hist(duration)) from requests").unwrap(),
+            Value::Text("object".to_owned())
+        );
+        assert_eq!(
+            value("select hist(duration) from requests where 0").unwrap(),
+            Value::Null
+        );
+        assert_eq!(
+            value(
+                "select group_concat(h ->> '$.count') from
+                   (select hist(duration) as h from requests group by duration % 2)"
+            )
+            .unwrap(),
+            Value::Text("50,50".to_owned())
+        );
+
+        // named histograms add up across calls, and Rust code can feed them
+        value("select hist(duration, 'requests') from requests").unwrap();
+        value("select hist(duration, 'requests') from requests where duration <= 10").unwrap();
+        histogram::record("requests", 1000.0);
+        histogram::record("other", 1.0);
+        assert_eq!(histogram::get("requests").unwrap().count(), 111);
+        let stats = db
+            .query_row(
+                "select count, min, max, p50 from hist_stats where name = 'requests'",
+                [],
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, f64>(1)?,
+                        row.get::<_, f64>(2)?,
+                        row.get::<_, f64>(3)?,
+                    ))
+                },
+            )
+            .unwrap();
+        assert_eq!((stats.0, stats.1, stats.2), (111, 1.0, 1000.0));
+        assert!((stats.3 - 46.0).abs() / 46.0 <= RELATIVE_ERROR);
+        assert_eq!(
+            value("select group_concat(name) from hist_stats").unwrap(),
+            Value::Text("other,requests".to_owned())
+        );
+
+        assert!(value("select hist(1, x'ff')").is_err());
+
+        assert_eq!(
+            value("select hist_reset('other')").unwrap(),
+            Value::Integer(1)
+        );
+        assert_eq!(value("select hist_reset()").unwrap(), Value::Integer(1));
+        assert_eq!(
+            value("select count(*) from hist_stats").unwrap(),
+            Value::Integer(0)
+        );
+        // not from a view
+        db.execute_batch("create view resets as select hist_reset() as result")
+            .unwrap();
+        assert!(value("select result from resets").is_err());
+    }
+}
@@ -0,0 +1,223 @@
+use serde::{Deserialize, Serialize};
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api, define_virtual_table,
+    index_plan::IndexPlan,
+    table::{
+        BestIndexError, ConstraintOperator, IndexInfo, OrderByDirection, VTab, VTabArguments,
+        VTabCursor,
+    },
+    Result,
+};
+use std::{mem, os::raw::c_int};
+
+static ROWS: &[(i64, &str)] = &[
+    (1, "alpha"),
+    (2, "beta"),
+    (3, "gamma"),
+    (4, "delta"),
+    (5, "epsilon"),
+];
+
+/// What the table does besides filtering, decided in xBestIndex.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Scan {
+    descending: bool,
+}
+
+#[repr(C)]
+pub struct PlanTable {
+    /// must be first
+    base: sqlite3_vtab,
+}
+
+impl<'vtab> VTab<'vtab> for PlanTable {
+    type Aux = ();
+    type Cursor = PlanCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, PlanTable)> {
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        Ok(("CREATE TABLE x(id, name)".to_owned(), PlanTable { base }))
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        let descending = match info.order_bys().as_slice() {
+            [order_by] => {
+                order_by.icolumn() == 0
+                    && matches!(order_by.direction(), OrderByDirection::Descending)
+            }
+            _ => false,
+        };
+        let mut plan = IndexPlan::new(Scan { descending });
+        for mut constraint in info.constraints() {
+            if constraint.column_idx() == 0
+                && matches!(
+                    constraint.op(),
+                    Some(ConstraintOperator::EQ | ConstraintOperator::GT)
+                )
+            {
+                plan.consume(&mut constraint, true);
+            }
+        }
+        plan.apply(&mut info).map_err(|_| BestIndexError::Error)?;
+        info.set_estimated_cost(10.0);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<PlanCursor> {
+        Ok(PlanCursor {
+            base: unsafe { mem::zeroed() },
+            rows: Vec::new(),
+            index: 0,
+        })
+    }
+}
+
+#[repr(C)]
+pub struct PlanCursor {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    rows: Vec<(i64, &'static str)>,
+    index: usize,
+}
+
+impl VTabCursor for PlanCursor {
+    fn filter(
+        &mut self,
+        idx_num: c_int,
+        idx_str: Option<&str>,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        let plan = IndexPlan::<Scan>::decode(idx_num, idx_str)?;
+        self.rows = ROWS.to_vec();
+        for (constraint, value) in plan.bind(values)? {
+            let value = api::value_int64(&value);
+            self.rows.retain(|(id, _)| match constraint.op {
+                ConstraintOperator::EQ => *id == value,
+                _ => *id > value,
+            });
+        }
+        if plan.payload.descending {
+            self.rows.reverse();
+        }
+        self.index = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.index += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.index >= self.rows.len()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, i: c_int) -> Result<()> {
+        let (id, name) = self.rows[self.index];
+        match i {
+            0 => api::result_int64(context, id),
+            _ => api::result_text(context, name)?,
+        }
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.rows[self.index].0)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_indexplan_init(db: *mut sqlite3) -> Result<()> {
+    define_virtual_table::<PlanTable>(db, "plan_rs", None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_round_trip() {
+        let plan = IndexPlan::new(Scan { descending: true });
+        let idx_str = plan.to_idx_str().unwrap();
+        assert_eq!(
+            IndexPlan::<Scan>::decode(0, Some(idx_str.as_str())).unwrap(),
+            plan
+        );
+        assert_eq!(IndexPlan::<Scan>::from_idx_str(&idx_str).unwrap(), plan);
+
+        let idx_str = r#"{"version":1,"constraints":[{"column":0,"op":"EQ","argv_index":1,"omit":true},{"column":-1,"op":{"FUNCTION":150},"argv_index":2,"omit":false}],"payload":null}"#;
+        let plan = IndexPlan::<()>::decode(2, Some(idx_str)).unwrap();
+        assert_eq!(plan.len(), 2);
+        assert_eq!(plan.constraints()[1].op, ConstraintOperator::FUNCTION(150));
+        assert!(plan.find(0, ConstraintOperator::EQ).unwrap().omit);
+        assert!(plan.find(0, ConstraintOperator::GT).is_none());
+        assert_eq!(
+            IndexPlan::<()>::from_idx_str(&plan.to_idx_str().unwrap()).unwrap(),
+            plan
+        );
+
+        // plans that didn't come from apply() are rejected
+        assert!(IndexPlan::<()>::decode(1, Some(idx_str)).is_err());
+        assert!(IndexPlan::<()>::decode(0, None).is_err());
+        assert!(IndexPlan::<()>::decode(0, Some("not json")).is_err());
+        assert!(IndexPlan::<()>::decode(
+            0,
+            Some(r#"{"version":2,"constraints":[],"payload":null}"#)
+        )
+        .is_err());
+        assert!(IndexPlan::<()>::decode(
+            1,
+            Some(
+                r#"{"version":1,"constraints":[{"column":0,"op":"EQ","argv_index":3,"omit":true}],"payload":null}"#
+            )
+        )
+        .is_err());
+        assert!(IndexPlan::<Scan>::from_idx_str(
+            r#"{"version":1,"constraints":[],"payload":null}"#
+        )
+        .is_err());
+        assert!(plan.bind(&[]).is_err());
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_indexplan_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("create virtual table t using plan_rs()")
+            .unwrap();
+        let ids = |sql: &str| -> Vec<i64> {
+            db.prepare(sql)
+                .unwrap()
+                .query_map([], |row| row.get(0))
+                .unwrap()
+                .collect::<rusqlite::Result<_>>()
+                .unwrap()
+        };
+
+        assert_eq!(ids("select id from t"), vec![1, 2, 3, 4, 5]);
+        assert_eq!(ids("select id from t where id = 3"), vec![3]);
+        assert_eq!(ids("select id from t where id > 3"), vec![4, 5]);
+        assert_eq!(ids("select id from t where id > 1 and id = 2"), vec![2]);
+        assert_eq!(
+            ids("select id from t order by id desc"),
+            vec![5, 4, 3, 2, 1]
+        );
+        assert_eq!(
+            ids("select id from t where id > 2 order by id desc"),
+            vec![5, 4, 3]
+        );
+    }
+}
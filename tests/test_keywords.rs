@@ -0,0 +1,73 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api, define_scalar_function,
+    keywords::{is_complete, is_keyword, keywords},
+    Result,
+};
+
+pub fn sql_is_keyword(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    api::result_bool(context, is_keyword(api::value_text(&values[0])?));
+    Ok(())
+}
+
+pub fn sql_is_complete(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    api::result_bool(context, is_complete(api::value_text(&values[0])?));
+    Ok(())
+}
+
+/// sql_keywords(), every keyword separated by commas.
+pub fn sql_keywords(context: *mut sqlite3_context, _values: &[*mut sqlite3_value]) -> Result<()> {
+    api::result_text(context, keywords().collect::<Vec<_>>().join(","))
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_keywords_init(db: *mut sqlite3) -> Result<()> {
+    let flags = FunctionFlags::UTF8 | FunctionFlags::DETERMINISTIC;
+    define_scalar_function(db, "sql_is_keyword", 1, sql_is_keyword, flags)?;
+    define_scalar_function(db, "sql_is_complete", 1, sql_is_complete, flags)?;
+    define_scalar_function(db, "sql_keywords", 0, sql_keywords, flags)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_keywords_init as *const (),
+                ),
+            ));
+        }
+        let conn = Connection::open_in_memory().unwrap();
+        let check = |sql: &str, arg: &str| -> bool {
+            conn.query_row(sql, [arg], |row| row.get(0)).unwrap()
+        };
+
+        assert!(check("select sql_is_keyword(?)", "select"));
+        assert!(check("select sql_is_keyword(?)", "NATURAL"));
+        assert!(!check("select sql_is_keyword(?)", "natural_sort"));
+        assert!(!check("select sql_is_keyword(?)", ""));
+
+        assert!(check("select sql_is_complete(?)", "select 1;"));
+        assert!(!check("select sql_is_complete(?)", "select 1"));
+        assert!(!check("select sql_is_complete(?)", "select ';"));
+        assert!(!check(
+            "select sql_is_complete(?)",
+            "create trigger t after insert on x begin select 1;"
+        ));
+
+        let all: String = conn
+            .query_row("select sql_keywords()", [], |row| row.get(0))
+            .unwrap();
+        let all: Vec<&str> = all.split(',').collect();
+        assert!(all.len() > 100);
+        assert!(all.contains(&"SELECT"));
+        assert!(all.contains(&"WITHOUT"));
+    }
+}
@@ -0,0 +1,180 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    kv::{define_kv_module, KvEntry, KvStore, MemoryStore},
+    Result,
+};
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Full scans the cursor asked the store for
+static FULL_SCANS: AtomicUsize = AtomicUsize::new(0);
+
+/// A MemoryStore that counts full scans, to check which plan was used.
+struct CountingStore(MemoryStore);
+
+impl KvStore for CountingStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.0.get(key)
+    }
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.0.put(key, value)
+    }
+    fn delete(&self, key: &[u8]) -> Result<()> {
+        self.0.delete(key)
+    }
+    fn scan_prefix<'a>(
+        &'a self,
+        prefix: &[u8],
+    ) -> Result<Box<dyn Iterator<Item = Result<KvEntry>> + 'a>> {
+        if prefix.is_empty() {
+            FULL_SCANS.fetch_add(1, Ordering::SeqCst);
+        }
+        self.0.scan_prefix(prefix)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_kv_init(db: *mut sqlite3) -> Result<()> {
+    define_kv_module(db, "kv", CountingStore(MemoryStore::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_kv_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "create virtual table settings using kv();
+            insert into settings values
+              ('theme', 'dark'),
+              ('user.name', 'alex'),
+              ('user.email', 'alex@example.com'),
+              ('binary', x'00ff');",
+        )
+        .unwrap();
+        let scans = || FULL_SCANS.load(Ordering::SeqCst);
+
+        let rows = |sql: &str| -> Vec<(String, String)> {
+            db.prepare(sql)
+                .unwrap()
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+                .unwrap()
+                .collect::<std::result::Result<_, _>>()
+                .unwrap()
+        };
+
+        let before = scans();
+        assert_eq!(
+            rows("select * from settings where key = 'theme'"),
+            vec![("theme".to_owned(), "dark".to_owned())]
+        );
+        assert!(rows("select * from settings where key = 'missing'").is_empty());
+        assert_eq!(
+            rows("select * from settings where key glob 'user.*'"),
+            vec![
+                ("user.email".to_owned(), "alex@example.com".to_owned()),
+                ("user.name".to_owned(), "alex".to_owned()),
+            ]
+        );
+        // the prefix narrows the scan, the pattern still filters
+        assert_eq!(
+            rows("select * from settings where key glob 'user.n*e'"),
+            vec![("user.name".to_owned(), "alex".to_owned())]
+        );
+        assert_eq!(scans(), before);
+
+        let (typ, hex): (String, String) = db
+            .query_row(
+                "select typeof(value), hex(value) from settings where key = 'binary'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((typ.as_str(), hex.as_str()), ("blob", "00FF"));
+
+        // inserting an existing key fails unless the statement resolves it
+        let err = db
+            .execute("insert into settings values ('theme', 'light')", [])
+            .unwrap_err();
+        assert_eq!(
+            err.sqlite_error_code(),
+            Some(rusqlite::ErrorCode::ConstraintViolation)
+        );
+        assert!(err.to_string().contains("UNIQUE constraint failed: key"));
+        db.execute(
+            "insert or ignore into settings values ('theme', 'ignored')",
+            [],
+        )
+        .unwrap();
+        assert_eq!(
+            rows("select * from settings where key = 'theme'"),
+            vec![("theme".to_owned(), "dark".to_owned())]
+        );
+        db.execute(
+            "insert or replace into settings values ('theme', 'light')",
+            [],
+        )
+        .unwrap();
+        // OR FAIL keeps the rows before the one that failed
+        assert!(db
+            .execute(
+                "insert or fail into settings values ('new.a', 'a'), ('theme', 'x')",
+                [],
+            )
+            .is_err());
+        assert_eq!(
+            rows("select * from settings where key glob 'new.*'"),
+            vec![("new.a".to_owned(), "a".to_owned())]
+        );
+        db.execute("delete from settings where key = 'new.a'", [])
+            .unwrap();
+        assert!(db
+            .execute(
+                "update settings set key = 'theme' where key = 'user.name'",
+                [],
+            )
+            .is_err());
+        db.execute(
+            "update settings set value = 'alex2' where key = 'user.name'",
+            [],
+        )
+        .unwrap();
+        db.execute(
+            "update settings set key = 'user.mail' where key = 'user.email'",
+            [],
+        )
+        .unwrap();
+        db.execute("delete from settings where key = 'binary'", [])
+            .unwrap();
+        assert_eq!(
+            rows("select * from settings"),
+            vec![
+                ("theme".to_owned(), "light".to_owned()),
+                ("user.mail".to_owned(), "alex@example.com".to_owned()),
+                ("user.name".to_owned(), "alex2".to_owned()),
+            ]
+        );
+        assert!(db
+            .execute("insert into settings values (null, 'x')", [])
+            .is_err());
+
+        // a second table over the same store sees the same entries
+        db.execute_batch("create virtual table settings2 using kv()")
+            .unwrap();
+        let count: i64 = db
+            .query_row("select count(*) from settings2", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 3);
+    }
+}
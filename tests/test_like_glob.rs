@@ -0,0 +1,130 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{api, compare, define_scalar_function, Result};
+
+pub fn rs_like(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let pattern = api::value_text(values.first().expect("1st argument as pattern"))?;
+    let text = api::value_text(values.get(1).expect("2nd argument as text"))?;
+    let escape = match values.get(2) {
+        Some(value) => api::value_text(value)?.chars().next(),
+        None => None,
+    };
+    api::result_bool(context, compare::like(pattern, text, escape));
+    Ok(())
+}
+
+pub fn rs_glob(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let pattern = api::value_text(values.first().expect("1st argument as pattern"))?;
+    let text = api::value_text(values.get(1).expect("2nd argument as text"))?;
+    api::result_bool(context, compare::glob(pattern, text));
+    Ok(())
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_likeglob_init(db: *mut sqlite3) -> Result<()> {
+    let flags = FunctionFlags::UTF8 | FunctionFlags::DETERMINISTIC;
+    define_scalar_function(db, "rs_like", 2, rs_like, flags)?;
+    define_scalar_function(db, "rs_like", 3, rs_like, flags)?;
+    define_scalar_function(db, "rs_glob", 2, rs_glob, flags)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    const TEXTS: &[&str] = &[
+        "",
+        "a",
+        "A",
+        "abc",
+        "ABC",
+        "abcabc",
+        "a%c",
+        "a_c",
+        "a]c",
+        "a-c",
+        "ab\\c",
+        "ÀbÇ",
+        "àbç",
+        "hello world",
+        "x*y",
+        "x?y",
+        "[x]",
+    ];
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_likeglob_init as *const (),
+                ),
+            ));
+        }
+
+        let db = Connection::open_in_memory().unwrap();
+
+        let like_patterns = [
+            "", "%", "_", "a%", "%c", "a_c", "%b%", "A%C", "__c", "%_", "a\\%c", "a\\_c", "a\\\\c",
+            "ab\\", "à%", "%%c", "_%_", "hello%", "%o w%",
+        ];
+        for pattern in like_patterns {
+            for text in TEXTS {
+                let (sqlite, rust): (bool, bool) = db
+                    .query_row(
+                        "select ?2 like ?1, rs_like(?1, ?2)",
+                        [pattern, text],
+                        |row| Ok((row.get(0)?, row.get(1)?)),
+                    )
+                    .unwrap();
+                assert_eq!(sqlite, rust, "{:?} like {:?}", text, pattern);
+                let (sqlite, rust): (bool, bool) = db
+                    .query_row(
+                        "select ?2 like ?1 escape '\\', rs_like(?1, ?2, '\\')",
+                        [pattern, text],
+                        |row| Ok((row.get(0)?, row.get(1)?)),
+                    )
+                    .unwrap();
+                assert_eq!(sqlite, rust, "{:?} like {:?} escape '\\'", text, pattern);
+            }
+        }
+
+        let glob_patterns = [
+            "",
+            "*",
+            "?",
+            "a*",
+            "*c",
+            "a?c",
+            "*b*",
+            "A*",
+            "[a]*",
+            "[^a]*",
+            "a[]]c",
+            "a[-]c",
+            "a[a-c]c",
+            "a[^a-c]c",
+            "*[x]*",
+            "[[]x]",
+            "a[b",
+            "x[*]y",
+            "x[?]y",
+            "[A-Za-z]*",
+            "*?",
+        ];
+        for pattern in glob_patterns {
+            for text in TEXTS {
+                let (sqlite, rust): (bool, bool) = db
+                    .query_row(
+                        "select ?2 glob ?1, rs_glob(?1, ?2)",
+                        [pattern, text],
+                        |row| Ok((row.get(0)?, row.get(1)?)),
+                    )
+                    .unwrap();
+                assert_eq!(sqlite, rust, "{:?} glob {:?}", text, pattern);
+            }
+        }
+    }
+}
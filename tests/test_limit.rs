@@ -0,0 +1,190 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api, define_virtual_table,
+    index_plan::{IndexPlan, LimitOffset},
+    table::{BestIndexError, ConstraintOperator, IndexInfo, VTab, VTabArguments, VTabCursor},
+    Result,
+};
+use std::{
+    mem,
+    os::raw::c_int,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+const ROWS: i64 = 100;
+
+/// How many rows the table has "fetched" from its backend.
+static FETCHED: AtomicUsize = AtomicUsize::new(0);
+
+#[repr(C)]
+pub struct PagedTable {
+    /// must be first
+    base: sqlite3_vtab,
+}
+
+impl<'vtab> VTab<'vtab> for PagedTable {
+    type Aux = ();
+    type Cursor = PagedCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, PagedTable)> {
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        Ok(("CREATE TABLE x(id, name)".to_owned(), PagedTable { base }))
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        let mut plan = IndexPlan::new(());
+        for mut constraint in info.constraints() {
+            if constraint.column_idx() == 0 && constraint.op() == Some(ConstraintOperator::GT) {
+                plan.consume(&mut constraint, true);
+            }
+        }
+        plan.consume_limit(&info);
+        plan.apply(&mut info).map_err(|_| BestIndexError::Error)?;
+        info.set_estimated_cost(ROWS as f64);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<PagedCursor> {
+        Ok(PagedCursor {
+            base: unsafe { mem::zeroed() },
+            rows: Vec::new(),
+            index: 0,
+        })
+    }
+}
+
+#[repr(C)]
+pub struct PagedCursor {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    rows: Vec<i64>,
+    index: usize,
+}
+
+impl VTabCursor for PagedCursor {
+    fn filter(
+        &mut self,
+        idx_num: c_int,
+        idx_str: Option<&str>,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        let plan = IndexPlan::<()>::decode(idx_num, idx_str)?;
+        let mut after = 0;
+        for (constraint, value) in plan.bind(values)? {
+            if constraint.op == ConstraintOperator::GT {
+                after = api::value_int64(&value);
+            }
+        }
+        let LimitOffset { limit, offset } = plan.limit_offset(values)?;
+        self.rows = (after + 1..=ROWS)
+            .skip(offset as usize)
+            .take(limit.map_or(usize::MAX, |limit| limit as usize))
+            .collect();
+        FETCHED.fetch_add(self.rows.len(), Ordering::SeqCst);
+        self.index = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.index += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.index >= self.rows.len()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, i: c_int) -> Result<()> {
+        let id = self.rows[self.index];
+        match i {
+            0 => api::result_int64(context, id),
+            _ => api::result_text(context, format!("row {}", id))?,
+        }
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.rows[self.index])
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_limit_init(db: *mut sqlite3) -> Result<()> {
+    define_virtual_table::<PagedTable>(db, "paged", None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, params_from_iter, types::Value, Connection};
+
+    #[test]
+    fn test_limit_offset_end() {
+        let page = LimitOffset {
+            limit: Some(10),
+            offset: 20,
+        };
+        assert_eq!(page.end(), Some(30));
+        assert_eq!(LimitOffset::default().end(), None);
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_limit_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("create virtual table t using paged()")
+            .unwrap();
+        // the ids a query returns, and how many rows the table fetched for it
+        let ids = |sql: &str, params: &[Value]| -> (Vec<i64>, usize) {
+            FETCHED.store(0, Ordering::SeqCst);
+            let ids = db
+                .prepare(sql)
+                .unwrap()
+                .query_map(params_from_iter(params), |row| row.get(0))
+                .unwrap()
+                .collect::<rusqlite::Result<_>>()
+                .unwrap();
+            (ids, FETCHED.load(Ordering::SeqCst))
+        };
+
+        assert_eq!(ids("select id from t limit 3", &[]), (vec![1, 2, 3], 3));
+        assert_eq!(
+            ids("select id from t limit 3 offset 10", &[]),
+            (vec![11, 12, 13], 3)
+        );
+        assert_eq!(
+            ids("select id from t where id > 90 limit 5 offset 2", &[]),
+            (vec![93, 94, 95, 96, 97], 5)
+        );
+        assert_eq!(
+            ids(
+                "select id from t limit ? offset ?",
+                &[Value::Integer(2), Value::Integer(4)]
+            ),
+            (vec![5, 6], 2)
+        );
+        assert_eq!(
+            ids("select id from t limit -1 offset 98", &[]),
+            (vec![99, 100], 2)
+        );
+        assert_eq!(ids("select id from t limit 0", &[]), (vec![], 0));
+
+        // a constraint the table leaves to SQLite keeps the LIMIT there too
+        assert_eq!(
+            ids("select id from t where name like 'row 1%' limit 2", &[]),
+            (vec![1, 10], 100)
+        );
+        assert_eq!(ids("select id from t", &[]).1, 100);
+    }
+}
@@ -0,0 +1,105 @@
+use sqlite_loadable::materialize::{define_refresh_function, Materializer};
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::Result;
+
+#[sqlite_entrypoint]
+pub fn sqlite3_materialize_init(db: *mut sqlite3) -> Result<()> {
+    let totals = Materializer::new(
+        "order_totals",
+        "select id, customer, price * quantity as total from orders",
+    )
+    .incremental("id", "orders");
+    define_refresh_function(db, "order_totals", totals)?;
+    let customers = Materializer::new(
+        "customer_totals",
+        "select customer, sum(price * quantity) as total from orders group by customer",
+    );
+    define_refresh_function(db, "customer_totals", customers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_materialize_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        let int = |sql: &str| db.query_row(sql, [], |row| row.get::<_, i64>(0));
+        let rows = |sql: &str| {
+            let mut stmt = db.prepare(sql).unwrap();
+            let rows: Vec<(String, i64)> = stmt
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+                .unwrap()
+                .map(|row| row.unwrap())
+                .collect();
+            rows
+        };
+        db.execute_batch(
+            "create table orders(id integer primary key, customer text, price, quantity);
+             insert into orders values (1, 'alex', 10, 1), (2, 'brian', 5, 2), (3, 'alex', 1, 3);",
+        )
+        .unwrap();
+
+        // the first refresh creates the table and fills it
+        assert_eq!(int("select order_totals_refresh()").unwrap(), 3);
+        assert_eq!(
+            rows("select customer, total from order_totals order by id"),
+            vec![
+                ("alex".to_owned(), 10),
+                ("brian".to_owned(), 10),
+                ("alex".to_owned(), 3)
+            ]
+        );
+        assert_eq!(int("select order_totals_refresh()").unwrap(), 0);
+
+        db.execute_batch(
+            "update orders set quantity = 4 where id = 2;
+             delete from orders where id = 3;
+             insert into orders values (4, 'carol', 2, 2);",
+        )
+        .unwrap();
+        // only the changed rows are written
+        assert_eq!(int("select order_totals_refresh()").unwrap(), 2);
+        assert_eq!(
+            rows("select customer, total from order_totals order by id"),
+            vec![
+                ("alex".to_owned(), 10),
+                ("brian".to_owned(), 20),
+                ("carol".to_owned(), 4)
+            ]
+        );
+        assert_eq!(int("select order_totals_refresh('full')").unwrap(), 3);
+
+        // without a key every refresh is full
+        assert_eq!(int("select customer_totals_refresh()").unwrap(), 3);
+        db.execute_batch("update orders set price = 20 where id = 1")
+            .unwrap();
+        assert_eq!(int("select customer_totals_refresh()").unwrap(), 3);
+        assert_eq!(
+            rows("select customer, total from customer_totals order by customer"),
+            vec![
+                ("alex".to_owned(), 20),
+                ("brian".to_owned(), 20),
+                ("carol".to_owned(), 4)
+            ]
+        );
+
+        assert!(int("select order_totals_refresh('partial')")
+            .unwrap_err()
+            .to_string()
+            .contains("unknown mode"));
+        // not from a view
+        db.execute_batch("create view refreshes as select order_totals_refresh() as result")
+            .unwrap();
+        assert!(int("select result from refreshes").is_err());
+    }
+}
@@ -0,0 +1,245 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api, define_virtual_table,
+    migration::{migrate, Migration},
+    schema::quote_identifier,
+    table::{BestIndexError, IndexInfo, VTab, VTabArguments, VTabCursor},
+    Result,
+};
+
+use std::{
+    mem,
+    os::raw::c_int,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+fn data_table(schema: &str, name: &str) -> String {
+    format!(
+        "{}.{}",
+        quote_identifier(schema),
+        quote_identifier(&format!("{}_data", name))
+    )
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration::new(1, |schema, name| {
+        format!(
+            "CREATE TABLE {}(id INTEGER PRIMARY KEY, body TEXT)",
+            data_table(schema, name)
+        )
+    }),
+    Migration::new(2, |schema, name| {
+        format!(
+            "ALTER TABLE {} ADD COLUMN created INTEGER; UPDATE {} SET created = 0",
+            data_table(schema, name),
+            data_table(schema, name)
+        )
+    }),
+    Migration::new(3, |schema, name| {
+        format!(
+            "ALTER TABLE {} ADD COLUMN broken; not valid sql",
+            data_table(schema, name)
+        )
+    }),
+];
+
+/// How many of MIGRATIONS the extension knows about, like a release number.
+static RELEASE: AtomicUsize = AtomicUsize::new(1);
+
+/// docs, a virtual table with one row holding its shadow table version.
+#[repr(C)]
+pub struct DocsTable {
+    /// must be first
+    base: sqlite3_vtab,
+    version: i64,
+}
+
+impl DocsTable {
+    fn open_tables(db: *mut sqlite3, args: VTabArguments) -> Result<(String, DocsTable)> {
+        let migrations = &MIGRATIONS[..RELEASE.load(Ordering::SeqCst)];
+        let version = migrate(db, &args.database_name, &args.table_name, migrations)?;
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        Ok((
+            "CREATE TABLE x(version)".to_owned(),
+            DocsTable { base, version },
+        ))
+    }
+}
+
+impl<'vtab> VTab<'vtab> for DocsTable {
+    type Aux = ();
+    type Cursor = DocsCursor;
+
+    fn create(
+        db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        args: VTabArguments,
+    ) -> Result<(String, DocsTable)> {
+        DocsTable::open_tables(db, args)
+    }
+
+    fn connect(
+        db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        args: VTabArguments,
+    ) -> Result<(String, DocsTable)> {
+        DocsTable::open_tables(db, args)
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        info.set_estimated_cost(1.0);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<DocsCursor> {
+        Ok(DocsCursor {
+            base: unsafe { mem::zeroed() },
+            version: self.version,
+            eof: false,
+        })
+    }
+}
+
+#[repr(C)]
+pub struct DocsCursor {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    version: i64,
+    eof: bool,
+}
+
+impl VTabCursor for DocsCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.eof = false;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.eof = true;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.eof
+    }
+
+    fn column(&self, context: *mut sqlite3_context, _i: c_int) -> Result<()> {
+        api::result_int64(context, self.version);
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(1)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_migration_init(db: *mut sqlite3) -> Result<()> {
+    define_virtual_table::<DocsTable>(db, "docs", None)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    fn columns(conn: &Connection, table: &str) -> Vec<String> {
+        conn.prepare("select name from pragma_table_info(?)")
+            .unwrap()
+            .query_map([table], |row| row.get(0))
+            .unwrap()
+            .collect::<std::result::Result<_, _>>()
+            .unwrap()
+    }
+
+    fn version(conn: &Connection) -> rusqlite::Result<i64> {
+        conn.query_row("select version from d", [], |row| row.get(0))
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_migration_init as *const (),
+                ),
+            ));
+        }
+        let path = std::env::temp_dir().join(format!(
+            "sqlite-loadable-migration-{}.db",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        // xCreate builds the shadow tables from scratch
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "create virtual table d using docs; insert into d_data(body) values ('a'), ('b')",
+        )
+        .unwrap();
+        assert_eq!(version(&conn).unwrap(), 1);
+        assert_eq!(columns(&conn, "d_data"), vec!["id", "body"]);
+        drop(conn);
+
+        // a newer release upgrades on xConnect, keeping the data
+        RELEASE.store(2, Ordering::SeqCst);
+        let conn = Connection::open(&path).unwrap();
+        assert_eq!(version(&conn).unwrap(), 2);
+        assert_eq!(columns(&conn, "d_data"), vec!["id", "body", "created"]);
+        let created: i64 = conn
+            .query_row("select sum(created is 0) from d_data", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(created, 2);
+        drop(conn);
+
+        // a failing migration leaves everything as it was
+        RELEASE.store(3, Ordering::SeqCst);
+        let conn = Connection::open(&path).unwrap();
+        let err = version(&conn).unwrap_err().to_string();
+        assert!(
+            err.contains("migration 3 of main.d failed: could not run"),
+            "{}",
+            err
+        );
+        assert_eq!(columns(&conn, "d_data"), vec!["id", "body", "created"]);
+        let stored: i64 = conn
+            .query_row(
+                "select value from d_meta where key = 'schema_version'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(stored, 2);
+        // including one from xCreate
+        assert!(conn
+            .execute_batch("create virtual table e using docs")
+            .is_err());
+        let leftover: i64 = conn
+            .query_row(
+                "select count(*) from sqlite_master where name like 'e%'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(leftover, 0);
+        drop(conn);
+
+        // an older release refuses tables it doesn't understand
+        RELEASE.store(1, Ordering::SeqCst);
+        let conn = Connection::open(&path).unwrap();
+        assert_eq!(
+            version(&conn).unwrap_err().to_string(),
+            "main.d has shadow table version 2, but this version of the extension only knows up to 1; upgrade the extension"
+        );
+        drop(conn);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
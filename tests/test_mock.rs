@@ -0,0 +1,104 @@
+#[cfg(feature = "testing")]
+use sqlite_loadable::prelude::*;
+#[cfg(feature = "testing")]
+use sqlite_loadable::{api, define_scalar_function, Error, Result};
+
+#[cfg(feature = "testing")]
+pub fn hello(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let name = api::value_text(values.first().expect("1st argument as name"))?;
+    api::result_text(context, format!("hello, {}!", name))?;
+    Ok(())
+}
+
+#[cfg(feature = "testing")]
+pub fn describe(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let value = values.first().expect("1st argument");
+    match api::value_type(value) {
+        api::ValueType::Null => Err(Error::new_message("null not allowed")),
+        api::ValueType::Integer => {
+            api::result_int64(context, api::value_int64(value) * 2);
+            Ok(())
+        }
+        api::ValueType::Float => {
+            api::result_double(context, api::value_double(value) / 2.0);
+            Ok(())
+        }
+        api::ValueType::Text => api::result_json(
+            context,
+            serde_json::json!({ "text": api::value_text(value)? }),
+        ),
+        api::ValueType::Blob => {
+            api::result_blob(context, api::value_blob(value));
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "testing")]
+#[sqlite_entrypoint]
+pub fn sqlite3_mock_init(db: *mut sqlite3) -> Result<()> {
+    let flags = FunctionFlags::UTF8 | FunctionFlags::DETERMINISTIC;
+    define_scalar_function(db, "hello", 1, hello, flags)?;
+    Ok(())
+}
+
+#[cfg(feature = "testing")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use sqlite_loadable::testing::{call_scalar, MockDatabase, MockResult, MockValue};
+
+    #[test]
+    fn test_mock_entrypoint() {
+        let mut db = MockDatabase::new();
+        unsafe {
+            sqlite3_mock_init(db.as_ptr(), std::ptr::null_mut(), std::ptr::null_mut());
+        }
+        let context = db
+            .call("hello", &[MockValue::Text("alex".to_owned())])
+            .unwrap();
+        assert_eq!(
+            context.result(),
+            &MockResult::Value(MockValue::Text("hello, alex!".to_owned()))
+        );
+        let context = db.call("hello", &[MockValue::Integer(1)]).unwrap();
+        assert_eq!(
+            context.result(),
+            &MockResult::Value(MockValue::Text("hello, 1!".to_owned()))
+        );
+        assert!(db.call("hello", &[]).is_none());
+        assert!(db.call("goodbye", &[MockValue::Null]).is_none());
+    }
+
+    #[test]
+    fn test_mock_call_scalar() {
+        assert_eq!(
+            call_scalar(describe, &[MockValue::Integer(21)]).result(),
+            &MockResult::Value(MockValue::Integer(42))
+        );
+        assert_eq!(
+            call_scalar(describe, &[MockValue::Float(3.0)]).result(),
+            &MockResult::Value(MockValue::Float(1.5))
+        );
+        assert_eq!(
+            call_scalar(describe, &[MockValue::Blob(vec![1, 2, 3])]).result(),
+            &MockResult::Value(MockValue::Blob(vec![1, 2, 3]))
+        );
+
+        let context = call_scalar(describe, &[MockValue::Text("alex".to_owned())]);
+        assert_eq!(
+            context.result(),
+            &MockResult::Value(MockValue::Text(r#"{"text":"alex"}"#.to_owned()))
+        );
+        assert_eq!(context.subtype(), b'J' as u32);
+
+        assert_eq!(
+            call_scalar(describe, &[MockValue::Null]).result(),
+            &MockResult::Error {
+                message: Some("null not allowed".to_owned()),
+                code: 1
+            }
+        );
+    }
+}
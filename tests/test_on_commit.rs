@@ -0,0 +1,73 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{api, define_scalar_function, hooks::on_commit, Result};
+use std::sync::Mutex;
+
+static COMMITTED: Mutex<Vec<i64>> = Mutex::new(Vec::new());
+
+/// notify(x) records x once the enclosing transaction commits.
+pub fn notify(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let x = api::value_int64(values.first().expect("1st argument"));
+    on_commit(api::context_db_handle(context), move || {
+        COMMITTED.lock().unwrap().push(x);
+    })?;
+    api::result_int64(context, x);
+    Ok(())
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_oncommit_init(db: *mut sqlite3) -> Result<()> {
+    define_scalar_function(db, "notify", 1, notify, FunctionFlags::UTF8)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    fn committed() -> Vec<i64> {
+        std::mem::take(&mut *COMMITTED.lock().unwrap())
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_oncommit_init as *const (),
+                ),
+            ));
+        }
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("create table t(x)").unwrap();
+
+        // implicit transaction
+        conn.execute("insert into t values (notify(1))", [])
+            .unwrap();
+        assert_eq!(committed(), vec![1]);
+
+        // nothing runs until commit
+        conn.execute_batch(
+            "begin; insert into t values (notify(2)); insert into t values (notify(3));",
+        )
+        .unwrap();
+        assert_eq!(committed(), Vec::<i64>::new());
+        conn.execute_batch("commit").unwrap();
+        assert_eq!(committed(), vec![2, 3]);
+
+        // dropped on rollback
+        conn.execute_batch("begin; insert into t values (notify(4)); rollback;")
+            .unwrap();
+        assert_eq!(committed(), Vec::<i64>::new());
+        conn.execute("insert into t values (5)", []).unwrap();
+        assert_eq!(committed(), Vec::<i64>::new());
+
+        // read-only work waits for the next write
+        conn.query_row("select notify(6)", [], |_| Ok(())).unwrap();
+        assert_eq!(committed(), Vec::<i64>::new());
+        conn.execute("insert into t values (notify(7))", [])
+            .unwrap();
+        assert_eq!(committed(), vec![6, 7]);
+    }
+}
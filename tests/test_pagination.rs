@@ -0,0 +1,81 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api, define_scalar_function,
+    pagination::{paginate, PageTokens},
+    Args, Result,
+};
+use std::sync::OnceLock;
+
+static TOKENS: OnceLock<PageTokens> = OnceLock::new();
+
+/// squares(count, page_size [, token]), a page of the first `count` squares.
+pub fn squares(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let (count, page_size, token): (u64, usize, Option<&str>) = Args::new(values).get()?;
+    let tokens = TOKENS.get_or_init(PageTokens::random);
+    let page = paginate((1..=count).map(|i| i * i), tokens, token, page_size)?;
+    api::result_json(context, page.to_json()?)
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_pagination_init(db: *mut sqlite3) -> Result<()> {
+    define_scalar_function(
+        db,
+        "squares",
+        -1,
+        squares,
+        FunctionFlags::UTF8 | FunctionFlags::RESULT_SUBTYPE,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_pagination_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        let page = |token: Option<&str>| -> (Vec<i64>, Option<String>) {
+            let page: String = db
+                .query_row("select squares(7, 3, ?)", [token], |row| row.get(0))
+                .unwrap();
+            let items = db
+                .prepare("select value from json_each(?, '$.items')")
+                .unwrap()
+                .query_map([&page], |row| row.get(0))
+                .unwrap()
+                .collect::<std::result::Result<_, _>>()
+                .unwrap();
+            let next = db
+                .query_row("select json_extract(?, '$.next')", [&page], |row| {
+                    row.get(0)
+                })
+                .unwrap();
+            (items, next)
+        };
+
+        let (items, next) = page(None);
+        assert_eq!(items, vec![1, 4, 9]);
+        let (items, next) = page(next.as_deref());
+        assert_eq!(items, vec![16, 25, 36]);
+        let (items, next) = page(next.as_deref());
+        assert_eq!(items, vec![49]);
+        assert_eq!(next, None);
+
+        let err = db
+            .query_row("select squares(7, 3, 'made-up')", [], |row| {
+                row.get::<_, String>(0)
+            })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "invalid page token");
+    }
+}
@@ -0,0 +1,95 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{params::define_params, Result};
+
+#[sqlite_entrypoint]
+pub fn sqlite3_params_init(db: *mut sqlite3) -> Result<()> {
+    define_params(db)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, types::Value, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_params_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "
+            create table orders(id integer primary key, total real);
+            insert into orders values (1, 5.0), (2, 50.0), (3, 500.0);
+            create view big_orders as select id from orders where total > param('min_total');
+            ",
+        )
+        .unwrap();
+        let ids = |sql: &str| -> Vec<i64> {
+            let mut stmt = db.prepare(sql).unwrap();
+            let rows = stmt.query_map([], |row| row.get(0)).unwrap();
+            rows.map(|row| row.unwrap()).collect()
+        };
+        let value = |sql: &str| db.query_row(sql, [], |row| row.get::<_, Value>(0)).unwrap();
+
+        // unset parameters are NULL, so the view is empty
+        assert_eq!(value("select param('min_total')"), Value::Null);
+        assert_eq!(ids("select id from big_orders"), Vec::<i64>::new());
+
+        value("select param_set('min_total', 10)");
+        assert_eq!(ids("select id from big_orders"), vec![2, 3]);
+        value("select param_set('min_total', 100.5)");
+        assert_eq!(ids("select id from big_orders"), vec![3]);
+
+        value("select param_set('label', 'big'), param_set('raw', x'0102')");
+        assert_eq!(
+            value("select param('label')"),
+            Value::Text("big".to_owned())
+        );
+        assert_eq!(value("select param('raw')"), Value::Blob(vec![1, 2]));
+
+        let mut stmt = db.prepare("select key, value from params").unwrap();
+        let rows: Vec<(String, Value)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                ("label".to_owned(), Value::Text("big".to_owned())),
+                ("min_total".to_owned(), Value::Real(100.5)),
+                ("raw".to_owned(), Value::Blob(vec![1, 2])),
+            ]
+        );
+        assert_eq!(
+            value("select value from params where key = 'label'"),
+            Value::Text("big".to_owned())
+        );
+        assert_eq!(
+            value("select count(*) from params where key = 'missing'"),
+            Value::Integer(0)
+        );
+
+        // NULL removes a key
+        value("select param_set('raw', null)");
+        assert_eq!(value("select count(*) from params"), Value::Integer(2));
+        assert_eq!(value("select param('raw')"), Value::Null);
+
+        // not from a view
+        db.execute_batch("create view setter as select param_set('label', 'small')")
+            .unwrap();
+        assert!(db
+            .query_row("select * from setter", [], |row| row.get::<_, Value>(0))
+            .is_err());
+
+        assert_eq!(value("select param_clear()"), Value::Integer(2));
+        assert_eq!(value("select count(*) from params"), Value::Integer(0));
+    }
+}
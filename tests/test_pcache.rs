@@ -0,0 +1,88 @@
+#[cfg(feature = "static")]
+use sqlite_loadable::pcache::{install_page_cache, FetchMode, Page, PageCache};
+#[cfg(feature = "static")]
+use std::collections::HashMap;
+#[cfg(feature = "static")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "static")]
+static CREATED: AtomicUsize = AtomicUsize::new(0);
+
+/// Keeps every page until SQLite discards it.
+#[cfg(feature = "static")]
+pub struct MapPageCache {
+    page_size: usize,
+    extra_size: usize,
+    pages: HashMap<u32, Box<Page>>,
+}
+
+#[cfg(feature = "static")]
+impl PageCache for MapPageCache {
+    fn create(page_size: usize, extra_size: usize, _purgeable: bool) -> Option<Self> {
+        Some(MapPageCache {
+            page_size,
+            extra_size,
+            pages: HashMap::new(),
+        })
+    }
+    fn set_cache_size(&mut self, _pages: i32) {}
+    fn page_count(&self) -> i32 {
+        self.pages.len() as i32
+    }
+    fn fetch(&mut self, key: u32, mode: FetchMode) -> Option<&mut Page> {
+        if !self.pages.contains_key(&key) {
+            if mode == FetchMode::Existing {
+                return None;
+            }
+            CREATED.fetch_add(1, Ordering::SeqCst);
+            self.pages
+                .insert(key, Page::new(key, self.page_size, self.extra_size));
+        }
+        self.pages.get_mut(&key).map(|page| page.as_mut())
+    }
+    fn unpin(&mut self, key: u32, discard: bool) {
+        if discard {
+            self.pages.remove(&key);
+        }
+    }
+    fn rekey(&mut self, old_key: u32, new_key: u32) {
+        self.pages.remove(&new_key);
+        if let Some(page) = self.pages.remove(&old_key) {
+            self.pages.insert(new_key, page);
+        }
+    }
+    fn truncate(&mut self, limit: u32) {
+        self.pages.retain(|key, _| *key < limit);
+    }
+}
+
+#[cfg(feature = "static")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_shutdown, Connection};
+
+    #[test]
+    fn test_install_page_cache() {
+        unsafe {
+            sqlite3_shutdown();
+        }
+        install_page_cache::<MapPageCache>().unwrap();
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "create table t(value text);
+            with recursive s(n) as (select 1 union all select n + 1 from s where n < 200)
+            insert into t select printf('%.500c', 'x') from s;",
+        )
+        .unwrap();
+        let (count, length): (i64, i64) = db
+            .query_row("select count(*), sum(length(value)) from t", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((count, length), (200, 200 * 500));
+        assert!(CREATED.load(Ordering::SeqCst) > 10);
+    }
+}
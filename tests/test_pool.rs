@@ -0,0 +1,228 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api, define_scalar_function_with_aux,
+    pool::{Pool, PooledConnection},
+    Error, Result,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+
+/// The pool, made on first use so in-memory databases can still load the
+/// extension, and how many connections its init hook ran on.
+#[derive(Default)]
+pub struct Workers {
+    pool: OnceLock<Pool>,
+    inits: Arc<AtomicUsize>,
+}
+
+impl Workers {
+    fn pool(&self, context: *mut sqlite3_context) -> Result<&Pool> {
+        if self.pool.get().is_none() {
+            let inits = Arc::clone(&self.inits);
+            let pool = Pool::new(api::context_db_handle(context), 2)?.with_init(move |conn| {
+                inits.fetch_add(1, Ordering::SeqCst);
+                rusqlite_conn(conn)
+                    .execute_batch("pragma query_only = 1")
+                    .map_err(|err| Error::new_message(err.to_string()))
+            });
+            let _ = self.pool.set(pool);
+        }
+        Ok(self.pool.get().expect("pool was just set"))
+    }
+}
+
+/// A rusqlite view of a pooled connection, which doesn't close it.
+fn rusqlite_conn(conn: &sqlite_loadable::database::Database) -> rusqlite::Connection {
+    unsafe { rusqlite::Connection::from_handle(conn.handle().cast::<rusqlite::ffi::sqlite3>()) }
+        .unwrap()
+}
+
+/// pool_count(table, threads) counts the rows of a table on each of
+/// `threads` threads, with connections from the pool, and sums the counts.
+pub fn pool_count(
+    context: *mut sqlite3_context,
+    values: &[*mut sqlite3_value],
+    workers: &Arc<Workers>,
+) -> Result<()> {
+    let table = api::value_text(&values[0])?.to_owned();
+    let threads = api::value_int64(&values[1]);
+    let pool = workers.pool(context)?;
+    let handles: Vec<_> = (0..threads)
+        .map(|_| {
+            let pool = pool.clone();
+            let table = table.clone();
+            std::thread::spawn(move || {
+                let conn = pool.get()?;
+                let count: i64 = rusqlite_conn(&conn)
+                    .query_row(&format!("select count(*) from \"{}\"", table), [], |row| {
+                        row.get(0)
+                    })
+                    .map_err(|err| Error::new_message(err.to_string()))?;
+                Ok::<_, Error>(count)
+            })
+        })
+        .collect();
+    let mut total = 0;
+    for handle in handles {
+        total += handle.join().expect("worker thread")?;
+    }
+    api::result_int64(context, total);
+    Ok(())
+}
+
+/// pool_stats(), the pool's size, idle connections and init hook runs.
+pub fn pool_stats(
+    context: *mut sqlite3_context,
+    _values: &[*mut sqlite3_value],
+    workers: &Arc<Workers>,
+) -> Result<()> {
+    let pool = workers.pool(context)?;
+    api::result_text(
+        context,
+        format!(
+            "{},{},{}",
+            pool.size(),
+            pool.idle(),
+            workers.inits.load(Ordering::SeqCst)
+        ),
+    )
+}
+
+/// pool_leak() returns a connection to the pool with a transaction open.
+pub fn pool_leak(
+    context: *mut sqlite3_context,
+    _values: &[*mut sqlite3_value],
+    workers: &Arc<Workers>,
+) -> Result<()> {
+    let conn = workers.pool(context)?.get()?;
+    rusqlite_conn(&conn)
+        .execute_batch("begin; select count(*) from t;")
+        .map_err(|err| Error::new_message(err.to_string()))?;
+    drop(conn);
+    api::result_null(context);
+    Ok(())
+}
+
+/// pool_exhausted() takes every connection, and returns whether
+/// try_get() then finds none.
+pub fn pool_exhausted(
+    context: *mut sqlite3_context,
+    _values: &[*mut sqlite3_value],
+    workers: &Arc<Workers>,
+) -> Result<()> {
+    let pool = workers.pool(context)?;
+    let taken: Vec<PooledConnection> = (0..pool.max_size())
+        .map(|_| pool.get())
+        .collect::<Result<_>>()?;
+    let exhausted = pool.try_get()?.is_none();
+    drop(taken);
+    api::result_bool(context, exhausted && pool.try_get()?.is_some());
+    Ok(())
+}
+
+/// pool_write() tries to write on a pooled connection, which the init hook
+/// made read-only.
+pub fn pool_write(
+    context: *mut sqlite3_context,
+    _values: &[*mut sqlite3_value],
+    workers: &Arc<Workers>,
+) -> Result<()> {
+    let conn = workers.pool(context)?.get()?;
+    rusqlite_conn(&conn)
+        .execute_batch("insert into t values (0)")
+        .map_err(|err| Error::new_message(err.to_string()))
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_pool_init(db: *mut sqlite3) -> Result<()> {
+    let workers = Arc::new(Workers::default());
+    let flags = FunctionFlags::UTF8;
+    define_scalar_function_with_aux(db, "pool_count", 2, pool_count, flags, Arc::clone(&workers))?;
+    define_scalar_function_with_aux(db, "pool_stats", 0, pool_stats, flags, Arc::clone(&workers))?;
+    define_scalar_function_with_aux(db, "pool_leak", 0, pool_leak, flags, Arc::clone(&workers))?;
+    define_scalar_function_with_aux(
+        db,
+        "pool_exhausted",
+        0,
+        pool_exhausted,
+        flags,
+        Arc::clone(&workers),
+    )?;
+    define_scalar_function_with_aux(db, "pool_write", 0, pool_write, flags, workers)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_pool_init as *const (),
+                ),
+            ));
+        }
+        let path = std::env::temp_dir().join(format!(
+            "sqlite-loadable-test-pool-{}.db",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "
+            create table t(x);
+            with recursive n(x) as (select 1 union all select x + 1 from n where x < 1000)
+            insert into t select x from n;
+            ",
+        )
+        .unwrap();
+        let text = |sql: &str| -> String { conn.query_row(sql, [], |row| row.get(0)).unwrap() };
+        let count = |sql: &str| -> i64 { conn.query_row(sql, [], |row| row.get(0)).unwrap() };
+
+        assert_eq!(text("select pool_stats()"), "0,0,0");
+
+        // eight threads share at most two connections, which are kept for
+        // later, so the init hook only runs once for each
+        for _ in 0..3 {
+            assert_eq!(count("select pool_count('t', 8)"), 8000);
+            let stats = text("select pool_stats()");
+            assert!(stats == "1,1,1" || stats == "2,2,2", "{}", stats);
+        }
+
+        assert!(conn
+            .query_row("select pool_exhausted()", [], |row| row.get::<_, bool>(0))
+            .unwrap());
+        assert_eq!(text("select pool_stats()"), "2,2,2");
+
+        // a connection returned inside a transaction is closed
+        conn.query_row("select pool_leak()", [], |_| Ok(()))
+            .unwrap();
+        assert_eq!(text("select pool_stats()"), "1,1,2");
+
+        // the init hook made every connection read-only
+        let err = conn
+            .query_row("select pool_write()", [], |_| Ok(()))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("readonly"), "{}", err);
+        assert_eq!(count("select count(*) from t"), 1000);
+
+        let memory = Connection::open_in_memory().unwrap();
+        let err = memory
+            .query_row("select pool_stats()", [], |row| row.get::<_, String>(0))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "cannot open another connection to an in-memory or temporary database"
+        );
+
+        drop(conn);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
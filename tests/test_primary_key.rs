@@ -0,0 +1,264 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api,
+    compare::ValueRef,
+    primary_key::{Key, KeyedOperation, PrimaryKey},
+    table::{
+        define_virtual_table_writeable, BestIndexError, IndexInfo, UpdateOperation, VTab,
+        VTabArguments, VTabCursor, VTabWriteable,
+    },
+    Error, Result,
+};
+
+use std::collections::BTreeMap;
+use std::{mem, os::raw::c_int};
+
+/// grades(student, course, grade), keyed by (student, course).
+#[repr(C)]
+pub struct GradesTable {
+    /// must be first
+    base: sqlite3_vtab,
+    primary_key: PrimaryKey,
+    rows: BTreeMap<i64, (Key, i64)>,
+    rowids: BTreeMap<Key, i64>,
+}
+
+impl GradesTable {
+    fn key_of(&self, rowid: &*mut sqlite3_value) -> Result<Key> {
+        self.rows
+            .get(&api::value_int64(rowid))
+            .map(|(key, _)| key.clone())
+            .ok_or_else(|| Error::new_message("no such row"))
+    }
+
+    fn check_free(&self, key: &Key) -> Result<()> {
+        if self.rowids.contains_key(key) {
+            return Err(Error::constraint(
+                "UNIQUE constraint failed: grades.student, grades.course",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<'vtab> VTab<'vtab> for GradesTable {
+    type Aux = ();
+    type Cursor = GradesCursor<'vtab>;
+
+    fn create(
+        db: *mut sqlite3,
+        aux: Option<&Self::Aux>,
+        args: VTabArguments,
+    ) -> Result<(String, Self)> {
+        Self::connect(db, aux, args)
+    }
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, GradesTable)> {
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        Ok((
+            "CREATE TABLE x(student, course, grade)".to_owned(),
+            GradesTable {
+                base,
+                primary_key: PrimaryKey::new(&[0, 1]),
+                rows: BTreeMap::new(),
+                rowids: BTreeMap::new(),
+            },
+        ))
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        info.set_estimated_cost(100.0);
+        Ok(())
+    }
+
+    fn open(&'vtab mut self) -> Result<GradesCursor<'vtab>> {
+        Ok(GradesCursor {
+            base: unsafe { mem::zeroed() },
+            rows: &self.rows,
+            rowids: vec![],
+            index: 0,
+        })
+    }
+}
+
+impl<'vtab> VTabWriteable<'vtab> for GradesTable {
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    fn update(&'vtab mut self, operation: UpdateOperation, p_rowid: *mut i64) -> Result<()> {
+        let operation = self
+            .primary_key
+            .route(&operation, |rowid| self.key_of(rowid))?;
+        if operation.key_changed() {
+            if let KeyedOperation::Update { new_key, .. } = &operation {
+                self.check_free(new_key)?;
+            }
+        }
+        match operation {
+            KeyedOperation::Delete { key, rowid } => {
+                self.rows.remove(&api::value_int64(rowid));
+                self.rowids.remove(&key);
+            }
+            KeyedOperation::Insert { key, values } => {
+                self.check_free(&key)?;
+                let rowid = self.rows.keys().next_back().map_or(1, |last| last + 1);
+                self.rowids.insert(key.clone(), rowid);
+                self.rows.insert(rowid, (key, api::value_int64(&values[2])));
+                unsafe { *p_rowid = rowid };
+            }
+            KeyedOperation::Update {
+                old_key,
+                new_key,
+                rowid,
+                values,
+            } => {
+                let rowid = api::value_int64(rowid);
+                self.rowids.remove(&old_key);
+                self.rowids.insert(new_key.clone(), rowid);
+                self.rows
+                    .insert(rowid, (new_key, api::value_int64(&values[2])));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[repr(C)]
+pub struct GradesCursor<'vtab> {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    rows: &'vtab BTreeMap<i64, (Key, i64)>,
+    rowids: Vec<i64>,
+    index: usize,
+}
+
+impl VTabCursor for GradesCursor<'_> {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.rowids = self.rows.keys().copied().collect();
+        self.index = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.index += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.index >= self.rowids.len()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, i: c_int) -> Result<()> {
+        let (key, grade) = &self.rows[&self.rowids[self.index]];
+        match (i, key.0.get(i as usize)) {
+            (2, _) => api::result_int64(context, *grade),
+            (_, Some(ValueRef::Text(text))) => {
+                api::result_text(context, String::from_utf8_lossy(text))?
+            }
+            (_, Some(ValueRef::Integer(i))) => api::result_int64(context, *i),
+            _ => api::result_null(context),
+        }
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.rowids[self.index])
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_primarykey_init(db: *mut sqlite3) -> Result<()> {
+    define_virtual_table_writeable::<GradesTable>(db, "grades", None)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_primarykey_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "create virtual table grades using grades();
+             insert into grades values
+               ('alex', 'math', 90),
+               ('alex', 'art', 70),
+               ('brian', 'math', 80);",
+        )
+        .unwrap();
+        let rows = |sql: &str| -> Vec<(String, String, i64)> {
+            db.prepare(sql)
+                .unwrap()
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+                .unwrap()
+                .collect::<std::result::Result<_, _>>()
+                .unwrap()
+        };
+
+        // the same key twice, and a NULL key column
+        assert!(db
+            .execute("insert into grades values ('alex', 'math', 10)", [])
+            .is_err());
+        assert!(db
+            .execute("insert into grades values ('alex', null, 10)", [])
+            .is_err());
+
+        // keeping the key
+        db.execute(
+            "update grades set grade = 95 where student = 'alex' and course = 'math'",
+            [],
+        )
+        .unwrap();
+        // changing the key to a free one, and to one that's taken
+        db.execute(
+            "update grades set course = 'music' where student = 'alex' and course = 'art'",
+            [],
+        )
+        .unwrap();
+        assert!(db
+            .execute(
+                "update grades set student = 'alex' where student = 'brian'",
+                []
+            )
+            .is_err());
+        db.execute(
+            "update grades set student = 'craig' where student = 'brian'",
+            [],
+        )
+        .unwrap();
+        // a freed key can be used again
+        db.execute("delete from grades where course = 'music'", [])
+            .unwrap();
+        db.execute("insert into grades values ('alex', 'music', 60)", [])
+            .unwrap();
+        db.execute("insert into grades values ('brian', 'math', 50)", [])
+            .unwrap();
+
+        assert_eq!(
+            rows("select * from grades order by student, course"),
+            vec![
+                ("alex".to_owned(), "math".to_owned(), 95),
+                ("alex".to_owned(), "music".to_owned(), 60),
+                ("brian".to_owned(), "math".to_owned(), 50),
+                ("craig".to_owned(), "math".to_owned(), 80),
+            ]
+        );
+    }
+}
@@ -0,0 +1,251 @@
+#[cfg(feature = "protobuf")]
+use sqlite_loadable::prelude::*;
+#[cfg(feature = "protobuf")]
+use sqlite_loadable::{
+    api, define_table_function,
+    protobuf::{split_length_delimited, ProtobufColumns},
+    schema::{create_table_sql, ColumnDefinition},
+    table::{BestIndexError, ConstraintOperator, IndexInfo, VTab, VTabArguments, VTabCursor},
+    Result,
+};
+
+#[cfg(feature = "protobuf")]
+use prost_reflect::{DescriptorPool, DynamicMessage, MessageDescriptor};
+#[cfg(feature = "protobuf")]
+use prost_types::{
+    field_descriptor_proto::{Label, Type},
+    DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet,
+};
+#[cfg(feature = "protobuf")]
+use std::{mem, os::raw::c_int};
+
+/// protobuf_each(stream) reads a stream of length-delimited messages,
+/// one row per message and one column per field.
+#[cfg(feature = "protobuf")]
+#[repr(C)]
+pub struct ProtobufEachTable {
+    /// must be first
+    base: sqlite3_vtab,
+    columns: ProtobufColumns,
+}
+
+#[cfg(feature = "protobuf")]
+impl<'vtab> VTab<'vtab> for ProtobufEachTable {
+    type Aux = ProtobufColumns;
+    type Cursor = ProtobufEachCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, ProtobufEachTable)> {
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        let columns = aux.expect("message descriptor as aux").clone();
+        let mut definitions = columns.column_definitions();
+        definitions.push(ColumnDefinition::new("stream", "HIDDEN"));
+        Ok((
+            create_table_sql(&definitions),
+            ProtobufEachTable { base, columns },
+        ))
+    }
+    fn destroy(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        let stream_column = self.columns.column_definitions().len() as i32;
+        let mut has_stream = false;
+        for mut constraint in info.constraints() {
+            if constraint.column_idx() == stream_column {
+                if constraint.usable() && constraint.op() == Some(ConstraintOperator::EQ) {
+                    constraint.set_omit(true);
+                    constraint.set_argv_index(1);
+                    has_stream = true;
+                } else {
+                    return Err(BestIndexError::Constraint);
+                }
+            }
+        }
+        if !has_stream {
+            return Err(BestIndexError::Error);
+        }
+        info.set_estimated_cost(1000.0);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<ProtobufEachCursor> {
+        Ok(ProtobufEachCursor {
+            base: unsafe { mem::zeroed() },
+            columns: self.columns.clone(),
+            messages: vec![],
+            rowid: 0,
+        })
+    }
+}
+
+#[cfg(feature = "protobuf")]
+#[repr(C)]
+pub struct ProtobufEachCursor {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    columns: ProtobufColumns,
+    messages: Vec<DynamicMessage>,
+    rowid: usize,
+}
+
+#[cfg(feature = "protobuf")]
+impl VTabCursor for ProtobufEachCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        let stream = api::value_blob(values.first().expect("stream argument"));
+        self.messages = split_length_delimited(stream)?
+            .into_iter()
+            .map(|message| self.columns.decode(message))
+            .collect::<Result<_>>()?;
+        self.rowid = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.rowid += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.rowid >= self.messages.len()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, i: c_int) -> Result<()> {
+        // the hidden stream column isn't a field, and never read back
+        if self.columns.field(i as usize).is_some() {
+            self.columns
+                .result_column(context, &self.messages[self.rowid], i as usize)?;
+        }
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.rowid as i64)
+    }
+}
+
+#[cfg(feature = "protobuf")]
+fn field(name: &str, number: i32, label: Label, r#type: Type) -> FieldDescriptorProto {
+    FieldDescriptorProto {
+        name: Some(name.to_owned()),
+        number: Some(number),
+        label: Some(label as i32),
+        r#type: Some(r#type as i32),
+        json_name: Some(name.to_owned()),
+        ..Default::default()
+    }
+}
+
+#[cfg(feature = "protobuf")]
+pub fn person_descriptor() -> MessageDescriptor {
+    let file = FileDescriptorProto {
+        name: Some("person.proto".to_owned()),
+        package: Some("test".to_owned()),
+        syntax: Some("proto3".to_owned()),
+        message_type: vec![DescriptorProto {
+            name: Some("Person".to_owned()),
+            field: vec![
+                field("name", 2, Label::Optional, Type::String),
+                field("id", 1, Label::Optional, Type::Int64),
+                field("tags", 3, Label::Repeated, Type::String),
+                field("score", 4, Label::Optional, Type::Double),
+                field("avatar", 5, Label::Optional, Type::Bytes),
+            ],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    DescriptorPool::from_file_descriptor_set(FileDescriptorSet { file: vec![file] })
+        .unwrap()
+        .get_message_by_name("test.Person")
+        .unwrap()
+}
+
+#[cfg(feature = "protobuf")]
+#[sqlite_entrypoint]
+pub fn sqlite3_protobuf_init(db: *mut sqlite3) -> Result<()> {
+    let columns = ProtobufColumns::new(person_descriptor());
+    define_table_function::<ProtobufEachTable>(db, "protobuf_each", Some(columns))?;
+    Ok(())
+}
+
+#[cfg(feature = "protobuf")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use prost::Message;
+    use prost_reflect::Value;
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    fn person(descriptor: &MessageDescriptor, id: i64, name: &str, tags: &[&str]) -> Vec<u8> {
+        let mut message = DynamicMessage::new(descriptor.clone());
+        message.set_field_by_name("id", Value::I64(id));
+        message.set_field_by_name("name", Value::String(name.to_owned()));
+        message.set_field_by_name(
+            "tags",
+            Value::List(tags.iter().map(|t| Value::String(t.to_string())).collect()),
+        );
+        message.set_field_by_name("score", Value::F64(id as f64 / 2.0));
+        message.encode_length_delimited_to_vec()
+    }
+
+    #[test]
+    fn test_protobuf_each() {
+        let descriptor = person_descriptor();
+        let columns = ProtobufColumns::new(descriptor.clone());
+        assert_eq!(
+            columns.create_table_sql(),
+            r#"CREATE TABLE x("id" INTEGER, "name" TEXT, "tags" TEXT, "score" REAL, "avatar" BLOB)"#
+        );
+
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_protobuf_init as *const (),
+                ),
+            ));
+        }
+        let conn = Connection::open_in_memory().unwrap();
+
+        let mut stream = person(&descriptor, 1, "alex", &["a", "b"]);
+        stream.extend(person(&descriptor, 2, "brian", &[]));
+
+        let rows: Vec<(i64, String, String, f64, Vec<u8>)> = conn
+            .prepare("select id, name, tags, score, avatar from protobuf_each(?)")
+            .unwrap()
+            .query_map([stream], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                ))
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                (1, "alex".to_owned(), r#"["a","b"]"#.to_owned(), 0.5, vec![]),
+                (2, "brian".to_owned(), "[]".to_owned(), 1.0, vec![]),
+            ]
+        );
+
+        let err = conn
+            .query_row("select * from protobuf_each(x'05ff')", [], |_| Ok(()))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "truncated length-delimited message");
+    }
+}
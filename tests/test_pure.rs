@@ -0,0 +1,108 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{define_pure_function, Result};
+
+fn clamp(value: f64, low: f64, high: f64) -> f64 {
+    value.max(low).min(high)
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_pure_init(db: *mut sqlite3) -> Result<()> {
+    let flags = FunctionFlags::UTF8;
+    define_pure_function(db, "xyz_add", |a: i64, b: i64| a.wrapping_add(b), flags)?;
+    define_pure_function(db, "xyz_clamp", clamp, flags)?;
+    define_pure_function(db, "xyz_hypot", f64::hypot, flags)?;
+    define_pure_function(db, "xyz_not", |b: bool| !b, flags)?;
+    define_pure_function(db, "xyz_low", |i: i32| i, flags)?;
+    define_pure_function(db, "xyz_answer", || 42_i64, flags)?;
+    define_pure_function(
+        db,
+        "xyz_half",
+        |i: Option<i64>| i.filter(|i| i % 2 == 0).map(|i| i / 2),
+        flags,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, types::Value, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_pure_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        let value = |sql: &str| db.query_row(sql, [], |row| row.get::<_, Value>(0));
+
+        assert_eq!(value("select xyz_add(1, 2)").unwrap(), Value::Integer(3));
+        // SQLite's own conversions
+        assert_eq!(
+            value("select xyz_add('40', 2.9)").unwrap(),
+            Value::Integer(42)
+        );
+        assert_eq!(value("select xyz_add(null, 1)").unwrap(), Value::Integer(1));
+        assert_eq!(
+            value("select xyz_add(9223372036854775807, 1)").unwrap(),
+            Value::Integer(i64::MIN)
+        );
+        assert_eq!(
+            value("select xyz_clamp(12, 0, 10)").unwrap(),
+            Value::Real(10.0)
+        );
+        assert_eq!(value("select xyz_hypot(3, 4)").unwrap(), Value::Real(5.0));
+        assert_eq!(value("select xyz_not(0.5)").unwrap(), Value::Integer(0));
+        assert_eq!(value("select xyz_not('abc')").unwrap(), Value::Integer(1));
+        assert_eq!(
+            value("select xyz_low(4294967297)").unwrap(),
+            Value::Integer(1)
+        );
+        assert_eq!(value("select xyz_answer()").unwrap(), Value::Integer(42));
+        assert_eq!(value("select xyz_half(8)").unwrap(), Value::Integer(4));
+        assert_eq!(value("select xyz_half(7)").unwrap(), Value::Null);
+        assert_eq!(value("select xyz_half(null)").unwrap(), Value::Null);
+
+        // the number of arguments is the function's
+        let err = value("select xyz_add(1)").unwrap_err();
+        assert!(
+            err.to_string().contains("wrong number of arguments"),
+            "{}",
+            err
+        );
+
+        // deterministic, so it works in an index
+        db.execute_batch(
+            "create table t(a, b);
+             create index t_sum on t(xyz_add(a, b));
+             insert into t values (1, 2), (3, 4);",
+        )
+        .unwrap();
+        assert_eq!(
+            value("select a from t where xyz_add(a, b) = 7").unwrap(),
+            Value::Integer(3)
+        );
+    }
+
+    #[test]
+    fn test_capturing_closure() {
+        let db = Connection::open_in_memory().unwrap();
+        let offset = 10_i64;
+        let err = define_pure_function(
+            unsafe { db.handle() }.cast(),
+            "xyz_offset",
+            move |a: i64| a + offset,
+            FunctionFlags::UTF8,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.result_error_message(),
+            "pure function xyz_offset() can't capture anything"
+        );
+    }
+}
@@ -0,0 +1,76 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api, define_scalar_function,
+    random::{random_blob, random_i64},
+    Result,
+};
+
+/// uuid4(), a random UUID in its usual text form.
+pub fn uuid4(context: *mut sqlite3_context, _values: &[*mut sqlite3_value]) -> Result<()> {
+    let mut bytes = random_blob(16);
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    api::result_text(
+        context,
+        format!(
+            "{}-{}-{}-{}-{}",
+            &hex[0..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        ),
+    )
+}
+
+pub fn rand_i64(context: *mut sqlite3_context, _values: &[*mut sqlite3_value]) -> Result<()> {
+    api::result_int64(context, random_i64());
+    Ok(())
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_random_init(db: *mut sqlite3) -> Result<()> {
+    define_scalar_function(db, "uuid4", 0, uuid4, FunctionFlags::UTF8)?;
+    define_scalar_function(db, "rand_i64", 0, rand_i64, FunctionFlags::UTF8)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_random_init as *const (),
+                ),
+            ));
+        }
+        let conn = Connection::open_in_memory().unwrap();
+
+        let (a, b): (String, String) = conn
+            .query_row("select uuid4(), uuid4()", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_ne!(a, b);
+        assert_eq!(a.len(), 36);
+        assert_eq!(&a[14..15], "4");
+        assert!(matches!(&a[19..20], "8" | "9" | "a" | "b"));
+
+        let distinct: i64 = conn
+            .query_row(
+                "with recursive n(i) as (select 1 union all select i + 1 from n where i < 100)
+                 select count(distinct rand_i64()) from n",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(distinct, 100);
+    }
+}
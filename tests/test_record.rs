@@ -0,0 +1,187 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api,
+    record::{define_record_function, FieldType, RecordSchema},
+    Error, Result,
+};
+
+use serde_json::{json, Value};
+
+/// Splits `scheme://host[:port][/path]` into a record.
+fn parse_url(values: &[*mut sqlite3_value]) -> Result<Value> {
+    if api::value_is_null(&values[0]) {
+        return Ok(Value::Null);
+    }
+    let url = api::value_text(&values[0])?;
+    let (scheme, rest) = url
+        .split_once("://")
+        .ok_or_else(|| Error::new_message("not a URL"))?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], Some(&rest[i..])),
+        None => (rest, None),
+    };
+    let (host, port) = match authority.split_once(':') {
+        Some((host, port)) => (
+            host,
+            Some(
+                port.parse::<i64>()
+                    .map_err(|_| Error::new_message("invalid port"))?,
+            ),
+        ),
+        None => (authority, None),
+    };
+    Ok(json!({
+        "scheme": scheme,
+        "host": host,
+        "port": port,
+        "path": path,
+        "secure": scheme == "https",
+    }))
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_record_init(db: *mut sqlite3) -> Result<()> {
+    let schema = RecordSchema::new()
+        .field("scheme", FieldType::Text)
+        .field("host", FieldType::Text)
+        .field("port", FieldType::Integer)
+        .field("path", FieldType::Text)
+        .field("secure", FieldType::Boolean);
+    define_record_function(
+        db,
+        "parse_url",
+        1,
+        schema,
+        parse_url,
+        FunctionFlags::UTF8 | FunctionFlags::DETERMINISTIC,
+    )?;
+    let strict = RecordSchema::new().field("n", FieldType::Integer);
+    define_record_function(
+        db,
+        "bad_record",
+        1,
+        strict,
+        |values| {
+            Ok(match api::value_int(&values[0]) {
+                0 => json!({"n": "not a number"}),
+                1 => json!({"extra": 1}),
+                _ => json!([1]),
+            })
+        },
+        FunctionFlags::UTF8,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, types::Value as SqlValue, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_record_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        let value = |sql: &str| db.query_row(sql, [], |row| row.get::<_, SqlValue>(0));
+
+        let record: String = db
+            .query_row(
+                "select parse_url('https://example.com:8080/a')",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(&record).unwrap(),
+            json!({"scheme": "https", "host": "example.com", "port": 8080, "path": "/a", "secure": true})
+        );
+        // the JSON functions see it as JSON, not as a string
+        assert_eq!(
+            value("select json_object('url', parse_url('http://a'))").unwrap(),
+            SqlValue::Text(
+                r#"{"url":{"host":"a","path":null,"port":null,"scheme":"http","secure":false}}"#
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            value("select parse_url('http://a:1') ->> 'port'").unwrap(),
+            SqlValue::Integer(1)
+        );
+
+        let row = db
+            .query_row(
+                "select scheme, host, port, path, secure, typeof(port)
+                 from parse_url_unpack(parse_url('https://example.com:8080/a'))",
+                [],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, i64>(2)?,
+                        row.get::<_, String>(3)?,
+                        row.get::<_, bool>(4)?,
+                        row.get::<_, String>(5)?,
+                    ))
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            row,
+            (
+                "https".to_owned(),
+                "example.com".to_owned(),
+                8080,
+                "/a".to_owned(),
+                true,
+                "integer".to_owned()
+            )
+        );
+
+        db.execute_batch(
+            "create table urls(id integer primary key, url text);
+             insert into urls(url) values ('http://a'), ('https://b:443/x'), (null);",
+        )
+        .unwrap();
+        let hosts: Vec<(i64, String, Option<i64>)> = db
+            .prepare(
+                "select urls.id, u.host, u.port
+                 from urls, parse_url_unpack(parse_url(urls.url)) as u
+                 order by urls.id",
+            )
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<std::result::Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            hosts,
+            vec![(1, "a".to_owned(), None), (2, "b".to_owned(), Some(443))]
+        );
+
+        assert_eq!(
+            value("select count(*) from parse_url_unpack(null)").unwrap(),
+            SqlValue::Integer(0)
+        );
+        assert!(value("select * from parse_url_unpack('[1]')").is_err());
+        assert!(value("select * from parse_url_unpack").is_err());
+        assert!(value("select parse_url('nope')")
+            .unwrap_err()
+            .to_string()
+            .contains("not a URL"));
+        for (n, message) in [
+            (0, "bad_record(): field n is declared Integer"),
+            (1, "bad_record(): extra isn't a field of the record"),
+            (2, "bad_record(): a record must be a JSON object"),
+        ] {
+            let err = value(&format!("select bad_record({})", n)).unwrap_err();
+            assert!(err.to_string().contains(message), "{}", err);
+        }
+    }
+}
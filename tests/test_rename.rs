@@ -0,0 +1,195 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api, define_virtual_table,
+    table::{IndexInfo, VTab, VTabArguments, VTabCursor},
+    BestIndexError, Error, Result,
+};
+use std::os::raw::c_int;
+
+/// A rusqlite view of the connection, which doesn't close it.
+fn rusqlite_conn(db: *mut sqlite3) -> rusqlite::Connection {
+    unsafe { rusqlite::Connection::from_handle(db.cast::<rusqlite::ffi::sqlite3>()) }.unwrap()
+}
+
+fn execute(db: *mut sqlite3, sql: &str) -> Result<()> {
+    rusqlite_conn(db)
+        .execute_batch(sql)
+        .map_err(|err| Error::new_message(err.to_string()))
+}
+
+/// logbook, a table that keeps its entries in a `<name>_entries` shadow
+/// table, which has to be renamed along with it.
+#[repr(C)]
+pub struct LogbookTable {
+    /// must be first
+    base: sqlite3_vtab,
+    db: *mut sqlite3,
+    name: String,
+}
+
+impl<'vtab> VTab<'vtab> for LogbookTable {
+    type Aux = ();
+    type Cursor = LogbookCursor;
+
+    fn create(
+        db: *mut sqlite3,
+        aux: Option<&Self::Aux>,
+        args: VTabArguments,
+    ) -> Result<(String, LogbookTable)> {
+        execute(
+            db,
+            &format!("create table \"{}_entries\"(entry text)", args.table_name),
+        )?;
+        Self::connect(db, aux, args)
+    }
+
+    fn connect(
+        db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        args: VTabArguments,
+    ) -> Result<(String, LogbookTable)> {
+        let base: sqlite3_vtab = unsafe { std::mem::zeroed() };
+        Ok((
+            "CREATE TABLE x(entry TEXT)".to_owned(),
+            LogbookTable {
+                base,
+                db,
+                name: args.table_name,
+            },
+        ))
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        info.set_estimated_cost(100.0);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<LogbookCursor> {
+        let base: sqlite3_vtab_cursor = unsafe { std::mem::zeroed() };
+        let conn = rusqlite_conn(self.db);
+        let mut stmt = conn
+            .prepare(&format!("select entry from \"{}_entries\"", self.name))
+            .map_err(|err| Error::new_message(err.to_string()))?;
+        let entries = stmt
+            .query_map([], |row| row.get(0))
+            .and_then(|rows| rows.collect())
+            .map_err(|err| Error::new_message(err.to_string()))?;
+        Ok(LogbookCursor {
+            base,
+            entries,
+            index: 0,
+        })
+    }
+
+    fn rename(&mut self, new_name: &str) -> Result<()> {
+        if new_name.starts_with("tmp") {
+            return Err(Error::new_message("logbooks can't be temporary"));
+        }
+        execute(
+            self.db,
+            &format!(
+                "alter table \"{}_entries\" rename to \"{}_entries\"",
+                self.name, new_name
+            ),
+        )?;
+        self.name = new_name.to_owned();
+        Ok(())
+    }
+}
+
+#[repr(C)]
+pub struct LogbookCursor {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    entries: Vec<String>,
+    index: usize,
+}
+
+impl VTabCursor for LogbookCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.index = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.index += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.index >= self.entries.len()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, _i: c_int) -> Result<()> {
+        api::result_text(context, &self.entries[self.index])
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.index as i64)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_rename_init(db: *mut sqlite3) -> Result<()> {
+    define_virtual_table::<LogbookTable>(db, "logbook", None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_rename_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "
+            create virtual table ships using logbook();
+            insert into ships_entries values ('set sail'), ('land ho');
+            alter table ships rename to voyages;
+            ",
+        )
+        .unwrap();
+        let tables = || -> Vec<String> {
+            db.prepare("select name from sqlite_master where type = 'table' order by name")
+                .unwrap()
+                .query_map([], |row| row.get(0))
+                .unwrap()
+                .collect::<std::result::Result<_, _>>()
+                .unwrap()
+        };
+
+        // the shadow table was renamed with it, so it still has its entries
+        assert_eq!(tables(), vec!["voyages", "voyages_entries"]);
+        let entries: String = db
+            .query_row("select group_concat(entry, ', ') from voyages", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(entries, "set sail, land ho");
+
+        // an error from rename() stops the rename
+        let err = db
+            .execute_batch("alter table voyages rename to tmp_voyages")
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("logbooks can't be temporary"),
+            "{}",
+            err
+        );
+        assert_eq!(tables(), vec!["voyages", "voyages_entries"]);
+    }
+}
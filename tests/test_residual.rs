@@ -0,0 +1,211 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api::{self, ColumnAffinityMap},
+    compare::ValueRef,
+    define_virtual_table,
+    residual::{ResidualFilter, ResidualPredicates},
+    table::{BestIndexError, IndexInfo, VTab, VTabArguments, VTabCursor},
+    Result,
+};
+
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{mem, os::raw::c_int};
+
+static ROWS: &[(i64, Option<&str>)] = &[
+    (1, Some("alpha")),
+    (2, Some("beta")),
+    (3, Some("gamma")),
+    (4, Some("delta")),
+    (5, None),
+];
+
+/// Rows the cursor handed back to SQLite, after residual filtering
+static PRODUCED: AtomicUsize = AtomicUsize::new(0);
+
+static DECLARED_TYPES: [Option<&str>; 4] = [None, None, Some("integer"), Some("text")];
+
+#[repr(C)]
+pub struct ResidualTable {
+    /// must be first
+    base: sqlite3_vtab,
+}
+
+impl<'vtab> VTab<'vtab> for ResidualTable {
+    type Aux = ();
+    type Cursor = ResidualCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, ResidualTable)> {
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        Ok((
+            "CREATE TABLE x(id, name, n integer, tag text collate nocase)".to_owned(),
+            ResidualTable { base },
+        ))
+    }
+    fn destroy(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        let mut argv = 0;
+        for mut constraint in info.constraints() {
+            if constraint.usable() {
+                argv += 1;
+                constraint.set_argv_index(argv);
+            }
+        }
+        let residual = ResidualPredicates::record(&info);
+        info.set_idxstr(&residual.to_idx_str())
+            .map_err(|_| BestIndexError::Error)?;
+        info.set_estimated_cost(10.0);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<ResidualCursor> {
+        Ok(ResidualCursor {
+            base: unsafe { mem::zeroed() },
+            rowid: 0,
+            residual: ResidualFilter::default(),
+            affinities: ColumnAffinityMap::from_declared_types(DECLARED_TYPES),
+        })
+    }
+}
+
+#[repr(C)]
+pub struct ResidualCursor {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    rowid: usize,
+    residual: ResidualFilter,
+    affinities: ColumnAffinityMap,
+}
+
+impl ResidualCursor {
+    fn value_ref(&self, i: i32) -> ValueRef<'static> {
+        let (id, name) = ROWS[self.rowid];
+        match i {
+            0 | 2 | -1 => ValueRef::Integer(id),
+            _ => name.map_or(ValueRef::Null, |name| {
+                ValueRef::Text(Cow::Borrowed(name.as_bytes()))
+            }),
+        }
+    }
+    fn skip_unmatched(&mut self) {
+        while self.rowid < ROWS.len() && !self.residual.matches(|i| self.value_ref(i)) {
+            self.rowid += 1;
+        }
+        if self.rowid < ROWS.len() {
+            PRODUCED.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+impl VTabCursor for ResidualCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        idx_str: Option<&str>,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.residual =
+            ResidualPredicates::from_idx_str(idx_str.unwrap_or(""))?.bind(values, &self.affinities);
+        self.rowid = 0;
+        self.skip_unmatched();
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.rowid += 1;
+        self.skip_unmatched();
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.rowid >= ROWS.len()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, i: c_int) -> Result<()> {
+        let (id, name) = ROWS[self.rowid];
+        match (i, name) {
+            (0 | 2, _) => api::result_int64(context, id),
+            (_, Some(name)) => api::result_text(context, name)?,
+            (_, None) => api::result_null(context),
+        }
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(ROWS[self.rowid].0)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_residual_init(db: *mut sqlite3) -> Result<()> {
+    define_virtual_table::<ResidualTable>(db, "residual_rs", None)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_residual_init as *const (),
+                ),
+            ));
+        }
+
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("create virtual table t using residual_rs()", [])
+            .unwrap();
+
+        let cases: &[(&str, &[i64])] = &[
+            ("id > 2", &[3, 4, 5]),
+            ("id <= '2'", &[1, 2, 3, 4, 5]),
+            ("id = 3", &[3]),
+            ("id != 3", &[1, 2, 4, 5]),
+            ("name like 'A%'", &[1]),
+            ("name glob '*ta'", &[2, 4]),
+            ("name is null", &[5]),
+            ("name is not null and id < 4", &[1, 2, 3]),
+            ("name is 'beta'", &[2]),
+            // the text is converted for INTEGER columns and the rowid
+            ("n <= '2'", &[1, 2]),
+            ("n = '3'", &[3]),
+            ("rowid = '3'", &[3]),
+            // NOCASE columns compare text without case
+            ("tag = 'BETA'", &[2]),
+            ("tag > 'C'", &[3, 4]),
+            ("tag is 'GAMMA'", &[3]),
+            ("name = 'BETA'", &[]),
+        ];
+        for (condition, expected) in cases {
+            PRODUCED.store(0, Ordering::SeqCst);
+            let ids: Vec<i64> = conn
+                .prepare(&format!("select id from t where {} order by id", condition))
+                .unwrap()
+                .query_map([], |r| r.get(0))
+                .unwrap()
+                .collect::<rusqlite::Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(&ids, expected, "{}", condition);
+            // the cursor only produced rows that SQLite kept
+            assert_eq!(
+                PRODUCED.load(Ordering::SeqCst),
+                expected.len(),
+                "{}",
+                condition
+            );
+        }
+    }
+}
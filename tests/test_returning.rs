@@ -0,0 +1,264 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api,
+    kv::{define_kv_module, MemoryStore},
+    table::{
+        define_virtual_table_writeable, BestIndexError, IndexInfo, UpdateOperation, VTab,
+        VTabArguments, VTabCursor, VTabWriteable,
+    },
+    Error, Result,
+};
+
+use std::collections::BTreeMap;
+use std::{mem, os::raw::c_int};
+
+type Notes = BTreeMap<i64, (String, i64)>;
+
+/// notes(body, pinned), a rowid table that picks rowids itself.
+#[repr(C)]
+pub struct NotesTable {
+    /// must be first
+    base: sqlite3_vtab,
+    notes: Notes,
+}
+
+impl<'vtab> VTab<'vtab> for NotesTable {
+    type Aux = ();
+    type Cursor = NotesCursor<'vtab>;
+
+    fn create(
+        db: *mut sqlite3,
+        aux: Option<&Self::Aux>,
+        args: VTabArguments,
+    ) -> Result<(String, Self)> {
+        Self::connect(db, aux, args)
+    }
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, NotesTable)> {
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        Ok((
+            "CREATE TABLE x(body, pinned)".to_owned(),
+            NotesTable {
+                base,
+                notes: BTreeMap::new(),
+            },
+        ))
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        info.set_estimated_cost(100.0);
+        Ok(())
+    }
+
+    fn open(&'vtab mut self) -> Result<NotesCursor<'vtab>> {
+        Ok(NotesCursor {
+            base: unsafe { mem::zeroed() },
+            notes: &self.notes,
+            rowids: vec![],
+            index: 0,
+        })
+    }
+}
+
+impl<'vtab> VTabWriteable<'vtab> for NotesTable {
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    fn update(&'vtab mut self, operation: UpdateOperation, p_rowid: *mut i64) -> Result<()> {
+        match operation {
+            UpdateOperation::Delete(rowid) => {
+                self.notes.remove(&api::value_int64(rowid));
+            }
+            UpdateOperation::Insert { values, rowid, .. } => {
+                let rowid = match rowid {
+                    Some(rowid) => api::value_int64(rowid),
+                    None => self.notes.keys().next_back().map_or(1, |last| last + 1),
+                };
+                if self.notes.contains_key(&rowid) {
+                    return Err(Error::constraint("UNIQUE constraint failed: notes.rowid"));
+                }
+                self.notes.insert(rowid, note(values)?);
+                unsafe { *p_rowid = rowid };
+            }
+            UpdateOperation::Update {
+                rowid,
+                new_rowid,
+                values,
+                ..
+            } => {
+                self.notes.remove(&api::value_int64(rowid));
+                self.notes
+                    .insert(api::value_int64(new_rowid), note(values)?);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn note(values: &[*mut sqlite3_value]) -> Result<(String, i64)> {
+    Ok((
+        api::value_text(&values[0])?.to_owned(),
+        api::value_int64(&values[1]),
+    ))
+}
+
+#[repr(C)]
+pub struct NotesCursor<'vtab> {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    notes: &'vtab Notes,
+    rowids: Vec<i64>,
+    index: usize,
+}
+
+impl VTabCursor for NotesCursor<'_> {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.rowids = self.notes.keys().copied().collect();
+        self.index = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.index += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.index >= self.rowids.len()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, i: c_int) -> Result<()> {
+        let (body, pinned) = &self.notes[&self.rowids[self.index]];
+        match i {
+            0 => api::result_text(context, body)?,
+            1 => api::result_int64(context, *pinned),
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.rowids[self.index])
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_returning_init(db: *mut sqlite3) -> Result<()> {
+    define_virtual_table_writeable::<NotesTable>(db, "notes", None)?;
+    define_kv_module(db, "kv", MemoryStore::new())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_returning_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "create virtual table notes using notes();
+             create virtual table settings using kv();",
+        )
+        .unwrap();
+        let returning = |sql: &str| -> Vec<(Option<i64>, String, i64)> {
+            db.prepare(sql)
+                .unwrap()
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+                .unwrap()
+                .collect::<std::result::Result<_, _>>()
+                .unwrap()
+        };
+
+        // rowids the table picks reach last_insert_rowid(), but RETURNING
+        // is evaluated before xUpdate runs and can't see them
+        assert_eq!(
+            returning("insert into notes values ('milk', 0) returning rowid, body, pinned"),
+            vec![(Some(-1), "milk".to_owned(), 0)]
+        );
+        assert_eq!(db.last_insert_rowid(), 1);
+        assert_eq!(
+            returning(
+                "insert into notes(body, pinned) values ('eggs', 1), ('bread', 0)
+                 returning rowid, upper(body), pinned + 10"
+            ),
+            vec![
+                (Some(-1), "EGGS".to_owned(), 11),
+                (Some(-1), "BREAD".to_owned(), 10)
+            ]
+        );
+        assert_eq!(db.last_insert_rowid(), 3);
+
+        // rowids the statement gives
+        assert_eq!(
+            returning("insert into notes(rowid, body, pinned) values (10, 'tea', 1) returning rowid, body, pinned"),
+            vec![(Some(10), "tea".to_owned(), 1)]
+        );
+        assert_eq!(
+            returning(
+                "insert into notes(rowid, body, pinned)
+                 select 20 + rowid, body || '!', pinned from notes where rowid < 3
+                 returning rowid, body, pinned"
+            ),
+            vec![
+                (Some(21), "milk!".to_owned(), 0),
+                (Some(22), "eggs!".to_owned(), 1)
+            ]
+        );
+        assert_eq!(db.last_insert_rowid(), 22);
+
+        // SQLite only supports RETURNING on INSERTs into virtual tables
+        for sql in [
+            "update notes set pinned = 1 where rowid = 3 returning rowid",
+            "delete from notes where rowid >= 21 returning rowid",
+        ] {
+            let err = db.prepare(sql).err().unwrap();
+            assert!(err
+                .to_string()
+                .contains("RETURNING is not available on virtual tables"));
+        }
+        assert!(db
+            .execute(
+                "insert into notes(rowid, body, pinned) values (1, 'dup', 0)",
+                []
+            )
+            .is_err());
+
+        // WITHOUT ROWID tables return their primary key
+        let entries = |sql: &str| -> Vec<(String, String)> {
+            db.prepare(sql)
+                .unwrap()
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+                .unwrap()
+                .collect::<std::result::Result<_, _>>()
+                .unwrap()
+        };
+        assert_eq!(
+            entries("insert into settings values ('a', '1'), ('b', '2') returning key, value"),
+            vec![
+                ("a".to_owned(), "1".to_owned()),
+                ("b".to_owned(), "2".to_owned())
+            ]
+        );
+        assert_eq!(
+            entries("insert or replace into settings values ('a', '3') returning key, value"),
+            vec![("a".to_owned(), "3".to_owned())]
+        );
+    }
+}
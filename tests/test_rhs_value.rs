@@ -0,0 +1,191 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api,
+    compare::ValueRef,
+    define_virtual_table,
+    table::{BestIndexError, ConstraintOperator, IndexInfo, VTab, VTabArguments, VTabCursor},
+    Result,
+};
+use std::{mem, os::raw::c_int};
+
+/// readings, one row a second for `ts` 0 to 999.
+const ROWS: i64 = 1000;
+
+#[repr(C)]
+pub struct ReadingsTable {
+    /// must be first
+    base: sqlite3_vtab,
+}
+
+impl<'vtab> VTab<'vtab> for ReadingsTable {
+    type Aux = ();
+    type Cursor = ReadingsCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, ReadingsTable)> {
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        Ok((
+            "CREATE TABLE x(ts, label)".to_owned(),
+            ReadingsTable { base },
+        ))
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        // narrow [start, end) with the range constraints on ts that have a
+        // known right-hand side, SQLite checks the constraints itself
+        let (mut start, mut end) = (0, ROWS);
+        let mut known = Vec::new();
+        for constraint in info.constraints() {
+            if constraint.column_idx() != 0 || !constraint.usable() {
+                continue;
+            }
+            let rhs = match constraint.rhs_value() {
+                Some(ValueRef::Integer(i)) => i,
+                Some(value) => {
+                    known.push(format!("{:?}", value));
+                    continue;
+                }
+                None => {
+                    known.push("?".to_owned());
+                    continue;
+                }
+            };
+            known.push(rhs.to_string());
+            match constraint.op() {
+                Some(ConstraintOperator::GT) => start = start.max(rhs + 1),
+                Some(ConstraintOperator::GE) => start = start.max(rhs),
+                Some(ConstraintOperator::LT) => end = end.min(rhs),
+                Some(ConstraintOperator::LE) => end = end.min(rhs + 1),
+                _ => (),
+            }
+        }
+        let rows = (end - start).max(0);
+        info.set_idxnum(rows as c_int);
+        info.set_idxstr(&known.join(","))
+            .map_err(|_| BestIndexError::Error)?;
+        info.set_estimated_rows(rows);
+        info.set_estimated_cost(rows as f64);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<ReadingsCursor> {
+        Ok(ReadingsCursor {
+            base: unsafe { mem::zeroed() },
+            ts: 0,
+        })
+    }
+}
+
+#[repr(C)]
+pub struct ReadingsCursor {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    ts: i64,
+}
+
+impl VTabCursor for ReadingsCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.ts = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.ts += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.ts >= ROWS
+    }
+
+    fn column(&self, context: *mut sqlite3_context, i: c_int) -> Result<()> {
+        match i {
+            0 => api::result_int64(context, self.ts),
+            _ => api::result_text(context, format!("t{}", self.ts))?,
+        }
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.ts)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_rhsvalue_init(db: *mut sqlite3) -> Result<()> {
+    define_virtual_table::<ReadingsTable>(db, "readings", None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_rhsvalue_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("create virtual table t using readings()")
+            .unwrap();
+        // the plan's detail shows idxNum, the estimated rows, and idxStr,
+        // the right-hand sides xBestIndex saw
+        let plan = |sql: &str| -> String {
+            db.query_row(&format!("explain query plan {}", sql), [], |row| row.get(3))
+                .unwrap()
+        };
+
+        assert_eq!(plan("select * from t"), "SCAN t VIRTUAL TABLE INDEX 1000:");
+        assert_eq!(
+            plan("select * from t where ts >= 100 and ts < 150"),
+            "SCAN t VIRTUAL TABLE INDEX 50:100,150"
+        );
+        assert_eq!(
+            plan("select * from t where ts > 990"),
+            "SCAN t VIRTUAL TABLE INDEX 9:990"
+        );
+        // bound parameters aren't known until xFilter, so rhs_value() is
+        // None for them
+        let bound: String = db
+            .query_row(
+                "explain query plan select * from t where ts < ?",
+                [50],
+                |row| row.get(3),
+            )
+            .unwrap();
+        assert_eq!(bound, "SCAN t VIRTUAL TABLE INDEX 1000:?");
+        let count: i64 = db
+            .query_row("select count(*) from t where ts < ?", [50], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(count, 50);
+        assert_eq!(
+            plan("select * from t where ts = 'abc'"),
+            "SCAN t VIRTUAL TABLE INDEX 1000:Text([97, 98, 99])"
+        );
+
+        let count: i64 = db
+            .query_row(
+                "select count(*) from t where ts >= 100 and ts < 150",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(count, 50);
+    }
+}
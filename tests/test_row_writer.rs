@@ -0,0 +1,150 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    define_table_function,
+    table::{BestIndexError, IndexInfo, RowWriter, VTab, VTabArguments, VTabRowCursor},
+    Result,
+};
+
+use std::{
+    mem,
+    os::raw::c_int,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+
+/// How many times the label column was produced.
+static LABELS: AtomicUsize = AtomicUsize::new(0);
+/// The columns used by the last query, as seen by xBestIndex.
+static COLUMNS_USED: AtomicU64 = AtomicU64::new(0);
+
+/// wide, the numbers 1 to 3 with their squares and labels.
+#[repr(C)]
+pub struct WideTable {
+    /// must be first
+    base: sqlite3_vtab,
+}
+
+impl<'vtab> VTab<'vtab> for WideTable {
+    type Aux = ();
+    type Cursor = WideCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, WideTable)> {
+        let base: sqlite3_vtab = unsafe { mem::zeroed() };
+        Ok((
+            "CREATE TABLE x(id, square, label)".to_owned(),
+            WideTable { base },
+        ))
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        let used = (0..3)
+            .filter(|i| info.column_used(*i))
+            .map(|i| 1 << i)
+            .sum();
+        COLUMNS_USED.store(used, Ordering::SeqCst);
+        info.set_estimated_cost(3.0);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<WideCursor> {
+        Ok(WideCursor {
+            base: unsafe { mem::zeroed() },
+            id: 1,
+        })
+    }
+}
+
+#[repr(C)]
+pub struct WideCursor {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    id: i64,
+}
+
+impl VTabRowCursor for WideCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.id = 1;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.id += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.id > 3
+    }
+
+    fn row(&self, w: &mut RowWriter) -> Result<()> {
+        w.int64(0, || self.id)?
+            .int64(1, || self.id * self.id)?
+            .text(2, || {
+                LABELS.fetch_add(1, Ordering::SeqCst);
+                format!("number {}", self.id)
+            })?;
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.id)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_rowwriter_init(db: *mut sqlite3) -> Result<()> {
+    define_table_function::<WideTable>(db, "wide", None)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_rowwriter_init as *const (),
+                ),
+            ));
+        }
+        let conn = Connection::open_in_memory().unwrap();
+
+        let squares: i64 = conn
+            .query_row("select sum(square) from wide", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(squares, 1 + 4 + 9);
+        assert_eq!(COLUMNS_USED.load(Ordering::SeqCst), 0b010);
+        assert_eq!(LABELS.load(Ordering::SeqCst), 0);
+
+        let rows: Vec<(i64, i64, String)> = conn
+            .prepare("select * from wide")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<std::result::Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                (1, 1, "number 1".to_owned()),
+                (2, 4, "number 2".to_owned()),
+                (3, 9, "number 3".to_owned())
+            ]
+        );
+        assert_eq!(COLUMNS_USED.load(Ordering::SeqCst), 0b111);
+        assert_eq!(LABELS.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,204 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api, define_virtual_table_writeable,
+    table::{IndexInfo, VTab, VTabArguments, VTabCursor, VTabRowidWriteable},
+    BestIndexError, Error, Result,
+};
+use std::collections::BTreeMap;
+use std::os::raw::c_int;
+use std::sync::{Arc, Mutex};
+
+type Notes = Arc<Mutex<BTreeMap<i64, String>>>;
+
+/// notes, a table of text by rowid kept in a map, like a key-value store.
+#[repr(C)]
+pub struct NotesTable {
+    /// must be first
+    base: sqlite3_vtab,
+    notes: Notes,
+}
+
+impl<'vtab> VTab<'vtab> for NotesTable {
+    type Aux = ();
+    type Cursor = NotesCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, NotesTable)> {
+        let base: sqlite3_vtab = unsafe { std::mem::zeroed() };
+        Ok((
+            "CREATE TABLE x(body TEXT)".to_owned(),
+            NotesTable {
+                base,
+                notes: Notes::default(),
+            },
+        ))
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        info.set_estimated_cost(100.0);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<NotesCursor> {
+        let base: sqlite3_vtab_cursor = unsafe { std::mem::zeroed() };
+        Ok(NotesCursor {
+            base,
+            notes: Arc::clone(&self.notes),
+            rows: Vec::new(),
+            index: 0,
+        })
+    }
+}
+
+impl NotesTable {
+    fn notes(&self) -> std::sync::MutexGuard<'_, BTreeMap<i64, String>> {
+        self.notes.lock().unwrap()
+    }
+}
+
+impl<'vtab> VTabRowidWriteable<'vtab> for NotesTable {
+    fn insert(&'vtab mut self, rowid: Option<i64>, values: &[*mut sqlite3_value]) -> Result<i64> {
+        let body = api::value_text(&values[0])?.to_owned();
+        let mut notes = self.notes();
+        let rowid = rowid.unwrap_or_else(|| notes.keys().last().map_or(1, |last| last + 1));
+        if notes.contains_key(&rowid) {
+            return Err(Error::constraint(format!("note {} already exists", rowid)));
+        }
+        notes.insert(rowid, body);
+        Ok(rowid)
+    }
+
+    fn update(
+        &'vtab mut self,
+        rowid: i64,
+        new_rowid: i64,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        let body = api::value_text(&values[0])?.to_owned();
+        let mut notes = self.notes();
+        notes.remove(&rowid);
+        notes.insert(new_rowid, body);
+        Ok(())
+    }
+
+    fn delete(&'vtab mut self, rowid: i64) -> Result<()> {
+        self.notes().remove(&rowid);
+        Ok(())
+    }
+}
+
+#[repr(C)]
+pub struct NotesCursor {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    notes: Notes,
+    /// The notes as of the scan's start.
+    rows: Vec<(i64, String)>,
+    index: usize,
+}
+
+impl VTabCursor for NotesCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.rows = self
+            .notes
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(rowid, body)| (*rowid, body.clone()))
+            .collect();
+        self.index = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.index += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.index >= self.rows.len()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, _i: c_int) -> Result<()> {
+        api::result_text(context, &self.rows[self.index].1)
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.rows[self.index].0)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_rowidwriteable_init(db: *mut sqlite3) -> Result<()> {
+    define_virtual_table_writeable::<NotesTable>(db, "notes", None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_rowidwriteable_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("create virtual table n using notes()")
+            .unwrap();
+        let rows = || -> Vec<(i64, String)> {
+            db.prepare("select rowid, body from n")
+                .unwrap()
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+                .unwrap()
+                .collect::<std::result::Result<_, _>>()
+                .unwrap()
+        };
+
+        // the table picks rowids, and they're what last_insert_rowid() sees
+        db.execute("insert into n(body) values ('first')", [])
+            .unwrap();
+        assert_eq!(db.last_insert_rowid(), 1);
+        db.execute("insert into n(rowid, body) values (10, 'tenth')", [])
+            .unwrap();
+        db.execute("insert into n(body) values ('eleventh')", [])
+            .unwrap();
+        assert_eq!(db.last_insert_rowid(), 11);
+        assert_eq!(
+            rows(),
+            vec![
+                (1, "first".to_owned()),
+                (10, "tenth".to_owned()),
+                (11, "eleventh".to_owned())
+            ]
+        );
+
+        db.execute("update n set body = upper(body) where rowid = 10", [])
+            .unwrap();
+        db.execute("update n set rowid = 2 where rowid = 11", [])
+            .unwrap();
+        db.execute("delete from n where rowid = 1", []).unwrap();
+        assert_eq!(
+            rows(),
+            vec![(2, "eleventh".to_owned()), (10, "TENTH".to_owned())]
+        );
+
+        let err = db
+            .execute("insert into n(rowid, body) values (2, 'again')", [])
+            .unwrap_err();
+        assert_eq!(err.to_string(), "note 2 already exists");
+    }
+}
@@ -0,0 +1,94 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    rtree::{define_rtree_geometry_function, define_rtree_query_function, Within},
+    Error, Result,
+};
+
+/// circle(x, y, radius) on a 2D R*Tree, ordering results by distance from
+/// the center.
+fn circle(info: &mut sqlite_loadable::rtree::QueryInfo) -> Result<()> {
+    let (x, y, radius) = (info.param(0)?, info.param(1)?, info.param(2)?);
+    let bounding_box = info.bounding_box();
+    let ((min_x, max_x), (min_y, max_y)) = match (bounding_box.range(0), bounding_box.range(1)) {
+        (Some(x), Some(y)) => (x, y),
+        _ => return Err(Error::new_message("circle() requires a 2D R*Tree")),
+    };
+    // distance from the center to the closest point of the box
+    let dx = (min_x - x).max(0.0).max(x - max_x);
+    let dy = (min_y - y).max(0.0).max(y - max_y);
+    let distance = (dx * dx + dy * dy).sqrt();
+    if distance > radius {
+        info.set_within(Within::Not);
+    } else {
+        info.set_within(Within::Partly);
+        info.set_score(distance);
+    }
+    if info.is_entry() {
+        *info.user_data::<usize>() += 1;
+        assert!(info.rowid().is_some());
+    }
+    Ok(())
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_rtree_init(db: *mut sqlite3) -> Result<()> {
+    define_rtree_query_function(db, "circle", circle)?;
+    define_rtree_geometry_function(db, "above", |params, bounding_box| {
+        match (params, bounding_box.range(1)) {
+            ([y], Some((_, max_y))) => Ok(max_y > *y),
+            _ => Err(Error::new_message("above() requires 1 argument")),
+        }
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_rtree_init as *const (),
+                ),
+            ));
+        }
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "
+            create virtual table points using rtree(id, min_x, max_x, min_y, max_y);
+            insert into points values (1, 0, 0, 0, 0), (2, 3, 3, 0, 0), (3, 1, 1, 1, 1), (4, 10, 10, 10, 10);
+            ",
+        )
+        .unwrap();
+
+        let ids = |sql: &str| -> Vec<i64> {
+            conn.prepare(sql)
+                .unwrap()
+                .query_map([], |row| row.get(0))
+                .unwrap()
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .unwrap()
+        };
+        assert_eq!(
+            ids("select id from points where id match circle(3, 0, 3)"),
+            vec![2, 3, 1]
+        );
+        let mut above = ids("select id from points where id match above(0.5)");
+        above.sort();
+        assert_eq!(above, vec![3, 4]);
+
+        let err = conn
+            .query_row(
+                "select id from points where id match circle(1, 2)",
+                [],
+                |row| row.get::<_, i64>(0),
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("error"), "{}", err);
+    }
+}
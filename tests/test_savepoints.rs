@@ -0,0 +1,240 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api, define_virtual_table_writeable_with_nested_transactions,
+    table::{
+        IndexInfo, VTab, VTabArguments, VTabCursor, VTabRowidWriteable,
+        VTabWriteableNestedTransactions, VTabWriteableWithTransactions,
+    },
+    BestIndexError, Result,
+};
+use std::os::raw::c_int;
+
+/// words, a table of words kept in memory, which undoes its changes when
+/// a transaction or savepoint rolls back.
+#[repr(C)]
+pub struct WordsTable {
+    /// must be first
+    base: sqlite3_vtab,
+    words: Vec<String>,
+    /// The words when the transaction began.
+    begun: Vec<String>,
+    /// The words at each open savepoint, by its id.
+    savepoints: Vec<(c_int, Vec<String>)>,
+}
+
+impl<'vtab> VTab<'vtab> for WordsTable {
+    type Aux = ();
+    type Cursor = WordsCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, WordsTable)> {
+        let base: sqlite3_vtab = unsafe { std::mem::zeroed() };
+        Ok((
+            "CREATE TABLE x(word TEXT)".to_owned(),
+            WordsTable {
+                base,
+                words: Vec::new(),
+                begun: Vec::new(),
+                savepoints: Vec::new(),
+            },
+        ))
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        info.set_estimated_cost(100.0);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<WordsCursor> {
+        let base: sqlite3_vtab_cursor = unsafe { std::mem::zeroed() };
+        Ok(WordsCursor {
+            base,
+            words: self.words.clone(),
+            index: 0,
+        })
+    }
+}
+
+impl<'vtab> VTabRowidWriteable<'vtab> for WordsTable {
+    fn insert(&'vtab mut self, _rowid: Option<i64>, values: &[*mut sqlite3_value]) -> Result<i64> {
+        self.words.push(api::value_text(&values[0])?.to_owned());
+        Ok(self.words.len() as i64)
+    }
+
+    fn update(
+        &'vtab mut self,
+        rowid: i64,
+        _new_rowid: i64,
+        values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.words[rowid as usize - 1] = api::value_text(&values[0])?.to_owned();
+        Ok(())
+    }
+
+    fn delete(&'vtab mut self, rowid: i64) -> Result<()> {
+        self.words.remove(rowid as usize - 1);
+        Ok(())
+    }
+}
+
+impl<'vtab> VTabWriteableWithTransactions<'vtab> for WordsTable {
+    fn begin(&'vtab mut self) -> Result<()> {
+        self.begun = self.words.clone();
+        Ok(())
+    }
+
+    fn commit(&'vtab mut self) -> Result<()> {
+        self.savepoints.clear();
+        Ok(())
+    }
+
+    fn rollback(&'vtab mut self) -> Result<()> {
+        self.words = std::mem::take(&mut self.begun);
+        self.savepoints.clear();
+        Ok(())
+    }
+}
+
+impl<'vtab> VTabWriteableNestedTransactions<'vtab> for WordsTable {
+    fn savepoint(&'vtab mut self, id: c_int) -> Result<()> {
+        self.savepoints.retain(|(open, _)| *open < id);
+        self.savepoints.push((id, self.words.clone()));
+        Ok(())
+    }
+
+    fn release(&'vtab mut self, id: c_int) -> Result<()> {
+        self.savepoints.retain(|(open, _)| *open < id);
+        Ok(())
+    }
+
+    fn rollback_to(&'vtab mut self, id: c_int) -> Result<()> {
+        self.savepoints.retain(|(open, _)| *open <= id);
+        // a savepoint from before begin() leaves the words as they were then
+        self.words = match self.savepoints.last() {
+            Some((_, words)) => words.clone(),
+            None => self.begun.clone(),
+        };
+        Ok(())
+    }
+}
+
+#[repr(C)]
+pub struct WordsCursor {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    words: Vec<String>,
+    index: usize,
+}
+
+impl VTabCursor for WordsCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.index = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.index += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.index >= self.words.len()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, _i: c_int) -> Result<()> {
+        api::result_text(context, &self.words[self.index])
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.index as i64 + 1)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_savepoints_init(db: *mut sqlite3) -> Result<()> {
+    define_virtual_table_writeable_with_nested_transactions::<WordsTable>(db, "words", None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_savepoints_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("create virtual table w using words()")
+            .unwrap();
+        let words = || -> String {
+            db.query_row("select group_concat(word, ' ') from w", [], |row| {
+                row.get::<_, Option<String>>(0)
+            })
+            .unwrap()
+            .unwrap_or_default()
+        };
+
+        db.execute_batch(
+            "
+            begin;
+            insert into w values ('one');
+            savepoint a;
+            insert into w values ('two');
+            savepoint b;
+            insert into w values ('three');
+            ",
+        )
+        .unwrap();
+        assert_eq!(words(), "one two three");
+        db.execute_batch("rollback to b").unwrap();
+        assert_eq!(words(), "one two");
+        // b is still open after rolling back to it
+        db.execute_batch("insert into w values ('again'); rollback to b")
+            .unwrap();
+        assert_eq!(words(), "one two");
+        db.execute_batch("rollback to a").unwrap();
+        assert_eq!(words(), "one");
+        db.execute_batch(
+            "
+            insert into w values ('four');
+            release a;
+            commit;
+            ",
+        )
+        .unwrap();
+        assert_eq!(words(), "one four");
+
+        // a savepoint from before the table's first write
+        db.execute_batch(
+            "
+            savepoint outer;
+            savepoint inner;
+            insert into w values ('five');
+            rollback to outer;
+            release outer;
+            ",
+        )
+        .unwrap();
+        assert_eq!(words(), "one four");
+
+        // and a whole transaction
+        db.execute_batch("begin; insert into w values ('six'); rollback")
+            .unwrap();
+        assert_eq!(words(), "one four");
+    }
+}
@@ -0,0 +1,88 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{Error, Result};
+
+/// repeat(text, count)
+#[scalar_function(deterministic)]
+fn repeat(text: &str, count: i64) -> String {
+    text.repeat(count.max(0) as usize)
+}
+
+#[scalar_function(name = "checked_div")]
+fn divide(a: i64, b: i64) -> Result<Option<i64>> {
+    if b == 0 {
+        return Err(Error::new_message("division by zero"));
+    }
+    Ok(a.checked_div(b))
+}
+
+#[scalar_function]
+fn greeting() -> &'static str {
+    "hello"
+}
+
+#[scalar_function(deterministic, innocuous)]
+fn initial(name: Option<String>) -> Option<String> {
+    name.and_then(|name| name.chars().next()).map(String::from)
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_scalarfunction_init(db: *mut sqlite3) -> Result<()> {
+    repeat::define(db)?;
+    divide::define(db)?;
+    greeting::define(db)?;
+    initial::define(db)?;
+
+    // the glue works for functions in a function body too
+    #[scalar_function(deterministic)]
+    fn shout(text: &str) -> String {
+        text.to_uppercase()
+    }
+    shout::define(db)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, types::Value, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_scalarfunction_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        let value = |sql: &str| db.query_row(sql, [], |row| row.get::<_, Value>(0));
+        let error = |sql: &str| value(sql).unwrap_err().to_string();
+        let text = |s: &str| Value::Text(s.to_owned());
+
+        // the functions are still plain Rust functions
+        assert_eq!(repeat("ab", 2), "abab");
+        assert_eq!(divide::NAME, "checked_div");
+        assert_eq!(divide::NUM_ARGS, 2);
+        assert_eq!(greeting::NUM_ARGS, 0);
+
+        assert_eq!(value("select repeat('ab', 3)").unwrap(), text("ababab"));
+        assert_eq!(
+            value("select checked_div(7, 2)").unwrap(),
+            Value::Integer(3)
+        );
+        assert_eq!(value("select greeting()").unwrap(), text("hello"));
+        assert_eq!(value("select initial('Ada')").unwrap(), text("A"));
+        assert_eq!(value("select initial(null)").unwrap(), Value::Null);
+        assert_eq!(value("select shout('hi')").unwrap(), text("HI"));
+
+        assert_eq!(error("select checked_div(1, 0)"), "division by zero");
+        assert_eq!(
+            error("select repeat('ab', 'x')"),
+            "argument 2: expected an integer, got text"
+        );
+        assert!(error("select repeat('ab')").contains("wrong number of arguments"));
+        assert!(error("select divide(1, 2)").contains("no such function"));
+    }
+}
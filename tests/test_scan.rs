@@ -0,0 +1,107 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{api, define_scalar_function, scan, Error, Result};
+
+/// line_count(value), the number of lines in a text or blob.
+pub fn line_count(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let bytes = api::value_blob(&values[0]);
+    api::result_int64(context, scan::count_lines(bytes) as i64);
+    Ok(())
+}
+
+/// nth_line(value, n), the nth line of a text or blob, counting from 1.
+pub fn nth_line(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let bytes = api::value_blob(&values[0]);
+    let n = api::value_int64(&values[1]);
+    match usize::try_from(n.saturating_sub(1))
+        .ok()
+        .and_then(|i| scan::lines(bytes).nth(i))
+    {
+        Some(line) => {
+            let line = scan::validate_utf8(line)
+                .map_err(|err| Error::new_message(format!("line {} isn't UTF-8: {}", n, err)))?;
+            api::result_text(context, line)?;
+        }
+        None => api::result_null(context),
+    }
+    Ok(())
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_scan_init(db: *mut sqlite3) -> Result<()> {
+    let flags = FunctionFlags::UTF8 | FunctionFlags::DETERMINISTIC;
+    define_scalar_function(db, "line_count", 1, line_count, flags)?;
+    define_scalar_function(db, "nth_line", 2, nth_line, flags)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, types::Value, Connection};
+
+    #[test]
+    fn test_lines() {
+        for text in ["", "a", "a\n", "a\r\nb", "\n\n", "x\ny\r\n\r\nz\r"] {
+            let lines: Vec<&[u8]> = scan::lines(text.as_bytes()).collect();
+            let expected: Vec<&[u8]> = text.lines().map(str::as_bytes).collect();
+            assert_eq!(lines, expected, "{:?}", text);
+            assert_eq!(scan::count_lines(text.as_bytes()), expected.len());
+        }
+    }
+
+    #[test]
+    fn test_find() {
+        let field = b"name,\"say \"\"hi\"\"\"\r\n";
+        assert_eq!(scan::find_byte(field, b','), Some(4));
+        assert_eq!(scan::find_byte(field, b';'), None);
+        assert_eq!(scan::find_any(field, b"\"\r"), Some(5));
+        assert_eq!(scan::find_any(field, b"\n\r,"), Some(4));
+        assert_eq!(scan::find_any(field, b"xz\n"), Some(18));
+        assert_eq!(scan::find_any(field, b""), None);
+        assert_eq!(scan::count_byte(field, b'"'), 6);
+
+        assert_eq!(scan::validate_utf8("héllo".as_bytes()), Ok("héllo"));
+        let err = scan::validate_utf8(b"ab\xffcd").unwrap_err();
+        assert_eq!(err.valid_up_to(), 2);
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_scan_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        let value = |sql: &str| db.query_row(sql, [], |row| row.get::<_, Value>(0));
+
+        assert_eq!(
+            value("select line_count('a' || char(10) || 'b' || char(10))").unwrap(),
+            Value::Integer(2)
+        );
+        assert_eq!(
+            value("select line_count(cast('a' || char(13, 10) || 'b' as blob))").unwrap(),
+            Value::Integer(2)
+        );
+        assert_eq!(value("select line_count('')").unwrap(), Value::Integer(0));
+        // a few megabytes, one line a row of a log
+        assert_eq!(
+            value(
+                "with recursive n(i) as (select 1 union all select i + 1 from n where i < 1 << 20)
+                 select line_count(group_concat('line ' || i, char(10))) from n"
+            )
+            .unwrap(),
+            Value::Integer(1 << 20)
+        );
+        assert_eq!(
+            value("select nth_line('one' || char(13, 10) || 'two', 2)").unwrap(),
+            Value::Text("two".to_owned())
+        );
+        assert_eq!(value("select nth_line('one', 2)").unwrap(), Value::Null);
+        let err = value("select nth_line(x'610aff', 2)").unwrap_err();
+        assert!(err.to_string().contains("line 2 isn't UTF-8"), "{}", err);
+    }
+}
@@ -0,0 +1,120 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api, define_scalar_function,
+    schema::{verify, ColumnDefinition, ShadowTable},
+    Result,
+};
+
+fn shadow_tables() -> Vec<ShadowTable> {
+    vec![
+        ShadowTable::new(
+            "data",
+            vec![
+                ColumnDefinition {
+                    name: "id".to_owned(),
+                    declared_type: "INTEGER".to_owned(),
+                    not_null: true,
+                },
+                ColumnDefinition::new("body", "TEXT"),
+            ],
+        ),
+        ShadowTable::new(
+            "config",
+            vec![
+                ColumnDefinition::new("key", "TEXT"),
+                ColumnDefinition::new("value", ""),
+            ],
+        ),
+    ]
+}
+
+/// verify_shadow_tables(vtab_name) checks the shadow tables of vtab_name in
+/// the main schema, returning 1 if they match.
+pub fn verify_shadow_tables(
+    context: *mut sqlite3_context,
+    values: &[*mut sqlite3_value],
+) -> Result<()> {
+    let vtab_name = api::value_text(&values[0])?;
+    verify(
+        api::context_db_handle(context),
+        "main",
+        vtab_name,
+        &shadow_tables(),
+    )?;
+    api::result_int(context, 1);
+    Ok(())
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_schemaverify_init(db: *mut sqlite3) -> Result<()> {
+    define_scalar_function(
+        db,
+        "verify_shadow_tables",
+        1,
+        verify_shadow_tables,
+        FunctionFlags::UTF8,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_schemaverify_init as *const (),
+                ),
+            ));
+        }
+        let conn = Connection::open_in_memory().unwrap();
+        for shadow_table in shadow_tables() {
+            conn.execute(&shadow_table.create_sql("main", "docs"), [])
+                .unwrap();
+        }
+        let verified: i64 = conn
+            .query_row("select verify_shadow_tables('docs')", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(verified, 1);
+
+        conn.execute_batch(
+            "
+            create table old_data(id integer, body blob, extra);
+            ",
+        )
+        .unwrap();
+        let err = conn
+            .query_row("select verify_shadow_tables('old')", [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "shadow tables of main.old don't match this version of the extension, a migration is needed: \
+            column \"id\" of old_data is nullable, expected NOT NULL; \
+            column \"body\" of old_data has type \"BLOB\", expected \"TEXT\"; \
+            old_data has unexpected column \"extra\"; \
+            shadow table old_config is missing (create it with: CREATE TABLE \"main\".\"old_config\"(\"key\" TEXT, \"value\"))"
+        );
+
+        conn.execute_batch("create table partial_data(id integer not null); create table partial_config(key text, value)")
+            .unwrap();
+        let err = conn
+            .query_row("select verify_shadow_tables('partial')", [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .unwrap_err();
+        assert!(
+            err.to_string().ends_with(
+                "partial_data is missing column \"body\" (add it with: ALTER TABLE \"partial_data\" ADD COLUMN \"body\" TEXT)"
+            ),
+            "{}",
+            err
+        );
+    }
+}
@@ -0,0 +1,173 @@
+#[cfg(feature = "script")]
+use sqlite_loadable::prelude::*;
+#[cfg(feature = "script")]
+use sqlite_loadable::{
+    script::{define_script_function, define_script_host, ScriptCache, CACHE_CAPACITY},
+    Result,
+};
+
+#[cfg(feature = "script")]
+#[sqlite_entrypoint]
+pub fn sqlite3_script_init(db: *mut sqlite3) -> Result<()> {
+    define_script_function(db, "clamp", 3, "args[0].max(args[1]).min(args[2])")?;
+    define_script_function(
+        db,
+        "describe",
+        1,
+        r#"
+        let value = args[0];
+        switch type_of(value) {
+            "()" => "null",
+            "i64" => `integer ${value}`,
+            "f64" => `real ${value}`,
+            "string" => `text ${value.len()}`,
+            "blob" => `blob ${value.len()}`,
+        }
+        "#,
+    )?;
+    define_script_host(db)
+}
+
+#[cfg(all(test, feature = "script"))]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, types::Value, Connection};
+
+    #[test]
+    fn test_script_cache() {
+        let cache = ScriptCache::new();
+        let first = cache.compile("40 + 2").unwrap();
+        let second = cache.compile("40 + 2").unwrap();
+        assert!(std::rc::Rc::ptr_eq(&first, &second));
+        cache.compile("1").unwrap();
+        assert_eq!(cache.stats(), (1, 2));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.engine().eval_ast::<i64>(&first).unwrap(), 42);
+
+        assert!(cache.invalidate("1"));
+        assert!(!cache.invalidate("1"));
+        cache.compile("1").unwrap();
+        assert_eq!(cache.stats(), (1, 3));
+        assert!(cache.compile("1 +").is_err());
+        assert_eq!(cache.clear(), 2);
+        assert!(cache.is_empty());
+
+        for i in 0..CACHE_CAPACITY + 1 {
+            cache.compile(&i.to_string()).unwrap();
+        }
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_script_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        let value = |sql: &str| db.query_row(sql, [], |row| row.get::<_, Value>(0));
+        let text = |sql: &str| {
+            db.query_row(sql, [], |row| row.get::<_, String>(0))
+                .unwrap()
+        };
+
+        assert_eq!(
+            value("select clamp(15, 0, 10)").unwrap(),
+            Value::Integer(10)
+        );
+        assert_eq!(
+            value("select clamp(-2.5, 0.0, 10.0)").unwrap(),
+            Value::Real(0.0)
+        );
+        assert_eq!(text("select describe(null)"), "null");
+        assert_eq!(text("select describe(7)"), "integer 7");
+        assert_eq!(text("select describe(1.5)"), "real 1.5");
+        assert_eq!(text("select describe('héllo')"), "text 5");
+        assert_eq!(text("select describe(x'0102')"), "blob 2");
+
+        db.execute_batch(
+            "select script_define('shout', 1, 'args[0].to_upper() + \"!\"');
+             select script_define('is_even', 1, 'args[0] % 2 == 0');
+             select script_define('quiet', 0, 'print(\"hidden\");');
+             select script_define('bytes', 1, 'let b = blob(args[0], 0x61); b');
+             select script_define('forever', 0, 'loop {}');
+             select script_define('pair', 0, '[1, 2]');
+             select script_define('args_count', -1, 'args.len()');
+             select script_define('double', 0, 'let s = \"x\"; loop { s += s; }');
+             select script_define('fill', 0, 'let b = blob(); loop { b.push(1); }');
+             select script_define('recurse', 0, 'fn f(n) { f(n + 1) } f(0)');",
+        )
+        .unwrap();
+        assert_eq!(text("select shout('hi')"), "HI!");
+        assert_eq!(value("select is_even(4)").unwrap(), Value::Integer(1));
+        assert_eq!(value("select is_even(3)").unwrap(), Value::Integer(0));
+        assert_eq!(value("select quiet()").unwrap(), Value::Null);
+        assert_eq!(
+            value("select bytes(3)").unwrap(),
+            Value::Blob(b"aaa".to_vec())
+        );
+        assert_eq!(
+            value("select args_count(1, 2, 3)").unwrap(),
+            Value::Integer(3)
+        );
+        assert!(value("select forever()").is_err());
+        // scripts can't run the process out of memory or stack
+        for (sql, message) in [
+            ("select double()", "Length of string too large"),
+            ("select fill()", "Size of array/BLOB too large"),
+            ("select recurse()", "Stack overflow"),
+        ] {
+            let err = value(sql).unwrap_err().to_string();
+            assert!(err.contains(message), "{}", err);
+        }
+        assert!(value("select pair()")
+            .unwrap_err()
+            .to_string()
+            .contains("pair() returned a value of type array"));
+        assert!(value("select shout(1)")
+            .unwrap_err()
+            .to_string()
+            .contains("shout():"));
+        assert!(db
+            .execute_batch("select script_define('broken', 1, 'args[0] +')")
+            .unwrap_err()
+            .to_string()
+            .contains("invalid script"));
+
+        // a script from every row is compiled once
+        assert_eq!(
+            value(
+                "with recursive n(i) as (select 1 union all select i + 1 from n where i < 1000)
+                 select sum(script_eval('args[0] * args[1]', i, 2)) from n"
+            )
+            .unwrap(),
+            Value::Integer(1001000)
+        );
+        assert_eq!(
+            value("select script_eval('args.len()')").unwrap(),
+            Value::Integer(0)
+        );
+        assert!(value("select script_eval()").is_err());
+        // ten script_define()s and one new script_eval() script, the
+        // other is args_count's
+        assert_eq!(
+            value("select script_cache_clear()").unwrap(),
+            Value::Integer(11)
+        );
+        assert_eq!(
+            value("select script_cache_clear()").unwrap(),
+            Value::Integer(0)
+        );
+        // functions defined from a cleared script keep working
+        assert_eq!(text("select shout('still')"), "STILL!");
+
+        // not from a view
+        db.execute_batch("create view defines as select script_define('x', 0, '1') as result")
+            .unwrap();
+        assert!(value("select result from defines").is_err());
+    }
+}
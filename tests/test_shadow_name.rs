@@ -0,0 +1,146 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api, define_virtual_table,
+    table::{IndexInfo, VTab, VTabArguments, VTabCursor},
+    BestIndexError, Error, Result,
+};
+use std::os::raw::c_int;
+
+fn execute(db: *mut sqlite3, sql: &str) -> Result<()> {
+    unsafe { rusqlite::Connection::from_handle(db.cast::<rusqlite::ffi::sqlite3>()) }
+        .and_then(|conn| conn.execute_batch(sql))
+        .map_err(|err| Error::new_message(err.to_string()))
+}
+
+/// vault, a table that keeps its contents in `<name>_data` and an index of
+/// them in `<name>_idx`, which nothing else should write to.
+#[repr(C)]
+pub struct VaultTable {
+    /// must be first
+    base: sqlite3_vtab,
+}
+
+impl<'vtab> VTab<'vtab> for VaultTable {
+    type Aux = ();
+    type Cursor = VaultCursor;
+
+    fn create(
+        db: *mut sqlite3,
+        aux: Option<&Self::Aux>,
+        args: VTabArguments,
+    ) -> Result<(String, VaultTable)> {
+        execute(
+            db,
+            &format!(
+                "create table \"{0}_data\"(value); create table \"{0}_idx\"(value); create table \"{0}_notes\"(note)",
+                args.table_name
+            ),
+        )?;
+        Self::connect(db, aux, args)
+    }
+
+    fn connect(
+        _db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, VaultTable)> {
+        let base: sqlite3_vtab = unsafe { std::mem::zeroed() };
+        Ok(("CREATE TABLE x(value)".to_owned(), VaultTable { base }))
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        info.set_estimated_cost(100.0);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<VaultCursor> {
+        let base: sqlite3_vtab_cursor = unsafe { std::mem::zeroed() };
+        Ok(VaultCursor { base })
+    }
+
+    fn shadow_names() -> &'static [&'static str] {
+        &["data", "idx"]
+    }
+}
+
+#[repr(C)]
+pub struct VaultCursor {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+}
+
+impl VTabCursor for VaultCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        true
+    }
+
+    fn column(&self, context: *mut sqlite3_context, _i: c_int) -> Result<()> {
+        api::result_null(context);
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(0)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_shadowname_init(db: *mut sqlite3) -> Result<()> {
+    define_virtual_table::<VaultTable>(db, "vault", None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{config::DbConfig, ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_shadowname_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        db.set_db_config(DbConfig::SQLITE_DBCONFIG_DEFENSIVE, true)
+            .unwrap();
+        db.execute_batch("create virtual table secrets using vault()")
+            .unwrap();
+
+        // shadow tables can be read, but not written
+        db.execute_batch("select * from secrets_data").unwrap();
+        for sql in [
+            "insert into secrets_data values (1)",
+            "delete from secrets_idx",
+            "drop table secrets_data",
+        ] {
+            let err = db.execute_batch(sql).unwrap_err();
+            assert!(err.to_string().contains("may not be"), "{}: {}", sql, err);
+        }
+        // other tables with the prefix aren't shadow tables
+        db.execute_batch("insert into secrets_notes values ('fine')")
+            .unwrap();
+
+        // without defensive mode, they can be written to
+        db.set_db_config(DbConfig::SQLITE_DBCONFIG_DEFENSIVE, false)
+            .unwrap();
+        db.execute_batch("insert into secrets_data values (1)")
+            .unwrap();
+    }
+}
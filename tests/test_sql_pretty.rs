@@ -0,0 +1,98 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{sql_pretty::define_sql_pretty, Result};
+
+#[sqlite_entrypoint]
+pub fn sqlite3_sqlpretty_init(db: *mut sqlite3) -> Result<()> {
+    define_sql_pretty(db)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, types::Value, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_sqlpretty_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        let pretty = |sql: &str| -> String {
+            db.query_row("select sql_pretty(?)", [sql], |row| row.get(0))
+                .unwrap()
+        };
+
+        assert_eq!(
+            pretty("select a, count(*) from t where b>1 group by a"),
+            "SELECT a, count(*)\nFROM t\nWHERE b > 1\nGROUP BY a"
+        );
+        assert_eq!(
+            pretty("select t.*, -x, cast(y as text) from t left outer join u on t.id=u.id order by 1 desc limit 10"),
+            "SELECT t.*, -x, CAST(y AS text)\nFROM t\nLEFT OUTER JOIN u ON t.id = u.id\nORDER BY 1 DESC\nLIMIT 10"
+        );
+
+        // subqueries are indented, while function arguments and windows
+        // stay on their line
+        assert_eq!(
+            pretty("select * from (select a from t where a in (select b from u)) where exists(select 1)"),
+            "SELECT *\nFROM (\n  SELECT a\n  FROM t\n  WHERE a IN (\n    SELECT b\n    FROM u\n  )\n)\nWHERE EXISTS (\n  SELECT 1\n)"
+        );
+        assert_eq!(
+            pretty("select group_concat(a order by b), sum(c) filter (where c>0) over (partition by d order by e) from t"),
+            "SELECT group_concat(a ORDER BY b), sum(c) FILTER (WHERE c > 0) OVER (PARTITION BY d ORDER BY e)\nFROM t"
+        );
+
+        // literals, quoted identifiers and comments are written as they were
+        assert_eq!(
+            pretty("select 'it''s  here', x'0aFF', 1.5e-3, \"Select\", [from] /* keep  me */ from t -- done\nwhere ?1 = :name"),
+            "SELECT 'it''s  here', x'0aFF', 1.5e-3, \"Select\", [from] /* keep  me */\nFROM t -- done\nWHERE ?1 = :name"
+        );
+
+        // statements are separated by a blank line
+        assert_eq!(
+            pretty("insert into t(a,b) values (1,2);delete from t where a=1;update t set a=a||'x'"),
+            "INSERT INTO t(a, b)\nVALUES (1, 2);\n\nDELETE FROM t\nWHERE a = 1;\n\nUPDATE t\nSET a = a || 'x'"
+        );
+
+        // pretty printing doesn't change what the query means
+        let query = "select  x ,  y from ( select 1 as x, 'a' as y union all select 2, 'b' ) order by x desc";
+        let rows = |sql: &str| -> Vec<(i64, String)> {
+            let mut stmt = db.prepare(sql).unwrap();
+            let rows = stmt
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+                .unwrap();
+            rows.map(|row| row.unwrap()).collect()
+        };
+        assert_eq!(rows(&pretty(query)), rows(query));
+
+        // big inputs come back whole
+        let list = (0..20_000)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        let big = pretty(&format!("select 1 where 1 in ({})", list));
+        assert_eq!(
+            big.len(),
+            "SELECT 1\nWHERE 1 IN ()".len() + list.len() + 19_999
+        );
+        assert!(big.ends_with("19998, 19999)"));
+
+        assert_eq!(
+            db.query_row("select sql_pretty(null)", [], |row| row.get::<_, Value>(0))
+                .unwrap(),
+            Value::Null
+        );
+        assert_eq!(pretty("  "), "");
+        assert_eq!(
+            db.query_row("select sql_pretty('select ''oops')", [], |_| Ok(()))
+                .unwrap_err()
+                .to_string(),
+            "sql_pretty: unterminated string starting at byte 7"
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    define_scalar_function,
+    static_table::{define_static_table, result_rows, StaticTable},
+    Result,
+};
+
+use std::sync::Arc;
+
+#[derive(StaticTable)]
+pub struct Person {
+    name: String,
+    age: i64,
+    email: Option<String>,
+    height: f64,
+    active: bool,
+    r#type: &'static str,
+}
+
+fn person(name: &str, age: i64, email: Option<&str>) -> Person {
+    Person {
+        name: name.to_owned(),
+        age,
+        email: email.map(str::to_owned),
+        height: 1.5 + age as f64 / 100.0,
+        active: age < 40,
+        r#type: "person",
+    }
+}
+
+/// new_hires(), the rows of a people table that isn't registered anywhere.
+pub fn new_hires(context: *mut sqlite3_context, _values: &[*mut sqlite3_value]) -> Result<()> {
+    result_rows(
+        context,
+        Arc::new(vec![person("dana", 25, None), person("eli", 52, None)]),
+    );
+    Ok(())
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_statictable_init(db: *mut sqlite3) -> Result<()> {
+    let people = vec![
+        person("alex", 30, Some("alex@example.com")),
+        person("brian", 45, None),
+        person("craig", 38, Some("craig@example.com")),
+    ];
+    define_static_table(db, "people", Some(Arc::new(people)))?;
+    define_static_table::<Person>(db, "people_of", None)?;
+    define_scalar_function(db, "new_hires", 0, new_hires, FunctionFlags::UTF8)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_statictable_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+
+        let names = |sql: &str| -> Vec<String> {
+            db.prepare(sql)
+                .unwrap()
+                .query_map([], |row| row.get(0))
+                .unwrap()
+                .collect::<std::result::Result<_, _>>()
+                .unwrap()
+        };
+        assert_eq!(
+            names("select name from people where age > 35 order by age"),
+            vec!["craig", "brian"]
+        );
+        assert_eq!(
+            names("select name from people where email is null"),
+            vec!["brian"]
+        );
+        assert_eq!(
+            names("select name from people where active and type = 'person'"),
+            vec!["alex", "craig"]
+        );
+
+        let columns: Vec<(String, String)> = db
+            .prepare("select name, type from pragma_table_info('people')")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<std::result::Result<_, _>>()
+            .unwrap();
+        let columns: Vec<(&str, &str)> = columns
+            .iter()
+            .map(|(name, typ)| (name.as_str(), typ.as_str()))
+            .collect();
+        assert_eq!(
+            columns,
+            vec![
+                ("name", "TEXT"),
+                ("age", "INTEGER"),
+                ("email", "TEXT"),
+                ("height", "REAL"),
+                ("active", "INTEGER"),
+                ("type", "TEXT"),
+            ]
+        );
+        let height: f64 = db
+            .query_row("select height from people where name = 'alex'", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(height, 1.8);
+
+        // rows passed as a pointer argument
+        assert_eq!(
+            names("select name from people_of(new_hires())"),
+            vec!["dana", "eli"]
+        );
+        assert_eq!(
+            names("select name from people(new_hires()) where age > 50"),
+            vec!["eli"]
+        );
+        assert!(db
+            .query_row("select * from people_of", [], |_| Ok(()))
+            .is_err());
+        assert!(db
+            .query_row("select * from people_of('not rows')", [], |_| Ok(()))
+            .is_err());
+    }
+}
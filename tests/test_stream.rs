@@ -0,0 +1,104 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    compare::ValueRef,
+    stream::{define_stream_function, Streams},
+    Result,
+};
+
+use std::borrow::Cow;
+use std::sync::OnceLock;
+
+fn streams() -> &'static Streams {
+    static STREAMS: OnceLock<Streams> = OnceLock::new();
+    STREAMS.get_or_init(Streams::new)
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_stream_init(db: *mut sqlite3) -> Result<()> {
+    define_stream_function(db, "stream", &["kind", "n"], streams().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    fn row(kind: &'static str, n: i64) -> Vec<ValueRef<'static>> {
+        vec![
+            ValueRef::Text(Cow::Borrowed(kind.as_bytes())),
+            ValueRef::Integer(n),
+        ]
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_stream_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+
+        // a producer far ahead of the query blocks on the small buffer
+        let events = streams().open("events", 4);
+        let producer = std::thread::spawn(move || {
+            for n in 1..=1000 {
+                events.send(row("click", n)).unwrap();
+            }
+        });
+        let (count, sum): (i64, i64) = db
+            .query_row(
+                "select count(*), sum(n) from stream('events') where kind = 'click'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        producer.join().unwrap();
+        assert_eq!((count, sum), (1000, 500500));
+        // the stream ended with its sender
+        assert!(streams().names().is_empty());
+        assert!(db
+            .query_row("select count(*) from stream('events')", [], |row| row
+                .get::<_, i64>(0))
+            .is_err());
+
+        // a live stream, read without waiting
+        let live = streams().open("live", 16);
+        live.send(row("a", 1)).unwrap();
+        live.send(vec![ValueRef::Null]).unwrap();
+        let read = |sql: &str| -> Vec<(Option<String>, Option<i64>)> {
+            db.prepare(sql)
+                .unwrap()
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+                .unwrap()
+                .collect::<std::result::Result<_, _>>()
+                .unwrap()
+        };
+        assert_eq!(
+            read("select * from stream('live', 0)"),
+            vec![(Some("a".to_owned()), Some(1)), (None, None)]
+        );
+        assert!(read("select * from stream('live', 0)").is_empty());
+        live.send(row("b", 2)).unwrap();
+        assert_eq!(
+            read("select * from stream('live', 10)"),
+            vec![(Some("b".to_owned()), Some(2))]
+        );
+        // rows a query doesn't read stay queued
+        live.send(row("c", 3)).unwrap();
+        live.send(row("d", 4)).unwrap();
+        assert_eq!(
+            read("select * from stream('live', 0) limit 1"),
+            vec![(Some("c".to_owned()), Some(3))]
+        );
+        drop(live);
+        assert_eq!(
+            read("select * from stream('live')"),
+            vec![(Some("d".to_owned()), Some(4))]
+        );
+        assert!(streams().names().is_empty());
+    }
+}
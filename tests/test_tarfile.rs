@@ -0,0 +1,155 @@
+#[cfg(feature = "tarfile")]
+use sqlite_loadable::prelude::*;
+#[cfg(feature = "tarfile")]
+use sqlite_loadable::{tarfile::define_tarfile, Result};
+
+#[cfg(feature = "tarfile")]
+#[sqlite_entrypoint]
+pub fn sqlite3_tarfile_init(db: *mut sqlite3) -> Result<()> {
+    define_tarfile(db)
+}
+
+#[cfg(all(test, feature = "tarfile"))]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, types::Value, Connection};
+    use std::io::Write;
+
+    fn archive() -> Vec<u8> {
+        let mut builder = tar::Builder::new(vec![]);
+        let mut append = |name: &str, entry_type: tar::EntryType, mode: u32, data: &[u8]| {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(entry_type);
+            header.set_mode(mode);
+            header.set_mtime(1700000000);
+            header.set_size(data.len() as u64);
+            if entry_type == tar::EntryType::Symlink {
+                header.set_link_name("hello.txt").unwrap();
+            }
+            builder.append_data(&mut header, name, data).unwrap();
+        };
+        append("docs/", tar::EntryType::Directory, 0o755, b"");
+        append(
+            "docs/hello.txt",
+            tar::EntryType::Regular,
+            0o644,
+            b"hello, world",
+        );
+        append("docs/latest", tar::EntryType::Symlink, 0o777, b"");
+        // longer than the 100 bytes a plain header holds
+        append(
+            &format!("{}.txt", "a".repeat(150)),
+            tar::EntryType::Regular,
+            0o600,
+            b"long",
+        );
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_tarfile_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        let text = |s: &str| Value::Text(s.to_owned());
+        let tar = archive();
+        let mut gzip = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        gzip.write_all(&tar).unwrap();
+        let gzip = gzip.finish().unwrap();
+
+        let expected = vec![
+            vec![
+                text("docs/"),
+                Value::Integer(0o755),
+                Value::Integer(1700000000),
+                Value::Integer(0),
+                text("dir"),
+                Value::Null,
+                Value::Null,
+            ],
+            vec![
+                text("docs/hello.txt"),
+                Value::Integer(0o644),
+                Value::Integer(1700000000),
+                Value::Integer(12),
+                text("file"),
+                Value::Null,
+                Value::Blob(b"hello, world".to_vec()),
+            ],
+            vec![
+                text("docs/latest"),
+                Value::Integer(0o777),
+                Value::Integer(1700000000),
+                Value::Integer(0),
+                text("symlink"),
+                text("hello.txt"),
+                Value::Null,
+            ],
+            vec![
+                text(&format!("{}.txt", "a".repeat(150))),
+                Value::Integer(0o600),
+                Value::Integer(1700000000),
+                Value::Integer(4),
+                text("file"),
+                Value::Null,
+                Value::Blob(b"long".to_vec()),
+            ],
+        ];
+        let rows = |sql: &str, archive: &[u8]| -> Vec<Vec<Value>> {
+            let mut stmt = db.prepare(sql).unwrap();
+            let n = stmt.column_count();
+            let rows = stmt
+                .query_map([archive], |row| {
+                    (0..n).map(|i| row.get::<_, Value>(i)).collect()
+                })
+                .unwrap();
+            rows.map(|row| row.unwrap()).collect()
+        };
+        let listing = "select name, mode, mtime, sz, type, linkname, data from tarfile(?)";
+        assert_eq!(rows(listing, &tar), expected);
+        assert_eq!(rows(listing, &gzip), expected);
+        assert_eq!(
+            rows("select name from tarfile(?) where type = 'file'", &gzip),
+            vec![vec![text("docs/hello.txt")], vec![expected[3][0].clone()]]
+        );
+
+        // from a path
+        let path = std::env::temp_dir().join(format!(
+            "sqlite-loadable-test-tarfile-{}.tar.gz",
+            std::process::id()
+        ));
+        std::fs::write(&path, &gzip).unwrap();
+        let data: Vec<u8> = db
+            .query_row(
+                "select data from tarfile(?) where name = 'docs/hello.txt'",
+                [path.to_str().unwrap()],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(data, b"hello, world");
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(db
+            .query_row("select * from tarfile()", [], |_| Ok(()))
+            .is_err());
+        let err = db
+            .query_row(
+                "select * from tarfile('/nonexistent/archive.tar')",
+                [],
+                |_| Ok(()),
+            )
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.starts_with("tar: /nonexistent/archive.tar: "),
+            "{}",
+            err
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api, define_scalar_function,
+    temp::{connection_temp_dir, TempFile},
+    Result,
+};
+
+/// spill(text) writes the text to a temporary file in 3-byte chunks, then
+/// reads it back.
+pub fn spill(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let text = api::value_blob(values.first().expect("1st argument"));
+    let mut file = TempFile::new(api::context_db_handle(context))?;
+    for (i, chunk) in text.chunks(3).enumerate() {
+        file.write_at(i as u64 * 3, chunk)?;
+    }
+    let mut buffer = vec![0; file.len()? as usize + 10];
+    let n = file.read_at(0, &mut buffer)?;
+    buffer.truncate(n);
+    file.truncate(0)?;
+    assert!(file.is_empty()?);
+    api::result_blob(context, &buffer);
+    Ok(())
+}
+
+pub fn temp_dir(context: *mut sqlite3_context, _values: &[*mut sqlite3_value]) -> Result<()> {
+    let dir = connection_temp_dir(api::context_db_handle(context))?;
+    std::fs::write(dir.join("spill.bin"), b"data").unwrap();
+    api::result_text(context, dir.to_str().unwrap())
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_temp_init(db: *mut sqlite3) -> Result<()> {
+    let flags = FunctionFlags::UTF8;
+    define_scalar_function(db, "spill", 1, spill, flags)?;
+    define_scalar_function(db, "temp_dir", 0, temp_dir, flags)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_temp_init as *const (),
+                ),
+            ));
+        }
+        let conn = Connection::open_in_memory().unwrap();
+        let spilled: Vec<u8> = conn
+            .query_row("select spill('hello, world')", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(spilled, b"hello, world");
+
+        let dirs: (String, String) = conn
+            .query_row("select temp_dir(), temp_dir()", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(dirs.0, dirs.1);
+        let dir = std::path::PathBuf::from(dirs.0);
+        assert!(dir.join("spill.bin").exists());
+
+        let other = Connection::open_in_memory().unwrap();
+        let other_dir: String = other
+            .query_row("select temp_dir()", [], |row| row.get(0))
+            .unwrap();
+        assert_ne!(dir, std::path::PathBuf::from(&other_dir));
+
+        drop(conn);
+        assert!(!dir.exists());
+        assert!(std::path::Path::new(&other_dir).exists());
+        drop(other);
+        assert!(!std::path::Path::new(&other_dir).exists());
+    }
+}
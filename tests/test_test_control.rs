@@ -0,0 +1,63 @@
+#[cfg(feature = "testing")]
+use sqlite_loadable::prelude::*;
+#[cfg(feature = "testing")]
+use sqlite_loadable::{api, define_scalar_function, random::random_i64, Result};
+
+#[cfg(feature = "testing")]
+pub fn rand_i64(context: *mut sqlite3_context, _values: &[*mut sqlite3_value]) -> Result<()> {
+    api::result_int64(context, random_i64());
+    Ok(())
+}
+
+#[cfg(feature = "testing")]
+#[sqlite_entrypoint]
+pub fn sqlite3_testcontrol_init(db: *mut sqlite3) -> Result<()> {
+    define_scalar_function(db, "rand_i64", 0, rand_i64, FunctionFlags::UTF8)?;
+    Ok(())
+}
+
+#[cfg(feature = "testing")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+    use sqlite_loadable::test_control::{prng_restore, prng_save, prng_seed, set_pending_byte};
+
+    fn sample(conn: &Connection) -> (i64, i64) {
+        conn.query_row("select rand_i64(), random()", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_testcontrol_init as *const (),
+                ),
+            ));
+        }
+        // loading the extension sets up the API table the wrappers use
+        let conn = Connection::open_in_memory().unwrap();
+
+        prng_seed(42, None).unwrap();
+        let first = sample(&conn);
+        prng_seed(42, None).unwrap();
+        assert_eq!(sample(&conn), first);
+        prng_seed(43, None).unwrap();
+        assert_ne!(sample(&conn), first);
+
+        prng_save();
+        let saved = sample(&conn);
+        assert_ne!(sample(&conn), saved);
+        prng_restore();
+        assert_eq!(sample(&conn), saved);
+
+        let default = set_pending_byte(0x1000);
+        assert_eq!(default, 0x40000000);
+        assert_eq!(set_pending_byte(default), 0x1000);
+    }
+}
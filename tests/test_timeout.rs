@@ -0,0 +1,188 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api,
+    clock::vfs_sleep,
+    define_virtual_table,
+    table::{IndexInfo, VTab, VTabArguments, VTabCursor},
+    timeout::{ScanTimeout, Timed},
+    vtab_argparse::{parse_argument, Argument, ConfigOptionValue},
+    BestIndexError, Error, Result,
+};
+use std::os::raw::c_int;
+
+/// slow(rows=N, delay=MS), a table of the numbers 1 to N that takes `delay`
+/// milliseconds to get to each row, like a paged remote API would.
+#[repr(C)]
+pub struct SlowTable {
+    /// must be first
+    base: sqlite3_vtab,
+    db: *mut sqlite3,
+    rows: i64,
+    delay: u32,
+    timeout: ScanTimeout,
+}
+
+impl<'vtab> VTab<'vtab> for SlowTable {
+    type Aux = ();
+    type Cursor = Timed<SlowCursor>;
+
+    fn connect(
+        db: *mut sqlite3,
+        _aux: Option<&Self::Aux>,
+        mut args: VTabArguments,
+    ) -> Result<(String, SlowTable)> {
+        let timeout = ScanTimeout::take_from(&mut args.arguments)?;
+        let (mut rows, mut delay) = (0, 0);
+        for argument in &args.arguments {
+            let option = match parse_argument(argument) {
+                Ok(Argument::Config(option)) => option,
+                _ => return Err(Error::new_message(format!("unknown argument {}", argument))),
+            };
+            let value = match &option.value {
+                ConfigOptionValue::Bareword(value) => value.parse::<i64>().ok(),
+                _ => None,
+            }
+            .ok_or_else(|| Error::new_message(format!("{} must be a number", option.key)))?;
+            match option.key.as_str() {
+                "rows" => rows = value,
+                "delay" => delay = value as u32,
+                key => return Err(Error::new_message(format!("unknown option {}", key))),
+            }
+        }
+        let base: sqlite3_vtab = unsafe { std::mem::zeroed() };
+        Ok((
+            "CREATE TABLE x(value INTEGER)".to_owned(),
+            SlowTable {
+                base,
+                db,
+                rows,
+                delay,
+                timeout,
+            },
+        ))
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        info.set_estimated_cost(self.rows as f64);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<Timed<SlowCursor>> {
+        let base: sqlite3_vtab_cursor = unsafe { std::mem::zeroed() };
+        let cursor = SlowCursor {
+            base,
+            db: self.db,
+            rows: self.rows,
+            delay: self.delay,
+            value: 0,
+        };
+        Ok(Timed::new(cursor, self.db, self.timeout))
+    }
+}
+
+#[repr(C)]
+pub struct SlowCursor {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    db: *mut sqlite3,
+    rows: i64,
+    delay: u32,
+    value: i64,
+}
+
+impl VTabCursor for SlowCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.value = 0;
+        self.next()
+    }
+
+    fn next(&mut self) -> Result<()> {
+        vfs_sleep(self.db, self.delay)?;
+        self.value += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.value > self.rows
+    }
+
+    fn column(&self, context: *mut sqlite3_context, _i: c_int) -> Result<()> {
+        api::result_int64(context, self.value);
+        Ok(())
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.value)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_timeout_init(db: *mut sqlite3) -> Result<()> {
+    define_virtual_table::<SlowTable>(db, "slow", None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection, ErrorCode};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_timeout_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "
+            create virtual table quick using slow(rows=5, delay=1, timeout=10000);
+            create virtual table stuck using slow(rows=100000, delay=5, timeout=40);
+            create virtual table unlimited using slow(rows=3, delay=1);
+            ",
+        )
+        .unwrap();
+        let sum = |sql: &str| -> rusqlite::Result<i64> { db.query_row(sql, [], |row| row.get(0)) };
+
+        assert_eq!(sum("select sum(value) from quick").unwrap(), 15);
+        assert_eq!(sum("select sum(value) from unlimited").unwrap(), 6);
+
+        // a scan that runs long is aborted, rather than hanging
+        let err = sum("select sum(value) from stuck").unwrap_err();
+        match &err {
+            rusqlite::Error::SqliteFailure(failure, Some(message)) => {
+                assert_eq!(failure.code, ErrorCode::OperationAborted);
+                assert_eq!(message, "virtual table scan timed out after 40 ms");
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+        // while a short one on the same table is fine, each scan has its own
+        // deadline
+        assert_eq!(
+            sum("select sum(value) from (select value from stuck limit 2)").unwrap(),
+            3
+        );
+        assert_eq!(
+            sum("select sum(value) from (select value from stuck limit 2)").unwrap(),
+            3
+        );
+
+        let err = db
+            .execute_batch("create virtual table bad using slow(rows=1, timeout=soon)")
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("timeout must be a whole number of milliseconds, not soon"),
+            "{}",
+            err
+        );
+    }
+}
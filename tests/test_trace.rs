@@ -0,0 +1,71 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    trace::{statement_sql, trace, TraceEvent, TraceEvents},
+    Result,
+};
+use std::sync::Mutex;
+
+static EVENTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+#[sqlite_entrypoint]
+pub fn sqlite3_trace_init(db: *mut sqlite3) -> Result<()> {
+    let mut rows = 0;
+    trace(db, TraceEvents::all(), move |event| {
+        let event = match event {
+            TraceEvent::Stmt { sql, .. } => format!("stmt {}", sql),
+            TraceEvent::Row { .. } => {
+                rows += 1;
+                return;
+            }
+            TraceEvent::Profile { stmt, elapsed } => {
+                assert!(elapsed.as_secs() < 60);
+                format!(
+                    "profile {} after {} rows",
+                    statement_sql(stmt).unwrap_or(""),
+                    std::mem::take(&mut rows)
+                )
+            }
+            TraceEvent::Close { .. } => "close".to_owned(),
+        };
+        EVENTS.lock().unwrap().push(event);
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_trace_init as *const (),
+                ),
+            ));
+        }
+        let conn = Connection::open_in_memory().unwrap();
+        let values: Vec<i64> = conn
+            .prepare("select 1 union all select 2")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(values, vec![1, 2]);
+        conn.close().unwrap();
+
+        let events = std::mem::take(&mut *EVENTS.lock().unwrap());
+        assert_eq!(
+            events,
+            vec![
+                "stmt select 1 union all select 2",
+                "profile select 1 union all select 2 after 2 rows",
+                "close",
+            ]
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    api, define_virtual_table_writeable_with_transactions,
+    table::{
+        IndexInfo, VTab, VTabArguments, VTabCursor, VTabRowidWriteable,
+        VTabWriteableWithTransactions,
+    },
+    BestIndexError, Error, Result,
+};
+use std::os::raw::c_int;
+use std::sync::{Arc, Mutex};
+
+/// Stands in for a remote API, which takes a batch of messages at a time
+/// and refuses the whole batch if any of them is empty.
+#[derive(Default)]
+pub struct Remote {
+    messages: Mutex<Vec<String>>,
+}
+
+impl Remote {
+    fn send(&self, batch: &[String]) -> Result<()> {
+        if batch.iter().any(|message| message.is_empty()) {
+            return Err(Error::new_message("the remote refused an empty message"));
+        }
+        self.messages.lock().unwrap().extend_from_slice(batch);
+        Ok(())
+    }
+}
+
+/// outbox, a table of messages that are sent to the remote a transaction
+/// at a time.
+#[repr(C)]
+pub struct OutboxTable {
+    /// must be first
+    base: sqlite3_vtab,
+    remote: Arc<Remote>,
+    pending: Vec<String>,
+}
+
+impl<'vtab> VTab<'vtab> for OutboxTable {
+    type Aux = Arc<Remote>;
+    type Cursor = OutboxCursor;
+
+    fn connect(
+        _db: *mut sqlite3,
+        aux: Option<&Self::Aux>,
+        _args: VTabArguments,
+    ) -> Result<(String, OutboxTable)> {
+        let base: sqlite3_vtab = unsafe { std::mem::zeroed() };
+        Ok((
+            "CREATE TABLE x(message TEXT)".to_owned(),
+            OutboxTable {
+                base,
+                remote: Arc::clone(aux.expect("the remote")),
+                pending: Vec::new(),
+            },
+        ))
+    }
+
+    fn best_index(&self, mut info: IndexInfo) -> core::result::Result<(), BestIndexError> {
+        info.set_estimated_cost(100.0);
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<OutboxCursor> {
+        let base: sqlite3_vtab_cursor = unsafe { std::mem::zeroed() };
+        // the transaction sees its own messages, before they're sent
+        let mut messages = self.remote.messages.lock().unwrap().clone();
+        messages.extend(self.pending.iter().cloned());
+        Ok(OutboxCursor {
+            base,
+            messages,
+            index: 0,
+        })
+    }
+}
+
+impl<'vtab> VTabRowidWriteable<'vtab> for OutboxTable {
+    fn insert(&'vtab mut self, _rowid: Option<i64>, values: &[*mut sqlite3_value]) -> Result<i64> {
+        self.pending.push(api::value_text(&values[0])?.to_owned());
+        Ok((self.remote.messages.lock().unwrap().len() + self.pending.len()) as i64)
+    }
+
+    fn update(
+        &'vtab mut self,
+        _rowid: i64,
+        _new_rowid: i64,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        Err(Error::new_message("sent messages can't be changed"))
+    }
+
+    fn delete(&'vtab mut self, _rowid: i64) -> Result<()> {
+        Err(Error::new_message("sent messages can't be deleted"))
+    }
+}
+
+impl<'vtab> VTabWriteableWithTransactions<'vtab> for OutboxTable {
+    fn sync(&'vtab mut self) -> Result<()> {
+        self.remote.send(&self.pending)
+    }
+
+    fn commit(&'vtab mut self) -> Result<()> {
+        self.pending.clear();
+        Ok(())
+    }
+
+    fn rollback(&'vtab mut self) -> Result<()> {
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+#[repr(C)]
+pub struct OutboxCursor {
+    /// Base class. Must be first
+    base: sqlite3_vtab_cursor,
+    messages: Vec<String>,
+    index: usize,
+}
+
+impl VTabCursor for OutboxCursor {
+    fn filter(
+        &mut self,
+        _idx_num: c_int,
+        _idx_str: Option<&str>,
+        _values: &[*mut sqlite3_value],
+    ) -> Result<()> {
+        self.index = 0;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.index += 1;
+        Ok(())
+    }
+
+    fn eof(&self) -> bool {
+        self.index >= self.messages.len()
+    }
+
+    fn column(&self, context: *mut sqlite3_context, _i: c_int) -> Result<()> {
+        api::result_text(context, &self.messages[self.index])
+    }
+
+    fn rowid(&self) -> Result<i64> {
+        Ok(self.index as i64 + 1)
+    }
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_transactions_init(db: *mut sqlite3) -> Result<()> {
+    define_virtual_table_writeable_with_transactions::<OutboxTable>(
+        db,
+        "outbox",
+        Some(Arc::new(Remote::default())),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_transactions_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("create virtual table o using outbox()")
+            .unwrap();
+        let messages = || -> Vec<String> {
+            db.prepare("select message from o")
+                .unwrap()
+                .query_map([], |row| row.get(0))
+                .unwrap()
+                .collect::<std::result::Result<_, _>>()
+                .unwrap()
+        };
+
+        // messages are sent together when the transaction commits
+        db.execute_batch(
+            "
+            begin;
+            insert into o values ('hello'), ('world');
+            insert into o values ('again');
+            commit;
+            ",
+        )
+        .unwrap();
+        assert_eq!(messages(), vec!["hello", "world", "again"]);
+
+        // and never sent if it rolls back
+        db.execute_batch("begin; insert into o values ('unsent');")
+            .unwrap();
+        assert_eq!(messages(), vec!["hello", "world", "again", "unsent"]);
+        db.execute_batch("rollback").unwrap();
+        assert_eq!(messages(), vec!["hello", "world", "again"]);
+
+        // a failed send rolls the transaction back, with the remote's error
+        let err = db
+            .execute_batch("begin; insert into o values ('fine'), (''); commit;")
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("the remote refused an empty message"),
+            "{}",
+            err
+        );
+        assert!(db.is_autocommit());
+        assert_eq!(messages(), vec!["hello", "world", "again"]);
+    }
+}
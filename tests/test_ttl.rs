@@ -0,0 +1,127 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::ttl::{define_sweep_function, TtlSweeper};
+use sqlite_loadable::{api, define_scalar_function, Result};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[sqlite_entrypoint]
+pub fn sqlite3_ttl_init(db: *mut sqlite3) -> Result<()> {
+    let sweeper = Arc::new(TtlSweeper::new("main", "cache").interval(Duration::from_secs(3600)));
+
+    // what a scan does for each row, and once it's done
+    let live = sweeper.clone();
+    define_scalar_function(
+        db,
+        "cache_live",
+        2,
+        move |context, values| {
+            let expires_at =
+                (!api::value_is_null(&values[1])).then(|| api::value_int64(&values[1]));
+            let now = live.now(api::context_db_handle(context))?;
+            api::result_bool(
+                context,
+                live.check_row(api::value_int64(&values[0]), expires_at, now),
+            );
+            Ok(())
+        },
+        FunctionFlags::UTF8,
+    )?;
+    let flush = sweeper.clone();
+    define_scalar_function(
+        db,
+        "cache_flush",
+        0,
+        move |context, _values| {
+            let deleted = flush.flush(api::context_db_handle(context))?;
+            api::result_int64(context, deleted as i64);
+            Ok(())
+        },
+        FunctionFlags::UTF8 | FunctionFlags::DIRECTONLY,
+    )?;
+    let periodic = sweeper.clone();
+    define_scalar_function(
+        db,
+        "cache_maybe_sweep",
+        0,
+        move |context, _values| {
+            let deleted = periodic.maybe_sweep(api::context_db_handle(context))?;
+            api::result_int64(context, deleted as i64);
+            Ok(())
+        },
+        FunctionFlags::UTF8 | FunctionFlags::DIRECTONLY,
+    )?;
+    define_scalar_function(
+        db,
+        "cache_expires_at",
+        1,
+        move |context, values| {
+            let ttl = Duration::from_secs(api::value_int64(&values[0]) as u64);
+            let expires_at = sweeper.expires_at(api::context_db_handle(context), ttl)?;
+            api::result_int64(context, expires_at);
+            Ok(())
+        },
+        FunctionFlags::UTF8,
+    )?;
+    define_sweep_function(db, "cache", TtlSweeper::new("main", "cache"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_ttl_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        let int = |sql: &str| db.query_row(sql, [], |row| row.get::<_, i64>(0)).unwrap();
+        let text = |sql: &str| {
+            db.query_row(sql, [], |row| row.get::<_, String>(0))
+                .unwrap()
+        };
+        db.execute_batch(
+            "create table cache(key text, value, expires_at integer);
+             create temp view now as
+               select cast((julianday('now') - 2440587.5) * 86400000 as integer) as ms;
+             insert into cache values
+               ('a', 1, (select ms - 1000 from now)),
+               ('b', 2, cache_expires_at(3600)),
+               ('c', 3, null),
+               ('d', 4, (select ms - 1 from now));",
+        )
+        .unwrap();
+        assert!((3_599_000..=3_601_000).contains(
+            &(int("select expires_at - (select ms from now) from cache where key = 'b'"))
+        ));
+
+        // scans hide expired rows, and delete them once done
+        assert_eq!(
+            text("select group_concat(key) from cache where cache_live(rowid, expires_at)"),
+            "b,c"
+        );
+        assert_eq!(int("select count(*) from cache"), 4);
+        assert_eq!(int("select cache_flush()"), 2);
+        assert_eq!(int("select cache_flush()"), 0);
+        assert_eq!(text("select group_concat(key) from cache"), "b,c");
+
+        db.execute_batch("insert into cache values ('e', 5, 0)")
+            .unwrap();
+        assert_eq!(int("select cache_sweep()"), 1);
+
+        // the first periodic sweep runs, the next waits for the interval
+        db.execute_batch("insert into cache values ('f', 6, 0), ('g', 7, 0)")
+            .unwrap();
+        assert_eq!(int("select cache_maybe_sweep()"), 2);
+        db.execute_batch("insert into cache values ('h', 8, 0)")
+            .unwrap();
+        assert_eq!(int("select cache_maybe_sweep()"), 0);
+        assert_eq!(int("select count(*) from cache"), 3);
+    }
+}
@@ -0,0 +1,94 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{api, define_scalar_function, Result};
+
+pub fn txn_state(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let schema = match values.first() {
+        Some(value) => Some(api::value_text(value)?),
+        None => None,
+    };
+    let state = match api::txn_state(api::context_db_handle(context), schema)? {
+        api::TransactionState::None => "none",
+        api::TransactionState::Read => "read",
+        api::TransactionState::Write => "write",
+    };
+    api::result_text(context, state)
+}
+
+pub fn outside_txn(context: *mut sqlite3_context, _values: &[*mut sqlite3_value]) -> Result<()> {
+    api::require_no_transaction(api::context_db_handle(context), "outside_txn()")?;
+    api::result_bool(context, true);
+    Ok(())
+}
+
+pub fn inside_txn(context: *mut sqlite3_context, _values: &[*mut sqlite3_value]) -> Result<()> {
+    api::require_write_transaction(api::context_db_handle(context), "main", "inside_txn()")?;
+    api::result_bool(context, true);
+    Ok(())
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_txnstate_init(db: *mut sqlite3) -> Result<()> {
+    let flags = FunctionFlags::UTF8;
+    define_scalar_function(db, "txn_state", 0, txn_state, flags)?;
+    define_scalar_function(db, "txn_state", 1, txn_state, flags)?;
+    define_scalar_function(db, "outside_txn", 0, outside_txn, flags)?;
+    define_scalar_function(db, "inside_txn", 0, inside_txn, flags)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_txnstate_init as *const (),
+                ),
+            ));
+        }
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("create table t(x)", []).unwrap();
+        let state = |sql: &str| -> String { conn.query_row(sql, [], |row| row.get(0)).unwrap() };
+
+        assert_eq!(state("select txn_state()"), "none");
+        assert!(conn
+            .query_row("select outside_txn()", [], |row| row.get::<_, bool>(0))
+            .unwrap());
+        assert_eq!(
+            conn.query_row("select inside_txn()", [], |row| row.get::<_, bool>(0))
+                .unwrap_err()
+                .to_string(),
+            "inside_txn() must be run inside a write transaction"
+        );
+
+        conn.execute("begin", []).unwrap();
+        conn.query_row("select count(*) from t", [], |row| row.get::<_, i64>(0))
+            .unwrap();
+        assert_eq!(state("select txn_state('main')"), "read");
+        conn.execute("insert into t values (1)", []).unwrap();
+        assert_eq!(state("select txn_state('main')"), "write");
+        assert_eq!(
+            conn.query_row("select outside_txn()", [], |row| row.get::<_, bool>(0))
+                .unwrap_err()
+                .to_string(),
+            "outside_txn() cannot be run inside a transaction"
+        );
+        assert!(conn
+            .query_row("select inside_txn()", [], |row| row.get::<_, bool>(0))
+            .unwrap());
+        conn.execute("commit", []).unwrap();
+
+        assert_eq!(
+            conn.query_row("select txn_state('missing')", [], |row| row
+                .get::<_, String>(0))
+                .unwrap_err()
+                .to_string(),
+            "no such database: missing"
+        );
+    }
+}
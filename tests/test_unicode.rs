@@ -0,0 +1,86 @@
+#[cfg(feature = "unicode")]
+use sqlite_loadable::prelude::*;
+#[cfg(feature = "unicode")]
+use sqlite_loadable::{unicode::define_unicode_functions, Result};
+
+#[cfg(feature = "unicode")]
+#[sqlite_entrypoint]
+pub fn sqlite3_unicode_init(db: *mut sqlite3) -> Result<()> {
+    define_unicode_functions(db)
+}
+
+#[cfg(all(test, feature = "unicode"))]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, types::Value, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_unicode_init as *const (),
+                ),
+            ));
+        }
+        let conn = Connection::open_in_memory().unwrap();
+        // s is "café" (with a combining accent), a space, and a family emoji
+        // made of 5 code points
+        let query = |expr: &str| -> Value {
+            conn.query_row(
+                &format!("select {} from (select ? as s)", expr),
+                ["cafe\u{301} 👩‍👩‍👧"],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+
+        assert_eq!(query("unicode_length(s)"), Value::Integer(6));
+        assert_eq!(query("length(s)"), Value::Integer(11));
+        assert_eq!(
+            query("unicode_substr(s, 4, 1)"),
+            Value::Text("e\u{301}".to_owned())
+        );
+        assert_eq!(query("unicode_substr(s, -1)"), Value::Text("👩‍👩‍👧".to_owned()));
+        assert_eq!(
+            query("unicode_upper('straße')"),
+            Value::Text("STRASSE".to_owned())
+        );
+        assert_eq!(query("unicode_lower('ÀÉ')"), Value::Text("àé".to_owned()));
+        assert_eq!(
+            query("unicode_nfc(s) = 'caf' || char(233) || ' 👩‍👩‍👧'"),
+            Value::Integer(1)
+        );
+        assert_eq!(query("unicode_nfd(unicode_nfc(s)) = s"), Value::Integer(1));
+        assert_eq!(query("unicode_length(null)"), Value::Null);
+        assert_eq!(query("unicode_substr(s, null)"), Value::Null);
+        // positions and lengths far outside the text don't overflow
+        let text = |s: &str| Value::Text(s.to_owned());
+        assert_eq!(
+            query("unicode_substr('abc', 2, 9223372036854775807)"),
+            text("bc")
+        );
+        assert_eq!(
+            query("unicode_substr('abc', 9223372036854775807)"),
+            text("")
+        );
+        assert_eq!(
+            query("unicode_substr('abc', 9223372036854775807, 9223372036854775807)"),
+            text("")
+        );
+        assert_eq!(
+            query("unicode_substr('abc', 2, -9223372036854775808)"),
+            text("a")
+        );
+        assert_eq!(
+            query("unicode_substr('abc', -9223372036854775808, -9223372036854775808)"),
+            text("")
+        );
+        assert_eq!(
+            query("unicode_substr('abc', -9223372036854775808)"),
+            text("abc")
+        );
+        assert_eq!(query("unicode_upper(null)"), Value::Null);
+    }
+}
@@ -0,0 +1,63 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{api, define_scalar_function, Error, Result};
+
+/// json_roundtrip(value), the value parsed by api::value_json and
+/// written back as JSON text.
+pub fn json_roundtrip(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let value = values.first().expect("1st argument");
+    let json = api::value_json(value).map_err(|err| Error::new_message(err.to_string()))?;
+    api::result_json(context, json)
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_valuejson_init(db: *mut sqlite3) -> Result<()> {
+    let flags = FunctionFlags::UTF8 | FunctionFlags::DETERMINISTIC | FunctionFlags::RESULT_SUBTYPE;
+    define_scalar_function(db, "json_roundtrip", 1, json_roundtrip, flags)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_valuejson_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        let roundtrip = |sql: &str| -> rusqlite::Result<String> {
+            db.query_row(&format!("select json_roundtrip({})", sql), [], |row| {
+                row.get(0)
+            })
+        };
+
+        // text from json(), with the 'J' subtype, and plain text
+        assert_eq!(
+            roundtrip("json('{\"a\": [1, 2]}')").unwrap(),
+            r#"{"a":[1,2]}"#
+        );
+        assert_eq!(roundtrip("'[true, null]'").unwrap(), "[true,null]");
+        // JSONB, as jsonb('{"a":[1,2.5,true,null],"b":"x"}') makes it
+        assert_eq!(
+            roundtrip("X'CC0F17618B133135322E35010017621778'").unwrap(),
+            r#"{"a":[1,2.5,true,null],"b":"x"}"#
+        );
+        // a blob of JSON text
+        assert_eq!(
+            roundtrip("cast('{\"b\": 1}' as blob)").unwrap(),
+            r#"{"b":1}"#
+        );
+        // and numbers
+        assert_eq!(roundtrip("42").unwrap(), "42");
+
+        let err = roundtrip("X'FF'").unwrap_err().to_string();
+        assert!(err.contains("expected value"), "{}", err);
+    }
+}
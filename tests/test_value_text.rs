@@ -0,0 +1,64 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{api, define_scalar_function, Result};
+
+pub fn text_lossy(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let value = values.first().expect("1st argument");
+    api::result_text(context, api::value_text_lossy(value))
+}
+
+pub fn bytes_as_text(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let value = values.first().expect("1st argument");
+    api::result_text(context, api::value_bytes_as_text(value))
+}
+
+pub fn text_strict(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let value = values.first().expect("1st argument");
+    api::result_text(context, api::value_text(value)?)
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_valuetext_init(db: *mut sqlite3) -> Result<()> {
+    let flags = FunctionFlags::UTF8 | FunctionFlags::DETERMINISTIC;
+    define_scalar_function(db, "text_lossy", 1, text_lossy, flags)?;
+    define_scalar_function(db, "bytes_as_text", 1, bytes_as_text, flags)?;
+    define_scalar_function(db, "text_strict", 1, text_strict, flags)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_valuetext_init as *const (),
+                ),
+            ));
+        }
+        let conn = Connection::open_in_memory().unwrap();
+        let text = |sql: &str| -> String { conn.query_row(sql, [], |row| row.get(0)).unwrap() };
+
+        // text that isn't valid UTF-8
+        assert_eq!(
+            text("select text_lossy(cast(x'61ff62' as text))"),
+            "a\u{fffd}b"
+        );
+        assert_eq!(text("select bytes_as_text(x'61ff62')"), "a\u{fffd}b");
+        assert!(conn
+            .query_row("select text_strict(cast(x'61ff62' as text))", [], |row| {
+                row.get::<_, String>(0)
+            })
+            .is_err());
+
+        // valid text, numbers and NULL
+        assert_eq!(text("select text_lossy('héllo')"), "héllo");
+        assert_eq!(text("select text_lossy(1.5)"), "1.5");
+        assert_eq!(text("select bytes_as_text(12)"), "12");
+        assert_eq!(text("select text_lossy(null)"), "");
+    }
+}
@@ -107,6 +107,8 @@ pub struct InCursor {
     base: sqlite3_vtab_cursor,
     rowid: i64,
     value: Option<String>,
+    /// The error from reading a single value as an IN list.
+    not_a_list: Option<String>,
 }
 impl InCursor {
     fn new() -> InCursor {
@@ -115,6 +117,7 @@ impl InCursor {
             base,
             rowid: 0,
             value: None,
+            not_a_list: None,
         }
     }
 }
@@ -137,7 +140,18 @@ impl VTabCursor for InCursor {
                     }
                     self.value = Some(value)
                 }
-                'y' => (),
+                'y' => {
+                    let mut list = InValues::new(values[idx]);
+                    let error = list
+                        .next()
+                        .and_then(|v| v.err())
+                        .map(|err| err.result_error_message());
+                    // and it's done after the error
+                    self.not_a_list = match list.next() {
+                        None => error,
+                        Some(_) => Some("InValues kept going after an error".to_owned()),
+                    };
+                }
                 _ => (),
             }
         }
@@ -163,6 +177,11 @@ impl VTabCursor for InCursor {
                     api::result_text(context, "")?;
                 }
             }
+            Some(Columns::B) => {
+                if let Some(error) = &self.not_a_list {
+                    api::result_text(context, error)?;
+                }
+            }
             _ => (),
         }
         Ok(())
@@ -229,5 +248,14 @@ mod tests {
             })
             .unwrap();
         assert_eq!(a, "123");
+
+        // a plain value isn't a list
+        let b: String = db
+            .query_row("select b from vtab_in where y = 1", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(
+            b,
+            "value isn't the list of an IN constraint enabled with enable_process_all_in()"
+        );
     }
 }
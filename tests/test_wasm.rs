@@ -0,0 +1,106 @@
+#[cfg(feature = "wasm")]
+use sqlite_loadable::prelude::*;
+#[cfg(feature = "wasm")]
+use sqlite_loadable::{wasm::define_wasm_host, Result};
+
+#[cfg(feature = "wasm")]
+#[sqlite_entrypoint]
+pub fn sqlite3_wasm_init(db: *mut sqlite3) -> Result<()> {
+    define_wasm_host(db)
+}
+
+#[cfg(all(test, feature = "wasm"))]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, types::Value, Connection};
+
+    const MATH: &str = r#"
+        (module
+          (func (export "add") (param i32 i32) (result i32)
+            local.get 0 local.get 1 i32.add)
+          (func (export "mul64") (param i64 i64) (result i64)
+            local.get 0 local.get 1 i64.mul)
+          (func (export "half") (param f64) (result f64)
+            local.get 0 f64.const 2 f64.div)
+          (func (export "nothing") (param i32))
+          (func (export "spin") (result i32)
+            (loop br 0) i32.const 0)
+          (func (export "pair") (result i32 i32)
+            i32.const 1 i32.const 2))
+    "#;
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_wasm_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        let define = |name: &str, export: Option<&str>| {
+            db.query_row(
+                "select wasm_define(?1, ?2, coalesce(?3, ?1))",
+                (name, MATH.as_bytes(), export),
+                |row| row.get::<_, Value>(0),
+            )
+        };
+        let value = |sql: &str| db.query_row(sql, [], |row| row.get::<_, Value>(0));
+
+        define("plus", Some("add")).unwrap();
+        define("multiply", Some("mul64")).unwrap();
+        define("half", None).unwrap();
+        define("noop", Some("nothing")).unwrap();
+        assert_eq!(value("select plus(40, 2)").unwrap(), Value::Integer(42));
+        assert_eq!(
+            value("select multiply(3000000000, 3)").unwrap(),
+            Value::Integer(9000000000)
+        );
+        assert_eq!(value("select half(5)").unwrap(), Value::Real(2.5));
+        assert_eq!(value("select plus(1, null)").unwrap(), Value::Null);
+        assert_eq!(value("select noop(1)").unwrap(), Value::Null);
+        assert!(value("select plus(3000000000, 1)")
+            .unwrap_err()
+            .to_string()
+            .contains("argument 1 is out of range for an i32"));
+        // arity comes from the export
+        assert!(value("select plus(1)").is_err());
+
+        define("spin", None).unwrap();
+        assert!(value("select spin()")
+            .unwrap_err()
+            .to_string()
+            .contains("ran out of fuel"));
+
+        assert!(define("pair", None)
+            .unwrap_err()
+            .to_string()
+            .contains("at most one is supported"));
+        assert!(define("missing", None)
+            .unwrap_err()
+            .to_string()
+            .contains("exports no function missing"));
+        assert!(db
+            .query_row("select wasm_define('x', x'00')", [], |row| row
+                .get::<_, Value>(0))
+            .unwrap_err()
+            .to_string()
+            .contains("invalid WebAssembly module"));
+        assert!(db
+            .query_row(
+                "select wasm_define('x', '(module (import \"env\" \"f\" (func)))')",
+                [],
+                |row| row.get::<_, Value>(0)
+            )
+            .unwrap_err()
+            .to_string()
+            .contains("could not instantiate"));
+
+        // not from a view
+        db.execute_batch("create view defines as select wasm_define('plus2', x'') as result")
+            .unwrap();
+        assert!(value("select result from defines").is_err());
+    }
+}
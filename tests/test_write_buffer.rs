@@ -0,0 +1,190 @@
+use sqlite_loadable::prelude::*;
+use sqlite_loadable::{
+    kv::{define_buffered_kv_module, KvStore, MemoryStore, WriteBuffer},
+    Result,
+};
+
+use std::sync::{Arc, OnceLock};
+
+/// The store behind the buffered table, shared with the test.
+static STORE: OnceLock<Arc<MemoryStore>> = OnceLock::new();
+
+fn store() -> &'static Arc<MemoryStore> {
+    STORE.get_or_init(|| Arc::new(MemoryStore::new()))
+}
+
+#[sqlite_entrypoint]
+pub fn sqlite3_writebuffer_init(db: *mut sqlite3) -> Result<()> {
+    define_buffered_kv_module(db, "buffered_kv", store().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    fn scan(store: &dyn KvStore, prefix: &str) -> Vec<(String, String)> {
+        store
+            .scan_prefix(prefix.as_bytes())
+            .unwrap()
+            .map(|entry| {
+                let (key, value) = entry.unwrap();
+                (
+                    String::from_utf8(key).unwrap(),
+                    String::from_utf8(value).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_overlay() {
+        let base = MemoryStore::new();
+        for (key, value) in [("a", "1"), ("b", "2"), ("c", "3"), ("x", "9")] {
+            base.put(key.as_bytes(), value.as_bytes()).unwrap();
+        }
+        let buffer = WriteBuffer::new(base);
+        buffer.put(b"b", b"20").unwrap();
+        buffer.delete(b"c").unwrap();
+        buffer.put(b"bb", b"22").unwrap();
+        buffer.put(b"0", b"0").unwrap();
+        buffer.delete(b"missing").unwrap();
+
+        assert_eq!(buffer.get(b"b").unwrap(), Some(b"20".to_vec()));
+        assert_eq!(buffer.get(b"c").unwrap(), None);
+        assert_eq!(buffer.get(b"a").unwrap(), Some(b"1".to_vec()));
+        let expected = |entries: &[(&str, &str)]| -> Vec<(String, String)> {
+            entries
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        assert_eq!(
+            scan(&buffer, ""),
+            expected(&[
+                ("0", "0"),
+                ("a", "1"),
+                ("b", "20"),
+                ("bb", "22"),
+                ("x", "9")
+            ])
+        );
+        assert_eq!(scan(&buffer, "b"), expected(&[("b", "20"), ("bb", "22")]));
+        assert_eq!(
+            scan(buffer.store(), ""),
+            expected(&[("a", "1"), ("b", "2"), ("c", "3"), ("x", "9")])
+        );
+
+        assert_eq!(buffer.pending().unwrap(), 5);
+        buffer.flush().unwrap();
+        assert_eq!(buffer.pending().unwrap(), 0);
+        assert_eq!(scan(buffer.store(), ""), scan(&buffer, ""));
+    }
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_writebuffer_init as *const (),
+                ),
+            ));
+        }
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch("create virtual table settings using buffered_kv()")
+            .unwrap();
+        let rows = |sql: &str| -> Vec<(String, String)> {
+            db.prepare(sql)
+                .unwrap()
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+                .unwrap()
+                .collect::<std::result::Result<_, _>>()
+                .unwrap()
+        };
+        let entry = |key: &str, value: &str| (key.to_owned(), value.to_owned());
+
+        // a statement outside a transaction is written when it finishes
+        db.execute("insert into settings values ('theme', 'dark')", [])
+            .unwrap();
+        assert_eq!(store().get(b"theme").unwrap(), Some(b"dark".to_vec()));
+
+        // inside one, reads see writes the store doesn't have yet
+        db.execute_batch(
+            "begin;
+             insert into settings values ('user.name', 'alex');
+             update settings set value = 'light' where key = 'theme';",
+        )
+        .unwrap();
+        assert_eq!(
+            rows("select * from settings"),
+            vec![entry("theme", "light"), entry("user.name", "alex")]
+        );
+        assert_eq!(
+            rows("select * from settings where key = 'user.name'"),
+            vec![entry("user.name", "alex")]
+        );
+        assert_eq!(store().get(b"user.name").unwrap(), None);
+        assert_eq!(store().get(b"theme").unwrap(), Some(b"dark".to_vec()));
+        db.execute_batch("commit").unwrap();
+        assert_eq!(store().get(b"user.name").unwrap(), Some(b"alex".to_vec()));
+        assert_eq!(store().get(b"theme").unwrap(), Some(b"light".to_vec()));
+
+        // a rollback drops them
+        db.execute_batch(
+            "begin;
+             delete from settings where key = 'theme';
+             insert into settings values ('user.email', 'alex@example.com');",
+        )
+        .unwrap();
+        assert_eq!(
+            rows("select * from settings where key glob 'user.*'"),
+            vec![
+                entry("user.email", "alex@example.com"),
+                entry("user.name", "alex")
+            ]
+        );
+        assert_eq!(rows("select * from settings where key = 'theme'"), vec![]);
+        db.execute_batch("rollback").unwrap();
+        assert_eq!(
+            rows("select * from settings"),
+            vec![entry("theme", "light"), entry("user.name", "alex")]
+        );
+        assert_eq!(store().get(b"user.email").unwrap(), None);
+
+        // a failing statement only undoes its own writes
+        db.execute_batch(
+            "begin;
+             insert into settings values ('a.1', '1');",
+        )
+        .unwrap();
+        assert!(db
+            .execute(
+                "insert into settings values ('a.2', '2'), ('theme', 'x')",
+                [],
+            )
+            .is_err());
+        db.execute_batch("commit").unwrap();
+        assert_eq!(store().get(b"a.1").unwrap(), Some(b"1".to_vec()));
+        assert_eq!(store().get(b"a.2").unwrap(), None);
+        assert_eq!(store().get(b"theme").unwrap(), Some(b"light".to_vec()));
+
+        // rolling back to a savepoint undoes the writes since it
+        db.execute_batch(
+            "begin;
+             insert into settings values ('b.1', '1');
+             savepoint s;
+             insert into settings values ('b.2', '2');
+             rollback to s;
+             insert into settings values ('b.3', '3');
+             release s;
+             commit;",
+        )
+        .unwrap();
+        assert_eq!(
+            rows("select * from settings where key glob 'b.*'"),
+            vec![entry("b.1", "1"), entry("b.3", "3")]
+        );
+        assert_eq!(store().get(b"b.2").unwrap(), None);
+    }
+}
@@ -0,0 +1,89 @@
+#[cfg(any(feature = "xml", feature = "html"))]
+use sqlite_loadable::prelude::*;
+#[cfg(any(feature = "xml", feature = "html"))]
+use sqlite_loadable::{api, define_scalar_function, Result};
+
+#[cfg(feature = "xml")]
+pub fn xml_select(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let xpath = api::value_text(values.get(1).expect("2nd argument as XPath"))?;
+    let nodes = sqlite_loadable::xml::value_xml_select(
+        values.first().expect("1st argument as document"),
+        xpath,
+    )?;
+    api::result_json(context, nodes)
+}
+
+#[cfg(feature = "html")]
+pub fn html_select(context: *mut sqlite3_context, values: &[*mut sqlite3_value]) -> Result<()> {
+    let selector = api::value_text(values.get(1).expect("2nd argument as CSS selector"))?;
+    let elements = sqlite_loadable::xml::value_html_select(
+        values.first().expect("1st argument as document"),
+        selector,
+    )?;
+    api::result_json(context, elements)
+}
+
+#[cfg(any(feature = "xml", feature = "html"))]
+#[sqlite_entrypoint]
+pub fn sqlite3_xml_init(db: *mut sqlite3) -> Result<()> {
+    let flags = FunctionFlags::UTF8 | FunctionFlags::DETERMINISTIC | FunctionFlags::RESULT_SUBTYPE;
+    #[cfg(feature = "xml")]
+    define_scalar_function(db, "xml_select", 2, xml_select, flags)?;
+    #[cfg(feature = "html")]
+    define_scalar_function(db, "html_select", 2, html_select, flags)?;
+    Ok(())
+}
+
+#[cfg(any(feature = "xml", feature = "html"))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, Connection};
+
+    fn connection() -> Connection {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_xml_init as *const (),
+                ),
+            ));
+        }
+        Connection::open_in_memory().unwrap()
+    }
+
+    #[cfg(feature = "xml")]
+    #[test]
+    fn test_xml_select() {
+        let conn = connection();
+        let titles: String = conn
+            .query_row(
+                "select group_concat(value, '|') from json_each(xml_select(?, '//item/title/text()'))",
+                ["<rss><channel><item><title>a</title></item><item><title>b</title></item></channel></rss>"],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(titles, "a|b");
+
+        let err = conn
+            .query_row("select xml_select('<a>', '/')", [], |row| {
+                row.get::<_, String>(0)
+            })
+            .unwrap_err();
+        assert!(err.to_string().starts_with("invalid XML"));
+    }
+
+    #[cfg(feature = "html")]
+    #[test]
+    fn test_html_select() {
+        let conn = connection();
+        let hrefs: String = conn
+            .query_row(
+                "select group_concat(value ->> '$.attributes.href', ',') from json_each(html_select(?, 'a[href]'))",
+                ["<p><a href='/one'>1</a> <a>no link</a> <a href='/two'>2</p>"],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(hrefs, "/one,/two");
+    }
+}
@@ -0,0 +1,185 @@
+#[cfg(feature = "zipfile")]
+use sqlite_loadable::prelude::*;
+#[cfg(feature = "zipfile")]
+use sqlite_loadable::{zipfile::define_zipfile, Result};
+
+#[cfg(feature = "zipfile")]
+#[sqlite_entrypoint]
+pub fn sqlite3_zipfile_init(db: *mut sqlite3) -> Result<()> {
+    define_zipfile(db)
+}
+
+#[cfg(all(test, feature = "zipfile"))]
+mod tests {
+    use super::*;
+
+    use rusqlite::{ffi::sqlite3_auto_extension, types::Value, Connection};
+
+    #[test]
+    fn test_rusqlite_auto_extension() {
+        unsafe {
+            sqlite3_auto_extension(Some(
+                std::mem::transmute::<*const (), unsafe extern "C" fn()>(
+                    sqlite3_zipfile_init as *const (),
+                ),
+            ));
+        }
+        let path = std::env::temp_dir().join(format!(
+            "sqlite-loadable-test-zipfile-{}.zip",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let path = path.to_str().unwrap().to_owned();
+
+        let db = Connection::open_in_memory().unwrap();
+        db.execute(
+            &format!(
+                "create virtual table temp.bundle using zipfile_table('{}')",
+                path
+            ),
+            [],
+        )
+        .unwrap();
+        db.execute_batch(
+            "
+            insert into temp.bundle(name, data, mtime) values ('hello.txt', 'hello, world', 1700000000);
+            insert into temp.bundle(name, mode, mtime, data, method) values ('raw.bin', 384, 1700000000, x'000102', 0);
+            insert into temp.bundle(name, mtime) values ('docs/', 1700000000);
+            ",
+        )
+        .unwrap();
+        assert!(std::path::Path::new(&path).exists());
+
+        let rows = |sql: &str| -> Vec<Vec<Value>> {
+            let mut stmt = db.prepare(sql).unwrap();
+            let n = stmt.column_count();
+            let rows = stmt
+                .query_map([], |row| (0..n).map(|i| row.get::<_, Value>(i)).collect())
+                .unwrap();
+            rows.map(|row| row.unwrap()).collect()
+        };
+        let text = |s: &str| Value::Text(s.to_owned());
+
+        // read back from the file, through the table function
+        let listing = format!(
+            "select name, mode, mtime, sz, data, method from zipfile('{}')",
+            path
+        );
+        let expected = vec![
+            vec![
+                text("hello.txt"),
+                Value::Integer(0o100644),
+                Value::Integer(1700000000),
+                Value::Integer(12),
+                Value::Blob(b"hello, world".to_vec()),
+                Value::Integer(8),
+            ],
+            vec![
+                text("raw.bin"),
+                Value::Integer(0o100600),
+                Value::Integer(1700000000),
+                Value::Integer(3),
+                Value::Blob(vec![0, 1, 2]),
+                Value::Integer(0),
+            ],
+            vec![
+                text("docs/"),
+                Value::Integer(0o40755),
+                Value::Integer(1700000000),
+                Value::Integer(0),
+                Value::Null,
+                Value::Integer(0),
+            ],
+        ];
+        assert_eq!(rows(&listing), expected);
+
+        // and from the archive's bytes
+        let bytes = std::fs::read(&path).unwrap();
+        let mut stmt = db
+            .prepare("select name from zipfile(?) order by name")
+            .unwrap();
+        let names: Vec<String> = stmt
+            .query_map([bytes], |row| row.get(0))
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+        assert_eq!(names, vec!["docs/", "hello.txt", "raw.bin"]);
+
+        // updating and deleting rewrites the archive, copying the rest
+        db.execute_batch(
+            "
+            update temp.bundle set data = 'bye' where name = 'hello.txt';
+            delete from temp.bundle where name = 'raw.bin';
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            rows(&format!("select name, data from zipfile('{}')", path)),
+            vec![
+                vec![text("hello.txt"), Value::Blob(b"bye".to_vec())],
+                vec![text("docs/"), Value::Null],
+            ]
+        );
+
+        // names are unique, unless the insert replaces
+        let err = db
+            .execute(
+                "insert into temp.bundle(name, data) values ('docs/', null)",
+                [],
+            )
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("UNIQUE constraint failed: name"), "{}", err);
+        db.execute(
+            "insert or replace into temp.bundle(name, data) values ('hello.txt', 'again')",
+            [],
+        )
+        .unwrap();
+        assert_eq!(
+            rows("select data from temp.bundle where name = 'hello.txt'"),
+            vec![vec![Value::Blob(b"again".to_vec())]]
+        );
+
+        // a rolled back transaction leaves the archive alone
+        db.execute_batch(
+            "
+            begin;
+            delete from temp.bundle;
+            rollback;
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            rows("select count(*) from temp.bundle"),
+            vec![vec![Value::Integer(2)]]
+        );
+        assert_eq!(
+            rows(&format!("select count(*) from zipfile('{}')", path)),
+            vec![vec![Value::Integer(2)]]
+        );
+
+        let error = |sql: &str| db.execute(sql, []).unwrap_err().to_string();
+        assert_eq!(
+            error("insert into temp.bundle(name, data, method) values ('x', 'y', 12)"),
+            "zip: method 12 isn't supported, only 0 (stored) and 8 (deflated) are"
+        );
+        assert_eq!(
+            error("insert into temp.bundle(name, data) values ('dir/', 'y')"),
+            "zip: dir/ is a directory, so it can't have data"
+        );
+        assert_eq!(
+            error("insert into temp.bundle(name, data, mtime) values ('old', 'y', 0)"),
+            "zip: mtime 0 isn't between the years 1980 and 2107"
+        );
+        assert!(db
+            .query_row("select * from zipfile()", [], |_| Ok(()))
+            .is_err());
+        assert!(db
+            .query_row("select * from zipfile(x'00')", [], |_| Ok(()))
+            .unwrap_err()
+            .to_string()
+            .starts_with("zip: "));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}